@@ -0,0 +1,17 @@
+//! Run with `cargo bench -p landmark-client`, or use `landmark-bench` to choose the chunk count and seed.
+
+const CHUNKS: usize = 512;
+const SEED: u64 = 1;
+const RUNS: usize = 3;
+
+fn main() {
+    for run in 1..=RUNS {
+        match landmark_client::run_benchmark(CHUNKS, SEED) {
+            Ok(report) => println!("Run {run}/{RUNS}\n{report}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Choice of the graphics backend and GPU.
+//!
+//! The adapter configured in the settings is used if it's available. Otherwise the other adapters of
+//! the configured backend are tried, then all backends, and finally a software adapter, so a bad
+//! setting never prevents the game from starting.
+
+use game_loop::winit::window::Window;
+
+use crate::{
+    error::LandmarkError,
+    settings::{AdapterSetting, BackendSetting, GraphicsSettings},
+};
+
+/// The adapter picked for rendering, with the instance it belongs to, so more windows can get
+/// surfaces the device can draw to.
+pub struct SelectedAdapter {
+    pub instance: wgpu::Instance,
+    pub surface: Option<wgpu::Surface>,
+    pub adapter: wgpu::Adapter,
+}
+
+/// Returns a readable line describing every adapter on every backend.
+pub fn list_adapters() -> Vec<String> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+
+    instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .map(|adapter| {
+            let info = adapter.get_info();
+            format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+        })
+        .collect()
+}
+
+/// Returns true if the adapter is the kind the setting asks for.
+fn matches(setting: &AdapterSetting, info: &wgpu::AdapterInfo) -> bool {
+    match setting {
+        AdapterSetting::Auto => true,
+        AdapterSetting::Discrete => info.device_type == wgpu::DeviceType::DiscreteGpu,
+        AdapterSetting::Integrated => info.device_type == wgpu::DeviceType::IntegratedGpu,
+        AdapterSetting::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+    }
+}
+
+/// Orders adapters by how likely they are to run the game well, lower is better.
+fn rank(device_type: wgpu::DeviceType) -> u8 {
+    match device_type {
+        wgpu::DeviceType::DiscreteGpu => 0,
+        wgpu::DeviceType::IntegratedGpu => 1,
+        wgpu::DeviceType::VirtualGpu => 2,
+        wgpu::DeviceType::Other => 3,
+        wgpu::DeviceType::Cpu => 4,
+    }
+}
+
+/// Picks the adapter of the instance which fits the setting best, among the ones that can draw to
+/// the surface. Falls back to the best other adapter if none matches the setting.
+fn pick_adapter(
+    instance: &wgpu::Instance,
+    backends: wgpu::Backends,
+    surface: Option<&wgpu::Surface>,
+    setting: &AdapterSetting,
+) -> Option<wgpu::Adapter> {
+    let mut adapters: Vec<wgpu::Adapter> = instance
+        .enumerate_adapters(backends)
+        .filter(|adapter| surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+        .collect();
+
+    adapters.sort_by_key(|adapter| rank(adapter.get_info().device_type));
+
+    if let Some(idx) = adapters
+        .iter()
+        .position(|adapter| matches(setting, &adapter.get_info()))
+    {
+        return Some(adapters.swap_remove(idx));
+    }
+
+    let adapter = adapters.into_iter().next()?;
+    log::warn!(
+        "No adapter matches {setting:?}, using {}",
+        adapter.get_info().name
+    );
+
+    Some(adapter)
+}
+
+/// Creates the instance for the backends and picks an adapter, with a surface of the window if
+/// there is one. Returns None if no adapter of the backends works.
+fn try_backends(
+    backends: wgpu::Backends,
+    window: Option<&Window>,
+    setting: &AdapterSetting,
+) -> Option<SelectedAdapter> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+
+    let surface = match window {
+        Some(window) => match unsafe { instance.create_surface(window) } {
+            Ok(surface) => Some(surface),
+            Err(e) => {
+                log::warn!("Failed to create a surface with {backends:?}: {e}");
+                return None;
+            }
+        },
+        None => None,
+    };
+
+    let adapter = pick_adapter(&instance, backends, surface.as_ref(), setting)?;
+
+    Some(SelectedAdapter {
+        instance,
+        surface,
+        adapter,
+    })
+}
+
+/// Selects the backend and adapter from the settings, falling back to any that works.
+/// The surface is created for the window if one is given.
+/// Fails if not even a software adapter is available.
+pub async fn select_adapter(
+    settings: &GraphicsSettings,
+    window: Option<&Window>,
+) -> Result<SelectedAdapter, LandmarkError> {
+    if let Some(selected) = try_backends(settings.backend.backends(), window, &settings.adapter) {
+        return Ok(selected);
+    }
+
+    if settings.backend != BackendSetting::Auto {
+        log::warn!(
+            "No adapter found for the {:?} backend, trying all backends",
+            settings.backend
+        );
+
+        if let Some(selected) = try_backends(wgpu::Backends::all(), window, &settings.adapter) {
+            return Ok(selected);
+        }
+    }
+
+    log::warn!("No hardware adapter found, trying a software adapter");
+
+    let instance = wgpu::Instance::default();
+    let surface = window.and_then(|window| unsafe { instance.create_surface(window) }.ok());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: true,
+            compatible_surface: surface.as_ref(),
+        })
+        .await
+        .ok_or(LandmarkError::NoAdapter)?;
+
+    Ok(SelectedAdapter {
+        instance,
+        surface,
+        adapter,
+    })
+}
@@ -0,0 +1,723 @@
+//! Import of Minecraft worlds saved in the Anvil format, for testing with large existing builds.
+//!
+//! Blocks of Minecraft 1.13 and newer are converted by their name through a [`BlockMapping`],
+//! block states are ignored. Coordinates are kept, so a block at Minecraft Y 64 ends up at Y 64.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    coords,
+    game_map::{BlockId, Chunk, ChunkCoords, InnerChunkCoords},
+    nbt::{self, Tag},
+    region::RegionStore,
+    save::encode_chunk,
+};
+
+/// Chunks along X and Z in an Anvil region file.
+const REGION_SIZE: i32 = 32;
+const SECTOR_SIZE: usize = 4096;
+/// Blocks along every axis of a Minecraft chunk section.
+const SECTION_SIZE: i32 = 16;
+/// Minecraft chunks along X and Z making up the columns of one Landmark chunk.
+const CHUNKS_PER_COLUMN: i32 = Chunk::SIZE / SECTION_SIZE;
+/// First data version whose block states don't span two longs, 20w17a.
+const NON_SPANNING_DATA_VERSION: i64 = 2529;
+/// Blocks which are always imported as air.
+const AIR_BLOCKS: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+/// Landmark blocks Minecraft blocks are converted to, loaded from a RON file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BlockMapping {
+    /// Block used for Minecraft blocks without a mapping, they become air if there is none.
+    #[serde(default)]
+    pub default: Option<String>,
+    /// Landmark block name by namespaced Minecraft block name, e.g. `"minecraft:stone": "Stone"`.
+    #[serde(default)]
+    pub blocks: HashMap<String, String>,
+    /// Minecraft blocks imported as air, in addition to the air blocks.
+    #[serde(default)]
+    pub air: Vec<String>,
+}
+
+impl BlockMapping {
+    pub fn load(path: &Path) -> Result<Self, AnvilError> {
+        let content = fs::read_to_string(path).map_err(|source| AnvilError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        ron::from_str(&content).map_err(|source| AnvilError::Mapping {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Returns the Landmark block a Minecraft block is converted to, or None for air.
+    fn map(&self, name: &str) -> Option<&str> {
+        if AIR_BLOCKS.contains(&name) || self.air.iter().any(|air| air == name) {
+            return None;
+        }
+
+        self.blocks
+            .get(name)
+            .or(self.default.as_ref())
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug)]
+pub enum AnvilError {
+    Mapping {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    NoRegions {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for AnvilError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnvilError::Mapping { path, source } => {
+                write!(f, "Invalid block mapping {}: {source}", path.display())
+            }
+            AnvilError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            AnvilError::NoRegions { path } => write!(
+                f,
+                "No .mca region files found in {}, select the region directory of a Minecraft world",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AnvilError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnvilError::Mapping { source, .. } => Some(source),
+            AnvilError::Io { source, .. } => Some(source),
+            AnvilError::NoRegions { .. } => None,
+        }
+    }
+}
+
+/// Outcome of an import, reported to the user.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub regions: usize,
+    /// Landmark chunks written to the world.
+    pub chunks: usize,
+    /// Minecraft chunks which couldn't be read or are saved by an unsupported version.
+    pub skipped_chunks: usize,
+    /// Minecraft blocks without a mapping, imported as the default block or air.
+    pub unmapped: BTreeSet<String>,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Imported {} chunks from {} regions",
+            self.chunks, self.regions
+        )?;
+
+        if self.skipped_chunks > 0 {
+            write!(f, ", skipped {} unreadable chunks", self.skipped_chunks)?;
+        }
+
+        if !self.unmapped.is_empty() {
+            write!(f, ", {} blocks had no mapping", self.unmapped.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts every region file in `source` and writes the chunks into the world at `destination`,
+/// replacing chunks already saved at the same positions.
+pub fn import(
+    source: &Path,
+    mapping: &BlockMapping,
+    destination: &Path,
+) -> Result<ImportSummary, AnvilError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| AnvilError::Io { path, source }
+    };
+
+    let mut region_paths: Vec<(i32, i32, PathBuf)> = fs::read_dir(source)
+        .map_err(io_error(source))?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let (x, z) = region_coords(&path)?;
+            Some((x, z, path))
+        })
+        .collect();
+
+    if region_paths.is_empty() {
+        return Err(AnvilError::NoRegions {
+            path: source.to_path_buf(),
+        });
+    }
+
+    region_paths.sort();
+
+    let mut importer = Importer {
+        mapping,
+        block_ids: HashMap::new(),
+        block_names: HashMap::new(),
+        summary: ImportSummary::default(),
+    };
+    let mut store = RegionStore::new(destination.to_path_buf());
+
+    for (x, z, path) in region_paths {
+        log::info!("Importing {}", path.display());
+
+        let chunks = read_region(&path).map_err(io_error(&path))?;
+        let encoded = importer.convert_region(x, z, &chunks);
+
+        importer.summary.regions += 1;
+        importer.summary.chunks += encoded.len();
+
+        store.write(encoded).map_err(io_error(destination))?;
+    }
+
+    for name in importer.summary.unmapped.iter() {
+        log::warn!("Minecraft block {name} has no mapping");
+    }
+
+    Ok(importer.summary)
+}
+
+/// Returns the region coordinates from a file name like `r.-1.2.mca`.
+fn region_coords(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.strip_suffix(".mca")?.split('.');
+
+    let x = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+
+    parts.next().is_none().then_some((x, z))
+}
+
+/// Reads the uncompressed NBT data of every chunk slot of a region file, empty slots are None.
+///
+/// The file starts with a table of the location of every chunk as a big endian u24 sector offset and
+/// a u8 sector count. A chunk is stored as its length as a big endian u32, the compression type and the data.
+fn read_region(path: &Path) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let bytes = fs::read(path)?;
+    let slot_count = (REGION_SIZE * REGION_SIZE) as usize;
+
+    if bytes.len() < SECTOR_SIZE {
+        return Ok(vec![None; slot_count]);
+    }
+
+    let mut chunks = Vec::with_capacity(slot_count);
+
+    for (slot, entry) in bytes[..SECTOR_SIZE].chunks_exact(4).enumerate() {
+        let offset = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as usize * SECTOR_SIZE;
+
+        if offset == 0 {
+            chunks.push(None);
+            continue;
+        }
+
+        let chunk = read_chunk(&bytes, offset).unwrap_or_else(|e| {
+            log::warn!("Skipping chunk slot {slot} of {}: {e}", path.display());
+            None
+        });
+
+        chunks.push(chunk);
+    }
+
+    Ok(chunks)
+}
+
+fn read_chunk(bytes: &[u8], offset: usize) -> io::Result<Option<Vec<u8>>> {
+    let header = bytes
+        .get(offset..offset + 5)
+        .ok_or_else(|| invalid_data("chunk is past the end of the file"))?;
+    let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let compression = header[4];
+
+    let data = length
+        .checked_sub(1)
+        .and_then(|length| bytes.get(offset + 5..offset + 5 + length))
+        .ok_or_else(|| invalid_data("chunk is past the end of the file"))?;
+
+    let mut decompressed = Vec::new();
+
+    match compression {
+        1 => {
+            flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed)?;
+        }
+        2 => {
+            flate2::read::ZlibDecoder::new(data).read_to_end(&mut decompressed)?;
+        }
+        3 => decompressed.extend_from_slice(data),
+        _ => {
+            return Err(invalid_data(
+                "chunk uses an unsupported compression or is stored in a separate file",
+            ))
+        }
+    }
+
+    Ok(Some(decompressed))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Minecraft blocks of a 16x16x16 section, indexed by Y, Z and X.
+struct Section {
+    y: i32,
+    palette: Vec<String>,
+    indices: Vec<u16>,
+}
+
+/// Reads the block sections of a chunk saved by Minecraft 1.13 or newer.
+fn read_sections(root: &Tag) -> Result<Vec<Section>, String> {
+    let data_version = root
+        .get("DataVersion")
+        .and_then(Tag::as_i64)
+        .unwrap_or_default();
+
+    // since 1.18 sections are in the root, before they were in the Level compound
+    let (sections, palette_key, states_key) = match root.get("sections") {
+        Some(sections) => (sections, "palette", "data"),
+        None => (
+            root.get("Level")
+                .and_then(|level| level.get("Sections"))
+                .ok_or("chunk has no sections")?,
+            "Palette",
+            "BlockStates",
+        ),
+    };
+
+    let mut result = Vec::new();
+
+    for section in sections.as_list().unwrap_or_default() {
+        let Some(y) = section.get("Y").and_then(Tag::as_i64) else {
+            continue;
+        };
+
+        let states = section.get("block_states").unwrap_or(section);
+        let Some(palette) = states.get(palette_key).and_then(Tag::as_list) else {
+            if section.get("Blocks").is_some() {
+                return Err("chunk is saved by a version older than 1.13".to_string());
+            }
+
+            // sections with only light data
+            continue;
+        };
+
+        let palette: Vec<String> = palette
+            .iter()
+            .map(|entry| {
+                entry
+                    .get("Name")
+                    .and_then(Tag::as_str)
+                    .unwrap_or(AIR_BLOCKS[0])
+                    .to_string()
+            })
+            .collect();
+
+        let data = states
+            .get(states_key)
+            .and_then(Tag::as_long_array)
+            .unwrap_or_default();
+
+        let indices = unpack_indices(
+            data,
+            palette.len(),
+            data_version >= NON_SPANNING_DATA_VERSION,
+        )?;
+
+        result.push(Section {
+            y: y as i32,
+            palette,
+            indices,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Unpacks palette indices of the 4096 blocks of a section from longs.
+///
+/// Every index takes as many bits as the largest one needs, but at least 4. Newer versions start
+/// a new long when the next index doesn't fit, older ones split it across two longs.
+fn unpack_indices(
+    data: &[i64],
+    palette_size: usize,
+    spanning_free: bool,
+) -> Result<Vec<u16>, String> {
+    let count = (SECTION_SIZE * SECTION_SIZE * SECTION_SIZE) as usize;
+
+    // a section of a single block has no data
+    if palette_size <= 1 || data.is_empty() {
+        return Ok(vec![0; count]);
+    }
+
+    let bits = (usize::BITS - (palette_size - 1).leading_zeros()).max(4) as usize;
+    let mask = (1u64 << bits) - 1;
+    let per_long = 64 / bits;
+
+    let expected = if spanning_free {
+        count.div_ceil(per_long)
+    } else {
+        (count * bits).div_ceil(64)
+    };
+
+    if data.len() < expected {
+        return Err(format!(
+            "section has {} longs of block data instead of {expected}",
+            data.len()
+        ));
+    }
+
+    let indices = (0..count)
+        .map(|idx| {
+            let value = if spanning_free {
+                (data[idx / per_long] as u64 >> (idx % per_long * bits)) & mask
+            } else {
+                let bit = idx * bits;
+                let (long, shift) = (bit / 64, bit % 64);
+                let mut value = data[long] as u64 >> shift;
+
+                if shift + bits > 64 {
+                    value |= (data[long + 1] as u64) << (64 - shift);
+                }
+
+                value & mask
+            };
+
+            value as u16
+        })
+        .collect();
+
+    Ok(indices)
+}
+
+struct Importer<'a> {
+    mapping: &'a BlockMapping,
+    /// IDs the mapped blocks are given in the imported chunks, they're only used to encode them by name.
+    block_ids: HashMap<String, BlockId>,
+    block_names: HashMap<BlockId, String>,
+    summary: ImportSummary,
+}
+
+impl Importer<'_> {
+    fn block_id(&mut self, minecraft_name: &str) -> Option<BlockId> {
+        if !self.mapping.blocks.contains_key(minecraft_name)
+            && !AIR_BLOCKS.contains(&minecraft_name)
+            && !self.mapping.air.iter().any(|air| air == minecraft_name)
+        {
+            self.summary.unmapped.insert(minecraft_name.to_string());
+        }
+
+        let name = self.mapping.map(minecraft_name)?;
+
+        if let Some(id) = self.block_ids.get(name) {
+            return Some(*id);
+        }
+
+        let id = self.block_ids.len() as BlockId;
+        self.block_ids.insert(name.to_string(), id);
+        self.block_names.insert(id, name.to_string());
+
+        Some(id)
+    }
+
+    /// Converts the chunks of a region file, a region of 512 blocks always consists of whole Landmark chunks.
+    fn convert_region(
+        &mut self,
+        region_x: i32,
+        region_z: i32,
+        chunks: &[Option<Vec<u8>>],
+    ) -> Vec<(ChunkCoords, Vec<u8>)> {
+        let columns = REGION_SIZE / CHUNKS_PER_COLUMN;
+        let mut encoded = Vec::new();
+
+        // one column of Landmark chunks at a time, so only a few of them are kept in memory
+        for column_z in 0..columns {
+            for column_x in 0..columns {
+                let mut converted: HashMap<ChunkCoords, Chunk> = HashMap::new();
+
+                for dz in 0..CHUNKS_PER_COLUMN {
+                    for dx in 0..CHUNKS_PER_COLUMN {
+                        let local_x = column_x * CHUNKS_PER_COLUMN + dx;
+                        let local_z = column_z * CHUNKS_PER_COLUMN + dz;
+                        let Some(bytes) = &chunks[(local_x + local_z * REGION_SIZE) as usize]
+                        else {
+                            continue;
+                        };
+
+                        let origin = glam::IVec3::new(
+                            (region_x * REGION_SIZE + local_x) * SECTION_SIZE,
+                            0,
+                            (region_z * REGION_SIZE + local_z) * SECTION_SIZE,
+                        );
+
+                        let sections = nbt::read(bytes)
+                            .map_err(|e| e.to_string())
+                            .and_then(|root| read_sections(&root));
+
+                        match sections {
+                            Ok(sections) => {
+                                for section in sections {
+                                    self.convert_section(&section, origin, &mut converted);
+                                }
+                            }
+                            Err(e) => {
+                                log::warn!("Skipping chunk at {} {}: {e}", origin.x, origin.z);
+                                self.summary.skipped_chunks += 1;
+                            }
+                        }
+                    }
+                }
+
+                // light is saved so imported chunks don't have to be relit, biomes come from the world generator
+                encoded.extend(converted.into_iter().map(|(coords, mut chunk)| {
+                    chunk.relight();
+                    (coords, encode_chunk(&chunk, &self.block_names))
+                }));
+            }
+        }
+
+        encoded
+    }
+
+    fn convert_section(
+        &mut self,
+        section: &Section,
+        origin: glam::IVec3,
+        converted: &mut HashMap<ChunkCoords, Chunk>,
+    ) {
+        let palette: Vec<Option<BlockId>> = section
+            .palette
+            .iter()
+            .map(|name| self.block_id(name))
+            .collect();
+
+        let section_origin = origin + glam::IVec3::new(0, section.y * SECTION_SIZE, 0);
+        let (coords, inner_origin) = coords::split_block(section_origin);
+        let chunk = converted.entry(coords).or_insert_with(Chunk::new);
+
+        for (idx, palette_idx) in section.indices.iter().enumerate() {
+            let Some(block) = palette.get(*palette_idx as usize).copied().flatten() else {
+                continue;
+            };
+
+            let idx = idx as i32;
+            let x = idx % SECTION_SIZE;
+            let z = idx / SECTION_SIZE % SECTION_SIZE;
+            let y = idx / (SECTION_SIZE * SECTION_SIZE);
+            let inner = inner_origin + InnerChunkCoords::new(x, y, z);
+
+            chunk.set_block(inner, Some(block));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const BLOCK_COUNT: usize = (SECTION_SIZE * SECTION_SIZE * SECTION_SIZE) as usize;
+
+    /// Indices cycling through a palette, so every bit of an index is used somewhere.
+    fn indices(palette_size: usize) -> Vec<u16> {
+        (0..BLOCK_COUNT)
+            .map(|idx| (idx * 7 % palette_size) as u16)
+            .collect()
+    }
+
+    /// Packs indices the way Minecraft 1.16 and newer does, no index spans two longs.
+    fn pack_non_spanning(indices: &[u16], bits: usize) -> Vec<i64> {
+        let per_long = 64 / bits;
+
+        indices
+            .chunks(per_long)
+            .map(|chunk| {
+                chunk.iter().enumerate().fold(0u64, |long, (idx, value)| {
+                    long | (*value as u64) << (idx * bits)
+                }) as i64
+            })
+            .collect()
+    }
+
+    /// Packs indices the way versions before 1.16 do, as one continuous run of bits.
+    fn pack_spanning(indices: &[u16], bits: usize) -> Vec<i64> {
+        let mut longs = vec![0u64; (indices.len() * bits).div_ceil(64)];
+
+        for (idx, value) in indices.iter().enumerate() {
+            for bit in 0..bits {
+                if value >> bit & 1 == 1 {
+                    let position = idx * bits + bit;
+                    longs[position / 64] |= 1 << (position % 64);
+                }
+            }
+        }
+
+        longs.into_iter().map(|long| long as i64).collect()
+    }
+
+    #[test]
+    fn single_block_sections_have_no_data() {
+        assert_eq!(unpack_indices(&[], 1, true).unwrap(), vec![0; BLOCK_COUNT]);
+        assert_eq!(unpack_indices(&[], 5, false).unwrap(), vec![0; BLOCK_COUNT]);
+    }
+
+    #[test]
+    fn non_spanning_indices() {
+        // 5 bits leave 4 bits of every long unused
+        for (palette_size, bits) in [(2, 4), (17, 5), (100, 7)] {
+            let expected = indices(palette_size);
+            let data = pack_non_spanning(&expected, bits);
+
+            assert_eq!(
+                unpack_indices(&data, palette_size, true).unwrap(),
+                expected,
+                "palette of {palette_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn spanning_indices() {
+        for (palette_size, bits) in [(2, 4), (17, 5), (100, 7)] {
+            let expected = indices(palette_size);
+            let data = pack_spanning(&expected, bits);
+
+            assert_eq!(
+                unpack_indices(&data, palette_size, false).unwrap(),
+                expected,
+                "palette of {palette_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn missing_longs_are_an_error() {
+        let data = pack_non_spanning(&indices(17), 5);
+
+        assert!(unpack_indices(&data[..data.len() - 1], 17, true).is_err());
+        // the spanning layout of the same indices needs fewer longs
+        assert!(unpack_indices(&data[..320], 17, false).is_ok());
+        assert!(unpack_indices(&data[..319], 17, false).is_err());
+    }
+
+    /// Builds a region file with chunks at the given slots, each as its compression type and stored bytes.
+    fn region_file(chunks: &[(usize, u8, Vec<u8>)]) -> Vec<u8> {
+        // the location table is followed by a table of timestamps
+        let mut bytes = vec![0; SECTOR_SIZE * 2];
+
+        for (slot, compression, data) in chunks {
+            let sector = bytes.len() / SECTOR_SIZE;
+            let entry = slot * 4;
+
+            bytes[entry..entry + 3].copy_from_slice(&(sector as u32).to_be_bytes()[1..]);
+            bytes[entry + 3] = 1;
+
+            bytes.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+            bytes.push(*compression);
+            bytes.extend_from_slice(data);
+            bytes.resize(bytes.len().next_multiple_of(SECTOR_SIZE), 0);
+        }
+
+        bytes
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+
+        encoder.finish().unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn region_chunks_are_decompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.mca");
+
+        let bytes = region_file(&[
+            (0, 2, zlib(b"zlib chunk")),
+            (5, 1, gzip(b"gzip chunk")),
+            (1023, 3, b"uncompressed chunk".to_vec()),
+        ]);
+        fs::write(&path, bytes).unwrap();
+
+        let chunks = read_region(&path).unwrap();
+
+        assert_eq!(chunks.len(), 1024);
+        assert_eq!(chunks[0].as_deref(), Some(&b"zlib chunk"[..]));
+        assert_eq!(chunks[5].as_deref(), Some(&b"gzip chunk"[..]));
+        assert_eq!(chunks[1023].as_deref(), Some(&b"uncompressed chunk"[..]));
+        assert_eq!(chunks.iter().flatten().count(), 3);
+    }
+
+    #[test]
+    fn unreadable_region_chunks_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.mca");
+
+        let mut bytes = region_file(&[
+            (0, 2, zlib(b"intact chunk")),
+            // stored in a separate .mcc file
+            (1, 2 | 128, Vec::new()),
+            (2, 2, b"not zlib".to_vec()),
+        ]);
+        // points past the end of the file
+        bytes[12..15].copy_from_slice(&[0, 0, 100]);
+        bytes[15] = 1;
+        fs::write(&path, bytes).unwrap();
+
+        let chunks = read_region(&path).unwrap();
+
+        assert_eq!(chunks[0].as_deref(), Some(&b"intact chunk"[..]));
+        assert!(chunks[1..].iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn empty_region_files_have_no_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.mca");
+        fs::write(&path, []).unwrap();
+
+        let chunks = read_region(&path).unwrap();
+
+        assert_eq!(chunks.len(), 1024);
+        assert!(chunks.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn region_coords_are_parsed_from_file_names() {
+        assert_eq!(region_coords(Path::new("world/r.-1.2.mca")), Some((-1, 2)));
+        assert_eq!(region_coords(Path::new("r.1.2.3.mca")), None);
+        assert_eq!(region_coords(Path::new("r.1.2.mcc")), None);
+    }
+}
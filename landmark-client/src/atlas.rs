@@ -0,0 +1,235 @@
+use std::collections::{HashMap, HashSet};
+
+use image::RgbaImage;
+
+/// Normalized texture coordinates of a rectangle in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub min: glam::Vec2,
+    pub max: glam::Vec2,
+}
+
+#[derive(Debug, Clone)]
+struct AtlasEntry {
+    slot: u32,
+    image: RgbaImage,
+}
+
+/// Result of replacing the set of textures stored in the atlas.
+#[derive(Debug, Default)]
+pub struct AtlasUpdate {
+    /// Names of textures which were added, removed or whose pixels changed.
+    pub changed_textures: HashSet<String>,
+    /// Slots whose pixels have to be uploaded to the GPU again.
+    pub dirty_slots: Vec<u32>,
+}
+
+/// Grid of equally sized block textures.
+///
+/// The atlas has a fixed size, so slots of textures which didn't change keep their UVs
+/// and only chunks using changed textures have to be remeshed after an update.
+/// Every tile is surrounded by copies of its edge texels, so filtering the smaller mip levels
+/// near the edge of a tile doesn't blend in the tiles next to it.
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    image: RgbaImage,
+    /// Occupancy of each slot, the first one is reserved for a white texture.
+    slots: Vec<bool>,
+    entries: HashMap<String, AtlasEntry>,
+}
+
+impl TextureAtlas {
+    pub const TILE_SIZE: u32 = 16;
+    pub const TILES_PER_SIDE: u32 = 16;
+    /// Texels repeating the edges on each side of a tile.
+    pub const PADDING: u32 = 8;
+    /// Size of a slot together with its padding.
+    pub const CELL_SIZE: u32 = Self::TILE_SIZE + 2 * Self::PADDING;
+    pub const SIZE: u32 = Self::CELL_SIZE * Self::TILES_PER_SIDE;
+    /// Mip levels of the atlas texture, the padding of the smallest one is still a texel wide.
+    pub const MIP_LEVELS: u32 = Self::PADDING.trailing_zeros() + 1;
+    /// Slot used by blocks without a texture, multiplying their color by white.
+    pub const WHITE_SLOT: u32 = 0;
+
+    pub fn new() -> Self {
+        let mut image = RgbaImage::new(Self::SIZE, Self::SIZE);
+        let mut slots = vec![false; (Self::TILES_PER_SIDE * Self::TILES_PER_SIDE) as usize];
+
+        let white = RgbaImage::from_pixel(
+            Self::TILE_SIZE,
+            Self::TILE_SIZE,
+            image::Rgba([255, 255, 255, 255]),
+        );
+        put_tile(&mut image, Self::WHITE_SLOT, &white);
+        slots[Self::WHITE_SLOT as usize] = true;
+
+        Self {
+            image,
+            slots,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Returns the pixel position of the top left corner of a slot, including its padding.
+    pub fn cell_origin(slot: u32) -> (u32, u32) {
+        (
+            (slot % Self::TILES_PER_SIDE) * Self::CELL_SIZE,
+            (slot / Self::TILES_PER_SIDE) * Self::CELL_SIZE,
+        )
+    }
+
+    /// Returns the pixel position of the top left corner of the texture in a slot.
+    pub fn slot_origin(slot: u32) -> (u32, u32) {
+        let (x, y) = Self::cell_origin(slot);
+        (x + Self::PADDING, y + Self::PADDING)
+    }
+
+    /// Returns the UVs of a texture, or of the white slot if the texture isn't present.
+    pub fn uv(&self, texture: Option<&str>) -> UvRect {
+        let slot = texture
+            .and_then(|name| self.entries.get(name))
+            .map(|entry| entry.slot)
+            .unwrap_or(Self::WHITE_SLOT);
+
+        Self::slot_uv(slot)
+    }
+
+    pub fn slot_uv(slot: u32) -> UvRect {
+        let (x, y) = Self::slot_origin(slot);
+        // the padding repeats the edges, so sampling right at them doesn't reach neighbouring slots
+        let min = glam::Vec2::new(x as f32, y as f32);
+        let max = min + glam::Vec2::splat(Self::TILE_SIZE as f32);
+
+        UvRect {
+            min: min / Self::SIZE as f32,
+            max: max / Self::SIZE as f32,
+        }
+    }
+
+    /// Replaces the stored textures, only touching slots of textures which changed.
+    pub fn update(&mut self, textures: &HashMap<String, RgbaImage>) -> AtlasUpdate {
+        let mut update = AtlasUpdate::default();
+
+        // free slots of textures which were removed or modified
+        let stale: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(name, entry)| textures.get(*name) != Some(&entry.image))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in stale {
+            if let Some(entry) = self.entries.remove(&name) {
+                self.slots[entry.slot as usize] = false;
+            }
+
+            update.changed_textures.insert(name);
+        }
+
+        // sort new textures, so the same packs always produce the same layout
+        let mut added: Vec<(&String, &RgbaImage)> = textures
+            .iter()
+            .filter(|(name, _)| !self.entries.contains_key(*name))
+            .collect();
+        added.sort_by_key(|(name, _)| *name);
+
+        for (name, image) in added {
+            update.changed_textures.insert(name.clone());
+
+            let Some(slot) = self.slots.iter().position(|used| !used) else {
+                log::warn!("Texture atlas is full, texture {name} will not be displayed");
+                continue;
+            };
+
+            let slot = slot as u32;
+            put_tile(&mut self.image, slot, image);
+
+            self.slots[slot as usize] = true;
+            self.entries.insert(
+                name.clone(),
+                AtlasEntry {
+                    slot,
+                    image: image.clone(),
+                },
+            );
+            update.dirty_slots.push(slot);
+        }
+
+        update
+    }
+}
+
+/// Writes a tile into its slot and fills the padding around it with copies of its edge texels.
+fn put_tile(atlas: &mut RgbaImage, slot: u32, tile: &RgbaImage) {
+    let (x, y) = TextureAtlas::cell_origin(slot);
+    let padding = TextureAtlas::PADDING as i32;
+
+    for py in 0..TextureAtlas::CELL_SIZE {
+        for px in 0..TextureAtlas::CELL_SIZE {
+            let tx = (px as i32 - padding).clamp(0, tile.width() as i32 - 1);
+            let ty = (py as i32 - padding).clamp(0, tile.height() as i32 - 1);
+
+            atlas.put_pixel(x + px, y + py, *tile.get_pixel(tx as u32, ty as u32));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_repeats_the_edges_of_tiles() {
+        let mut tile = RgbaImage::new(TextureAtlas::TILE_SIZE, TextureAtlas::TILE_SIZE);
+        tile.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+
+        let mut atlas = TextureAtlas::new();
+        let textures = HashMap::from([("stone".to_string(), tile)]);
+        let update = atlas.update(&textures);
+
+        let (x, y) = TextureAtlas::cell_origin(update.dirty_slots[0]);
+        let corner = TextureAtlas::PADDING;
+
+        for offset in 0..=corner {
+            assert_eq!(
+                atlas.image().get_pixel(x + offset, y + corner).0,
+                [255, 0, 0, 255]
+            );
+            assert_eq!(
+                atlas.image().get_pixel(x + corner, y + offset).0,
+                [255, 0, 0, 255]
+            );
+        }
+        assert_eq!(
+            atlas.image().get_pixel(x + corner + 1, y + corner).0,
+            [0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn smallest_mip_level_keeps_a_texel_of_padding() {
+        let scale = 1 << (TextureAtlas::MIP_LEVELS - 1);
+
+        assert_eq!(TextureAtlas::PADDING / scale, 1);
+        assert_eq!(TextureAtlas::CELL_SIZE % scale, 0);
+    }
+
+    #[test]
+    fn uvs_cover_the_tile_without_its_padding() {
+        let uv = TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT);
+        let size = TextureAtlas::SIZE as f32;
+
+        assert_eq!(
+            uv.min * size,
+            glam::Vec2::splat(TextureAtlas::PADDING as f32)
+        );
+        assert_eq!(
+            (uv.max - uv.min) * size,
+            glam::Vec2::splat(TextureAtlas::TILE_SIZE as f32)
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Measures how fast chunks are generated and meshed, so optimizations of either can be compared.
+//!
+//! Chunks are generated from a heightmap of noise with a fixed seed, then meshed once all of them
+//! exist, so faces between chunks are hidden like in the game. Both run on a single thread.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::LandmarkError,
+    game_map::{Chunk, ChunkCoords, GameMap},
+    heightmap::Heightmap,
+    loader::ResourceDictionary,
+    mesher::mesh_loaded_chunk,
+    worldgen::Terrain,
+};
+
+/// Heights of the generated hills, spanning three layers of chunks.
+const MIN_HEIGHT: i32 = -24;
+const MAX_HEIGHT: i32 = 56;
+const LAYERS: i32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub chunks: usize,
+    pub generation: Duration,
+    pub vertices: u64,
+    pub meshing: Duration,
+}
+
+impl BenchReport {
+    /// Returns how many blocks were generated per second, including air.
+    pub fn blocks_per_second(&self) -> f64 {
+        (self.chunks as u64 * Chunk::BLOCKS_COUNT as u64) as f64 / self.generation.as_secs_f64()
+    }
+
+    pub fn vertices_per_second(&self) -> f64 {
+        self.vertices as f64 / self.meshing.as_secs_f64()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Generated {} chunks in {:.1?}: {:.0} blocks/s",
+            self.chunks,
+            self.generation,
+            self.blocks_per_second()
+        )?;
+        write!(
+            f,
+            "Meshed {} vertices in {:.1?}: {:.0} vertices/s",
+            self.vertices,
+            self.meshing,
+            self.vertices_per_second()
+        )
+    }
+}
+
+/// Returns the coordinates of the chunks, in columns of all layers around the origin.
+fn chunk_coords(chunks: usize, side: i32) -> Vec<ChunkCoords> {
+    (0..side * side)
+        .flat_map(|idx| {
+            let (x, z) = (idx % side - side / 2, idx / side - side / 2);
+            (0..LAYERS).map(move |y| ChunkCoords::new(x, y - 1, z))
+        })
+        .take(chunks)
+        .collect()
+}
+
+/// Generates and meshes the chunks with the built-in blocks.
+pub fn run(chunks: usize, seed: u64) -> Result<BenchReport, LandmarkError> {
+    let resource_dictionary = ResourceDictionary::builtin(Vec::new())?;
+
+    let side = (chunks as f64 / LAYERS as f64).sqrt().ceil() as i32;
+    let coords = chunk_coords(chunks, side);
+
+    let terrain = Terrain::Heightmap(Heightmap::from_noise(
+        seed,
+        (side * Chunk::SIZE) as u32,
+        MIN_HEIGHT,
+        MAX_HEIGHT,
+        resource_dictionary.get_block_id("Grass"),
+        resource_dictionary.get_block_id("Stone"),
+    ));
+
+    let mut game_map = GameMap::new();
+
+    let started = Instant::now();
+    for coords in coords.iter() {
+        game_map.chunks.insert(*coords, terrain.generate(*coords));
+    }
+    let generation = started.elapsed();
+
+    let started = Instant::now();
+    let vertices = coords
+        .iter()
+        .map(|coords| {
+            let meshes = mesh_loaded_chunk(&game_map, *coords, &resource_dictionary);
+
+            (meshes.solid.vertices.len() + meshes.fluid.vertices.len()) as u64
+        })
+        .sum();
+    let meshing = started.elapsed();
+
+    Ok(BenchReport {
+        chunks: coords.len(),
+        generation,
+        vertices,
+        meshing,
+    })
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+
+use game_loop::winit::event::{KeyboardInput, VirtualKeyCode};
+
+/// A key as it appears in the settings file.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Key {
+    /// Physical position of the key, independent of the keyboard layout.
+    Scancode(u32),
+    /// Key producing the given symbol in the current layout.
+    Virtual(VirtualKeyCode),
+}
+
+impl Key {
+    pub fn matches(&self, event: &KeyboardInput) -> bool {
+        match self {
+            Key::Scancode(scancode) => *scancode == event.scancode,
+            Key::Virtual(keycode) => Some(*keycode) == event.virtual_keycode,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    /// Selects the hotbar slot with the given index, starting at zero.
+    SelectSlot(u8),
+    /// Releases the cursor and opens the pause menu.
+    ReleaseCursor,
+    /// Opens or closes the inventory, where stacks can be moved with the mouse.
+    ToggleInventory,
+    /// Opens or closes the map of the explored world, scrolling zooms it.
+    ToggleMap,
+    /// Opens the chat with a `/` typed, so commands can be entered right away.
+    OpenConsole,
+    OpenChat,
+    /// Leaves the pause menu or the console.
+    Close,
+    /// Sends the line typed into the chat.
+    Submit,
+    DeleteCharacter,
+    /// Replaces the typed line with the previous line sent.
+    RecallOlder,
+    RecallNewer,
+    ScrollChatUp,
+    ScrollChatDown,
+    ToggleChunkBorders,
+    ToggleWireframe,
+    ReloadResources,
+    CyclePresentMode,
+    ToggleProfiler,
+    DumpProfile,
+    /// Hold Shift to hide the HUD, Ctrl for a higher resolution and Alt to copy it to the clipboard.
+    Screenshot,
+    ToggleSpawnStats,
+    /// Shows how many chunks and entities were drawn and culled, and how much memory models use.
+    ToggleDrawStats,
+    ToggleFullscreen,
+    /// Hold Ctrl to undo the last block edit, or Ctrl and Shift to redo it.
+    Undo,
+}
+
+/// State of the game deciding which set of bindings is active.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputContext {
+    #[default]
+    Gameplay,
+    /// The cursor is released to interact with menus.
+    Ui,
+    /// The chat or console is open, keys not bound here are used for typing.
+    Console,
+}
+
+/// Key bound to each action within one context.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct BindingSet(BTreeMap<Action, Key>);
+
+impl BindingSet {
+    fn new(bindings: &[(Action, Key)]) -> Self {
+        Self(bindings.iter().copied().collect())
+    }
+
+    /// Returns the action bound to the key of the event.
+    /// If the key is bound multiple times, the first action in declaration order wins.
+    pub fn action_for(&self, event: &KeyboardInput) -> Option<Action> {
+        self.0
+            .iter()
+            .find(|(_, key)| key.matches(event))
+            .map(|(action, _)| *action)
+    }
+
+    /// Returns keys bound to more than one action together with those actions.
+    pub fn conflicts(&self) -> Vec<(Key, Vec<Action>)> {
+        let mut actions_by_key: BTreeMap<Key, Vec<Action>> = BTreeMap::new();
+
+        for (action, key) in self.0.iter() {
+            actions_by_key.entry(*key).or_default().push(*action);
+        }
+
+        actions_by_key
+            .into_iter()
+            .filter(|(_, actions)| actions.len() > 1)
+            .collect()
+    }
+
+    /// Adds bindings of `defaults` for actions which are not bound.
+    fn fill_missing(&mut self, defaults: &BindingSet) {
+        for (action, key) in defaults.0.iter() {
+            self.0.entry(*action).or_insert(*key);
+        }
+    }
+}
+
+/// Binding sets of every input context, so the same key can do different things in each of them.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub gameplay: BindingSet,
+    pub ui: BindingSet,
+    pub console: BindingSet,
+}
+
+impl KeyBindings {
+    pub fn context(&self, context: InputContext) -> &BindingSet {
+        match context {
+            InputContext::Gameplay => &self.gameplay,
+            InputContext::Ui => &self.ui,
+            InputContext::Console => &self.console,
+        }
+    }
+
+    /// Binds actions missing from the settings file to their default keys and warns about conflicts.
+    pub fn validate(&mut self) {
+        let defaults = Self::default();
+
+        self.gameplay.fill_missing(&defaults.gameplay);
+        self.ui.fill_missing(&defaults.ui);
+        self.console.fill_missing(&defaults.console);
+
+        for (name, set) in [
+            ("gameplay", &self.gameplay),
+            ("ui", &self.ui),
+            ("console", &self.console),
+        ] {
+            for (key, actions) in set.conflicts() {
+                log::warn!(
+                    "Key {key:?} is bound to multiple {name} actions {actions:?}, only {:?} will be used",
+                    actions[0]
+                );
+            }
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use VirtualKeyCode as Vk;
+
+        Self {
+            gameplay: BindingSet::new(&[
+                (Action::MoveForward, Key::Scancode(17)),  // W
+                (Action::MoveBackward, Key::Scancode(31)), // S
+                (Action::MoveLeft, Key::Scancode(30)),     // A
+                (Action::MoveRight, Key::Scancode(32)),    // D
+                (Action::MoveUp, Key::Scancode(57)),       // Space
+                (Action::MoveDown, Key::Scancode(42)),     // LShift
+                (Action::SelectSlot(0), Key::Scancode(2)), // 1
+                (Action::SelectSlot(1), Key::Scancode(3)),
+                (Action::SelectSlot(2), Key::Scancode(4)),
+                (Action::SelectSlot(3), Key::Scancode(5)),
+                (Action::SelectSlot(4), Key::Scancode(6)),
+                (Action::SelectSlot(5), Key::Scancode(7)),
+                (Action::SelectSlot(6), Key::Scancode(8)),
+                (Action::SelectSlot(7), Key::Scancode(9)),
+                (Action::SelectSlot(8), Key::Scancode(10)), // 9
+                (Action::ReleaseCursor, Key::Virtual(Vk::Escape)),
+                (Action::ToggleInventory, Key::Virtual(Vk::E)),
+                (Action::ToggleMap, Key::Virtual(Vk::M)),
+                (Action::OpenConsole, Key::Virtual(Vk::Grave)),
+                (Action::OpenChat, Key::Virtual(Vk::T)),
+                (Action::Screenshot, Key::Virtual(Vk::F2)),
+                (Action::ToggleChunkBorders, Key::Virtual(Vk::F3)),
+                (Action::ToggleWireframe, Key::Virtual(Vk::F4)),
+                (Action::ReloadResources, Key::Virtual(Vk::F5)),
+                (Action::CyclePresentMode, Key::Virtual(Vk::F6)),
+                (Action::ToggleProfiler, Key::Virtual(Vk::F7)),
+                (Action::DumpProfile, Key::Virtual(Vk::F8)),
+                (Action::ToggleSpawnStats, Key::Virtual(Vk::F9)),
+                (Action::ToggleDrawStats, Key::Virtual(Vk::F10)),
+                (Action::ToggleFullscreen, Key::Virtual(Vk::F11)),
+                (Action::Undo, Key::Virtual(Vk::Z)),
+            ]),
+            ui: BindingSet::new(&[
+                (Action::Close, Key::Virtual(Vk::Escape)),
+                (Action::ToggleInventory, Key::Virtual(Vk::E)),
+                (Action::ToggleMap, Key::Virtual(Vk::M)),
+                (Action::Screenshot, Key::Virtual(Vk::F2)),
+                (Action::ToggleChunkBorders, Key::Virtual(Vk::F3)),
+                (Action::ToggleWireframe, Key::Virtual(Vk::F4)),
+                (Action::ReloadResources, Key::Virtual(Vk::F5)),
+                (Action::CyclePresentMode, Key::Virtual(Vk::F6)),
+                (Action::ToggleProfiler, Key::Virtual(Vk::F7)),
+                (Action::DumpProfile, Key::Virtual(Vk::F8)),
+                (Action::ToggleSpawnStats, Key::Virtual(Vk::F9)),
+                (Action::ToggleDrawStats, Key::Virtual(Vk::F10)),
+                (Action::ToggleFullscreen, Key::Virtual(Vk::F11)),
+            ]),
+            console: BindingSet::new(&[
+                (Action::Close, Key::Virtual(Vk::Escape)),
+                (Action::Submit, Key::Virtual(Vk::Return)),
+                (Action::DeleteCharacter, Key::Virtual(Vk::Back)),
+                (Action::RecallOlder, Key::Virtual(Vk::Up)),
+                (Action::RecallNewer, Key::Virtual(Vk::Down)),
+                (Action::ScrollChatUp, Key::Virtual(Vk::PageUp)),
+                (Action::ScrollChatDown, Key::Virtual(Vk::PageDown)),
+            ]),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+/// Kind of landscape of a column of blocks, assigned by the world generator.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Biome {
+    #[default]
+    Plains,
+    Hills,
+    Mountains,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 3] = [Biome::Plains, Biome::Hills, Biome::Mountains];
+
+    /// Name used in spawn rules and world saves.
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Plains => "plains",
+            Biome::Hills => "hills",
+            Biome::Mountains => "mountains",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|biome| biome.name() == name)
+    }
+
+    /// Number the server sends instead of the name, the position in `ALL`.
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    /// Chooses the biome of a column by where its height lies between the lowest and the highest terrain,
+    /// the lowest third are plains and the highest third mountains.
+    pub fn from_height(height: i32, min_height: i32, max_height: i32) -> Self {
+        let range = (max_height - min_height).max(1) as f32;
+        let relative = (height - min_height) as f32 / range;
+
+        if relative < 1.0 / 3.0 {
+            Biome::Plains
+        } else if relative < 2.0 / 3.0 {
+            Biome::Hills
+        } else {
+            Biome::Mountains
+        }
+    }
+}
+
+impl fmt::Display for Biome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
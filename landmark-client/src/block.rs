@@ -1,7 +1,239 @@
-use crate::color::Color;
+use crate::{color::Color, game_map::FaceDirection, lights::LightSource};
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct BlockData {
     pub name: String,
     pub color: Color,
+    /// Name of a file in `textures/blocks` without the extension, multiplied by the color.
+    #[serde(default)]
+    pub texture: Option<String>,
+    /// Additional textures used instead of `texture` at some positions, so large surfaces don't look tiled.
+    /// The texture of each block is picked by a hash of its position, so it never changes between meshes.
+    #[serde(default)]
+    pub variants: Vec<String>,
+    /// Name of a texture strip with the tiles of `ConnectedTile` from left to right, replacing `texture`.
+    /// Faces are then drawn as if blocks of the same type next to each other were one surface, like glass panes.
+    #[serde(default)]
+    pub connected_texture: Option<String>,
+    /// Appearance of single faces, like grass having a different top, sides and bottom.
+    #[serde(default)]
+    pub faces: BlockFaces,
+    /// Seconds it takes to break the block in survival, zero breaks it at once.
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    /// Falls down when the block below it is removed, like sand.
+    #[serde(default)]
+    pub falls: bool,
+    /// Makes the block a portal, a player stepping onto it is moved to the linked position.
+    #[serde(default)]
+    pub portal: Option<PortalLink>,
+    /// Changes the block over time, run when a random tick hits it.
+    #[serde(default)]
+    pub random_tick: Option<RandomTick>,
+    /// Light given off by the block, like lava glowing. Such blocks are drawn brighter than white, so
+    /// they bloom.
+    #[serde(default)]
+    pub light: Option<LightSource>,
+    /// Drawn as a fluid surface with animated waves, like water. Faces between two fluid blocks are hidden,
+    /// while faces of other blocks under the fluid stay visible through it.
+    #[serde(default)]
+    pub fluid: bool,
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+impl BlockData {
+    /// Name of the placeholder block which replaces saved blocks that are no longer defined.
+    pub const MISSING: &'static str = "Missing";
+
+    /// Creates the placeholder block, always defined after all other blocks.
+    /// It's plain magenta, so missing blocks stand out without hiding the shape of builds.
+    pub fn missing() -> Self {
+        Self {
+            name: Self::MISSING.to_string(),
+            color: Color {
+                r: 255,
+                g: 0,
+                b: 255,
+            },
+            texture: None,
+            variants: Vec::new(),
+            connected_texture: None,
+            faces: BlockFaces::default(),
+            hardness: default_hardness(),
+            falls: false,
+            portal: None,
+            random_tick: None,
+            light: None,
+            fluid: false,
+        }
+    }
+
+    /// Returns the main texture followed by its variants, variants are ignored without a main texture.
+    pub fn textures(&self) -> impl Iterator<Item = &String> {
+        self.texture
+            .iter()
+            .chain(self.variants.iter().take_while(|_| self.texture.is_some()))
+    }
+
+    /// Returns the textures only used by some faces.
+    pub fn face_textures(&self) -> impl Iterator<Item = &String> {
+        self.faces.all().filter_map(|face| face.texture.as_ref())
+    }
+
+    /// Returns the color of a face, multiplied by its texture.
+    pub fn face_color(&self, face_dir: FaceDirection) -> Color {
+        self.faces
+            .get(face_dir, |face| face.color)
+            .unwrap_or(self.color)
+    }
+
+    /// Returns the names the tiles of the connected texture are stored under, in the order of `ConnectedTile`.
+    pub fn connected_tiles(&self) -> Option<[String; ConnectedTile::COUNT]> {
+        let texture = self.connected_texture.as_ref()?;
+
+        Some(std::array::from_fn(|idx| connected_tile_name(texture, idx)))
+    }
+}
+
+/// Overrides of the texture or color of some faces, the more specific faces take priority over `all`.
+///
+/// ```ron
+/// faces: (
+///     top: Some((texture: Some("grass_top"))),
+///     side: Some((texture: Some("grass_side"))),
+///     bottom: Some((texture: Some("soil"), color: Some((r: 150, g: 100, b: 0)))),
+/// ),
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BlockFaces {
+    pub all: Option<FaceAppearance>,
+    pub top: Option<FaceAppearance>,
+    /// All four horizontal faces.
+    pub side: Option<FaceAppearance>,
+    pub bottom: Option<FaceAppearance>,
+}
+
+impl BlockFaces {
+    /// Returns the first value set for the face, from the most specific override to `all`.
+    pub fn get<T>(
+        &self,
+        face_dir: FaceDirection,
+        value: impl Fn(&FaceAppearance) -> Option<T>,
+    ) -> Option<T> {
+        let specific = match face_dir {
+            FaceDirection::PosY => &self.top,
+            FaceDirection::NegY => &self.bottom,
+            _ => &self.side,
+        };
+
+        specific
+            .as_ref()
+            .and_then(&value)
+            .or_else(|| self.all.as_ref().and_then(&value))
+    }
+
+    fn all(&self) -> impl Iterator<Item = &FaceAppearance> {
+        [&self.all, &self.top, &self.side, &self.bottom]
+            .into_iter()
+            .flatten()
+    }
+}
+
+/// Texture or color replacing the ones of the block on some faces, unset values are taken from the block.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FaceAppearance {
+    /// Name of a file in `textures/blocks` without the extension, used without the variants of the block.
+    pub texture: Option<String>,
+    pub color: Option<Color>,
+}
+
+/// Where a portal block leads, the position of the player is scaled horizontally and then offset,
+/// so a scale of 8 moves eight blocks on the other side for every block walked here.
+///
+/// ```ron
+/// portal: Some((scale: 8.0, offset: (0.0, 0.0, 4096.0))),
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PortalLink {
+    pub scale: f64,
+    pub offset: [f64; 3],
+}
+
+impl Default for PortalLink {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            offset: [0.0; 3],
+        }
+    }
+}
+
+impl PortalLink {
+    /// Returns the position a player at `position` arrives at.
+    pub fn destination(&self, position: glam::DVec3) -> glam::DVec3 {
+        glam::DVec3::new(position.x * self.scale, position.y, position.z * self.scale)
+            + glam::DVec3::from_array(self.offset)
+    }
+}
+
+/// What a block does when a random tick hits it. Every server tick a few random blocks of the chunks players
+/// have in view are ticked, so a single block is hit every couple of minutes on average.
+///
+/// ```ron
+/// random_tick: Some(Spread(onto: "Soil")),
+/// random_tick: Some(Grow(into: "Ripe Wheat")),
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum RandomTick {
+    /// Turns a random `onto` block next to it into this block if the sky is open above it, like grass
+    /// growing over soil. Turns into `onto` itself once a block is placed on top of it.
+    Spread { onto: String },
+    /// Turns into the next growth stage `into` while the sky is open above it, like crops.
+    Grow { into: String },
+}
+
+/// Returns the name a tile of a connected texture strip is stored under.
+pub fn connected_tile_name(texture: &str, idx: usize) -> String {
+    format!("{texture}#{idx}")
+}
+
+/// Tiles of a connected texture, each quarter of a face uses the matching quarter of one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectedTile {
+    /// Borders on all sides, looks like the block has no neighbours.
+    Isolated,
+    /// Connected horizontally, borders only on the top and bottom edges.
+    AlongU,
+    /// Connected vertically, borders only on the left and right edges.
+    AlongV,
+    /// No borders on the edges, only in the corners.
+    InnerCorner,
+    /// No borders at all.
+    Inside,
+}
+
+impl ConnectedTile {
+    pub const COUNT: usize = 5;
+}
+
+/// Deterministic hash of a block position, used to pick texture variants.
+pub fn position_hash(position: glam::IVec3) -> u32 {
+    let mut hash = (position.x as u32).wrapping_mul(0x8da6_b343)
+        ^ (position.y as u32).wrapping_mul(0xd816_3841)
+        ^ (position.z as u32).wrapping_mul(0xcb1a_b31f);
+
+    // finalizer of murmur3, spreads neighbouring positions over the whole range
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+
+    hash
 }
@@ -1,12 +1,19 @@
 use shipyard::*;
 use wgpu::util::DeviceExt;
 
-use crate::rendererer::Renderer;
+use crate::{game_map::ChunkCoords, rendererer::Renderer};
 
+/// Distance of the far side of the frozen view drawn as lines, the view itself has no far plane.
+const FROZEN_VIEW_DEPTH: f32 = 48.0;
+
+/// The camera position is kept in f64 and everything is rendered relative to the chunk containing it,
+/// so vertices don't jitter from f32 imprecision far away from the world origin.
 #[derive(Debug, Unique)]
 pub struct Camera {
-    pub eye: glam::Vec3,
-    pub target: glam::Vec3,
+    pub eye: glam::DVec3,
+    pub target: glam::DVec3,
+    /// Chunk used as the origin of the render space, updated together with the view projection.
+    pub origin: ChunkCoords,
     pub yaw: f32,
     pub pitch: f32,
     fovy: f32,
@@ -17,14 +24,22 @@ pub struct Camera {
 }
 
 impl Camera {
+    /// Where the player starts and respawns.
+    pub const SPAWN_POSITION: glam::DVec3 = glam::DVec3::new(0.0, 0.0, -1.0);
+
     pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
-        let eye = glam::Vec3::new(0.0, 0.0, -1.0);
-        let target = glam::Vec3::ZERO;
+        let eye = Self::SPAWN_POSITION;
+        let target = glam::DVec3::ZERO;
+        let origin = ChunkCoords::from_world_position(eye);
         let aspect = config.width as f32 / config.height as f32;
         let fovy: f32 = 75.0;
         let near = 0.1;
 
-        let view = glam::Mat4::look_at_lh(eye, target, glam::Vec3::Y);
+        let view = glam::Mat4::look_at_lh(
+            (eye - origin.as_world_position()).as_vec3(),
+            (target - origin.as_world_position()).as_vec3(),
+            glam::Vec3::Y,
+        );
         let proj = glam::Mat4::perspective_infinite_lh(fovy.to_radians(), aspect, near);
 
         let view_proj = proj * view;
@@ -38,6 +53,7 @@ impl Camera {
         Self {
             eye,
             target,
+            origin,
             yaw: 0.0,
             pitch: 0.0,
             fovy,
@@ -48,6 +64,59 @@ impl Camera {
         }
     }
 
+    /// Returns the planes of the view in the render space, relative to the origin chunk.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(self.view_proj)
+    }
+
+    /// Returns the current view, which keeps culling after the camera moves on.
+    pub fn freeze(&self) -> FrozenView {
+        FrozenView {
+            view_proj: self.view_proj,
+            origin: self.origin,
+            near: self.near,
+        }
+    }
+
+    /// Returns the distance of the near plane.
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    /// Returns the normalized direction the camera is looking at.
+    pub fn look_direction(&self) -> glam::DVec3 {
+        (self.target - self.eye).normalize()
+    }
+
+    /// Returns the tangent of the angle between the look direction and the corners of the view.
+    pub fn tan_half_diagonal_fov(&self) -> f64 {
+        let tan_half_fovy = (self.fovy.to_radians() as f64 / 2.0).tan();
+
+        tan_half_fovy * (1.0 + (self.aspect * self.aspect) as f64).sqrt()
+    }
+
+    /// Projects a world position to pixel coordinates with the origin in the top left corner.
+    /// Returns None for positions behind the camera.
+    pub fn world_to_screen(
+        &self,
+        position: glam::DVec3,
+        screen_size: glam::Vec2,
+    ) -> Option<glam::Vec2> {
+        let relative = (position - self.origin.as_world_position()).as_vec3();
+        let clip = self.view_proj * relative.extend(1.0);
+
+        if clip.w <= self.near {
+            return None;
+        }
+
+        let ndc = clip.truncate().truncate() / clip.w;
+
+        Some(glam::Vec2::new(
+            (ndc.x + 1.0) / 2.0 * screen_size.x,
+            (1.0 - ndc.y) / 2.0 * screen_size.y,
+        ))
+    }
+
     pub fn update_view_projection_matrix(&mut self, renderer: &Renderer) {
         self.aspect = renderer.config.width as f32 / renderer.config.height as f32;
 
@@ -56,9 +125,12 @@ impl Camera {
         look_direction = glam::Mat3::from_rotation_y(self.yaw.to_radians()) * look_direction;
         look_direction = look_direction.normalize();
 
-        self.target = self.eye + look_direction;
+        self.target = self.eye + look_direction.as_dvec3();
+        self.origin = ChunkCoords::from_world_position(self.eye);
 
-        let view = glam::Mat4::look_at_lh(self.eye, self.target, glam::Vec3::Y);
+        // subtract the origin in f64 before converting, so the camera stays precise
+        let relative_eye = (self.eye - self.origin.as_world_position()).as_vec3();
+        let view = glam::Mat4::look_to_lh(relative_eye, look_direction, glam::Vec3::Y);
         let proj =
             glam::Mat4::perspective_infinite_lh(self.fovy.to_radians(), self.aspect, self.near);
 
@@ -70,6 +142,74 @@ impl Camera {
     }
 }
 
+/// View of the camera at the moment it was frozen.
+#[derive(Debug, Clone, Copy)]
+pub struct FrozenView {
+    view_proj: glam::Mat4,
+    /// Origin of the render space at the time, the camera may have moved to another one since.
+    pub origin: ChunkCoords,
+    near: f32,
+}
+
+impl FrozenView {
+    /// Returns the planes of the view in the render space around `origin`.
+    pub fn frustum(&self, origin: ChunkCoords) -> Frustum {
+        let offset = origin.translation_from(self.origin);
+
+        Frustum::from_view_projection(self.view_proj * glam::Mat4::from_translation(offset))
+    }
+
+    /// Returns the corners of the view on the near plane and `FROZEN_VIEW_DEPTH` away, relative to
+    /// the origin it was frozen in. They are indexed with bits: right = 1, top = 2, far = 4.
+    pub fn corners(&self) -> [glam::Vec3; 8] {
+        let inverse = self.view_proj.inverse();
+        // the infinite projection maps a distance d to the depth 1 - near / d
+        let far_depth = 1.0 - self.near / FROZEN_VIEW_DEPTH;
+
+        std::array::from_fn(|idx| {
+            let x = if idx & 1 != 0 { 1.0 } else { -1.0 };
+            let y = if idx & 2 != 0 { 1.0 } else { -1.0 };
+            let z = if idx & 4 != 0 { far_depth } else { 0.0 };
+
+            inverse.project_point3(glam::Vec3::new(x, y, z))
+        })
+    }
+}
+
+/// Planes bounding the visible part of the render space, with normals pointing inside.
+/// The projection has no far plane, so only the near and the four side planes are kept.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [glam::Vec4; 5],
+}
+
+impl Frustum {
+    /// Extracts the planes from the rows of a view projection matrix with depth from 0 to 1.
+    pub fn from_view_projection(view_proj: glam::Mat4) -> Self {
+        let (x, y, z, w) = (
+            view_proj.row(0),
+            view_proj.row(1),
+            view_proj.row(2),
+            view_proj.row(3),
+        );
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z],
+        }
+    }
+
+    /// Returns false if the box lies completely outside of the frustum.
+    /// Boxes near corners of the frustum may be reported as visible even though they aren't.
+    pub fn intersects_box(&self, min: glam::Vec3, max: glam::Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner of the box furthest along the normal of the plane
+            let corner = glam::Vec3::select(plane.truncate().cmpge(glam::Vec3::ZERO), max, min);
+
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
 pub fn update_camera_sys(mut camera: UniqueViewMut<Camera>, renderer: UniqueView<Renderer>) {
     camera.update_view_projection_matrix(&renderer);
 }
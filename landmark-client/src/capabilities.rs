@@ -0,0 +1,126 @@
+use crate::{
+    lights::LightBuffers,
+    settings::{LightingMode, PresentModeSetting, Settings},
+};
+
+/// Optional graphics features detected on the adapter at startup.
+#[derive(Debug, Clone)]
+pub struct GraphicsCapabilities {
+    pub adapter_name: String,
+    pub backend: wgpu::Backend,
+    pub device_type: wgpu::DeviceType,
+    /// Required for the wireframe debug mode.
+    pub polygon_mode_line: bool,
+    /// Required for GPU timings.
+    pub timestamp_query: bool,
+    /// Required for indirect chunk rendering.
+    pub multi_draw_indirect: bool,
+    /// Required for indirect chunk rendering, the transforms of chunks are picked by the first instance.
+    pub indirect_first_instance: bool,
+    /// Required for clustered lighting, the light grid is read from storage buffers.
+    pub storage_buffers: bool,
+    pub present_modes: Vec<wgpu::PresentMode>,
+}
+
+impl GraphicsCapabilities {
+    /// Detects features of the adapter, present modes are only known when rendering to a surface.
+    pub fn detect(adapter: &wgpu::Adapter, surface: Option<&wgpu::Surface>) -> Self {
+        let info = adapter.get_info();
+        let features = adapter.features();
+
+        Self {
+            adapter_name: info.name,
+            backend: info.backend,
+            device_type: info.device_type,
+            polygon_mode_line: features.contains(wgpu::Features::POLYGON_MODE_LINE),
+            timestamp_query: features.contains(wgpu::Features::TIMESTAMP_QUERY),
+            multi_draw_indirect: features.contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+            indirect_first_instance: features.contains(wgpu::Features::INDIRECT_FIRST_INSTANCE),
+            storage_buffers: adapter.limits().max_storage_buffers_per_shader_stage
+                >= LightBuffers::STORAGE_BUFFERS,
+            present_modes: surface
+                .map(|surface| surface.get_capabilities(adapter).present_modes)
+                .unwrap_or_else(|| vec![wgpu::PresentMode::Fifo]),
+        }
+    }
+
+    /// Returns all optional features which should be requested when creating the device.
+    pub fn features(&self) -> wgpu::Features {
+        let mut features = wgpu::Features::empty();
+
+        features.set(wgpu::Features::POLYGON_MODE_LINE, self.polygon_mode_line);
+        features.set(wgpu::Features::TIMESTAMP_QUERY, self.timestamp_query);
+        features.set(
+            wgpu::Features::MULTI_DRAW_INDIRECT,
+            self.multi_draw_indirect,
+        );
+        features.set(
+            wgpu::Features::INDIRECT_FIRST_INSTANCE,
+            self.indirect_first_instance,
+        );
+
+        features
+    }
+
+    pub fn supports_present_mode(&self, present_mode: PresentModeSetting) -> bool {
+        self.present_modes
+            .contains(&wgpu::PresentMode::from(present_mode))
+    }
+
+    /// Disables options in the settings which depend on features that are not available.
+    pub fn restrict_settings(&self, settings: &mut Settings) {
+        if !self.supports_present_mode(settings.graphics.present_mode) {
+            log::warn!(
+                "Present mode {:?} is not supported, switching to Fifo",
+                settings.graphics.present_mode
+            );
+            settings.graphics.present_mode = PresentModeSetting::Fifo;
+        }
+
+        if settings.graphics.lighting == LightingMode::Clustered && !self.storage_buffers {
+            log::warn!("Clustered lighting is not supported, switching to forward lighting");
+            settings.graphics.lighting = LightingMode::Forward;
+        }
+    }
+
+    /// Logs which optional features are active and what depends on them.
+    pub fn log(&self) {
+        log::info!(
+            "Using {} ({:?}, {:?})",
+            self.adapter_name,
+            self.backend,
+            self.device_type
+        );
+
+        let matrix = [
+            (
+                "POLYGON_MODE_LINE",
+                self.polygon_mode_line,
+                "wireframe mode",
+            ),
+            ("TIMESTAMP_QUERY", self.timestamp_query, "GPU timings"),
+            (
+                "MULTI_DRAW_INDIRECT",
+                self.multi_draw_indirect,
+                "indirect chunk rendering",
+            ),
+            (
+                "INDIRECT_FIRST_INSTANCE",
+                self.indirect_first_instance,
+                "indirect chunk rendering",
+            ),
+            (
+                "STORAGE_BUFFERS",
+                self.storage_buffers,
+                "clustered lighting",
+            ),
+        ];
+
+        for (feature, supported, dependents) in matrix {
+            let status = if supported { "enabled" } else { "disabled" };
+            log::info!("{feature:<20} {status:<8} ({dependents})");
+        }
+
+        log::info!("Present modes: {:?}", self.present_modes);
+    }
+}
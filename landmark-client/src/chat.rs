@@ -0,0 +1,294 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use shipyard::*;
+
+use crate::{player::RemotePlayer, settings::Settings};
+
+/// How long a chat bubble stays above the player who sent the message.
+const CHAT_BUBBLE_DURATION: Duration = Duration::from_secs(5);
+/// Amount of received messages kept for scrolling back.
+const MAX_HISTORY: usize = 200;
+/// Amount of sent messages which can be recalled with the arrow keys.
+const MAX_SENT: usize = 50;
+/// Longest message which can be typed.
+pub const MAX_MESSAGE_LENGTH: usize = 256;
+
+/// Message travelling between players.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// Name of the player who sent the message, None for messages from the game itself.
+    pub sender: Option<String>,
+    pub text: String,
+}
+
+/// Queues connecting the chat to the network. The network layer sends `outgoing`
+/// messages and pushes messages received from the server to `incoming`.
+/// Without a connection, outgoing messages are echoed back locally.
+#[derive(Debug, Default, Unique)]
+pub struct ChatBus {
+    pub outgoing: VecDeque<ChatMessage>,
+    pub incoming: VecDeque<ChatMessage>,
+    /// Commands the client doesn't know, executed by the server instead.
+    pub commands: VecDeque<String>,
+    /// Set by the network layer while connected to a server.
+    pub connected: bool,
+}
+
+/// Received message shown in the chat window.
+#[derive(Debug, Clone)]
+pub struct ChatLine {
+    pub message: ChatMessage,
+    pub received_at: Instant,
+}
+
+/// State of the chat window and the line being typed into it.
+#[derive(Debug, Default, Unique)]
+pub struct Chat {
+    pub open: bool,
+    pub input: String,
+    /// Received messages, oldest first.
+    pub history: VecDeque<ChatLine>,
+    /// Amount of messages scrolled back from the newest one.
+    pub scroll: usize,
+    /// Lines sent earlier, newest last.
+    sent: VecDeque<String>,
+    /// Index into `sent` while recalling earlier lines.
+    recalled: Option<usize>,
+    /// Lines submitted since the last update, processed by `chat_sys`.
+    submitted: Vec<String>,
+    /// Submitted commands waiting to be executed by the game.
+    commands: Vec<String>,
+    /// Ignores typed characters until a key is released, so the key opening the chat isn't typed into it.
+    suppress_typing: bool,
+}
+
+impl Chat {
+    /// Opens the chat window with `input` already typed.
+    pub fn open(&mut self, input: &str) {
+        self.open = true;
+        self.input = input.to_string();
+        self.scroll = 0;
+        self.recalled = None;
+        self.suppress_typing = true;
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.input.clear();
+        self.scroll = 0;
+    }
+
+    /// Called on every key release.
+    pub fn key_released(&mut self) {
+        self.suppress_typing = false;
+    }
+
+    pub fn type_character(&mut self, character: char) {
+        if !self.open
+            || self.suppress_typing
+            || character.is_control()
+            || self.input.chars().count() >= MAX_MESSAGE_LENGTH
+        {
+            return;
+        }
+
+        self.input.push(character);
+    }
+
+    pub fn delete_character(&mut self) {
+        self.input.pop();
+    }
+
+    /// Sends the typed line and closes the chat.
+    pub fn submit(&mut self) {
+        let line = self.input.trim().to_string();
+        self.close();
+
+        if line.is_empty() {
+            return;
+        }
+
+        if self.sent.len() == MAX_SENT {
+            self.sent.pop_front();
+        }
+
+        self.sent.push_back(line.clone());
+        self.submitted.push(line);
+    }
+
+    /// Replaces the input with an earlier sent line, `older` moves further back in time.
+    pub fn recall(&mut self, older: bool) {
+        if self.sent.is_empty() {
+            return;
+        }
+
+        let recalled = match (self.recalled, older) {
+            (None, true) => Some(self.sent.len() - 1),
+            (None, false) => None,
+            (Some(idx), true) => Some(idx.saturating_sub(1)),
+            (Some(idx), false) => (idx + 1 < self.sent.len()).then_some(idx + 1),
+        };
+
+        self.recalled = recalled;
+        self.input = recalled
+            .map(|idx| self.sent[idx].clone())
+            .unwrap_or_default();
+    }
+
+    /// Scrolls the history by `delta` messages, positive values scroll back.
+    pub fn scroll(&mut self, delta: i32) {
+        let max_scroll = self.history.len().saturating_sub(1) as i32;
+        self.scroll = (self.scroll as i32 + delta).clamp(0, max_scroll) as usize;
+    }
+
+    /// Takes commands submitted since the last call.
+    pub fn take_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.commands)
+    }
+
+    /// Adds a message to the history.
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(ChatLine {
+            message,
+            received_at: Instant::now(),
+        });
+
+        // keep the view on the same messages while scrolled back
+        if self.scroll > 0 {
+            self.scroll(1);
+        }
+    }
+
+    /// Shows a message from the game itself, e.g. command output.
+    pub fn push_system(&mut self, text: &str) {
+        for line in text.lines() {
+            self.push(ChatMessage {
+                sender: None,
+                text: line.to_string(),
+            });
+        }
+    }
+}
+
+/// Recent chat message shown above the player who sent it.
+#[derive(Debug, Clone, Component)]
+pub struct ChatBubble {
+    pub text: String,
+    shown_at: Instant,
+}
+
+/// Hook for transforming chat messages before they are displayed, e.g. to mask profanity.
+pub trait ChatFilter: Send + Sync {
+    fn filter(&self, message: &str) -> String;
+}
+
+/// Filters applied in registration order to every displayed message. None are registered by default.
+#[derive(Default, Unique)]
+pub struct ChatFilters {
+    filters: Vec<Box<dyn ChatFilter>>,
+}
+
+// Nothing registers filters yet
+#[allow(unused)]
+impl ChatFilters {
+    pub fn register(&mut self, filter: impl ChatFilter + 'static) {
+        self.filters.push(Box::new(filter));
+    }
+
+    pub fn apply(&self, message: &str) -> String {
+        self.filters
+            .iter()
+            .fold(message.to_string(), |message, filter| {
+                filter.filter(&message)
+            })
+    }
+}
+
+impl std::fmt::Debug for ChatFilters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChatFilters")
+            .field("filters", &self.filters.len())
+            .finish()
+    }
+}
+
+/// Shows a message above the player entity `id`, replacing its previous bubble.
+pub fn show_chat_bubble(
+    filters: &ChatFilters,
+    chat_bubbles: &mut ViewMut<ChatBubble>,
+    id: EntityId,
+    message: &str,
+) {
+    chat_bubbles.add_component_unchecked(
+        id,
+        ChatBubble {
+            text: filters.apply(message),
+            shown_at: Instant::now(),
+        },
+    );
+}
+
+/// Routes submitted chat lines, commands are left in the chat for the game to execute.
+/// Received messages are filtered, added to the history and shown above their senders.
+pub fn chat_sys(
+    settings: UniqueView<Settings>,
+    filters: UniqueView<ChatFilters>,
+    mut chat: UniqueViewMut<Chat>,
+    mut bus: UniqueViewMut<ChatBus>,
+    remote_players: View<RemotePlayer>,
+    mut chat_bubbles: ViewMut<ChatBubble>,
+) {
+    let submitted = std::mem::take(&mut chat.submitted);
+
+    for line in submitted {
+        if line.starts_with('/') {
+            chat.commands.push(line);
+            continue;
+        }
+
+        bus.outgoing.push_back(ChatMessage {
+            sender: Some(settings.player_name.clone()),
+            text: line,
+        });
+    }
+
+    if !bus.connected {
+        let echoed: Vec<ChatMessage> = bus.outgoing.drain(..).collect();
+        bus.incoming.extend(echoed);
+    }
+
+    while let Some(mut message) = bus.incoming.pop_front() {
+        let sender = remote_players
+            .iter()
+            .with_id()
+            .find(|(_, player)| Some(&player.name) == message.sender.as_ref());
+
+        if let Some((id, _)) = sender {
+            show_chat_bubble(&filters, &mut chat_bubbles, id, &message.text);
+        }
+
+        message.text = filters.apply(&message.text);
+        chat.push(message);
+    }
+}
+
+/// Removes chat bubbles which were shown long enough.
+pub fn expire_chat_bubbles_sys(mut chat_bubbles: ViewMut<ChatBubble>) {
+    let expired: Vec<EntityId> = chat_bubbles
+        .iter()
+        .with_id()
+        .filter(|(_, bubble)| bubble.shown_at.elapsed() >= CHAT_BUBBLE_DURATION)
+        .map(|(id, _)| id)
+        .collect();
+
+    for id in expired {
+        chat_bubbles.delete(id);
+    }
+}
@@ -0,0 +1,285 @@
+//! Shared buffers holding the meshes of all chunks, so they can be drawn with a single indirect draw.
+//!
+//! Every chunk gets a range of the vertex buffer, a range of the index buffer and a slot of the
+//! instance buffer holding its transform. The ranges are freed when the allocation is dropped
+//! together with the model, and reused by chunks loaded later.
+
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use crate::{model::Vertex, transform::RawTransform};
+
+/// About 64 MiB of vertices.
+const VERTEX_CAPACITY: u32 = 1 << 21;
+const INDEX_CAPACITY: u32 = 1 << 22;
+const SLOT_CAPACITY: u32 = 1 << 13;
+
+/// First fit allocator of ranges of a buffer, counted in elements.
+#[derive(Debug)]
+struct RangeAllocator {
+    /// Free ranges sorted by their start, neighbors are always merged.
+    free: Vec<Range<u32>>,
+}
+
+impl RangeAllocator {
+    fn new(capacity: u32) -> Self {
+        Self {
+            free: vec![Range {
+                start: 0,
+                end: capacity,
+            }],
+        }
+    }
+
+    fn allocate(&mut self, len: u32) -> Option<Range<u32>> {
+        let idx = self
+            .free
+            .iter()
+            .position(|range| range.len() as u32 >= len)?;
+        let start = self.free[idx].start;
+
+        self.free[idx].start += len;
+        if self.free[idx].is_empty() {
+            self.free.remove(idx);
+        }
+
+        Some(start..start + len)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        let idx = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(idx, range);
+
+        if idx + 1 < self.free.len() && self.free[idx].end == self.free[idx + 1].start {
+            self.free[idx].end = self.free.remove(idx + 1).end;
+        }
+        if idx > 0 && self.free[idx - 1].end == self.free[idx].start {
+            self.free[idx - 1].end = self.free.remove(idx).end;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Allocators {
+    vertices: RangeAllocator,
+    indices: RangeAllocator,
+    slots: RangeAllocator,
+}
+
+#[derive(Debug)]
+struct Shared {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    allocators: Mutex<Allocators>,
+}
+
+/// Buffers shared by the meshes of all chunks, only used if the device supports indirect draws.
+#[derive(Debug)]
+pub struct ChunkArena {
+    shared: Arc<Shared>,
+}
+
+impl ChunkArena {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let create_buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: usage | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+
+        Self {
+            shared: Arc::new(Shared {
+                vertex_buffer: create_buffer(
+                    "chunk_arena_vertices",
+                    VERTEX_CAPACITY as u64 * std::mem::size_of::<Vertex>() as u64,
+                    wgpu::BufferUsages::VERTEX,
+                ),
+                index_buffer: create_buffer(
+                    "chunk_arena_indices",
+                    INDEX_CAPACITY as u64 * std::mem::size_of::<u16>() as u64,
+                    wgpu::BufferUsages::INDEX,
+                ),
+                instance_buffer: create_buffer(
+                    "chunk_arena_instances",
+                    SLOT_CAPACITY as u64 * std::mem::size_of::<RawTransform>() as u64,
+                    wgpu::BufferUsages::VERTEX,
+                ),
+                allocators: Mutex::new(Allocators {
+                    vertices: RangeAllocator::new(VERTEX_CAPACITY),
+                    indices: RangeAllocator::new(INDEX_CAPACITY),
+                    slots: RangeAllocator::new(SLOT_CAPACITY),
+                }),
+            }),
+        }
+    }
+
+    /// Uploads a mesh, returns None if the arena has no room left for it.
+    pub fn allocate(
+        &self,
+        queue: &wgpu::Queue,
+        vertices: &[Vertex],
+        indices: &[u16],
+        transform: RawTransform,
+    ) -> Option<ArenaAllocation> {
+        // buffer writes must be a multiple of four bytes long, so indices are allocated in pairs
+        let mut padded_indices = indices.to_vec();
+        if !padded_indices.len().is_multiple_of(2) {
+            padded_indices.push(0);
+        }
+
+        let (vertex_range, index_range, slot) = {
+            let mut allocators = self.shared.allocators.lock().unwrap();
+
+            let vertex_range = allocators.vertices.allocate(vertices.len() as u32)?;
+            let Some(index_range) = allocators.indices.allocate(padded_indices.len() as u32) else {
+                allocators.vertices.free(vertex_range);
+                return None;
+            };
+            let Some(slot) = allocators.slots.allocate(1) else {
+                allocators.vertices.free(vertex_range);
+                allocators.indices.free(index_range);
+                return None;
+            };
+
+            (vertex_range, index_range, slot.start)
+        };
+
+        let shared = &self.shared;
+        queue.write_buffer(
+            &shared.vertex_buffer,
+            vertex_range.start as u64 * std::mem::size_of::<Vertex>() as u64,
+            bytemuck::cast_slice(vertices),
+        );
+        queue.write_buffer(
+            &shared.index_buffer,
+            index_range.start as u64 * std::mem::size_of::<u16>() as u64,
+            bytemuck::cast_slice(&padded_indices),
+        );
+
+        let allocation = ArenaAllocation {
+            shared: self.shared.clone(),
+            vertices: vertex_range,
+            indices: index_range,
+            index_count: indices.len() as u32,
+            slot,
+        };
+        allocation.set_transform(queue, transform);
+
+        Some(allocation)
+    }
+
+    /// Draws every allocation with one indirect draw call, in the order they are given.
+    pub fn draw_all<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        indirect_buffer: &'a wgpu::Buffer,
+        count: u32,
+    ) {
+        bind(&self.shared, rpass);
+        rpass.multi_draw_indexed_indirect(indirect_buffer, 0, count);
+    }
+}
+
+fn bind<'a>(shared: &'a Shared, rpass: &mut wgpu::RenderPass<'a>) {
+    rpass.set_vertex_buffer(0, shared.vertex_buffer.slice(..));
+    rpass.set_vertex_buffer(1, shared.instance_buffer.slice(..));
+    rpass.set_index_buffer(shared.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+}
+
+/// Part of the arena holding the mesh of one chunk, freed when it's dropped.
+#[derive(Debug)]
+pub struct ArenaAllocation {
+    shared: Arc<Shared>,
+    vertices: Range<u32>,
+    /// May be one longer than the indices of the mesh.
+    indices: Range<u32>,
+    index_count: u32,
+    /// Index of the transform in the instance buffer.
+    slot: u32,
+}
+
+impl ArenaAllocation {
+    pub fn set_transform(&self, queue: &wgpu::Queue, transform: RawTransform) {
+        queue.write_buffer(
+            &self.shared.instance_buffer,
+            self.slot as u64 * std::mem::size_of::<RawTransform>() as u64,
+            bytemuck::cast_slice(&[transform]),
+        );
+    }
+
+    /// Returns the arguments of an indirect draw of the mesh.
+    pub fn indirect_draw(&self) -> wgpu::util::DrawIndexedIndirect {
+        wgpu::util::DrawIndexedIndirect {
+            vertex_count: self.index_count,
+            instance_count: 1,
+            base_index: self.indices.start,
+            vertex_offset: self.vertices.start as i32,
+            base_instance: self.slot,
+        }
+    }
+
+    /// Draws the mesh on its own.
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        bind(&self.shared, rpass);
+        rpass.draw_indexed(
+            self.indices.start..self.indices.start + self.index_count,
+            self.vertices.start as i32,
+            self.slot..self.slot + 1,
+        );
+    }
+
+    /// Returns the size of the parts of the buffers it takes.
+    pub fn buffer_bytes(&self) -> u64 {
+        self.vertices.len() as u64 * std::mem::size_of::<Vertex>() as u64
+            + self.indices.len() as u64 * std::mem::size_of::<u16>() as u64
+            + std::mem::size_of::<RawTransform>() as u64
+    }
+}
+
+impl Drop for ArenaAllocation {
+    fn drop(&mut self) {
+        let mut allocators = self.shared.allocators.lock().unwrap();
+
+        allocators.vertices.free(self.vertices.clone());
+        allocators.indices.free(self.indices.clone());
+        allocators.slots.free(self.slot..self.slot + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_ranges_are_merged() {
+        let mut allocator = RangeAllocator::new(10);
+        let a = allocator.allocate(3).unwrap();
+        let b = allocator.allocate(3).unwrap();
+        let c = allocator.allocate(4).unwrap();
+
+        assert_eq!(allocator.allocate(1), None);
+
+        allocator.free(a);
+        allocator.free(c);
+        allocator.free(b);
+
+        assert_eq!(allocator.free, vec![0..10]);
+    }
+
+    #[test]
+    fn first_fitting_range_is_used() {
+        let mut allocator = RangeAllocator::new(10);
+        let a = allocator.allocate(2).unwrap();
+        let _b = allocator.allocate(3).unwrap();
+        allocator.free(a);
+
+        assert_eq!(allocator.allocate(3), Some(5..8));
+        assert_eq!(allocator.allocate(2), Some(0..2));
+    }
+}
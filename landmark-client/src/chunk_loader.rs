@@ -0,0 +1,311 @@
+use landmark_protocol::message::ServerMessage;
+use shipyard::*;
+
+use crate::{
+    biome::Biome,
+    camera::Camera,
+    coords,
+    game_map::{Chunk, ChunkCoords, ChunkTag, FaceDirection, GameMap},
+    lights::ChunkLights,
+    loader::ResourceDictionary,
+    mesher::remesh_around_block,
+    model::{MissingModel, Model, UpdatedModel},
+    plugins::Plugins,
+    priority::ChunkPriority,
+    save::WorldSave,
+    settings::Settings,
+    teleport::PendingTeleport,
+    water::{FluidModel, UpdatedFluidModel},
+    worldgen::WorldGenerator,
+};
+
+/// Amount of chunk layers loaded above and below the camera.
+const VERTICAL_RADIUS: i32 = 1;
+
+/// Chunks sent by the server, which decides what the client has loaded while it's connected.
+#[derive(Debug, Default, Unique)]
+pub struct ServerChunks {
+    /// Set while connected to a server, including the one of singleplayer, chunks aren't generated
+    /// locally then.
+    pub connected: bool,
+    /// Chunk and block messages received since the last update, in the order they arrived.
+    pub received: Vec<ServerMessage>,
+}
+
+/// Returns true if the chunk lies within `radius` chunks of `center`.
+fn is_in_range(center: ChunkCoords, coords: ChunkCoords, radius: i32) -> bool {
+    let (dx, dy, dz) = (
+        coords.x - center.x,
+        coords.y - center.y,
+        coords.z - center.z,
+    );
+
+    dx * dx + dz * dz <= radius * radius && dy.abs() <= VERTICAL_RADIUS
+}
+
+/// Returns true if the chunk is in range of the camera or needed by a pending teleport.
+fn is_wanted(
+    center: ChunkCoords,
+    coords: ChunkCoords,
+    radius: i32,
+    teleport: &[ChunkCoords],
+) -> bool {
+    is_in_range(center, coords, radius) || teleport.contains(&coords)
+}
+
+/// Requests generation of chunks entering the view distance around the camera and unloads the ones leaving it.
+/// Chunks at the destination of a pending teleport are loaded too.
+#[allow(clippy::too_many_arguments)]
+pub fn chunk_loading_sys(
+    camera: UniqueView<Camera>,
+    teleport: UniqueView<PendingTeleport>,
+    settings: UniqueView<Settings>,
+    server_chunks: UniqueView<ServerChunks>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut world_generator: UniqueViewMut<WorldGenerator>,
+    mut missing_models: ViewMut<MissingModel>,
+    mut models: ViewMut<Model>,
+    mut updated_models: ViewMut<UpdatedModel>,
+    // grouped, systems can't take more than ten views
+    (mut chunk_lights, mut fluid_models, mut updated_fluid_models): (
+        ViewMut<ChunkLights>,
+        ViewMut<FluidModel>,
+        ViewMut<UpdatedFluidModel>,
+    ),
+) {
+    // the server sends the chunks in view itself
+    if server_chunks.connected {
+        return;
+    }
+
+    let center = ChunkCoords::from_world_position(camera.eye);
+    let radius = settings.graphics.view_distance as i32;
+    let teleport = teleport.required_chunks();
+
+    // Unload chunks with a margin of one chunk, so moving along the border doesn't reload them constantly
+    let unloaded: Vec<ChunkCoords> = game_map
+        .chunks
+        .keys()
+        .filter(|coords| !is_wanted(center, **coords, radius + 1, &teleport))
+        .copied()
+        .collect();
+
+    for coords in unloaded {
+        // edits were already copied to the world save by the autosave system
+        unload_chunk(
+            &mut game_map,
+            coords,
+            &mut models,
+            &mut missing_models,
+            &mut updated_models,
+            &mut chunk_lights,
+            &mut fluid_models,
+            &mut updated_fluid_models,
+        );
+    }
+
+    // Cancel requests which left the view distance and move the ones in view forward
+    let priority = ChunkPriority::new(&camera);
+    world_generator.reprioritize(&priority, |coords| {
+        is_wanted(center, coords, radius, &teleport)
+    });
+
+    let mut wanted = teleport.clone();
+    for dy in -VERTICAL_RADIUS..=VERTICAL_RADIUS {
+        for dz in -radius..=radius {
+            for dx in -radius..=radius {
+                let coords = center + ChunkCoords::new(dx, dy, dz);
+
+                if is_in_range(center, coords, radius) {
+                    wanted.push(coords);
+                }
+            }
+        }
+    }
+
+    for coords in wanted {
+        if !game_map.chunks.contains_key(&coords) && !world_generator.is_pending(coords) {
+            world_generator.request(coords, &priority);
+        }
+    }
+}
+
+/// Inserts chunks finished by the world generator into the map and spawns their entities.
+/// Mods can modify the chunks before they are inserted.
+#[allow(clippy::too_many_arguments)]
+pub fn generated_chunks_sys(
+    (camera, teleport): (UniqueView<Camera>, UniqueView<PendingTeleport>),
+    settings: UniqueView<Settings>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    mut plugins: UniqueViewMut<Plugins>,
+    world_save: UniqueView<WorldSave>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut world_generator: UniqueViewMut<WorldGenerator>,
+    mut entities: EntitiesViewMut,
+    mut chunk_tags: ViewMut<ChunkTag>,
+    mut missing_models: ViewMut<MissingModel>,
+) {
+    let center = ChunkCoords::from_world_position(camera.eye);
+    let radius = settings.graphics.view_distance as i32;
+    let teleport = teleport.required_chunks();
+
+    for (coords, chunk) in world_generator.receive() {
+        // the camera may have moved away while the chunk was generated
+        if !is_wanted(center, coords, radius + 1, &teleport) {
+            continue;
+        }
+
+        // edited chunks are loaded from the save, mods have already seen them when they were first generated
+        let chunk = match world_save.saved_chunk(coords, &resource_dictionary) {
+            Some(mut saved) if saved.is_modified() || !world_generator.replaces_unmodified() => {
+                saved.restore_missing(&chunk);
+                saved
+            }
+            saved => {
+                // the regenerated chunk replaces the outdated one in the save
+                if saved.is_some() {
+                    game_map.edited.insert(coords);
+                }
+
+                plugins.chunk_generated(coords, chunk, resource_dictionary.block_ids())
+            }
+        };
+        insert_chunk(
+            &mut game_map,
+            coords,
+            chunk,
+            &mut entities,
+            &mut chunk_tags,
+            &mut missing_models,
+        );
+    }
+}
+
+/// Applies the chunks, unloads and block changes sent by the server.
+#[allow(clippy::too_many_arguments)]
+pub fn server_chunks_sys(
+    mut server_chunks: UniqueViewMut<ServerChunks>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut entities: EntitiesViewMut,
+    mut chunk_tags: ViewMut<ChunkTag>,
+    mut models: ViewMut<Model>,
+    mut missing_models: ViewMut<MissingModel>,
+    mut updated_models: ViewMut<UpdatedModel>,
+    mut chunk_lights: ViewMut<ChunkLights>,
+    mut fluid_models: ViewMut<FluidModel>,
+    mut updated_fluid_models: ViewMut<UpdatedFluidModel>,
+) {
+    for message in std::mem::take(&mut server_chunks.received) {
+        match message {
+            ServerMessage::Chunk(data) => {
+                let [x, y, z] = data.coords;
+                let coords = ChunkCoords::new(x, y, z);
+
+                let Some(mut chunk) = Chunk::from_blocks(data.blocks) else {
+                    log::warn!("Server sent chunk {coords:?} with a wrong number of blocks");
+                    continue;
+                };
+                chunk.relight();
+
+                // the chunk is left without biomes if the server didn't send valid ones
+                let biomes = data.biomes.into_iter().map(Biome::from_id).collect();
+                if let Some(biomes) = biomes {
+                    chunk.set_biomes(biomes);
+                }
+
+                insert_chunk(
+                    &mut game_map,
+                    coords,
+                    chunk,
+                    &mut entities,
+                    &mut chunk_tags,
+                    &mut missing_models,
+                );
+            }
+            ServerMessage::UnloadChunk([x, y, z]) => unload_chunk(
+                &mut game_map,
+                ChunkCoords::new(x, y, z),
+                &mut models,
+                &mut missing_models,
+                &mut updated_models,
+                &mut chunk_lights,
+                &mut fluid_models,
+                &mut updated_fluid_models,
+            ),
+            ServerMessage::BlockChanged { position, block } => {
+                let position = glam::IVec3::from_array(position);
+                let (coords, inner) = coords::split_block(position);
+
+                // the server owns the world, so the change isn't recorded as an edit to save
+                let Some(chunk) = game_map.chunks.get_mut(&coords) else {
+                    continue;
+                };
+                chunk.set_block(inner, block);
+                chunk.relight();
+
+                remesh_around_block(&game_map, &mut missing_models, position);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Inserts a loaded chunk into the map and marks it and its neighbours for meshing.
+fn insert_chunk(
+    game_map: &mut GameMap,
+    coords: ChunkCoords,
+    chunk: Chunk,
+    entities: &mut EntitiesViewMut,
+    chunk_tags: &mut ViewMut<ChunkTag>,
+    missing_models: &mut ViewMut<MissingModel>,
+) {
+    game_map.chunks.insert(coords, chunk);
+
+    match game_map.chunk_entity_map.get(&coords) {
+        Some(id) => missing_models.add_component_unchecked(*id, MissingModel),
+        None => {
+            let id = entities.add_entity(
+                (&mut *chunk_tags, &mut *missing_models),
+                (ChunkTag { coords }, MissingModel),
+            );
+
+            game_map.chunk_entity_map.insert(coords, id);
+        }
+    }
+
+    // Faces on the borders of loaded neighbours may have become hidden
+    for face in 0..6 {
+        let neighbour = coords + ChunkCoords::from(FaceDirection::from(face));
+
+        if game_map.chunks.contains_key(&neighbour) {
+            if let Some(id) = game_map.chunk_entity_map.get(&neighbour) {
+                missing_models.add_component_unchecked(*id, MissingModel);
+            }
+        }
+    }
+}
+
+/// Removes a chunk from the map together with its models and lights.
+#[allow(clippy::too_many_arguments)]
+fn unload_chunk(
+    game_map: &mut GameMap,
+    coords: ChunkCoords,
+    models: &mut ViewMut<Model>,
+    missing_models: &mut ViewMut<MissingModel>,
+    updated_models: &mut ViewMut<UpdatedModel>,
+    chunk_lights: &mut ViewMut<ChunkLights>,
+    fluid_models: &mut ViewMut<FluidModel>,
+    updated_fluid_models: &mut ViewMut<UpdatedFluidModel>,
+) {
+    game_map.chunks.remove(&coords);
+
+    // The entity itself is kept, so the chunk gets the same ID when it's loaded again
+    if let Some(id) = game_map.chunk_entity_map.get(&coords) {
+        models.delete(*id);
+        missing_models.delete(*id);
+        updated_models.delete(*id);
+        chunk_lights.delete(*id);
+        fluid_models.delete(*id);
+        updated_fluid_models.delete(*id);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::game_map::{BlockId, Chunk};
+
+/// Most blocks a palette can index with a byte.
+const MAX_PALETTE_SIZE: usize = u8::MAX as usize + 1;
+
+/// Blocks of a chunk in the order of `InnerChunkCoords::as_idx`, stored compactly while the chunk holds
+/// few kinds of blocks. Chunks of air above the terrain or of stone below it take almost no memory.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ChunkStorage {
+    /// Every block of the chunk is the same.
+    Uniform(Option<BlockId>),
+    /// Each block is an index into the palette, used for up to 256 kinds of blocks.
+    Palette {
+        palette: Vec<Option<BlockId>>,
+        indices: Vec<u8>,
+    },
+    Dense(Vec<Option<BlockId>>),
+}
+
+impl ChunkStorage {
+    /// Stores the blocks in the most compact form, their count must be `Chunk::BLOCKS_COUNT`.
+    pub fn from_blocks(blocks: Vec<Option<BlockId>>) -> Self {
+        let mut storage = ChunkStorage::Dense(blocks);
+        storage.compact();
+        storage
+    }
+
+    pub fn get(&self, idx: usize) -> Option<BlockId> {
+        match self {
+            ChunkStorage::Uniform(block) => *block,
+            ChunkStorage::Palette { palette, indices } => palette[indices[idx] as usize],
+            ChunkStorage::Dense(blocks) => blocks[idx],
+        }
+    }
+
+    /// Sets a block, switching to a larger form if the block doesn't fit into the current one.
+    pub fn set(&mut self, idx: usize, block: Option<BlockId>) {
+        match self {
+            ChunkStorage::Uniform(current) if *current == block => {}
+            ChunkStorage::Uniform(current) => {
+                let mut indices = vec![0; Chunk::BLOCKS_COUNT as usize];
+                indices[idx] = 1;
+
+                *self = ChunkStorage::Palette {
+                    palette: vec![*current, block],
+                    indices,
+                };
+            }
+            ChunkStorage::Palette { palette, indices } => {
+                match palette.iter().position(|known| *known == block) {
+                    Some(palette_idx) => indices[idx] = palette_idx as u8,
+                    None if palette.len() < MAX_PALETTE_SIZE => {
+                        palette.push(block);
+                        indices[idx] = (palette.len() - 1) as u8;
+                    }
+                    None => {
+                        let mut blocks: Vec<Option<BlockId>> = self.iter().collect();
+                        blocks[idx] = block;
+                        *self = ChunkStorage::Dense(blocks);
+                    }
+                }
+            }
+            ChunkStorage::Dense(blocks) => blocks[idx] = block,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<BlockId>> + '_ {
+        (0..Chunk::BLOCKS_COUNT as usize).map(|idx| self.get(idx))
+    }
+
+    /// Returns the kinds of blocks which may be in the chunk, blocks removed from a palette may still be listed.
+    pub fn kinds(&self) -> Vec<Option<BlockId>> {
+        match self {
+            ChunkStorage::Uniform(block) => vec![*block],
+            ChunkStorage::Palette { palette, .. } => palette.clone(),
+            ChunkStorage::Dense(blocks) => {
+                let mut kinds = Vec::new();
+
+                for block in blocks {
+                    if !kinds.contains(block) {
+                        kinds.push(*block);
+                    }
+                }
+
+                kinds
+            }
+        }
+    }
+
+    /// Switches to the smallest form able to hold the blocks, dropping palette entries no block uses.
+    pub fn compact(&mut self) {
+        let mut palette: Vec<Option<BlockId>> = Vec::new();
+        let mut indices = Vec::with_capacity(Chunk::BLOCKS_COUNT as usize);
+
+        for block in self.iter() {
+            let palette_idx = match palette.iter().position(|known| *known == block) {
+                Some(palette_idx) => palette_idx,
+                None if palette.len() < MAX_PALETTE_SIZE => {
+                    palette.push(block);
+                    palette.len() - 1
+                }
+                // too many kinds of blocks for a palette
+                None => return,
+            };
+
+            indices.push(palette_idx as u8);
+        }
+
+        *self = match palette.as_slice() {
+            [block] => ChunkStorage::Uniform(*block),
+            _ => ChunkStorage::Palette { palette, indices },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNT: usize = Chunk::BLOCKS_COUNT as usize;
+
+    fn is_palette(storage: &ChunkStorage) -> bool {
+        matches!(storage, ChunkStorage::Palette { .. })
+    }
+
+    #[test]
+    fn setting_the_same_block_stays_uniform() {
+        let mut storage = ChunkStorage::Uniform(Some(1));
+        storage.set(10, Some(1));
+
+        assert_eq!(storage, ChunkStorage::Uniform(Some(1)));
+    }
+
+    #[test]
+    fn second_kind_switches_to_palette() {
+        let mut storage = ChunkStorage::Uniform(None);
+        storage.set(10, Some(3));
+
+        assert!(is_palette(&storage));
+        assert_eq!(storage.get(10), Some(3));
+        assert_eq!(storage.get(11), None);
+        assert_eq!(storage.kinds(), vec![None, Some(3)]);
+    }
+
+    #[test]
+    fn kind_beyond_the_palette_switches_to_dense() {
+        let mut storage = ChunkStorage::Uniform(None);
+
+        // air and 255 blocks fill the palette
+        for idx in 0..MAX_PALETTE_SIZE - 1 {
+            storage.set(idx, Some(idx as BlockId));
+        }
+        assert!(is_palette(&storage));
+
+        storage.set(COUNT - 1, Some(1000));
+
+        let ChunkStorage::Dense(blocks) = &storage else {
+            panic!("expected dense storage, got {storage:?}");
+        };
+        assert_eq!(blocks.len(), COUNT);
+
+        for idx in 0..MAX_PALETTE_SIZE - 1 {
+            assert_eq!(storage.get(idx), Some(idx as BlockId));
+        }
+        assert_eq!(storage.get(MAX_PALETTE_SIZE), None);
+        assert_eq!(storage.get(COUNT - 1), Some(1000));
+    }
+
+    #[test]
+    fn compact_shrinks_to_the_smallest_form() {
+        let mut storage = ChunkStorage::Uniform(None);
+        storage.set(0, Some(1));
+        storage.set(0, Some(2));
+
+        // the palette still lists the replaced block
+        assert_eq!(storage.kinds(), vec![None, Some(1), Some(2)]);
+
+        storage.compact();
+        assert_eq!(storage.kinds(), vec![Some(2), None]);
+
+        storage.set(0, None);
+        storage.compact();
+        assert_eq!(storage, ChunkStorage::Uniform(None));
+    }
+
+    #[test]
+    fn compact_keeps_dense_chunks_with_too_many_kinds() {
+        let blocks: Vec<Option<BlockId>> = (0..COUNT).map(|idx| Some(idx as BlockId)).collect();
+        let storage = ChunkStorage::from_blocks(blocks.clone());
+
+        assert_eq!(storage, ChunkStorage::Dense(blocks));
+    }
+
+    #[test]
+    fn dense_chunks_compact_back_to_a_palette() {
+        let mut storage = ChunkStorage::Uniform(None);
+        for idx in 0..MAX_PALETTE_SIZE {
+            storage.set(idx, Some(idx as BlockId + 1));
+        }
+        assert!(matches!(storage, ChunkStorage::Dense(_)));
+
+        // back to air and 255 other blocks
+        storage.set(0, None);
+        storage.compact();
+
+        assert!(is_palette(&storage));
+        assert_eq!(storage.get(0), None);
+        assert_eq!(storage.get(1), Some(2));
+        assert_eq!(storage.get(MAX_PALETTE_SIZE), None);
+    }
+
+    #[test]
+    fn from_blocks_of_a_single_kind_is_uniform() {
+        let storage = ChunkStorage::from_blocks(vec![Some(7); COUNT]);
+
+        assert_eq!(storage, ChunkStorage::Uniform(Some(7)));
+        assert_eq!(storage.iter().count(), COUNT);
+    }
+}
@@ -1,11 +1,12 @@
-#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
-/// sRGB-converted representation of a color.
+/// Color converted from sRGB to linear values, which are written to sRGB targets as is.
+/// The GPU converts them back to sRGB, so the color appears as it was specified.
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct RawColor {
@@ -14,12 +15,91 @@ pub struct RawColor {
     b: f32,
 }
 
+/// Converts an sRGB encoded channel from 0 to 255 to its linear value from 0 to 1.
+pub fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+
+    // sRGB is linear close to black, the power curve only starts above it
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 impl From<Color> for RawColor {
     fn from(value: Color) -> Self {
         Self {
-            r: f32::powf((value.r as f32 / 255.0 + 0.055) / 1.055, 2.4),
-            g: f32::powf((value.g as f32 / 255.0 + 0.055) / 1.055, 2.4),
-            b: f32::powf((value.b as f32 / 255.0 + 0.055) / 1.055, 2.4),
+            r: srgb_to_linear(value.r),
+            g: srgb_to_linear(value.g),
+            b: srgb_to_linear(value.b),
+        }
+    }
+}
+
+impl RawColor {
+    pub fn with_alpha(self, alpha: f32) -> glam::Vec4 {
+        glam::Vec4::new(self.r, self.g, self.b, alpha)
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.r, self.g, self.b]
+    }
+
+    /// Multiplies the brightness, values above 1 are brighter than white.
+    pub fn scaled(self, factor: f32) -> Self {
+        Self {
+            r: self.r * factor,
+            g: self.g * factor,
+            b: self.b * factor,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn converts_known_srgb_values_to_linear() {
+        assert_close(srgb_to_linear(0), 0.0);
+        assert_close(srgb_to_linear(255), 1.0);
+        // on the linear segment near black
+        assert_close(srgb_to_linear(10), 0.003_035_27);
+        assert_close(srgb_to_linear(128), 0.215_860_5);
+        assert_close(srgb_to_linear(188), 0.502_886_5);
+    }
+
+    #[test]
+    fn conversion_is_continuous_and_increasing() {
+        for channel in 1..=255u8 {
+            let previous = srgb_to_linear(channel - 1);
+            let current = srgb_to_linear(channel);
+
+            assert!(current > previous);
+            // the steepest step is at the top of the range
+            assert!(current - previous < 0.01);
+        }
+    }
+
+    #[test]
+    fn raw_color_converts_every_channel() {
+        let raw = RawColor::from(Color {
+            r: 255,
+            g: 128,
+            b: 0,
+        });
+
+        let [r, g, b] = raw.to_array();
+        assert_close(r, 1.0);
+        assert_close(g, 0.215_860_5);
+        assert_close(b, 0.0);
+    }
+}
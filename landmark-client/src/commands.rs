@@ -0,0 +1,722 @@
+use landmark_common::{
+    command::{CommandRegistry, PermissionLevel},
+    netsim::NetworkConditions,
+};
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    chat::Chat,
+    connection::{NetworkSimulation, Permissions},
+    coords,
+    debug::DebugRenderState,
+    edit_history::EditHistory,
+    export::{ExportRegion, ExportedMesh},
+    game_map::{BlockId, GameMap},
+    game_mode::GameMode,
+    health::{Damage, DamageCause, DamageEvents},
+    input::InputState,
+    inventory::Inventory,
+    loader::ResourceDictionary,
+    model::MissingModel,
+    physics::EYE_HEIGHT,
+    player::LocalPlayer,
+    save::WorldSave,
+    schematic::{Orientation, Schematic},
+    screenshot::ScreenshotOptions,
+    settings::Settings,
+    teleport::PendingTeleport,
+    tick::TickControl,
+    world_edit::WorldEditor,
+};
+
+/// Level of commands creating items or changing many blocks at once, servers let the same level play
+/// creative.
+const CREATIVE_LEVEL: PermissionLevel = PermissionLevel::Moderator;
+
+/// How far away the block looked at can be picked as a corner of the selection.
+const PICK_DISTANCE: f64 = 64.0;
+/// Commands which move the player or change where they spawn, they are sent to the server while
+/// connected as it owns the player and the spawn points.
+const SERVER_COMMANDS: [&str; 6] = ["setspawn", "spawn", "sethome", "home", "top", "tp"];
+
+/// Returns true if the command line has to be executed by the server while connected.
+pub fn is_server_command(line: &str) -> bool {
+    line.trim()
+        .trim_start_matches('/')
+        .split_whitespace()
+        .next()
+        .is_some_and(|name| SERVER_COMMANDS.contains(&name))
+}
+
+/// Creates the registry of commands which can be typed into the chat after a `/`.
+pub fn client_commands() -> CommandRegistry<World> {
+    let mut commands = CommandRegistry::<World>::new();
+
+    commands.register(
+        "tp",
+        "<x> <y> <z>",
+        "moves the camera to the given position",
+        |world, args| {
+            let [x, y, z] = args else {
+                return Err("expected three coordinates".to_string());
+            };
+
+            let parse = |value: &str| {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| format!("{value} is not a number"))
+            };
+            let position = glam::DVec3::new(parse(x)?, parse(y)?, parse(z)?);
+
+            world.borrow::<UniqueViewMut<Camera>>().unwrap().eye = position;
+
+            Ok(format!(
+                "Teleported to {:.1} {:.1} {:.1}",
+                position.x, position.y, position.z
+            ))
+        },
+    );
+
+    commands.register_restricted(
+        "give",
+        CREATIVE_LEVEL,
+        "<item> [count]",
+        "adds items to the inventory",
+        |world, args| {
+            let (name, count) = match args {
+                [name] => (*name, 1),
+                [name, count] => (
+                    *name,
+                    count
+                        .parse::<u32>()
+                        .map_err(|_| format!("{count} is not a valid count"))?,
+                ),
+                _ => return Err("expected an item name and an optional count".to_string()),
+            };
+
+            let resource_dictionary = world.borrow::<UniqueView<ResourceDictionary>>().unwrap();
+            let Some(id) = resource_dictionary.find_item_id(name) else {
+                return Err(format!("item {name} doesn't exist"));
+            };
+            let item = resource_dictionary.get_item_data_from_id(id);
+
+            let (players, mut inventories) = world
+                .borrow::<(View<LocalPlayer>, ViewMut<Inventory>)>()
+                .unwrap();
+            let Some((_, inventory)) = (&players, &mut inventories).iter().next() else {
+                return Err("there is no player".to_string());
+            };
+
+            let leftover = inventory.add(&item, count);
+
+            Ok(format!("Gave {} {}", count - leftover, item.name))
+        },
+    );
+
+    commands.register(
+        "screenshot",
+        "[nohud] [hires] [clipboard]",
+        "captures a screenshot",
+        |world, args| {
+            let options = ScreenshotOptions::from_args(args)?;
+            world
+                .borrow::<UniqueViewMut<InputState>>()
+                .unwrap()
+                .screenshot = Some(options);
+
+            Ok(String::new())
+        },
+    );
+
+    commands.register(
+        "export",
+        "[x1 y1 z1 x2 y2 z2] <file.obj|file.gltf>",
+        "exports loaded terrain, or the blocks between two corners, for 3D software",
+        |world, args| {
+            // the file name used to come first, so it's still accepted before the corners
+            let split = if args.first().is_some_and(|arg| arg.parse::<i32>().is_err()) {
+                args.split_first()
+            } else {
+                args.split_last()
+            };
+            let Some((path, corners)) = split else {
+                return Err("expected a file name".to_string());
+            };
+
+            let region = match corners {
+                [] => None,
+                [_, _, _, _, _, _] => {
+                    let mut values = [0; 6];
+                    for (value, arg) in values.iter_mut().zip(corners) {
+                        *value = arg
+                            .parse::<i32>()
+                            .map_err(|_| format!("{arg} is not a block coordinate"))?;
+                    }
+
+                    Some(ExportRegion::new(
+                        glam::IVec3::new(values[0], values[1], values[2]),
+                        glam::IVec3::new(values[3], values[4], values[5]),
+                    ))
+                }
+                _ => return Err("expected six coordinates of two corners".to_string()),
+            };
+
+            let (game_map, resource_dictionary) = world
+                .borrow::<(UniqueView<GameMap>, UniqueView<ResourceDictionary>)>()
+                .unwrap();
+
+            let mesh = ExportedMesh::build(&game_map, &resource_dictionary, region);
+            mesh.export(std::path::Path::new(path), &resource_dictionary)
+                .map_err(|e| e.to_string())?;
+
+            Ok(format!(
+                "Exported {} triangles to {path}",
+                mesh.triangle_count()
+            ))
+        },
+    );
+
+    commands.register("reload", "", "reloads resource packs", |world, _| {
+        world
+            .borrow::<UniqueViewMut<InputState>>()
+            .unwrap()
+            .reload_resources = true;
+
+        Ok("Reloading resources".to_string())
+    });
+
+    commands.register(
+        "regenerate",
+        "",
+        "generates chunks which weren't modified again with the current world settings, only in dev mode",
+        |world, _| {
+            if !world.borrow::<UniqueView<Settings>>().unwrap().dev_mode {
+                return Err("regenerating the world requires dev mode".to_string());
+            }
+
+            world
+                .borrow::<UniqueViewMut<InputState>>()
+                .unwrap()
+                .regenerate_world = true;
+
+            Ok(String::new())
+        },
+    );
+
+    commands.register("kill", "", "kills the player", |world, _| {
+        let (mut damage_events, players) = world
+            .borrow::<(UniqueViewMut<DamageEvents>, View<LocalPlayer>)>()
+            .unwrap();
+
+        for entity in players.iter().ids() {
+            damage_events.push(Damage {
+                entity,
+                amount: u32::MAX,
+                cause: DamageCause::Void,
+            });
+        }
+
+        Ok(String::new())
+    });
+
+    commands.register(
+        "gamemode",
+        "[creative|survival]",
+        "switches between flying and building freely, and surviving",
+        |world, args| {
+            let (mut game_mode, permissions) = world
+                .borrow::<(UniqueViewMut<GameMode>, UniqueView<Permissions>)>()
+                .unwrap();
+
+            let mode = match args {
+                [] => game_mode.toggled(),
+                [mode] => mode.parse()?,
+                _ => return Err("expected a game mode".to_string()),
+            };
+            if mode == GameMode::Creative && !permissions.creative {
+                return Err("the server doesn't allow creative".to_string());
+            }
+            *game_mode = mode;
+
+            Ok(format!("Switched to {}", *game_mode))
+        },
+    );
+
+    commands.register(
+        "freezefrustum",
+        "",
+        "keeps culling with the current view while the camera moves on, or unfreezes it",
+        |world, _| {
+            let camera = world.borrow::<UniqueView<Camera>>().unwrap();
+            let mut debug_state = world.borrow::<UniqueViewMut<DebugRenderState>>().unwrap();
+
+            Ok(match debug_state.frozen_view.take() {
+                Some(_) => "Unfroze the view".to_string(),
+                None => {
+                    debug_state.frozen_view = Some(camera.freeze());
+                    "Froze the view, culling follows it until /freezefrustum is used again"
+                        .to_string()
+                }
+            })
+        },
+    );
+
+    commands.register(
+        "viewdistance",
+        "<chunks>",
+        "changes the radius of loaded chunks, the fog follows it",
+        |world, args| {
+            let [chunks] = args else {
+                return Err("expected a number of chunks".to_string());
+            };
+
+            let chunks = chunks
+                .parse::<u32>()
+                .ok()
+                .filter(|chunks| (1..=32).contains(chunks))
+                .ok_or_else(|| format!("{chunks} is not a number of chunks from 1 to 32"))?;
+
+            world
+                .borrow::<UniqueViewMut<Settings>>()
+                .unwrap()
+                .graphics
+                .view_distance = chunks;
+
+            Ok(format!("Set the view distance to {chunks} chunks"))
+        },
+    );
+
+    commands.register(
+        "setspawn",
+        "",
+        "makes the player respawn where they stand",
+        |world, _| {
+            let position = world.borrow::<UniqueView<Camera>>().unwrap().eye;
+
+            world
+                .borrow::<UniqueViewMut<WorldSave>>()
+                .unwrap()
+                .set_spawn_point(position)
+                .map_err(|e| format!("failed to save the spawn point: {e}"))?;
+
+            Ok(format!(
+                "Set the spawn point to {:.1} {:.1} {:.1}",
+                position.x, position.y, position.z
+            ))
+        },
+    );
+
+    commands.register(
+        "spawn",
+        "",
+        "teleports to the spawn point once its chunks are loaded",
+        |world, _| {
+            let position = world
+                .borrow::<UniqueView<WorldSave>>()
+                .unwrap()
+                .spawn_point();
+            world
+                .borrow::<UniqueViewMut<PendingTeleport>>()
+                .unwrap()
+                .request(position);
+
+            Ok("Teleporting to the spawn point".to_string())
+        },
+    );
+
+    commands.register(
+        "sethome",
+        "",
+        "makes /home teleport to where the player stands",
+        |world, _| {
+            let position = world.borrow::<UniqueView<Camera>>().unwrap().eye;
+
+            world
+                .borrow::<UniqueViewMut<WorldSave>>()
+                .unwrap()
+                .set_home_point(position)
+                .map_err(|e| format!("failed to save the home point: {e}"))?;
+
+            Ok(format!(
+                "Set the home point to {:.1} {:.1} {:.1}",
+                position.x, position.y, position.z
+            ))
+        },
+    );
+
+    commands.register(
+        "home",
+        "",
+        "teleports to the point set with /sethome once its chunks are loaded",
+        |world, _| {
+            let Some(position) = world
+                .borrow::<UniqueView<WorldSave>>()
+                .unwrap()
+                .home_point()
+            else {
+                return Err("no home point is set, use /sethome first".to_string());
+            };
+            world
+                .borrow::<UniqueViewMut<PendingTeleport>>()
+                .unwrap()
+                .request(position);
+
+            Ok("Teleporting home".to_string())
+        },
+    );
+
+    commands.register(
+        "top",
+        "",
+        "teleports onto the highest block of the column the player is in",
+        |world, _| {
+            let eye = world.borrow::<UniqueView<Camera>>().unwrap().eye;
+            let Some(height) = world
+                .borrow::<UniqueView<GameMap>>()
+                .unwrap()
+                .surface_height(eye.floor().as_ivec3())
+            else {
+                return Err("there is no block above or below the player".to_string());
+            };
+
+            let position = glam::DVec3::new(eye.x, (height + 1) as f64 + EYE_HEIGHT, eye.z);
+            world
+                .borrow::<UniqueViewMut<PendingTeleport>>()
+                .unwrap()
+                .request(position);
+
+            Ok(format!(
+                "Teleporting to the surface at height {}",
+                height + 1
+            ))
+        },
+    );
+
+    commands.register("clear", "", "clears the chat history", |world, _| {
+        world
+            .borrow::<UniqueViewMut<Chat>>()
+            .unwrap()
+            .history
+            .clear();
+
+        Ok(String::new())
+    });
+
+    commands.register(
+        "netsim",
+        "[off | <latency_ms> [jitter_ms] [loss%]]",
+        "shows or sets simulated latency, jitter and packet loss of the connection",
+        |world, args| {
+            let simulation = world.borrow::<UniqueView<NetworkSimulation>>().unwrap();
+
+            if args.is_empty() {
+                return Ok(format!("Simulating {}", simulation.0.conditions()));
+            }
+
+            let conditions = NetworkConditions::from_args(args)?;
+            simulation.0.set_conditions(conditions);
+
+            Ok(format!("Now simulating {conditions}"))
+        },
+    );
+
+    commands.register(
+        "tick",
+        "[freeze | step [count] | rate <multiplier>]",
+        "freezes, steps or changes the speed of the simulation, rendering continues",
+        |world, args| {
+            let mut tick_control = world.borrow::<UniqueViewMut<TickControl>>().unwrap();
+
+            match args {
+                [] => Ok(format!(
+                    "The simulation is {} at {}x speed",
+                    if tick_control.is_frozen() {
+                        "frozen"
+                    } else {
+                        "running"
+                    },
+                    tick_control.rate()
+                )),
+                ["freeze"] => {
+                    let frozen = !tick_control.is_frozen();
+                    tick_control.set_frozen(frozen);
+
+                    Ok(if frozen {
+                        "Froze the simulation".to_string()
+                    } else {
+                        "Resumed the simulation".to_string()
+                    })
+                }
+                ["step", count @ ..] => {
+                    let count = match count {
+                        [] => 1,
+                        [count] => count
+                            .parse::<u32>()
+                            .map_err(|_| format!("{count} is not a valid step count"))?,
+                        _ => return Err("expected a single step count".to_string()),
+                    };
+                    tick_control.step(count);
+
+                    Ok(format!("Stepping the simulation {count} times"))
+                }
+                ["rate", rate] => {
+                    let rate = rate
+                        .parse::<f64>()
+                        .map_err(|_| format!("{rate} is not a number"))?;
+                    tick_control.set_rate(rate)?;
+
+                    Ok(format!("Running the simulation at {rate}x speed"))
+                }
+                _ => Err("expected freeze, step or rate".to_string()),
+            }
+        },
+    );
+
+    for (index, name) in [(0, "pos1"), (1, "pos2")] {
+        commands.register(
+            name,
+            "[x y z]",
+            "picks a corner of the selection, the block looked at or stood in by default",
+            move |world, args| {
+                let position = match args {
+                    [] => {
+                        let (camera, game_map) = world
+                            .borrow::<(UniqueView<Camera>, UniqueView<GameMap>)>()
+                            .unwrap();
+
+                        game_map
+                            .raycast(camera.eye, camera.look_direction(), PICK_DISTANCE)
+                            .map_or_else(
+                                || coords::block_containing(camera.eye),
+                                |hit| hit.block_coords,
+                            )
+                    }
+                    [x, y, z] => {
+                        let parse = |value: &str| {
+                            value
+                                .parse::<i32>()
+                                .map_err(|_| format!("{value} is not a block coordinate"))
+                        };
+
+                        glam::IVec3::new(parse(x)?, parse(y)?, parse(z)?)
+                    }
+                    _ => return Err("expected three coordinates or none".to_string()),
+                };
+
+                let mut editor = world.borrow::<UniqueViewMut<WorldEditor>>().unwrap();
+                editor.corners[index] = Some(position);
+
+                let mut message = format!(
+                    "Set corner {} to {} {} {}",
+                    index + 1,
+                    position.x,
+                    position.y,
+                    position.z
+                );
+                if let Ok(selection) = editor.selection() {
+                    message += &format!(", {} blocks selected", selection.volume());
+                }
+
+                Ok(message)
+            },
+        );
+    }
+
+    commands.register_restricted(
+        "fill",
+        CREATIVE_LEVEL,
+        "<block|air>",
+        "sets every block of the selection",
+        |world, args| {
+            let [name] = args else {
+                return Err("expected a block name".to_string());
+            };
+            let block = parse_block(world, name)?;
+
+            let (editor, mut history, mut game_map, mut missing_models) = world
+                .borrow::<(
+                    UniqueView<WorldEditor>,
+                    UniqueViewMut<EditHistory>,
+                    UniqueViewMut<GameMap>,
+                    ViewMut<MissingModel>,
+                )>()
+                .unwrap();
+            let transaction = editor.fill(&mut game_map, &mut missing_models, block)?;
+            let changed = transaction.len();
+            history.record(transaction);
+
+            Ok(format!("Changed {changed} blocks"))
+        },
+    );
+
+    commands.register_restricted(
+        "replace",
+        CREATIVE_LEVEL,
+        "<from|air> <to|air>",
+        "replaces one block with another in the selection",
+        |world, args| {
+            let [from, to] = args else {
+                return Err("expected the names of two blocks".to_string());
+            };
+            let (from, to) = (parse_block(world, from)?, parse_block(world, to)?);
+
+            let (editor, mut history, mut game_map, mut missing_models) = world
+                .borrow::<(
+                    UniqueView<WorldEditor>,
+                    UniqueViewMut<EditHistory>,
+                    UniqueViewMut<GameMap>,
+                    ViewMut<MissingModel>,
+                )>()
+                .unwrap();
+            let transaction = editor.replace(&mut game_map, &mut missing_models, from, to)?;
+            let changed = transaction.len();
+            history.record(transaction);
+
+            Ok(format!("Replaced {changed} blocks"))
+        },
+    );
+
+    commands.register(
+        "copy",
+        "",
+        "copies the selection, relative to where the player stands",
+        |world, _| {
+            let (mut editor, game_map, camera) = world
+                .borrow::<(
+                    UniqueViewMut<WorldEditor>,
+                    UniqueView<GameMap>,
+                    UniqueView<Camera>,
+                )>()
+                .unwrap();
+            let copied = editor.copy(&game_map, coords::block_containing(camera.eye))?;
+
+            Ok(format!("Copied {copied} blocks"))
+        },
+    );
+
+    commands.register_restricted(
+        "paste",
+        CREATIVE_LEVEL,
+        "[90|180|270] [mirror]",
+        "places the copied blocks where they were relative to the player, turned around the player",
+        |world, args| {
+            let orientation = Orientation::from_args(args)?;
+
+            let (editor, mut history, mut game_map, mut missing_models, camera) = world
+                .borrow::<(
+                    UniqueView<WorldEditor>,
+                    UniqueViewMut<EditHistory>,
+                    UniqueViewMut<GameMap>,
+                    ViewMut<MissingModel>,
+                    UniqueView<Camera>,
+                )>()
+                .unwrap();
+            let transaction = editor.paste(
+                &mut game_map,
+                &mut missing_models,
+                coords::block_containing(camera.eye),
+                orientation,
+            )?;
+            let changed = transaction.len();
+            history.record(transaction);
+
+            Ok(format!("Changed {changed} blocks"))
+        },
+    );
+
+    commands.register(
+        "schematic",
+        "save <file> | load <file>",
+        "saves the selection to a file, or loads a file to be pasted",
+        |world, args| {
+            let (resource_dictionary, mut editor) = world
+                .borrow::<(UniqueView<ResourceDictionary>, UniqueViewMut<WorldEditor>)>()
+                .unwrap();
+
+            match args {
+                ["save", path] => {
+                    let selection = editor.selection()?;
+                    let game_map = world.borrow::<UniqueView<GameMap>>().unwrap();
+
+                    Schematic::from_selection(&game_map, &selection)
+                        .save(std::path::Path::new(path), &resource_dictionary)
+                        .map_err(|e| format!("failed to save {path}: {e}"))?;
+
+                    Ok(format!("Saved {} blocks to {path}", selection.volume()))
+                }
+                ["load", path] => {
+                    let schematic =
+                        Schematic::load(std::path::Path::new(path), &resource_dictionary)
+                            .map_err(|e| e.to_string())?;
+                    let size = schematic.size();
+                    editor.set_clipboard(schematic);
+
+                    Ok(format!(
+                        "Loaded {} x {} x {} blocks, use /paste to place them",
+                        size.x, size.y, size.z
+                    ))
+                }
+                _ => Err("expected save or load and a file name".to_string()),
+            }
+        },
+    );
+
+    commands.register_restricted(
+        "undo",
+        CREATIVE_LEVEL,
+        "",
+        "reverts the last block edit",
+        |world, _| {
+            let (mut history, mut game_map, mut missing_models) = world
+                .borrow::<(
+                    UniqueViewMut<EditHistory>,
+                    UniqueViewMut<GameMap>,
+                    ViewMut<MissingModel>,
+                )>()
+                .unwrap();
+            let changed = history
+                .undo(&mut game_map, &mut missing_models)
+                .ok_or("there is nothing to undo")?;
+
+            Ok(format!("Reverted {changed} blocks"))
+        },
+    );
+
+    commands.register_restricted(
+        "redo",
+        CREATIVE_LEVEL,
+        "",
+        "applies the last undone block edit again",
+        |world, _| {
+            let (mut history, mut game_map, mut missing_models) = world
+                .borrow::<(
+                    UniqueViewMut<EditHistory>,
+                    UniqueViewMut<GameMap>,
+                    ViewMut<MissingModel>,
+                )>()
+                .unwrap();
+            let changed = history
+                .redo(&mut game_map, &mut missing_models)
+                .ok_or("there is nothing to redo")?;
+
+            Ok(format!("Changed {changed} blocks again"))
+        },
+    );
+
+    commands
+}
+
+/// Finds a block by name, `air` is no block.
+fn parse_block(world: &World, name: &str) -> Result<Option<BlockId>, String> {
+    if name == "air" {
+        return Ok(None);
+    }
+
+    world
+        .borrow::<UniqueView<ResourceDictionary>>()
+        .unwrap()
+        .find_block_id(name)
+        .map(Some)
+        .ok_or_else(|| format!("block {name} doesn't exist"))
+}
@@ -0,0 +1,421 @@
+use std::{
+    fmt,
+    net::TcpStream,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use landmark_common::{
+    command::PermissionLevel,
+    netsim::{NetworkSimulator, SimulatedStream},
+    secure::{fingerprint, Keypair, SecureError, SecureStream, TrustStore, Verification},
+};
+use landmark_protocol::{
+    handshake,
+    message::{ClientMessage, ItemStack, PlayerData, ServerMessage, Welcome},
+    transport::{recv_message, send_message, try_recv_message, TcpTransport, Transport},
+    ProtocolError,
+};
+use landmark_server::LocalServer;
+use shipyard::{IntoIter, Unique, UniqueViewMut, View};
+
+use crate::{
+    chat::{ChatBus, ChatMessage},
+    chunk_loader::ServerChunks,
+    game_map::GameMap,
+    game_mode::GameMode,
+    inventory::Inventory,
+    player::LocalPlayer,
+    prediction::Prediction,
+    replication::Replication,
+    teleport::PendingTeleport,
+    weather::WeatherState,
+    world_store::WorldStore,
+};
+
+/// File storing identities of servers the client has connected to.
+const KNOWN_SERVERS_PATH: &str = "known_servers.txt";
+/// File holding the key pair which identifies the player to servers.
+pub const IDENTITY_KEY_PATH: &str = "identity.key";
+/// How long to wait for the server to close the connection after saying goodbye.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Simulated network conditions applied to connections to servers, changed by the `/netsim` command.
+#[derive(Debug, Default, Clone, Unique)]
+pub struct NetworkSimulation(pub NetworkSimulator);
+
+#[derive(Debug)]
+pub enum ConnectionError {
+    Secure(SecureError),
+    Protocol(ProtocolError),
+    /// The server key differs from the one seen previously at the same address.
+    IdentityChanged {
+        address: String,
+        expected: Vec<u8>,
+        received: Vec<u8>,
+    },
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::Secure(e) => write!(f, "{e}"),
+            ConnectionError::Protocol(e) => write!(f, "{e}"),
+            ConnectionError::IdentityChanged {
+                address,
+                expected,
+                received,
+            } => write!(
+                f,
+                "Identity of server {address} has changed! Expected {}, received {}. \
+                 If this is intended, remove its entry from {KNOWN_SERVERS_PATH}",
+                fingerprint(expected),
+                fingerprint(received)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
+
+impl From<SecureError> for ConnectionError {
+    fn from(value: SecureError) -> Self {
+        ConnectionError::Secure(value)
+    }
+}
+
+impl From<ProtocolError> for ConnectionError {
+    fn from(value: ProtocolError) -> Self {
+        ConnectionError::Protocol(value)
+    }
+}
+
+impl From<std::io::Error> for ConnectionError {
+    fn from(value: std::io::Error) -> Self {
+        ConnectionError::Secure(value.into())
+    }
+}
+
+/// What the server allows the player, the player may do anything while not connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Unique)]
+pub struct Permissions {
+    /// Level commands of the client run at.
+    pub level: PermissionLevel,
+    /// Whether the player may play creative.
+    pub creative: bool,
+}
+
+impl Permissions {
+    /// Permissions of a player until the server told them theirs.
+    pub const GUEST: Self = Self {
+        level: PermissionLevel::Guest,
+        creative: false,
+    };
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            level: PermissionLevel::Admin,
+            creative: true,
+        }
+    }
+}
+
+/// Connection of the game to the server it plays on. In singleplayer that's a server running in the
+/// same process, so the game behaves exactly like it does online.
+#[derive(Default, Unique)]
+pub struct ServerLink {
+    // behind a mutex only to make the link shareable, it's never actually locked
+    transport: Option<Mutex<Box<dyn Transport>>>,
+    /// Keeps the embedded server running while playing singleplayer.
+    local_server: Option<LocalServer>,
+    welcome: Option<Welcome>,
+    /// State of the player saved by the server in the previous session.
+    pub player: Option<PlayerData>,
+    /// Inventory slots the server was told about last.
+    sent_inventory: Vec<Option<ItemStack>>,
+    /// Game mode the server was told about last, None until it was told after joining.
+    sent_game_mode: Option<GameMode>,
+}
+
+impl ServerLink {
+    /// Starts a server in this process on the world in `world_dir`, whose chunks are kept in `chunks`,
+    /// and joins it as the player with the given name.
+    pub fn singleplayer(
+        name: &str,
+        world_dir: &Path,
+        chunks: WorldStore,
+    ) -> Result<Self, ConnectionError> {
+        let (local_server, transport) = LocalServer::spawn(world_dir, Box::new(chunks))?;
+
+        let mut link = Self::join(Box::new(transport), name)?;
+        link.local_server = Some(local_server);
+
+        Ok(link)
+    }
+
+    /// Agrees on the protocol with the server and joins as the player with the given name.
+    fn join(mut transport: Box<dyn Transport>, name: &str) -> Result<Self, ConnectionError> {
+        send_message(
+            transport.as_mut(),
+            &ClientMessage::Hello(handshake::hello(name)),
+            false,
+        )?;
+
+        let welcome = handshake::accept_response(recv_message(transport.as_mut())?)?;
+        log::info!(
+            "Speaking protocol version {}: {}",
+            welcome.version,
+            welcome.motd
+        );
+
+        let player = handshake::accept_join(recv_message(transport.as_mut())?)?;
+
+        Ok(Self {
+            transport: Some(Mutex::new(transport)),
+            local_server: None,
+            welcome: Some(welcome),
+            sent_inventory: player.inventory.clone(),
+            sent_game_mode: None,
+            player: Some(player),
+        })
+    }
+
+    /// Returns the message of the day of the server, which names the world.
+    pub fn server_name(&self) -> Option<&str> {
+        self.welcome.as_ref().map(|welcome| welcome.motd.as_str())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// Says goodbye to the server and waits a moment until it closes the connection, so it has saved
+    /// the player. The embedded server is stopped afterwards.
+    pub fn disconnect(&mut self) {
+        if let Some(transport) = self.transport.take() {
+            let mut transport = transport.into_inner().unwrap();
+
+            if send_message(transport.as_mut(), &ClientMessage::Disconnect, false).is_ok() {
+                let deadline = Instant::now() + DISCONNECT_TIMEOUT;
+
+                while Instant::now() < deadline {
+                    match transport.try_recv() {
+                        Ok(None) => std::thread::sleep(Duration::from_millis(1)),
+                        Ok(Some(_)) => {}
+                        Err(_) => break,
+                    }
+                }
+            }
+        }
+
+        if let Some(mut local_server) = self.local_server.take() {
+            local_server.stop();
+        }
+    }
+
+    /// Sends what the game queued for the server and hands the received messages to the systems
+    /// handling them.
+    #[allow(clippy::too_many_arguments)]
+    fn exchange(
+        &mut self,
+        bus: &mut ChatBus,
+        prediction: &mut Prediction,
+        server_chunks: &mut ServerChunks,
+        replication: &mut Replication,
+        weather: &mut WeatherState,
+        game_map: &mut GameMap,
+        inventory: Option<&Inventory>,
+        teleport: &mut PendingTeleport,
+        permissions: &mut Permissions,
+        game_mode: &mut GameMode,
+    ) -> Result<(), ProtocolError> {
+        // edits made while offline only change the local copy of the world
+        let changed_blocks = std::mem::take(&mut game_map.unsent_blocks);
+
+        let compression = self
+            .welcome
+            .as_ref()
+            .is_some_and(|welcome| welcome.compression);
+        let Some(transport) = self.transport.as_mut() else {
+            return Ok(());
+        };
+        let transport = transport.get_mut().unwrap().as_mut();
+
+        for message in bus.outgoing.drain(..) {
+            send_message(transport, &ClientMessage::Chat(message.text), compression)?;
+        }
+
+        for line in bus.commands.drain(..) {
+            send_message(transport, &ClientMessage::Command(line), compression)?;
+        }
+
+        for input in prediction.outgoing.drain(..) {
+            send_message(transport, &ClientMessage::Input(input), compression)?;
+        }
+
+        if teleport.take_respawn_request() {
+            send_message(transport, &ClientMessage::Respawn, compression)?;
+        }
+
+        // sent before the blocks, so the server judges them by the mode they were changed in
+        if self.sent_game_mode != Some(*game_mode) {
+            self.sent_game_mode = Some(*game_mode);
+            let message = ClientMessage::GameMode {
+                creative: *game_mode == GameMode::Creative,
+            };
+            send_message(transport, &message, compression)?;
+        }
+
+        for position in changed_blocks {
+            let message = ClientMessage::SetBlock {
+                position: position.to_array(),
+                block: game_map.get_block_world(position),
+            };
+            send_message(transport, &message, compression)?;
+        }
+
+        if let Some(inventory) =
+            inventory.filter(|inventory| inventory.slots() != self.sent_inventory)
+        {
+            self.sent_inventory = inventory.slots().to_vec();
+            let message = ClientMessage::Inventory(self.sent_inventory.clone());
+            send_message(transport, &message, compression)?;
+        }
+
+        while let Some(message) = try_recv_message::<ServerMessage>(transport)? {
+            match message {
+                ServerMessage::Chat { sender, text } => {
+                    bus.incoming.push_back(ChatMessage { sender, text });
+                }
+                ServerMessage::Chunk(_)
+                | ServerMessage::UnloadChunk(_)
+                | ServerMessage::BlockChanged { .. } => server_chunks.received.push(message),
+                ServerMessage::SpawnEntity { .. }
+                | ServerMessage::UpdateEntity { .. }
+                | ServerMessage::DespawnEntity(_) => replication.received.push(message),
+                ServerMessage::PlayerState {
+                    last_input,
+                    position,
+                } => prediction
+                    .corrections
+                    .push((last_input, glam::DVec3::from_array(position))),
+                ServerMessage::Weather(new_weather) => weather.set(new_weather),
+                ServerMessage::Teleport(position) => {
+                    prediction.teleported();
+                    teleport.request(glam::DVec3::from_array(position));
+                }
+                ServerMessage::Permissions { level, creative } => {
+                    *permissions = Permissions { level, creative };
+
+                    if !creative && *game_mode == GameMode::Creative {
+                        *game_mode = GameMode::Survival;
+                        bus.incoming.push_back(ChatMessage {
+                            sender: None,
+                            text: "Switched to survival, the server doesn't allow creative"
+                                .to_string(),
+                        });
+                    }
+                }
+                ServerMessage::Disconnect { reason } | ServerMessage::Rejected { reason } => {
+                    bus.incoming.push_back(ChatMessage {
+                        sender: None,
+                        text: format!("Disconnected: {reason}"),
+                    });
+
+                    return Err(ProtocolError::Closed);
+                }
+                ServerMessage::Welcome(_) | ServerMessage::Joined(_) => {
+                    log::warn!("Ignoring a repeated handshake message from the server");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Passes messages between the game and the server.
+#[allow(clippy::too_many_arguments)]
+pub fn server_link_sys(
+    mut link: UniqueViewMut<ServerLink>,
+    mut bus: UniqueViewMut<ChatBus>,
+    mut prediction: UniqueViewMut<Prediction>,
+    mut server_chunks: UniqueViewMut<ServerChunks>,
+    mut replication: UniqueViewMut<Replication>,
+    mut weather: UniqueViewMut<WeatherState>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut teleport: UniqueViewMut<PendingTeleport>,
+    (players, inventories): (View<LocalPlayer>, View<Inventory>),
+    (mut permissions, mut game_mode): (UniqueViewMut<Permissions>, UniqueViewMut<GameMode>),
+) {
+    let inventory = (&players, &inventories)
+        .iter()
+        .next()
+        .map(|(_, inventory)| inventory);
+
+    if let Err(e) = link.exchange(
+        &mut bus,
+        &mut prediction,
+        &mut server_chunks,
+        &mut replication,
+        &mut weather,
+        &mut game_map,
+        inventory,
+        &mut teleport,
+        &mut permissions,
+        &mut game_mode,
+    ) {
+        if !matches!(e, ProtocolError::Closed) {
+            log::error!("Lost the connection to the server: {e}");
+        }
+
+        link.disconnect();
+        bus.connected = false;
+        prediction.connected = false;
+        teleport.connected = false;
+        *permissions = Permissions::default();
+    }
+}
+
+/// Opens an encrypted connection to the server, verifies its identity, agrees on the protocol and joins
+/// as the player with the given name. The server knows the player by the identity key, not the name.
+pub fn connect(
+    address: &str,
+    name: &str,
+    identity: &Keypair,
+    simulation: &NetworkSimulation,
+) -> Result<ServerLink, ConnectionError> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+
+    let stream = SecureStream::connect(stream, identity)?;
+    let server_key = stream.remote_public_key()?.to_vec();
+
+    let mut trust_store = TrustStore::load(Path::new(KNOWN_SERVERS_PATH))?;
+
+    match trust_store.verify(address, &server_key)? {
+        Verification::Trusted => {
+            log::info!("Connected to {address}, server identity verified");
+        }
+        Verification::NewServer => {
+            log::warn!(
+                "Connected to {address} for the first time, trusting its identity {}",
+                fingerprint(&server_key)
+            );
+        }
+        Verification::Changed { expected } => {
+            return Err(ConnectionError::IdentityChanged {
+                address: address.to_string(),
+                expected,
+                received: server_key,
+            });
+        }
+    }
+
+    let transport = TcpTransport::new(SimulatedStream::new(stream, simulation.0.clone()));
+
+    ServerLink::join(Box::new(transport), name)
+}
@@ -0,0 +1,170 @@
+//! Conversions between world block positions, chunk coordinates and positions within chunks.
+//!
+//! World positions are split with floor division, so block -1 lies in chunk -1 at the inner position 31,
+//! truncating division would put it into chunk 0 together with block 0.
+
+use crate::game_map::{Chunk, ChunkCoords, InnerChunkCoords};
+
+/// Returns the block containing a world space position.
+pub fn block_containing(position: glam::DVec3) -> glam::IVec3 {
+    position.floor().as_ivec3()
+}
+
+/// Returns the chunk containing a world block position.
+pub fn chunk_of_block(position: glam::IVec3) -> ChunkCoords {
+    let coords = position.div_euclid(glam::IVec3::splat(Chunk::SIZE));
+
+    ChunkCoords::new(coords.x, coords.y, coords.z)
+}
+
+/// Splits a world block position into the chunk containing it and the position within that chunk.
+pub fn split_block(position: glam::IVec3) -> (ChunkCoords, InnerChunkCoords) {
+    let inner = position.rem_euclid(glam::IVec3::splat(Chunk::SIZE));
+
+    (
+        chunk_of_block(position),
+        InnerChunkCoords::new(inner.x, inner.y, inner.z),
+    )
+}
+
+/// Returns the world block position of a block within a chunk, the inverse of `split_block`.
+pub fn join_block(coords: ChunkCoords, inner: InnerChunkCoords) -> glam::IVec3 {
+    chunk_origin(coords) + inner.as_ivec3()
+}
+
+/// Returns the world position of the block in the minimum corner of a chunk.
+pub fn chunk_origin(coords: ChunkCoords) -> glam::IVec3 {
+    coords.as_ivec3() * Chunk::SIZE
+}
+
+/// Returns the position within a chunk for an offset from its origin, or None if it lies outside of the chunk.
+pub fn inner_from_offset(offset: glam::IVec3) -> Option<InnerChunkCoords> {
+    let inside = offset.cmpge(glam::IVec3::ZERO).all()
+        && offset.cmplt(glam::IVec3::splat(Chunk::SIZE)).all();
+
+    inside.then(|| InnerChunkCoords::new(offset.x, offset.y, offset.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_positive_positions() {
+        let (coords, inner) = split_block(glam::IVec3::new(0, 31, 32));
+
+        assert_eq!(coords, ChunkCoords::new(0, 0, 1));
+        assert_eq!(inner, InnerChunkCoords::new(0, 31, 0));
+    }
+
+    #[test]
+    fn splits_negative_positions_with_floor_division() {
+        let (coords, inner) = split_block(glam::IVec3::new(-1, -32, -33));
+
+        assert_eq!(coords, ChunkCoords::new(-1, -1, -2));
+        assert_eq!(inner, InnerChunkCoords::new(31, 0, 31));
+    }
+
+    #[test]
+    fn join_is_inverse_of_split() {
+        for value in [-65, -64, -33, -32, -31, -1, 0, 1, 31, 32, 33, 64] {
+            let position = glam::IVec3::new(value, -value, value * 3);
+            let (coords, inner) = split_block(position);
+
+            assert_eq!(join_block(coords, inner), position);
+            assert_eq!(chunk_of_block(position), coords);
+        }
+    }
+
+    #[test]
+    fn block_containing_rounds_down() {
+        assert_eq!(
+            block_containing(glam::DVec3::new(-0.5, 0.5, -32.0)),
+            glam::IVec3::new(-1, 0, -32)
+        );
+        assert_eq!(
+            chunk_of_block(block_containing(glam::DVec3::new(-0.001, 31.999, 32.0))),
+            ChunkCoords::new(-1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn chunk_origin_of_negative_chunk() {
+        assert_eq!(
+            chunk_origin(ChunkCoords::new(-1, 0, 2)),
+            glam::IVec3::new(-32, 0, 64)
+        );
+    }
+
+    #[test]
+    fn offsets_outside_of_chunk_are_rejected() {
+        assert_eq!(
+            inner_from_offset(glam::IVec3::new(0, 31, 5)),
+            Some(InnerChunkCoords::new(0, 31, 5))
+        );
+        assert_eq!(inner_from_offset(glam::IVec3::new(-1, 0, 0)), None);
+        assert_eq!(inner_from_offset(glam::IVec3::new(0, 32, 0)), None);
+    }
+
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        /// World block positions far enough from the i32 limits for the chunk origin to be representable.
+        fn block_position() -> impl Strategy<Value = glam::IVec3> {
+            let range = -(1 << 26)..(1 << 26);
+            (range.clone(), range.clone(), range).prop_map(|(x, y, z)| glam::IVec3::new(x, y, z))
+        }
+
+        fn inner_position() -> impl Strategy<Value = InnerChunkCoords> {
+            (0..Chunk::SIZE, 0..Chunk::SIZE, 0..Chunk::SIZE)
+                .prop_map(|(x, y, z)| InnerChunkCoords::new(x, y, z))
+        }
+
+        proptest! {
+            #[test]
+            fn idx_is_in_bounds_and_roundtrips(inner in inner_position()) {
+                let idx = inner.as_idx();
+                let size = Chunk::SIZE as usize;
+
+                prop_assert!(idx < Chunk::BLOCKS_COUNT as usize);
+
+                let (x, y, z) = (idx % size, idx / size % size, idx / (size * size));
+                prop_assert_eq!(InnerChunkCoords::new(x as i32, y as i32, z as i32), inner);
+            }
+
+            #[test]
+            fn idx_is_unique(a in inner_position(), b in inner_position()) {
+                prop_assert_eq!(a.as_idx() == b.as_idx(), a == b);
+            }
+
+            #[test]
+            fn split_roundtrips(position in block_position()) {
+                let (coords, inner) = split_block(position);
+
+                prop_assert_eq!(join_block(coords, inner), position);
+                prop_assert_eq!(inner_from_offset(position - chunk_origin(coords)), Some(inner));
+            }
+
+            #[test]
+            fn join_roundtrips(
+                coords in block_position().prop_map(|c| ChunkCoords::new(c.x >> 5, c.y >> 5, c.z >> 5)),
+                inner in inner_position(),
+            ) {
+                prop_assert_eq!(split_block(join_block(coords, inner)), (coords, inner));
+            }
+
+            #[test]
+            fn world_position_is_in_the_same_chunk_as_its_block(
+                block in block_position(),
+                fraction in (0.0..1.0, 0.0..1.0, 0.0..1.0),
+            ) {
+                let position = block.as_dvec3() + glam::DVec3::new(fraction.0, fraction.1, fraction.2);
+
+                prop_assert_eq!(block_containing(position), block);
+                prop_assert_eq!(ChunkCoords::from_world_position(position), chunk_of_block(block));
+            }
+        }
+    }
+}
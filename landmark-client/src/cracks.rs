@@ -0,0 +1,120 @@
+//! Cracks drawn over the block the player is breaking, spreading as the block gets closer to breaking.
+
+use shipyard::*;
+
+use crate::{
+    atlas::TextureAtlas,
+    camera::Camera,
+    color::Color,
+    drops::BreakingProgress,
+    model::{Model, ModelConstructor, Vertex},
+    rendererer::Renderer,
+    transform::Transform,
+};
+
+/// Cracks starting at the center of every face.
+const CRACK_RAYS: u32 = 5;
+/// Segments of every crack, revealed one after another.
+const CRACK_SEGMENTS: u32 = 3;
+/// Distance of the lines from the faces, so they aren't hidden by the block.
+const FACE_OFFSET: f32 = 0.005;
+const CRACK_COLOR: Color = Color {
+    r: 20,
+    g: 20,
+    b: 20,
+};
+
+/// Returns a number from -0.5 to 0.5 which only depends on `seed`.
+fn jitter(seed: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9e37_79b9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+
+    (x & 0xffff) as f32 / 0xffff as f32 - 0.5
+}
+
+/// Builds a line list model of the cracks of a unit block, `progress` from 0 to 1 decides how far they spread.
+/// The cracks have the same shape on every block, only more of them is shown as the progress increases.
+pub fn crack_model_constructor(progress: f32) -> ModelConstructor {
+    let mut model_constructor = ModelConstructor::new();
+    let uv = TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT).min;
+
+    // segments are revealed in rings, so all cracks grow at the same time
+    let steps = CRACK_RAYS * CRACK_SEGMENTS;
+    let shown = (progress * steps as f32).ceil() as u32;
+
+    for axis in 0..3 {
+        for side in [0.0, 1.0] {
+            let face = axis as u32 * 2 + side as u32;
+            let depth = if side == 0.0 {
+                -FACE_OFFSET
+            } else {
+                1.0 + FACE_OFFSET
+            };
+
+            // maps a point on the face to the block, the face is spanned by the other two axes
+            let point = |p: glam::Vec2| {
+                let mut position = glam::Vec3::ZERO;
+                position[axis] = depth;
+                position[(axis + 1) % 3] = p.x;
+                position[(axis + 2) % 3] = p.y;
+                position
+            };
+
+            for ray in 0..CRACK_RAYS {
+                let base_angle = (ray as f32 + jitter(face * 31 + ray) * 0.5) / CRACK_RAYS as f32
+                    * std::f32::consts::TAU;
+                let mut start = glam::Vec2::splat(0.5);
+
+                for segment in 0..CRACK_SEGMENTS {
+                    if segment * CRACK_RAYS + ray >= shown {
+                        break;
+                    }
+
+                    let seed = (face * CRACK_RAYS + ray) * CRACK_SEGMENTS + segment;
+                    let angle = base_angle + jitter(seed) * 0.8;
+                    let radius = (segment + 1) as f32 / CRACK_SEGMENTS as f32 * 0.48;
+                    let end = glam::Vec2::splat(0.5) + glam::Vec2::from_angle(angle) * radius;
+
+                    let idx = model_constructor.vertices.len() as u16;
+                    for p in [start, end] {
+                        model_constructor.vertices.push(Vertex {
+                            position: point(p),
+                            color: CRACK_COLOR.into(),
+                            uv,
+                        });
+                    }
+                    model_constructor.indices.extend([idx, idx + 1]);
+
+                    start = end;
+                }
+            }
+        }
+    }
+
+    model_constructor
+}
+
+/// Rebuilds the cracks of the block being broken, relative to the camera origin.
+pub fn update_crack_model_sys(
+    mut renderer: UniqueViewMut<Renderer>,
+    camera: UniqueView<Camera>,
+    progress: UniqueView<BreakingProgress>,
+) {
+    renderer.crack_model = progress.current().and_then(|(block, progress)| {
+        let mut model_constructor = crack_model_constructor(progress);
+
+        if model_constructor.indices.is_empty() {
+            return None;
+        }
+
+        let translation = block.as_dvec3() - camera.origin.as_world_position();
+        model_constructor.transform = Transform {
+            rotation: glam::Quat::IDENTITY,
+            translation: translation.as_vec3(),
+        };
+
+        Some(Model::new(&renderer.device, &model_constructor))
+    });
+}
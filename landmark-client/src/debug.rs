@@ -0,0 +1,200 @@
+use std::fmt;
+
+use shipyard::*;
+
+use crate::{
+    atlas::TextureAtlas,
+    camera::{Camera, FrozenView},
+    color::Color,
+    game_map::{Chunk, ChunkTag},
+    model::{Model, ModelConstructor, Vertex},
+    rendererer::Renderer,
+    spawning::Mob,
+    transform::Transform,
+};
+
+/// Debug visualization toggles used by the renderer.
+#[derive(Debug, Unique, Default)]
+pub struct DebugRenderState {
+    pub wireframe: bool,
+    pub chunk_borders: bool,
+    /// Shows profiler timings in the window title.
+    pub profiler: bool,
+    /// Shows spawn attempts and their rejections in the window title.
+    pub spawn_stats: bool,
+    /// Shows counts of drawn and culled models in the window title.
+    pub draw_stats: bool,
+    /// View which culls the world instead of the moving camera, set by `/freezefrustum`.
+    pub frozen_view: Option<FrozenView>,
+}
+
+/// Models drawn in the last frame and the ones skipped because they were outside of the view.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawStats {
+    pub chunks: CullCount,
+    /// Mobs and item drops.
+    pub entities: CullCount,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CullCount {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+impl CullCount {
+    pub fn count(&mut self, visible: bool) {
+        if visible {
+            self.drawn += 1;
+        } else {
+            self.culled += 1;
+        }
+    }
+}
+
+impl fmt::Display for DrawStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "chunks {} drawn {} culled, entities {} drawn {} culled",
+            self.chunks.drawn, self.chunks.culled, self.entities.drawn, self.entities.culled
+        )
+    }
+}
+
+/// Sizes of the models held by the GPU, shown together with the draw stats.
+#[derive(Debug, Clone, Copy, Default, Unique)]
+pub struct RenderStats {
+    pub vertices: u64,
+    pub indices: u64,
+    /// Size of the vertex, index and instance buffers of all models.
+    pub buffer_bytes: u64,
+    pub chunk_models: u32,
+    /// Models created in the last frame.
+    pub meshes_built: u32,
+    /// Meshes of new chunks held back in the last frame because of the GPU memory budget.
+    pub deferred_meshes: u32,
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} chunk models, {} vertices, {} indices, {:.1} MiB, {} built, {} deferred",
+            self.chunk_models,
+            self.vertices,
+            self.indices,
+            self.buffer_bytes as f64 / (1024.0 * 1024.0),
+            self.meshes_built,
+            self.deferred_meshes
+        )
+    }
+}
+
+/// Sums up the sizes of all models.
+pub fn update_render_stats_sys(
+    mut render_stats: UniqueViewMut<RenderStats>,
+    models: View<Model>,
+    chunks: View<ChunkTag>,
+) {
+    render_stats.vertices = 0;
+    render_stats.indices = 0;
+    render_stats.buffer_bytes = 0;
+
+    for model in models.iter() {
+        render_stats.vertices += model.vertex_count() as u64;
+        render_stats.indices += model.index_count() as u64;
+        render_stats.buffer_bytes += model.buffer_bytes();
+    }
+
+    render_stats.chunk_models = (&chunks, &models).iter().count() as u32;
+}
+
+/// Builds a line list model of a box spanning a single chunk.
+pub fn chunk_border_model_constructor() -> ModelConstructor {
+    box_model_constructor(
+        glam::Vec3::splat(Chunk::SIZE as f32),
+        Color {
+            r: 255,
+            g: 220,
+            b: 0,
+        },
+    )
+}
+
+/// Builds a line list model of the box mobs are drawn as, until they have models of their own.
+pub fn mob_model_constructor() -> ModelConstructor {
+    box_model_constructor(Mob::SIZE, Color { r: 230, g: 0, b: 0 })
+}
+
+/// Builds a line list model of a box with its minimum corner at the origin.
+fn box_model_constructor(size: glam::Vec3, color: Color) -> ModelConstructor {
+    let corners = std::array::from_fn(|idx| {
+        let x = if idx & 1 != 0 { size.x } else { 0.0 };
+        let y = if idx & 2 != 0 { size.y } else { 0.0 };
+        let z = if idx & 4 != 0 { size.z } else { 0.0 };
+
+        glam::Vec3::new(x, y, z)
+    });
+
+    edges_model_constructor(corners, color)
+}
+
+/// Builds a line list model of the edges of the view frozen by `/freezefrustum`, its corners are
+/// relative to the origin of the render space the view was frozen in.
+pub fn frustum_model_constructor(corners: [glam::Vec3; 8]) -> ModelConstructor {
+    edges_model_constructor(
+        corners,
+        Color {
+            r: 0,
+            g: 230,
+            b: 230,
+        },
+    )
+}
+
+/// Builds a line list model of the twelve edges of a box with corners indexed with bits:
+/// x = 1, y = 2, z = 4.
+fn edges_model_constructor(corners: [glam::Vec3; 8], color: Color) -> ModelConstructor {
+    let mut model_constructor = ModelConstructor::new();
+
+    // lines aren't textured, so all vertices sample the white slot
+    let uv = TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT).min;
+
+    for position in corners {
+        model_constructor.vertices.push(Vertex {
+            position,
+            color: color.into(),
+            uv,
+        });
+    }
+
+    // every edge connects two corners differing by exactly one bit
+    for idx in 0..8u16 {
+        for bit in [1, 2, 4] {
+            if idx & bit == 0 {
+                model_constructor.indices.push(idx);
+                model_constructor.indices.push(idx | bit);
+            }
+        }
+    }
+
+    model_constructor
+}
+
+/// Rebuilds the edges of the frozen view, placed relative to the camera origin.
+pub fn update_frustum_model_sys(
+    mut renderer: UniqueViewMut<Renderer>,
+    camera: UniqueView<Camera>,
+    debug_state: UniqueView<DebugRenderState>,
+) {
+    renderer.frustum_model = debug_state.frozen_view.map(|view| {
+        let mut model_constructor = frustum_model_constructor(view.corners());
+        model_constructor.transform = Transform {
+            rotation: glam::Quat::IDENTITY,
+            translation: view.origin.translation_from(camera.origin),
+        };
+
+        Model::new(&renderer.device, &model_constructor)
+    });
+}
@@ -0,0 +1,359 @@
+//! Second window showing the same world from another camera, for looking at chunk loading,
+//! culling and meshing from outside of the player's view.
+//!
+//! The view has its own surface, frame targets and camera uniform, everything else is shared with
+//! the main window. Tab switches between a top-down map and a free camera, which is moved with
+//! WASD, Space and Left Shift and turned with the arrow keys while the window is focused, the map
+//! zooms with + and -.
+
+use std::{collections::HashSet, fmt};
+
+use game_loop::winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, KeyboardInput, VirtualKeyCode},
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder, WindowId},
+};
+use shipyard::*;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::{Camera, Frustum},
+    hdr::FrameTargets,
+    rendererer::{Renderer, Scene, Viewpoint},
+    UPDATES_PER_SECOND,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DebugViewMode {
+    /// Orthographic view looking down on the player.
+    Map,
+    /// Perspective camera moving independently of the player.
+    FreeCamera,
+}
+
+/// How far above the player the map camera is, blocks higher up are cut off.
+const MAP_HEIGHT: f32 = 128.0;
+/// How far below the map camera blocks are still drawn.
+const MAP_DEPTH: f32 = 512.0;
+/// Half of the blocks shown vertically by the map.
+const DEFAULT_MAP_EXTENT: f32 = 64.0;
+const MIN_MAP_EXTENT: f32 = 8.0;
+const MAX_MAP_EXTENT: f32 = 512.0;
+/// Blocks per second.
+const FREE_CAMERA_SPEED: f64 = 20.0;
+/// Degrees per second.
+const FREE_CAMERA_TURN_SPEED: f32 = 90.0;
+
+pub struct DebugView {
+    // declared before the window, so the surface is dropped first
+    surface: wgpu::Surface,
+    window: Window,
+    config: wgpu::SurfaceConfiguration,
+    targets: FrameTargets,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    view_proj: glam::Mat4,
+    pub mode: DebugViewMode,
+    /// Position of the free camera, it starts where the main camera was when the view opened.
+    eye: glam::DVec3,
+    yaw: f32,
+    pitch: f32,
+    map_extent: f32,
+    held_keys: HashSet<VirtualKeyCode>,
+    focused: bool,
+}
+
+impl fmt::Debug for DebugView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugView")
+            .field("window", &self.window.id())
+            .field("mode", &self.mode)
+            .field("eye", &self.eye)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DebugView {
+    /// Opens the window, returns None if its surface can't be drawn to by the renderer.
+    pub fn open(
+        event_loop: &EventLoopWindowTarget<()>,
+        renderer: &Renderer,
+        camera: &Camera,
+        mode: DebugViewMode,
+    ) -> Option<Self> {
+        let window = WindowBuilder::new()
+            .with_title("Landmark - debug view")
+            .with_inner_size(PhysicalSize::new(640, 480))
+            .build(event_loop)
+            .map_err(|e| log::error!("Failed to create the debug view window: {e}"))
+            .ok()?;
+
+        // Safety: the surface is dropped before the window, see the field order
+        let surface = unsafe { renderer.instance.create_surface(&window) }
+            .map_err(|e| log::error!("Failed to create a surface for the debug view: {e}"))
+            .ok()?;
+
+        // the pipelines only render to the format of the main window
+        let capabilities = surface.get_capabilities(&renderer.adapter);
+        if !capabilities.formats.contains(&renderer.config.format) {
+            log::error!(
+                "The debug view window doesn't support the {:?} format of the main window",
+                renderer.config.format
+            );
+            return None;
+        }
+
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: capabilities.alpha_modes[0],
+            ..renderer.config.clone()
+        };
+        surface.configure(&renderer.device, &config);
+
+        let targets = FrameTargets::new(
+            &renderer.device,
+            &renderer.texture_bind_group_layout,
+            config.width,
+            config.height,
+        );
+
+        let view_proj = glam::Mat4::IDENTITY;
+        let buffer = renderer
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("debug_view_camera"),
+                contents: bytemuck::cast_slice(&[view_proj]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &renderer.camera_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+                label: Some("debug_view_camera"),
+            });
+
+        Some(Self {
+            surface,
+            window,
+            config,
+            targets,
+            buffer,
+            bind_group,
+            view_proj,
+            mode,
+            eye: camera.eye,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            map_extent: DEFAULT_MAP_EXTENT,
+            held_keys: HashSet::new(),
+            focused: false,
+        })
+    }
+
+    pub fn window_id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    /// Keys pressed while the debug view is focused shouldn't control the player.
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+
+        if !focused {
+            self.held_keys.clear();
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        texture_layout: &wgpu::BindGroupLayout,
+        new_size: PhysicalSize<u32>,
+    ) {
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.surface.configure(device, &self.config);
+        self.targets = FrameTargets::new(
+            device,
+            texture_layout,
+            self.config.width,
+            self.config.height,
+        );
+    }
+
+    pub fn handle_key(&mut self, input: KeyboardInput) {
+        let Some(key) = input.virtual_keycode else {
+            return;
+        };
+
+        if input.state == ElementState::Released {
+            self.held_keys.remove(&key);
+            return;
+        }
+
+        // key repeats arrive as more presses
+        if !self.held_keys.insert(key) {
+            return;
+        }
+
+        match key {
+            VirtualKeyCode::Tab => {
+                self.mode = match self.mode {
+                    DebugViewMode::Map => DebugViewMode::FreeCamera,
+                    DebugViewMode::FreeCamera => DebugViewMode::Map,
+                };
+            }
+            VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd => {
+                self.map_extent = (self.map_extent / 2.0).max(MIN_MAP_EXTENT);
+            }
+            VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => {
+                self.map_extent = (self.map_extent * 2.0).min(MAX_MAP_EXTENT);
+            }
+            _ => {}
+        }
+    }
+
+    fn held(&self, key: VirtualKeyCode) -> f32 {
+        if self.held_keys.contains(&key) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Moves the free camera by the held keys for one update.
+    fn move_free_camera(&mut self) {
+        let dt = 1.0 / UPDATES_PER_SECOND as f32;
+
+        self.yaw += (self.held(VirtualKeyCode::Right) - self.held(VirtualKeyCode::Left))
+            * FREE_CAMERA_TURN_SPEED
+            * dt;
+        self.pitch = (self.pitch
+            + (self.held(VirtualKeyCode::Down) - self.held(VirtualKeyCode::Up))
+                * FREE_CAMERA_TURN_SPEED
+                * dt)
+            .clamp(-89.0, 89.0);
+
+        let forward = glam::Mat3::from_rotation_y(self.yaw.to_radians()) * glam::Vec3::Z;
+        let right = glam::Vec3::Y.cross(forward);
+        let movement = forward * (self.held(VirtualKeyCode::W) - self.held(VirtualKeyCode::S))
+            + right * (self.held(VirtualKeyCode::D) - self.held(VirtualKeyCode::A))
+            + glam::Vec3::Y
+                * (self.held(VirtualKeyCode::Space) - self.held(VirtualKeyCode::LShift));
+
+        self.eye += movement.normalize_or_zero().as_dvec3() * FREE_CAMERA_SPEED * dt as f64;
+    }
+
+    /// Computes the view projection in the render space of the main camera and uploads it.
+    fn update_view_projection(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let aspect = self.config.width as f32 / self.config.height as f32;
+        let origin = camera.origin.as_world_position();
+
+        self.view_proj = match self.mode {
+            DebugViewMode::Map => {
+                let eye = (camera.eye - origin).as_vec3() + glam::Vec3::Y * MAP_HEIGHT;
+                // north is up on the map
+                let view = glam::Mat4::look_to_lh(eye, glam::Vec3::NEG_Y, glam::Vec3::Z);
+                let (half_width, half_height) = (self.map_extent * aspect, self.map_extent);
+                let proj = glam::Mat4::orthographic_lh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    0.0,
+                    MAP_DEPTH,
+                );
+
+                proj * view
+            }
+            DebugViewMode::FreeCamera => {
+                let mut look_direction = glam::Vec3::Z;
+                look_direction =
+                    glam::Mat3::from_rotation_x(self.pitch.to_radians()) * look_direction;
+                look_direction =
+                    glam::Mat3::from_rotation_y(self.yaw.to_radians()) * look_direction;
+
+                let eye = (self.eye - origin).as_vec3();
+                let view = glam::Mat4::look_to_lh(eye, look_direction, glam::Vec3::Y);
+                let proj = glam::Mat4::perspective_infinite_lh(75.0_f32.to_radians(), aspect, 0.1);
+
+                proj * view
+            }
+        };
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.view_proj]));
+    }
+
+    /// Draws the world without the HUD into the window, surface errors are only logged so they
+    /// never affect the main window.
+    pub fn render(&self, renderer: &Renderer, scene: &Scene) {
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&renderer.device, &self.config);
+                return;
+            }
+            Err(e) => {
+                log::warn!("Failed to draw the debug view: {e}");
+                return;
+            }
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("debug_view"),
+            });
+
+        let viewpoint = Viewpoint {
+            frustum: Frustum::from_view_projection(self.view_proj),
+            camera_bind_group: &self.bind_group,
+        };
+        renderer.encode_frame(
+            &mut encoder,
+            &view,
+            &self.targets,
+            scene,
+            &viewpoint,
+            false,
+            false,
+        );
+
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+    }
+}
+
+pub fn move_debug_view_sys(mut renderer: UniqueViewMut<Renderer>) {
+    if let Some(debug_view) = renderer.debug_view.as_mut() {
+        if debug_view.mode == DebugViewMode::FreeCamera {
+            debug_view.move_free_camera();
+        }
+    }
+}
+
+pub fn update_debug_view_sys(mut renderer: UniqueViewMut<Renderer>, camera: UniqueView<Camera>) {
+    let renderer = &mut *renderer;
+
+    if let Some(debug_view) = renderer.debug_view.as_mut() {
+        debug_view.update_view_projection(&renderer.queue, &camera);
+    }
+}
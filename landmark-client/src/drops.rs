@@ -0,0 +1,354 @@
+use std::time::{Duration, Instant};
+
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    coords,
+    edit_history::{apply_changes, EditHistory},
+    game_map::{BlockId, ChunkCoords, FaceDirection, GameMap, InnerChunkCoords},
+    game_mode::GameMode,
+    input::InputState,
+    inventory::Inventory,
+    loader::ResourceDictionary,
+    mesher::ModelConstructorChunkExt,
+    model::{MissingModel, Model, ModelConstructor, UpdatedModel},
+    particles::{ParticleEffect, ParticleEmitter},
+    player::LocalPlayer,
+    rendererer::Renderer,
+    transform::Transform,
+    UPDATES_PER_SECOND,
+};
+
+/// Maximum distance between the camera and a block the player can break.
+const REACH: f64 = 6.0;
+/// Edge length of the block model of a dropped item.
+const DROP_SIZE: f32 = 0.25;
+/// Upward velocity of an item dropped from a broken block, in blocks per second.
+const POP_VELOCITY: f64 = 4.0;
+/// In blocks per second squared.
+const GRAVITY: f64 = 20.0;
+const TERMINAL_VELOCITY: f64 = 40.0;
+/// Distance from the camera at which dropped items are picked up.
+const PICKUP_RADIUS: f64 = 1.5;
+/// Prevents picking up an item in the same moment its block is broken.
+const PICKUP_DELAY: Duration = Duration::from_millis(500);
+/// Dropped items disappear if nobody picks them up in time.
+const DESPAWN_TIME: Duration = Duration::from_secs(300);
+/// Height of the bobbing motion of the model.
+const BOB_HEIGHT: f32 = 0.1;
+const BOB_SPEED: f32 = 2.0;
+/// In radians per second.
+const ROTATION_SPEED: f32 = 1.5;
+
+/// Items lying in the world, usually dropped from broken blocks.
+#[derive(Debug, Clone, Component)]
+pub struct ItemDrop {
+    pub item: String,
+    pub count: u32,
+    /// Center of the bottom of the item.
+    pub position: glam::DVec3,
+    velocity: glam::DVec3,
+    dropped_at: Instant,
+}
+
+impl ItemDrop {
+    pub fn new(item: String, count: u32, position: glam::DVec3) -> Self {
+        Self {
+            item,
+            count,
+            position,
+            velocity: glam::DVec3::new(0.0, POP_VELOCITY, 0.0),
+            dropped_at: Instant::now(),
+        }
+    }
+
+    /// Creates items flying from `position` with the velocity, in blocks per second.
+    pub fn thrown(item: String, count: u32, position: glam::DVec3, velocity: glam::DVec3) -> Self {
+        Self {
+            velocity,
+            ..Self::new(item, count, position)
+        }
+    }
+
+    /// Applies gravity and stops the item on top of blocks.
+    fn fall(&mut self, game_map: &GameMap) {
+        let dt = 1.0 / UPDATES_PER_SECOND as f64;
+
+        // blocks placed into the item push it out on top
+        if game_map
+            .get_block_world(coords::block_containing(self.position))
+            .is_some()
+        {
+            self.position.y = self.position.y.floor() + 1.0;
+            self.velocity = glam::DVec3::ZERO;
+        }
+
+        self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+
+        let next = self.position + self.velocity * dt;
+
+        if self.velocity.y < 0.0
+            && game_map
+                .get_block_world(coords::block_containing(next))
+                .is_some()
+        {
+            self.position.y = next.y.floor() + 1.0;
+            self.velocity = glam::DVec3::ZERO;
+        } else {
+            self.position = next;
+        }
+    }
+}
+
+/// Builds a small cube with the block texture, centered horizontally on the origin and standing on it.
+pub fn drop_model_constructor(
+    resource_dictionary: &ResourceDictionary,
+    item: &str,
+) -> Option<ModelConstructor> {
+    let item = resource_dictionary.get_item_data_from_name(item);
+    let block_id = resource_dictionary.find_block_id(item.places_block.as_deref()?)?;
+
+    Some(block_model_constructor(
+        resource_dictionary,
+        block_id,
+        DROP_SIZE,
+    ))
+}
+
+/// Builds a cube of `size` looking like the block, centered horizontally on the origin and standing on it.
+pub fn block_model_constructor(
+    resource_dictionary: &ResourceDictionary,
+    block_id: BlockId,
+    size: f32,
+) -> ModelConstructor {
+    let block = resource_dictionary.get_block_data_from_id(block_id);
+    let mut model_constructor = ModelConstructor::new();
+    let coords = InnerChunkCoords::new(0, 0, 0);
+
+    for face in 0..6 {
+        let face_dir = FaceDirection::from(face);
+        let uv = resource_dictionary
+            .get_block_face_uv(block_id, face_dir)
+            .unwrap_or_else(|| resource_dictionary.get_block_uv(block_id));
+
+        model_constructor.add_block_face(coords, face_dir, block.face_color(face_dir), uv);
+    }
+
+    // faces are built around the center of the block at the chunk origin
+    let offset = glam::Vec3::new(0.5, 0.0, 0.5);
+    for vertex in model_constructor.vertices.iter_mut() {
+        vertex.position = (vertex.position - offset) * size;
+    }
+
+    model_constructor
+}
+
+/// Block the player is holding the button on while blocks take time to break.
+#[derive(Debug, Default, Unique)]
+pub struct BreakingProgress {
+    target: Option<glam::IVec3>,
+    /// Seconds the button was held on the target.
+    elapsed: f32,
+    /// Seconds the target takes to break.
+    hardness: f32,
+}
+
+impl BreakingProgress {
+    fn reset(&mut self) {
+        self.target = None;
+        self.elapsed = 0.0;
+    }
+
+    /// Returns the block being broken and how far along it is, from 0 to 1.
+    pub fn current(&self) -> Option<(glam::IVec3, f32)> {
+        let target = self.target?;
+
+        Some((target, (self.elapsed / self.hardness).min(1.0)))
+    }
+}
+
+/// Breaks the block the camera looks at and drops the item placing it.
+/// Blocks break on click in creative, in survival the button has to be held for their hardness.
+#[allow(clippy::too_many_arguments)]
+pub fn block_breaking_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    camera: UniqueView<Camera>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    game_mode: UniqueView<GameMode>,
+    mut progress: UniqueViewMut<BreakingProgress>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut entities: EntitiesViewMut,
+    mut drops: ViewMut<ItemDrop>,
+    mut history: UniqueViewMut<EditHistory>,
+    // grouped, systems can't take more than ten views
+    (mut updated_models, mut missing_models, mut emitters): (
+        ViewMut<UpdatedModel>,
+        ViewMut<MissingModel>,
+        ViewMut<ParticleEmitter>,
+    ),
+) {
+    let clicked = std::mem::take(&mut input_state.break_block);
+    let holding = game_mode.has_break_time() && input_state.breaking && input_state.cursor_captured;
+
+    if !clicked && !holding {
+        progress.reset();
+        return;
+    }
+
+    let Some((hit, block_id)) = game_map
+        .raycast(camera.eye, camera.look_direction(), REACH)
+        .and_then(|hit| Some((hit, game_map.get_block_world(hit.block_coords)?)))
+    else {
+        progress.reset();
+        return;
+    };
+
+    let block = resource_dictionary.get_block_data_from_id(block_id);
+
+    if game_mode.has_break_time() {
+        // looking at another block starts over
+        if progress.target != Some(hit.block_coords) {
+            progress.target = Some(hit.block_coords);
+            progress.elapsed = 0.0;
+            progress.hardness = block.hardness;
+        }
+
+        progress.elapsed += 1.0 / UPDATES_PER_SECOND as f32;
+
+        if progress.elapsed < block.hardness {
+            return;
+        }
+
+        progress.reset();
+    }
+
+    let transaction = apply_changes(
+        &mut game_map,
+        &mut missing_models,
+        [(hit.block_coords, None)],
+    );
+    if transaction.is_empty() {
+        return;
+    }
+
+    if game_mode.records_edits() {
+        history.record(transaction);
+    }
+
+    entities.add_entity(
+        &mut emitters,
+        ParticleEmitter {
+            effect: ParticleEffect::Debris,
+            block: block_id,
+            position: hit.block_coords.as_dvec3(),
+        },
+    );
+
+    let Some(item_id) = resource_dictionary.find_item_for_block(&block.name) else {
+        return;
+    };
+
+    let item = resource_dictionary.get_item_data_from_id(item_id);
+
+    let Some(model_constructor) = drop_model_constructor(&resource_dictionary, &item.name) else {
+        return;
+    };
+
+    let position = hit.block_coords.as_dvec3() + glam::DVec3::new(0.5, 0.0, 0.5);
+
+    entities.add_entity(
+        (&mut drops, &mut updated_models),
+        (
+            ItemDrop::new(item.name, 1, position),
+            UpdatedModel(model_constructor),
+        ),
+    );
+}
+
+/// Moves dropped items and adds them to the inventory of the player once they are close enough.
+#[allow(clippy::too_many_arguments)]
+pub fn item_drops_sys(
+    camera: UniqueView<Camera>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    game_map: UniqueView<GameMap>,
+    mut entities: EntitiesViewMut,
+    players: View<LocalPlayer>,
+    mut inventories: ViewMut<Inventory>,
+    mut drops: ViewMut<ItemDrop>,
+    mut models: ViewMut<Model>,
+) {
+    let mut removed = Vec::new();
+    let mut inventory = (&players, &mut inventories)
+        .iter()
+        .next()
+        .map(|(_, inventory)| inventory);
+
+    for (id, drop) in (&mut drops).iter().with_id() {
+        if drop.dropped_at.elapsed() > DESPAWN_TIME {
+            removed.push(id);
+            continue;
+        }
+
+        // items in unloaded chunks would fall forever
+        if game_map
+            .chunks
+            .contains_key(&ChunkCoords::from_world_position(drop.position))
+        {
+            drop.fall(&game_map);
+        }
+
+        let Some(inventory) = inventory.as_mut() else {
+            continue;
+        };
+
+        let center = drop.position + glam::DVec3::Y * (DROP_SIZE as f64 / 2.0);
+
+        if drop.dropped_at.elapsed() < PICKUP_DELAY || center.distance(camera.eye) > PICKUP_RADIUS {
+            continue;
+        }
+
+        let Some(item_id) = resource_dictionary.find_item_id(&drop.item) else {
+            // the item was removed by a resource reload
+            removed.push(id);
+            continue;
+        };
+
+        let item = resource_dictionary.get_item_data_from_id(item_id);
+        drop.count = inventory.add(&item, drop.count);
+
+        if drop.count == 0 {
+            removed.push(id);
+        }
+    }
+
+    for id in removed {
+        drops.delete(id);
+        models.delete(id);
+        entities.delete_unchecked(id);
+    }
+}
+
+/// Places models of dropped items relative to the camera origin, bobbing and rotating.
+pub fn update_drop_models_sys(
+    renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    drops: View<ItemDrop>,
+    mut models: ViewMut<Model>,
+) {
+    let origin = camera.origin.as_world_position();
+
+    for (drop, model) in (&drops, &mut models).iter() {
+        let time = drop.dropped_at.elapsed().as_secs_f32();
+        // the model hovers above the ground, so it never sinks into it while bobbing
+        let bob = BOB_HEIGHT * (1.0 + (time * BOB_SPEED).sin());
+
+        model.set_transform(
+            &renderer.queue,
+            Transform {
+                rotation: glam::Quat::from_rotation_y(time * ROTATION_SPEED),
+                translation: (drop.position - origin).as_vec3() + glam::Vec3::Y * bob,
+            },
+        );
+    }
+}
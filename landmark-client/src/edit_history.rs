@@ -0,0 +1,258 @@
+//! History of block edits, so they can be undone and redone.
+//!
+//! Every edit is recorded as a transaction of the blocks it changed, with their state before and
+//! after. Old transactions are forgotten once the history holds too many changes.
+
+use std::collections::VecDeque;
+
+use shipyard::*;
+
+use crate::{
+    game_map::{BlockId, GameMap},
+    input::InputState,
+    mesher::remesh_around_block,
+    model::MissingModel,
+};
+
+/// Block changes kept by the history, about 24 bytes each.
+const MAX_STORED_CHANGES: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockChange {
+    pub position: glam::IVec3,
+    pub old: Option<BlockId>,
+    pub new: Option<BlockId>,
+}
+
+/// Blocks changed together by one edit, undone and redone at once.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    changes: Vec<BlockChange>,
+}
+
+impl Transaction {
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Sets blocks in loaded chunks and returns the changes made. Blocks in chunks which aren't loaded
+/// and blocks which already have the new state are skipped.
+pub fn apply_changes(
+    game_map: &mut GameMap,
+    missing_models: &mut ViewMut<MissingModel>,
+    changes: impl IntoIterator<Item = (glam::IVec3, Option<BlockId>)>,
+) -> Transaction {
+    let mut transaction = Transaction::default();
+
+    for (position, new) in changes {
+        let old = game_map.get_block_world(position);
+
+        if old == new || game_map.set_block_world(position, new).is_none() {
+            continue;
+        }
+
+        transaction.changes.push(BlockChange { position, old, new });
+    }
+
+    for change in &transaction.changes {
+        remesh_around_block(game_map, missing_models, change.position);
+    }
+
+    transaction
+}
+
+#[derive(Debug, Default, Unique)]
+pub struct EditHistory {
+    /// Latest transaction last.
+    undo: VecDeque<Transaction>,
+    /// Undone transactions, the latest undone last.
+    redo: Vec<Transaction>,
+    /// Changes held by both stacks.
+    stored_changes: usize,
+}
+
+impl EditHistory {
+    /// Adds a transaction which was just applied, edits which were undone can't be redone after it.
+    pub fn record(&mut self, transaction: Transaction) {
+        if transaction.is_empty() {
+            return;
+        }
+
+        for undone in self.redo.drain(..) {
+            self.stored_changes -= undone.len();
+        }
+
+        self.stored_changes += transaction.len();
+        self.undo.push_back(transaction);
+
+        // the latest transaction is kept even if it's bigger than the limit
+        while self.stored_changes > MAX_STORED_CHANGES && self.undo.len() > 1 {
+            let forgotten = self.undo.pop_front().unwrap();
+            self.stored_changes -= forgotten.len();
+        }
+    }
+
+    /// Reverts the latest transaction, returns how many blocks changed or None if there is nothing
+    /// to undo.
+    pub fn undo(
+        &mut self,
+        game_map: &mut GameMap,
+        missing_models: &mut ViewMut<MissingModel>,
+    ) -> Option<usize> {
+        let transaction = self.undo.pop_back()?;
+
+        // in reverse, in case a block was changed more than once
+        let reverted = apply_changes(
+            game_map,
+            missing_models,
+            transaction
+                .changes
+                .iter()
+                .rev()
+                .map(|change| (change.position, change.old)),
+        );
+        self.redo.push(transaction);
+
+        Some(reverted.len())
+    }
+
+    /// Applies the latest undone transaction again, returns how many blocks changed or None if
+    /// there is nothing to redo.
+    pub fn redo(
+        &mut self,
+        game_map: &mut GameMap,
+        missing_models: &mut ViewMut<MissingModel>,
+    ) -> Option<usize> {
+        let transaction = self.redo.pop()?;
+
+        let reapplied = apply_changes(
+            game_map,
+            missing_models,
+            transaction
+                .changes
+                .iter()
+                .map(|change| (change.position, change.new)),
+        );
+        self.undo.push_back(transaction);
+
+        Some(reapplied.len())
+    }
+}
+
+/// Undoes or redoes edits requested with the keyboard.
+pub fn edit_history_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    mut history: UniqueViewMut<EditHistory>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut missing_models: ViewMut<MissingModel>,
+) {
+    if std::mem::take(&mut input_state.undo) {
+        match history.undo(&mut game_map, &mut missing_models) {
+            Some(changed) => log::info!("Undid an edit of {changed} blocks"),
+            None => log::info!("There is nothing to undo"),
+        }
+    }
+
+    if std::mem::take(&mut input_state.redo) {
+        match history.redo(&mut game_map, &mut missing_models) {
+            Some(changed) => log::info!("Redid an edit of {changed} blocks"),
+            None => log::info!("There is nothing to redo"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_map::{Chunk, ChunkCoords};
+
+    fn loaded_map() -> GameMap {
+        let mut game_map = GameMap::new();
+
+        for (x, z) in [(0, 0), (-1, 0), (0, -1), (-1, -1)] {
+            game_map
+                .chunks
+                .insert(ChunkCoords::new(x, 0, z), Chunk::new());
+        }
+
+        game_map
+    }
+
+    fn transaction(count: usize) -> Transaction {
+        Transaction {
+            changes: (0..count as i32)
+                .map(|x| BlockChange {
+                    position: glam::IVec3::new(x, 0, 0),
+                    old: None,
+                    new: Some(1),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn recording_clears_redo() {
+        let mut history = EditHistory::default();
+        history.record(transaction(2));
+        history.redo.push(history.undo.pop_back().unwrap());
+
+        assert!(!history.redo.is_empty());
+        history.record(transaction(3));
+
+        assert!(history.redo.is_empty());
+        assert_eq!(history.stored_changes, 3);
+    }
+
+    #[test]
+    fn oldest_transactions_are_forgotten() {
+        let mut history = EditHistory::default();
+        history.record(transaction(MAX_STORED_CHANGES / 2));
+        history.record(transaction(MAX_STORED_CHANGES / 2));
+        history.record(transaction(1));
+
+        assert_eq!(history.undo.len(), 2);
+        assert_eq!(history.stored_changes, MAX_STORED_CHANGES / 2 + 1);
+
+        // a single transaction bigger than the limit is still kept
+        history.record(transaction(MAX_STORED_CHANGES + 1));
+        assert_eq!(history.undo.len(), 1);
+    }
+
+    #[test]
+    fn only_changes_in_loaded_chunks_are_applied() {
+        let world = World::new();
+        let mut game_map = loaded_map();
+        game_map.set_block_world(glam::IVec3::new(1, 0, 0), Some(3));
+
+        world.run(|mut missing_models: ViewMut<MissingModel>| {
+            let transaction = apply_changes(
+                &mut game_map,
+                &mut missing_models,
+                [
+                    (glam::IVec3::new(0, 0, 0), Some(3)),
+                    (glam::IVec3::new(1, 0, 0), Some(3)),
+                    // above the loaded chunks
+                    (glam::IVec3::new(0, Chunk::SIZE, 0), Some(3)),
+                ],
+            );
+
+            assert_eq!(
+                transaction.changes,
+                vec![BlockChange {
+                    position: glam::IVec3::new(0, 0, 0),
+                    old: None,
+                    new: Some(3),
+                }]
+            );
+            assert_eq!(
+                game_map.get_block_world(glam::IVec3::new(0, Chunk::SIZE, 0)),
+                None
+            );
+        });
+    }
+}
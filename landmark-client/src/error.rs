@@ -0,0 +1,73 @@
+//! Errors which keep the game from starting.
+//!
+//! Problems the game can recover from are logged where they happen instead, e.g. missing
+//! resources fall back to the built-in ones.
+
+use std::fmt;
+
+use crate::{loader::ResourceError, replay::ReplayError};
+
+#[derive(Debug)]
+pub enum LandmarkError {
+    Window(winit::error::OsError),
+    /// No adapter of any backend works, not even a software adapter.
+    NoAdapter,
+    /// The selected adapter can't draw to the window.
+    NoSurface,
+    Device(wgpu::RequestDeviceError),
+    /// Neither the resource packs nor the built-in definitions could be loaded.
+    Resources(ResourceError),
+    /// The input recording to play back could not be loaded.
+    Replay(ReplayError),
+}
+
+impl fmt::Display for LandmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LandmarkError::Window(e) => write!(f, "Failed to create a window: {e}"),
+            LandmarkError::NoAdapter => write!(f, "No graphics adapter is available"),
+            LandmarkError::NoSurface => {
+                write!(f, "Failed to create a surface for the window")
+            }
+            LandmarkError::Device(e) => write!(f, "Failed to create the graphics device: {e}"),
+            LandmarkError::Resources(e) => write!(f, "Failed to load resources: {e}"),
+            LandmarkError::Replay(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for LandmarkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LandmarkError::Window(e) => Some(e),
+            LandmarkError::Device(e) => Some(e),
+            LandmarkError::Resources(e) => Some(e),
+            LandmarkError::Replay(e) => Some(e),
+            LandmarkError::NoAdapter | LandmarkError::NoSurface => None,
+        }
+    }
+}
+
+impl From<winit::error::OsError> for LandmarkError {
+    fn from(e: winit::error::OsError) -> Self {
+        LandmarkError::Window(e)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for LandmarkError {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        LandmarkError::Device(e)
+    }
+}
+
+impl From<ResourceError> for LandmarkError {
+    fn from(e: ResourceError) -> Self {
+        LandmarkError::Resources(e)
+    }
+}
+
+impl From<ReplayError> for LandmarkError {
+    fn from(e: ReplayError) -> Self {
+        LandmarkError::Replay(e)
+    }
+}
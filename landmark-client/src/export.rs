@@ -0,0 +1,365 @@
+use std::{
+    fmt::{self, Write as _},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    coords,
+    game_map::{Chunk, ChunkCoords, GameMap, InnerChunkCoords},
+    loader::ResourceDictionary,
+    mesher::mesh_loaded_chunk,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Wavefront OBJ with a material file, vertex colors are appended to the positions.
+    Obj,
+    /// glTF 2.0 with the geometry in a separate binary file.
+    Gltf,
+}
+
+impl ExportFormat {
+    /// Picks the format from the extension of the exported file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "obj" => Some(ExportFormat::Obj),
+            "gltf" => Some(ExportFormat::Gltf),
+            _ => None,
+        }
+    }
+}
+
+/// Box of blocks selected for export, both corners are included.
+#[derive(Debug, Clone, Copy)]
+pub struct ExportRegion {
+    pub min: glam::IVec3,
+    pub max: glam::IVec3,
+}
+
+impl ExportRegion {
+    pub fn new(a: glam::IVec3, b: glam::IVec3) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    fn contains(&self, position: glam::IVec3) -> bool {
+        position.cmpge(self.min).all() && position.cmple(self.max).all()
+    }
+}
+
+/// Chunk meshes merged into a single mesh in world space.
+/// Colors are linear and multiply the texture, which is the block texture atlas.
+#[derive(Debug, Default)]
+pub struct ExportedMesh {
+    positions: Vec<glam::Vec3>,
+    colors: Vec<[f32; 3]>,
+    uvs: Vec<glam::Vec2>,
+    indices: Vec<u32>,
+}
+
+impl ExportedMesh {
+    /// Merges meshes of the loaded chunks, or only of blocks in the region if there is one.
+    /// Blocks cut off by the region are closed by their faces towards the removed blocks.
+    pub fn build(
+        game_map: &GameMap,
+        resource_dictionary: &ResourceDictionary,
+        region: Option<ExportRegion>,
+    ) -> Self {
+        let mut mesh = Self::default();
+
+        let Some(region) = region else {
+            let mut coords: Vec<ChunkCoords> = game_map.chunks.keys().copied().collect();
+            coords.sort();
+
+            for coords in coords {
+                mesh.add_chunk(game_map, coords, resource_dictionary);
+            }
+
+            return mesh;
+        };
+
+        let min = coords::chunk_of_block(region.min);
+        let max = coords::chunk_of_block(region.max);
+
+        // chunks around the region are included too, but emptied,
+        // so faces on the borders of the selected chunks are not hidden by them
+        let mut selected = GameMap::new();
+        for z in min.z - 1..=max.z + 1 {
+            for y in min.y - 1..=max.y + 1 {
+                for x in min.x - 1..=max.x + 1 {
+                    let coords = ChunkCoords::new(x, y, z);
+
+                    if let Some(chunk) = game_map.chunks.get(&coords) {
+                        selected
+                            .chunks
+                            .insert(coords, cut_chunk(chunk, coords, &region));
+                    }
+                }
+            }
+        }
+
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let coords = ChunkCoords::new(x, y, z);
+
+                    if selected.chunks.contains_key(&coords) {
+                        mesh.add_chunk(&selected, coords, resource_dictionary);
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+
+    fn add_chunk(
+        &mut self,
+        game_map: &GameMap,
+        coords: ChunkCoords,
+        resource_dictionary: &ResourceDictionary,
+    ) {
+        let meshes = mesh_loaded_chunk(game_map, coords, resource_dictionary);
+        let origin = coords.as_world_position().as_vec3();
+
+        // fluids are exported as plain surfaces of their block color
+        for model_constructor in [meshes.solid, meshes.fluid] {
+            let start = self.positions.len() as u32;
+
+            for vertex in model_constructor.vertices.iter() {
+                self.positions.push(vertex.position + origin);
+                self.colors.push(vertex.color.to_array());
+                self.uvs.push(vertex.uv);
+            }
+
+            self.indices.extend(
+                model_constructor
+                    .indices
+                    .iter()
+                    .map(|idx| start + *idx as u32),
+            );
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Writes the mesh in the format given by the extension of `path`.
+    /// The texture atlas is written next to it as a PNG with the same name.
+    pub fn export(
+        &self,
+        path: &Path,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Result<(), ExportError> {
+        let format = ExportFormat::from_path(path).ok_or_else(|| ExportError::UnknownFormat {
+            path: path.to_path_buf(),
+        })?;
+
+        let texture_path = path.with_extension("png");
+        resource_dictionary
+            .atlas
+            .image()
+            .save(&texture_path)
+            .map_err(|source| ExportError::Texture {
+                path: texture_path.clone(),
+                source,
+            })?;
+
+        let result = match format {
+            ExportFormat::Obj => self.write_obj(path, &file_name(&texture_path)),
+            ExportFormat::Gltf => self.write_gltf(path, &file_name(&texture_path)),
+        };
+
+        result.map_err(|source| ExportError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn write_obj(&self, path: &Path, texture: &str) -> io::Result<()> {
+        let material_path = path.with_extension("mtl");
+
+        fs::write(
+            &material_path,
+            format!("newmtl terrain\nKd 1 1 1\nmap_Kd {texture}\n"),
+        )?;
+
+        let mut obj = String::new();
+        writeln!(obj, "# Exported from Landmark").unwrap();
+        writeln!(obj, "mtllib {}", file_name(&material_path)).unwrap();
+        writeln!(obj, "o terrain").unwrap();
+
+        for (position, color) in self.positions.iter().zip(self.colors.iter()) {
+            writeln!(
+                obj,
+                "v {} {} {} {} {} {}",
+                position.x, position.y, position.z, color[0], color[1], color[2]
+            )
+            .unwrap();
+        }
+
+        // OBJ texture coordinates start at the bottom of the image
+        for uv in self.uvs.iter() {
+            writeln!(obj, "vt {} {}", uv.x, 1.0 - uv.y).unwrap();
+        }
+
+        writeln!(obj, "usemtl terrain").unwrap();
+        for triangle in self.indices.chunks_exact(3) {
+            // indices in OBJ start at 1
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(obj, "f {a}/{a} {b}/{b} {c}/{c}").unwrap();
+        }
+
+        fs::write(path, obj)
+    }
+
+    fn write_gltf(&self, path: &Path, texture: &str) -> io::Result<()> {
+        let buffer_path = path.with_extension("bin");
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut views = Vec::new();
+
+        let mut push_view = |bytes: &[u8]| {
+            views.push((buffer.len(), bytes.len()));
+            buffer.extend_from_slice(bytes);
+        };
+
+        push_view(bytemuck::cast_slice(&self.positions));
+        push_view(bytemuck::cast_slice(&self.colors));
+        push_view(bytemuck::cast_slice(&self.uvs));
+        push_view(bytemuck::cast_slice(&self.indices));
+
+        fs::write(&buffer_path, &buffer)?;
+
+        let (min, max) = self.positions.iter().fold(
+            (glam::Vec3::splat(f32::MAX), glam::Vec3::splat(f32::MIN)),
+            |(min, max), position| (min.min(*position), max.max(*position)),
+        );
+        let (min, max) = if self.positions.is_empty() {
+            (glam::Vec3::ZERO, glam::Vec3::ZERO)
+        } else {
+            (min, max)
+        };
+
+        let buffer_views = views
+            .iter()
+            .enumerate()
+            .map(|(idx, (offset, length))| {
+                // the last view holds indices, the others vertex attributes
+                let target = if idx == views.len() - 1 { 34963 } else { 34962 };
+
+                format!(
+                    r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{length},"target":{target}}}"#
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let vertex_count = self.positions.len();
+        let index_count = self.indices.len();
+
+        // component types are 5126 for f32 and 5125 for u32, filters 9728 are nearest
+        let gltf = format!(
+            r#"{{
+  "asset": {{"version": "2.0", "generator": "Landmark"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0]}}],
+  "nodes": [{{"name": "terrain", "mesh": 0}}],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "COLOR_0": 1, "TEXCOORD_0": 2}}, "indices": 3, "material": 0}}]}}],
+  "materials": [{{"name": "terrain", "pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}, "metallicFactor": 0.0, "roughnessFactor": 1.0}}, "alphaMode": "MASK"}}],
+  "textures": [{{"sampler": 0, "source": 0}}],
+  "samplers": [{{"magFilter": 9728, "minFilter": 9728}}],
+  "images": [{{"uri": "{texture}"}}],
+  "buffers": [{{"uri": "{buffer}", "byteLength": {buffer_length}}}],
+  "bufferViews": [{buffer_views}],
+  "accessors": [
+    {{"bufferView": 0, "componentType": 5126, "count": {vertex_count}, "type": "VEC3", "min": [{}, {}, {}], "max": [{}, {}, {}]}},
+    {{"bufferView": 1, "componentType": 5126, "count": {vertex_count}, "type": "VEC3"}},
+    {{"bufferView": 2, "componentType": 5126, "count": {vertex_count}, "type": "VEC2"}},
+    {{"bufferView": 3, "componentType": 5125, "count": {index_count}, "type": "SCALAR"}}
+  ]
+}}
+"#,
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z,
+            buffer = file_name(&buffer_path),
+            buffer_length = buffer.len(),
+        );
+
+        fs::write(path, gltf)
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    UnknownFormat {
+        path: PathBuf,
+    },
+    Texture {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::UnknownFormat { path } => {
+                write!(f, "{} is not an .obj or .gltf file", path.display())
+            }
+            ExportError::Texture { path, source } => {
+                write!(f, "Failed to write texture {}: {source}", path.display())
+            }
+            ExportError::Io { path, source } => {
+                write!(f, "Failed to write {}: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::UnknownFormat { .. } => None,
+            ExportError::Texture { source, .. } => Some(source),
+            ExportError::Io { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Returns a copy of the chunk without blocks outside of the region.
+fn cut_chunk(chunk: &Chunk, coords: ChunkCoords, region: &ExportRegion) -> Chunk {
+    let mut blocks = Vec::with_capacity(Chunk::BLOCKS_COUNT as usize);
+
+    // in the order of `InnerChunkCoords::as_idx`
+    for z in 0..Chunk::SIZE {
+        for y in 0..Chunk::SIZE {
+            for x in 0..Chunk::SIZE {
+                let inner = InnerChunkCoords::new(x, y, z);
+                let block = chunk.get_block(inner);
+                blocks.push(block.filter(|_| region.contains(coords::join_block(coords, inner))));
+            }
+        }
+    }
+
+    Chunk::from_blocks(blocks).unwrap()
+}
+
+/// Returns the file name of a path, files next to each other reference each other by it.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
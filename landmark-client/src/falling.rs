@@ -0,0 +1,199 @@
+//! Blocks like sand which fall down when nothing is below them.
+//!
+//! Blocks next to changed blocks are checked every update. A falling block without support is
+//! removed from the map and becomes an entity with gravity, which turns back into a block where
+//! it lands.
+
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    coords,
+    drops::block_model_constructor,
+    game_map::{BlockId, ChunkCoords, GameMap},
+    loader::ResourceDictionary,
+    mesher::remesh_around_block,
+    model::{MissingModel, Model, UpdatedModel},
+    rendererer::Renderer,
+    transform::Transform,
+    UPDATES_PER_SECOND,
+};
+
+/// In blocks per second squared.
+const GRAVITY: f64 = 20.0;
+const TERMINAL_VELOCITY: f64 = 40.0;
+
+/// Block falling as an entity, until it lands on another block.
+#[derive(Debug, Clone, Component)]
+pub struct FallingBlock {
+    pub block: BlockId,
+    /// Minimum corner of the block, only its height changes while falling.
+    pub position: glam::DVec3,
+    /// Downwards, in blocks per second.
+    speed: f64,
+}
+
+impl FallingBlock {
+    pub fn new(block: BlockId, position: glam::IVec3) -> Self {
+        Self {
+            block,
+            position: position.as_dvec3(),
+            speed: 0.0,
+        }
+    }
+
+    /// Returns the block which is no longer falling once it reached the ground,
+    /// or None while it's still falling.
+    fn fall(&mut self, game_map: &GameMap) -> Option<glam::IVec3> {
+        let dt = 1.0 / UPDATES_PER_SECOND as f64;
+
+        self.speed = (self.speed + GRAVITY * dt).min(TERMINAL_VELOCITY);
+
+        let next = self.position - glam::DVec3::Y * self.speed * dt;
+        let below = coords::block_containing(next);
+
+        // blocks in unloaded chunks don't support anything, but the block waits for them to load
+        if !game_map
+            .chunks
+            .contains_key(&ChunkCoords::from_world_position(next))
+        {
+            return None;
+        }
+
+        if game_map.get_block_world(below).is_some() {
+            return Some(below + glam::IVec3::Y);
+        }
+
+        self.position = next;
+        None
+    }
+}
+
+/// Returns the block at `position` if it falls and there is nothing below it to rest on.
+fn unsupported_falling_block(
+    game_map: &GameMap,
+    resource_dictionary: &ResourceDictionary,
+    position: glam::IVec3,
+) -> Option<BlockId> {
+    let block = game_map.get_block_world(position)?;
+    let below = position - glam::IVec3::Y;
+
+    let falls = resource_dictionary.get_block_data_from_id(block).falls
+        && game_map.get_block_world(below).is_none()
+        && game_map.chunks.contains_key(&coords::split_block(below).0);
+
+    falls.then_some(block)
+}
+
+/// Turns unsupported falling blocks next to changed blocks into entities, and places falling
+/// entities back into the map where they land.
+pub fn falling_blocks_sys(
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut entities: EntitiesViewMut,
+    mut falling_blocks: ViewMut<FallingBlock>,
+    mut models: ViewMut<Model>,
+    mut updated_models: ViewMut<UpdatedModel>,
+    mut missing_models: ViewMut<MissingModel>,
+) {
+    let changed = std::mem::take(&mut game_map.changed_blocks);
+
+    for position in changed {
+        // the changed block may have been placed in the air, or removed from below another one
+        for candidate in [position, position + glam::IVec3::Y] {
+            let Some(block) = unsupported_falling_block(&game_map, &resource_dictionary, candidate)
+            else {
+                continue;
+            };
+
+            // removing it queues the block above, so whole columns fall one after another
+            game_map.set_block_world(candidate, None);
+            remesh_around_block(&game_map, &mut missing_models, candidate);
+
+            entities.add_entity(
+                (&mut falling_blocks, &mut updated_models),
+                (
+                    FallingBlock::new(block, candidate),
+                    UpdatedModel(block_model_constructor(&resource_dictionary, block, 1.0)),
+                ),
+            );
+        }
+    }
+
+    let mut landed = Vec::new();
+
+    for (id, falling_block) in (&mut falling_blocks).iter().with_id() {
+        if let Some(position) = falling_block.fall(&game_map) {
+            landed.push((id, falling_block.block, position));
+        }
+    }
+
+    for (id, block, position) in landed {
+        // something else took the place in the meantime, the block is lost
+        if game_map.get_block_world(position).is_none()
+            && game_map.set_block_world(position, Some(block)).is_some()
+        {
+            remesh_around_block(&game_map, &mut missing_models, position);
+        }
+
+        falling_blocks.delete(id);
+        models.delete(id);
+        entities.delete_unchecked(id);
+    }
+}
+
+/// Places models of falling blocks relative to the camera origin.
+pub fn update_falling_block_models_sys(
+    renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    falling_blocks: View<FallingBlock>,
+    mut models: ViewMut<Model>,
+) {
+    let origin = camera.origin.as_world_position();
+    // the models stand on their origin, centered horizontally
+    let center = glam::DVec3::new(0.5, 0.0, 0.5);
+
+    for (falling_block, model) in (&falling_blocks, &mut models).iter() {
+        model.set_transform(
+            &renderer.queue,
+            Transform {
+                rotation: glam::Quat::IDENTITY,
+                translation: (falling_block.position + center - origin).as_vec3(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_map::Chunk;
+
+    #[test]
+    fn block_lands_on_top_of_the_ground() {
+        let mut game_map = GameMap::new();
+        game_map
+            .chunks
+            .insert(ChunkCoords::new(0, 0, 0), Chunk::new());
+        game_map.set_block_world(glam::IVec3::new(2, 3, 2), Some(1));
+
+        let mut falling_block = FallingBlock::new(5, glam::IVec3::new(2, 10, 2));
+        let landed = (0..UPDATES_PER_SECOND * 2).find_map(|_| falling_block.fall(&game_map));
+
+        assert_eq!(landed, Some(glam::IVec3::new(2, 4, 2)));
+    }
+
+    #[test]
+    fn block_waits_above_unloaded_chunks() {
+        let mut game_map = GameMap::new();
+        game_map
+            .chunks
+            .insert(ChunkCoords::new(0, 0, 0), Chunk::new());
+
+        let mut falling_block = FallingBlock::new(5, glam::IVec3::new(2, 1, 2));
+        let landed = (0..UPDATES_PER_SECOND * 2).find_map(|_| falling_block.fall(&game_map));
+
+        assert_eq!(landed, None);
+        assert!(falling_block.position.y >= 0.0);
+    }
+}
@@ -0,0 +1,296 @@
+//! Fixed-point math used by the deterministic physics mode.
+//!
+//! Floating point results of functions like `sin` or `sqrt` depend on the platform and the math library,
+//! so the same inputs can move entities differently on two machines. Integer arithmetic doesn't,
+//! which is what lockstep simulation, replays and server reconciliation need.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Signed number with 32 integer and 32 fractional bits.
+///
+/// Every value converts to f64 exactly as long as its magnitude is below 2^21, which covers any position
+/// the physics works with, so positions can be stored as f64 between updates without losing determinism.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    const FRAC_BITS: u32 = 32;
+
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << Self::FRAC_BITS);
+    /// Smallest positive value.
+    pub const EPSILON: Self = Self(1);
+    const PI: Self = Self(13_493_037_705);
+
+    pub const fn from_int(value: i32) -> Self {
+        Self((value as i64) << Self::FRAC_BITS)
+    }
+
+    /// Rounds to the closest fixed-point value, scaling by a power of two and rounding are exact in IEEE 754.
+    pub fn from_f64(value: f64) -> Self {
+        Self((value * Self::ONE.0 as f64).round() as i64)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Self::ONE.0 as f64
+    }
+
+    /// Square root rounded down, negative values have none and return zero.
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Self::ZERO;
+        }
+
+        // sqrt(v * 2^32) * 2^16 has 32 fractional bits again
+        Self(isqrt((self.0 as u128) << Self::FRAC_BITS) as i64)
+    }
+
+    /// Returns the sine and cosine of an angle in degrees.
+    pub fn sin_cos_degrees(self) -> (Self, Self) {
+        let full_turn = Self::from_int(360).0;
+        let quarter = Self::from_int(90).0;
+        let angle = self.0.rem_euclid(full_turn);
+
+        let quadrant = angle / quarter;
+        let mut within = Self(angle % quarter);
+
+        // the polynomials are most accurate close to zero, so angles past 45 degrees use the complementary angle
+        let complementary = within > Self::from_int(45);
+        if complementary {
+            within = Self(quarter) - within;
+        }
+
+        let radians = within * Self::PI / Self::from_int(180);
+        let (mut sin, mut cos) = (sin_taylor(radians), cos_taylor(radians));
+
+        if complementary {
+            std::mem::swap(&mut sin, &mut cos);
+        }
+
+        match quadrant {
+            0 => (sin, cos),
+            1 => (cos, -sin),
+            2 => (-sin, -cos),
+            _ => (-cos, sin),
+        }
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self(((self.0 as i128 * rhs.0 as i128) >> Self::FRAC_BITS) as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+
+    /// Division by zero returns zero instead of panicking in the middle of the physics.
+    fn div(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            return Self::ZERO;
+        }
+
+        Self((((self.0 as i128) << Self::FRAC_BITS) / rhs.0 as i128) as i64)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0.wrapping_neg())
+    }
+}
+
+/// Integer square root rounded down, by Newton's method.
+fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+
+    let mut x = 1u128 << (128 - value.leading_zeros()).div_ceil(2);
+
+    loop {
+        let next = (x + value / x) / 2;
+
+        if next >= x {
+            return x;
+        }
+
+        x = next;
+    }
+}
+
+/// Sine of an angle from 0 to pi / 4 radians, from its Taylor series up to x^11.
+fn sin_taylor(x: Fixed) -> Fixed {
+    let x2 = x * x;
+    let mut term = x;
+    let mut sum = x;
+
+    for n in [2, 4, 6, 8, 10] {
+        term = -(term * x2) / Fixed::from_int(n * (n + 1));
+        sum = sum + term;
+    }
+
+    sum
+}
+
+/// Cosine of an angle from 0 to pi / 4 radians, from its Taylor series up to x^12.
+fn cos_taylor(x: Fixed) -> Fixed {
+    let x2 = x * x;
+    let mut term = Fixed::ONE;
+    let mut sum = Fixed::ONE;
+
+    for n in [1, 3, 5, 7, 9, 11] {
+        term = -(term * x2) / Fixed::from_int(n * (n + 1));
+        sum = sum + term;
+    }
+
+    sum
+}
+
+/// Vector of fixed-point numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FixedVec3 {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub z: Fixed,
+}
+
+impl FixedVec3 {
+    pub const fn new(x: Fixed, y: Fixed, z: Fixed) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn from_dvec3(value: glam::DVec3) -> Self {
+        Self::new(
+            Fixed::from_f64(value.x),
+            Fixed::from_f64(value.y),
+            Fixed::from_f64(value.z),
+        )
+    }
+
+    pub fn to_dvec3(self) -> glam::DVec3 {
+        glam::DVec3::new(self.x.to_f64(), self.y.to_f64(), self.z.to_f64())
+    }
+
+    pub fn length(self) -> Fixed {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    pub fn scale(self, factor: Fixed) -> Self {
+        Self::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+
+    /// Rotates the vector around the Y axis like `glam::Mat3::from_rotation_y`.
+    pub fn rotate_y(self, degrees: Fixed) -> Self {
+        let (sin, cos) = degrees.sin_cos_degrees();
+
+        Self::new(
+            cos * self.x + sin * self.z,
+            self.y,
+            cos * self.z - sin * self.x,
+        )
+    }
+}
+
+impl Add for FixedVec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for FixedVec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Fixed, b: f64) -> bool {
+        (a.to_f64() - b).abs() < 1e-8
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Fixed::from_f64(2.5);
+        let b = Fixed::from_f64(-0.75);
+
+        assert_eq!((a + b).to_f64(), 1.75);
+        assert_eq!((a - b).to_f64(), 3.25);
+        assert_eq!((a * b).to_f64(), -1.875);
+        assert!(close(a / b, -10.0 / 3.0));
+        assert_eq!(a / Fixed::ZERO, Fixed::ZERO);
+        assert_eq!((-a).to_f64(), -2.5);
+    }
+
+    #[test]
+    fn f64_round_trip_is_exact() {
+        for value in [0.0, 1.0, -1.5, 0.05, 1234.5678, -98765.4321] {
+            let fixed = Fixed::from_f64(value);
+            assert_eq!(Fixed::from_f64(fixed.to_f64()), fixed);
+        }
+    }
+
+    #[test]
+    fn sqrt() {
+        assert_eq!(Fixed::from_int(16).sqrt(), Fixed::from_int(4));
+        assert_eq!(Fixed::ZERO.sqrt(), Fixed::ZERO);
+        assert_eq!(Fixed::from_int(-4).sqrt(), Fixed::ZERO);
+        assert!(close(Fixed::from_int(2).sqrt(), std::f64::consts::SQRT_2));
+        assert!(close(Fixed::from_f64(0.01).sqrt(), 0.1));
+    }
+
+    #[test]
+    fn sin_cos() {
+        for degrees in (-720..=720).step_by(5) {
+            let (sin, cos) = Fixed::from_int(degrees).sin_cos_degrees();
+            let radians = (degrees as f64).to_radians();
+
+            assert!(close(sin, radians.sin()), "sin {degrees}");
+            assert!(close(cos, radians.cos()), "cos {degrees}");
+        }
+
+        assert_eq!(Fixed::from_int(90).sin_cos_degrees().1, Fixed::ZERO);
+    }
+
+    /// Raw values are the same on every platform, a change here breaks replays and lockstep between versions.
+    #[test]
+    fn results_are_bit_exact() {
+        assert_eq!(Fixed::from_int(2).sqrt().0, 6_074_000_999);
+        assert_eq!(Fixed::from_f64(0.05).0, 214_748_365);
+
+        let (sin, cos) = Fixed::from_f64(33.3).sin_cos_degrees();
+        assert_eq!((sin.0, cos.0), (2_358_035_048, 3_589_765_283));
+
+        let rotated =
+            FixedVec3::new(Fixed::ONE, Fixed::ZERO, Fixed::ONE).rotate_y(Fixed::from_int(-120));
+        assert_eq!((rotated.x.0, rotated.z.0), (-5_867_034_435, 1_572_067_139));
+    }
+}
@@ -1,9 +1,13 @@
 use core::fmt;
-use std::{collections::HashMap, ops};
+use std::{
+    collections::{HashMap, HashSet},
+    ops,
+    sync::Arc,
+};
 
 use shipyard::*;
 
-use crate::model::MissingModel;
+use crate::{biome::Biome, chunk_storage::ChunkStorage, coords};
 
 pub type BlockId = u32;
 
@@ -12,51 +16,196 @@ pub struct GameMap {
     pub chunks: HashMap<ChunkCoords, Chunk>,
     /// Maps chunk coordinates to corespoding entitiy ID - these should remain the same even if chunk is offloaded.
     pub chunk_entity_map: HashMap<ChunkCoords, EntityId>,
+    /// Chunks whose blocks changed since they were last copied to the world save.
+    pub edited: HashSet<ChunkCoords>,
+    /// Blocks set since falling blocks were last checked, their neighbors may have lost support.
+    pub changed_blocks: Vec<glam::IVec3>,
+    /// Blocks set since they were last sent to the server.
+    pub unsent_blocks: Vec<glam::IVec3>,
 }
 
 impl GameMap {
-    pub fn new_test(world: &mut World) -> Self {
-        let mut chunks = HashMap::new();
-        let mut chunk_entity_map = HashMap::new();
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            chunk_entity_map: HashMap::new(),
+            edited: HashSet::new(),
+            changed_blocks: Vec::new(),
+            unsent_blocks: Vec::new(),
+        }
+    }
 
-        for cz in -5..5 {
-            for cx in -5..5 {
-                let coords = ChunkCoords::new(cx, 0, cz);
-                let mut chunk = Chunk::new();
+    /// Returns the block at a world position, positions in unloaded chunks are treated as empty.
+    pub fn get_block_world(&self, position: glam::IVec3) -> Option<BlockId> {
+        let (coords, inner) = coords::split_block(position);
 
-                for bz in 0..32 {
-                    for bx in 0..32 {
-                        let mut max_y = if (cx + cz) % 2 == 0 { 3 } else { 2 };
+        self.chunks
+            .get(&coords)
+            .and_then(|chunk| chunk.get_block(inner))
+    }
 
-                        if (3..=Chunk::SIZE - 3).contains(&bx)
-                            && (3..=Chunk::SIZE - 3).contains(&bz)
-                        {
-                            max_y += 1;
-                        }
+    /// Returns the sky light at a world position, blocks are lit unless a block in a loaded chunk is above them.
+    pub fn light_at(&self, position: glam::IVec3) -> u8 {
+        let (mut coords, inner) = coords::split_block(position);
+        let mut lowest = inner.y;
+
+        while let Some(chunk) = self.chunks.get(&coords) {
+            if chunk
+                .height(inner.x, inner.z)
+                .is_some_and(|height| height >= lowest)
+            {
+                return 0;
+            }
 
-                        let block: u32 = (bx + bz) as u32 % 3;
+            coords = coords + ChunkCoords::new(0, 1, 0);
+            lowest = 0;
+        }
 
-                        for by in 0..max_y {
-                            chunk.set_block(InnerChunkCoords::new(bx, by, bz), Some(block));
-                        }
-                    }
-                }
+        Chunk::MAX_LIGHT
+    }
+
+    /// Returns the world height of the highest block of the column at a world position, searching the loaded
+    /// chunks stacked above and below it. None if there is no block in them.
+    pub fn surface_height(&self, position: glam::IVec3) -> Option<i32> {
+        let (mut coords, inner) = coords::split_block(position);
 
-                chunks.insert(coords, chunk);
-                chunk_entity_map.insert(
-                    coords,
-                    world.add_entity((ChunkTag { coords }, MissingModel)),
-                );
+        while self
+            .chunks
+            .contains_key(&(coords + ChunkCoords::new(0, 1, 0)))
+        {
+            coords = coords + ChunkCoords::new(0, 1, 0);
+        }
+
+        while let Some(chunk) = self.chunks.get(&coords) {
+            if let Some(height) = chunk.height(inner.x, inner.z) {
+                return Some(coords::chunk_origin(coords).y + height);
             }
+
+            coords = coords + ChunkCoords::new(0, -1, 0);
         }
 
-        Self {
-            chunks,
-            chunk_entity_map,
+        None
+    }
+
+    /// Returns the biome of the column at a world position, or None if its chunk isn't loaded.
+    pub fn biome_at(&self, position: glam::IVec3) -> Option<Biome> {
+        let (coords, inner) = coords::split_block(position);
+
+        self.chunks.get(&coords)?.get_biome(inner)
+    }
+
+    /// Sets the block at a world position and returns the coordinates of the modified chunk,
+    /// or None if the chunk isn't loaded.
+    pub fn set_block_world(
+        &mut self,
+        position: glam::IVec3,
+        block: Option<BlockId>,
+    ) -> Option<ChunkCoords> {
+        let (coords, inner) = coords::split_block(position);
+
+        let chunk = self.chunks.get_mut(&coords)?;
+        chunk.set_block(inner, block);
+        chunk.set_modified(true);
+        self.edited.insert(coords);
+        self.changed_blocks.push(position);
+        self.unsent_blocks.push(position);
+
+        Some(coords)
+    }
+
+    /// Walks the blocks along a ray and returns the first one which isn't empty,
+    /// blocks in unloaded chunks are treated as empty.
+    pub fn raycast(
+        &self,
+        origin: glam::DVec3,
+        direction: glam::DVec3,
+        max_distance: f64,
+    ) -> Option<RayHit> {
+        let direction = direction.try_normalize()?;
+        let mut block_coords = coords::block_containing(origin);
+        let mut face = None;
+        let mut distance = 0.0;
+
+        let step = glam::IVec3::new(
+            if direction.x > 0.0 { 1 } else { -1 },
+            if direction.y > 0.0 { 1 } else { -1 },
+            if direction.z > 0.0 { 1 } else { -1 },
+        );
+
+        // distance along the ray needed to cross one block on each axis
+        let t_delta = direction.recip().abs();
+
+        // distance along the ray to the next block boundary on each axis
+        let mut t_max = glam::DVec3::ZERO;
+        for axis in 0..3 {
+            let offset = origin[axis] - block_coords[axis] as f64;
+
+            t_max[axis] = if direction[axis] > 0.0 {
+                (1.0 - offset) * t_delta[axis]
+            } else if direction[axis] < 0.0 {
+                offset * t_delta[axis]
+            } else {
+                f64::INFINITY
+            };
+        }
+
+        loop {
+            if self.get_block_world(block_coords).is_some() {
+                return Some(RayHit {
+                    block_coords,
+                    face,
+                    distance,
+                });
+            }
+
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+                0
+            } else if t_max.y < t_max.z {
+                1
+            } else {
+                2
+            };
+
+            if t_max[axis] > max_distance {
+                return None;
+            }
+
+            block_coords[axis] += step[axis];
+            distance = t_max[axis];
+            t_max[axis] += t_delta[axis];
+
+            // the ray enters the next block through the face looking back at it
+            let entered_from_below = step[axis] > 0;
+            face = Some(match (axis, entered_from_below) {
+                (0, true) => FaceDirection::NegX,
+                (0, false) => FaceDirection::PosX,
+                (1, true) => FaceDirection::NegY,
+                (1, false) => FaceDirection::PosY,
+                (_, true) => FaceDirection::NegZ,
+                (_, false) => FaceDirection::PosZ,
+            });
         }
     }
 }
 
+/// Block hit by a ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// World position of the block.
+    pub block_coords: glam::IVec3,
+    /// Face the ray entered the block through, None if the ray started inside of it.
+    pub face: Option<FaceDirection>,
+    /// Distance along the ray from its origin to the face.
+    pub distance: f64,
+}
+
+impl RayHit {
+    /// Returns the position of the empty block in front of the hit face, where a block would be placed.
+    pub fn adjacent(&self) -> Option<glam::IVec3> {
+        self.face.map(|face| self.block_coords + face.normal())
+    }
+}
+
 #[derive(Debug, Clone, Copy, Component)]
 pub struct ChunkTag {
     pub coords: ChunkCoords,
@@ -64,29 +213,259 @@ pub struct ChunkTag {
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Chunk {
-    blocks: Vec<Option<BlockId>>,
+    blocks: ChunkStorage,
+    /// Biome of every column in the order of `column_idx`, None until the world generator assigns them.
+    #[serde(default)]
+    biomes: Option<Vec<Biome>>,
+    /// Sky light of every block in the order of `InnerChunkCoords::as_idx`, None until it's computed.
+    #[serde(default)]
+    light: Option<Vec<u8>>,
+    /// Height of the highest block of every column in the order of `column_idx`, -1 for empty columns.
+    /// Kept up to date with the blocks, None only for chunks deserialized without it.
+    #[serde(default)]
+    heights: Option<Vec<i8>>,
+    /// Set once the player changes a block, generated chunks are unmodified.
+    #[serde(default)]
+    modified: bool,
+    /// Saved names of blocks which aren't defined by the loaded packs, by the index of the placeholder
+    /// block standing in for them. Written back when the chunk is saved, until the placeholder is replaced.
+    #[serde(skip)]
+    unknown_blocks: HashMap<u16, Arc<str>>,
 }
 
 impl Chunk {
     pub const SIZE: i32 = 32;
     pub const BLOCKS_COUNT: i32 = Chunk::SIZE * Chunk::SIZE * Chunk::SIZE;
+    pub const COLUMNS_COUNT: i32 = Chunk::SIZE * Chunk::SIZE;
+    /// Light level of blocks open to the sky.
+    pub const MAX_LIGHT: u8 = 15;
 
     pub fn new() -> Self {
-        let blocks = vec![None; Chunk::BLOCKS_COUNT as usize];
+        Self {
+            blocks: ChunkStorage::Uniform(None),
+            biomes: None,
+            light: None,
+            heights: Some(vec![-1; Chunk::COLUMNS_COUNT as usize]),
+            modified: false,
+            unknown_blocks: HashMap::new(),
+        }
+    }
+
+    /// Creates a chunk from blocks in the order of `InnerChunkCoords::as_idx`, or None if the count doesn't match.
+    pub fn from_blocks(blocks: Vec<Option<BlockId>>) -> Option<Self> {
+        (blocks.len() == Chunk::BLOCKS_COUNT as usize).then(|| {
+            let mut chunk = Self {
+                blocks: ChunkStorage::from_blocks(blocks),
+                biomes: None,
+                light: None,
+                heights: None,
+                modified: false,
+                unknown_blocks: HashMap::new(),
+            };
+            chunk.compute_heights();
+
+            chunk
+        })
+    }
+
+    /// Returns all blocks in the order of `InnerChunkCoords::as_idx`.
+    pub fn blocks(&self) -> impl Iterator<Item = Option<BlockId>> + '_ {
+        self.blocks.iter()
+    }
 
-        Self { blocks }
+    /// Returns the block filling the whole chunk, if all of its blocks are the same.
+    pub fn uniform_block(&self) -> Option<Option<BlockId>> {
+        match self.blocks {
+            ChunkStorage::Uniform(block) => Some(block),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the chunk is known to contain only air.
+    pub fn is_empty(&self) -> bool {
+        self.uniform_block() == Some(None)
+    }
+
+    /// Stores the blocks in the smallest form able to hold them,
+    /// worth calling once after filling the chunk block by block.
+    pub fn compact(&mut self) {
+        self.blocks.compact();
     }
 
     pub fn get_block(&self, coords: InnerChunkCoords) -> Option<BlockId> {
-        self.blocks[coords.as_idx()]
+        self.blocks.get(coords.as_idx())
     }
 
+    /// Sets a block, keeping the height and light of its column up to date.
     pub fn set_block(&mut self, coords: InnerChunkCoords, block: Option<BlockId>) {
-        self.blocks[coords.as_idx()] = block;
+        self.blocks.set(coords.as_idx(), block);
+        if !self.unknown_blocks.is_empty() {
+            self.unknown_blocks.remove(&(coords.as_idx() as u16));
+        }
+
+        if let Some(heights) = &mut self.heights {
+            let height = &mut heights[column_idx(coords.x, coords.z)];
+
+            if block.is_some() {
+                *height = (*height).max(coords.y as i8);
+            } else if coords.y == *height as i32 {
+                *height = column_height(&self.blocks, coords.x, coords.z);
+            }
+        }
+
+        if self.light.is_some() {
+            self.relight_column(coords.x, coords.z);
+        }
+    }
+
+    /// Returns true if any block of the chunk is one of `blocks`.
+    pub fn contains_any(&self, blocks: &HashSet<BlockId>) -> bool {
+        !blocks.is_empty()
+            && self
+                .blocks
+                .kinds()
+                .iter()
+                .any(|block| block.is_some_and(|block| blocks.contains(&block)))
+    }
+
+    /// Returns biomes of all columns in the order of `column_idx`, or None if they weren't assigned.
+    pub fn biomes(&self) -> Option<&[Biome]> {
+        self.biomes.as_deref()
+    }
+
+    /// Assigns biomes to all columns, None if the count doesn't match.
+    pub fn set_biomes(&mut self, biomes: Vec<Biome>) -> Option<()> {
+        (biomes.len() == Chunk::COLUMNS_COUNT as usize).then(|| self.biomes = Some(biomes))
+    }
+
+    pub fn get_biome(&self, coords: InnerChunkCoords) -> Option<Biome> {
+        self.biomes
+            .as_ref()
+            .map(|biomes| biomes[column_idx(coords.x, coords.z)])
+    }
+
+    /// Returns light of all blocks in the order of `InnerChunkCoords::as_idx`, or None if it wasn't computed.
+    pub fn light(&self) -> Option<&[u8]> {
+        self.light.as_deref()
+    }
+
+    /// Sets light of all blocks, None if the count doesn't match.
+    pub fn set_light(&mut self, light: Vec<u8>) -> Option<()> {
+        (light.len() == Chunk::BLOCKS_COUNT as usize).then(|| self.light = Some(light))
+    }
+
+    /// Returns the height of the highest block of a column within the chunk, or None if it's empty.
+    pub fn height(&self, x: i32, z: i32) -> Option<i32> {
+        let height = match &self.heights {
+            Some(heights) => heights[column_idx(x, z)],
+            None => column_height(&self.blocks, x, z),
+        };
+
+        (height >= 0).then_some(height as i32)
+    }
+
+    fn compute_heights(&mut self) {
+        let heights = match self.blocks {
+            ChunkStorage::Uniform(None) => vec![-1; Chunk::COLUMNS_COUNT as usize],
+            ChunkStorage::Uniform(Some(_)) => {
+                vec![Chunk::SIZE as i8 - 1; Chunk::COLUMNS_COUNT as usize]
+            }
+            _ => (0..Chunk::SIZE)
+                .flat_map(|z| (0..Chunk::SIZE).map(move |x| (x, z)))
+                .map(|(x, z)| column_height(&self.blocks, x, z))
+                .collect(),
+        };
+
+        self.heights = Some(heights);
+    }
+
+    /// Computes the light of every block.
+    ///
+    /// There is no light propagation yet, sky light falls straight down from the top of the chunk
+    /// until it hits a block, so the light of a chunk doesn't depend on its neighbours.
+    /// `GameMap::light_at` combines it with the chunks above.
+    pub fn relight(&mut self) {
+        if self.light.is_none() {
+            self.light = Some(vec![0; Chunk::BLOCKS_COUNT as usize]);
+        }
+
+        for z in 0..Chunk::SIZE {
+            for x in 0..Chunk::SIZE {
+                self.relight_column(x, z);
+            }
+        }
+    }
+
+    fn relight_column(&mut self, x: i32, z: i32) {
+        let Some(light) = &mut self.light else {
+            return;
+        };
+
+        let height = match &self.heights {
+            Some(heights) => heights[column_idx(x, z)],
+            None => column_height(&self.blocks, x, z),
+        } as i32;
+
+        for y in 0..Chunk::SIZE {
+            let level = if y > height { Chunk::MAX_LIGHT } else { 0 };
+            light[InnerChunkCoords::new(x, y, z).as_idx()] = level;
+        }
+    }
+
+    /// Returns the saved name of the block at `idx` in the order of `InnerChunkCoords::as_idx`,
+    /// if a placeholder stands in for it.
+    pub fn unknown_block(&self, idx: usize) -> Option<&str> {
+        self.unknown_blocks.get(&(idx as u16)).map(|name| &**name)
+    }
+
+    /// Remembers the saved name of the placeholder block at `idx`, so saving the chunk keeps it.
+    pub fn set_unknown_block(&mut self, idx: usize, name: Arc<str>) {
+        self.unknown_blocks.insert(idx as u16, name);
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn set_modified(&mut self, modified: bool) {
+        self.modified = modified;
+    }
+
+    /// Fills in the biomes, heights and light a saved chunk doesn't have, biomes are taken from the same chunk
+    /// as it was generated, heights and light are computed again.
+    pub fn restore_missing(&mut self, generated: &Chunk) {
+        if self.biomes.is_none() {
+            self.biomes = generated.biomes.clone();
+        }
+
+        if self.heights.is_none() {
+            self.compute_heights();
+        }
+
+        if self.light.is_none() {
+            self.relight();
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Returns the height of the highest block of a column within a chunk, -1 if it's empty.
+fn column_height(blocks: &ChunkStorage, x: i32, z: i32) -> i8 {
+    (0..Chunk::SIZE)
+        .rev()
+        .find(|&y| {
+            blocks
+                .get(InnerChunkCoords::new(x, y, z).as_idx())
+                .is_some()
+        })
+        .map_or(-1, |y| y as i8)
+}
+
+/// Index of a column of blocks within a chunk, columns are ordered row by row along X.
+pub fn column_idx(x: i32, z: i32) -> usize {
+    (z * Chunk::SIZE + x) as usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ChunkCoords {
     pub x: i32,
     pub y: i32,
@@ -98,6 +477,21 @@ impl ChunkCoords {
         Self { x, y, z }
     }
 
+    /// Returns coordinates of the chunk containing a world space position.
+    pub fn from_world_position(position: glam::DVec3) -> Self {
+        let chunk_position = (position / Chunk::SIZE as f64).floor();
+
+        Self::new(
+            chunk_position.x as i32,
+            chunk_position.y as i32,
+            chunk_position.z as i32,
+        )
+    }
+
+    pub fn as_ivec3(&self) -> glam::IVec3 {
+        glam::IVec3::new(self.x, self.y, self.z)
+    }
+
     pub fn as_translation(&self) -> glam::Vec3 {
         glam::Vec3::new(
             self.x as f32 * Chunk::SIZE as f32,
@@ -105,6 +499,17 @@ impl ChunkCoords {
             self.z as f32 * Chunk::SIZE as f32,
         )
     }
+
+    /// Returns the world space position of the chunk with full precision.
+    pub fn as_world_position(&self) -> glam::DVec3 {
+        glam::DVec3::new(self.x as f64, self.y as f64, self.z as f64) * Chunk::SIZE as f64
+    }
+
+    /// Returns the translation of the chunk relative to the `origin` chunk.
+    /// Differences are small near the camera, so they stay precise in f32 anywhere in the world.
+    pub fn translation_from(&self, origin: ChunkCoords) -> glam::Vec3 {
+        ChunkCoords::new(self.x - origin.x, self.y - origin.y, self.z - origin.z).as_translation()
+    }
 }
 
 impl fmt::Display for ChunkCoords {
@@ -161,6 +566,10 @@ impl InnerChunkCoords {
         z * chunk_size * chunk_size + y * chunk_size + x
     }
 
+    pub fn as_ivec3(&self) -> glam::IVec3 {
+        glam::IVec3::new(self.x, self.y, self.z)
+    }
+
     pub fn as_block_center(&self) -> glam::Vec3 {
         glam::Vec3::new(
             self.x as f32 + 0.5,
@@ -231,6 +640,18 @@ impl FaceDirection {
         !self.is_positive()
     }
 
+    /// Returns the unit vector pointing out of the face.
+    pub fn normal(self) -> glam::IVec3 {
+        match self {
+            FaceDirection::PosX => glam::IVec3::X,
+            FaceDirection::NegX => glam::IVec3::NEG_X,
+            FaceDirection::PosY => glam::IVec3::Y,
+            FaceDirection::NegY => glam::IVec3::NEG_Y,
+            FaceDirection::PosZ => glam::IVec3::Z,
+            FaceDirection::NegZ => glam::IVec3::NEG_Z,
+        }
+    }
+
     pub fn is_x(self) -> bool {
         match self {
             FaceDirection::PosX => true,
@@ -281,3 +702,215 @@ impl From<usize> for FaceDirection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a map with the given blocks, loading empty chunks around them.
+    fn map_with_blocks(blocks: &[glam::IVec3]) -> GameMap {
+        let mut game_map = GameMap::new();
+
+        for position in blocks {
+            let (coords, _) = coords::split_block(*position);
+            game_map.chunks.entry(coords).or_insert_with(Chunk::new);
+            game_map.set_block_world(*position, Some(0));
+        }
+
+        game_map
+    }
+
+    fn load_chunks(game_map: &mut GameMap, from: ChunkCoords, to: ChunkCoords) {
+        for z in from.z..=to.z {
+            for y in from.y..=to.y {
+                for x in from.x..=to.x {
+                    game_map
+                        .chunks
+                        .entry(ChunkCoords::new(x, y, z))
+                        .or_insert_with(Chunk::new);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hits_block_across_chunk_border() {
+        let block = glam::IVec3::new(Chunk::SIZE + 3, 0, 0);
+        let mut game_map = map_with_blocks(&[block]);
+        load_chunks(
+            &mut game_map,
+            ChunkCoords::new(0, 0, 0),
+            ChunkCoords::new(1, 0, 0),
+        );
+
+        let origin = glam::DVec3::new(Chunk::SIZE as f64 - 2.5, 0.5, 0.5);
+        let hit = game_map
+            .raycast(origin, glam::DVec3::X, 10.0)
+            .expect("the block should be hit");
+
+        assert_eq!(hit.block_coords, block);
+        assert_eq!(hit.face, Some(FaceDirection::NegX));
+        assert!((hit.distance - 5.5).abs() < 1e-9);
+        assert_eq!(hit.adjacent(), Some(block - glam::IVec3::X));
+    }
+
+    #[test]
+    fn hits_block_at_negative_coordinates() {
+        let block = glam::IVec3::new(-Chunk::SIZE - 1, -1, -1);
+        let mut game_map = map_with_blocks(&[block]);
+        load_chunks(
+            &mut game_map,
+            ChunkCoords::new(-2, -1, -1),
+            ChunkCoords::new(0, 0, 0),
+        );
+
+        let origin = glam::DVec3::new(0.5, -0.5, -0.5);
+        let hit = game_map
+            .raycast(origin, glam::DVec3::NEG_X, 40.0)
+            .expect("the block should be hit");
+
+        assert_eq!(hit.block_coords, block);
+        assert_eq!(hit.face, Some(FaceDirection::PosX));
+        assert!((hit.distance - Chunk::SIZE as f64 - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hits_top_face_diagonally() {
+        let block = glam::IVec3::new(-2, -3, 2);
+        let game_map = map_with_blocks(&[block]);
+
+        // enters the block from above, exactly at the middle of its top face
+        let origin = glam::DVec3::new(-0.5, 0.0, 3.5);
+        let target = glam::DVec3::new(-1.5, -2.0, 2.5);
+        let hit = game_map
+            .raycast(origin, target - origin, 10.0)
+            .expect("the block should be hit");
+
+        assert_eq!(hit.block_coords, block);
+        assert_eq!(hit.face, Some(FaceDirection::PosY));
+        assert!((hit.distance - (target - origin).length()).abs() < 1e-9);
+        assert_eq!(hit.adjacent(), Some(block + glam::IVec3::Y));
+    }
+
+    #[test]
+    fn misses_beyond_max_distance() {
+        let game_map = map_with_blocks(&[glam::IVec3::new(0, 0, 10)]);
+
+        let origin = glam::DVec3::new(0.5, 0.5, 0.5);
+        assert!(game_map.raycast(origin, glam::DVec3::Z, 8.0).is_none());
+        assert!(game_map.raycast(origin, glam::DVec3::Z, 10.0).is_some());
+        assert!(game_map.raycast(origin, glam::DVec3::ZERO, 10.0).is_none());
+    }
+
+    #[test]
+    fn heights_follow_block_changes() {
+        let top = glam::IVec3::new(3, Chunk::SIZE + 5, -2);
+        let mut game_map = map_with_blocks(&[glam::IVec3::new(3, 2, -2), top]);
+        load_chunks(
+            &mut game_map,
+            ChunkCoords::new(0, 0, -1),
+            ChunkCoords::new(0, 2, -1),
+        );
+
+        // searched from anywhere in the column, across the stacked chunks
+        let below = glam::IVec3::new(3, -10, -2);
+        assert_eq!(game_map.surface_height(below), Some(top.y));
+        assert_eq!(game_map.surface_height(top + glam::IVec3::X), None);
+        assert_eq!(
+            game_map.surface_height(top * glam::IVec3::new(1, 0, 1)),
+            Some(top.y)
+        );
+        assert_eq!(game_map.light_at(glam::IVec3::new(3, 3, -2)), 0);
+
+        game_map.set_block_world(top, None);
+        assert_eq!(game_map.surface_height(top), Some(2));
+        assert_eq!(
+            game_map.light_at(glam::IVec3::new(3, 3, -2)),
+            Chunk::MAX_LIGHT
+        );
+
+        game_map.set_block_world(glam::IVec3::new(3, 2, -2), None);
+        assert_eq!(game_map.surface_height(top), None);
+    }
+
+    #[test]
+    fn starts_inside_of_block() {
+        let block = glam::IVec3::new(-1, 4, 7);
+        let game_map = map_with_blocks(&[block]);
+
+        let hit = game_map
+            .raycast(glam::DVec3::new(-0.5, 4.5, 7.5), glam::DVec3::Y, 5.0)
+            .expect("the block should be hit");
+
+        assert_eq!(hit.block_coords, block);
+        assert_eq!(hit.face, None);
+        assert_eq!(hit.distance, 0.0);
+        assert_eq!(hit.adjacent(), None);
+    }
+
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        /// Chunk filled with a few kinds of blocks at random positions, so it's stored with a palette.
+        fn palette_chunk() -> impl Strategy<Value = Chunk> {
+            let kinds = prop::collection::vec(prop::option::of(0..BlockId::MAX), 2..32);
+            let edits = prop::collection::vec(
+                (
+                    0..Chunk::SIZE,
+                    0..Chunk::SIZE,
+                    0..Chunk::SIZE,
+                    any::<prop::sample::Index>(),
+                ),
+                1..256,
+            );
+
+            (kinds, edits, any::<bool>()).prop_map(|(kinds, edits, modified)| {
+                let mut chunk = Chunk::new();
+
+                for (x, y, z, kind) in edits {
+                    chunk.set_block(InnerChunkCoords::new(x, y, z), *kind.get(&kinds));
+                }
+
+                chunk.compact();
+                chunk.set_modified(modified);
+                chunk
+            })
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(64))]
+
+            #[test]
+            fn chunk_serde_roundtrips(chunk in palette_chunk()) {
+                let serialized = ron::to_string(&chunk).unwrap();
+                let deserialized: Chunk = ron::from_str(&serialized).unwrap();
+
+                // only edits to air leave the chunk uniform
+                let compact = !matches!(chunk.blocks, ChunkStorage::Dense(_));
+                prop_assert!(compact);
+                prop_assert_eq!(&deserialized.blocks, &chunk.blocks);
+                prop_assert!(deserialized.blocks().eq(chunk.blocks()));
+                prop_assert_eq!(deserialized.is_modified(), chunk.is_modified());
+            }
+
+            #[test]
+            fn edited_blocks_are_read_back(
+                edits in prop::collection::vec((0..Chunk::SIZE, 0..Chunk::SIZE, 0..Chunk::SIZE, prop::option::of(0..4u32)), 1..64),
+            ) {
+                let mut chunk = Chunk::new();
+                let mut expected = vec![None; Chunk::BLOCKS_COUNT as usize];
+
+                for (x, y, z, block) in edits {
+                    let inner = InnerChunkCoords::new(x, y, z);
+                    chunk.set_block(inner, block);
+                    expected[inner.as_idx()] = block;
+                }
+
+                chunk.compact();
+                prop_assert!(chunk.blocks().eq(expected.iter().copied()));
+            }
+        }
+    }
+}
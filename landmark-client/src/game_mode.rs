@@ -0,0 +1,75 @@
+//! Rules which differ between creative and survival play.
+
+use std::{fmt, str::FromStr};
+
+use shipyard::*;
+
+/// Set of rules the player is playing by, switched with `/gamemode`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Unique, serde::Serialize, serde::Deserialize,
+)]
+pub enum GameMode {
+    /// Flying, instant block breaking and placing blocks without using them up.
+    #[default]
+    Creative,
+    /// Gravity, blocks take time to break, placed blocks are taken from the inventory and the
+    /// player can be hurt.
+    Survival,
+}
+
+impl GameMode {
+    pub fn can_fly(self) -> bool {
+        self == GameMode::Creative
+    }
+
+    /// Whether placing a block takes it from the inventory.
+    pub fn uses_up_blocks(self) -> bool {
+        self == GameMode::Survival
+    }
+
+    /// Whether blocks break after a time depending on their hardness, instead of on the first click.
+    pub fn has_break_time(self) -> bool {
+        self == GameMode::Survival
+    }
+
+    /// Whether the player can be hurt, falling out of the world is deadly either way.
+    pub fn takes_damage(self) -> bool {
+        self == GameMode::Survival
+    }
+
+    /// Whether blocks broken and placed by the player can be undone, in survival undoing would
+    /// give back placed blocks and keep the drops of broken ones.
+    pub fn records_edits(self) -> bool {
+        self == GameMode::Creative
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            GameMode::Creative => GameMode::Survival,
+            GameMode::Survival => GameMode::Creative,
+        }
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameMode::Creative => write!(f, "creative"),
+            GameMode::Survival => write!(f, "survival"),
+        }
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "creative" | "c" => Ok(GameMode::Creative),
+            "survival" | "s" => Ok(GameMode::Survival),
+            _ => Err(format!(
+                "{s} is not a game mode, expected creative or survival"
+            )),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! High dynamic range rendering with bloom.
+//!
+//! The world is drawn into a floating point target, so glowing blocks can be brighter than white.
+//! Those parts of the frame are copied into a chain of textures, each half the size of the previous one,
+//! which are then added back up from the smallest, spreading their light into a wide glow. A composite
+//! pass adds the glow to the frame and maps it onto the range of the display before the HUD is drawn.
+
+use shipyard::*;
+use wgpu::util::DeviceExt;
+
+use crate::{rendererer::Renderer, settings::Settings, texture};
+
+/// Format of the target the world is drawn into.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Textures of the bloom chain, the glow reaches about `2^BLOOM_LEVELS` pixels far.
+const BLOOM_LEVELS: u32 = 6;
+
+/// Parameters of the composite pass as laid out in the uniform buffer.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BloomUniform {
+    intensity: f32,
+    _padding: [f32; 3],
+}
+
+/// Textures a view is drawn into before it's composited onto its surface, sized like the view.
+#[derive(Debug)]
+pub struct FrameTargets {
+    pub depth: texture::Texture,
+    pub hdr: texture::Texture,
+    /// Halved again at every level, starting at half the size of the frame.
+    pub bloom: Vec<texture::Texture>,
+    /// Samples `hdr`.
+    pub hdr_bind_group: wgpu::BindGroup,
+    /// Sample the bloom levels in the same order.
+    pub bloom_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl FrameTargets {
+    /// Creates the targets of a view, the textures are sampled with bind groups of `texture_layout`.
+    pub fn new(
+        device: &wgpu::Device,
+        texture_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let depth = texture::Texture::create_sized_depth_texture(device, width, height, "depth");
+        let hdr = texture::Texture::create_render_target(device, width, height, HDR_FORMAT, "hdr");
+
+        let bloom: Vec<texture::Texture> = (1..=BLOOM_LEVELS)
+            .map(|level| {
+                texture::Texture::create_render_target(
+                    device,
+                    (width >> level).max(1),
+                    (height >> level).max(1),
+                    HDR_FORMAT,
+                    "bloom_level",
+                )
+            })
+            .collect();
+
+        let bind_group = |texture: &texture::Texture| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: texture_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                ],
+                label: Some("frame_target_bind_group"),
+            })
+        };
+
+        Self {
+            hdr_bind_group: bind_group(&hdr),
+            bloom_bind_groups: bloom.iter().map(bind_group).collect(),
+            depth,
+            hdr,
+            bloom,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Bloom {
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    /// Strength of the glow written to the uniform, the bloom passes are skipped at zero.
+    pub intensity: f32,
+}
+
+impl Bloom {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bloom_buffer"),
+            contents: bytemuck::cast_slice(&[BloomUniform {
+                intensity: 0.0,
+                _padding: [0.0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("bloom_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("bloom_bind_group"),
+        });
+
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+            intensity: 0.0,
+        }
+    }
+
+    fn set_intensity(&mut self, queue: &wgpu::Queue, intensity: f32) {
+        self.intensity = intensity;
+
+        let uniform = BloomUniform {
+            intensity,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+/// Applies the bloom intensity of the settings, negative values turn it off like zero.
+pub fn update_bloom_sys(settings: UniqueView<Settings>, mut renderer: UniqueViewMut<Renderer>) {
+    let intensity = settings.graphics.bloom.max(0.0);
+
+    if intensity != renderer.bloom.intensity {
+        let renderer = &mut *renderer;
+        renderer.bloom.set_intensity(&renderer.queue, intensity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_matches_the_shader_layout() {
+        // the intensity, rounded up to 16 bytes
+        assert_eq!(std::mem::size_of::<BloomUniform>(), 16);
+    }
+}
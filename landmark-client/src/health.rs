@@ -0,0 +1,113 @@
+//! Health of the player and the damage dealt to it.
+//!
+//! Anything hurting the player pushes a `Damage` to `DamageEvents` instead of changing the health
+//! directly, so the flash on the screen and death are handled in one place.
+
+use std::time::{Duration, Instant};
+
+use shipyard::*;
+
+use crate::{
+    game_mode::GameMode,
+    input::InputState,
+    menu::{kill_player, Menu},
+    player::LocalPlayer,
+};
+
+/// How long the screen flashes red after the player was hurt.
+pub const DAMAGE_FLASH_DURATION: Duration = Duration::from_millis(400);
+
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub const PLAYER_MAX: u32 = 20;
+
+    pub fn full(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageCause {
+    Fall,
+    /// Fell out of the bottom of the world, always deadly.
+    Void,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Damage {
+    pub entity: EntityId,
+    pub amount: u32,
+    pub cause: DamageCause,
+}
+
+/// Damage dealt since the last update, applied by `damage_sys`.
+#[derive(Debug, Default, Unique)]
+pub struct DamageEvents {
+    pending: Vec<Damage>,
+    /// When the local player was last hurt, for the red flash of the HUD.
+    pub last_hurt: Option<Instant>,
+}
+
+impl DamageEvents {
+    pub fn push(&mut self, damage: Damage) {
+        self.pending.push(damage);
+    }
+
+    /// Returns how strong the red flash is, from 1 right after the player was hurt down to 0.
+    pub fn flash(&self) -> f32 {
+        let Some(last_hurt) = self.last_hurt else {
+            return 0.0;
+        };
+
+        1.0 - (last_hurt.elapsed().as_secs_f32() / DAMAGE_FLASH_DURATION.as_secs_f32()).min(1.0)
+    }
+}
+
+/// Takes the damage off the health of the entities, the local player dies when none is left.
+pub fn damage_sys(
+    game_mode: UniqueView<GameMode>,
+    mut events: UniqueViewMut<DamageEvents>,
+    mut menu: UniqueViewMut<Menu>,
+    mut input_state: UniqueViewMut<InputState>,
+    local_players: View<LocalPlayer>,
+    mut healths: ViewMut<Health>,
+) {
+    for damage in std::mem::take(&mut events.pending) {
+        let local = local_players.contains(damage.entity);
+
+        if local && !game_mode.takes_damage() && damage.cause != DamageCause::Void {
+            continue;
+        }
+
+        let Ok(health) = (&mut healths).get(damage.entity) else {
+            continue;
+        };
+
+        // the dead can't be hurt
+        if health.is_dead() || damage.amount == 0 {
+            continue;
+        }
+
+        health.current = health.current.saturating_sub(damage.amount);
+
+        if !local {
+            continue;
+        }
+
+        events.last_hurt = Some(Instant::now());
+
+        if health.is_dead() {
+            log::info!("Player died of {:?} damage", damage.cause);
+            kill_player(&mut menu, &mut input_state);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use crate::{
+    biome::Biome,
+    block::BlockData,
+    color::Color,
+    coords,
+    game_map::{BlockId, Chunk, ChunkCoords, InnerChunkCoords},
+    loader::ResourceDictionary,
+    settings::HeightmapSettings,
+};
+
+#[derive(Debug)]
+pub enum HeightmapError {
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    SizeMismatch {
+        heightmap: (u32, u32),
+        color_map: (u32, u32),
+    },
+    InvalidRange {
+        min: i32,
+        max: i32,
+    },
+    UnknownBlock {
+        name: String,
+    },
+}
+
+impl fmt::Display for HeightmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeightmapError::Image { path, source } => {
+                write!(f, "Failed to load image {}: {source}", path.display())
+            }
+            HeightmapError::SizeMismatch {
+                heightmap,
+                color_map,
+            } => write!(
+                f,
+                "Color map is {}x{} but the heightmap is {}x{}",
+                color_map.0, color_map.1, heightmap.0, heightmap.1
+            ),
+            HeightmapError::InvalidRange { min, max } => {
+                write!(f, "Height range {min} to {max} is empty")
+            }
+            HeightmapError::UnknownBlock { name } => {
+                write!(f, "Block {name} used by the heightmap is not defined")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeightmapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HeightmapError::Image { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Terrain built from a grayscale image, every pixel is a column of blocks centered around the origin.
+/// Black pixels are at the lowest height, white ones at the highest. Outside of the image there is only air.
+#[derive(Debug)]
+pub struct Heightmap {
+    width: u32,
+    depth: u32,
+    /// Height of the top block of every column, row by row.
+    heights: Vec<i32>,
+    min_height: i32,
+    max_height: i32,
+    /// Top block of every column, row by row.
+    surface: Vec<BlockId>,
+    fill_block: BlockId,
+}
+
+impl Heightmap {
+    pub fn load(
+        settings: &HeightmapSettings,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Result<Self, HeightmapError> {
+        if settings.max_height < settings.min_height {
+            return Err(HeightmapError::InvalidRange {
+                min: settings.min_height,
+                max: settings.max_height,
+            });
+        }
+
+        let find_block = |name: &str| {
+            resource_dictionary
+                .find_block_id(name)
+                .ok_or_else(|| HeightmapError::UnknownBlock {
+                    name: name.to_string(),
+                })
+        };
+        let surface_block = find_block(&settings.surface_block)?;
+        let fill_block = find_block(&settings.fill_block)?;
+
+        // 16 bit images keep their precision, so tall ranges don't get terraced
+        let image = open_image(&settings.image)?.into_luma16();
+        let (width, depth) = image.dimensions();
+        let range = (settings.max_height - settings.min_height) as f64;
+
+        let heights = image
+            .pixels()
+            .map(|pixel| {
+                settings.min_height + (pixel.0[0] as f64 / u16::MAX as f64 * range).round() as i32
+            })
+            .collect();
+
+        let surface = match &settings.color_map {
+            Some(path) => {
+                let color_map = open_image(path)?.into_rgb8();
+
+                if color_map.dimensions() != (width, depth) {
+                    return Err(HeightmapError::SizeMismatch {
+                        heightmap: (width, depth),
+                        color_map: color_map.dimensions(),
+                    });
+                }
+
+                let mut closest: HashMap<[u8; 3], BlockId> = HashMap::new();
+
+                color_map
+                    .pixels()
+                    .map(|pixel| {
+                        *closest
+                            .entry(pixel.0)
+                            .or_insert_with(|| closest_block(resource_dictionary, pixel.0))
+                    })
+                    .collect()
+            }
+            None => vec![surface_block; (width * depth) as usize],
+        };
+
+        log::info!(
+            "Loaded a {width}x{depth} heightmap from {} with heights from {} to {}",
+            settings.image,
+            settings.min_height,
+            settings.max_height
+        );
+
+        Ok(Self {
+            width,
+            depth,
+            heights,
+            min_height: settings.min_height,
+            max_height: settings.max_height,
+            surface,
+            fill_block,
+        })
+    }
+
+    /// Creates a square heightmap of rolling hills from a seed, so the same terrain can be generated
+    /// again without an image. Used by the benchmarks.
+    pub fn from_noise(
+        seed: u64,
+        size: u32,
+        min_height: i32,
+        max_height: i32,
+        surface_block: BlockId,
+        fill_block: BlockId,
+    ) -> Self {
+        let range = (max_height - min_height) as f32;
+
+        let heights = (0..size * size)
+            .map(|idx| {
+                let noise = fractal_noise(seed, (idx % size) as f32, (idx / size) as f32);
+                min_height + (noise * range).round() as i32
+            })
+            .collect();
+
+        Self {
+            width: size,
+            depth: size,
+            heights,
+            min_height,
+            max_height,
+            surface: vec![surface_block; (size * size) as usize],
+            fill_block,
+        }
+    }
+
+    /// Returns the index of the column at a world space position, or None if it's outside of the image.
+    fn column(&self, x: i32, z: i32) -> Option<usize> {
+        let px = x + self.width as i32 / 2;
+        let pz = z + self.depth as i32 / 2;
+
+        if (0..self.width as i32).contains(&px) && (0..self.depth as i32).contains(&pz) {
+            Some((pz as u32 * self.width + px as u32) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn generate_chunk(&self, coords: ChunkCoords) -> Chunk {
+        let mut chunk = Chunk::new();
+        let origin = coords::chunk_origin(coords);
+
+        for bz in 0..Chunk::SIZE {
+            for bx in 0..Chunk::SIZE {
+                let Some(column) = self.column(origin.x + bx, origin.z + bz) else {
+                    continue;
+                };
+                let height = self.heights[column];
+
+                for by in 0..Chunk::SIZE {
+                    let y = origin.y + by;
+
+                    let block = if y < height {
+                        self.fill_block
+                    } else if y == height {
+                        self.surface[column]
+                    } else {
+                        break;
+                    };
+
+                    chunk.set_block(InnerChunkCoords::new(bx, by, bz), Some(block));
+                }
+            }
+        }
+
+        chunk
+    }
+
+    /// Returns the biome of every column of a chunk by the height of the terrain there,
+    /// columns outside of the image are plains.
+    pub fn biomes(&self, coords: ChunkCoords) -> Vec<Biome> {
+        let origin = coords::chunk_origin(coords);
+
+        (0..Chunk::SIZE)
+            .flat_map(|bz| (0..Chunk::SIZE).map(move |bx| (bx, bz)))
+            .map(|(bx, bz)| match self.column(origin.x + bx, origin.z + bz) {
+                Some(column) => {
+                    Biome::from_height(self.heights[column], self.min_height, self.max_height)
+                }
+                None => Biome::Plains,
+            })
+            .collect()
+    }
+}
+
+/// Wavelength of the largest hills in blocks, each further octave halves it.
+const NOISE_WAVELENGTH: f32 = 128.0;
+const NOISE_OCTAVES: i32 = 5;
+
+/// Returns a random value from 0 to 1 for a point of the noise lattice.
+fn lattice_value(seed: u64, x: i32, z: i32) -> f32 {
+    // splitmix64 of the seed and the point
+    let mut hash = seed ^ ((x as u32 as u64) << 32 | z as u32 as u64);
+    hash = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Smoothly interpolated value noise from 0 to 1 with a wavelength of one.
+fn value_noise(seed: u64, x: f32, z: f32) -> f32 {
+    let (x0, z0) = (x.floor() as i32, z.floor() as i32);
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, tz) = (smooth(x - x0 as f32), smooth(z - z0 as f32));
+
+    let top = lattice_value(seed, x0, z0) * (1.0 - tx) + lattice_value(seed, x0 + 1, z0) * tx;
+    let bottom =
+        lattice_value(seed, x0, z0 + 1) * (1.0 - tx) + lattice_value(seed, x0 + 1, z0 + 1) * tx;
+
+    top * (1.0 - tz) + bottom * tz
+}
+
+/// Sums octaves of value noise, each with half the wavelength and amplitude, scaled from 0 to 1.
+fn fractal_noise(seed: u64, x: f32, z: f32) -> f32 {
+    let mut sum = 0.0;
+    let mut total_amplitude = 0.0;
+
+    for octave in 0..NOISE_OCTAVES {
+        let frequency = (1 << octave) as f32 / NOISE_WAVELENGTH;
+        let amplitude = 1.0 / (1 << octave) as f32;
+
+        sum += value_noise(
+            seed.wrapping_add(octave as u64),
+            x * frequency,
+            z * frequency,
+        ) * amplitude;
+        total_amplitude += amplitude;
+    }
+
+    sum / total_amplitude
+}
+
+fn open_image(path: &str) -> Result<image::DynamicImage, HeightmapError> {
+    image::open(path).map_err(|source| HeightmapError::Image {
+        path: PathBuf::from(path),
+        source,
+    })
+}
+
+/// Returns the block whose color is the closest to the given one.
+fn closest_block(resource_dictionary: &ResourceDictionary, rgb: [u8; 3]) -> BlockId {
+    let distance = |color: Color| {
+        [
+            color.r as i32 - rgb[0] as i32,
+            color.g as i32 - rgb[1] as i32,
+            color.b as i32 - rgb[2] as i32,
+        ]
+        .iter()
+        .map(|channel| channel * channel)
+        .sum::<i32>()
+    };
+
+    resource_dictionary
+        .block_ids()
+        .iter()
+        .filter(|(name, _)| name.as_str() != BlockData::MISSING)
+        .map(|(_, id)| *id)
+        .min_by_key(|id| {
+            (
+                distance(resource_dictionary.get_block_data_from_id(*id).color),
+                *id,
+            )
+        })
+        .expect("No blocks are loaded")
+}
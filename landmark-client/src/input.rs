@@ -1,13 +1,60 @@
-use game_loop::winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode};
+use game_loop::winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+};
 use shipyard::*;
 
-use crate::camera::Camera;
+use crate::{
+    bindings::{Action, InputContext},
+    camera::Camera,
+    chat::Chat,
+    debug::DebugRenderState,
+    game_map::GameMap,
+    game_mode::GameMode,
+    inventory::InventoryClick,
+    menu::{Menu, Screen},
+    physics::slide_player,
+    prediction::apply_movement,
+    rendererer::Renderer,
+    screenshot::ScreenshotOptions,
+    settings::Settings,
+    world_map::WorldMap,
+};
 
 #[derive(Debug, Unique, Default)]
 pub struct InputState {
+    /// Decides which key bindings are active.
+    pub context: InputContext,
     pub cursor_in_window: bool,
     pub cursor_captured: bool,
+    /// Position of the cursor in pixels from the top left corner of the window.
+    pub cursor_position: glam::Vec2,
     pub fullscreen: bool,
+    /// Modifier keys currently held, used to select options of some actions.
+    pub modifiers: ModifiersState,
+    /// Set when resource packs should be reloaded, cleared once the reload is done.
+    pub reload_resources: bool,
+    /// Set when chunks the player didn't modify should be generated again with the current settings.
+    pub regenerate_world: bool,
+    /// Set when the profiler trace should be written to disk.
+    pub dump_profile: bool,
+    /// Set when a screenshot should be captured after the next frame.
+    pub screenshot: Option<ScreenshotOptions>,
+    /// Hotbar slot requested with number keys.
+    pub select_slot: Option<usize>,
+    /// Hotbar slots to move the selection by, accumulated from the mouse wheel.
+    pub scroll: i32,
+    /// Set when the selected block should be placed where the camera looks.
+    pub place_block: bool,
+    /// Set when the block the camera looks at should be broken.
+    pub break_block: bool,
+    /// Held while the left button is down, blocks with a break time only break while it's held.
+    pub breaking: bool,
+    /// Set when the last block edit should be undone.
+    pub undo: bool,
+    /// Set when the last undone block edit should be applied again.
+    pub redo: bool,
+    /// Mouse buttons pressed and released since the last update while the inventory is open.
+    pub inventory_clicks: Vec<InventoryClick>,
     pub forward: bool,
     pub backward: bool,
     pub leftward: bool,
@@ -16,40 +63,146 @@ pub struct InputState {
     pub downward: bool,
 }
 
-pub fn keyboard_input_sys(event: KeyboardInput, mut input_state: UniqueViewMut<InputState>) {
-    let state = event.state == ElementState::Pressed;
-
-    // Variable for virtual key code if input was not a scan code.
-    let mut keycode: Option<VirtualKeyCode> = None;
-
-    match event.scancode {
-        // W
-        17 => input_state.forward = state,
-        // S
-        31 => input_state.backward = state,
-        // A
-        30 => input_state.leftward = state,
-        // D
-        32 => input_state.rightward = state,
-        // LShift
-        42 => input_state.downward = state,
-        // Space
-        57 => input_state.upward = state,
-        // Other - process as virtual code
-        _ => keycode = event.virtual_keycode,
-    }
-
-    // Check virtual key codes.
-    if !state {
+impl InputState {
+    /// Releases all movement keys, used when another context takes over the keyboard.
+    pub fn stop_movement(&mut self) {
+        self.forward = false;
+        self.backward = false;
+        self.leftward = false;
+        self.rightward = false;
+        self.upward = false;
+        self.downward = false;
+    }
+}
+
+/// Messages scrolled by a single key press.
+const CHAT_SCROLL_STEP: i32 = 5;
+
+/// Adds characters typed while the chat is open to its input line.
+pub fn typed_character_sys(character: char, mut chat: UniqueViewMut<Chat>) {
+    chat.type_character(character);
+}
+
+pub fn keyboard_input_sys(
+    event: KeyboardInput,
+    mut input_state: UniqueViewMut<InputState>,
+    mut debug_state: UniqueViewMut<DebugRenderState>,
+    mut settings: UniqueViewMut<Settings>,
+    renderer: UniqueView<Renderer>,
+    mut chat: UniqueViewMut<Chat>,
+    mut menu: UniqueViewMut<Menu>,
+) {
+    let pressed = event.state == ElementState::Pressed;
+
+    if !pressed {
+        chat.key_released();
+    }
+
+    let Some(action) = settings
+        .controls
+        .context(input_state.context)
+        .action_for(&event)
+    else {
+        return;
+    };
+
+    // Movement keys are held, so releases have to be processed too.
+    match action {
+        Action::MoveForward => input_state.forward = pressed,
+        Action::MoveBackward => input_state.backward = pressed,
+        Action::MoveLeft => input_state.leftward = pressed,
+        Action::MoveRight => input_state.rightward = pressed,
+        Action::MoveUp => input_state.upward = pressed,
+        Action::MoveDown => input_state.downward = pressed,
+        _ => {}
+    }
+
+    if !pressed {
         return;
     }
 
-    if let Some(keycode) = keycode {
-        match keycode {
-            VirtualKeyCode::Escape => input_state.cursor_captured = false,
-            VirtualKeyCode::F11 => input_state.fullscreen = !input_state.fullscreen,
+    match action {
+        Action::ReleaseCursor => menu.open(Screen::Paused, &mut input_state),
+        Action::ToggleInventory => match menu.screen {
+            Screen::Playing => menu.open(Screen::Inventory, &mut input_state),
+            Screen::Inventory => menu.close(&mut input_state),
             _ => {}
+        },
+        Action::ToggleMap => match menu.screen {
+            Screen::Playing => menu.open(Screen::Map, &mut input_state),
+            Screen::Map => menu.close(&mut input_state),
+            _ => {}
+        },
+        Action::OpenConsole | Action::OpenChat => {
+            input_state.stop_movement();
+            input_state.context = InputContext::Console;
+            chat.open(if action == Action::OpenConsole {
+                "/"
+            } else {
+                ""
+            });
+        }
+        Action::Close => {
+            chat.close();
+
+            // Escape closes the console, or returns to the game from the pause menu
+            match menu.screen {
+                Screen::Playing => {
+                    if input_state.context == InputContext::Ui {
+                        input_state.cursor_captured = true;
+                    }
+
+                    input_state.context = InputContext::Gameplay;
+                }
+                Screen::Paused | Screen::Inventory | Screen::Map => menu.close(&mut input_state),
+                // other screens can only be left with their buttons
+                Screen::Dead | Screen::Title => input_state.context = InputContext::Ui,
+            }
+        }
+        Action::Submit => {
+            input_state.context = InputContext::Gameplay;
+            chat.submit();
+        }
+        Action::DeleteCharacter => chat.delete_character(),
+        Action::RecallOlder => chat.recall(true),
+        Action::RecallNewer => chat.recall(false),
+        Action::ScrollChatUp => chat.scroll(CHAT_SCROLL_STEP),
+        Action::ScrollChatDown => chat.scroll(-CHAT_SCROLL_STEP),
+        Action::ToggleChunkBorders => debug_state.chunk_borders = !debug_state.chunk_borders,
+        Action::ToggleWireframe => {
+            if renderer.capabilities.polygon_mode_line {
+                debug_state.wireframe = !debug_state.wireframe;
+            } else {
+                log::warn!("Wireframe mode is not supported by the graphics adapter");
+            }
+        }
+        Action::ReloadResources => input_state.reload_resources = true,
+        Action::CyclePresentMode => {
+            // Skip modes the surface doesn't support, Fifo is always available
+            let mut present_mode = settings.graphics.present_mode.next();
+            while !renderer.capabilities.supports_present_mode(present_mode) {
+                present_mode = present_mode.next();
+            }
+
+            settings.graphics.present_mode = present_mode;
+        }
+        Action::ToggleProfiler => debug_state.profiler = !debug_state.profiler,
+        Action::DumpProfile => input_state.dump_profile = true,
+        Action::Screenshot => {
+            input_state.screenshot = Some(ScreenshotOptions::from_modifiers(input_state.modifiers))
+        }
+        Action::SelectSlot(slot) => input_state.select_slot = Some(slot as usize),
+        Action::ToggleSpawnStats => debug_state.spawn_stats = !debug_state.spawn_stats,
+        Action::ToggleDrawStats => debug_state.draw_stats = !debug_state.draw_stats,
+        Action::ToggleFullscreen => input_state.fullscreen = !input_state.fullscreen,
+        Action::Undo if input_state.modifiers.ctrl() => {
+            if input_state.modifiers.shift() {
+                input_state.redo = true;
+            } else {
+                input_state.undo = true;
+            }
         }
+        _ => {}
     }
 }
 
@@ -86,20 +239,112 @@ pub fn mouse_input_sys(
     camera.pitch = new_pitch;
 }
 
-pub fn mouse_button_sys(button: &MouseButton, mut input_state: UniqueViewMut<InputState>) {
+pub fn mouse_button_sys(
+    (state, button): (ElementState, MouseButton),
+    mut input_state: UniqueViewMut<InputState>,
+    mut menu: UniqueViewMut<Menu>,
+    renderer: UniqueView<Renderer>,
+) {
     if !input_state.cursor_in_window {
         return;
     }
 
-    // left button
-    if *button == MouseButton::Left {
+    // dragging stacks in the inventory needs releases too
+    if menu.screen == Screen::Inventory {
+        let click = InventoryClick {
+            button,
+            pressed: state == ElementState::Pressed,
+            shift: input_state.modifiers.shift(),
+            cursor: input_state.cursor_position,
+        };
+        input_state.inventory_clicks.push(click);
+
+        return;
+    }
+
+    if state != ElementState::Pressed {
+        if button == MouseButton::Left {
+            input_state.breaking = false;
+        }
+
+        return;
+    }
+
+    // buttons of open screens take over the mouse
+    if menu.is_open() {
+        if button == MouseButton::Left {
+            let screen_size =
+                glam::Vec2::new(renderer.size.width as f32, renderer.size.height as f32);
+            menu.click(screen_size, input_state.cursor_position);
+        }
+
+        return;
+    }
+
+    if button == MouseButton::Right
+        && input_state.cursor_captured
+        && input_state.context == InputContext::Gameplay
+    {
+        input_state.place_block = true;
+    }
+
+    if button == MouseButton::Left
+        && input_state.cursor_captured
+        && input_state.context == InputContext::Gameplay
+    {
+        input_state.break_block = true;
+        input_state.breaking = true;
+    }
+
+    // left button returns to the game, but doesn't close the console
+    if button == MouseButton::Left && input_state.context != InputContext::Console {
         input_state.cursor_captured = true;
+        input_state.context = InputContext::Gameplay;
     }
 }
 
-pub fn move_player_sys(input_state: UniqueView<InputState>, mut camera: UniqueViewMut<Camera>) {
-    const MOVEMENT_SPEED: f32 = 0.05;
+pub fn mouse_wheel_sys(
+    delta: MouseScrollDelta,
+    mut input_state: UniqueViewMut<InputState>,
+    mut chat: UniqueViewMut<Chat>,
+    menu: UniqueView<Menu>,
+    mut world_map: UniqueViewMut<WorldMap>,
+) {
+    let y = match delta {
+        MouseScrollDelta::LineDelta(_, y) => y as f64,
+        MouseScrollDelta::PixelDelta(position) => position.y,
+    };
+
+    if chat.open {
+        chat.scroll(y.signum() as i32);
+        return;
+    }
 
+    // scrolling up zooms in
+    if menu.screen == Screen::Map {
+        world_map.zoom_by(y.signum() as i32);
+        return;
+    }
+
+    if !input_state.cursor_captured || input_state.context != InputContext::Gameplay {
+        return;
+    }
+
+    // scrolling down moves the selection to the right
+    if y > 0.0 {
+        input_state.scroll -= 1;
+    } else if y < 0.0 {
+        input_state.scroll += 1;
+    }
+}
+
+pub fn move_player_sys(
+    input_state: UniqueView<InputState>,
+    settings: UniqueView<Settings>,
+    game_mode: UniqueView<GameMode>,
+    game_map: UniqueView<GameMap>,
+    mut camera: UniqueViewMut<Camera>,
+) {
     if !input_state.cursor_captured {
         return;
     }
@@ -130,10 +375,25 @@ pub fn move_player_sys(input_state: UniqueView<InputState>, mut camera: UniqueVi
         movement.y -= 1.0;
     }
 
-    if movement != glam::Vec3::ZERO {
-        movement = movement.normalize() * MOVEMENT_SPEED;
-        movement = glam::Mat3::from_rotation_y(camera.yaw.to_radians()) * movement;
+    // without flight the up key jumps instead, see `player_gravity_sys`
+    if !game_mode.can_fly() {
+        movement.y = 0.0;
+    }
 
-        camera.eye += movement;
+    if movement == glam::Vec3::ZERO {
+        return;
     }
+
+    let eye = apply_movement(
+        camera.eye,
+        movement,
+        camera.yaw,
+        settings.physics.deterministic,
+    );
+
+    camera.eye = if game_mode.can_fly() {
+        eye
+    } else {
+        slide_player(&game_map, camera.eye, eye)
+    };
 }
@@ -0,0 +1,157 @@
+//! Smooth movement of entities mirrored from the server.
+//!
+//! Transforms arrive at the packet rate, which is far below the frame rate and irregular.
+//! Entities are drawn a short delay in the past instead, between the two snapshots around that
+//! moment. When snapshots stop arriving, entities keep moving at their last speed for a short time
+//! before they stop.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use landmark_protocol::replication::{ComponentData, Replicated};
+use shipyard::*;
+
+use crate::{settings::Settings, transform::Transform};
+
+/// Snapshots kept per entity, enough for a few seconds at the packet rate.
+const MAX_SNAPSHOTS: usize = 64;
+
+/// Transforms of an entity received from the server, oldest first.
+#[derive(Debug, Clone, Default, Component)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<(Instant, Transform)>,
+}
+
+impl SnapshotBuffer {
+    pub fn push(&mut self, received: Instant, transform: Transform) {
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+
+        self.snapshots.push_back((received, transform));
+    }
+
+    /// Returns the transform at `time`, extrapolated for at most `max_extrapolation` past the newest
+    /// snapshot. Snapshots which are no longer needed for later times are dropped.
+    pub fn sample(&mut self, time: Instant, max_extrapolation: Duration) -> Option<Transform> {
+        // keep the newest snapshot before `time` as the start of the interpolation
+        while self.snapshots.len() > 2 && self.snapshots[1].0 <= time {
+            self.snapshots.pop_front();
+        }
+
+        let (start, end) = match self.snapshots.len() {
+            0 => return None,
+            1 => return Some(self.snapshots[0].1),
+            _ => (self.snapshots[0], self.snapshots[1]),
+        };
+
+        if time <= start.0 {
+            return Some(start.1);
+        }
+
+        let span = end.0.saturating_duration_since(start.0).as_secs_f32();
+        if span <= 0.0 {
+            return Some(end.1);
+        }
+
+        // past the newest snapshot the factor exceeds 1, which continues the last movement
+        let limit = end.0 + max_extrapolation;
+        let t = time.min(limit).duration_since(start.0).as_secs_f32() / span;
+
+        Some(Transform {
+            translation: start.1.translation.lerp(end.1.translation, t),
+            rotation: if t <= 1.0 {
+                start.1.rotation.slerp(end.1.rotation, t)
+            } else {
+                end.1.rotation
+            },
+        })
+    }
+}
+
+/// Records transforms received from the server, the entity is drawn at them by `interpolation_sys`.
+pub fn push_snapshot(all_storages: &mut AllStorages, entity: EntityId, data: &ComponentData) {
+    let Some(transform) = Transform::from_data(data) else {
+        return;
+    };
+    let now = Instant::now();
+
+    let mut buffers = all_storages.borrow::<ViewMut<SnapshotBuffer>>().unwrap();
+    if let Ok(buffer) = (&mut buffers).get(entity) {
+        buffer.push(now, transform);
+        return;
+    }
+    drop(buffers);
+
+    let mut buffer = SnapshotBuffer::default();
+    buffer.push(now, transform);
+
+    all_storages.add_component(entity, (buffer, transform));
+}
+
+/// Moves mirrored entities to their transform at the configured delay in the past.
+pub fn interpolation_sys(
+    settings: UniqueView<Settings>,
+    mut buffers: ViewMut<SnapshotBuffer>,
+    mut transforms: ViewMut<Transform>,
+) {
+    let delay = Duration::from_millis(settings.network.interpolation_delay_ms.into());
+    let max_extrapolation = Duration::from_millis(settings.network.max_extrapolation_ms.into());
+
+    let Some(time) = Instant::now().checked_sub(delay) else {
+        return;
+    };
+
+    for (buffer, transform) in (&mut buffers, &mut transforms).iter() {
+        if let Some(sampled) = buffer.sample(time, max_extrapolation) {
+            *transform = sampled;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32) -> Transform {
+        Transform {
+            translation: glam::Vec3::new(x, 0.0, 0.0),
+            rotation: glam::Quat::IDENTITY,
+        }
+    }
+
+    fn sample_x(buffer: &mut SnapshotBuffer, time: Instant) -> f32 {
+        buffer
+            .sample(time, Duration::from_millis(100))
+            .unwrap()
+            .translation
+            .x
+    }
+
+    #[test]
+    fn interpolates_between_snapshots() {
+        let start = Instant::now();
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(start, at(0.0));
+        buffer.push(start + Duration::from_millis(100), at(1.0));
+        buffer.push(start + Duration::from_millis(200), at(3.0));
+
+        assert_eq!(sample_x(&mut buffer, start), 0.0);
+        assert!((sample_x(&mut buffer, start + Duration::from_millis(50)) - 0.5).abs() < 1e-4);
+        assert!((sample_x(&mut buffer, start + Duration::from_millis(150)) - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn extrapolates_briefly_after_the_last_snapshot() {
+        let start = Instant::now();
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(start, at(0.0));
+        buffer.push(start + Duration::from_millis(100), at(1.0));
+
+        assert!((sample_x(&mut buffer, start + Duration::from_millis(150)) - 1.5).abs() < 1e-4);
+        // stops once the extrapolation limit is reached
+        assert!((sample_x(&mut buffer, start + Duration::from_millis(1000)) - 2.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,548 @@
+use std::ops::Range;
+
+use game_loop::winit::event::MouseButton;
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    coords,
+    drops::{drop_model_constructor, ItemDrop},
+    edit_history::{apply_changes, EditHistory},
+    game_map::GameMap,
+    game_mode::GameMode,
+    input::InputState,
+    item::ItemData,
+    loader::ResourceDictionary,
+    menu::{Menu, Screen},
+    model::{MissingModel, UpdatedModel},
+    player::LocalPlayer,
+    rendererer::Renderer,
+};
+
+/// Maximum distance between the camera and a block the player can place blocks against.
+const REACH: f64 = 6.0;
+/// Size of an inventory slot in pixels.
+pub const SLOT_SIZE: f32 = 40.0;
+pub const SLOT_GAP: f32 = 4.0;
+/// Distance between the hotbar and the bottom of the window.
+pub const HOTBAR_MARGIN: f32 = 12.0;
+/// Space between the hotbar and the other slots while the inventory is open.
+const INVENTORY_GAP: f32 = 16.0;
+/// Space between the slots and the edges of the inventory background.
+pub const INVENTORY_PADDING: f32 = 12.0;
+/// Speed of items thrown out of the inventory, in blocks per second.
+const THROW_SPEED: f64 = 6.0;
+
+/// Items of the same kind occupying a single inventory slot, the server saves them as they are.
+pub use landmark_protocol::message::ItemStack;
+
+#[derive(Debug, Clone, Component)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    /// Index of the selected hotbar slot.
+    selected: usize,
+    /// Stack picked up with the cursor while the inventory screen is open.
+    held: Option<ItemStack>,
+    /// Slot the held stack was picked up from while the mouse button is down,
+    /// releasing the button over another slot drops the stack there.
+    drag_source: Option<usize>,
+}
+
+impl Inventory {
+    pub const SLOT_COUNT: usize = 36;
+    /// The first slots of the inventory form the hotbar.
+    pub const HOTBAR_SIZE: usize = 9;
+
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; Self::SLOT_COUNT],
+            selected: 0,
+            held: None,
+            drag_source: None,
+        }
+    }
+
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots
+    }
+
+    /// Replaces the slots with ones saved by the server, slots the inventory doesn't have are dropped.
+    pub fn restore(&mut self, mut slots: Vec<Option<ItemStack>>) {
+        slots.resize(Self::SLOT_COUNT, None);
+        self.slots = slots;
+    }
+
+    pub fn hotbar(&self) -> &[Option<ItemStack>] {
+        &self.slots[..Self::HOTBAR_SIZE]
+    }
+
+    pub fn held(&self) -> Option<&ItemStack> {
+        self.held.as_ref()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, slot: usize) {
+        if slot < Self::HOTBAR_SIZE {
+            self.selected = slot;
+        }
+    }
+
+    /// Moves the selection by `delta` slots, wrapping around the ends of the hotbar.
+    pub fn scroll(&mut self, delta: i32) {
+        self.selected =
+            (self.selected as i32 + delta).rem_euclid(Self::HOTBAR_SIZE as i32) as usize;
+    }
+
+    /// Adds items, filling existing stacks before empty slots.
+    /// Returns the amount of items which didn't fit.
+    pub fn add(&mut self, item: &ItemData, count: u32) -> u32 {
+        self.insert(0..Self::SLOT_COUNT, &item.name, item.max_stack_size, count)
+    }
+
+    /// Adds items to the slots in `range` like `add`.
+    fn insert(
+        &mut self,
+        range: Range<usize>,
+        item: &str,
+        max_stack_size: u32,
+        mut count: u32,
+    ) -> u32 {
+        let max_stack_size = max_stack_size.max(1);
+
+        for stack in self.slots[range.clone()].iter_mut().flatten() {
+            if count == 0 {
+                break;
+            }
+
+            if stack.item == item {
+                let added = count.min(max_stack_size.saturating_sub(stack.count));
+                stack.count += added;
+                count -= added;
+            }
+        }
+
+        for slot in self.slots[range].iter_mut() {
+            if count == 0 {
+                break;
+            }
+
+            if slot.is_none() {
+                let added = count.min(max_stack_size);
+                *slot = Some(ItemStack {
+                    item: item.to_string(),
+                    count: added,
+                });
+                count -= added;
+            }
+        }
+
+        count
+    }
+
+    pub fn selected_stack(&self) -> Option<&ItemStack> {
+        self.slots[self.selected].as_ref()
+    }
+
+    /// Removes a single item from the selected stack and returns its name.
+    pub fn take_selected(&mut self) -> Option<String> {
+        let slot = &mut self.slots[self.selected];
+        let stack = slot.as_mut()?;
+        let item = stack.item.clone();
+
+        stack.count -= 1;
+        if stack.count == 0 {
+            *slot = None;
+        }
+
+        Some(item)
+    }
+
+    /// Picks up the stack in the slot if nothing is held, otherwise puts the held stack into the slot.
+    /// Held items are added to a stack of the same item as long as it has space, a different item is swapped.
+    pub fn click_slot(&mut self, idx: usize, max_stack_size: impl Fn(&str) -> u32) {
+        let slot = &mut self.slots[idx];
+
+        match (self.held.as_mut(), slot.as_mut()) {
+            (None, _) => self.held = slot.take(),
+            (Some(held), Some(stack)) if held.item == stack.item => {
+                let space = max_stack_size(&stack.item)
+                    .max(1)
+                    .saturating_sub(stack.count);
+                let moved = held.count.min(space);
+
+                stack.count += moved;
+                held.count -= moved;
+
+                if held.count == 0 {
+                    self.held = None;
+                }
+            }
+            (Some(_), _) => std::mem::swap(&mut self.held, slot),
+        }
+    }
+
+    /// Picks up half of the stack in the slot, rounded up, if nothing is held.
+    /// Otherwise puts a single held item into the slot, if it's empty or has space for the same item.
+    pub fn split_slot(&mut self, idx: usize, max_stack_size: impl Fn(&str) -> u32) {
+        let slot = &mut self.slots[idx];
+
+        let Some(held) = self.held.as_mut() else {
+            let Some(stack) = slot.as_mut() else {
+                return;
+            };
+
+            let taken = stack.count.div_ceil(2);
+            stack.count -= taken;
+            self.held = Some(ItemStack {
+                item: stack.item.clone(),
+                count: taken,
+            });
+
+            if stack.count == 0 {
+                *slot = None;
+            }
+
+            return;
+        };
+
+        match slot {
+            None => {
+                *slot = Some(ItemStack {
+                    item: held.item.clone(),
+                    count: 1,
+                })
+            }
+            Some(stack)
+                if stack.item == held.item && stack.count < max_stack_size(&stack.item).max(1) =>
+            {
+                stack.count += 1
+            }
+            Some(_) => return,
+        }
+
+        held.count -= 1;
+        if held.count == 0 {
+            self.held = None;
+        }
+    }
+
+    /// Moves the stack in the slot between the hotbar and the rest of the inventory, as far as it fits.
+    pub fn quick_move(&mut self, idx: usize, max_stack_size: impl Fn(&str) -> u32) {
+        let Some(stack) = self.slots[idx].take() else {
+            return;
+        };
+
+        let target = if idx < Self::HOTBAR_SIZE {
+            Self::HOTBAR_SIZE..Self::SLOT_COUNT
+        } else {
+            0..Self::HOTBAR_SIZE
+        };
+
+        let remaining = self.insert(
+            target,
+            &stack.item,
+            max_stack_size(&stack.item),
+            stack.count,
+        );
+
+        if remaining > 0 {
+            self.slots[idx] = Some(ItemStack {
+                count: remaining,
+                ..stack
+            });
+        }
+    }
+
+    /// Removes up to `count` held items, so they can be thrown.
+    pub fn take_held(&mut self, count: u32) -> Option<ItemStack> {
+        let held = self.held.as_mut()?;
+        let taken = count.min(held.count);
+
+        held.count -= taken;
+        let stack = ItemStack {
+            item: held.item.clone(),
+            count: taken,
+        };
+
+        if held.count == 0 {
+            self.held = None;
+        }
+
+        Some(stack)
+    }
+
+    /// Puts the held stack back into the inventory, returns the items which didn't fit.
+    pub fn return_held(&mut self, max_stack_size: impl Fn(&str) -> u32) -> Option<ItemStack> {
+        let held = self.held.take()?;
+        let remaining = self.insert(
+            0..Self::SLOT_COUNT,
+            &held.item,
+            max_stack_size(&held.item),
+            held.count,
+        );
+
+        (remaining > 0).then_some(ItemStack {
+            count: remaining,
+            ..held
+        })
+    }
+}
+
+/// Returns the corners of a slot. The hotbar is at the bottom of the window,
+/// the other slots are in rows above it, which are only visible while the inventory is open.
+pub fn slot_rect(screen_size: glam::Vec2, idx: usize) -> (glam::Vec2, glam::Vec2) {
+    let columns = Inventory::HOTBAR_SIZE;
+    let rows = (Inventory::SLOT_COUNT - Inventory::HOTBAR_SIZE) / columns;
+    let row_width = columns as f32 * SLOT_SIZE + (columns - 1) as f32 * SLOT_GAP;
+    let hotbar_top = screen_size.y - HOTBAR_MARGIN - SLOT_SIZE;
+
+    let (column, top) = if idx < Inventory::HOTBAR_SIZE {
+        (idx, hotbar_top)
+    } else {
+        let row = (idx - Inventory::HOTBAR_SIZE) / columns;
+        let rows_below = (rows - 1 - row) as f32;
+
+        (
+            (idx - Inventory::HOTBAR_SIZE) % columns,
+            hotbar_top - INVENTORY_GAP - SLOT_SIZE - rows_below * (SLOT_SIZE + SLOT_GAP),
+        )
+    };
+
+    let min = glam::Vec2::new(
+        (screen_size.x - row_width) / 2.0 + column as f32 * (SLOT_SIZE + SLOT_GAP),
+        top,
+    );
+
+    (min, min + SLOT_SIZE)
+}
+
+/// Returns the corners of the background of the open inventory, which encloses all slots.
+pub fn inventory_rect(screen_size: glam::Vec2) -> (glam::Vec2, glam::Vec2) {
+    let (min, _) = slot_rect(screen_size, Inventory::HOTBAR_SIZE);
+    let (_, max) = slot_rect(screen_size, Inventory::HOTBAR_SIZE - 1);
+
+    (min - INVENTORY_PADDING, max + INVENTORY_PADDING)
+}
+
+/// Returns the index of the slot under the cursor.
+pub fn slot_at(screen_size: glam::Vec2, cursor: glam::Vec2) -> Option<usize> {
+    (0..Inventory::SLOT_COUNT).find(|idx| {
+        let (min, max) = slot_rect(screen_size, *idx);
+
+        cursor.cmpge(min).all() && cursor.cmplt(max).all()
+    })
+}
+
+fn max_stack_size(resource_dictionary: &ResourceDictionary, item: &str) -> u32 {
+    resource_dictionary
+        .find_item_id(item)
+        .map(|id| resource_dictionary.get_item_data_from_id(id).max_stack_size)
+        .unwrap_or(1)
+}
+
+/// Mouse button pressed or released while the inventory screen is open.
+#[derive(Debug, Clone, Copy)]
+pub struct InventoryClick {
+    pub button: MouseButton,
+    pub pressed: bool,
+    /// Whether Shift was held, which moves whole stacks.
+    pub shift: bool,
+    pub cursor: glam::Vec2,
+}
+
+/// Throws items out of the inventory in the direction the camera looks.
+fn throw(
+    stack: ItemStack,
+    camera: &Camera,
+    resource_dictionary: &ResourceDictionary,
+    entities: &mut EntitiesViewMut,
+    drops: &mut ViewMut<ItemDrop>,
+    updated_models: &mut ViewMut<UpdatedModel>,
+) {
+    let direction = camera.look_direction();
+    let drop = ItemDrop::thrown(stack.item, stack.count, camera.eye, direction * THROW_SPEED);
+
+    match drop_model_constructor(resource_dictionary, &drop.item) {
+        Some(model_constructor) => {
+            entities.add_entity(
+                (&mut *drops, &mut *updated_models),
+                (drop, UpdatedModel(model_constructor)),
+            );
+        }
+        // items without a block have no model yet, they can still be picked up again
+        None => {
+            entities.add_entity(&mut *drops, drop);
+        }
+    }
+}
+
+/// Applies clicks on the open inventory screen: picking up, dragging, splitting, quick-moving
+/// and throwing stacks. Held items are put back when the screen is closed.
+#[allow(clippy::too_many_arguments)]
+pub fn inventory_screen_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    menu: UniqueView<Menu>,
+    renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    players: View<LocalPlayer>,
+    mut inventories: ViewMut<Inventory>,
+    mut entities: EntitiesViewMut,
+    mut drops: ViewMut<ItemDrop>,
+    mut updated_models: ViewMut<UpdatedModel>,
+) {
+    let clicks = std::mem::take(&mut input_state.inventory_clicks);
+
+    let Some((_, inventory)) = (&players, &mut inventories).iter().next() else {
+        return;
+    };
+
+    let max_stack_size = |item: &str| max_stack_size(&resource_dictionary, item);
+    let mut throw_stack = |stack: ItemStack| {
+        throw(
+            stack,
+            &camera,
+            &resource_dictionary,
+            &mut entities,
+            &mut drops,
+            &mut updated_models,
+        )
+    };
+
+    if menu.screen != Screen::Inventory {
+        inventory.drag_source = None;
+
+        if let Some(remaining) = inventory.return_held(max_stack_size) {
+            throw_stack(remaining);
+        }
+
+        return;
+    }
+
+    let screen_size = glam::Vec2::new(renderer.size.width as f32, renderer.size.height as f32);
+
+    for click in clicks {
+        let slot = slot_at(screen_size, click.cursor);
+        let (min, max) = inventory_rect(screen_size);
+        let outside = click.cursor.cmplt(min).any() || click.cursor.cmpge(max).any();
+
+        match (click.button, click.pressed, slot) {
+            (MouseButton::Left, true, Some(idx)) if click.shift => {
+                inventory.quick_move(idx, max_stack_size)
+            }
+            (MouseButton::Left, true, Some(idx)) => {
+                if inventory.held.is_none() {
+                    inventory.drag_source = Some(idx);
+                }
+
+                inventory.click_slot(idx, max_stack_size);
+            }
+            (MouseButton::Left, true, None) if outside => {
+                if let Some(stack) = inventory.take_held(u32::MAX) {
+                    throw_stack(stack);
+                }
+            }
+            // a stack dragged onto another slot is dropped there, released over its own slot it stays held
+            (MouseButton::Left, false, _) => {
+                let Some(source) = inventory.drag_source.take() else {
+                    continue;
+                };
+
+                match slot {
+                    Some(idx) if idx != source => inventory.click_slot(idx, max_stack_size),
+                    None if outside => {
+                        if let Some(stack) = inventory.take_held(u32::MAX) {
+                            throw_stack(stack);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (MouseButton::Right, true, Some(idx)) => inventory.split_slot(idx, max_stack_size),
+            (MouseButton::Right, true, None) if outside => {
+                if let Some(stack) = inventory.take_held(1) {
+                    throw_stack(stack);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Applies hotbar selection requests and places the block of the selected item where the camera looks.
+/// Placed blocks are only taken from the stack in survival.
+#[allow(clippy::too_many_arguments)]
+pub fn inventory_input_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    camera: UniqueView<Camera>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    game_mode: UniqueView<GameMode>,
+    mut game_map: UniqueViewMut<GameMap>,
+    players: View<LocalPlayer>,
+    mut inventories: ViewMut<Inventory>,
+    mut missing_models: ViewMut<MissingModel>,
+    mut history: UniqueViewMut<EditHistory>,
+) {
+    let select_slot = input_state.select_slot.take();
+    let scroll = std::mem::take(&mut input_state.scroll);
+    let place_block = std::mem::take(&mut input_state.place_block);
+
+    let Some((_, inventory)) = (&players, &mut inventories).iter().next() else {
+        return;
+    };
+
+    if let Some(slot) = select_slot {
+        inventory.select(slot);
+    }
+
+    if scroll != 0 {
+        inventory.scroll(scroll);
+    }
+
+    if !place_block {
+        return;
+    }
+
+    let Some(block_id) = inventory
+        .selected_stack()
+        .and_then(|stack| resource_dictionary.find_item_id(&stack.item))
+        .and_then(|id| resource_dictionary.get_item_data_from_id(id).places_block)
+        .and_then(|block| resource_dictionary.find_block_id(&block))
+    else {
+        return;
+    };
+
+    let Some(hit) = game_map.raycast(camera.eye, camera.look_direction(), REACH) else {
+        return;
+    };
+
+    // the ray started inside of a block
+    let Some(target) = hit.adjacent() else {
+        return;
+    };
+
+    // the block would be placed into the camera
+    if target == coords::block_containing(camera.eye) || game_map.get_block_world(target).is_some()
+    {
+        return;
+    }
+
+    let transaction = apply_changes(
+        &mut game_map,
+        &mut missing_models,
+        [(target, Some(block_id))],
+    );
+    if transaction.is_empty() {
+        return;
+    }
+
+    if game_mode.uses_up_blocks() {
+        inventory.take_selected();
+    }
+    if game_mode.records_edits() {
+        history.record(transaction);
+    }
+}
@@ -0,0 +1,24 @@
+use crate::lights::LightSource;
+
+pub type ItemId = u32;
+
+fn default_max_stack_size() -> u32 {
+    64
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ItemData {
+    pub name: String,
+    /// Name of a file in `textures/items` without the extension.
+    /// Items without an icon are drawn as the block they place.
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default = "default_max_stack_size")]
+    pub max_stack_size: u32,
+    /// Name of the block placed when the item is used.
+    #[serde(default)]
+    pub places_block: Option<String>,
+    /// Light given off while the item is held or lies on the ground, like a torch.
+    #[serde(default)]
+    pub light: Option<LightSource>,
+}
@@ -1,80 +1,678 @@
+mod adapter;
+#[cfg(feature = "anvil")]
+mod anvil;
+mod atlas;
+mod bench;
+mod bindings;
+mod biome;
 mod block;
 mod camera;
+mod capabilities;
+mod chat;
+mod chunk_arena;
+mod chunk_loader;
+mod chunk_storage;
 mod color;
+mod commands;
+mod connection;
+mod coords;
+mod cracks;
+mod debug;
+mod debug_view;
+mod drops;
+mod edit_history;
+mod error;
+mod export;
+mod falling;
+mod fixed;
+mod font;
 mod game_map;
+mod game_mode;
+mod hdr;
+mod health;
+mod heightmap;
 mod input;
+mod interpolation;
+mod inventory;
+mod item;
+mod lights;
 mod loader;
+mod menu;
 mod mesher;
+mod minimap;
+mod mipmap;
+mod mobs;
 mod model;
+#[cfg(feature = "anvil")]
+mod nbt;
+mod particles;
+mod physics;
+mod player;
+mod plugins;
+mod portal;
+mod prediction;
+mod priority;
+mod profiler;
+mod random_tick;
+mod region;
+mod render_cache;
 mod rendererer;
+mod replay;
+mod replication;
+mod save;
+mod schematic;
+mod screenshot;
+mod settings;
+mod shader;
+mod shadows;
+mod spawning;
+mod sun;
+mod telemetry;
+mod teleport;
 mod texture;
+mod tick;
 mod transform;
+mod ui;
+mod vox;
+mod water;
+mod weather;
+mod world_edit;
+mod world_map;
+mod world_store;
+mod worldgen;
 
-use std::sync::Arc;
+use std::{path::Path, sync::Arc, time::Instant};
 
-use camera::update_camera_sys;
+use block::BlockData;
+use camera::{update_camera_sys, Camera};
+use chat::{chat_sys, expire_chat_bubbles_sys, Chat, ChatBus, ChatFilters};
+use chunk_loader::{chunk_loading_sys, generated_chunks_sys, server_chunks_sys, ServerChunks};
+use commands::{client_commands, is_server_command};
+use connection::{server_link_sys, ConnectionError, NetworkSimulation, Permissions, ServerLink};
+use cracks::update_crack_model_sys;
+use debug::{update_frustum_model_sys, update_render_stats_sys, DebugRenderState, RenderStats};
+use debug_view::{move_debug_view_sys, update_debug_view_sys, DebugView};
+use drops::{block_breaking_sys, item_drops_sys, update_drop_models_sys, BreakingProgress};
+use edit_history::{edit_history_sys, EditHistory};
+pub use error::LandmarkError;
+use falling::{falling_blocks_sys, update_falling_block_models_sys};
 use game_loop::{
     game_loop,
     winit::{
+        dpi::PhysicalSize,
         event::{DeviceEvent, Event, WindowEvent},
-        event_loop::EventLoop,
-        window::{CursorGrabMode, Fullscreen, Window, WindowBuilder},
+        event_loop::{EventLoop, EventLoopWindowTarget},
+        window::{CursorGrabMode, Fullscreen, Icon, Window, WindowBuilder},
     },
 };
 use game_map::GameMap;
-use loader::ResourceDictionary;
+use hdr::update_bloom_sys;
+use health::{damage_sys, DamageEvents, Health};
+use interpolation::interpolation_sys;
+use inventory::{inventory_input_sys, inventory_screen_sys, Inventory};
+use landmark_common::{
+    command::{CommandError, CommandRegistry},
+    secure::Keypair,
+};
+use landmark_server::ChunkStore;
+use lights::update_point_lights_sys;
+use loader::{reload_resources_sys, PinnedPack, ResourceDictionary, ResourcePacks};
+use menu::{menu_action_sys, player_death_sys, Menu, MenuAction, Screen};
 use mesher::chunk_mesher_sys;
-use model::update_models_sys;
+use minimap::{update_minimap_sys, Minimap};
+use mobs::{mob_ai_sys, MobDefinitions};
+use model::{update_chunk_transforms_sys, update_models_sys};
+use particles::{
+    footstep_dust_sys, particles_sys, update_particle_instances_sys, Footsteps, Particles,
+};
+use physics::{
+    entity_collision_sys, fall_damage_sys, player_gravity_sys, FallTracker, PlayerMotion,
+    SpatialIndex,
+};
+use player::LocalPlayer;
+use plugins::{plugins_tick_sys, Plugins};
+use portal::{portal_sys, PortalTracker};
+use prediction::{reconcile_player_sys, Prediction};
+use profiler::{dump_profile_sys, Profiler};
+use replay::{replay_input_sys, InputReplay};
+pub use replay::{ReplayError, ReplayMode};
+use replication::{remote_players_sys, replication_sys, Replication};
+use save::{autosave_sys, WorldSave};
+use screenshot::screenshot_sys;
+use settings::{FullscreenMode, Settings};
+use shadows::update_shadow_map_sys;
 use shipyard::*;
+use spawning::{mob_spawning_sys, MobSpawner, SpawnRules};
+use sun::{sun_sys, SunCycle};
+use telemetry::{record_telemetry_sys, Telemetry};
+use teleport::{teleport_sys, PendingTeleport};
+use tick::TickControl;
+use ui::update_hud_sys;
+use water::{update_fluid_models_sys, update_water_sys};
+use weather::{update_sky_sys, weather_sys, WeatherState};
+use world_edit::WorldEditor;
+use world_map::{place_world_map_tiles_sys, update_world_map_sys, WorldMap};
+use world_store::WorldStore;
+use worldgen::{
+    regenerate_world_sys, watch_worldgen_sys, PlacedStructure, Terrain, WorldGenerator,
+    WorldgenWatcher,
+};
 
 use input::*;
 use rendererer::*;
 
+const WINDOW_TITLE: &str = "Landmark";
+/// Rate of the fixed update step, simulations use it as their time step.
+const UPDATES_PER_SECOND: u32 = 240;
+
 #[derive(Debug)]
 struct Game {
     pub world: World,
+    /// Commands typed into the chat, they get access to the whole world.
+    commands: CommandRegistry<World>,
+    /// Set when the player chose to quit the game from a menu.
+    exit_requested: bool,
+    /// Whether the cursor is currently grabbed by the window, follows the input state.
+    cursor_captured: bool,
 }
 
 impl Game {
-    pub fn init(window: &Window) -> Self {
+    /// Creates the game, joining the server at `server` or starting a singleplayer server if it's None.
+    pub fn init(window: &Window, server: Option<&str>) -> Result<Self, LandmarkError> {
+        let mut settings = Settings::load();
+        // mods register their blocks before resources are loaded
+        let plugins = Plugins::load();
+        let world_dir = Path::new(WorldSave::DIR);
+        let (resource_packs, resource_dictionary) =
+            load_resources(&settings, Some(world_dir), plugins.registered_blocks())?;
+
+        let (renderer, camera) = pollster::block_on(Renderer::init(
+            window,
+            &mut settings,
+            &resource_packs,
+            &resource_dictionary,
+        ))?;
+        let telemetry = Telemetry::new(settings.telemetry.enabled, &renderer.capabilities);
+        let world_save = WorldSave::open(world_dir);
+
+        window.set_window_icon(load_window_icon(&resource_packs));
+
+        let simulation = NetworkSimulation::default();
+        let link = match server {
+            Some(address) => Keypair::load_or_generate(Path::new(connection::IDENTITY_KEY_PATH))
+                .map_err(ConnectionError::from)
+                .and_then(|identity| {
+                    connection::connect(address, &settings.player_name, &identity, &simulation)
+                })
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to join {address}: {e}");
+                    ServerLink::default()
+                }),
+            None => {
+                let chunks = WorldStore::open(
+                    world_save.share(),
+                    &settings.world,
+                    resource_dictionary.clone(),
+                    Plugins::load(),
+                );
+
+                ServerLink::singleplayer(&settings.player_name, world_dir, chunks).unwrap_or_else(
+                    |e| {
+                        log::error!("Failed to start the singleplayer server: {e}");
+                        ServerLink::default()
+                    },
+                )
+            }
+        };
+
+        let game = Self::with_renderer(
+            settings,
+            resource_packs,
+            resource_dictionary,
+            renderer,
+            camera,
+            telemetry,
+            plugins,
+            world_save,
+        );
+
+        game.world
+            .borrow::<UniqueViewMut<ChatBus>>()
+            .unwrap()
+            .connected = link.is_connected();
+        game.world
+            .borrow::<UniqueViewMut<Prediction>>()
+            .unwrap()
+            .connected = link.is_connected();
+        game.world
+            .borrow::<UniqueViewMut<ServerChunks>>()
+            .unwrap()
+            .connected = link.is_connected();
+        game.world
+            .borrow::<UniqueViewMut<PendingTeleport>>()
+            .unwrap()
+            .connected = link.is_connected();
+        if link.is_connected() {
+            *game.world.borrow::<UniqueViewMut<Permissions>>().unwrap() = Permissions::GUEST;
+        }
+        // the server decides where its players are, including the one of singleplayer
+        if let Some(player) = link.player.as_ref() {
+            game.world
+                .borrow::<UniqueViewMut<PendingTeleport>>()
+                .unwrap()
+                .request(glam::DVec3::from_array(player.position));
+        }
+        // players who never synced their inventory keep the starting one
+        if let Some(player) = link
+            .player
+            .as_ref()
+            .filter(|player| !player.inventory.is_empty())
+        {
+            let (players, mut inventories) = game
+                .world
+                .borrow::<(View<LocalPlayer>, ViewMut<Inventory>)>()
+                .unwrap();
+
+            if let Some((_, inventory)) = (&players, &mut inventories).iter().next() {
+                inventory.restore(player.inventory.clone());
+            }
+        }
+
+        *game.world.borrow::<UniqueViewMut<ServerLink>>().unwrap() = link;
+        // the connection applies the conditions set by the `/netsim` command
+        *game
+            .world
+            .borrow::<UniqueViewMut<NetworkSimulation>>()
+            .unwrap() = simulation;
+
+        Ok(game)
+    }
+
+    /// Creates the game rendering into an offscreen texture, using default settings.
+    /// Mods, the world save and the singleplayer server are not loaded, so the rendered frames only
+    /// depend on the resources.
+    pub fn init_headless(size: PhysicalSize<u32>) -> Result<Self, LandmarkError> {
+        let settings = Settings::default();
+        let (resource_packs, resource_dictionary) = load_resources(&settings, None, Vec::new())?;
+
+        let (renderer, camera) = pollster::block_on(Renderer::init_headless(
+            size,
+            &resource_packs,
+            &resource_dictionary,
+        ))?;
+        let telemetry = Telemetry::new(settings.telemetry.enabled, &renderer.capabilities);
+
+        Ok(Self::with_renderer(
+            settings,
+            resource_packs,
+            resource_dictionary,
+            renderer,
+            camera,
+            telemetry,
+            Plugins::new(),
+            WorldSave::in_memory(),
+        ))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn with_renderer(
+        settings: Settings,
+        resource_packs: ResourcePacks,
+        resource_dictionary: ResourceDictionary,
+        mut renderer: Renderer,
+        mut camera: Camera,
+        telemetry: Telemetry,
+        plugins: Plugins,
+        world_save: WorldSave,
+    ) -> Self {
         let mut world = World::new();
+        let game_map = GameMap::new();
+        let world_map = WorldMap::open(world_save.map_dir());
+        camera.eye = world_save.spawn_point();
 
-        let resource_dictionary = ResourceDictionary::new();
+        // there is no way to obtain items yet, so the player starts with a stack of each
+        let mut inventory = Inventory::new();
+        for id in resource_dictionary.item_ids() {
+            let item = resource_dictionary.get_item_data_from_id(id);
+            inventory.add(&item, item.max_stack_size);
+        }
+        world.add_entity((LocalPlayer, inventory, Health::full(Health::PLAYER_MAX)));
 
-        let (renderer, camera) = pollster::block_on(Renderer::init(window));
+        let terrain = Terrain::from_settings(&settings.world, &resource_dictionary);
+        let structures = PlacedStructure::from_settings(&settings.world, &resource_dictionary);
+        renderer.update_entity_models(&resource_dictionary);
 
-        let game_map = GameMap::new_test(&mut world);
+        let spawn_rules = SpawnRules::load(&resource_packs).unwrap_or_else(|e| {
+            log::error!("{e}, mobs will not spawn");
+            SpawnRules::default()
+        });
+        let mob_definitions = MobDefinitions::load(&resource_packs).unwrap_or_else(|e| {
+            log::error!("{e}, mobs use default properties");
+            MobDefinitions::default()
+        });
 
+        world.add_unique(resource_packs);
         world.add_unique(resource_dictionary);
         world.add_unique(renderer);
         world.add_unique(camera);
         world.add_unique(game_map);
+        world.add_unique(WorldGenerator::new(terrain, structures));
+        world.add_unique(WorldgenWatcher::new(&settings.world));
         world.add_unique(InputState::default());
+        world.add_unique(InputReplay::default());
+        world.add_unique(TickControl::default());
+        world.add_unique(PendingTeleport::default());
+        world.add_unique(Permissions::default());
+        world.add_unique(PortalTracker::default());
+        world.add_unique(WeatherState::default());
+        world.add_unique(SunCycle::default());
+        world.add_unique(DebugRenderState::default());
+        world.add_unique(RenderStats::default());
+        world.add_unique(settings.game_mode);
+        world.add_unique(settings);
+        world.add_unique(FrameLimiter::new());
+        world.add_unique(Profiler::new());
+        world.add_unique(MobSpawner::new(spawn_rules));
+        world.add_unique(mob_definitions);
+        world.add_unique(ChatFilters::default());
+        world.add_unique(Chat::default());
+        world.add_unique(ChatBus::default());
+        world.add_unique(telemetry);
+        world.add_unique(Menu::default());
+        world.add_unique(plugins);
+        world.add_unique(SpatialIndex::default());
+        world.add_unique(world_save);
+        world.add_unique(NetworkSimulation::default());
+        world.add_unique(Prediction::default());
+        world.add_unique(ServerChunks::default());
+        world.add_unique(ServerLink::default());
+        world.add_unique(Replication::default());
+        world.add_unique(DamageEvents::default());
+        world.add_unique(FallTracker::default());
+        world.add_unique(PlayerMotion::default());
+        world.add_unique(BreakingProgress::default());
+        world.add_unique(Minimap::new());
+        world.add_unique(world_map);
+        world.add_unique(WorldEditor::default());
+        world.add_unique(EditHistory::default());
+        world.add_unique(Particles::default());
+        world.add_unique(Footsteps::default());
 
+        // keeps running while the simulation is frozen, so commands still work and chunks keep loading
+        Workload::new("io")
+            .with_system(server_link_sys)
+            .with_system(chat_sys)
+            .with_system(expire_chat_bubbles_sys)
+            .with_system(reload_resources_sys)
+            .with_system(watch_worldgen_sys)
+            .with_system(regenerate_world_sys)
+            .with_system(autosave_sys)
+            .with_system(chunk_loading_sys)
+            .with_system(generated_chunks_sys)
+            .with_system(server_chunks_sys)
+            .with_system(teleport_sys)
+            .with_system(move_debug_view_sys)
+            .with_system(dump_profile_sys)
+            .add_to_world(&world)
+            .unwrap();
+
+        // the simulation, runs as many times per fixed update as the `TickControl` decides
         Workload::new("update")
+            .with_system(replay_input_sys)
+            .with_system(replication_sys)
+            .with_system(interpolation_sys)
+            .with_system(remote_players_sys)
             .with_system(move_player_sys)
+            .with_system(player_gravity_sys)
+            .with_system(reconcile_player_sys)
+            .with_system(player_death_sys)
+            .with_system(fall_damage_sys)
+            .with_system(portal_sys)
+            .with_system(damage_sys)
+            .with_system(inventory_input_sys)
+            .with_system(inventory_screen_sys)
+            .with_system(block_breaking_sys)
+            .with_system(edit_history_sys)
+            .with_system(falling_blocks_sys)
+            .with_system(footstep_dust_sys)
+            .with_system(weather_sys)
+            .with_system(sun_sys)
+            .with_system(particles_sys)
+            .with_system(item_drops_sys)
+            .with_system(mob_spawning_sys)
+            .with_system(mob_ai_sys)
+            .with_system(entity_collision_sys)
+            .with_system(plugins_tick_sys)
+            .add_to_world(&world)
+            .unwrap();
+
+        // separate from the update workload, so meshing shows up on its own in the profiler
+        Workload::new("mesh")
             .with_system(chunk_mesher_sys)
             .add_to_world(&world)
             .unwrap();
 
         Workload::new("render")
+            .with_system(apply_present_mode_sys)
+            .with_system(apply_texture_filtering_sys)
+            .with_system(update_sky_sys)
+            .with_system(update_fog_sys)
+            .with_system(update_bloom_sys)
+            .with_system(update_point_lights_sys)
             .with_system(update_camera_sys)
+            .with_system(update_shadow_map_sys)
+            .with_system(update_water_sys)
+            .with_system(update_debug_view_sys)
+            .with_system(update_minimap_sys)
+            .with_system(update_world_map_sys)
+            .with_system(place_world_map_tiles_sys)
             .with_system(update_models_sys)
+            .with_system(update_render_stats_sys)
+            .with_system(update_chunk_transforms_sys)
+            .with_system(update_fluid_models_sys)
+            .with_system(update_drop_models_sys)
+            .with_system(update_falling_block_models_sys)
+            .with_system(update_crack_model_sys)
+            .with_system(update_frustum_model_sys)
+            .with_system(update_particle_instances_sys)
+            .with_system(update_hud_sys)
+            .with_system(record_telemetry_sys)
             .add_to_world(&world)
             .unwrap();
 
-        Self { world }
+        Self {
+            world,
+            commands: client_commands(),
+            exit_requested: false,
+            cursor_captured: false,
+        }
+    }
+
+    /// Runs `f` and records its duration in the profiler.
+    fn profiled<R>(&self, name: &'static str, f: impl FnOnce(&World) -> R) -> R {
+        let start = Instant::now();
+        let result = f(&self.world);
+
+        self.world
+            .borrow::<UniqueViewMut<Profiler>>()
+            .unwrap()
+            .record(name, start, start.elapsed());
+
+        result
     }
 
     pub fn update(&mut self) {
-        self.world.run_workload("update").unwrap();
+        // the world behind the title screen is empty and stays that way until the player starts playing
+        let on_title = self.world.borrow::<UniqueView<Menu>>().unwrap().screen == Screen::Title;
+
+        if !on_title {
+            self.profiled("io", |world| world.run_workload("io").unwrap());
+
+            let steps = self
+                .world
+                .borrow::<UniqueViewMut<TickControl>>()
+                .unwrap()
+                .advance();
+            for _ in 0..steps {
+                self.profiled("update", |world| world.run_workload("update").unwrap());
+            }
+
+            self.profiled("mesh", |world| world.run_workload("mesh").unwrap());
+        }
+
+        self.run_commands();
+        self.run_menu_action();
+    }
+
+    /// Applies the action of the menu button clicked since the last update.
+    fn run_menu_action(&mut self) {
+        let chosen = self
+            .world
+            .borrow::<UniqueViewMut<Menu>>()
+            .unwrap()
+            .chosen
+            .take();
+
+        match chosen {
+            Some(MenuAction::SaveAndQuit) => self.quit_to_title(),
+            Some(MenuAction::Quit) => self.exit_requested = true,
+            Some(action) => self.world.run_with_data(menu_action_sys, action),
+            None => {}
+        }
+    }
+
+    /// Saves the session and replaces the world with an empty one showing the title screen.
+    /// The renderer belongs to the window, so only the GPU resources of the world are released with it.
+    fn quit_to_title(&mut self) {
+        self.save();
+
+        // the old world is dropped at the end of the block,
+        // stopping world generation threads and releasing models of chunks, mobs and items
+        let (
+            settings,
+            resource_packs,
+            resource_dictionary,
+            renderer,
+            telemetry,
+            plugins,
+            world_save,
+            input_replay,
+        ) = {
+            let world = std::mem::take(&mut self.world);
+
+            (
+                world.remove_unique::<Settings>().unwrap(),
+                world.remove_unique::<ResourcePacks>().unwrap(),
+                world.remove_unique::<ResourceDictionary>().unwrap(),
+                world.remove_unique::<Renderer>().unwrap(),
+                world.remove_unique::<Telemetry>().unwrap(),
+                world.remove_unique::<Plugins>().unwrap(),
+                world.remove_unique::<WorldSave>().unwrap(),
+                world.remove_unique::<InputReplay>().unwrap(),
+            )
+        };
+
+        let camera = Camera::new(&renderer.device, &renderer.config);
+
+        *self = Self::with_renderer(
+            settings,
+            resource_packs,
+            resource_dictionary,
+            renderer,
+            camera,
+            telemetry,
+            plugins,
+            world_save,
+        );
+        // a recording goes on across worlds until the game exits
+        *self.world.borrow::<UniqueViewMut<InputReplay>>().unwrap() = input_replay;
+
+        let (mut menu, mut input_state) = self
+            .world
+            .borrow::<(UniqueViewMut<Menu>, UniqueViewMut<InputState>)>()
+            .unwrap();
+        menu.open(Screen::Title, &mut input_state);
+
+        log::info!("Quit to the title screen");
+    }
+
+    /// Writes state which outlives the session to disk.
+    /// Only edited chunks of the world are saved, the rest is generated again on every start.
+    fn save(&mut self) {
+        self.world.run(autosave_sys);
+        self.world
+            .borrow::<UniqueViewMut<WorldSave>>()
+            .unwrap()
+            .save_now();
+        self.world
+            .borrow::<UniqueViewMut<WorldMap>>()
+            .unwrap()
+            .save();
+
+        self.world
+            .borrow::<UniqueViewMut<Telemetry>>()
+            .unwrap()
+            .save();
+    }
+
+    /// Executes commands submitted in the chat at the permission level granted by the server, and
+    /// shows their output in it. While connected, commands moving the player, commands the client
+    /// doesn't know and commands the player isn't allowed to execute locally are sent to the server,
+    /// which answers in the chat.
+    fn run_commands(&mut self) {
+        let lines = self
+            .world
+            .borrow::<UniqueViewMut<Chat>>()
+            .unwrap()
+            .take_commands();
+
+        for line in lines {
+            {
+                let mut bus = self.world.borrow::<UniqueViewMut<ChatBus>>().unwrap();
+                if bus.connected && is_server_command(&line) {
+                    bus.commands.push_back(line);
+                    continue;
+                }
+            }
+
+            let level = self
+                .world
+                .borrow::<UniqueView<Permissions>>()
+                .unwrap()
+                .level;
+
+            let output = match self.commands.execute(&mut self.world, &line, level) {
+                Ok(output) => output,
+                Err(e @ (CommandError::Unknown(_) | CommandError::Forbidden { .. })) => {
+                    let mut bus = self.world.borrow::<UniqueViewMut<ChatBus>>().unwrap();
+                    if bus.connected {
+                        bus.commands.push_back(line);
+                        continue;
+                    }
+
+                    e.to_string()
+                }
+                Err(e) => e.to_string(),
+            };
+
+            if !output.is_empty() {
+                self.world
+                    .borrow::<UniqueViewMut<Chat>>()
+                    .unwrap()
+                    .push_system(&output);
+            }
+        }
     }
 
     /// Renders a frame and returns false on exit.
     pub fn render(&mut self) -> bool {
-        self.world.run_workload("render").unwrap();
+        if self.exit_requested {
+            return false;
+        }
+
+        self.profiled("prepare", |world| world.run_workload("render").unwrap());
 
-        match self.world.run(rendering_sys) {
+        match self.profiled("draw", |world| world.run(rendering_sys)) {
             Ok(()) => {}
             // Reconfigure the surface if lost
             Err(wgpu::SurfaceError::Lost) => {
@@ -87,11 +685,165 @@ impl Game {
             Err(e) => eprintln!("{:?}", e),
         }
 
+        self.world.run(screenshot_sys);
+
+        self.world.run(frame_limiter_sys);
+
+        self.world
+            .borrow::<UniqueViewMut<Profiler>>()
+            .unwrap()
+            .end_frame();
+
         true
     }
 
+    /// Called before the game exits normally.
+    pub fn shutdown(&mut self) {
+        self.world
+            .borrow::<UniqueViewMut<ServerLink>>()
+            .unwrap()
+            .disconnect();
+
+        self.world.run(autosave_sys);
+        self.world
+            .borrow::<UniqueViewMut<WorldSave>>()
+            .unwrap()
+            .save_now();
+        self.world
+            .borrow::<UniqueViewMut<WorldMap>>()
+            .unwrap()
+            .save();
+
+        self.world
+            .borrow::<UniqueViewMut<Telemetry>>()
+            .unwrap()
+            .finish();
+        self.world
+            .borrow::<UniqueViewMut<InputReplay>>()
+            .unwrap()
+            .finish();
+    }
+
+    /// Shows the world and frame rate in the window title, followed by profiler timings with the
+    /// state of the world IO thread, spawn and draw statistics while their overlays are enabled.
+    pub fn update_overlay(&mut self, window: &Window) {
+        let debug_state = self.world.borrow::<UniqueView<DebugRenderState>>().unwrap();
+        let mut profiler = self.world.borrow::<UniqueViewMut<Profiler>>().unwrap();
+        let mut spawner = self.world.borrow::<UniqueViewMut<MobSpawner>>().unwrap();
+        let renderer = self.world.borrow::<UniqueView<Renderer>>().unwrap();
+        let menu = self.world.borrow::<UniqueView<Menu>>().unwrap();
+        let link = self.world.borrow::<UniqueView<ServerLink>>().unwrap();
+
+        let Some(profiler_summary) = profiler.refresh_summary() else {
+            return;
+        };
+        let profiler_summary = profiler_summary.to_string();
+
+        let spawn_summary = spawner.stats.take_summary();
+        let mut title = WINDOW_TITLE.to_string();
+
+        if menu.screen != Screen::Title {
+            if let Some(name) = link.server_name() {
+                title = format!("{title} - {name}");
+            }
+        }
+
+        title = format!("{title} | {:.0} FPS", profiler.fps());
+
+        if debug_state.profiler {
+            let save_stats = self
+                .world
+                .borrow::<UniqueView<WorldSave>>()
+                .unwrap()
+                .stats();
+            title = format!("{title} | {profiler_summary} | {save_stats}");
+        }
+
+        if debug_state.spawn_stats {
+            title = format!("{title} | {spawn_summary}");
+        }
+
+        if debug_state.draw_stats {
+            let render_stats = self.world.borrow::<UniqueView<RenderStats>>().unwrap();
+            title = format!("{title} | {} | {}", renderer.draw_stats, *render_stats);
+        }
+
+        window.set_title(&title);
+    }
+
+    /// Opens the second window configured in the settings, only available in dev mode.
+    pub fn open_debug_view(&mut self, event_loop: &EventLoopWindowTarget<()>) {
+        let settings = self.world.borrow::<UniqueView<Settings>>().unwrap();
+        let Some(mode) = settings.debug_view.filter(|_| settings.dev_mode) else {
+            return;
+        };
+
+        let mut renderer = self.world.borrow::<UniqueViewMut<Renderer>>().unwrap();
+        let camera = self.world.borrow::<UniqueView<Camera>>().unwrap();
+        renderer.debug_view = DebugView::open(event_loop, &renderer, &camera, mode);
+    }
+
+    /// Handles events of the debug view window, the other events are ignored.
+    fn handle_debug_view_event(&mut self, event: &WindowEvent) {
+        let mut renderer = self.world.borrow::<UniqueViewMut<Renderer>>().unwrap();
+        let renderer = &mut *renderer;
+        let Some(debug_view) = renderer.debug_view.as_mut() else {
+            return;
+        };
+
+        match event {
+            // closing it only closes the view, the game keeps running
+            WindowEvent::CloseRequested => renderer.debug_view = None,
+            WindowEvent::Resized(physical_size) => debug_view.resize(
+                &renderer.device,
+                &renderer.texture_bind_group_layout,
+                *physical_size,
+            ),
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => debug_view.resize(
+                &renderer.device,
+                &renderer.texture_bind_group_layout,
+                **new_inner_size,
+            ),
+            WindowEvent::Focused(focused) => debug_view.set_focused(*focused),
+            WindowEvent::KeyboardInput { input, .. } => debug_view.handle_key(*input),
+            _ => {}
+        }
+    }
+
+    /// Returns true if the event belongs to the debug view window instead of the main one.
+    fn is_debug_view_event(&self, event: &Event<()>) -> bool {
+        let Event::WindowEvent { window_id, .. } = event else {
+            return false;
+        };
+
+        self.world
+            .borrow::<UniqueView<Renderer>>()
+            .unwrap()
+            .debug_view
+            .as_ref()
+            .is_some_and(|debug_view| debug_view.window_id() == *window_id)
+    }
+
+    /// Returns true while the debug view window has the keyboard focus.
+    fn debug_view_focused(&self) -> bool {
+        self.world
+            .borrow::<UniqueView<Renderer>>()
+            .unwrap()
+            .debug_view
+            .as_ref()
+            .is_some_and(|debug_view| debug_view.is_focused())
+    }
+
     // Handles window events and returns false when CloseRequested is detected.
     pub fn handle_events(&mut self, window: &Window, event: &Event<()>) -> bool {
+        if self.is_debug_view_event(event) {
+            if let Event::WindowEvent { event, .. } = event {
+                self.handle_debug_view_event(event);
+            }
+
+            return true;
+        }
+
         match event {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => {
@@ -109,14 +861,32 @@ impl Game {
                         .unwrap()
                         .cursor_in_window = true;
                 }
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.world
+                        .borrow::<UniqueViewMut<InputState>>()
+                        .unwrap()
+                        .cursor_position = glam::Vec2::new(position.x as f32, position.y as f32);
+                }
                 WindowEvent::CursorLeft { .. } => {
                     self.world
                         .borrow::<UniqueViewMut<InputState>>()
                         .unwrap()
                         .cursor_in_window = false;
                 }
-                WindowEvent::MouseInput { button, .. } => {
-                    self.world.run_with_data(mouse_button_sys, button)
+                WindowEvent::MouseInput { state, button, .. } => self
+                    .world
+                    .run_with_data(mouse_button_sys, (*state, *button)),
+                WindowEvent::ReceivedCharacter(character) => {
+                    self.world.run_with_data(typed_character_sys, *character)
+                }
+                WindowEvent::ModifiersChanged(modifiers) => {
+                    self.world
+                        .borrow::<UniqueViewMut<InputState>>()
+                        .unwrap()
+                        .modifiers = *modifiers;
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    self.world.run_with_data(mouse_wheel_sys, *delta)
                 }
                 _ => {}
             },
@@ -124,7 +894,10 @@ impl Game {
                 DeviceEvent::MouseMotion { delta } => {
                     self.world.run_with_data(mouse_input_sys, delta)
                 }
-                DeviceEvent::Key(event) => self.world.run_with_data(keyboard_input_sys, event),
+                // raw key events arrive regardless of which window is focused
+                DeviceEvent::Key(event) if !self.debug_view_focused() => {
+                    self.world.run_with_data(keyboard_input_sys, event)
+                }
                 _ => {}
             },
             _ => {}
@@ -133,28 +906,22 @@ impl Game {
         // Process requests to change the window state.
         let input_state = self.world.borrow::<UniqueView<InputState>>().unwrap();
 
-        // Check if cursor should be captured.
-        if input_state.cursor_captured {
-            window.set_cursor_visible(false);
-            window.set_cursor_grab(CursorGrabMode::Confined).unwrap();
-        } else {
-            window.set_cursor_visible(true);
-            window.set_cursor_grab(CursorGrabMode::None).unwrap();
+        if input_state.cursor_captured != self.cursor_captured {
+            self.cursor_captured = input_state.cursor_captured;
+            set_cursor_captured(window, self.cursor_captured);
         }
 
         // Check if fullscreen should be enabled.
         if input_state.fullscreen {
             if window.fullscreen().is_none() {
-                let monitor = window
-                    .current_monitor()
-                    .expect("Could not get a reference to the current monitor");
+                let mode = self
+                    .world
+                    .borrow::<UniqueView<Settings>>()
+                    .unwrap()
+                    .graphics
+                    .fullscreen;
 
-                let video_mode = monitor
-                    .video_modes()
-                    .nth(0)
-                    .expect("Could not get a preferred video mode of the current monitor");
-
-                window.set_fullscreen(Some(Fullscreen::Exclusive(video_mode)));
+                window.set_fullscreen(Some(fullscreen(window, mode)));
             }
         } else if window.fullscreen().is_some() {
             window.set_fullscreen(None);
@@ -164,36 +931,329 @@ impl Game {
     }
 }
 
-pub fn run() {
+/// Returns the fullscreen state for the mode, exclusive fullscreen uses the largest video mode with the
+/// highest refresh rate of the current monitor. Falls back to borderless if the monitor has no modes.
+fn fullscreen(window: &Window, mode: FullscreenMode) -> Fullscreen {
+    let monitor = window.current_monitor();
+
+    if mode == FullscreenMode::Exclusive {
+        let video_mode = monitor.as_ref().and_then(|monitor| {
+            monitor.video_modes().max_by_key(|video_mode| {
+                let size = video_mode.size();
+                (
+                    size.width * size.height,
+                    video_mode.refresh_rate_millihertz(),
+                )
+            })
+        });
+
+        match video_mode {
+            Some(video_mode) => return Fullscreen::Exclusive(video_mode),
+            None => log::warn!("No video modes found for exclusive fullscreen, using borderless"),
+        }
+    }
+
+    Fullscreen::Borderless(monitor)
+}
+
+/// Loads `icon.png` from the resource packs for the window.
+fn load_window_icon(resource_packs: &ResourcePacks) -> Option<Icon> {
+    let path = resource_packs.find(Path::new("icon.png"))?;
+
+    let image = match image::open(&path) {
+        Ok(image) => image.into_rgba8(),
+        Err(e) => {
+            log::error!("Failed to load the window icon {}: {e}", path.display());
+            return None;
+        }
+    };
+
+    let (width, height) = image.dimensions();
+
+    Icon::from_rgba(image.into_raw(), width, height)
+        .map_err(|e| log::error!("Invalid window icon {}: {e}", path.display()))
+        .ok()
+}
+
+/// Grabs and hides the cursor or releases it. Windows can only be confined on some platforms and
+/// locked on others, so the other mode is tried if one fails. Without a grab the cursor is only hidden.
+fn set_cursor_captured(window: &Window, captured: bool) {
+    window.set_cursor_visible(!captured);
+
+    let result = if captured {
+        window
+            .set_cursor_grab(CursorGrabMode::Confined)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+
+    if let Err(e) = result {
+        log::warn!("Failed to change the cursor grab: {e}");
+    }
+}
+
+/// Loads resource packs listed in the settings, falling back to built-in block definitions.
+/// Blocks registered by mods are added after the blocks of the packs.
+/// The packs are checked against the ones the world in `world_dir` was created with, if given.
+fn load_resources(
+    settings: &Settings,
+    world_dir: Option<&Path>,
+    mod_blocks: Vec<BlockData>,
+) -> Result<(ResourcePacks, ResourceDictionary), LandmarkError> {
+    let resource_packs = ResourcePacks::new(&settings.resource_packs);
+    let resource_packs = match world_dir {
+        Some(dir) => select_world_packs(settings, dir, resource_packs),
+        None => resource_packs,
+    };
+
+    let resource_dictionary = match ResourceDictionary::new(&resource_packs, mod_blocks.clone()) {
+        Ok(resource_dictionary) => resource_dictionary,
+        Err(e) => {
+            log::error!("{e}, using built-in block definitions");
+            ResourceDictionary::builtin(mod_blocks)?
+        }
+    };
+
+    Ok((resource_packs, resource_dictionary))
+}
+
+/// Warns if the packs differ from the ones the world in `dir` was created with, as blocks missing from
+/// the packs would be remapped. Returns the pinned packs instead if the settings ask for them and all
+/// of them are installed. Worlds without recorded packs are pinned to the given ones, a record which
+/// can't be read is left as is.
+fn select_world_packs(
+    settings: &Settings,
+    dir: &Path,
+    resource_packs: ResourcePacks,
+) -> ResourcePacks {
+    let loaded = resource_packs.pinned();
+
+    let pinned = match PinnedPack::load_world_packs(dir) {
+        Ok(Some(pinned)) => pinned,
+        Ok(None) => {
+            if let Err(e) = PinnedPack::pin_world_packs(dir, &loaded) {
+                log::error!("Failed to record the resource packs of the world: {e}");
+            }
+
+            return resource_packs;
+        }
+        Err(e) => {
+            log::error!(
+                "Failed to load the resource packs of the world from {}, using the enabled packs: {e}",
+                dir.join(PinnedPack::FILE_NAME).display()
+            );
+
+            return resource_packs;
+        }
+    };
+
+    if pinned == loaded {
+        return resource_packs;
+    }
+
+    let list = |packs: &[PinnedPack]| {
+        packs
+            .iter()
+            .map(PinnedPack::to_string)
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    log::warn!(
+        "The world was created with the resource packs [{}] but [{}] are enabled, its blocks may change",
+        list(&pinned),
+        list(&loaded)
+    );
+
+    if !pinned.iter().all(PinnedPack::is_available) {
+        log::warn!("Some of the packs of the world aren't installed, using the enabled packs");
+        return resource_packs;
+    }
+
+    if !settings.load_pinned_packs {
+        log::warn!(
+            "Enable load_pinned_packs in {} to load the world with its own packs",
+            Settings::PATH
+        );
+        return resource_packs;
+    }
+
+    log::info!("Loading the resource packs of the world");
+    let names: Vec<String> = pinned.into_iter().map(|pack| pack.name).collect();
+
+    ResourcePacks::new(&names)
+}
+
+/// Runs the game without a window, rendering into an offscreen texture.
+/// Meant for integration tests and tools which need rendered frames without a display.
+pub struct HeadlessGame {
+    game: Game,
+}
+
+impl HeadlessGame {
+    pub fn new(width: u32, height: u32) -> Result<Self, LandmarkError> {
+        Ok(Self {
+            game: Game::init_headless(PhysicalSize::new(width, height))?,
+        })
+    }
+
+    /// Runs a single update, chunks are generated in the background and appear over multiple updates.
+    pub fn update(&mut self) {
+        self.game.update();
+    }
+
+    /// Replaces the input of the following updates with a recording, e.g. for smoke tests of the game loop.
+    pub fn play_input(&mut self, path: &Path) -> Result<(), ReplayError> {
+        *self
+            .game
+            .world
+            .borrow::<UniqueViewMut<InputReplay>>()
+            .unwrap() = InputReplay::new(ReplayMode::Play(path.to_owned()))?;
+
+        Ok(())
+    }
+
+    /// Returns true while updates of the recording passed to `play_input` are left.
+    pub fn is_playing_input(&self) -> bool {
+        self.game
+            .world
+            .borrow::<UniqueView<InputReplay>>()
+            .unwrap()
+            .is_playing()
+    }
+
+    /// Renders a frame and returns its pixels.
+    pub fn render(&mut self) -> Option<image::RgbaImage> {
+        if !self.game.render() {
+            return None;
+        }
+
+        self.game
+            .world
+            .borrow::<UniqueView<Renderer>>()
+            .unwrap()
+            .read_pixels()
+    }
+}
+
+/// Writes the locally recorded telemetry to `path`.
+pub fn export_telemetry(path: &std::path::Path) -> std::io::Result<()> {
+    telemetry::export(path)
+}
+
+/// Generates and meshes chunks from a fixed seed, returns how long it took.
+pub fn run_benchmark(chunks: usize, seed: u64) -> Result<bench::BenchReport, LandmarkError> {
+    bench::run(chunks, seed)
+}
+
+/// Opens the world in `dir` for a dedicated server, with the resource packs, mods and world settings
+/// the client would play it with.
+pub fn open_world(dir: &Path) -> Result<Box<dyn ChunkStore>, LandmarkError> {
+    let settings = Settings::load();
+    let plugins = Plugins::load();
+    let (_, resource_dictionary) =
+        load_resources(&settings, Some(dir), plugins.registered_blocks())?;
+
+    Ok(Box::new(WorldStore::open(
+        WorldSave::open(dir),
+        &settings.world,
+        resource_dictionary,
+        plugins,
+    )))
+}
+
+/// Returns a line describing every GPU the game can render with.
+pub fn list_adapters() -> Vec<String> {
+    adapter::list_adapters()
+}
+
+/// Converts the region files of a Minecraft world into the saved world, returns a summary of the import.
+#[cfg(feature = "anvil")]
+pub fn import_anvil(
+    source: &std::path::Path,
+    mapping: &std::path::Path,
+) -> Result<String, anvil::AnvilError> {
     env_logger::init();
 
+    let mapping = anvil::BlockMapping::load(mapping)?;
+    let summary = anvil::import(source, &mapping, Path::new(WorldSave::DIR))?;
+
+    Ok(summary.to_string())
+}
+
+/// Opens the window and runs the game until it's closed, only returns if it can't start.
+/// The input of the session is recorded or played back from a file depending on `replay`.
+/// The game joins the server at `server`, or plays singleplayer if it's None.
+pub fn run(replay: Option<ReplayMode>, server: Option<String>) -> Result<(), LandmarkError> {
+    env_logger::init();
+
+    let replay = replay.map(InputReplay::new).transpose()?;
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_title("Landmark")
-        .build(&event_loop)
-        .expect("Failed to create a window");
+        .with_title(WINDOW_TITLE)
+        .build(&event_loop)?;
     let window = Arc::new(window);
 
-    let game = Game::init(&window);
+    let mut game = Game::init(&window, server.as_deref())?;
+    game.open_debug_view(&event_loop);
+
+    if let Some(replay) = replay {
+        *game.world.borrow::<UniqueViewMut<InputReplay>>().unwrap() = replay;
+    }
 
     game_loop(
         event_loop,
         window,
         game,
-        240,
+        UPDATES_PER_SECOND,
         0.1,
         |g| {
             g.game.update();
         },
         |g| {
             if !g.game.render() {
+                g.game.shutdown();
                 g.exit();
             }
+
+            g.game.update_overlay(&g.window);
         },
         |g, event| {
             if !g.game.handle_events(&g.window, event) {
+                g.game.shutdown();
                 g.exit();
             }
         },
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_without_packs_is_pinned() {
+        let dir = tempfile::tempdir().unwrap();
+        let resource_packs = ResourcePacks::new(&[]);
+        let loaded = resource_packs.pinned();
+
+        select_world_packs(&Settings::default(), dir.path(), resource_packs);
+
+        assert_eq!(
+            PinnedPack::load_world_packs(dir.path()).unwrap(),
+            Some(loaded)
+        );
+    }
+
+    #[test]
+    fn corrupt_pinned_packs_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PinnedPack::FILE_NAME);
+        std::fs::write(&path, "[(name: ").unwrap();
+
+        select_world_packs(&Settings::default(), dir.path(), ResourcePacks::new(&[]));
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[(name: ");
+    }
+}
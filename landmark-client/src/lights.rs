@@ -0,0 +1,511 @@
+//! Point lights of entities and blocks, like a torch held by the player or glowing lava.
+//!
+//! They are added in the world shader on top of the color of nearby geometry, so they light up the
+//! terrain around them without changing the light stored in chunks.
+//!
+//! With forward lighting only the few lights nearest to the camera are drawn, every fragment adds up
+//! all of them. Clustered lighting sorts the lights into a grid of cells around the camera instead, so
+//! every fragment only adds up the lights reaching its cell and many more lights can be drawn.
+
+use bytemuck::Zeroable;
+use shipyard::*;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    color::{Color, RawColor},
+    drops::ItemDrop,
+    game_map::{Chunk, ChunkCoords, InnerChunkCoords},
+    inventory::Inventory,
+    loader::ResourceDictionary,
+    player::LocalPlayer,
+    rendererer::Renderer,
+};
+
+/// Lights the forward path draws at once, has to match `MAX_POINT_LIGHTS` in `lights.wgsl`.
+const MAX_POINT_LIGHTS: usize = 8;
+/// Lights the clustered path draws at once.
+const MAX_CLUSTERED_LIGHTS: usize = 256;
+/// Entries of all cells of the light grid together, lights beyond it are left out of the fullest cells.
+const MAX_LIGHT_INDICES: usize = 16384;
+/// Blocks covered by a cell of the light grid along each axis.
+const CELL_SIZE: f64 = 8.0;
+/// Cells of the light grid along each axis, it's centered on the camera.
+const GRID_SIZE: glam::UVec3 = glam::UVec3::new(16, 8, 16);
+const CELL_COUNT: usize = (GRID_SIZE.x * GRID_SIZE.y * GRID_SIZE.z) as usize;
+/// Lights of blocks further from the camera are never drawn.
+const BLOCK_LIGHT_DISTANCE: f64 = 64.0;
+/// How far below the eye a held light is, roughly where the hand holding it is.
+const HAND_OFFSET: f64 = 0.3;
+/// How far above the ground the light of a dropped item is.
+const DROP_OFFSET: f64 = 0.25;
+
+/// Light given off by an item, it fades out completely at the radius.
+///
+/// ```ron
+/// light: Some((color: (r: 255, g: 170, b: 80), radius: 10.0)),
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LightSource {
+    pub color: Color,
+    /// In blocks.
+    pub radius: f32,
+}
+
+/// Light at a position in the world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: glam::DVec3,
+    pub source: LightSource,
+}
+
+/// Point light as laid out in the uniform buffer.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct RawPointLight {
+    position: glam::Vec3,
+    radius: f32,
+    color: glam::Vec3,
+    _padding: f32,
+}
+
+impl RawPointLight {
+    /// Places the light relative to `origin` like the rest of the world.
+    fn new(light: &PointLight, origin: glam::DVec3) -> Self {
+        Self {
+            position: (light.position - origin).as_vec3(),
+            radius: light.source.radius,
+            color: RawColor::from(light.source.color).to_array().into(),
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Sorts the lights by their distance to the eye, nearest first.
+fn sort_by_distance(lights: &mut [PointLight], eye: glam::DVec3) {
+    lights.sort_by(|a, b| {
+        a.position
+            .distance_squared(eye)
+            .total_cmp(&b.position.distance_squared(eye))
+    });
+}
+
+/// Point lights of the forward path as laid out in the uniform buffer.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct LightsUniform {
+    lights: [RawPointLight; MAX_POINT_LIGHTS],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl LightsUniform {
+    /// Keeps the lights nearest to the eye, their positions are made relative to `origin` like the
+    /// rest of the world.
+    pub fn new(mut lights: Vec<PointLight>, eye: glam::DVec3, origin: glam::DVec3) -> Self {
+        sort_by_distance(&mut lights, eye);
+
+        let mut uniform = Self::zeroed();
+
+        for (raw, light) in uniform.lights.iter_mut().zip(&lights) {
+            *raw = RawPointLight::new(light, origin);
+        }
+
+        uniform.count = lights.len().min(MAX_POINT_LIGHTS) as u32;
+        uniform
+    }
+}
+
+/// Placement of the light grid as laid out in the uniform buffer.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct LightGridUniform {
+    min: glam::Vec3,
+    cell_size: f32,
+    size: glam::UVec3,
+    _padding: u32,
+}
+
+/// Lights of the clustered path sorted into the cells of the light grid.
+#[derive(Debug)]
+pub struct LightClusters {
+    grid: LightGridUniform,
+    lights: Vec<RawPointLight>,
+    /// Offset into `indices` and count of the lights reaching every cell, x changing fastest.
+    cells: Vec<[u32; 2]>,
+    /// Indices into `lights`.
+    indices: Vec<u32>,
+}
+
+impl LightClusters {
+    /// Centers the grid on the eye in steps of whole cells and keeps the lights nearest to it.
+    pub fn new(mut lights: Vec<PointLight>, eye: glam::DVec3, origin: glam::DVec3) -> Self {
+        sort_by_distance(&mut lights, eye);
+        lights.truncate(MAX_CLUSTERED_LIGHTS);
+
+        let half_extent = GRID_SIZE.as_dvec3() * CELL_SIZE / 2.0;
+        let min = ((eye - half_extent) / CELL_SIZE).floor() * CELL_SIZE;
+        let last_cell = GRID_SIZE.as_ivec3() - glam::IVec3::ONE;
+
+        let mut cell_lights = vec![Vec::new(); CELL_COUNT];
+
+        for (idx, light) in lights.iter().enumerate() {
+            let radius = light.source.radius as f64;
+            let first = ((light.position - radius - min) / CELL_SIZE)
+                .floor()
+                .as_ivec3();
+            let last = ((light.position + radius - min) / CELL_SIZE)
+                .floor()
+                .as_ivec3();
+
+            for z in first.z.max(0)..=last.z.min(last_cell.z) {
+                for y in first.y.max(0)..=last.y.min(last_cell.y) {
+                    for x in first.x.max(0)..=last.x.min(last_cell.x) {
+                        cell_lights[cell_idx(glam::IVec3::new(x, y, z))].push(idx as u32);
+                    }
+                }
+            }
+        }
+
+        let mut cells = Vec::with_capacity(cell_lights.len());
+        let mut indices = Vec::new();
+
+        for list in cell_lights {
+            let count = list.len().min(MAX_LIGHT_INDICES - indices.len());
+            cells.push([indices.len() as u32, count as u32]);
+            indices.extend_from_slice(&list[..count]);
+        }
+
+        Self {
+            grid: LightGridUniform {
+                min: (min - origin).as_vec3(),
+                cell_size: CELL_SIZE as f32,
+                size: GRID_SIZE,
+                _padding: 0,
+            },
+            lights: lights
+                .iter()
+                .map(|light| RawPointLight::new(light, origin))
+                .collect(),
+            cells,
+            indices,
+        }
+    }
+}
+
+/// Returns the index of a cell of the light grid, x changing fastest like in the shader.
+fn cell_idx(cell: glam::IVec3) -> usize {
+    let size = GRID_SIZE.as_ivec3();
+
+    ((cell.z * size.y + cell.y) * size.x + cell.x) as usize
+}
+
+/// Buffers of the point lights, bound to the world shader after the fog.
+#[derive(Debug)]
+pub enum LightBuffers {
+    /// Holds a `LightsUniform`.
+    Forward(wgpu::Buffer),
+    Clustered(Box<ClusterBuffers>),
+}
+
+/// Hold the parts of `LightClusters`, with room for as many lights and indices as can be drawn.
+#[derive(Debug)]
+pub struct ClusterBuffers {
+    grid: wgpu::Buffer,
+    lights: wgpu::Buffer,
+    cells: wgpu::Buffer,
+    indices: wgpu::Buffer,
+}
+
+impl LightBuffers {
+    /// Storage buffers read by the fragment shader with clustered lighting.
+    pub const STORAGE_BUFFERS: u32 = 3;
+
+    pub fn new(device: &wgpu::Device, clustered: bool) -> Self {
+        if !clustered {
+            return Self::Forward(
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("lights_buffer"),
+                    contents: bytemuck::cast_slice(&[LightsUniform::zeroed()]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                }),
+            );
+        }
+
+        let storage = |label, size: usize| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+
+        Self::Clustered(Box::new(ClusterBuffers {
+            grid: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("light_grid_buffer"),
+                contents: bytemuck::cast_slice(&[LightGridUniform::zeroed()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+            lights: storage(
+                "clustered_lights_buffer",
+                MAX_CLUSTERED_LIGHTS * std::mem::size_of::<RawPointLight>(),
+            ),
+            cells: storage(
+                "light_cells_buffer",
+                CELL_COUNT * std::mem::size_of::<[u32; 2]>(),
+            ),
+            indices: storage(
+                "light_indices_buffer",
+                MAX_LIGHT_INDICES * std::mem::size_of::<u32>(),
+            ),
+        }))
+    }
+
+    /// Returns the entries of the buffers in the bind group layout, starting at binding 1.
+    pub fn layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = wgpu::BufferBindingType::Storage { read_only: true };
+
+        match self {
+            Self::Forward(_) => vec![entry(1, wgpu::BufferBindingType::Uniform)],
+            Self::Clustered(_) => vec![
+                entry(1, wgpu::BufferBindingType::Uniform),
+                entry(2, storage),
+                entry(3, storage),
+                entry(4, storage),
+            ],
+        }
+    }
+
+    /// Returns the buffers bound to the entries of `layout_entries`.
+    pub fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>> {
+        let buffers = match self {
+            Self::Forward(buffer) => vec![buffer],
+            Self::Clustered(buffers) => vec![
+                &buffers.grid,
+                &buffers.lights,
+                &buffers.cells,
+                &buffers.indices,
+            ],
+        };
+
+        buffers
+            .into_iter()
+            .zip(1..)
+            .map(|(buffer, binding)| wgpu::BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect()
+    }
+
+    /// Uploads the lights drawn around the eye.
+    fn write(
+        &self,
+        queue: &wgpu::Queue,
+        lights: Vec<PointLight>,
+        eye: glam::DVec3,
+        origin: glam::DVec3,
+    ) {
+        match self {
+            Self::Forward(buffer) => {
+                let uniform = LightsUniform::new(lights, eye, origin);
+                queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[uniform]));
+            }
+            Self::Clustered(buffers) => {
+                let clusters = LightClusters::new(lights, eye, origin);
+
+                queue.write_buffer(&buffers.grid, 0, bytemuck::cast_slice(&[clusters.grid]));
+                queue.write_buffer(&buffers.cells, 0, bytemuck::cast_slice(&clusters.cells));
+
+                // cells without lights never read the others
+                if !clusters.indices.is_empty() {
+                    queue.write_buffer(&buffers.lights, 0, bytemuck::cast_slice(&clusters.lights));
+                    queue.write_buffer(
+                        &buffers.indices,
+                        0,
+                        bytemuck::cast_slice(&clusters.indices),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Lights of the glowing blocks of a chunk, found whenever the chunk is meshed.
+#[derive(Debug, Clone, Default, Component)]
+pub struct ChunkLights(pub Vec<PointLight>);
+
+impl ChunkLights {
+    pub fn new(
+        chunk: &Chunk,
+        coords: ChunkCoords,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Self {
+        if chunk.is_empty() {
+            return Self::default();
+        }
+
+        let origin = coords.as_world_position();
+        let size = Chunk::SIZE as usize;
+
+        let lights = chunk
+            .blocks()
+            .enumerate()
+            .filter_map(|(idx, block)| {
+                let source = resource_dictionary.get_block_light(block?)?;
+                let inner = InnerChunkCoords::new(
+                    (idx % size) as i32,
+                    (idx / size % size) as i32,
+                    (idx / (size * size)) as i32,
+                );
+
+                Some(PointLight {
+                    position: origin + inner.as_block_center().as_dvec3(),
+                    source,
+                })
+            })
+            .collect();
+
+        Self(lights)
+    }
+}
+
+/// Returns the light of an item, None if it gives off none or isn't defined.
+fn item_light(resource_dictionary: &ResourceDictionary, item: &str) -> Option<LightSource> {
+    let id = resource_dictionary.find_item_id(item)?;
+
+    resource_dictionary.get_item_data_from_id(id).light
+}
+
+/// Gathers the lights of the held item, dropped items and glowing blocks and uploads those drawn.
+pub fn update_point_lights_sys(
+    camera: UniqueView<Camera>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    renderer: UniqueView<Renderer>,
+    players: View<LocalPlayer>,
+    inventories: View<Inventory>,
+    drops: View<ItemDrop>,
+    chunk_lights: View<ChunkLights>,
+) {
+    let mut lights = Vec::new();
+
+    if let Some((_, inventory)) = (&players, &inventories).iter().next() {
+        let held = inventory
+            .selected_stack()
+            .and_then(|stack| item_light(&resource_dictionary, &stack.item));
+
+        lights.extend(held.map(|source| PointLight {
+            position: camera.eye - glam::DVec3::Y * HAND_OFFSET,
+            source,
+        }));
+    }
+
+    for drop in drops.iter() {
+        if let Some(source) = item_light(&resource_dictionary, &drop.item) {
+            lights.push(PointLight {
+                position: drop.position + glam::DVec3::Y * DROP_OFFSET,
+                source,
+            });
+        }
+    }
+
+    let max_distance = BLOCK_LIGHT_DISTANCE * BLOCK_LIGHT_DISTANCE;
+    lights.extend(
+        chunk_lights
+            .iter()
+            .flat_map(|chunk_lights| &chunk_lights.0)
+            .filter(|light| light.position.distance_squared(camera.eye) < max_distance),
+    );
+
+    renderer.lights.write(
+        &renderer.queue,
+        lights,
+        camera.eye,
+        camera.origin.as_world_position(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light_at(x: f64) -> PointLight {
+        PointLight {
+            position: glam::DVec3::new(x, 0.0, 0.0),
+            source: LightSource {
+                color: Color { r: 255, g: 0, b: 0 },
+                radius: x as f32,
+            },
+        }
+    }
+
+    #[test]
+    fn uniform_matches_the_shader_layout() {
+        // 8 lights of 32 bytes followed by the count, rounded up to a multiple of 16 bytes
+        assert_eq!(std::mem::size_of::<LightsUniform>(), 272);
+    }
+
+    #[test]
+    fn nearest_lights_are_kept() {
+        let lights = (1..=10).rev().map(|x| light_at(x as f64)).collect();
+        let origin = glam::DVec3::new(1.0, 0.0, 0.0);
+        let uniform = LightsUniform::new(lights, glam::DVec3::ZERO, origin);
+
+        assert_eq!(uniform.count, MAX_POINT_LIGHTS as u32);
+        assert_eq!(uniform.lights[0].radius, 1.0);
+        assert_eq!(uniform.lights[0].position, glam::Vec3::ZERO);
+        assert_eq!(uniform.lights[7].radius, 8.0);
+    }
+
+    #[test]
+    fn grid_matches_the_shader_layout() {
+        assert_eq!(std::mem::size_of::<LightGridUniform>(), 32);
+    }
+
+    /// Returns the indices of the lights reaching the cell containing a position.
+    fn lights_of_cell(clusters: &LightClusters, position: glam::DVec3) -> &[u32] {
+        let min = clusters.grid.min.as_dvec3();
+        let cell = ((position - min) / CELL_SIZE).floor().as_ivec3();
+        let [offset, count] = clusters.cells[cell_idx(cell)];
+
+        &clusters.indices[offset as usize..(offset + count) as usize]
+    }
+
+    #[test]
+    fn lights_are_sorted_into_the_cells_they_reach() {
+        let mut far = light_at(-30.0);
+        far.source.radius = 30.0;
+        let mut near = light_at(4.0);
+        near.source.radius = 10.0;
+
+        let clusters = LightClusters::new(vec![far, near], glam::DVec3::ZERO, glam::DVec3::ZERO);
+
+        assert_eq!(clusters.grid.min, glam::Vec3::new(-64.0, -32.0, -64.0));
+        // sorted by distance, so the near light comes first
+        assert_eq!(
+            lights_of_cell(&clusters, glam::DVec3::new(1.0, 0.0, 0.0)),
+            [0, 1]
+        );
+        assert_eq!(
+            lights_of_cell(&clusters, glam::DVec3::new(-20.0, 0.0, 0.0)),
+            [1]
+        );
+        assert_eq!(
+            lights_of_cell(&clusters, glam::DVec3::new(-62.0, 0.0, 0.0)),
+            [1]
+        );
+        assert!(lights_of_cell(&clusters, glam::DVec3::new(40.0, 0.0, 0.0)).is_empty());
+        assert!(lights_of_cell(&clusters, glam::DVec3::new(10.0, 20.0, 0.0)).is_empty());
+    }
+}
@@ -1,39 +1,686 @@
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use shipyard::*;
 
-use crate::{block::BlockData, game_map::BlockId};
+use crate::{
+    atlas::{TextureAtlas, UvRect},
+    block::{connected_tile_name, position_hash, BlockData, ConnectedTile},
+    game_map::{BlockId, ChunkTag, FaceDirection, GameMap},
+    input::InputState,
+    item::{ItemData, ItemId},
+    lights::LightSource,
+    mobs::MobDefinitions,
+    model::MissingModel,
+    random_tick::BlockTick,
+    rendererer::Renderer,
+    schematic::Schematic,
+    settings::Settings,
+    shader::ShaderIncludes,
+    spawning::{MobSpawner, SpawnRules},
+    vox::{VoxModel, VoxResources},
+};
 
-#[derive(Debug, Unique)]
+/// Shader compiled into the binary, used when the shader file cannot be loaded.
+pub const BUILTIN_SHADER: &str = include_str!("../../res/shaders/shader.wgsl");
+/// UI shader compiled into the binary, used when the shader file cannot be loaded.
+pub const BUILTIN_UI_SHADER: &str = include_str!("../../res/shaders/ui.wgsl");
+/// Particle shader compiled into the binary, used when the shader file cannot be loaded.
+pub const BUILTIN_PARTICLE_SHADER: &str = include_str!("../../res/shaders/particle.wgsl");
+/// Water shader compiled into the binary, used when the shader file cannot be loaded.
+pub const BUILTIN_WATER_SHADER: &str = include_str!("../../res/shaders/water.wgsl");
+/// Bloom and composite shader compiled into the binary, used when the shader file cannot be loaded.
+pub const BUILTIN_BLOOM_SHADER: &str = include_str!("../../res/shaders/bloom.wgsl");
+/// Shader filling the mip levels of the block atlas, which resource packs cannot replace.
+pub const BUILTIN_MIPMAP_SHADER: &str = include_str!("../../res/shaders/mipmap.wgsl");
+/// Files included by the built-in shaders, used when an included file cannot be loaded.
+const BUILTIN_SHADER_INCLUDES: [(&str, &str); 4] = [
+    ("camera.wgsl", include_str!("../../res/shaders/camera.wgsl")),
+    ("fog.wgsl", include_str!("../../res/shaders/fog.wgsl")),
+    ("lights.wgsl", include_str!("../../res/shaders/lights.wgsl")),
+    (
+        "shadows.wgsl",
+        include_str!("../../res/shaders/shadows.wgsl"),
+    ),
+];
+
+/// Block definitions compiled into the binary, used when no block definitions can be loaded.
+const BUILTIN_BLOCKS: [&str; 11] = [
+    include_str!("../../res/blocks/glass.ron"),
+    include_str!("../../res/blocks/grass.ron"),
+    include_str!("../../res/blocks/lava.ron"),
+    include_str!("../../res/blocks/portal.ron"),
+    include_str!("../../res/blocks/ripe_wheat.ron"),
+    include_str!("../../res/blocks/sand.ron"),
+    include_str!("../../res/blocks/soil.ron"),
+    include_str!("../../res/blocks/stone.ron"),
+    include_str!("../../res/blocks/water.ron"),
+    include_str!("../../res/blocks/wheat_sprouts.ron"),
+    include_str!("../../res/blocks/young_wheat.ron"),
+];
+
+/// Item definitions compiled into the binary, used together with the built-in blocks.
+const BUILTIN_ITEMS: [&str; 10] = [
+    include_str!("../../res/items/glass.ron"),
+    include_str!("../../res/items/grass.ron"),
+    include_str!("../../res/items/lava.ron"),
+    include_str!("../../res/items/portal.ron"),
+    include_str!("../../res/items/sand.ron"),
+    include_str!("../../res/items/soil.ron"),
+    include_str!("../../res/items/stone.ron"),
+    include_str!("../../res/items/torch.ron"),
+    include_str!("../../res/items/water.ron"),
+    include_str!("../../res/items/wheat_seeds.ron"),
+];
+
+#[derive(Debug)]
+pub enum ResourceError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    Empty {
+        path: PathBuf,
+    },
+    Invalid {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceError::Io { path, source } => {
+                write!(f, "Failed to read {}: {source}", path.display())
+            }
+            ResourceError::Parse { path, source } => {
+                write!(f, "Failed to parse file {}: {source}", path.display())
+            }
+            ResourceError::Empty { path } => {
+                write!(f, "Directory {} contains no resources", path.display())
+            }
+            ResourceError::Invalid { path, reason } => {
+                write!(f, "Invalid resource {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResourceError::Io { source, .. } => Some(source),
+            ResourceError::Parse { source, .. } => Some(source),
+            ResourceError::Empty { .. } | ResourceError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Optional description of a pack, read from `pack.ron` in its directory.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct PackInfo {
+    version: Option<String>,
+}
+
+/// A resource pack as recorded with a world, so the world can tell when it's loaded with other packs.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PinnedPack {
+    pub name: String,
+    /// Version from `pack.ron` of the pack, None if it has none.
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl PinnedPack {
+    /// File in the world directory listing the packs the world was created with.
+    pub const FILE_NAME: &'static str = "packs.ron";
+
+    /// Returns true if the pack is installed in the same version.
+    pub fn is_available(&self) -> bool {
+        let root = Path::new(ResourcePacks::PACKS_DIR).join(&self.name);
+
+        root.is_dir() && read_pack_version(&root) == self.version
+    }
+
+    /// Returns the resource packs the world in `dir` was created with, None if none were recorded yet.
+    /// A file which can't be read or parsed is an error, so it isn't replaced by the packs loaded now.
+    pub fn load_world_packs(dir: &Path) -> io::Result<Option<Vec<Self>>> {
+        let content = match fs::read_to_string(dir.join(Self::FILE_NAME)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        ron::from_str(&content)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Records the resource packs of the world in `dir`, so loading it with other packs can be noticed.
+    pub fn pin_world_packs(dir: &Path, packs: &[Self]) -> io::Result<()> {
+        let content = ron::ser::to_string_pretty(packs, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(Self::FILE_NAME), content)
+    }
+}
+
+impl fmt::Display for PinnedPack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {version}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Reads the version from `pack.ron` of a pack, packs without the file have no version.
+fn read_pack_version(root: &Path) -> Option<String> {
+    let path = root.join(ResourcePacks::PACK_INFO_FILE);
+    let content = fs::read_to_string(&path).ok()?;
+
+    match ron::from_str::<PackInfo>(&content) {
+        Ok(info) => info.version,
+        Err(e) => {
+            log::warn!("Failed to parse {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Resource roots searched in priority order, user packs first and the default resources last.
+#[derive(Debug, Clone, Unique)]
+pub struct ResourcePacks {
+    roots: Vec<PathBuf>,
+    /// Names of the packs which were found, in the same order as their roots.
+    names: Vec<String>,
+}
+
+impl ResourcePacks {
+    pub const DEFAULT_ROOT: &'static str = "res";
+    pub const PACKS_DIR: &'static str = "resourcepacks";
+    pub const PACK_INFO_FILE: &'static str = "pack.ron";
+
+    /// Creates the pack list from pack directory names, highest priority first.
+    pub fn new(pack_names: &[String]) -> Self {
+        let mut roots = Vec::new();
+        let mut names = Vec::new();
+
+        for name in pack_names {
+            let root = Path::new(Self::PACKS_DIR).join(name);
+
+            if root.is_dir() {
+                roots.push(root);
+                names.push(name.clone());
+            } else {
+                log::warn!("Resource pack {} not found, skipping it", root.display());
+            }
+        }
+
+        roots.push(PathBuf::from(Self::DEFAULT_ROOT));
+
+        Self { roots, names }
+    }
+
+    /// Returns the loaded packs with their versions, highest priority first. The default resources
+    /// are always loaded, so they aren't listed.
+    pub fn pinned(&self) -> Vec<PinnedPack> {
+        self.names
+            .iter()
+            .zip(&self.roots)
+            .map(|(name, root)| PinnedPack {
+                name: name.clone(),
+                version: read_pack_version(root),
+            })
+            .collect()
+    }
+
+    /// Returns the path of a resource from the highest priority pack containing it.
+    pub fn find(&self, relative: &Path) -> Option<PathBuf> {
+        self.roots
+            .iter()
+            .map(|root| root.join(relative))
+            .find(|path| path.is_file())
+    }
+
+    /// Lists files of a resource directory merged from all packs, sorted by file name.
+    /// Files from higher priority packs override files with the same name from lower ones.
+    pub fn list(&self, relative_dir: &Path) -> Result<Vec<PathBuf>, ResourceError> {
+        let mut files: HashMap<std::ffi::OsString, PathBuf> = HashMap::new();
+        let mut found_dir = false;
+
+        // iterate from the lowest priority so higher priority files replace the earlier ones
+        for root in self.roots.iter().rev() {
+            let dir = root.join(relative_dir);
+
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(source) => return Err(ResourceError::Io { path: dir, source }),
+            };
+
+            found_dir = true;
+
+            for entry in entries {
+                let path = entry
+                    .map_err(|source| ResourceError::Io {
+                        path: dir.clone(),
+                        source,
+                    })?
+                    .path();
+
+                if let Some(name) = path.file_name() {
+                    files.insert(name.to_os_string(), path);
+                }
+            }
+        }
+
+        if !found_dir {
+            return Err(ResourceError::Io {
+                path: Path::new(Self::DEFAULT_ROOT).join(relative_dir),
+                source: io::ErrorKind::NotFound.into(),
+            });
+        }
+
+        let mut files: Vec<(std::ffi::OsString, PathBuf)> = files.into_iter().collect();
+        files.sort();
+
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+}
+
+/// Changes caused by reloading the resource dictionary.
+#[derive(Debug, Default)]
+pub struct ReloadSummary {
+    /// Blocks whose definition or texture changed, chunks containing them have to be remeshed.
+    pub affected_blocks: HashSet<BlockId>,
+    /// Atlas slots which have to be uploaded to the GPU again.
+    pub dirty_slots: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Unique)]
 pub struct ResourceDictionary {
     blocks: HashMap<BlockId, BlockData>,
     block_names: HashMap<String, BlockId>,
+    /// UVs of the main texture followed by its variants.
+    block_uvs: HashMap<BlockId, Vec<UvRect>>,
+    /// UVs of the connected texture tiles, only present for blocks which have one.
+    block_connected_uvs: HashMap<BlockId, [UvRect; ConnectedTile::COUNT]>,
+    /// UVs of the textures of single faces by `FaceDirection`, only present for blocks which have any.
+    block_face_uvs: HashMap<BlockId, [Option<UvRect>; 6]>,
+    /// Random ticks of blocks with the named blocks resolved, only present for blocks which have one.
+    block_ticks: HashMap<BlockId, BlockTick>,
+    /// Light given off by blocks, only present for blocks which glow.
+    block_lights: HashMap<BlockId, LightSource>,
+    /// Blocks drawn as fluid surfaces.
+    fluid_blocks: HashSet<BlockId>,
+    items: HashMap<ItemId, ItemData>,
+    item_names: HashMap<String, ItemId>,
+    /// UVs of item icons, only present for items which have one.
+    item_uvs: HashMap<ItemId, UvRect>,
+    pub atlas: TextureAtlas,
+    /// Blocks registered by mods, appended after the blocks of the resource packs on every reload.
+    mod_blocks: Vec<BlockData>,
+    /// Structures loaded from `.vox` models and `.schematic` files, by file name.
+    structures: HashMap<String, Arc<Schematic>>,
+    /// Models of mobs loaded from `.vox` files, by the kind of mob.
+    entity_models: HashMap<String, VoxModel>,
 }
 
 #[allow(unused)]
 impl ResourceDictionary {
-    pub fn new() -> Self {
+    /// Loads the dictionary from the resource packs.
+    pub fn new(
+        resource_packs: &ResourcePacks,
+        mod_blocks: Vec<BlockData>,
+    ) -> Result<Self, ResourceError> {
+        let vox = VoxResources::load(resource_packs);
+        let mut block_data = load_block_data(resource_packs)?;
+        block_data.extend(vox.palette_blocks());
+
+        let mut dictionary =
+            Self::from_data(block_data, load_item_data(resource_packs)?, mod_blocks);
+        dictionary.add_structures(vox, resource_packs);
+
+        let textures = dictionary.load_textures(resource_packs);
+        dictionary.atlas.update(&textures);
+        dictionary.update_uvs();
+
+        Ok(dictionary)
+    }
+
+    /// Reloads definitions and textures, keeping atlas slots of textures which didn't change.
+    pub fn reload(
+        &mut self,
+        resource_packs: &ResourcePacks,
+    ) -> Result<ReloadSummary, ResourceError> {
+        let vox = VoxResources::load(resource_packs);
+        let mut block_data = load_block_data(resource_packs)?;
+        block_data.extend(vox.palette_blocks());
+
+        let mut reloaded = Self::from_data(
+            block_data,
+            load_item_data(resource_packs)?,
+            self.mod_blocks.clone(),
+        );
+        reloaded.add_structures(vox, resource_packs);
+        let textures = reloaded.load_textures(resource_packs);
+        let atlas_update = self.atlas.update(&textures);
+
+        let mut affected_blocks = HashSet::new();
+
+        // block IDs are positional, so a new block may shift the IDs of the following ones
+        for (id, block) in reloaded.blocks.iter() {
+            let texture_changed = block
+                .textures()
+                .chain(block.face_textures())
+                .cloned()
+                .chain(block.connected_tiles().into_iter().flatten())
+                .any(|texture| {
+                    atlas_update
+                        .changed_textures
+                        .contains(&texture_key(BLOCK_TEXTURES_DIR, &texture))
+                });
+
+            if texture_changed || self.blocks.get(id) != Some(block) {
+                affected_blocks.insert(*id);
+            }
+        }
+
+        for id in self.blocks.keys() {
+            if !reloaded.blocks.contains_key(id) {
+                affected_blocks.insert(*id);
+            }
+        }
+
+        self.blocks = reloaded.blocks;
+        self.block_names = reloaded.block_names;
+        self.items = reloaded.items;
+        self.item_names = reloaded.item_names;
+        self.structures = reloaded.structures;
+        self.entity_models = reloaded.entity_models;
+        self.update_uvs();
+
+        Ok(ReloadSummary {
+            affected_blocks,
+            dirty_slots: atlas_update.dirty_slots,
+        })
+    }
+
+    /// Creates the dictionary from definitions compiled into the binary.
+    pub fn builtin(mod_blocks: Vec<BlockData>) -> Result<Self, ResourceError> {
+        fn parse<T: serde::de::DeserializeOwned>(
+            dir: &str,
+            content: &str,
+        ) -> Result<T, ResourceError> {
+            ron::from_str(content).map_err(|source| ResourceError::Parse {
+                path: Path::new("res").join(dir),
+                source,
+            })
+        }
+
+        let block_data = BUILTIN_BLOCKS
+            .iter()
+            .map(|content| parse("blocks", content))
+            .collect::<Result<_, _>>()?;
+
+        let item_data = BUILTIN_ITEMS
+            .iter()
+            .map(|content| parse("items", content))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self::from_data(block_data, item_data, mod_blocks))
+    }
+
+    fn from_data(
+        block_data: Vec<BlockData>,
+        item_data: Vec<ItemData>,
+        mod_blocks: Vec<BlockData>,
+    ) -> Self {
         let mut blocks = HashMap::new();
         let mut block_names = HashMap::new();
 
-        let block_data = load_block_data();
         for (idx, block) in block_data.into_iter().enumerate() {
             block_names.insert(block.name.clone(), idx as u32);
             blocks.insert(idx as u32, block);
         }
 
+        for block in mod_blocks.iter() {
+            if block_names.contains_key(&block.name) {
+                log::warn!(
+                    "Block {} registered by a mod is already defined, ignoring it",
+                    block.name
+                );
+                continue;
+            }
+
+            let id = blocks.len() as u32;
+            block_names.insert(block.name.clone(), id);
+            blocks.insert(id, block.clone());
+        }
+
+        if !block_names.contains_key(BlockData::MISSING) {
+            let id = blocks.len() as u32;
+            block_names.insert(BlockData::MISSING.to_string(), id);
+            blocks.insert(id, BlockData::missing());
+        }
+
+        let mut block_ticks = HashMap::new();
+
+        for (id, block) in blocks.iter() {
+            let Some(tick) = &block.random_tick else {
+                continue;
+            };
+
+            match BlockTick::resolve(tick, &block_names) {
+                Some(tick) => {
+                    block_ticks.insert(*id, tick);
+                }
+                None => log::warn!(
+                    "Random tick of block {} names a block which is not defined",
+                    block.name
+                ),
+            }
+        }
+
+        let block_lights = blocks
+            .iter()
+            .filter_map(|(id, block)| Some((*id, block.light?)))
+            .collect();
+
+        let fluid_blocks = blocks
+            .iter()
+            .filter(|(_, block)| block.fluid)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut items = HashMap::new();
+        let mut item_names = HashMap::new();
+
+        for (idx, item) in item_data.into_iter().enumerate() {
+            if let Some(block) = &item.places_block {
+                if !block_names.contains_key(block) {
+                    log::warn!(
+                        "Item {} places block {block} which is not defined",
+                        item.name
+                    );
+                }
+            }
+
+            item_names.insert(item.name.clone(), idx as u32);
+            items.insert(idx as u32, item);
+        }
+
         Self {
             blocks,
             block_names,
+            block_uvs: HashMap::new(),
+            block_connected_uvs: HashMap::new(),
+            block_face_uvs: HashMap::new(),
+            block_ticks,
+            block_lights,
+            fluid_blocks,
+            items,
+            item_names,
+            item_uvs: HashMap::new(),
+            atlas: TextureAtlas::new(),
+            mod_blocks,
+            structures: HashMap::new(),
+            entity_models: HashMap::new(),
         }
     }
 
+    /// Adds structures and entity models, the blocks of structure palettes have to be defined already.
+    /// Schematic files replace models with the same name.
+    fn add_structures(&mut self, vox: VoxResources, resource_packs: &ResourcePacks) {
+        for (name, model) in vox.structures {
+            let structure = model.to_schematic(&name, self);
+            self.structures.insert(name, Arc::new(structure));
+        }
+
+        self.entity_models = vox.entities.into_iter().collect();
+
+        // the directory is optional
+        let Ok(paths) = resource_packs.list(Path::new("structures")) else {
+            return;
+        };
+
+        let schematic_paths = paths.into_iter().filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "schematic")
+        });
+
+        for path in schematic_paths {
+            let Some(name) = path.file_stem() else {
+                continue;
+            };
+
+            match Schematic::load(&path, self) {
+                Ok(schematic) => {
+                    let name = name.to_string_lossy().into_owned();
+                    self.structures.insert(name, Arc::new(schematic));
+                }
+                Err(e) => log::error!("{e}, skipping it"),
+            }
+        }
+    }
+
+    /// Loads block textures and item icons referenced by the definitions.
+    fn load_textures(&self, resource_packs: &ResourcePacks) -> HashMap<String, image::RgbaImage> {
+        let mut textures = load_textures(
+            resource_packs,
+            BLOCK_TEXTURES_DIR,
+            self.blocks
+                .values()
+                .flat_map(|block| block.textures().chain(block.face_textures())),
+        );
+
+        textures.extend(load_connected_textures(
+            resource_packs,
+            self.blocks
+                .values()
+                .filter_map(|block| block.connected_texture.as_ref()),
+        ));
+
+        textures.extend(load_textures(
+            resource_packs,
+            ITEM_TEXTURES_DIR,
+            self.items.values().filter_map(|item| item.icon.as_ref()),
+        ));
+
+        textures
+    }
+
+    /// Rebuilds the UV tables from the current atlas layout.
+    fn update_uvs(&mut self) {
+        self.block_uvs = self
+            .blocks
+            .iter()
+            .map(|(id, block)| {
+                let mut uvs: Vec<UvRect> = block
+                    .textures()
+                    .map(|texture| {
+                        self.atlas
+                            .uv(Some(&texture_key(BLOCK_TEXTURES_DIR, texture)))
+                    })
+                    .collect();
+
+                if uvs.is_empty() {
+                    uvs.push(self.atlas.uv(None));
+                }
+
+                (*id, uvs)
+            })
+            .collect();
+
+        self.block_connected_uvs = self
+            .blocks
+            .iter()
+            .filter_map(|(id, block)| {
+                let uvs = block
+                    .connected_tiles()?
+                    .map(|tile| self.atlas.uv(Some(&texture_key(BLOCK_TEXTURES_DIR, &tile))));
+
+                Some((*id, uvs))
+            })
+            .collect();
+
+        self.block_face_uvs = self
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.face_textures().next().is_some())
+            .map(|(id, block)| {
+                let uvs = std::array::from_fn(|face| {
+                    let texture = block.faces.get(face.into(), |face| face.texture.clone())?;
+
+                    Some(
+                        self.atlas
+                            .uv(Some(&texture_key(BLOCK_TEXTURES_DIR, &texture))),
+                    )
+                });
+
+                (*id, uvs)
+            })
+            .collect();
+
+        self.item_uvs = self
+            .items
+            .iter()
+            .filter_map(|(id, item)| {
+                let key = texture_key(ITEM_TEXTURES_DIR, item.icon.as_ref()?);
+
+                Some((*id, self.atlas.uv(Some(&key))))
+            })
+            .collect();
+    }
+
     pub fn get_block_id(&self, name: &str) -> BlockId {
         *self.block_names.get(name).unwrap_or_else(|| {
             panic!("Requested a block with name {name} but its definition is not present")
         })
     }
 
+    /// Returns the ID of a block, or None if no definition with that name is loaded.
+    pub fn find_block_id(&self, name: &str) -> Option<BlockId> {
+        self.block_names.get(name).copied()
+    }
+
+    /// Returns IDs of all loaded blocks by name.
+    pub fn block_ids(&self) -> &HashMap<String, BlockId> {
+        &self.block_names
+    }
+
     pub fn get_block_data_from_name(&self, name: &str) -> BlockData {
         self.blocks.get(&self.get_block_id(name)).unwrap().clone()
     }
@@ -46,23 +693,375 @@ impl ResourceDictionary {
             })
             .clone()
     }
+
+    /// Returns the random tick of a block, or None if it doesn't change over time.
+    pub fn get_block_tick(&self, id: BlockId) -> Option<BlockTick> {
+        self.block_ticks.get(&id).copied()
+    }
+
+    /// Returns the light given off by a block, or None if it doesn't glow.
+    pub fn get_block_light(&self, id: BlockId) -> Option<LightSource> {
+        self.block_lights.get(&id).copied()
+    }
+
+    /// Returns true if a block is drawn as a fluid surface.
+    pub fn is_fluid(&self, id: BlockId) -> bool {
+        self.fluid_blocks.contains(&id)
+    }
+
+    pub fn get_block_uv(&self, id: BlockId) -> UvRect {
+        self.block_uvs
+            .get(&id)
+            .and_then(|uvs| uvs.first())
+            .copied()
+            .unwrap_or_else(|| TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT))
+    }
+
+    /// Returns the UVs of the texture variant used by a block at the given world position.
+    pub fn get_block_uv_at(&self, id: BlockId, position: glam::IVec3) -> UvRect {
+        let Some(uvs) = self.block_uvs.get(&id).filter(|uvs| !uvs.is_empty()) else {
+            return TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT);
+        };
+
+        uvs[position_hash(position) as usize % uvs.len()]
+    }
+
+    /// Returns the UVs of the texture of a single face, or None if the face uses the textures of the block.
+    pub fn get_block_face_uv(&self, id: BlockId, face_dir: FaceDirection) -> Option<UvRect> {
+        self.block_face_uvs.get(&id)?[face_dir as usize]
+    }
+
+    /// Returns the UVs of the connected texture tiles of a block, or None if it has no connected texture.
+    pub fn get_block_connected_uvs(&self, id: BlockId) -> Option<[UvRect; ConnectedTile::COUNT]> {
+        self.block_connected_uvs.get(&id).copied()
+    }
+
+    pub fn get_item_id(&self, name: &str) -> ItemId {
+        *self.item_names.get(name).unwrap_or_else(|| {
+            panic!("Requested an item with name {name} but its definition is not present")
+        })
+    }
+
+    /// Returns the ID of an item, or None if no definition with that name is loaded.
+    pub fn find_item_id(&self, name: &str) -> Option<ItemId> {
+        self.item_names.get(name).copied()
+    }
+
+    /// Returns the item placing a block, the one with the lowest ID if there are more of them.
+    pub fn find_item_for_block(&self, block: &str) -> Option<ItemId> {
+        self.items
+            .iter()
+            .filter(|(_, item)| item.places_block.as_deref() == Some(block))
+            .map(|(id, _)| *id)
+            .min()
+    }
+
+    /// Returns IDs of all loaded items in ascending order.
+    pub fn item_ids(&self) -> Vec<ItemId> {
+        let mut ids: Vec<ItemId> = self.items.keys().copied().collect();
+        ids.sort();
+
+        ids
+    }
+
+    pub fn get_item_data_from_name(&self, name: &str) -> ItemData {
+        self.items.get(&self.get_item_id(name)).unwrap().clone()
+    }
+
+    pub fn get_item_data_from_id(&self, id: ItemId) -> ItemData {
+        self.items
+            .get(&id)
+            .unwrap_or_else(|| {
+                panic!("Requested an item with id {id} but its definition is not present")
+            })
+            .clone()
+    }
+
+    /// Returns the UVs of the icon of an item, or None if it has no icon.
+    pub fn get_item_uv(&self, id: ItemId) -> Option<UvRect> {
+        self.item_uvs.get(&id).copied()
+    }
+
+    pub fn find_structure(&self, name: &str) -> Option<Arc<Schematic>> {
+        self.structures.get(name).cloned()
+    }
+
+    pub fn entity_models(&self) -> &HashMap<String, VoxModel> {
+        &self.entity_models
+    }
+}
+
+pub fn load_block_data(resource_packs: &ResourcePacks) -> Result<Vec<BlockData>, ResourceError> {
+    load_definitions(resource_packs, Path::new("blocks"))
+}
+
+pub fn load_item_data(resource_packs: &ResourcePacks) -> Result<Vec<ItemData>, ResourceError> {
+    load_definitions(resource_packs, Path::new("items"))
+}
+
+/// Parses every RON file of a resource directory, at least one has to be present.
+fn load_definitions<T: serde::de::DeserializeOwned>(
+    resource_packs: &ResourcePacks,
+    root: &Path,
+) -> Result<Vec<T>, ResourceError> {
+    let mut definitions = Vec::new();
+
+    for path in resource_packs.list(root)? {
+        let content = fs::read_to_string(&path).map_err(|source| ResourceError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+        let data: T = ron::from_str(content.as_str())
+            .map_err(|source| ResourceError::Parse { path, source })?;
+
+        definitions.push(data);
+    }
+
+    if definitions.is_empty() {
+        return Err(ResourceError::Empty {
+            path: root.to_path_buf(),
+        });
+    }
+
+    Ok(definitions)
+}
+
+const BLOCK_TEXTURES_DIR: &str = "blocks";
+const ITEM_TEXTURES_DIR: &str = "items";
+
+/// Returns the name a texture is stored under in the atlas, so blocks and items can share texture names.
+fn texture_key(dir: &str, name: &str) -> String {
+    format!("{dir}/{name}")
+}
+
+/// Loads textures from a subdirectory of `textures`, keyed by `texture_key`.
+/// Textures which cannot be loaded are skipped, so their blocks are drawn with plain colors.
+pub fn load_textures<'a>(
+    resource_packs: &ResourcePacks,
+    dir: &str,
+    names: impl Iterator<Item = &'a String>,
+) -> HashMap<String, image::RgbaImage> {
+    let names: HashSet<&String> = names.collect();
+    let mut textures = HashMap::new();
+
+    for name in names {
+        let size = TextureAtlas::TILE_SIZE;
+
+        if let Some(texture) = load_texture_file(resource_packs, dir, name, size, size) {
+            textures.insert(texture_key(dir, name), texture);
+        }
+    }
+
+    textures
+}
+
+/// Loads connected texture strips of blocks and splits them into tiles keyed by `connected_tile_name`.
+pub fn load_connected_textures<'a>(
+    resource_packs: &ResourcePacks,
+    names: impl Iterator<Item = &'a String>,
+) -> HashMap<String, image::RgbaImage> {
+    let names: HashSet<&String> = names.collect();
+    let mut textures = HashMap::new();
+
+    for name in names {
+        let size = TextureAtlas::TILE_SIZE;
+        let width = size * ConnectedTile::COUNT as u32;
+
+        let Some(strip) = load_texture_file(resource_packs, BLOCK_TEXTURES_DIR, name, width, size)
+        else {
+            continue;
+        };
+
+        for idx in 0..ConnectedTile::COUNT {
+            let tile = image::imageops::crop_imm(&strip, idx as u32 * size, 0, size, size);
+
+            textures.insert(
+                texture_key(BLOCK_TEXTURES_DIR, &connected_tile_name(name, idx)),
+                tile.to_image(),
+            );
+        }
+    }
+
+    textures
+}
+
+/// Loads a texture file, resizing it if it doesn't have the expected dimensions.
+fn load_texture_file(
+    resource_packs: &ResourcePacks,
+    dir: &str,
+    name: &str,
+    width: u32,
+    height: u32,
+) -> Option<image::RgbaImage> {
+    let relative = Path::new("textures").join(dir).join(format!("{name}.png"));
+    let Some(path) = resource_packs.find(&relative) else {
+        log::warn!("Texture {} not found", relative.display());
+        return None;
+    };
+
+    let mut texture = match image::open(&path) {
+        Ok(texture) => texture.to_rgba8(),
+        Err(e) => {
+            log::warn!("Failed to load texture {}: {e}", path.display());
+            return None;
+        }
+    };
+
+    if texture.dimensions() != (width, height) {
+        log::warn!(
+            "Texture {} is not {width}x{height}, resizing it",
+            path.display()
+        );
+
+        texture = image::imageops::resize(
+            &texture,
+            width,
+            height,
+            image::imageops::FilterType::Nearest,
+        );
+    }
+
+    Some(texture)
 }
 
-pub fn load_block_data() -> Vec<BlockData> {
-    let root = "res/blocks";
-    let paths = fs::read_dir(root).unwrap_or_else(|_| panic!("Directory {root} not found"));
+/// Loads a shader source from the `shaders` directory of the resource packs.
+pub fn load_shader_source(
+    resource_packs: &ResourcePacks,
+    name: &str,
+) -> Result<String, ResourceError> {
+    let relative = Path::new("shaders").join(name);
 
-    let mut blocks = Vec::new();
+    let path = resource_packs
+        .find(&relative)
+        .ok_or_else(|| ResourceError::Io {
+            path: relative.clone(),
+            source: io::ErrorKind::NotFound.into(),
+        })?;
 
-    for file in paths {
-        let path = file.unwrap().path();
-        let content = fs::read_to_string(path.clone()).unwrap();
+    fs::read_to_string(&path).map_err(|source| ResourceError::Io { path, source })
+}
 
-        let data: BlockData = ron::from_str(content.as_str())
-            .unwrap_or_else(|e| panic!("Failed to parse file {}: {e}", path.display()));
+/// Returns the files included by the built-in shaders.
+pub fn builtin_shader_includes() -> ShaderIncludes {
+    BUILTIN_SHADER_INCLUDES
+        .iter()
+        .map(|(name, source)| (name.to_string(), source.to_string()))
+        .collect()
+}
 
-        blocks.push(data);
+/// Reloads resource packs listed in the settings file on request.
+/// Only chunks containing blocks whose definition or texture changed are remeshed.
+#[allow(clippy::too_many_arguments)]
+pub fn reload_resources_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    mut settings: UniqueViewMut<Settings>,
+    mut resource_packs: UniqueViewMut<ResourcePacks>,
+    mut resource_dictionary: UniqueViewMut<ResourceDictionary>,
+    mut renderer: UniqueViewMut<Renderer>,
+    mut spawner: UniqueViewMut<MobSpawner>,
+    mut mob_definitions: UniqueViewMut<MobDefinitions>,
+    game_map: UniqueView<GameMap>,
+    chunks: View<ChunkTag>,
+    mut missing_models: ViewMut<MissingModel>,
+) {
+    if !input_state.reload_resources {
+        return;
     }
 
-    blocks
+    input_state.reload_resources = false;
+
+    // packs may have been enabled or disabled since startup
+    settings.resource_packs = Settings::load().resource_packs;
+    *resource_packs = ResourcePacks::new(&settings.resource_packs);
+
+    let summary = match resource_dictionary.reload(&resource_packs) {
+        Ok(summary) => summary,
+        Err(e) => {
+            log::error!("{e}, keeping the current block definitions");
+            return;
+        }
+    };
+
+    renderer.reload_shaders(&resource_packs);
+    renderer.write_atlas(&resource_dictionary.atlas, &summary.dirty_slots);
+    renderer.update_entity_models(&resource_dictionary);
+
+    match SpawnRules::load(&resource_packs) {
+        Ok(rules) => spawner.rules = rules,
+        Err(e) => log::error!("{e}, keeping the current spawn rules"),
+    }
+
+    match MobDefinitions::load(&resource_packs) {
+        Ok(definitions) => *mob_definitions = definitions,
+        Err(e) => log::error!("{e}, keeping the current mob definitions"),
+    }
+
+    let mut remeshed = 0;
+
+    for (id, chunk_tag) in chunks.iter().with_id() {
+        let Some(chunk) = game_map.chunks.get(&chunk_tag.coords) else {
+            continue;
+        };
+
+        if chunk.contains_any(&summary.affected_blocks) {
+            missing_models.add_component_unchecked(id, MissingModel);
+            remeshed += 1;
+        }
+    }
+
+    log::info!(
+        "Reloaded resource packs, {} blocks changed, remeshing {remeshed} chunks",
+        summary.affected_blocks.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(name: &str, version: Option<&str>) -> PinnedPack {
+        PinnedPack {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn world_without_pinned_packs() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(PinnedPack::load_world_packs(dir.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn pinned_packs_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let packs = vec![pack("faithful", Some("1.2")), pack("default", None)];
+
+        PinnedPack::pin_world_packs(dir.path(), &packs).unwrap();
+
+        assert_eq!(
+            PinnedPack::load_world_packs(dir.path()).unwrap(),
+            Some(packs)
+        );
+    }
+
+    #[test]
+    fn corrupt_pinned_packs_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(PinnedPack::FILE_NAME), "[(name: ").unwrap();
+
+        let error = PinnedPack::load_world_packs(dir.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn unreadable_pinned_packs_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // a directory in place of the file can't be read as one
+        fs::create_dir(dir.path().join(PinnedPack::FILE_NAME)).unwrap();
+
+        assert!(PinnedPack::load_world_packs(dir.path()).is_err());
+    }
 }
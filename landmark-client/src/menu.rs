@@ -0,0 +1,197 @@
+use shipyard::*;
+
+use crate::{
+    bindings::InputContext,
+    camera::Camera,
+    health::{Damage, DamageCause, DamageEvents, Health},
+    input::InputState,
+    player::LocalPlayer,
+    save::WorldSave,
+    teleport::PendingTeleport,
+};
+
+/// Players below this height die, nothing is generated that deep.
+const VOID_DEPTH: f64 = -256.0;
+/// Size of a menu button in pixels.
+pub const BUTTON_SIZE: glam::Vec2 = glam::Vec2::new(320.0, 40.0);
+pub const BUTTON_GAP: f32 = 8.0;
+
+/// Screen drawn over the world which takes over the mouse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Screen {
+    /// No screen is open.
+    #[default]
+    Playing,
+    Paused,
+    /// The whole inventory, the world keeps running behind it.
+    Inventory,
+    /// The map of the explored world, the world keeps running behind it.
+    Map,
+    Dead,
+    /// Shown after quitting a world, the world is empty and not updated.
+    Title,
+}
+
+impl Screen {
+    pub fn heading(self) -> &'static str {
+        match self {
+            Screen::Playing => "",
+            Screen::Paused => "Game paused",
+            Screen::Inventory => "",
+            Screen::Map => "",
+            Screen::Dead => "You died",
+            Screen::Title => "Landmark",
+        }
+    }
+
+    /// Buttons of the screen from top to bottom.
+    pub fn buttons(self) -> &'static [(&'static str, MenuAction)] {
+        match self {
+            Screen::Playing | Screen::Inventory | Screen::Map => &[],
+            Screen::Paused => &[
+                ("Resume", MenuAction::Resume),
+                ("Save and quit to title", MenuAction::SaveAndQuit),
+            ],
+            Screen::Dead => &[
+                ("Respawn", MenuAction::Respawn),
+                ("Save and quit to title", MenuAction::SaveAndQuit),
+            ],
+            Screen::Title => &[("Play", MenuAction::Play), ("Quit game", MenuAction::Quit)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Resume,
+    Respawn,
+    /// Saves the session and replaces the world with the title screen.
+    SaveAndQuit,
+    /// Starts playing from the title screen.
+    Play,
+    /// Exits the game.
+    Quit,
+}
+
+#[derive(Debug, Default, Unique)]
+pub struct Menu {
+    pub screen: Screen,
+    /// Action of the clicked button. Handled by the game after the update,
+    /// because quitting replaces the whole world.
+    pub chosen: Option<MenuAction>,
+}
+
+impl Menu {
+    pub fn is_open(&self) -> bool {
+        self.screen != Screen::Playing
+    }
+
+    /// Opens a screen, releasing the cursor so its buttons can be clicked.
+    pub fn open(&mut self, screen: Screen, input_state: &mut InputState) {
+        self.screen = screen;
+        input_state.stop_movement();
+        input_state.cursor_captured = false;
+        input_state.context = InputContext::Ui;
+    }
+
+    /// Closes the open screen and returns to the game.
+    pub fn close(&mut self, input_state: &mut InputState) {
+        self.screen = Screen::Playing;
+        input_state.cursor_captured = true;
+        input_state.context = InputContext::Gameplay;
+    }
+
+    /// Returns the index of the button under the cursor.
+    pub fn button_at(&self, screen_size: glam::Vec2, cursor: glam::Vec2) -> Option<usize> {
+        let count = self.screen.buttons().len();
+
+        (0..count).find(|idx| {
+            let (min, max) = button_rect(screen_size, *idx, count);
+
+            cursor.cmpge(min).all() && cursor.cmplt(max).all()
+        })
+    }
+
+    /// Chooses the action of the button under the cursor, if there is one.
+    pub fn click(&mut self, screen_size: glam::Vec2, cursor: glam::Vec2) {
+        if let Some(idx) = self.button_at(screen_size, cursor) {
+            self.chosen = Some(self.screen.buttons()[idx].1);
+        }
+    }
+}
+
+/// Returns the corners of a button, buttons of a screen are stacked below the middle of the window.
+pub fn button_rect(screen_size: glam::Vec2, idx: usize, count: usize) -> (glam::Vec2, glam::Vec2) {
+    let height = count as f32 * (BUTTON_SIZE.y + BUTTON_GAP) - BUTTON_GAP;
+    let top = screen_size.y / 2.0 + (screen_size.y / 2.0 - height) / 3.0;
+
+    let min = glam::Vec2::new(
+        (screen_size.x - BUTTON_SIZE.x) / 2.0,
+        top + idx as f32 * (BUTTON_SIZE.y + BUTTON_GAP),
+    );
+
+    (min, min + BUTTON_SIZE)
+}
+
+/// Kills the player, who can then only respawn or quit.
+pub fn kill_player(menu: &mut Menu, input_state: &mut InputState) {
+    if menu.screen != Screen::Dead {
+        menu.open(Screen::Dead, input_state);
+    }
+}
+
+/// Kills the player falling out of the world, unless they are about to be teleported.
+pub fn player_death_sys(
+    camera: UniqueView<Camera>,
+    menu: UniqueView<Menu>,
+    teleport: UniqueView<PendingTeleport>,
+    mut damage_events: UniqueViewMut<DamageEvents>,
+    local_players: View<LocalPlayer>,
+) {
+    if !matches!(menu.screen, Screen::Playing | Screen::Inventory)
+        || camera.eye.y >= VOID_DEPTH
+        || teleport.is_pending()
+    {
+        return;
+    }
+
+    for entity in local_players.iter().ids() {
+        damage_events.push(Damage {
+            entity,
+            amount: u32::MAX,
+            cause: DamageCause::Void,
+        });
+    }
+}
+
+/// Applies menu actions which don't replace the world.
+pub fn menu_action_sys(
+    action: MenuAction,
+    mut menu: UniqueViewMut<Menu>,
+    mut input_state: UniqueViewMut<InputState>,
+    mut camera: UniqueViewMut<Camera>,
+    world_save: UniqueView<WorldSave>,
+    mut teleport: UniqueViewMut<PendingTeleport>,
+    (local_players, mut healths): (View<LocalPlayer>, ViewMut<Health>),
+) {
+    match action {
+        MenuAction::Resume | MenuAction::Play => menu.close(&mut input_state),
+        MenuAction::Respawn => {
+            for (_, health) in (&local_players, &mut healths).iter() {
+                *health = Health::full(health.max);
+            }
+
+            // the server decides where the spawn point is while connected
+            if teleport.connected {
+                teleport.request_respawn();
+            } else {
+                camera.eye = world_save.spawn_point();
+            }
+            camera.yaw = 0.0;
+            camera.pitch = 0.0;
+            menu.close(&mut input_state);
+        }
+        // handled by the game
+        MenuAction::SaveAndQuit | MenuAction::Quit => {}
+    }
+}
@@ -1,19 +1,114 @@
 use shipyard::*;
 
 use crate::{
+    atlas::UvRect,
+    block::ConnectedTile,
+    camera::Camera,
     color::Color,
-    game_map::{Chunk, ChunkCoords, ChunkTag, FaceDirection, GameMap, InnerChunkCoords},
+    coords,
+    game_map::{BlockId, Chunk, ChunkCoords, ChunkTag, FaceDirection, GameMap, InnerChunkCoords},
+    lights::ChunkLights,
     loader::ResourceDictionary,
     model::{MissingModel, ModelConstructor, UpdatedModel, Vertex},
-    transform::Transform,
+    priority::{ChunkJobQueue, ChunkPriority},
+    water::UpdatedFluidModel,
 };
 
-trait ModelConstructorChunkExt {
-    fn add_block_face(&mut self, coords: InnerChunkCoords, face_dir: FaceDirection, color: Color);
+pub trait ModelConstructorChunkExt {
+    fn add_block_face(
+        &mut self,
+        coords: InnerChunkCoords,
+        face_dir: FaceDirection,
+        color: Color,
+        uv: UvRect,
+    );
+
+    /// Adds the part of a face between `min` and `max`, given in texture coordinates of the face from 0 to 1.
+    /// The texture of the whole face is `uv`, only the matching part of it is used.
+    fn add_block_face_part(
+        &mut self,
+        coords: InnerChunkCoords,
+        face_dir: FaceDirection,
+        color: Color,
+        uv: UvRect,
+        min: glam::Vec2,
+        max: glam::Vec2,
+    );
+}
+
+/// Rotation of a face built facing positive Y, so it faces `face_dir`.
+/// The X axis of the unrotated face follows the U texture coordinate and Z follows V.
+fn face_rotation(face_dir: FaceDirection) -> glam::Quat {
+    match face_dir {
+        FaceDirection::PosX => {
+            // rotate ccw along Y and ccw along Z
+            glam::Quat::from_euler(
+                glam::EulerRot::XZY,
+                0.0,
+                -90f32.to_radians(),
+                -90f32.to_radians(),
+            )
+        }
+        FaceDirection::NegX => {
+            // rotate cw along Y and cw along Z
+            glam::Quat::from_euler(
+                glam::EulerRot::XZY,
+                0.0,
+                90f32.to_radians(),
+                90f32.to_radians(),
+            )
+        }
+        FaceDirection::PosY => {
+            // already rotated correctly
+            glam::Quat::IDENTITY
+        }
+        FaceDirection::NegY => {
+            // rotate 180 along X
+            glam::Quat::from_euler(glam::EulerRot::ZYX, 0.0, 0.0, 180f32.to_radians())
+        }
+        FaceDirection::PosZ => {
+            // rotate 180 along Y and cw along X
+            glam::Quat::from_euler(
+                glam::EulerRot::ZXY,
+                0.0,
+                90f32.to_radians(),
+                180f32.to_radians(),
+            )
+        }
+        FaceDirection::NegZ => {
+            // rotate ccw along X
+            glam::Quat::from_euler(glam::EulerRot::ZYX, 0.0, 0.0, -90f32.to_radians())
+        }
+    }
 }
 
 impl ModelConstructorChunkExt for ModelConstructor {
-    fn add_block_face(&mut self, coords: InnerChunkCoords, face_dir: FaceDirection, color: Color) {
+    fn add_block_face(
+        &mut self,
+        coords: InnerChunkCoords,
+        face_dir: FaceDirection,
+        color: Color,
+        uv: UvRect,
+    ) {
+        self.add_block_face_part(
+            coords,
+            face_dir,
+            color,
+            uv,
+            glam::Vec2::ZERO,
+            glam::Vec2::ONE,
+        );
+    }
+
+    fn add_block_face_part(
+        &mut self,
+        coords: InnerChunkCoords,
+        face_dir: FaceDirection,
+        color: Color,
+        uv: UvRect,
+        min: glam::Vec2,
+        max: glam::Vec2,
+    ) {
         // 2-----3
         // |\    |
         // | \ B |
@@ -25,72 +120,35 @@ impl ModelConstructorChunkExt for ModelConstructor {
         // A: 0 1 2
         // B: 2 1 3
 
+        // face coordinates of the points
+        let corners = [
+            min,
+            glam::Vec2::new(max.x, min.y),
+            glam::Vec2::new(min.x, max.y),
+            max,
+        ];
+
         // create face at the center of coordinate system facing positive Y and rotate it
-        let a = glam::Vec3::new(-0.5, 0.5, -0.5);
-        let b = glam::Vec3::new(0.5, 0.5, -0.5);
-        let c = glam::Vec3::new(-0.5, 0.5, 0.5);
-        let d = glam::Vec3::new(0.5, 0.5, 0.5);
-
-        // pack points into vector to simplify rotations
-        let mut points = vec![a, b, c, d];
-
-        let rot = match face_dir {
-            FaceDirection::PosX => {
-                // rotate ccw along Y and ccw along Z
-                glam::Quat::from_euler(
-                    glam::EulerRot::XZY,
-                    0.0,
-                    -90f32.to_radians(),
-                    -90f32.to_radians(),
-                )
-            }
-            FaceDirection::NegX => {
-                // rotate cw along Y and cw along Z
-                glam::Quat::from_euler(
-                    glam::EulerRot::XZY,
-                    0.0,
-                    90f32.to_radians(),
-                    90f32.to_radians(),
-                )
-            }
-            FaceDirection::PosY => {
-                // already rotated correctly
-                glam::Quat::IDENTITY
-            }
-            FaceDirection::NegY => {
-                // rotate 180 along X
-                glam::Quat::from_euler(glam::EulerRot::ZYX, 0.0, 0.0, 180f32.to_radians())
-            }
-            FaceDirection::PosZ => {
-                // rotate 180 along Y and cw along X
-                glam::Quat::from_euler(
-                    glam::EulerRot::ZXY,
-                    0.0,
-                    90f32.to_radians(),
-                    180f32.to_radians(),
-                )
-            }
-            FaceDirection::NegZ => {
-                // rotate ccw along X
-                glam::Quat::from_euler(glam::EulerRot::ZYX, 0.0, 0.0, -90f32.to_radians())
-            }
-        };
+        let rot = face_rotation(face_dir);
 
-        // rotate them to face correct direction
-        points = points.into_iter().map(|p| rot * p).collect();
+        let points = corners.map(|corner| {
+            let point = glam::Vec3::new(corner.x - 0.5, 0.5, corner.y - 0.5);
 
-        // translate them to corrent position
-        points = points
-            .into_iter()
-            .map(|p| p + coords.as_block_center())
-            .collect();
+            // rotate them to face correct direction and translate them to correct position
+            rot * point + coords.as_block_center()
+        });
+
+        // texture coordinates of the points in the same order
+        let uvs = corners.map(|corner| uv.min + (uv.max - uv.min) * corner);
 
         // produce vertices from the calculated points
         let mut vertices: Vec<Vertex> = points
             .into_iter()
-            .map(|p| Vertex {
+            .zip(uvs)
+            .map(|(p, uv)| Vertex {
                 position: p,
                 color: color.into(),
+                uv,
             })
             .collect();
 
@@ -113,71 +171,228 @@ impl ModelConstructorChunkExt for ModelConstructor {
     }
 }
 
+/// Marks the chunk containing a changed block for remeshing, together with
+/// neighbouring chunks if the block lies on the border of its chunk.
+pub fn remesh_around_block(
+    game_map: &GameMap,
+    missing_models: &mut ViewMut<MissingModel>,
+    position: glam::IVec3,
+) {
+    let (coords, inner) = coords::split_block(position);
+    let inner = inner.as_ivec3();
+    let mut remeshed = vec![coords];
+
+    for axis in 0..3 {
+        let mut offset = glam::IVec3::ZERO;
+
+        if inner[axis] == 0 {
+            offset[axis] = -1;
+        } else if inner[axis] == Chunk::SIZE - 1 {
+            offset[axis] = 1;
+        } else {
+            continue;
+        }
+
+        remeshed.push(coords + ChunkCoords::new(offset.x, offset.y, offset.z));
+    }
+
+    for coords in remeshed {
+        if !game_map.chunks.contains_key(&coords) {
+            continue;
+        }
+
+        if let Some(id) = game_map.chunk_entity_map.get(&coords) {
+            missing_models.add_component_unchecked(*id, MissingModel);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ConstructedChunk {
     pub coords: ChunkCoords,
     pub model_constructor: ModelConstructor,
 }
 
+/// Meshes of a chunk, fluids are drawn by their own pipeline after the rest of the world.
+#[derive(Debug)]
+pub struct ChunkMeshes {
+    pub solid: ModelConstructor,
+    pub fluid: ModelConstructor,
+}
+
 #[derive(Debug, Clone)]
 pub struct MeshChunkRequest<'a> {
-    pub requested_coords: ChunkCoords,
+    pub coords: ChunkCoords,
     pub requested_chunk: &'a Chunk,
     pub adjacent_chunks: Vec<Option<&'a Chunk>>,
+    pub neighbors: NeighborView<'a>,
+}
+
+/// Blocks of a chunk and of the 26 chunks around it, addressed relative to the origin of the chunk.
+#[derive(Debug, Clone)]
+pub struct NeighborView<'a> {
+    /// Indexed by the chunk offset from -1 to 1 on each axis, X changing fastest.
+    chunks: [Option<&'a Chunk>; 27],
+}
+
+impl<'a> NeighborView<'a> {
+    pub fn new(game_map: &'a GameMap, coords: ChunkCoords) -> Self {
+        let mut chunks = [None; 27];
+
+        for (idx, chunk) in chunks.iter_mut().enumerate() {
+            let offset = ChunkCoords::new(
+                idx as i32 % 3 - 1,
+                idx as i32 / 3 % 3 - 1,
+                idx as i32 / 9 - 1,
+            );
+            *chunk = game_map.chunks.get(&(coords + offset));
+        }
+
+        Self { chunks }
+    }
+
+    /// Returns the block at a position relative to the chunk origin.
+    /// Positions outside of the neighbouring chunks or in unloaded chunks are empty.
+    pub fn get_block(&self, position: glam::IVec3) -> Option<BlockId> {
+        let (offset, inner) = coords::split_block(position);
+        let offset = offset.as_ivec3();
+
+        if offset.abs().max_element() > 1 {
+            return None;
+        }
+
+        let idx = (offset.x + 1) + (offset.y + 1) * 3 + (offset.z + 1) * 9;
+
+        self.chunks[idx as usize]?.get_block(inner)
+    }
 }
 
+/// Brightness of blocks giving off light relative to their color, above the bloom threshold so they glow.
+const EMISSIVE_BRIGHTNESS: f32 = 4.0;
+
+/// Limits meshing work done in a single update to avoid stutters.
+const MAX_MESHES_PER_UPDATE: usize = 8;
+
+/// Meshes chunks missing a model, the ones closest to the camera and inside the view first.
+#[allow(clippy::too_many_arguments)]
 pub fn chunk_mesher_sys(
+    camera: UniqueView<Camera>,
     game_map: UniqueView<GameMap>,
     resource_dictionary: UniqueView<ResourceDictionary>,
     chunks: View<ChunkTag>,
     mut missing_models: ViewMut<MissingModel>,
     mut updated_models: ViewMut<UpdatedModel>,
+    mut updated_fluid_models: ViewMut<UpdatedFluidModel>,
+    mut chunk_lights: ViewMut<ChunkLights>,
 ) {
-    let mut processed_chunks: Vec<(EntityId, ModelConstructor)> = Vec::new();
+    let priority = ChunkPriority::new(&camera);
+    let mut queue = ChunkJobQueue::new();
 
-    for (id, (chunk, _)) in (&chunks, &missing_models).iter().with_id() {
-        let requested_coords = chunk.coords;
-        let requested_chunk = game_map.chunks.get(&requested_coords).unwrap();
+    for (chunk, _) in (&chunks, &missing_models).iter() {
+        queue.push(chunk.coords, &priority);
+    }
 
-        let mut adjacent_chunks = Vec::with_capacity(6);
-        for face in 0..6 {
-            let dir = FaceDirection::from(face);
-            let offset = ChunkCoords::from(dir);
+    let mut processed_chunks: Vec<(EntityId, ChunkMeshes, ChunkLights)> = Vec::new();
 
-            adjacent_chunks.push(game_map.chunks.get(&(requested_coords + offset)));
+    while let Some(requested_coords) = queue.pop() {
+        if processed_chunks.len() >= MAX_MESHES_PER_UPDATE {
+            break;
         }
 
-        let request = MeshChunkRequest {
+        let id = game_map.chunk_entity_map[&requested_coords];
+        let meshes = mesh_loaded_chunk(&game_map, requested_coords, &resource_dictionary);
+        let lights = ChunkLights::new(
+            &game_map.chunks[&requested_coords],
             requested_coords,
-            requested_chunk,
-            adjacent_chunks,
-        };
-
-        let model_constructor = mesh_chunk(&request, &resource_dictionary);
+            &resource_dictionary,
+        );
 
-        processed_chunks.push((id, model_constructor));
+        processed_chunks.push((id, meshes, lights));
     }
 
-    for (id, model_constructor) in processed_chunks.into_iter() {
+    for (id, meshes, lights) in processed_chunks.into_iter() {
         missing_models.delete(id);
-        updated_models.add_component_unchecked(id, UpdatedModel(model_constructor))
+        updated_models.add_component_unchecked(id, UpdatedModel(meshes.solid));
+        updated_fluid_models.add_component_unchecked(id, UpdatedFluidModel(meshes.fluid));
+        chunk_lights.add_component_unchecked(id, lights);
+    }
+}
+
+/// Builds the meshes of a chunk in the map, faces next to other chunks in the map are hidden.
+/// Vertices are relative to the origin of the chunk.
+pub fn mesh_loaded_chunk(
+    game_map: &GameMap,
+    coords: ChunkCoords,
+    resource_dictionary: &ResourceDictionary,
+) -> ChunkMeshes {
+    let requested_chunk = game_map.chunks.get(&coords).unwrap();
+
+    let mut adjacent_chunks = Vec::with_capacity(6);
+    for face in 0..6 {
+        let dir = FaceDirection::from(face);
+        let offset = ChunkCoords::from(dir);
+
+        adjacent_chunks.push(game_map.chunks.get(&(coords + offset)));
     }
+
+    let request = MeshChunkRequest {
+        coords,
+        requested_chunk,
+        adjacent_chunks,
+        neighbors: NeighborView::new(game_map, coords),
+    };
+
+    mesh_chunk(&request, resource_dictionary)
 }
 
 /// Stores visibility of each face of each block in a chunk.
 type FaceVisibilityMap = Vec<[bool; 6]>;
 
-fn generate_visibility_map(request: &MeshChunkRequest) -> FaceVisibilityMap {
+/// Returns true if the block touches a face of its chunk.
+fn is_on_border(x: i32, y: i32, z: i32) -> bool {
+    [x, y, z]
+        .iter()
+        .any(|value| *value == 0 || *value == Chunk::SIZE - 1)
+}
+
+/// Returns true if the chunk is filled with a single solid block and so are all of its neighbours,
+/// none of its faces can be seen then.
+fn is_enclosed(request: &MeshChunkRequest, resource_dictionary: &ResourceDictionary) -> bool {
+    let is_solid_filled = |chunk: &Chunk| {
+        chunk
+            .uniform_block()
+            .flatten()
+            .is_some_and(|block| !resource_dictionary.is_fluid(block))
+    };
+
+    is_solid_filled(request.requested_chunk)
+        && request
+            .adjacent_chunks
+            .iter()
+            .all(|chunk| chunk.is_some_and(is_solid_filled))
+}
+
+/// Faces are visible next to empty blocks and fluids, but faces between two fluid blocks are hidden.
+fn generate_visibility_map(
+    request: &MeshChunkRequest,
+    resource_dictionary: &ResourceDictionary,
+) -> FaceVisibilityMap {
     let mut visibility_map: FaceVisibilityMap = vec![[false; 6]; Chunk::BLOCKS_COUNT as usize];
+    let is_filled = request.requested_chunk.uniform_block().is_some();
 
     for z in 0..Chunk::SIZE {
         for y in 0..Chunk::SIZE {
             for x in 0..Chunk::SIZE {
+                if is_filled && !is_on_border(x, y, z) {
+                    continue;
+                }
+
                 // TODO: This function should check transparency of adjacent blocks
                 let coords = InnerChunkCoords::new(x, y, z);
-                if request.requested_chunk.get_block(coords).is_none() {
+                let Some(block) = request.requested_chunk.get_block(coords) else {
                     continue;
-                }
+                };
+                let is_fluid = resource_dictionary.is_fluid(block);
 
                 for face in 0..6 {
                     let dir = FaceDirection::from(face);
@@ -214,9 +429,12 @@ fn generate_visibility_map(request: &MeshChunkRequest) -> FaceVisibilityMap {
                     }
 
                     if let Some(chunk) = checked_chunk {
-                        if chunk.get_block(checked_coords).is_none() {
-                            visibility_map[coords.as_idx()][face] = true;
-                        }
+                        visibility_map[coords.as_idx()][face] = match chunk
+                            .get_block(checked_coords)
+                        {
+                            Some(neighbor) => !is_fluid && resource_dictionary.is_fluid(neighbor),
+                            None => true,
+                        };
                     }
                 }
             }
@@ -226,29 +444,152 @@ fn generate_visibility_map(request: &MeshChunkRequest) -> FaceVisibilityMap {
     visibility_map
 }
 
-fn mesh_chunk(
-    request: &MeshChunkRequest,
-    resource_dictionary: &ResourceDictionary,
-) -> ModelConstructor {
-    let mut model_constructor = ModelConstructor::new();
+/// Bit of each of the 8 blocks around a face in the connection mask, indexed by the offset
+/// along the U and V axes of the face from -1 to 1, U changing fastest. The center has no bit.
+const CONNECTION_BITS: [u8; 9] = [0, 1, 2, 3, 0, 4, 5, 6, 7];
+
+/// Returns a mask of the blocks around a face which are the same as the block of the face.
+fn connection_mask(
+    neighbors: &NeighborView,
+    block: BlockId,
+    position: glam::IVec3,
+    face_dir: FaceDirection,
+) -> u8 {
+    let rot = face_rotation(face_dir);
+    let u_axis = (rot * glam::Vec3::X).round().as_ivec3();
+    let v_axis = (rot * glam::Vec3::Z).round().as_ivec3();
+
+    let mut mask = 0;
+
+    for dv in -1..=1 {
+        for du in -1..=1 {
+            if du == 0 && dv == 0 {
+                continue;
+            }
+
+            let neighbor = position + u_axis * du + v_axis * dv;
+
+            if neighbors.get_block(neighbor) == Some(block) {
+                mask |= 1 << CONNECTION_BITS[((dv + 1) * 3 + du + 1) as usize];
+            }
+        }
+    }
+
+    mask
+}
+
+/// Returns the connected texture tile of a quarter of a face, given which of the neighbours
+/// next to it along U and V and in its corner are connected.
+fn connected_tile(u_side: bool, v_side: bool, corner: bool) -> usize {
+    match (u_side, v_side, corner) {
+        (false, false, _) => ConnectedTile::Isolated as usize,
+        (true, false, _) => ConnectedTile::AlongU as usize,
+        (false, true, _) => ConnectedTile::AlongV as usize,
+        (true, true, false) => ConnectedTile::InnerCorner as usize,
+        (true, true, true) => ConnectedTile::Inside as usize,
+    }
+}
+
+/// Adds a face split into quarters, each textured by the neighbours around its corner.
+fn add_connected_face(
+    model_constructor: &mut ModelConstructor,
+    neighbors: &NeighborView,
+    block: BlockId,
+    coords: InnerChunkCoords,
+    face_dir: FaceDirection,
+    color: Color,
+    tiles: &[UvRect; ConnectedTile::COUNT],
+) {
+    let position = coords.as_ivec3();
+    let mask = connection_mask(neighbors, block, position, face_dir);
+    let connected =
+        |du: i32, dv: i32| mask & (1 << CONNECTION_BITS[((dv + 1) * 3 + du + 1) as usize]) != 0;
+
+    for (qu, qv) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        // direction of the neighbours on the side of the quarter
+        let du = if qu == 0 { -1 } else { 1 };
+        let dv = if qv == 0 { -1 } else { 1 };
+
+        let tile = connected_tile(connected(du, 0), connected(0, dv), connected(du, dv));
+        let min = glam::Vec2::new(qu as f32, qv as f32) * 0.5;
+
+        model_constructor.add_block_face_part(coords, face_dir, color, tiles[tile], min, min + 0.5);
+    }
+}
 
-    model_constructor.transform = Transform {
-        rotation: glam::Quat::IDENTITY,
-        translation: request.requested_coords.as_translation(),
+fn mesh_chunk(request: &MeshChunkRequest, resource_dictionary: &ResourceDictionary) -> ChunkMeshes {
+    // the transforms are left at the origin, chunks are placed relative to the camera when rendering
+    let mut meshes = ChunkMeshes {
+        solid: ModelConstructor::new(),
+        fluid: ModelConstructor::new(),
     };
 
-    let visibility_map = generate_visibility_map(request);
+    if request.requested_chunk.is_empty() || is_enclosed(request, resource_dictionary) {
+        return meshes;
+    }
+
+    // Faces inside of a chunk filled with a single block are always hidden, only its outer shell is meshed.
+    // The shell keeps a face per block rather than a single box, the atlas can't repeat a texture across
+    // a larger face.
+    let is_filled = request.requested_chunk.uniform_block().is_some();
+
+    let visibility_map = generate_visibility_map(request, resource_dictionary);
+    let chunk_position = coords::chunk_origin(request.coords);
 
     for z in 0..Chunk::SIZE {
         for y in 0..Chunk::SIZE {
             for x in 0..Chunk::SIZE {
+                if is_filled && !is_on_border(x, y, z) {
+                    continue;
+                }
+
                 let coords = InnerChunkCoords::new(x, y, z);
 
                 if let Some(block) = request.requested_chunk.get_block(coords) {
+                    let position = chunk_position + glam::IVec3::new(x, y, z);
+                    let connected_uvs = resource_dictionary.get_block_connected_uvs(block);
+                    let model_constructor = if resource_dictionary.is_fluid(block) {
+                        &mut meshes.fluid
+                    } else {
+                        &mut meshes.solid
+                    };
+                    let first_vertex = model_constructor.vertices.len();
+
                     for face in 0..6 {
                         if visibility_map[coords.as_idx()][face] {
-                            let color = resource_dictionary.get_block_data_from_id(block).color;
-                            model_constructor.add_block_face(coords, face.into(), color);
+                            let face_dir = FaceDirection::from(face);
+                            let color = resource_dictionary
+                                .get_block_data_from_id(block)
+                                .face_color(face_dir);
+
+                            // textures of single faces replace the connected texture too
+                            if let Some(uv) = resource_dictionary.get_block_face_uv(block, face_dir)
+                            {
+                                model_constructor.add_block_face(coords, face_dir, color, uv);
+                                continue;
+                            }
+
+                            if let Some(tiles) = connected_uvs {
+                                add_connected_face(
+                                    model_constructor,
+                                    &request.neighbors,
+                                    block,
+                                    coords,
+                                    face_dir,
+                                    color,
+                                    &tiles,
+                                );
+                                continue;
+                            }
+
+                            let uv = resource_dictionary.get_block_uv_at(block, position);
+                            model_constructor.add_block_face(coords, face_dir, color, uv);
+                        }
+                    }
+
+                    if resource_dictionary.get_block_light(block).is_some() {
+                        for vertex in &mut model_constructor.vertices[first_vertex..] {
+                            vertex.color = vertex.color.scaled(EMISSIVE_BRIGHTNESS);
                         }
                     }
                 }
@@ -256,5 +597,57 @@ fn mesh_chunk(
         }
     }
 
-    model_constructor
+    meshes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads the chunk at the origin and its six neighbours, all filled with glass.
+    fn filled_map(resource_dictionary: &ResourceDictionary) -> GameMap {
+        let glass = resource_dictionary.get_block_id("Glass");
+        let mut game_map = GameMap::new();
+
+        let mut fill = |coords: ChunkCoords| {
+            let chunk =
+                Chunk::from_blocks(vec![Some(glass); Chunk::BLOCKS_COUNT as usize]).unwrap();
+            game_map.chunks.insert(coords, chunk);
+        };
+
+        fill(ChunkCoords::new(0, 0, 0));
+        for face in 0..6 {
+            fill(ChunkCoords::from(FaceDirection::from(face)));
+        }
+
+        game_map
+    }
+
+    #[test]
+    fn enclosed_filled_chunks_have_no_faces() {
+        let resource_dictionary = ResourceDictionary::builtin(Vec::new()).unwrap();
+        let game_map = filled_map(&resource_dictionary);
+
+        let meshes = mesh_loaded_chunk(&game_map, ChunkCoords::new(0, 0, 0), &resource_dictionary);
+
+        assert!(meshes.solid.vertices.is_empty());
+        assert!(meshes.fluid.vertices.is_empty());
+    }
+
+    #[test]
+    fn filled_chunks_show_the_side_facing_air() {
+        let resource_dictionary = ResourceDictionary::builtin(Vec::new()).unwrap();
+        let mut game_map = filled_map(&resource_dictionary);
+        game_map
+            .chunks
+            .insert(ChunkCoords::new(0, 1, 0), Chunk::new());
+
+        let meshes = mesh_loaded_chunk(&game_map, ChunkCoords::new(0, 0, 0), &resource_dictionary);
+
+        // one face of 4 vertices per block of the top layer
+        assert_eq!(
+            meshes.solid.vertices.len(),
+            (Chunk::COLUMNS_COUNT * 4) as usize
+        );
+    }
 }
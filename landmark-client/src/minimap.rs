@@ -0,0 +1,271 @@
+//! Top-down map of the loaded world around the player, shown in a corner of the screen.
+//!
+//! The highest block of every column is kept by chunk and only looked up again for chunks whose mesh
+//! changed. The texture is redrawn from these columns when one of them changes or the player moves
+//! to another block.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use image::{Rgba, RgbaImage};
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    coords,
+    game_map::{column_idx, BlockId, Chunk, ChunkCoords, ChunkTag, FaceDirection, GameMap},
+    loader::ResourceDictionary,
+    model::UpdatedModel,
+    rendererer::Renderer,
+};
+
+/// Edge length of the minimap texture, every texel shows one column of blocks.
+pub const SIZE: u32 = 128;
+/// Color of columns in chunks which aren't loaded.
+const UNKNOWN_COLOR: Rgba<u8> = Rgba([20, 20, 20, 160]);
+/// How much brighter or darker a column is when it's higher or lower than the one north of it.
+const SLOPE_SHADING: f32 = 0.15;
+
+/// Highest block of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfaceBlock {
+    /// World height of the block.
+    pub y: i32,
+    pub block: BlockId,
+}
+
+/// Highest block of every column of a chunk, in the order of `column_idx`.
+type ChunkSurface = Vec<Option<SurfaceBlock>>;
+
+fn chunk_surface(coords: ChunkCoords, chunk: &Chunk) -> ChunkSurface {
+    let origin = coords::chunk_origin(coords);
+    let mut surface = vec![None; Chunk::COLUMNS_COUNT as usize];
+
+    if chunk.is_empty() {
+        return surface;
+    }
+
+    for z in 0..Chunk::SIZE {
+        for x in 0..Chunk::SIZE {
+            surface[column_idx(x, z)] = chunk.height(x, z).and_then(|y| {
+                let inner = coords::inner_from_offset(glam::IVec3::new(x, y, z))?;
+
+                chunk.get_block(inner).map(|block| SurfaceBlock {
+                    y: origin.y + y,
+                    block,
+                })
+            });
+        }
+    }
+
+    surface
+}
+
+/// Colors of the top faces of blocks, looked up once per block while drawing.
+pub struct BlockColors<'a> {
+    resource_dictionary: &'a ResourceDictionary,
+    cache: HashMap<BlockId, Color>,
+}
+
+impl<'a> BlockColors<'a> {
+    pub fn new(resource_dictionary: &'a ResourceDictionary) -> Self {
+        Self {
+            resource_dictionary,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, block: BlockId) -> Color {
+        *self.cache.entry(block).or_insert_with(|| {
+            self.resource_dictionary
+                .get_block_data_from_id(block)
+                .face_color(FaceDirection::PosY)
+        })
+    }
+}
+
+#[derive(Debug, Unique)]
+pub struct Minimap {
+    /// Highest blocks of the columns of loaded chunks, by the X and Z and then the Y of the chunks.
+    columns: HashMap<(i32, i32), BTreeMap<i32, ChunkSurface>>,
+    /// Column in the middle of the texture, None until it's first drawn.
+    center: Option<glam::IVec2>,
+    /// Set when columns changed since the texture was drawn.
+    dirty: bool,
+    image: RgbaImage,
+    /// X and Z of chunks whose columns changed since they were last taken by the world map.
+    changed_chunks: HashSet<(i32, i32)>,
+}
+
+impl Minimap {
+    pub fn new() -> Self {
+        Self {
+            columns: HashMap::new(),
+            center: None,
+            dirty: false,
+            image: RgbaImage::from_pixel(SIZE, SIZE, UNKNOWN_COLOR),
+            changed_chunks: HashSet::new(),
+        }
+    }
+
+    /// Recomputes the columns of a chunk, chunks which aren't loaded are forgotten.
+    pub fn update_chunk(&mut self, coords: ChunkCoords, chunk: Option<&Chunk>) {
+        let stack = self.columns.entry((coords.x, coords.z)).or_default();
+
+        match chunk {
+            Some(chunk) => {
+                stack.insert(coords.y, chunk_surface(coords, chunk));
+                self.changed_chunks.insert((coords.x, coords.z));
+            }
+            None => {
+                stack.remove(&coords.y);
+            }
+        }
+
+        self.dirty = true;
+    }
+
+    /// Returns the X and Z of chunks whose columns changed since the last call.
+    pub fn take_changed_chunks(&mut self) -> HashSet<(i32, i32)> {
+        std::mem::take(&mut self.changed_chunks)
+    }
+
+    /// Forgets the columns of chunks which were unloaded since they were added.
+    fn retain_loaded(&mut self, game_map: &GameMap) {
+        self.columns.retain(|&(x, z), stack| {
+            stack.retain(|&y, _| game_map.chunks.contains_key(&ChunkCoords::new(x, y, z)));
+            !stack.is_empty()
+        });
+    }
+
+    /// Returns the highest loaded block of the column at a world position.
+    pub fn surface_at(&self, x: i32, z: i32) -> Option<SurfaceBlock> {
+        let (chunk_x, chunk_z) = (x.div_euclid(Chunk::SIZE), z.div_euclid(Chunk::SIZE));
+        let idx = column_idx(x.rem_euclid(Chunk::SIZE), z.rem_euclid(Chunk::SIZE));
+
+        self.columns
+            .get(&(chunk_x, chunk_z))?
+            .values()
+            .rev()
+            .find_map(|surface| surface[idx])
+    }
+
+    /// Returns the color the column is drawn with, shaded by the slope towards the north,
+    /// or None if no block of it is loaded.
+    pub fn column_color(&self, x: i32, z: i32, colors: &mut BlockColors) -> Option<Rgba<u8>> {
+        let surface = self.surface_at(x, z)?;
+        let color = colors.get(surface.block);
+
+        let slope = self
+            .surface_at(x, z + 1)
+            .map_or(0, |north| (surface.y - north.y).clamp(-1, 1));
+        let shade = 1.0 + slope as f32 * SLOPE_SHADING;
+        let channel = |value: u8| (value as f32 * shade).round().clamp(0.0, 255.0) as u8;
+
+        Some(Rgba([
+            channel(color.r),
+            channel(color.g),
+            channel(color.b),
+            255,
+        ]))
+    }
+
+    /// Redraws the texture with the column at `center` in the middle, north is up.
+    fn redraw(&mut self, center: glam::IVec2, colors: &mut BlockColors) {
+        let half = SIZE as i32 / 2;
+
+        for v in 0..SIZE {
+            for u in 0..SIZE {
+                let x = center.x - half + u as i32;
+                let z = center.y + half - 1 - v as i32;
+                let color = self.column_color(x, z, colors).unwrap_or(UNKNOWN_COLOR);
+
+                self.image.put_pixel(u, v, color);
+            }
+        }
+
+        self.center = Some(center);
+        self.dirty = false;
+    }
+}
+
+/// Keeps the columns up to date with the chunks remeshed since the last frame and uploads
+/// the texture if it changed, must run before the new meshes are uploaded.
+pub fn update_minimap_sys(
+    mut minimap: UniqueViewMut<Minimap>,
+    renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    game_map: UniqueView<GameMap>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    chunks: View<ChunkTag>,
+    updated_models: View<UpdatedModel>,
+) {
+    for (chunk, _) in (&chunks, &updated_models).iter() {
+        minimap.update_chunk(chunk.coords, game_map.chunks.get(&chunk.coords));
+    }
+
+    let eye = coords::block_containing(camera.eye);
+    let center = glam::IVec2::new(eye.x, eye.z);
+
+    if minimap.dirty || minimap.center != Some(center) {
+        minimap.retain_loaded(&game_map);
+        minimap.redraw(center, &mut BlockColors::new(&resource_dictionary));
+
+        renderer
+            .minimap_texture
+            .write_region(&renderer.queue, (0, 0), &minimap.image);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_with(blocks: &[(glam::IVec3, BlockId)]) -> Chunk {
+        let mut chunk = Chunk::new();
+
+        for (position, block) in blocks {
+            let inner = coords::inner_from_offset(*position).unwrap();
+            chunk.set_block(inner, Some(*block));
+        }
+
+        chunk
+    }
+
+    #[test]
+    fn surface_is_the_highest_block_of_the_stacked_chunks() {
+        let mut minimap = Minimap::new();
+        let low = chunk_with(&[(glam::IVec3::new(3, 31, 4), 1)]);
+        let high = chunk_with(&[(glam::IVec3::new(3, 2, 4), 2)]);
+
+        minimap.update_chunk(ChunkCoords::new(0, 0, 0), Some(&low));
+        minimap.update_chunk(ChunkCoords::new(0, 1, 0), Some(&high));
+
+        assert_eq!(
+            minimap.surface_at(3, 4),
+            Some(SurfaceBlock { y: 34, block: 2 })
+        );
+        // columns without a block in the upper chunk fall through to the lower one
+        assert_eq!(minimap.surface_at(4, 4), None);
+
+        minimap.update_chunk(ChunkCoords::new(0, 1, 0), None);
+
+        assert_eq!(
+            minimap.surface_at(3, 4),
+            Some(SurfaceBlock { y: 31, block: 1 })
+        );
+    }
+
+    #[test]
+    fn negative_positions_find_their_chunk() {
+        let mut minimap = Minimap::new();
+        let chunk = chunk_with(&[(glam::IVec3::new(31, 0, 0), 5)]);
+
+        minimap.update_chunk(ChunkCoords::new(-1, -1, 0), Some(&chunk));
+
+        assert_eq!(
+            minimap.surface_at(-1, 0),
+            Some(SurfaceBlock { y: -32, block: 5 })
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Kinds of mobs and how they move around on their own.
+//!
+//! Kinds are defined in `mobs.ron` of the resource packs, spawn rules in `spawning.ron` refer to them
+//! by name. Mobs wander around randomly, hostile ones chase the player once they come close.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use glam::Vec3Swizzles;
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    coords,
+    game_map::GameMap,
+    loader::{ResourceError, ResourcePacks},
+    physics::EYE_HEIGHT,
+    spawning::Mob,
+    UPDATES_PER_SECOND,
+};
+
+/// In blocks per second squared.
+const GRAVITY: f64 = 20.0;
+const TERMINAL_VELOCITY: f64 = 40.0;
+/// Chasing mobs stop this close to the player.
+const CHASE_STOP_DISTANCE: f64 = 1.0;
+
+fn default_speed() -> f64 {
+    2.0
+}
+
+fn default_health() -> u32 {
+    10
+}
+
+/// Properties of a kind of mob.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MobDefinition {
+    /// Name of the entity model the mob is drawn with, the name of the kind if not set.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Walking speed in blocks per second.
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    #[serde(default = "default_health")]
+    pub health: u32,
+    #[serde(default)]
+    pub behavior: Behavior,
+}
+
+impl Default for MobDefinition {
+    fn default() -> Self {
+        Self {
+            model: None,
+            speed: default_speed(),
+            health: default_health(),
+            behavior: Behavior::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+pub enum Behavior {
+    /// Walks around randomly.
+    #[default]
+    Wander,
+    /// Wanders until the player comes within `range` blocks, then follows them.
+    Chase { range: f64 },
+}
+
+/// Kinds of mobs by name, loaded from `mobs.ron`.
+#[derive(Debug, Default, Unique)]
+pub struct MobDefinitions(pub HashMap<String, MobDefinition>);
+
+impl MobDefinitions {
+    /// Loads mob definitions from the highest priority resource pack containing them.
+    pub fn load(resource_packs: &ResourcePacks) -> Result<Self, ResourceError> {
+        let relative = Path::new("mobs.ron");
+
+        let Some(path) = resource_packs.find(relative) else {
+            log::info!("No mob definitions found, mobs use default properties");
+            return Ok(Self::default());
+        };
+
+        let content = fs::read_to_string(&path).map_err(|source| ResourceError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+        ron::from_str(&content)
+            .map(Self)
+            .map_err(|source| ResourceError::Parse { path, source })
+    }
+
+    /// Returns the definition of the kind, kinds without one use the defaults.
+    pub fn get(&self, kind: &str) -> MobDefinition {
+        self.0.get(kind).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AiState {
+    Idle { ticks: u32 },
+    Wandering { direction: glam::DVec2, ticks: u32 },
+    Chasing,
+}
+
+/// What a mob is currently doing.
+#[derive(Debug, Clone, Component)]
+pub struct MobAi {
+    state: AiState,
+    /// State of the xorshift generator deciding where and how long to wander.
+    rng: u64,
+    vertical_velocity: f64,
+}
+
+impl MobAi {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: AiState::Idle { ticks: 0 },
+            // xorshift never leaves zero
+            rng: seed | 1,
+            vertical_velocity: 0.0,
+        }
+    }
+
+    /// Returns a random number from 0 to 1.
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a random number of ticks between `min` and `max` seconds.
+    fn random_ticks(&mut self, min: f64, max: f64) -> u32 {
+        ((min + (max - min) * self.random()) * UPDATES_PER_SECOND as f64) as u32
+    }
+
+    /// Returns the horizontal direction the mob wants to walk in, zero to stand still.
+    fn think(
+        &mut self,
+        behavior: Behavior,
+        position: glam::DVec3,
+        player: glam::DVec3,
+    ) -> glam::DVec2 {
+        let to_player = (player - position).xz();
+
+        if let Behavior::Chase { range } = behavior {
+            let distance = to_player.length();
+
+            if distance <= range {
+                self.state = AiState::Chasing;
+
+                return if distance > CHASE_STOP_DISTANCE {
+                    to_player / distance
+                } else {
+                    glam::DVec2::ZERO
+                };
+            }
+        }
+
+        match self.state {
+            AiState::Idle { ticks: 0 } => {
+                let angle = self.random() * std::f64::consts::TAU;
+                self.state = AiState::Wandering {
+                    direction: glam::DVec2::from_angle(angle),
+                    ticks: self.random_ticks(1.0, 3.0),
+                };
+            }
+            AiState::Idle { ref mut ticks } => *ticks -= 1,
+            AiState::Wandering { ticks: 0, .. } | AiState::Chasing => {
+                self.state = AiState::Idle {
+                    ticks: self.random_ticks(2.0, 6.0),
+                };
+            }
+            AiState::Wandering { ref mut ticks, .. } => *ticks -= 1,
+        }
+
+        match self.state {
+            AiState::Wandering { direction, .. } => direction,
+            _ => glam::DVec2::ZERO,
+        }
+    }
+
+    /// Stops wandering into an obstacle, the mob picks a new direction after a pause.
+    fn blocked(&mut self) {
+        if let AiState::Wandering { .. } = self.state {
+            self.state = AiState::Idle {
+                ticks: self.random_ticks(0.5, 2.0),
+            };
+        }
+    }
+}
+
+fn is_solid(game_map: &GameMap, position: glam::DVec3) -> bool {
+    game_map
+        .get_block_world(coords::block_containing(position))
+        .is_some()
+}
+
+/// Returns true if a mob standing at `position` doesn't intersect any blocks.
+fn has_room(game_map: &GameMap, position: glam::DVec3) -> bool {
+    let height = Mob::SIZE.y as f64;
+
+    !is_solid(game_map, position)
+        && !is_solid(game_map, position + glam::DVec3::Y * (height / 2.0))
+        && !is_solid(game_map, position + glam::DVec3::Y * (height - 0.01))
+}
+
+/// Moves the mob horizontally, stepping up single blocks. Returns false if it was blocked.
+fn walk(game_map: &GameMap, position: &mut glam::DVec3, step: glam::DVec2) -> bool {
+    let next = *position + glam::DVec3::new(step.x, 0.0, step.y);
+
+    if has_room(game_map, next) {
+        *position = next;
+        return true;
+    }
+
+    let stepped = glam::DVec3::new(next.x, next.y.floor() + 1.0, next.z);
+    if has_room(game_map, stepped) {
+        *position = stepped;
+        return true;
+    }
+
+    false
+}
+
+/// Applies gravity and lands the mob on top of blocks.
+fn fall(game_map: &GameMap, position: &mut glam::DVec3, velocity: &mut f64) {
+    let dt = 1.0 / UPDATES_PER_SECOND as f64;
+
+    if is_solid(game_map, *position - glam::DVec3::Y * 0.01) {
+        *velocity = 0.0;
+        return;
+    }
+
+    *velocity = (*velocity - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+    let next = *position + glam::DVec3::Y * (*velocity * dt);
+
+    if is_solid(game_map, next) {
+        position.y = next.y.floor() + 1.0;
+        *velocity = 0.0;
+    } else {
+        *position = next;
+    }
+}
+
+/// Moves mobs according to the behavior of their kind.
+pub fn mob_ai_sys(
+    definitions: UniqueView<MobDefinitions>,
+    camera: UniqueView<Camera>,
+    game_map: UniqueView<GameMap>,
+    mut mobs: ViewMut<Mob>,
+    mut ais: ViewMut<MobAi>,
+) {
+    let player = camera.eye - glam::DVec3::Y * EYE_HEIGHT;
+    let dt = 1.0 / UPDATES_PER_SECOND as f64;
+
+    for (mob, ai) in (&mut mobs, &mut ais).iter() {
+        // mobs in chunks which aren't loaded yet stay where they are
+        if !game_map
+            .chunks
+            .contains_key(&coords::chunk_of_block(coords::block_containing(
+                mob.position,
+            )))
+        {
+            continue;
+        }
+
+        let definition = definitions.0.get(&mob.kind);
+        let behavior = definition.map(|d| d.behavior).unwrap_or_default();
+        let speed = definition.map(|d| d.speed).unwrap_or_else(default_speed);
+
+        let direction = ai.think(behavior, mob.position, player);
+
+        if direction != glam::DVec2::ZERO
+            && !walk(&game_map, &mut mob.position, direction * speed * dt)
+        {
+            ai.blocked();
+        }
+
+        fall(&game_map, &mut mob.position, &mut ai.vertical_velocity);
+    }
+}
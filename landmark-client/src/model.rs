@@ -1,9 +1,16 @@
+use std::time::{Duration, Instant};
+
 use shipyard::*;
 use wgpu::util::DeviceExt;
 
 use crate::{
+    camera::Camera,
+    chunk_arena::{ArenaAllocation, ChunkArena},
     color::RawColor,
+    debug::RenderStats,
+    game_map::ChunkTag,
     rendererer::Renderer,
+    settings::Settings,
     transform::{RawTransform, Transform},
 };
 
@@ -12,11 +19,12 @@ use crate::{
 pub struct Vertex {
     pub position: glam::Vec3,
     pub color: RawColor,
+    pub uv: glam::Vec2,
 }
 
 impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
 
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
@@ -44,16 +52,32 @@ impl ModelConstructor {
     }
 }
 
-#[derive(Debug, Component)]
-pub struct Model {
-    _vertices: Vec<Vertex>,
-    indices: Vec<u16>,
-    _transform: Transform,
+/// Where the geometry of a model lives on the GPU.
+#[derive(Debug)]
+pub enum ModelStorage {
+    /// Buffers of its own, drawn with a draw call each.
+    Buffers(Box<ModelBuffers>),
+    /// Part of the chunk arena, drawn together with the other chunks.
+    Arena(ArenaAllocation),
+}
+
+#[derive(Debug)]
+pub struct ModelBuffers {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub instance_buffer: wgpu::Buffer,
 }
 
+#[derive(Debug, Component)]
+pub struct Model {
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+    transform: Transform,
+    /// Corners of the box around the vertices before they are transformed.
+    bounds: (glam::Vec3, glam::Vec3),
+    pub storage: ModelStorage,
+}
+
 impl Model {
     pub fn new(device: &wgpu::Device, model_constructor: &ModelConstructor) -> Self {
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -72,43 +96,262 @@ impl Model {
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
             contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
 
+        Self::with_storage(
+            model_constructor,
+            ModelStorage::Buffers(Box::new(ModelBuffers {
+                vertex_buffer,
+                index_buffer,
+                instance_buffer,
+            })),
+        )
+    }
+
+    /// Uploads the model to the chunk arena, returns None if it's full.
+    pub fn in_arena(
+        arena: &ChunkArena,
+        queue: &wgpu::Queue,
+        model_constructor: &ModelConstructor,
+    ) -> Option<Self> {
+        let allocation = arena.allocate(
+            queue,
+            &model_constructor.vertices,
+            &model_constructor.indices,
+            RawTransform::from(model_constructor.transform),
+        )?;
+
+        Some(Self::with_storage(
+            model_constructor,
+            ModelStorage::Arena(allocation),
+        ))
+    }
+
+    fn with_storage(model_constructor: &ModelConstructor, storage: ModelStorage) -> Self {
+        let bounds = model_constructor.vertices.iter().fold(
+            (glam::Vec3::INFINITY, glam::Vec3::NEG_INFINITY),
+            |(min, max), vertex| (min.min(vertex.position), max.max(vertex.position)),
+        );
+
         Self {
-            _vertices: model_constructor.vertices.clone(),
+            vertices: model_constructor.vertices.clone(),
             indices: model_constructor.indices.clone(),
-            _transform: model_constructor.transform,
-            vertex_buffer,
-            index_buffer,
-            instance_buffer,
+            transform: model_constructor.transform,
+            bounds,
+            storage,
         }
     }
 
+    pub fn vertex_count(&self) -> u32 {
+        self.vertices.len() as u32
+    }
+
     pub fn index_count(&self) -> u32 {
         self.indices.len() as u32
     }
+
+    /// Returns the size of the vertex, index and instance buffers, or of its part of the arena.
+    pub fn buffer_bytes(&self) -> u64 {
+        match &self.storage {
+            ModelStorage::Buffers(buffers) => {
+                buffers.vertex_buffer.size()
+                    + buffers.index_buffer.size()
+                    + buffers.instance_buffer.size()
+            }
+            ModelStorage::Arena(allocation) => allocation.buffer_bytes(),
+        }
+    }
+
+    /// Returns the corners of a box around the model drawn with its own transform.
+    pub fn bounds(&self) -> (glam::Vec3, glam::Vec3) {
+        self.bounds_at(self.transform)
+    }
+
+    /// Returns the corners of a box around the model drawn with a transform, used for instances.
+    pub fn bounds_at(&self, transform: Transform) -> (glam::Vec3, glam::Vec3) {
+        let (min, max) = self.bounds;
+
+        (0..8).fold(
+            (glam::Vec3::INFINITY, glam::Vec3::NEG_INFINITY),
+            |(bounds_min, bounds_max), idx| {
+                let corner = glam::Vec3::new(
+                    if idx & 1 != 0 { max.x } else { min.x },
+                    if idx & 2 != 0 { max.y } else { min.y },
+                    if idx & 4 != 0 { max.z } else { min.z },
+                );
+                let corner = transform.rotation * corner + transform.translation;
+
+                (bounds_min.min(corner), bounds_max.max(corner))
+            },
+        )
+    }
+
+    pub fn translation(&self) -> glam::Vec3 {
+        self.transform.translation
+    }
+
+    /// Moves the model, only writing the instance buffer if the translation changed.
+    pub fn set_translation(&mut self, queue: &wgpu::Queue, translation: glam::Vec3) {
+        self.set_transform(
+            queue,
+            Transform {
+                rotation: self.transform.rotation,
+                translation,
+            },
+        );
+    }
+
+    /// Moves and rotates the model, only writing the instance buffer if the transform changed.
+    pub fn set_transform(&mut self, queue: &wgpu::Queue, transform: Transform) {
+        if self.transform.translation == transform.translation
+            && self.transform.rotation == transform.rotation
+        {
+            return;
+        }
+
+        self.transform = transform;
+
+        let raw_transform = RawTransform::from(self.transform);
+        match &self.storage {
+            ModelStorage::Buffers(buffers) => queue.write_buffer(
+                &buffers.instance_buffer,
+                0,
+                bytemuck::cast_slice(&[raw_transform]),
+            ),
+            ModelStorage::Arena(allocation) => allocation.set_transform(queue, raw_transform),
+        }
+    }
 }
 
+/// Time newly loaded chunks take to rise into their place.
+const RISE_DURATION: Duration = Duration::from_millis(300);
+/// How far below their place newly loaded chunks start rising, in blocks.
+const RISE_DEPTH: f32 = 8.0;
+
 #[derive(Debug, Clone, Copy, Component)]
 pub struct MissingModel;
 
+/// Animates a chunk which was just loaded in view rising into place, so it doesn't pop in.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct RiseIn {
+    started: Instant,
+}
+
+impl RiseIn {
+    /// Returns how far below its place the chunk is drawn, or None once it arrived.
+    fn depth(&self, now: Instant) -> Option<f32> {
+        let progress = now.duration_since(self.started).as_secs_f32() / RISE_DURATION.as_secs_f32();
+
+        // slows down towards the end
+        (progress < 1.0).then(|| RISE_DEPTH * (1.0 - progress).powi(2))
+    }
+}
+
 #[derive(Debug, Component)]
 pub struct UpdatedModel(pub ModelConstructor);
 
+/// Places chunk models relative to the camera origin, below it while they are rising in.
+pub fn update_chunk_transforms_sys(
+    renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    chunks: View<ChunkTag>,
+    mut models: ViewMut<Model>,
+    mut rise_ins: ViewMut<RiseIn>,
+) {
+    let now = Instant::now();
+    let mut arrived = Vec::new();
+
+    for (id, (chunk, model)) in (&chunks, &mut models).iter().with_id() {
+        let depth = match rise_ins.get(id).map(|rise_in| rise_in.depth(now)) {
+            Ok(Some(depth)) => depth,
+            Ok(None) => {
+                arrived.push(id);
+                0.0
+            }
+            Err(_) => 0.0,
+        };
+
+        model.set_translation(
+            &renderer.queue,
+            chunk.coords.translation_from(camera.origin) - glam::Vec3::Y * depth,
+        );
+    }
+
+    for id in arrived {
+        rise_ins.delete(id);
+    }
+}
+
+/// Creates models of updated meshes. Chunks which are loaded in view rise into place,
+/// those loaded outside of it and chunks which were only remeshed appear at once.
+/// Meshes of newly loaded chunks wait while the models exceed the GPU memory budget.
+#[allow(clippy::too_many_arguments)]
 pub fn update_models_sys(
     renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    settings: UniqueView<Settings>,
+    mut render_stats: UniqueViewMut<RenderStats>,
+    chunks: View<ChunkTag>,
     mut models: ViewMut<Model>,
     mut updated_models: ViewMut<UpdatedModel>,
+    mut rise_ins: ViewMut<RiseIn>,
 ) {
     let mut processed_models: Vec<EntityId> = Vec::new();
+    let frustum = camera.frustum();
+    let budget = settings
+        .graphics
+        .gpu_memory_budget
+        .map(|megabytes| megabytes as u64 * 1024 * 1024);
+    let mut buffer_bytes = render_stats.buffer_bytes;
+    let mut deferred = 0;
 
     for (id, updated_model) in updated_models.iter().with_id() {
-        let model = Model::new(&renderer.device, &updated_model.0);
+        // replaced meshes and entities are small or free their old buffers, so they are never held back
+        let is_new_chunk = chunks.contains(id) && !models.contains(id);
+        if is_new_chunk && budget.is_some_and(|budget| buffer_bytes > budget) {
+            deferred += 1;
+            continue;
+        }
+
+        // empty meshes are left out of the arena, they would only take up a slot
+        let model = renderer
+            .chunk_arena
+            .as_ref()
+            .filter(|_| chunks.contains(id) && !updated_model.0.indices.is_empty())
+            .and_then(|arena| Model::in_arena(arena, &renderer.queue, &updated_model.0))
+            .unwrap_or_else(|| Model::new(&renderer.device, &updated_model.0));
+        buffer_bytes += model.buffer_bytes();
+
+        if let (Ok(chunk), false) = (chunks.get(id), models.contains(id)) {
+            let (min, max) = model.bounds_at(Transform {
+                rotation: glam::Quat::IDENTITY,
+                translation: chunk.coords.translation_from(camera.origin),
+            });
+
+            if frustum.intersects_box(min, max) {
+                rise_ins.add_component_unchecked(
+                    id,
+                    RiseIn {
+                        started: Instant::now(),
+                    },
+                );
+            }
+        }
+
         models.add_component_unchecked(id, model);
         processed_models.push(id);
     }
 
+    if deferred > 0 && render_stats.deferred_meshes == 0 {
+        log::warn!(
+            "Models exceed the GPU memory budget, new chunks wait until others are unloaded"
+        );
+    }
+
+    render_stats.meshes_built = processed_models.len() as u32;
+    render_stats.deferred_meshes = deferred;
+
     for id in processed_models.into_iter() {
         updated_models.delete(id);
     }
@@ -0,0 +1,201 @@
+//! Reader of the Named Binary Tag format Minecraft saves its data in.
+
+use std::{collections::HashMap, io};
+
+/// Compounds and lists nested deeper than this are rejected, so a damaged file can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Returns a tag of a compound by its name.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any integer tag.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(value) => Some(*value as i64),
+            Tag::Short(value) => Some(*value as i64),
+            Tag::Int(value) => Some(*value as i64),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(tags) => Some(tags),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the root compound of uncompressed NBT data, its name is ignored.
+pub fn read(bytes: &[u8]) -> io::Result<Tag> {
+    let mut reader = Reader { bytes };
+
+    if reader.u8()? != 10 {
+        return Err(invalid_data("root tag is not a compound"));
+    }
+
+    reader.string()?;
+    reader.payload(10, 0)
+}
+
+/// Reads big endian values from NBT data.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            return Err(invalid_data("unexpected end of NBT data"));
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        usize::try_from(self.i32()?).map_err(|_| invalid_data("negative length"))
+    }
+
+    /// Strings are in modified UTF-8, which only differs from UTF-8 for characters block names don't use.
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.i16()? as u16 as usize;
+
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn payload(&mut self, kind: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("NBT data is nested too deep"));
+        }
+
+        let tag = match kind {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(self.i16()?),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(self.i64()?),
+            5 => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            6 => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            7 => {
+                let length = self.length()?;
+                Tag::ByteArray(self.take(length)?.iter().map(|byte| *byte as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let kind = self.u8()?;
+                let length = self.length()?;
+
+                // every element takes at least a byte, unless the list is empty
+                if kind != 0 && length > self.bytes.len() {
+                    return Err(invalid_data("list is longer than the NBT data"));
+                }
+
+                let mut tags = Vec::with_capacity(length);
+                for _ in 0..length {
+                    tags.push(self.payload(kind, depth + 1)?);
+                }
+
+                Tag::List(tags)
+            }
+            10 => {
+                let mut tags = HashMap::new();
+
+                loop {
+                    let kind = self.u8()?;
+                    if kind == 0 {
+                        break;
+                    }
+
+                    let name = self.string()?;
+                    tags.insert(name, self.payload(kind, depth + 1)?);
+                }
+
+                Tag::Compound(tags)
+            }
+            11 => {
+                let length = self.length()?;
+                let bytes = self.take(length.saturating_mul(4))?;
+
+                Tag::IntArray(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|value| i32::from_be_bytes(value.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            12 => {
+                let length = self.length()?;
+                let bytes = self.take(length.saturating_mul(8))?;
+
+                Tag::LongArray(
+                    bytes
+                        .chunks_exact(8)
+                        .map(|value| i64::from_be_bytes(value.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            _ => return Err(invalid_data("unknown NBT tag type")),
+        };
+
+        Ok(tag)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
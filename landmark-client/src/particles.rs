@@ -0,0 +1,487 @@
+//! Small short-lived squares like debris of broken blocks, dust kicked up by footsteps and rain.
+//!
+//! Effects are started by adding an entity with a `ParticleEmitter`, which spawns its particles on
+//! the next update and is removed. Particles are moved on the CPU and drawn with one instance each.
+
+use shipyard::*;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    atlas::{TextureAtlas, UvRect},
+    biome::Biome,
+    camera::Camera,
+    color::RawColor,
+    coords,
+    game_map::{BlockId, Chunk, FaceDirection, GameMap},
+    game_mode::GameMode,
+    loader::ResourceDictionary,
+    physics::EYE_HEIGHT,
+    rendererer::Renderer,
+    UPDATES_PER_SECOND,
+};
+
+/// Particles alive at once, the oldest are replaced when more are spawned.
+const MAX_PARTICLES: usize = 4096;
+/// Horizontal distance walked on the ground between two puffs of dust, in blocks.
+const STEP_LENGTH: f64 = 1.5;
+/// Horizontal distance from the camera rain and snow fall within.
+const PRECIPITATION_RADIUS: f64 = 16.0;
+/// Height above the camera rain and snow start falling from.
+const PRECIPITATION_HEIGHT: f64 = 12.0;
+
+/// Effects an emitter can spawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParticleEffect {
+    /// Pieces of a broken block flying out of it.
+    Debris,
+    /// A few pieces of the block walked on, kicked up around the position.
+    Dust,
+}
+
+/// Spawns the particles of an effect made of pieces of a block, then it's removed.
+#[derive(Debug, Clone, Component)]
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    pub block: BlockId,
+    /// Minimum corner of the broken block for debris, the feet of the walker for dust.
+    pub position: glam::DVec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: glam::DVec3,
+    /// In blocks per second.
+    pub velocity: glam::DVec3,
+    /// Downwards, in blocks per second squared.
+    pub gravity: f64,
+    /// In seconds.
+    pub age: f32,
+    pub lifetime: f32,
+    /// Width of the square in blocks, it shrinks away towards the end of the lifetime.
+    pub size: f32,
+    pub color: glam::Vec4,
+    pub uv: UvRect,
+    /// Disappears when it hits a block instead of resting on it, like raindrops.
+    pub vanishes_on_impact: bool,
+}
+
+impl Particle {
+    /// Moves the particle by one update, it stops once it hits a block.
+    /// Returns false once it has lived through its lifetime.
+    fn update(&mut self, game_map: &GameMap) -> bool {
+        let dt = 1.0 / UPDATES_PER_SECOND as f64;
+
+        self.age += dt as f32;
+        if self.age >= self.lifetime {
+            return false;
+        }
+
+        self.velocity.y -= self.gravity * dt;
+        let next = self.position + self.velocity * dt;
+
+        if game_map
+            .get_block_world(coords::block_containing(next))
+            .is_some()
+        {
+            if self.vanishes_on_impact {
+                return false;
+            }
+
+            self.velocity = glam::DVec3::ZERO;
+        } else {
+            self.position = next;
+        }
+
+        true
+    }
+
+    /// Returns the size of the square at the current age.
+    fn current_size(&self) -> f32 {
+        let remaining = 1.0 - self.age / self.lifetime;
+        self.size * (remaining * 4.0).min(1.0)
+    }
+}
+
+/// Particle instance data as laid out in the vertex buffer.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct RawParticle {
+    /// Relative to the camera origin.
+    position: glam::Vec3,
+    size: f32,
+    color: glam::Vec4,
+    uv_min: glam::Vec2,
+    uv_max: glam::Vec2,
+}
+
+impl RawParticle {
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32,
+        2 => Float32x4,
+        3 => Float32x2,
+        4 => Float32x2
+    ];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Every living particle.
+#[derive(Debug, Unique)]
+pub struct Particles {
+    particles: Vec<Particle>,
+    /// Where the next particle is written once the limit is reached.
+    oldest: usize,
+    /// State of the xorshift generator spreading the particles.
+    rng: u64,
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Self {
+            particles: Vec::new(),
+            oldest: 0,
+            // xorshift never leaves zero
+            rng: 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+}
+
+impl Particles {
+    /// Returns a number from 0 to 1.
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a number from -1 to 1.
+    fn random_signed(&mut self) -> f64 {
+        self.random() * 2.0 - 1.0
+    }
+
+    pub fn spawn(&mut self, particle: Particle) {
+        if self.particles.len() < MAX_PARTICLES {
+            self.particles.push(particle);
+        } else {
+            self.particles[self.oldest] = particle;
+            self.oldest = (self.oldest + 1) % MAX_PARTICLES;
+        }
+    }
+
+    /// Spawns the particles of an emitter, colored and textured like a random part of its block.
+    pub fn emit(&mut self, emitter: &ParticleEmitter, resource_dictionary: &ResourceDictionary) {
+        let block = resource_dictionary.get_block_data_from_id(emitter.block);
+        let color = RawColor::from(block.face_color(FaceDirection::PosY)).with_alpha(1.0);
+        let uv = resource_dictionary
+            .get_block_face_uv(emitter.block, FaceDirection::PosY)
+            .unwrap_or_else(|| resource_dictionary.get_block_uv(emitter.block));
+
+        let count = match emitter.effect {
+            ParticleEffect::Debris => 24,
+            ParticleEffect::Dust => 4,
+        };
+
+        for _ in 0..count {
+            let (position, velocity) = match emitter.effect {
+                ParticleEffect::Debris => {
+                    let offset = glam::DVec3::new(self.random(), self.random(), self.random());
+                    // pieces fly away from the center of the block and a little upwards
+                    let velocity = (offset - 0.5) * 4.0 + glam::DVec3::Y * 2.0;
+
+                    (emitter.position + offset, velocity)
+                }
+                ParticleEffect::Dust => {
+                    let offset =
+                        glam::DVec3::new(self.random_signed(), 0.0, self.random_signed()) * 0.3;
+                    let velocity = offset + glam::DVec3::Y * (1.0 + self.random());
+
+                    (emitter.position + offset + glam::DVec3::Y * 0.05, velocity)
+                }
+            };
+
+            let lifetime = match emitter.effect {
+                ParticleEffect::Debris => 0.6 + self.random() as f32 * 0.6,
+                ParticleEffect::Dust => 0.3 + self.random() as f32 * 0.3,
+            };
+
+            let size = 0.1 + self.random() as f32 * 0.08;
+            let piece = self.random_piece(uv);
+
+            self.spawn(Particle {
+                position,
+                velocity,
+                gravity: 16.0,
+                age: 0.0,
+                lifetime,
+                size,
+                color,
+                uv: piece,
+                vanishes_on_impact: false,
+            });
+        }
+    }
+
+    /// Spawns `count` raindrops, or snowflakes in the mountains, above the camera.
+    /// Drops are only spawned in columns open to the sky, so they never fall under a roof or in caves.
+    pub fn precipitate(&mut self, eye: glam::DVec3, game_map: &GameMap, count: usize) {
+        for _ in 0..count {
+            let offset = glam::DVec3::new(self.random_signed(), 0.0, self.random_signed())
+                * PRECIPITATION_RADIUS;
+            let position = eye + offset + glam::DVec3::Y * PRECIPITATION_HEIGHT;
+            let block = coords::block_containing(position);
+
+            if game_map.light_at(block) < Chunk::MAX_LIGHT {
+                continue;
+            }
+
+            let snow = game_map.biome_at(block) == Some(Biome::Mountains);
+            let particle = if snow {
+                let drift = glam::DVec3::new(self.random_signed(), 0.0, self.random_signed());
+
+                Particle {
+                    position,
+                    velocity: drift * 0.5 - glam::DVec3::Y * 2.0,
+                    gravity: 0.0,
+                    age: 0.0,
+                    lifetime: 8.0,
+                    size: 0.08,
+                    color: glam::Vec4::new(1.0, 1.0, 1.0, 0.9),
+                    uv: TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT),
+                    vanishes_on_impact: true,
+                }
+            } else {
+                Particle {
+                    position,
+                    velocity: -glam::DVec3::Y * (14.0 + self.random() * 4.0),
+                    gravity: 0.0,
+                    age: 0.0,
+                    lifetime: 2.0,
+                    size: 0.05,
+                    color: glam::Vec4::new(0.5, 0.6, 0.9, 0.6),
+                    uv: TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT),
+                    vanishes_on_impact: true,
+                }
+            };
+
+            self.spawn(particle);
+        }
+    }
+
+    /// Returns a random part a quarter as wide as the texture, so particles look like pieces of
+    /// the block.
+    fn random_piece(&mut self, uv: UvRect) -> UvRect {
+        let size = (uv.max - uv.min) / 4.0;
+        let min = uv.min + size * 3.0 * glam::Vec2::new(self.random() as f32, self.random() as f32);
+
+        UvRect {
+            min,
+            max: min + size,
+        }
+    }
+
+    /// Moves every particle by one update and removes those which have lived through their lifetime.
+    pub fn update(&mut self, game_map: &GameMap) {
+        let before = self.particles.len();
+        self.particles
+            .retain_mut(|particle| particle.update(game_map));
+
+        if self.particles.len() != before {
+            self.oldest = 0;
+        }
+    }
+}
+
+/// Distance the local player walked since the last puff of dust.
+#[derive(Debug, Default, Unique)]
+pub struct Footsteps {
+    last_feet: Option<glam::DVec3>,
+    walked: f64,
+}
+
+/// Spawns the particles of new emitters and moves the living ones.
+pub fn particles_sys(
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    game_map: UniqueView<GameMap>,
+    mut particles: UniqueViewMut<Particles>,
+    mut entities: EntitiesViewMut,
+    mut emitters: ViewMut<ParticleEmitter>,
+) {
+    particles.update(&game_map);
+
+    let emitted: Vec<_> = (&emitters).iter().with_id().map(|(id, _)| id).collect();
+
+    for id in emitted {
+        if let Some(emitter) = emitters.remove(id) {
+            particles.emit(&emitter, &resource_dictionary);
+        }
+
+        entities.delete_unchecked(id);
+    }
+}
+
+/// Kicks up dust from the block under the local player every few steps they walk on the ground.
+pub fn footstep_dust_sys(
+    game_mode: UniqueView<GameMode>,
+    camera: UniqueView<Camera>,
+    game_map: UniqueView<GameMap>,
+    mut footsteps: UniqueViewMut<Footsteps>,
+    mut entities: EntitiesViewMut,
+    mut emitters: ViewMut<ParticleEmitter>,
+) {
+    let feet = camera.eye - glam::DVec3::Y * EYE_HEIGHT;
+    let last_feet = footsteps.last_feet.replace(feet);
+
+    let ground = game_map.get_block_world(coords::block_containing(feet - glam::DVec3::Y * 0.01));
+
+    let (Some(last_feet), Some(block), false) = (last_feet, ground, game_mode.can_fly()) else {
+        return;
+    };
+
+    let step = (feet - last_feet) * glam::DVec3::new(1.0, 0.0, 1.0);
+    // teleports aren't steps
+    if step.length() > STEP_LENGTH {
+        return;
+    }
+
+    footsteps.walked += step.length();
+
+    if footsteps.walked >= STEP_LENGTH {
+        footsteps.walked -= STEP_LENGTH;
+
+        entities.add_entity(
+            &mut emitters,
+            ParticleEmitter {
+                effect: ParticleEffect::Dust,
+                block,
+                position: feet,
+            },
+        );
+    }
+}
+
+/// Uploads the living particles, placed relative to the camera origin.
+pub fn update_particle_instances_sys(
+    mut renderer: UniqueViewMut<Renderer>,
+    camera: UniqueView<Camera>,
+    particles: UniqueView<Particles>,
+) {
+    let origin = camera.origin.as_world_position();
+
+    let instance_data: Vec<RawParticle> = particles
+        .particles
+        .iter()
+        .map(|particle| RawParticle {
+            position: (particle.position - origin).as_vec3(),
+            size: particle.current_size(),
+            color: particle.color,
+            uv_min: particle.uv.min,
+            uv_max: particle.uv.max,
+        })
+        .collect();
+
+    renderer.particle_instances = (!instance_data.is_empty()).then(|| {
+        let buffer = renderer
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("particle_instances"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        (buffer, instance_data.len() as u32)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_map::{Chunk, ChunkCoords};
+
+    fn particle(position: glam::DVec3) -> Particle {
+        Particle {
+            position,
+            velocity: glam::DVec3::ZERO,
+            gravity: 16.0,
+            age: 0.0,
+            lifetime: 1.0,
+            size: 0.1,
+            color: glam::Vec4::ONE,
+            uv: UvRect {
+                min: glam::Vec2::ZERO,
+                max: glam::Vec2::ONE,
+            },
+            vanishes_on_impact: false,
+        }
+    }
+
+    #[test]
+    fn particles_rest_on_blocks_and_expire() {
+        let mut game_map = GameMap::new();
+        game_map
+            .chunks
+            .insert(ChunkCoords::new(0, 0, 0), Chunk::new());
+        game_map.set_block_world(glam::IVec3::new(1, 1, 1), Some(1));
+
+        let mut particles = Particles::default();
+        particles.spawn(particle(glam::DVec3::new(1.5, 3.0, 1.5)));
+
+        for _ in 0..UPDATES_PER_SECOND / 2 {
+            particles.update(&game_map);
+        }
+
+        let position = particles.particles[0].position;
+        assert!(position.y >= 2.0 && position.y < 2.5, "{position}");
+
+        for _ in 0..UPDATES_PER_SECOND {
+            particles.update(&game_map);
+        }
+
+        assert_eq!(particles.particles.len(), 0);
+    }
+
+    #[test]
+    fn precipitation_stays_out_of_covered_columns() {
+        let mut game_map = GameMap::new();
+        game_map
+            .chunks
+            .insert(ChunkCoords::new(0, 0, 0), Chunk::new());
+
+        for z in 0..Chunk::SIZE {
+            for x in 0..Chunk::SIZE {
+                game_map.set_block_world(glam::IVec3::new(x, Chunk::SIZE - 1, z), Some(1));
+            }
+        }
+
+        let mut particles = Particles::default();
+        particles.precipitate(glam::DVec3::splat(16.0), &game_map, 100);
+        assert!(particles.particles.is_empty());
+
+        // the sky is open above unloaded chunks
+        particles.precipitate(glam::DVec3::new(16.0, 100.0, 16.0), &game_map, 100);
+        assert_eq!(particles.particles.len(), 100);
+    }
+
+    #[test]
+    fn oldest_particles_are_replaced() {
+        let mut particles = Particles::default();
+
+        for x in 0..MAX_PARTICLES + 2 {
+            particles.spawn(particle(glam::DVec3::X * x as f64));
+        }
+
+        assert_eq!(particles.particles.len(), MAX_PARTICLES);
+        assert_eq!(
+            particles.particles[1].position.x,
+            (MAX_PARTICLES + 1) as f64
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    coords,
+    fixed::{Fixed, FixedVec3},
+    game_map::GameMap,
+    game_mode::GameMode,
+    health::{Damage, DamageCause, DamageEvents},
+    input::InputState,
+    player::{LocalPlayer, RemotePlayer},
+    settings::Settings,
+    spawning::Mob,
+    UPDATES_PER_SECOND,
+};
+
+/// Collision passes per update, crowds of entities need a few passes to spread out evenly.
+const SUBSTEPS: u32 = 4;
+/// Edge length of the cells of the spatial index, larger than any entity.
+const CELL_SIZE: f64 = 2.0;
+const PLAYER_RADIUS: f64 = 0.3;
+const PLAYER_HEIGHT: f64 = 1.8;
+/// Height of the camera above the feet of the local player.
+pub const EYE_HEIGHT: f64 = 1.6;
+/// Falls up to this many blocks don't hurt, every further block costs a point of health.
+const SAFE_FALL_DISTANCE: f64 = 3.0;
+/// Moving down further than this in one update is a teleport rather than a fall.
+const MAX_FALL_STEP: f64 = 1.0;
+/// In blocks per second squared.
+const GRAVITY: f64 = 20.0;
+const TERMINAL_VELOCITY: f64 = 40.0;
+/// Upwards speed of a jump in blocks per second, enough to get on top of a block.
+const JUMP_VELOCITY: f64 = 7.0;
+
+/// Vertical cylinder occupied by an entity.
+#[derive(Debug, Clone, Copy)]
+struct Body {
+    id: EntityId,
+    /// Center of the bottom of the cylinder.
+    position: glam::DVec3,
+    radius: f64,
+    height: f64,
+    /// Remote players are moved by the server only, they push others but aren't pushed.
+    movable: bool,
+    moved: bool,
+}
+
+impl Body {
+    fn bounds(&self) -> (glam::DVec3, glam::DVec3) {
+        let extent = glam::DVec3::new(self.radius, 0.0, self.radius);
+
+        (
+            self.position - extent,
+            self.position + extent + glam::DVec3::Y * self.height,
+        )
+    }
+
+    /// Returns true if no block intersects the column of the body at the given position.
+    fn fits(&self, game_map: &GameMap, position: glam::DVec3) -> bool {
+        let bottom = position.floor().as_ivec3();
+        let top = (position.y + self.height).ceil() as i32;
+
+        (bottom.y..top).all(|y| {
+            game_map
+                .get_block_world(glam::IVec3::new(bottom.x, y, bottom.z))
+                .is_none()
+        })
+    }
+}
+
+/// Entities sorted into the cells of a uniform grid, so only entities close to each other are tested for collisions.
+#[derive(Debug, Default, Unique)]
+pub struct SpatialIndex {
+    cells: HashMap<glam::IVec3, Vec<EntityId>>,
+}
+
+impl SpatialIndex {
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    /// Adds an entity to every cell its bounds overlap.
+    pub fn insert(&mut self, id: EntityId, min: glam::DVec3, max: glam::DVec3) {
+        let (min, max) = (cell_of(min), cell_of(max));
+
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    self.cells
+                        .entry(glam::IVec3::new(x, y, z))
+                        .or_default()
+                        .push(id);
+                }
+            }
+        }
+    }
+
+    /// Returns entities in the cells overlapped by the bounds, each of them once.
+    pub fn query(&self, min: glam::DVec3, max: glam::DVec3) -> Vec<EntityId> {
+        let (min, max) = (cell_of(min), cell_of(max));
+        let mut found = Vec::new();
+
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    if let Some(ids) = self.cells.get(&glam::IVec3::new(x, y, z)) {
+                        found.extend(ids.iter().copied());
+                    }
+                }
+            }
+        }
+
+        found.sort_unstable();
+        found.dedup();
+
+        found
+    }
+}
+
+fn cell_of(position: glam::DVec3) -> glam::IVec3 {
+    (position / CELL_SIZE).floor().as_ivec3()
+}
+
+/// Moves a position by the input directions rotated by the yaw, like the player moves normally,
+/// but in fixed-point math so the result is the same on every platform.
+///
+/// Input components are -1, 0 or 1, the result stays on the fixed-point grid as long as the position does.
+pub fn deterministic_movement(
+    position: glam::DVec3,
+    input: glam::Vec3,
+    yaw: f32,
+    speed: f32,
+) -> glam::DVec3 {
+    let input = FixedVec3::from_dvec3(input.as_dvec3());
+    let length = input.length();
+
+    if length == Fixed::ZERO {
+        return position;
+    }
+
+    // f32 to f64 is exact, so converting the yaw and speed doesn't depend on the platform
+    let movement = input
+        .scale(Fixed::from_f64(speed as f64) / length)
+        .rotate_y(Fixed::from_f64(yaw as f64));
+
+    (FixedVec3::from_dvec3(position) + movement).to_dvec3()
+}
+
+/// Returns how far `b` has to be pushed away from `a` horizontally to stop overlapping, or None if they don't.
+fn separation(
+    a: glam::DVec3,
+    b: glam::DVec3,
+    min_distance: f64,
+    deterministic: bool,
+) -> Option<glam::DVec3> {
+    if deterministic {
+        let mut offset = FixedVec3::from_dvec3(b) - FixedVec3::from_dvec3(a);
+        offset.y = Fixed::ZERO;
+
+        let distance = offset.length();
+        let min_distance = Fixed::from_f64(min_distance);
+
+        if distance >= min_distance {
+            return None;
+        }
+
+        let direction = if distance > Fixed::EPSILON {
+            offset.scale(Fixed::ONE / distance)
+        } else {
+            FixedVec3::new(Fixed::ONE, Fixed::ZERO, Fixed::ZERO)
+        };
+
+        return Some(direction.scale(min_distance - distance).to_dvec3());
+    }
+
+    let mut offset = b - a;
+    offset.y = 0.0;
+
+    let distance = offset.length();
+
+    if distance >= min_distance {
+        return None;
+    }
+
+    // entities standing exactly on top of each other still have to be separated somehow
+    let direction = if distance > f64::EPSILON {
+        offset / distance
+    } else {
+        glam::DVec3::X
+    };
+
+    Some(direction * (min_distance - distance))
+}
+
+/// Pushes apart the bodies of two entities if they overlap.
+/// Bodies are only moved horizontally and never into blocks.
+fn push_apart(game_map: &GameMap, a: &mut Body, b: &mut Body, deterministic: bool) {
+    let vertical_overlap =
+        a.position.y < b.position.y + b.height && b.position.y < a.position.y + a.height;
+
+    if !vertical_overlap {
+        return;
+    }
+
+    let Some(separation) = separation(a.position, b.position, a.radius + b.radius, deterministic)
+    else {
+        return;
+    };
+
+    let (share_a, share_b) = match (a.movable, b.movable) {
+        (true, true) => (0.5, 0.5),
+        (true, false) => (1.0, 0.0),
+        (false, true) => (0.0, 1.0),
+        (false, false) => return,
+    };
+
+    // halving a fixed-point value and adding it to a position on the fixed-point grid is exact in f64
+    for (body, push) in [(a, -separation * share_a), (b, separation * share_b)] {
+        let position = body.position + push;
+
+        if push != glam::DVec3::ZERO && body.fits(game_map, position) {
+            body.position = position;
+            body.moved = true;
+        }
+    }
+}
+
+/// Resolves overlaps between players and mobs, so they don't walk through each other.
+pub fn entity_collision_sys(
+    game_map: UniqueView<GameMap>,
+    settings: UniqueView<Settings>,
+    mut index: UniqueViewMut<SpatialIndex>,
+    mut camera: UniqueViewMut<Camera>,
+    local_players: View<LocalPlayer>,
+    remote_players: View<RemotePlayer>,
+    mut mobs: ViewMut<Mob>,
+) {
+    let mut bodies: Vec<Body> = Vec::new();
+
+    for (id, _) in local_players.iter().with_id() {
+        bodies.push(Body {
+            id,
+            position: camera.eye - glam::DVec3::Y * EYE_HEIGHT,
+            radius: PLAYER_RADIUS,
+            height: PLAYER_HEIGHT,
+            movable: true,
+            moved: false,
+        });
+    }
+
+    for (id, player) in remote_players.iter().with_id() {
+        bodies.push(Body {
+            id,
+            position: player.position,
+            radius: PLAYER_RADIUS,
+            height: PLAYER_HEIGHT,
+            movable: false,
+            moved: false,
+        });
+    }
+
+    for (id, mob) in mobs.iter().with_id() {
+        bodies.push(Body {
+            id,
+            position: mob.position,
+            radius: Mob::SIZE.x.max(Mob::SIZE.z) as f64 / 2.0,
+            height: Mob::SIZE.y as f64,
+            movable: true,
+            moved: false,
+        });
+    }
+
+    if bodies.len() < 2 {
+        return;
+    }
+
+    let body_indices: HashMap<EntityId, usize> = bodies
+        .iter()
+        .enumerate()
+        .map(|(idx, body)| (body.id, idx))
+        .collect();
+
+    for _ in 0..SUBSTEPS {
+        index.clear();
+
+        for body in bodies.iter() {
+            let (min, max) = body.bounds();
+            index.insert(body.id, min, max);
+        }
+
+        for idx in 0..bodies.len() {
+            let (min, max) = bodies[idx].bounds();
+
+            for other in index.query(min, max) {
+                let other_idx = body_indices[&other];
+
+                // every pair is resolved once, from the body that comes first
+                if other_idx <= idx {
+                    continue;
+                }
+
+                let (left, right) = bodies.split_at_mut(other_idx);
+                push_apart(
+                    &game_map,
+                    &mut left[idx],
+                    &mut right[0],
+                    settings.physics.deterministic,
+                );
+            }
+        }
+    }
+
+    for body in bodies.iter().filter(|body| body.moved) {
+        if local_players.contains(body.id) {
+            camera.eye = body.position + glam::DVec3::Y * EYE_HEIGHT;
+        } else if let Ok(mob) = (&mut mobs).get(body.id) {
+            mob.position = body.position;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replays a fixed sequence of inputs, the final position has to be the same on every platform.
+    #[test]
+    fn deterministic_movement_is_bit_exact() {
+        let mut position = glam::DVec3::new(10.25, 64.0, -3.5);
+
+        for step in 0..1000 {
+            let input = glam::Vec3::new(
+                [0.0, 1.0, -1.0][step % 3],
+                [0.0, 0.0, 0.0, 1.0, -1.0][step % 5],
+                [1.0, 1.0, 0.0, -1.0][step % 4],
+            );
+            let yaw = (step as f32 * 7.3) % 360.0 - 180.0;
+
+            position = deterministic_movement(position, input, yaw, 0.05);
+        }
+
+        assert_eq!(
+            [position.x, position.y, position.z].map(f64::to_bits),
+            [
+                4621929643661066240,
+                4634204590550515712,
+                13838731520168165376
+            ]
+        );
+    }
+
+    #[test]
+    fn deterministic_movement_matches_float_movement() {
+        let input = glam::Vec3::new(1.0, 0.0, 1.0);
+        let yaw: f32 = 30.0;
+
+        let expected = glam::DVec3::new(1.0, 2.0, 3.0)
+            + (glam::Mat3::from_rotation_y(yaw.to_radians()) * input.normalize() * 0.05).as_dvec3();
+        let moved = deterministic_movement(glam::DVec3::new(1.0, 2.0, 3.0), input, yaw, 0.05);
+
+        assert!(moved.distance(expected) < 1e-6);
+        assert_eq!(
+            deterministic_movement(glam::DVec3::ONE, glam::Vec3::ZERO, yaw, 0.05),
+            glam::DVec3::ONE
+        );
+    }
+
+    #[test]
+    fn deterministic_separation() {
+        let a = glam::DVec3::new(0.0, 0.0, 0.0);
+        let b = glam::DVec3::new(0.3, 0.5, 0.4);
+
+        let push = separation(a, b, 0.6, true).unwrap();
+        assert!(push.distance(separation(a, b, 0.6, false).unwrap()) < 1e-8);
+        assert_eq!(
+            [push.x, push.y, push.z].map(f64::to_bits),
+            [4588807732361953280, 0, 4590429028208345088]
+        );
+
+        assert_eq!(
+            separation(a, a, 0.6, true),
+            Some(glam::DVec3::X * Fixed::from_f64(0.6).to_f64())
+        );
+        assert_eq!(separation(a, glam::DVec3::X, 0.6, true), None);
+    }
+}
+
+/// How far the local player has fallen without touching the ground.
+#[derive(Debug, Default, Unique)]
+pub struct FallTracker {
+    last_feet: Option<glam::DVec3>,
+    distance: f64,
+}
+
+/// Hurts the local player landing on a block after falling further than `SAFE_FALL_DISTANCE`.
+pub fn fall_damage_sys(
+    camera: UniqueView<Camera>,
+    game_map: UniqueView<GameMap>,
+    mut tracker: UniqueViewMut<FallTracker>,
+    mut damage_events: UniqueViewMut<DamageEvents>,
+    local_players: View<LocalPlayer>,
+) {
+    let feet = camera.eye - glam::DVec3::Y * EYE_HEIGHT;
+    let drop = tracker.last_feet.map_or(0.0, |last| last.y - feet.y);
+    tracker.last_feet = Some(feet);
+
+    if drop > 0.0 && drop <= MAX_FALL_STEP {
+        tracker.distance += drop;
+    } else if drop != 0.0 {
+        // moving up or teleporting ends the fall
+        tracker.distance = 0.0;
+    }
+
+    let on_ground = game_map
+        .get_block_world((feet - glam::DVec3::Y * 0.01).floor().as_ivec3())
+        .is_some();
+
+    if !on_ground {
+        return;
+    }
+
+    let distance = std::mem::take(&mut tracker.distance);
+    if distance <= SAFE_FALL_DISTANCE {
+        return;
+    }
+
+    for entity in local_players.iter().ids() {
+        damage_events.push(Damage {
+            entity,
+            amount: (distance - SAFE_FALL_DISTANCE).ceil() as u32,
+            cause: DamageCause::Fall,
+        });
+    }
+}
+
+/// Returns true if no block intersects the column of the local player with the camera at `eye`.
+fn player_fits(game_map: &GameMap, eye: glam::DVec3) -> bool {
+    let feet = eye - glam::DVec3::Y * EYE_HEIGHT;
+    let bottom = feet.floor().as_ivec3();
+    let top = (feet.y + PLAYER_HEIGHT).ceil() as i32;
+
+    (bottom.y..top).all(|y| {
+        game_map
+            .get_block_world(glam::IVec3::new(bottom.x, y, bottom.z))
+            .is_none()
+    })
+}
+
+/// Returns where the player walking from `from` to `to` ends up, sliding along blocks in the way.
+pub fn slide_player(game_map: &GameMap, from: glam::DVec3, to: glam::DVec3) -> glam::DVec3 {
+    // a player stuck inside of blocks can always walk out
+    if !player_fits(game_map, from) {
+        return to;
+    }
+
+    [
+        to,
+        glam::DVec3::new(to.x, from.y, from.z),
+        glam::DVec3::new(from.x, from.y, to.z),
+    ]
+    .into_iter()
+    .find(|&eye| player_fits(game_map, eye))
+    .unwrap_or(from)
+}
+
+/// Vertical movement of the local player while they can't fly.
+#[derive(Debug, Default, Unique)]
+pub struct PlayerMotion {
+    vertical_velocity: f64,
+}
+
+/// Pulls the local player down to the ground unless they can fly, the up key jumps.
+pub fn player_gravity_sys(
+    game_mode: UniqueView<GameMode>,
+    input_state: UniqueView<InputState>,
+    game_map: UniqueView<GameMap>,
+    mut camera: UniqueViewMut<Camera>,
+    mut motion: UniqueViewMut<PlayerMotion>,
+) {
+    let feet = camera.eye - glam::DVec3::Y * EYE_HEIGHT;
+
+    // the player would fall through chunks which aren't loaded yet
+    if game_mode.can_fly()
+        || !game_map
+            .chunks
+            .contains_key(&coords::chunk_of_block(coords::block_containing(feet)))
+    {
+        motion.vertical_velocity = 0.0;
+        return;
+    }
+
+    let dt = 1.0 / UPDATES_PER_SECOND as f64;
+    let on_ground = game_map
+        .get_block_world(coords::block_containing(feet - glam::DVec3::Y * 0.01))
+        .is_some();
+
+    if on_ground && motion.vertical_velocity <= 0.0 {
+        motion.vertical_velocity = if input_state.upward && input_state.cursor_captured {
+            JUMP_VELOCITY
+        } else {
+            return;
+        };
+    }
+
+    motion.vertical_velocity = (motion.vertical_velocity - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+    let eye = camera.eye + glam::DVec3::Y * (motion.vertical_velocity * dt);
+
+    if player_fits(&game_map, eye) {
+        camera.eye = eye;
+    } else if motion.vertical_velocity < 0.0 {
+        // landed, the feet rest on top of the block
+        let feet = (eye.y - EYE_HEIGHT).floor() + 1.0;
+        camera.eye.y = feet + EYE_HEIGHT;
+        motion.vertical_velocity = 0.0;
+    } else {
+        // bumped the head
+        motion.vertical_velocity = 0.0;
+    }
+}
@@ -0,0 +1,18 @@
+use shipyard::*;
+
+/// Marks the entity controlled by this client.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct LocalPlayer;
+
+/// Another player connected to the same server, mirrored from its replicated entity.
+#[derive(Debug, Clone, Component)]
+pub struct RemotePlayer {
+    pub name: String,
+    /// Position of the center of the player's feet.
+    pub position: glam::DVec3,
+}
+
+impl RemotePlayer {
+    /// Height above the feet at which name tags are drawn.
+    pub const NAME_TAG_HEIGHT: f64 = 2.1;
+}
@@ -0,0 +1,412 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+use shipyard::*;
+use wasmtime::{Caller, Config, Engine, Linker, Module, Store, TypedFunc, WasmParams, WasmResults};
+
+use crate::{
+    block::{BlockData, BlockFaces},
+    color::Color,
+    game_map::{BlockId, Chunk, ChunkCoords, InnerChunkCoords},
+};
+
+/// Module name mods import the host functions from.
+const HOST_MODULE: &str = "landmark";
+/// Instructions a mod can execute in a single call, so a stuck mod doesn't freeze the game.
+const FUEL_PER_CALL: u64 = 10_000_000;
+
+/// Part of the game a mod can access through the host functions.
+#[derive(Default)]
+struct HostState {
+    /// Set once `init` returned, blocks can't be registered after that.
+    initialized: bool,
+    registered_blocks: Vec<BlockData>,
+    /// IDs of the loaded blocks by name, only present during `chunk_generated`.
+    block_ids: HashMap<String, BlockId>,
+    /// The chunk being generated, only present during `chunk_generated`.
+    chunk: Option<Chunk>,
+}
+
+struct Plugin {
+    /// File name of the module without the extension.
+    name: String,
+    store: Store<HostState>,
+    tick: Option<TypedFunc<u64, ()>>,
+    chunk_generated: Option<TypedFunc<(i32, i32, i32), ()>>,
+}
+
+impl Plugin {
+    fn load(engine: &Engine, linker: &Linker<HostState>, path: &Path) -> anyhow::Result<Self> {
+        let module = Module::from_file(engine, path)?;
+        let mut store = Store::new(engine, HostState::default());
+
+        store.set_fuel(FUEL_PER_CALL)?;
+        let instance = linker.instantiate(&mut store, &module)?;
+
+        if let Ok(init) = instance.get_typed_func::<(), ()>(&mut store, "init") {
+            call_with_fuel(&mut store, &init, ())?;
+        }
+
+        store.data_mut().initialized = true;
+
+        Ok(Self {
+            name: path
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            tick: instance.get_typed_func(&mut store, "tick").ok(),
+            chunk_generated: instance.get_typed_func(&mut store, "chunk_generated").ok(),
+            store,
+        })
+    }
+}
+
+/// Gameplay mods compiled to WebAssembly, loaded from `mods/` in the working directory.
+///
+/// A mod may export any of these functions:
+/// - `init()` is called once after loading, blocks can only be registered here.
+/// - `tick(count: i64)` is called every update with the number of updates since the mods were loaded.
+/// - `chunk_generated(x: i32, y: i32, z: i32)` is called with the coordinates of every generated chunk
+///   before it is added to the map, the chunk can be modified during the call.
+///
+/// Functions imported from the `landmark` module, strings are passed as a pointer and a length
+/// into the exported `memory` and block IDs of -1 mean air:
+/// - `log(ptr: i32, len: i32)` writes a message to the game log.
+/// - `register_block(ptr: i32, len: i32, rgb: i32)` defines a block with a plain color given as `0xRRGGBB`.
+/// - `block_id(ptr: i32, len: i32) -> i32` returns the ID of a block by name, -1 if it doesn't exist.
+/// - `get_block(x: i32, y: i32, z: i32) -> i32` and `set_block(x: i32, y: i32, z: i32, id: i32)`
+///   access blocks of the generated chunk, positions go from 0 to 31 within the chunk.
+///
+/// A mod which traps or runs out of fuel is disabled, the others keep running.
+#[derive(Unique)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+    ticks: u64,
+}
+
+impl Plugins {
+    pub const DIR: &'static str = "mods";
+
+    /// Creates the subsystem without any mods.
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+            ticks: 0,
+        }
+    }
+
+    /// Loads `.wasm` and `.wat` modules from the mods directory in file name order and initializes them.
+    /// Mods which fail to load are skipped.
+    pub fn load() -> Self {
+        let mut plugins = Self::new();
+
+        let paths = match mod_paths(Path::new(Self::DIR)) {
+            Ok(paths) if paths.is_empty() => return plugins,
+            Ok(paths) => paths,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return plugins,
+            Err(e) => {
+                log::error!("Failed to list mods in {}: {e}", Self::DIR);
+                return plugins;
+            }
+        };
+
+        let (engine, linker) = match runtime() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::error!("Failed to set up the WebAssembly runtime: {e:#}, mods are disabled");
+                return plugins;
+            }
+        };
+
+        for path in paths {
+            match Plugin::load(&engine, &linker, &path) {
+                Ok(plugin) => {
+                    log::info!("Loaded mod {}", plugin.name);
+                    plugins.plugins.push(plugin);
+                }
+                Err(e) => log::error!("Failed to load mod {}: {e:#}", path.display()),
+            }
+        }
+
+        plugins
+    }
+
+    /// Returns blocks registered by all mods, in the order the mods were loaded.
+    pub fn registered_blocks(&self) -> Vec<BlockData> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.store.data().registered_blocks.iter().cloned())
+            .collect()
+    }
+
+    pub fn tick(&mut self) {
+        let ticks = self.ticks;
+        self.ticks += 1;
+
+        self.plugins.retain_mut(|plugin| {
+            let Some(tick) = &plugin.tick else {
+                return true;
+            };
+
+            let result = call_with_fuel(&mut plugin.store, tick, ticks);
+            keep_plugin(plugin, result)
+        });
+    }
+
+    /// Lets mods modify a generated chunk before it is added to the map.
+    pub fn chunk_generated(
+        &mut self,
+        coords: ChunkCoords,
+        chunk: Chunk,
+        block_ids: &HashMap<String, BlockId>,
+    ) -> Chunk {
+        let mut chunk = Some(chunk);
+
+        self.plugins.retain_mut(|plugin| {
+            let Some(chunk_generated) = &plugin.chunk_generated else {
+                return true;
+            };
+
+            let state = plugin.store.data_mut();
+            state.chunk = chunk.take();
+            state.block_ids.clone_from(block_ids);
+
+            let result = call_with_fuel(
+                &mut plugin.store,
+                chunk_generated,
+                (coords.x, coords.y, coords.z),
+            );
+
+            // changes made before a failure are kept
+            chunk = plugin.store.data_mut().chunk.take();
+            keep_plugin(plugin, result)
+        });
+
+        chunk.expect("Chunk was not returned by a mod")
+    }
+}
+
+impl Default for Plugins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugins")
+            .field(
+                "plugins",
+                &self
+                    .plugins
+                    .iter()
+                    .map(|plugin| &plugin.name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("ticks", &self.ticks)
+            .finish()
+    }
+}
+
+/// Runs mods every update.
+pub fn plugins_tick_sys(mut plugins: UniqueViewMut<Plugins>) {
+    plugins.tick();
+}
+
+/// Creates the engine running mods and links the host functions.
+fn runtime() -> anyhow::Result<(Engine, Linker<HostState>)> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+
+    let engine = Engine::new(&config)?;
+    let linker = host_api(&engine)?;
+
+    Ok((engine, linker))
+}
+
+/// Returns paths of the modules in a directory, sorted so registered blocks get the same IDs on every start.
+fn mod_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("wasm" | "wat")
+        ) {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn call_with_fuel<P: WasmParams, R: WasmResults>(
+    store: &mut Store<HostState>,
+    func: &TypedFunc<P, R>,
+    params: P,
+) -> anyhow::Result<R> {
+    store.set_fuel(FUEL_PER_CALL)?;
+    func.call(store, params)
+}
+
+/// Logs the failure of a mod, returns false if it should be disabled.
+fn keep_plugin(plugin: &Plugin, result: anyhow::Result<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("Mod {} failed and was disabled: {e:#}", plugin.name);
+            false
+        }
+    }
+}
+
+/// Reads a UTF-8 string from the memory of a mod.
+fn read_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> anyhow::Result<String> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| anyhow!("mod doesn't export its memory"))?;
+
+    let bytes = memory
+        .data(&caller)
+        .get(ptr as u32 as usize..)
+        .and_then(|data| data.get(..len as u32 as usize))
+        .ok_or_else(|| anyhow!("string is outside of the memory"))?;
+
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+/// Converts a position within the chunk passed by a mod, failing if it's outside of the chunk.
+fn inner_coords(x: i32, y: i32, z: i32) -> anyhow::Result<InnerChunkCoords> {
+    if [x, y, z]
+        .iter()
+        .any(|value| !(0..Chunk::SIZE).contains(value))
+    {
+        bail!("position {x} {y} {z} is outside of the chunk");
+    }
+
+    Ok(InnerChunkCoords::new(x, y, z))
+}
+
+fn current_chunk<'a>(caller: &'a mut Caller<'_, HostState>) -> anyhow::Result<&'a mut Chunk> {
+    caller
+        .data_mut()
+        .chunk
+        .as_mut()
+        .ok_or_else(|| anyhow!("chunks can only be accessed in chunk_generated"))
+}
+
+/// Defines the functions mods import from the host module.
+fn host_api(engine: &Engine) -> anyhow::Result<Linker<HostState>> {
+    let mut linker = Linker::new(engine);
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> anyhow::Result<()> {
+            let message = read_string(&mut caller, ptr, len)?;
+            log::info!("[mod] {message}");
+
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "register_block",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32, rgb: i32| -> anyhow::Result<()> {
+            if caller.data().initialized {
+                bail!("blocks can only be registered in init");
+            }
+
+            let name = read_string(&mut caller, ptr, len)?;
+            let color = Color {
+                r: (rgb >> 16) as u8,
+                g: (rgb >> 8) as u8,
+                b: rgb as u8,
+            };
+
+            caller.data_mut().registered_blocks.push(BlockData {
+                name,
+                color,
+                texture: None,
+                variants: Vec::new(),
+                connected_texture: None,
+                faces: BlockFaces::default(),
+                hardness: 1.0,
+                falls: false,
+                portal: None,
+                random_tick: None,
+                light: None,
+                fluid: false,
+            });
+
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "block_id",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> anyhow::Result<i32> {
+            let name = read_string(&mut caller, ptr, len)?;
+
+            Ok(caller
+                .data()
+                .block_ids
+                .get(&name)
+                .map_or(-1, |id| *id as i32))
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "get_block",
+        |mut caller: Caller<'_, HostState>, x: i32, y: i32, z: i32| -> anyhow::Result<i32> {
+            let coords = inner_coords(x, y, z)?;
+            let block = current_chunk(&mut caller)?.get_block(coords);
+
+            Ok(block.map_or(-1, |id| id as i32))
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "set_block",
+        |mut caller: Caller<'_, HostState>,
+         x: i32,
+         y: i32,
+         z: i32,
+         id: i32|
+         -> anyhow::Result<()> {
+            let coords = inner_coords(x, y, z)?;
+
+            let block = if id < 0 {
+                None
+            } else if caller
+                .data()
+                .block_ids
+                .values()
+                .any(|known| *known == id as BlockId)
+            {
+                Some(id as BlockId)
+            } else {
+                bail!("block {id} doesn't exist");
+            };
+
+            current_chunk(&mut caller)?.set_block(coords, block);
+
+            Ok(())
+        },
+    )?;
+
+    Ok(linker)
+}
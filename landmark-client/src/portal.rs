@@ -0,0 +1,58 @@
+//! Portal blocks, which move a player stepping onto them to the position their link maps to.
+//!
+//! The move goes through `PendingTeleport`, so the chunks at the destination are streamed in before
+//! the camera leaves and the player never lands in unloaded terrain. While connected, the server sends
+//! players through portals.
+
+use shipyard::*;
+
+use crate::{
+    block::PortalLink, camera::Camera, game_map::GameMap, loader::ResourceDictionary,
+    physics::EYE_HEIGHT, teleport::PendingTeleport,
+};
+
+/// Remembers whether the player was on a portal in the last update, portals only trigger when stepped onto.
+/// Otherwise arriving on a portal at the destination would immediately send the player back.
+#[derive(Debug, Default, Unique)]
+pub struct PortalTracker {
+    on_portal: bool,
+}
+
+/// Returns the link of the portal a player with the eye at `eye` stands in or on.
+pub fn portal_at(
+    game_map: &GameMap,
+    resource_dictionary: &ResourceDictionary,
+    eye: glam::DVec3,
+) -> Option<PortalLink> {
+    let feet = eye - glam::DVec3::Y * EYE_HEIGHT;
+
+    // the block the feet are in, for portals lower than a full block, and the block stood on
+    [feet, feet - glam::DVec3::Y * 0.01]
+        .into_iter()
+        .filter_map(|position| game_map.get_block_world(position.floor().as_ivec3()))
+        .find_map(|block| resource_dictionary.get_block_data_from_id(block).portal)
+}
+
+/// Sends the local player through the portal block they stepped onto.
+pub fn portal_sys(
+    camera: UniqueView<Camera>,
+    game_map: UniqueView<GameMap>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    mut tracker: UniqueViewMut<PortalTracker>,
+    mut teleport: UniqueViewMut<PendingTeleport>,
+) {
+    if teleport.connected {
+        return;
+    }
+
+    let link = portal_at(&game_map, &resource_dictionary, camera.eye);
+
+    let was_on_portal = std::mem::replace(&mut tracker.on_portal, link.is_some());
+    if was_on_portal {
+        return;
+    }
+
+    if let Some(link) = link {
+        teleport.request(link.destination(camera.eye));
+    }
+}
@@ -0,0 +1,259 @@
+//! Prediction of the local player's movement while the server decides where the player is.
+//!
+//! The player moves locally right away, and every update which moved it is sent to the server with a
+//! sequence number and the position it reached. When the server reports the position after one of the
+//! updates, the predicted position is shifted by how far that differs from what the client had at that
+//! point. The shift is blended away over a few updates, so small corrections don't make the camera jump.
+
+use std::collections::VecDeque;
+
+use landmark_protocol::message::PlayerInput;
+use shipyard::*;
+
+use crate::{camera::Camera, physics::deterministic_movement};
+
+/// Distance moved per update with a single movement key held.
+pub const MOVEMENT_SPEED: f32 = 0.05;
+/// Inputs kept while waiting for the server, about ten seconds of moving.
+const MAX_PENDING_INPUTS: usize = 2400;
+/// Corrections larger than this are applied at once, e.g. after the server moved the player back.
+const SNAP_DISTANCE: f64 = 2.0;
+/// Share of the remaining correction applied every update.
+const CORRECTION_RATE: f64 = 0.2;
+/// Corrections shorter than this are applied at once instead of blending them forever.
+const MIN_CORRECTION: f64 = 0.001;
+
+#[derive(Debug, Default, Unique)]
+pub struct Prediction {
+    /// Set by the network code while connected to a server, inputs are only recorded then.
+    pub connected: bool,
+    next_sequence: u32,
+    /// Inputs applied locally which the server hasn't acknowledged yet, oldest first.
+    pending: VecDeque<PlayerInput>,
+    /// Inputs which weren't sent yet, drained by the network code.
+    pub outgoing: Vec<PlayerInput>,
+    /// Positions reported by the server, with the sequence number of the last input they include.
+    pub corrections: Vec<(u32, glam::DVec3)>,
+    /// Position and yaw of the last recorded input, updates which don't change them aren't sent.
+    last_recorded: Option<(glam::DVec3, f32)>,
+    /// Difference between the shown and the predicted position, which is still being blended away.
+    error: glam::DVec3,
+}
+
+impl Prediction {
+    /// Numbers the position the player reached in this update, so it can be corrected once the server
+    /// processed it. Nothing is recorded if the player didn't move or turn.
+    pub fn record(&mut self, position: glam::DVec3, yaw: f32) {
+        if !self.connected || self.last_recorded == Some((position, yaw)) {
+            return;
+        }
+
+        let input = PlayerInput {
+            sequence: self.next_sequence,
+            position: position.to_array(),
+            yaw,
+        };
+
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.last_recorded = Some((position, yaw));
+
+        if self.pending.len() == MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+
+        self.pending.push_back(input);
+        self.outgoing.push(input);
+    }
+
+    /// Returns the position predicted from the one reported by the server, keeping the difference to the
+    /// current position to blend it away.
+    pub fn reconcile(
+        &mut self,
+        last_input: u32,
+        server_position: glam::DVec3,
+        current: glam::DVec3,
+    ) -> glam::DVec3 {
+        // sequence numbers wrap around, an input is acknowledged if it isn't after the last processed one
+        let mut acknowledged = None;
+        while self
+            .pending
+            .front()
+            .is_some_and(|input| (input.sequence.wrapping_sub(last_input) as i32) <= 0)
+        {
+            acknowledged = self.pending.pop_front();
+        }
+
+        // a correction for an input which was already corrected
+        let Some(acknowledged) = acknowledged.filter(|input| input.sequence == last_input) else {
+            return current;
+        };
+
+        let offset = server_position - glam::DVec3::from_array(acknowledged.position);
+        if offset == glam::DVec3::ZERO {
+            return current;
+        }
+
+        // the inputs the server hasn't processed yet started from the corrected position
+        for input in self.pending.iter_mut() {
+            input.position = (glam::DVec3::from_array(input.position) + offset).to_array();
+        }
+        self.last_recorded = None;
+
+        let predicted = current + offset;
+
+        if offset.length() > SNAP_DISTANCE {
+            log::warn!(
+                "Predicted position was off by {:.2} blocks, moving to the server position",
+                offset.length()
+            );
+            self.error = glam::DVec3::ZERO;
+
+            return predicted;
+        }
+
+        self.error = current - predicted;
+        current
+    }
+
+    /// Forgets the inputs sent before the server teleported the player, it ignores them.
+    pub fn teleported(&mut self) {
+        self.pending.clear();
+        self.last_recorded = None;
+        self.error = glam::DVec3::ZERO;
+    }
+
+    /// Returns how much to move the shown position towards the predicted one this update.
+    fn correction_step(&mut self) -> glam::DVec3 {
+        let step = if self.error.length() < MIN_CORRECTION {
+            self.error
+        } else {
+            self.error * CORRECTION_RATE
+        };
+
+        self.error -= step;
+        step
+    }
+}
+
+/// Moves a position by the input directions rotated by the yaw. Input components are -1, 0 or 1.
+pub fn apply_movement(
+    position: glam::DVec3,
+    movement: glam::Vec3,
+    yaw: f32,
+    deterministic: bool,
+) -> glam::DVec3 {
+    if deterministic {
+        return deterministic_movement(position, movement, yaw, MOVEMENT_SPEED);
+    }
+
+    if movement == glam::Vec3::ZERO {
+        return position;
+    }
+
+    let movement =
+        glam::Mat3::from_rotation_y(yaw.to_radians()) * (movement.normalize() * MOVEMENT_SPEED);
+
+    position + movement.as_dvec3()
+}
+
+/// Records where the player moved during this update, then applies the latest position reported by
+/// the server and blends away the difference to the prediction.
+pub fn reconcile_player_sys(
+    mut prediction: UniqueViewMut<Prediction>,
+    mut camera: UniqueViewMut<Camera>,
+) {
+    prediction.record(camera.eye, camera.yaw);
+
+    let corrections = std::mem::take(&mut prediction.corrections);
+
+    // older corrections are superseded by the latest one
+    if let Some((last_input, position)) = corrections.last() {
+        camera.eye = prediction.reconcile(*last_input, *position, camera.eye);
+    }
+
+    if prediction.error != glam::DVec3::ZERO {
+        camera.eye -= prediction.correction_step();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected() -> Prediction {
+        Prediction {
+            connected: true,
+            ..Prediction::default()
+        }
+    }
+
+    #[test]
+    fn only_changes_are_recorded() {
+        let mut prediction = Prediction::default();
+        prediction.record(glam::DVec3::ZERO, 0.0);
+        assert!(prediction.outgoing.is_empty());
+
+        let mut prediction = connected();
+        prediction.record(glam::DVec3::ZERO, 0.0);
+        prediction.record(glam::DVec3::ZERO, 0.0);
+        prediction.record(glam::DVec3::X, 0.0);
+        prediction.record(glam::DVec3::X, 90.0);
+
+        let sequences: Vec<u32> = prediction
+            .outgoing
+            .iter()
+            .map(|input| input.sequence)
+            .collect();
+        assert_eq!(sequences, [0, 1, 2]);
+    }
+
+    #[test]
+    fn accepted_positions_keep_the_prediction() {
+        let mut prediction = connected();
+        prediction.record(glam::DVec3::X, 0.0);
+        prediction.record(glam::DVec3::X * 2.0, 0.0);
+
+        let current = glam::DVec3::X * 2.0;
+        assert_eq!(prediction.reconcile(0, glam::DVec3::X, current), current);
+        assert_eq!(prediction.error, glam::DVec3::ZERO);
+    }
+
+    #[test]
+    fn small_corrections_are_blended() {
+        let mut prediction = connected();
+        prediction.record(glam::DVec3::X, 0.0);
+        prediction.record(glam::DVec3::X * 2.0, 0.0);
+
+        // the server stopped the player half a block earlier
+        let current = glam::DVec3::X * 2.0;
+        let offset = glam::DVec3::X * -0.5;
+        assert_eq!(
+            prediction.reconcile(0, glam::DVec3::X + offset, current),
+            current
+        );
+        assert_eq!(prediction.error, -offset);
+
+        // the input the server hasn't seen yet is expected at the corrected position
+        let pending = prediction.pending.front().unwrap();
+        assert_eq!(pending.position, (glam::DVec3::X * 2.0 + offset).to_array());
+
+        // correcting the same input again does nothing
+        assert_eq!(
+            prediction.reconcile(0, glam::DVec3::X + offset, current),
+            current
+        );
+    }
+
+    #[test]
+    fn large_corrections_snap() {
+        let mut prediction = connected();
+        prediction.record(glam::DVec3::X * 100.0, 0.0);
+
+        let current = glam::DVec3::X * 100.0;
+        assert_eq!(
+            prediction.reconcile(0, glam::DVec3::ZERO, current),
+            glam::DVec3::ZERO
+        );
+        assert_eq!(prediction.error, glam::DVec3::ZERO);
+    }
+}
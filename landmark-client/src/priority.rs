@@ -0,0 +1,116 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{
+    camera::Camera,
+    game_map::{Chunk, ChunkCoords},
+};
+
+/// Chunks outside of the view are handled as if they were this many times further away.
+const OUT_OF_VIEW_PENALTY: f64 = 4.0;
+
+/// Ranks chunk jobs by distance to the camera, preferring chunks inside the view.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkPriority {
+    eye: glam::DVec3,
+    look_direction: glam::DVec3,
+    /// Tangent of the angle between the view direction and the corners of the view.
+    tan_half_fov: f64,
+}
+
+impl ChunkPriority {
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            eye: camera.eye,
+            look_direction: camera.look_direction(),
+            tan_half_fov: camera.tan_half_diagonal_fov(),
+        }
+    }
+
+    /// Ranks chunks only by their distance to `eye`, for players whose view isn't known.
+    pub fn around(eye: glam::DVec3) -> Self {
+        // without a direction and with an unbounded field of view every chunk counts as in view
+        Self {
+            eye,
+            look_direction: glam::DVec3::ZERO,
+            tan_half_fov: f64::MAX,
+        }
+    }
+
+    /// Returns true if the bounding sphere of the chunk intersects the view cone.
+    pub fn is_in_view(&self, coords: ChunkCoords) -> bool {
+        let (offset, radius) = self.chunk_offset(coords);
+        let distance = offset.length();
+
+        if distance <= radius {
+            return true;
+        }
+
+        let along = offset.dot(self.look_direction);
+        let across = (distance * distance - along * along).max(0.0).sqrt();
+        let cos_half_fov = 1.0 / (1.0 + self.tan_half_fov * self.tan_half_fov).sqrt();
+
+        along * self.tan_half_fov + radius / cos_half_fov >= across
+    }
+
+    /// Returns the priority of a chunk, chunks with lower keys should be handled first.
+    pub fn key(&self, coords: ChunkCoords) -> u64 {
+        let distance_squared = self.chunk_offset(coords).0.length_squared();
+
+        if self.is_in_view(coords) {
+            distance_squared as u64
+        } else {
+            (distance_squared * OUT_OF_VIEW_PENALTY * OUT_OF_VIEW_PENALTY) as u64
+        }
+    }
+
+    /// Returns the offset from the camera to the chunk center and the radius of its bounding sphere.
+    fn chunk_offset(&self, coords: ChunkCoords) -> (glam::DVec3, f64) {
+        let half_size = Chunk::SIZE as f64 / 2.0;
+        let center = coords.as_world_position() + glam::DVec3::splat(half_size);
+
+        (center - self.eye, half_size * 3f64.sqrt())
+    }
+}
+
+/// Queue of chunk jobs popped in order of their `ChunkPriority`.
+#[derive(Debug, Default)]
+pub struct ChunkJobQueue {
+    heap: BinaryHeap<Reverse<(u64, ChunkCoords)>>,
+}
+
+impl ChunkJobQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, coords: ChunkCoords, priority: &ChunkPriority) {
+        self.heap.push(Reverse((priority.key(coords), coords)));
+    }
+
+    pub fn pop(&mut self) -> Option<ChunkCoords> {
+        self.heap.pop().map(|Reverse((_, coords))| coords)
+    }
+
+    /// Recomputes priorities after the camera moved, dropping jobs for which `keep` returns false.
+    pub fn reprioritize(
+        &mut self,
+        priority: &ChunkPriority,
+        mut keep: impl FnMut(ChunkCoords) -> bool,
+    ) -> Vec<ChunkCoords> {
+        let mut dropped = Vec::new();
+
+        self.heap = std::mem::take(&mut self.heap)
+            .into_iter()
+            .filter_map(|Reverse((_, coords))| {
+                if keep(coords) {
+                    Some(Reverse((priority.key(coords), coords)))
+                } else {
+                    dropped.push(coords);
+                    None
+                }
+            })
+            .collect();
+
+        dropped
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use shipyard::*;
+
+use crate::input::InputState;
+
+/// Amount of spans kept for the trace dump, roughly 10 seconds of frames.
+const MAX_SPANS: usize = 8192;
+/// How often averages shown in the overlay are recomputed.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Chrome trace thread used for spans measured on the CPU.
+const CPU_TRACK: u32 = 0;
+/// Chrome trace thread used for spans measured with GPU timestamps.
+const GPU_TRACK: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    name: &'static str,
+    track: u32,
+    /// Offset from the creation of the profiler.
+    start: Duration,
+    duration: Duration,
+}
+
+/// Collects timings of the game loop stages and of GPU render passes.
+#[derive(Debug, Unique)]
+pub struct Profiler {
+    created: Instant,
+    spans: VecDeque<Span>,
+    /// Total duration and sample count per span name since the last summary.
+    accumulated: BTreeMap<&'static str, (Duration, u32)>,
+    last_summary: Instant,
+    summary: String,
+    /// Frames finished since the last summary.
+    frames: u32,
+    fps: f64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            created: Instant::now(),
+            spans: VecDeque::new(),
+            accumulated: BTreeMap::new(),
+            last_summary: Instant::now(),
+            summary: String::new(),
+            frames: 0,
+            fps: 0.0,
+        }
+    }
+
+    /// Records a span measured on the CPU.
+    pub fn record(&mut self, name: &'static str, start: Instant, duration: Duration) {
+        let start = start.saturating_duration_since(self.created);
+        self.push(name, CPU_TRACK, start, duration);
+    }
+
+    /// Records a span measured on the GPU. Only the duration is known,
+    /// so the span is placed at the time its results arrived.
+    pub fn record_gpu(&mut self, name: &'static str, duration: Duration) {
+        let start = self.created.elapsed().saturating_sub(duration);
+        self.push(name, GPU_TRACK, start, duration);
+    }
+
+    fn push(&mut self, name: &'static str, track: u32, start: Duration, duration: Duration) {
+        if self.spans.len() == MAX_SPANS {
+            self.spans.pop_front();
+        }
+
+        self.spans.push_back(Span {
+            name,
+            track,
+            start,
+            duration,
+        });
+
+        let (total, count) = self.accumulated.entry(name).or_default();
+        *total += duration;
+        *count += 1;
+    }
+
+    /// Counts a frame for the frame rate.
+    pub fn end_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Returns the frame rate measured for the last summary.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Returns average timings of each span once per second, meant for the debug overlay.
+    pub fn refresh_summary(&mut self) -> Option<&str> {
+        if self.last_summary.elapsed() < SUMMARY_INTERVAL {
+            return None;
+        }
+
+        self.summary.clear();
+
+        for (name, (total, count)) in self.accumulated.iter() {
+            let average = total.as_secs_f64() * 1000.0 / *count as f64;
+            let _ = write!(self.summary, "{name} {average:.2}ms  ");
+        }
+
+        self.summary.truncate(self.summary.trim_end().len());
+        self.accumulated.clear();
+        self.fps = self.frames as f64 / self.last_summary.elapsed().as_secs_f64();
+        self.frames = 0;
+        self.last_summary = Instant::now();
+
+        Some(&self.summary)
+    }
+
+    /// Writes recorded spans in the Chrome trace event format, viewable in `chrome://tracing` or Perfetto.
+    pub fn write_chrome_trace(&self, path: &Path) -> io::Result<()> {
+        let mut json = String::from("[\n");
+
+        for (idx, span) in self.spans.iter().enumerate() {
+            if idx > 0 {
+                json.push_str(",\n");
+            }
+
+            let _ = write!(
+                json,
+                r#"{{"name":"{}","ph":"X","pid":0,"tid":{},"ts":{},"dur":{}}}"#,
+                span.name,
+                span.track,
+                span.start.as_micros(),
+                span.duration.as_micros()
+            );
+        }
+
+        json.push_str("\n]\n");
+
+        fs::write(path, json)
+    }
+}
+
+/// Dumps the recorded spans to a trace file on request.
+pub fn dump_profile_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    profiler: UniqueView<Profiler>,
+) {
+    if !input_state.dump_profile {
+        return;
+    }
+
+    input_state.dump_profile = false;
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let path = format!("profile-{timestamp}.json");
+
+    match profiler.write_chrome_trace(Path::new(&path)) {
+        Ok(()) => log::info!("Wrote profile to {path}"),
+        Err(e) => log::error!("Failed to write profile to {path}: {e}"),
+    }
+}
+
+/// Measures render passes with timestamp queries, requires `Features::TIMESTAMP_QUERY`.
+#[derive(Debug)]
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Set by the map callback once the readback buffer can be read.
+    mapped: Arc<AtomicBool>,
+    /// True while the readback buffer is mapped or waiting to be mapped.
+    in_flight: bool,
+    /// True if results of the current frame were copied and should be mapped after submitting.
+    copied: bool,
+}
+
+impl GpuTimer {
+    const QUERY_COUNT: u32 = 2;
+    const BUFFER_SIZE: u64 = Self::QUERY_COUNT as u64 * wgpu::QUERY_SIZE as u64;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu_timer"),
+            ty: wgpu::QueryType::Timestamp,
+            count: Self::QUERY_COUNT,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_timer_resolve"),
+            size: Self::BUFFER_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_timer_readback"),
+            size: Self::BUFFER_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            mapped: Arc::new(AtomicBool::new(false)),
+            in_flight: false,
+            copied: false,
+        }
+    }
+
+    /// Returns timestamp writes for the beginning and the end of a render pass.
+    pub fn timestamp_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    /// Resolves the timestamps, must be called after the measured pass ends.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(
+            &self.query_set,
+            0..Self::QUERY_COUNT,
+            &self.resolve_buffer,
+            0,
+        );
+
+        // the previous results are still being read, skip this frame
+        if !self.in_flight {
+            encoder.copy_buffer_to_buffer(
+                &self.resolve_buffer,
+                0,
+                &self.readback_buffer,
+                0,
+                Self::BUFFER_SIZE,
+            );
+            self.copied = true;
+        }
+    }
+
+    /// Starts mapping the results, must be called after the encoder is submitted.
+    pub fn map(&mut self) {
+        if !self.copied {
+            return;
+        }
+
+        self.copied = false;
+        self.in_flight = true;
+
+        let mapped = Arc::clone(&self.mapped);
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    mapped.store(true, Ordering::Release);
+                }
+            });
+    }
+
+    /// Returns the duration of the last measured pass once its results are available.
+    pub fn read(&mut self, device: &wgpu::Device) -> Option<Duration> {
+        if !self.in_flight {
+            return None;
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return None;
+        }
+
+        let ticks = {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            timestamps[1].saturating_sub(timestamps[0])
+        };
+
+        self.readback_buffer.unmap();
+        self.in_flight = false;
+
+        Some(Duration::from_nanos(
+            (ticks as f64 * self.period as f64) as u64,
+        ))
+    }
+}
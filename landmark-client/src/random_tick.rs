@@ -0,0 +1,176 @@
+//! Random ticks, which the server runs on a few random blocks of every loaded chunk each tick, like grass
+//! spreading over soil and crops growing. Blocks opt in with `random_tick` in their definition.
+
+use std::collections::HashMap;
+
+use landmark_server::{BlockPos, ChunkStore, WorldTick};
+
+use crate::{block::RandomTick, game_map::BlockId, loader::ResourceDictionary};
+
+/// `RandomTick` of a block with the named blocks resolved to their IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockTick {
+    Spread { onto: BlockId },
+    Grow { into: BlockId },
+}
+
+impl BlockTick {
+    /// Returns None if a block named by the tick isn't defined.
+    pub fn resolve(tick: &RandomTick, block_names: &HashMap<String, BlockId>) -> Option<Self> {
+        match tick {
+            RandomTick::Spread { onto } => Some(Self::Spread {
+                onto: *block_names.get(onto)?,
+            }),
+            RandomTick::Grow { into } => Some(Self::Grow {
+                into: *block_names.get(into)?,
+            }),
+        }
+    }
+
+    /// Returns the block to change when the tick hits `block` at `pos`, and the block replacing it.
+    /// `neighbour` picks the block a spreading block tries to spread onto, relative to it.
+    fn apply(
+        self,
+        world: &dyn ChunkStore,
+        block: BlockId,
+        pos: BlockPos,
+        neighbour: [i32; 3],
+    ) -> Option<(BlockPos, BlockId)> {
+        match self {
+            Self::Spread { onto } => {
+                if is_covered(world, pos) {
+                    return Some((pos, onto));
+                }
+
+                let [x, y, z] = neighbour;
+                let target = pos.offset(x, y, z);
+                let spreads = target != pos
+                    && world.block(target) == Some(Some(onto))
+                    && !is_covered(world, target)
+                    && has_sky(world, target);
+
+                spreads.then_some((target, block))
+            }
+            Self::Grow { into } => has_sky(world, pos).then_some((pos, into)),
+        }
+    }
+}
+
+/// Returns true if there is a block right on top of the block at `pos`.
+fn is_covered(world: &dyn ChunkStore, pos: BlockPos) -> bool {
+    world.block(pos.offset(0, 1, 0)).flatten().is_some()
+}
+
+/// Returns true if nothing is above the block at `pos` up to the sky.
+fn has_sky(world: &dyn ChunkStore, pos: BlockPos) -> bool {
+    world.sees_sky(pos.offset(0, 1, 0))
+}
+
+/// Returns an offset from -1 to 1 on every axis picked by a random number.
+fn random_neighbour(random: u64) -> [i32; 3] {
+    [
+        (random % 3) as i32 - 1,
+        (random / 3 % 3) as i32 - 1,
+        (random / 9 % 3) as i32 - 1,
+    ]
+}
+
+/// Registers the random ticks of the blocks defined by the resources.
+pub fn register(world_tick: &mut WorldTick, resource_dictionary: &ResourceDictionary) {
+    for (name, block) in resource_dictionary.block_ids() {
+        let block = *block;
+        let Some(tick) = resource_dictionary.get_block_tick(block) else {
+            continue;
+        };
+
+        world_tick.register_random(
+            name,
+            Box::new(move |context, pos| {
+                let neighbour = random_neighbour(context.random());
+
+                if let Some((target, changed)) = tick.apply(context.world(), block, pos, neighbour)
+                {
+                    context.set_block(target, Some(changed));
+                }
+            }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use landmark_server::ChunkPos;
+
+    use super::*;
+    use crate::world_store::{
+        tests::{load, open},
+        WorldStore,
+    };
+
+    const SOIL: BlockId = 1;
+    const GRASS: BlockId = 2;
+    const STONE: BlockId = 3;
+
+    /// Opens a world with a row of soil high above the ground of its first chunk.
+    fn flat_world(dir: &std::path::Path) -> WorldStore {
+        let mut world = open(dir);
+        load(&mut world, ChunkPos::new(0, 0, 0));
+
+        for x in 0..4 {
+            world.set_block(BlockPos::new(x, 12, 0), Some(SOIL));
+        }
+
+        world
+    }
+
+    #[test]
+    fn grass_spreads_onto_soil_under_the_sky() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut world = flat_world(dir.path());
+        world.set_block(BlockPos::new(1, 12, 0), Some(GRASS));
+        world.set_block(BlockPos::new(3, 16, 0), Some(STONE));
+
+        let tick = BlockTick::Spread { onto: SOIL };
+        let pos = BlockPos::new(1, 12, 0);
+
+        assert_eq!(
+            tick.apply(&world, GRASS, pos, [1, 0, 0]),
+            Some((BlockPos::new(2, 12, 0), GRASS))
+        );
+        assert_eq!(tick.apply(&world, GRASS, pos, [0, 0, 1]), None);
+
+        // the soil next to it is shaded by the stone far above
+        let next_to_shade = BlockPos::new(2, 12, 0);
+        assert_eq!(tick.apply(&world, GRASS, next_to_shade, [1, 0, 0]), None);
+    }
+
+    #[test]
+    fn covered_grass_turns_into_soil() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut world = flat_world(dir.path());
+        let pos = BlockPos::new(1, 12, 0);
+        world.set_block(pos, Some(GRASS));
+        world.set_block(pos.offset(0, 1, 0), Some(STONE));
+
+        let tick = BlockTick::Spread { onto: SOIL };
+
+        assert_eq!(tick.apply(&world, GRASS, pos, [1, 0, 0]), Some((pos, SOIL)));
+    }
+
+    #[test]
+    fn crops_only_grow_under_the_sky() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut world = flat_world(dir.path());
+        let pos = BlockPos::new(0, 13, 0);
+        world.set_block(pos, Some(GRASS));
+
+        let tick = BlockTick::Grow { into: STONE };
+        assert_eq!(
+            tick.apply(&world, GRASS, pos, [0, 0, 0]),
+            Some((pos, STONE))
+        );
+
+        world.set_block(pos.offset(0, 3, 0), Some(SOIL));
+        assert_eq!(tick.apply(&world, GRASS, pos, [0, 0, 0]), None);
+    }
+}
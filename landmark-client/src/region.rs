@@ -0,0 +1,687 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use landmark_world::{
+    migrate::{check_version, migrate_chunk},
+    FORMAT_VERSION,
+};
+
+use crate::game_map::ChunkCoords;
+
+/// Chunks along X and Z stored in a single region file, a region covers one layer of chunks.
+const REGION_SIZE: i32 = 32;
+const SLOT_COUNT: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const SECTOR_SIZE: u64 = 4096;
+const MAGIC: &[u8; 4] = b"LMRG";
+/// Magic, version and a location of every slot.
+const HEADER_SIZE: u64 = 8 + SLOT_COUNT as u64 * 8;
+const HEADER_SECTORS: u32 = HEADER_SIZE.div_ceil(SECTOR_SIZE) as u32;
+const COMPRESSION_LEVEL: i32 = 3;
+/// Copies of a region file kept from previous sessions, `.1` is the newest.
+const BACKUP_COUNT: u32 = 3;
+
+/// Identifies a region file, chunks are grouped by their position divided by the region size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct RegionCoords {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl RegionCoords {
+    /// Returns the region containing a chunk and the index of its slot.
+    fn of(coords: ChunkCoords) -> (Self, usize) {
+        let region = Self {
+            x: coords.x.div_euclid(REGION_SIZE),
+            y: coords.y,
+            z: coords.z.div_euclid(REGION_SIZE),
+        };
+        let slot =
+            coords.x.rem_euclid(REGION_SIZE) + coords.z.rem_euclid(REGION_SIZE) * REGION_SIZE;
+
+        (region, slot as usize)
+    }
+
+    fn file_name(&self) -> String {
+        format!("r.{}.{}.{}.lmr", self.x, self.y, self.z)
+    }
+}
+
+/// Position of a chunk within a region file, an empty slot has zero length.
+#[derive(Debug, Clone, Copy, Default)]
+struct Location {
+    first_sector: u32,
+    /// Length of the compressed chunk in bytes.
+    length: u32,
+}
+
+impl Location {
+    fn sectors(&self) -> std::ops::Range<u32> {
+        let count = (self.length as u64).div_ceil(SECTOR_SIZE) as u32;
+        self.first_sector..self.first_sector + count
+    }
+
+    fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+/// An open region file with its location table.
+///
+/// The file starts with the magic `LMRG`, the format version as u32 and a table with the first sector and the length
+/// in bytes of every slot as two u32, all little endian. Chunks follow, each compressed with zstd on its own and
+/// starting at a sector boundary, so a changed chunk is written without touching the rest of the file.
+#[derive(Debug)]
+struct RegionFile {
+    file: fs::File,
+    table: Vec<Location>,
+    /// Sectors occupied by the header or a chunk.
+    used: Vec<bool>,
+}
+
+impl RegionFile {
+    fn open(path: &Path, create: bool) -> io::Result<Option<Self>> {
+        let file = match fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(create)
+            .truncate(false)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !create => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut region = Self {
+            file,
+            table: vec![Location::default(); SLOT_COUNT],
+            used: vec![true; HEADER_SECTORS as usize],
+        };
+
+        if region.file.metadata()?.len() == 0 {
+            region.write_header()?;
+            return Ok(Some(region));
+        }
+
+        let mut header = vec![0; HEADER_SIZE as usize];
+        region
+            .file
+            .read_exact(&mut header)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => {
+                    io::Error::new(io::ErrorKind::InvalidData, "truncated region file")
+                }
+                _ => e,
+            })?;
+
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a region file",
+            ));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        check_version(version).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let sector_count = region.file.metadata()?.len().div_ceil(SECTOR_SIZE) as u32;
+
+        for (slot, entry) in header[8..].chunks_exact(8).enumerate() {
+            let location = Location {
+                first_sector: u32::from_le_bytes(entry[..4].try_into().unwrap()),
+                length: u32::from_le_bytes(entry[4..].try_into().unwrap()),
+            };
+
+            let sectors = location.sectors();
+            let overlaps = sectors
+                .clone()
+                .any(|sector| region.used.get(sector as usize) == Some(&true));
+
+            // a damaged entry only loses its own chunk, which is generated again
+            if location.is_empty() || sectors.end > sector_count || overlaps {
+                if !location.is_empty() {
+                    log::warn!("Ignoring damaged chunk slot {slot} in {}", path.display());
+                }
+                continue;
+            }
+
+            region.mark(sectors, true);
+            region.table[slot] = location;
+        }
+
+        if version < FORMAT_VERSION {
+            return region.upgrade(path, version).map(Some);
+        }
+
+        Ok(Some(region))
+    }
+
+    /// Replaces a region file saved in an older format version with one containing all of its chunks migrated.
+    /// The old file is only replaced once the new one is complete.
+    fn upgrade(mut self, path: &Path, version: u32) -> io::Result<Self> {
+        log::info!(
+            "Upgrading {} from format version {version} to {FORMAT_VERSION}",
+            path.display()
+        );
+
+        let mut chunks = Vec::new();
+
+        for slot in 0..SLOT_COUNT {
+            if let Some(bytes) = self.read(slot)? {
+                let migrated = migrate_chunk(version, bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                chunks.push((slot, migrated));
+            }
+        }
+
+        let temporary = with_suffix(path, ".tmp");
+
+        if temporary.exists() {
+            fs::remove_file(&temporary)?;
+        }
+
+        let mut upgraded = Self::open(&temporary, true)?.expect("Created region file exists");
+        upgraded.write(&chunks)?;
+        drop(upgraded);
+
+        // older versions of the game can still read the backup
+        rotate_backups(path)?;
+        fs::rename(&temporary, path)?;
+
+        Self::open(path, false)?.ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+        for location in self.table.iter() {
+            header.extend_from_slice(&location.first_sector.to_le_bytes());
+            header.extend_from_slice(&location.length.to_le_bytes());
+        }
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.sync_data()
+    }
+
+    fn mark(&mut self, sectors: std::ops::Range<u32>, used: bool) {
+        if self.used.len() < sectors.end as usize {
+            self.used.resize(sectors.end as usize, false);
+        }
+
+        for sector in sectors {
+            self.used[sector as usize] = used;
+        }
+    }
+
+    /// Finds the first run of free sectors long enough, extending the file if there is none.
+    fn allocate(&mut self, count: u32) -> u32 {
+        let mut run_start = 0;
+        let mut run_length = 0;
+
+        for (sector, used) in self.used.iter().enumerate() {
+            if *used {
+                run_length = 0;
+                continue;
+            }
+
+            if run_length == 0 {
+                run_start = sector as u32;
+            }
+
+            run_length += 1;
+
+            if run_length == count {
+                break;
+            }
+        }
+
+        let first_sector = if run_length == count {
+            run_start
+        } else if run_length > 0 && run_start as usize + run_length as usize == self.used.len() {
+            // free sectors at the end of the file are extended
+            run_start
+        } else {
+            self.used.len() as u32
+        };
+
+        self.mark(first_sector..first_sector + count, true);
+
+        first_sector
+    }
+
+    fn read(&mut self, slot: usize) -> io::Result<Option<Vec<u8>>> {
+        let location = self.table[slot];
+
+        if location.is_empty() {
+            return Ok(None);
+        }
+
+        let mut compressed = vec![0; location.length as usize];
+        self.file
+            .seek(SeekFrom::Start(location.first_sector as u64 * SECTOR_SIZE))?;
+        self.file.read_exact(&mut compressed)?;
+
+        zstd::decode_all(compressed.as_slice()).map(Some)
+    }
+
+    /// Writes chunks into free sectors and only then points the table to them,
+    /// so a crash in the middle leaves the previous versions readable.
+    /// A slot written more than once keeps the last of its chunks.
+    fn write(&mut self, chunks: &[(usize, Vec<u8>)]) -> io::Result<()> {
+        let last_writes: HashMap<usize, usize> = chunks
+            .iter()
+            .enumerate()
+            .map(|(idx, (slot, _))| (*slot, idx))
+            .collect();
+        let mut replaced = Vec::with_capacity(chunks.len());
+
+        for (idx, (slot, payload)) in chunks.iter().enumerate() {
+            if last_writes[slot] != idx {
+                continue;
+            }
+
+            let compressed = zstd::encode_all(payload.as_slice(), COMPRESSION_LEVEL)?;
+            let count = (compressed.len() as u64).div_ceil(SECTOR_SIZE) as u32;
+            let first_sector = self.allocate(count);
+
+            self.file
+                .seek(SeekFrom::Start(first_sector as u64 * SECTOR_SIZE))?;
+            self.file.write_all(&compressed)?;
+
+            replaced.push((
+                *slot,
+                Location {
+                    first_sector,
+                    length: compressed.len() as u32,
+                },
+            ));
+        }
+
+        self.file.sync_data()?;
+
+        let mut freed = Vec::with_capacity(replaced.len());
+        for (slot, location) in replaced {
+            freed.push(self.table[slot].sectors());
+            self.table[slot] = location;
+        }
+
+        self.write_header()?;
+
+        for sectors in freed {
+            self.mark(sectors, false);
+        }
+
+        Ok(())
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+
+    PathBuf::from(name)
+}
+
+fn backup_path(path: &Path, idx: u32) -> PathBuf {
+    with_suffix(path, &format!(".{idx}"))
+}
+
+/// Copies the region file to the newest backup, the oldest backup is overwritten.
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    for idx in (1..BACKUP_COUNT).rev() {
+        let backup = backup_path(path, idx);
+
+        if backup.exists() {
+            fs::rename(&backup, backup_path(path, idx + 1))?;
+        }
+    }
+
+    fs::copy(path, backup_path(path, 1)).map(|_| ())
+}
+
+/// Opens the region file, replacing it with its newest backup which can be opened if it's damaged.
+/// The damaged file is kept next to it, so it can still be inspected.
+fn open_or_restore(path: &Path, create: bool) -> io::Result<Option<RegionFile>> {
+    let error = match RegionFile::open(path, create) {
+        Ok(region) => return Ok(region),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => e,
+        Err(e) => return Err(e),
+    };
+
+    log::error!("Region file {} is damaged: {error}", path.display());
+
+    let backups: Vec<PathBuf> = (1..=BACKUP_COUNT)
+        .map(|idx| backup_path(path, idx))
+        .filter(|backup| backup.exists())
+        .collect();
+
+    if backups.is_empty() {
+        return Err(error);
+    }
+
+    fs::rename(path, with_suffix(path, ".damaged"))?;
+
+    for backup in backups {
+        fs::copy(&backup, path)?;
+
+        match RegionFile::open(path, false) {
+            Ok(region) => {
+                log::warn!("Restored {} from {}", path.display(), backup.display());
+                return Ok(region);
+            }
+            Err(e) => log::error!("Backup {} is damaged too: {e}", backup.display()),
+        }
+    }
+
+    fs::remove_file(path)?;
+
+    Err(error)
+}
+
+/// Region files of a world, opened on first access.
+///
+/// Before a region file is written for the first time in a session, it's copied to a backup, the last
+/// three are kept. A region file which can't be opened is restored from its newest working backup.
+#[derive(Debug)]
+pub struct RegionStore {
+    dir: PathBuf,
+    regions: HashMap<RegionCoords, RegionFile>,
+    /// Regions already backed up in this session.
+    backed_up: HashSet<RegionCoords>,
+}
+
+impl RegionStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            regions: HashMap::new(),
+            backed_up: HashSet::new(),
+        }
+    }
+
+    /// Returns the region file, or None if it doesn't exist and `create` is false.
+    fn region(
+        &mut self,
+        coords: RegionCoords,
+        create: bool,
+    ) -> io::Result<Option<&mut RegionFile>> {
+        if !self.regions.contains_key(&coords) {
+            if create {
+                fs::create_dir_all(&self.dir)?;
+            }
+
+            let path = self.dir.join(coords.file_name());
+            let Some(region) = open_or_restore(&path, create)? else {
+                return Ok(None);
+            };
+
+            self.regions.insert(coords, region);
+        }
+
+        Ok(self.regions.get_mut(&coords))
+    }
+
+    /// Returns the uncompressed data of a chunk, or None if it was never written.
+    pub fn read(&mut self, coords: ChunkCoords) -> io::Result<Option<Vec<u8>>> {
+        let (region, slot) = RegionCoords::of(coords);
+
+        match self.region(region, false)? {
+            Some(region) => region.read(slot),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes chunks, each region file is synced once for all of its chunks.
+    pub fn write(
+        &mut self,
+        chunks: impl IntoIterator<Item = (ChunkCoords, Vec<u8>)>,
+    ) -> io::Result<()> {
+        let mut by_region: HashMap<RegionCoords, Vec<(usize, Vec<u8>)>> = HashMap::new();
+
+        for (coords, payload) in chunks {
+            let (region, slot) = RegionCoords::of(coords);
+            by_region.entry(region).or_default().push((slot, payload));
+        }
+
+        for (coords, chunks) in by_region {
+            if !self.backed_up.contains(&coords) {
+                let path = self.dir.join(coords.file_name());
+
+                if path.exists() {
+                    rotate_backups(&path)?;
+                }
+
+                self.backed_up.insert(coords);
+            }
+
+            if let Some(region) = self.region(coords, true)? {
+                region.write(&chunks)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes zstd can't compress, so a payload fills a known number of sectors.
+    fn noise(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn create(dir: &Path) -> (PathBuf, RegionFile) {
+        let path = dir.join("r.0.0.0.lmr");
+        let region = RegionFile::open(&path, true).unwrap().unwrap();
+
+        (path, region)
+    }
+
+    #[test]
+    fn allocate_first_fit() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, mut region) = create(dir.path());
+        let start = HEADER_SECTORS;
+
+        assert_eq!(region.allocate(2), start);
+        assert_eq!(region.allocate(1), start + 2);
+
+        region.mark(start..start + 2, false);
+
+        // the first free sector fits, the following one is too short for two sectors
+        assert_eq!(region.allocate(1), start);
+        assert_eq!(region.allocate(2), start + 3);
+    }
+
+    #[test]
+    fn allocate_extends_free_sectors_at_the_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, mut region) = create(dir.path());
+        let start = HEADER_SECTORS;
+
+        region.allocate(3);
+        region.mark(start + 2..start + 3, false);
+
+        assert_eq!(region.allocate(2), start + 2);
+        assert_eq!(region.used.len(), start as usize + 4);
+    }
+
+    #[test]
+    fn chunks_are_read_back_after_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut region) = create(dir.path());
+        let small = noise(100, 1);
+        let large = noise(SECTOR_SIZE as usize * 2 + 100, 2);
+
+        region
+            .write(&[(0, small.clone()), (SLOT_COUNT - 1, large.clone())])
+            .unwrap();
+        drop(region);
+
+        let mut region = RegionFile::open(&path, false).unwrap().unwrap();
+
+        assert_eq!(region.read(0).unwrap(), Some(small));
+        assert_eq!(region.read(SLOT_COUNT - 1).unwrap(), Some(large));
+        assert_eq!(region.read(1).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_file_isnt_created_when_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.0.lmr");
+
+        assert!(RegionFile::open(&path, false).unwrap().is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn rewritten_chunks_reuse_sectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut region) = create(dir.path());
+
+        region.write(&[(1, noise(100, 0))]).unwrap();
+
+        for seed in 1..10 {
+            region.write(&[(0, noise(100, seed))]).unwrap();
+        }
+
+        // the old version of a chunk stays until the new one is written, so two sectors alternate
+        let sectors = fs::metadata(&path).unwrap().len().div_ceil(SECTOR_SIZE);
+        assert!(sectors <= HEADER_SECTORS as u64 + 3, "{sectors} sectors");
+
+        assert_eq!(region.read(0).unwrap(), Some(noise(100, 9)));
+        assert_eq!(region.read(1).unwrap(), Some(noise(100, 0)));
+    }
+
+    #[test]
+    fn slots_written_twice_in_a_batch_keep_the_last_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut region) = create(dir.path());
+        let last = noise(100, 2);
+
+        region
+            .write(&[(0, noise(SECTOR_SIZE as usize * 3, 1)), (0, last.clone())])
+            .unwrap();
+
+        // the file only grows by the sector of the last chunk
+        let sectors = fs::metadata(&path).unwrap().len().div_ceil(SECTOR_SIZE);
+        assert_eq!(sectors, HEADER_SECTORS as u64 + 1);
+        assert_eq!(region.read(0).unwrap(), Some(last.clone()));
+
+        drop(region);
+        let mut region = RegionFile::open(&path, false).unwrap().unwrap();
+        assert_eq!(region.read(0).unwrap(), Some(last));
+    }
+
+    #[test]
+    fn damaged_slots_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut region) = create(dir.path());
+        let chunk = noise(100, 1);
+
+        region.write(&[(0, chunk.clone())]).unwrap();
+        let location = region.table[0];
+        drop(region);
+
+        let mut file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        let mut patch = |slot: u64, first_sector: u32, length: u32| {
+            file.seek(SeekFrom::Start(8 + slot * 8)).unwrap();
+            file.write_all(&first_sector.to_le_bytes()).unwrap();
+            file.write_all(&length.to_le_bytes()).unwrap();
+        };
+        // overlaps the first chunk
+        patch(1, location.first_sector, location.length);
+        // points past the end of the file
+        patch(2, location.first_sector + 100, 100);
+        drop(file);
+
+        let mut region = RegionFile::open(&path, false).unwrap().unwrap();
+
+        assert_eq!(region.read(0).unwrap(), Some(chunk.clone()));
+        assert_eq!(region.read(1).unwrap(), None);
+        assert_eq!(region.read(2).unwrap(), None);
+
+        // sectors of the intact chunk are still taken
+        region.write(&[(1, noise(100, 2))]).unwrap();
+        assert_eq!(region.read(0).unwrap(), Some(chunk));
+        assert_eq!(region.read(1).unwrap(), Some(noise(100, 2)));
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.0.lmr");
+        fs::write(&path, vec![0; HEADER_SIZE as usize]).unwrap();
+
+        let error = RegionFile::open(&path, false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn regions_are_backed_up_once_per_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let coords = ChunkCoords::new(0, 0, 0);
+        let path = dir.path().join(RegionCoords::of(coords).0.file_name());
+
+        for session in 0..5 {
+            let mut store = RegionStore::new(dir.path().to_path_buf());
+            store.write([(coords, noise(100, session * 2))]).unwrap();
+            store
+                .write([(coords, noise(100, session * 2 + 1))])
+                .unwrap();
+        }
+
+        // every backup holds the last chunk written in an earlier session
+        for idx in 1..=BACKUP_COUNT {
+            let backup = backup_path(&path, idx);
+            let mut region = RegionFile::open(&backup, false).unwrap().unwrap();
+            let session = 4 - idx as u64;
+
+            assert_eq!(region.read(0).unwrap(), Some(noise(100, session * 2 + 1)));
+        }
+
+        assert!(!backup_path(&path, BACKUP_COUNT + 1).exists());
+    }
+
+    #[test]
+    fn damaged_regions_are_restored_from_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let coords = ChunkCoords::new(0, 0, 0);
+        let path = dir.path().join(RegionCoords::of(coords).0.file_name());
+
+        for seed in 0..2 {
+            let mut store = RegionStore::new(dir.path().to_path_buf());
+            store.write([(coords, noise(100, seed))]).unwrap();
+        }
+
+        fs::write(&path, b"damaged").unwrap();
+
+        let mut store = RegionStore::new(dir.path().to_path_buf());
+        assert_eq!(store.read(coords).unwrap(), Some(noise(100, 0)));
+        assert_eq!(
+            fs::read(with_suffix(&path, ".damaged")).unwrap(),
+            b"damaged"
+        );
+    }
+}
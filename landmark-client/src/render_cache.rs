@@ -0,0 +1,101 @@
+//! Pipelines and bind groups looked up by name, only created again when what they're made of changes.
+//!
+//! Every object is stored with a hash of the key it was requested with, e.g. the shader source
+//! and render state of a pipeline or the ids of the resources a bind group binds. Requesting a
+//! name with the same key returns the cached object.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+#[derive(Debug)]
+struct Cached<T> {
+    key: u64,
+    value: T,
+}
+
+#[derive(Debug, Default)]
+pub struct RenderCache {
+    pipelines: HashMap<&'static str, Cached<wgpu::RenderPipeline>>,
+    bind_groups: HashMap<&'static str, Cached<wgpu::BindGroup>>,
+}
+
+impl RenderCache {
+    /// Returns the pipeline of the name, creating it if it's missing or was requested with another key.
+    pub fn pipeline(
+        &mut self,
+        name: &'static str,
+        key: &impl Hash,
+        create: impl FnOnce() -> wgpu::RenderPipeline,
+    ) -> &wgpu::RenderPipeline {
+        get_or_create(&mut self.pipelines, name, key, create)
+    }
+
+    /// Returns the bind group of the name, creating it if it's missing or was requested with another key.
+    pub fn bind_group(
+        &mut self,
+        name: &'static str,
+        key: &impl Hash,
+        create: impl FnOnce() -> wgpu::BindGroup,
+    ) -> &wgpu::BindGroup {
+        get_or_create(&mut self.bind_groups, name, key, create)
+    }
+
+    pub fn get_pipeline(&self, name: &str) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(name).map(|cached| &cached.value)
+    }
+
+    pub fn get_bind_group(&self, name: &str) -> Option<&wgpu::BindGroup> {
+        self.bind_groups.get(name).map(|cached| &cached.value)
+    }
+}
+
+fn hash_key(key: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn get_or_create<'a, T>(
+    cache: &'a mut HashMap<&'static str, Cached<T>>,
+    name: &'static str,
+    key: &impl Hash,
+    create: impl FnOnce() -> T,
+) -> &'a T {
+    let key = hash_key(key);
+
+    if cache.get(name).is_none_or(|cached| cached.key != key) {
+        log::debug!("Creating {name}");
+        cache.insert(
+            name,
+            Cached {
+                key,
+                value: create(),
+            },
+        );
+    }
+
+    &cache[name].value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_are_recreated_only_for_new_keys() {
+        let mut cache = HashMap::new();
+        let mut created = 0;
+
+        for key in ["a", "a", "b", "b", "a"] {
+            get_or_create(&mut cache, "object", &key, || {
+                created += 1;
+                created
+            });
+        }
+
+        assert_eq!(created, 3);
+        assert_eq!(cache["object"].value, 3);
+    }
+}
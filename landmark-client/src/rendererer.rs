@@ -1,66 +1,262 @@
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
 use game_loop::winit::{dpi::PhysicalSize, window::Window};
 use shipyard::*;
+use wgpu::util::DeviceExt;
 
 use crate::{
-    camera::Camera,
-    model::{Model, Vertex},
+    adapter::{select_adapter, SelectedAdapter},
+    atlas::TextureAtlas,
+    camera::{Camera, Frustum},
+    capabilities::GraphicsCapabilities,
+    chunk_arena::ChunkArena,
+    debug::{chunk_border_model_constructor, mob_model_constructor, DebugRenderState, DrawStats},
+    debug_view::DebugView,
+    error::LandmarkError,
+    font,
+    game_map::{Chunk, ChunkTag},
+    hdr::{Bloom, FrameTargets, HDR_FORMAT},
+    lights::LightBuffers,
+    loader::{
+        builtin_shader_includes, load_shader_source, ResourceDictionary, ResourcePacks,
+        BUILTIN_BLOOM_SHADER, BUILTIN_PARTICLE_SHADER, BUILTIN_SHADER, BUILTIN_UI_SHADER,
+        BUILTIN_WATER_SHADER,
+    },
+    minimap,
+    mipmap::MipmapGenerator,
+    model::{Model, ModelStorage, Vertex},
+    particles::RawParticle,
+    profiler::{GpuTimer, Profiler},
+    render_cache::RenderCache,
+    settings::{GraphicsSettings, PresentModeSetting, Settings, ShadowQuality, TextureFiltering},
+    shader::{ShaderFeatures, ShaderIncludes},
+    shadows::ShadowMap,
+    spawning::Mob,
     texture,
-    transform::RawTransform,
+    transform::{RawTransform, Transform},
+    ui::{UiModel, UiVertex},
+    water::{FluidModel, WaterSurface},
+    world_map,
 };
 
+/// Linear color of the sky.
+pub const SKY_COLOR: glam::Vec3 = glam::Vec3::new(0.0, 0.0, 1.0);
+
+/// Fog parameters as laid out in the uniform buffer.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct FogUniform {
+    color: glam::Vec3,
+    /// Distance at which the fog hides geometry completely.
+    end: f32,
+}
+
 #[derive(Debug, Unique)]
 pub struct Renderer {
     pub size: PhysicalSize<u32>,
-    pub surface: wgpu::Surface,
+    pub target: RenderTarget,
+    /// Kept to create surfaces for more windows.
+    pub instance: wgpu::Instance,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
-    pub pipeline: wgpu::RenderPipeline,
-    pub depth_texture: texture::Texture,
+    /// Present mode requested in the settings, may differ from the configured one if unsupported.
+    pub present_mode: PresentModeSetting,
+    pub capabilities: GraphicsCapabilities,
+    pub pipeline_layout: wgpu::PipelineLayout,
+    pub ui_pipeline_layout: wgpu::PipelineLayout,
+    /// Only binds the camera, the shadow pass draws into the texture the other pipelines sample.
+    pub shadow_pipeline_layout: wgpu::PipelineLayout,
+    /// Camera, water and fog groups of the fluid pipeline.
+    pub water_pipeline_layout: wgpu::PipelineLayout,
+    /// Source texture of the bloom passes.
+    pub bloom_pipeline_layout: wgpu::PipelineLayout,
+    /// Frame, bloom and bloom uniform groups of the composite pass.
+    pub composite_pipeline_layout: wgpu::PipelineLayout,
+    /// Pipelines and texture bind groups by name, see `create_pipelines`.
+    pub render_cache: RenderCache,
+    /// Features the world shader is preprocessed with.
+    pub shader_features: ShaderFeatures,
+    pub chunk_border_model: Model,
+    pub mob_model: Model,
+    /// Meshes of mobs with a model, by their kind. Other mobs are drawn as boxes.
+    pub entity_models: HashMap<String, Model>,
+    /// Targets of the window the world is drawn into before it's composited.
+    pub targets: FrameTargets,
+    /// Layout sampling a texture with filtering, shared by the atlas and the frame targets.
+    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub camera_bind_group_layout: wgpu::BindGroupLayout,
     pub camera_bind_group: wgpu::BindGroup,
+    /// Color the screen is cleared with, distant geometry fades into it.
+    pub sky_color: glam::Vec3,
+    /// Fog parameters, updated every frame by `update_fog_sys`.
+    pub fog_buffer: wgpu::Buffer,
+    /// Point lights near the camera, updated every frame by `update_point_lights_sys`.
+    pub lights: LightBuffers,
+    /// Binds the fog and the point lights.
+    pub fog_bind_group: wgpu::BindGroup,
+    /// Depth of the world seen from the sun, drawn by `encode_shadow_pass`.
+    pub shadow_map: ShadowMap,
+    pub water: WaterSurface,
+    pub bloom: Bloom,
+    /// Second window showing the world from another camera, only open in dev mode.
+    pub debug_view: Option<DebugView>,
+    /// Block textures, their smaller mip levels are filled by `mipmap_generator` after every upload.
+    pub atlas_texture: texture::Texture,
+    mipmap_generator: MipmapGenerator,
+    /// How the atlas is sampled, from the settings.
+    pub texture_filtering: TextureFiltering,
+    /// Top-down view of the world around the player, drawn by `update_minimap_sys`.
+    pub minimap_texture: texture::Texture,
+    /// Tiles of the map screen, placed by `place_world_map_tiles_sys`.
+    pub world_map_texture: texture::Texture,
+    /// HUD drawn over the world, rebuilt every frame.
+    pub hud_model: Option<UiModel>,
+    /// Cracks drawn over the block the player is breaking, rebuilt every frame.
+    pub crack_model: Option<Model>,
+    /// Edges of the view frozen by `/freezefrustum`, rebuilt every frame.
+    pub frustum_model: Option<Model>,
+    /// Instances of the living particles, rebuilt every frame.
+    pub particle_instances: Option<(wgpu::Buffer, u32)>,
+    /// Shared buffers of the chunk meshes, only present if the device supports
+    /// `Features::MULTI_DRAW_INDIRECT` and `Features::INDIRECT_FIRST_INSTANCE`.
+    pub chunk_arena: Option<ChunkArena>,
+    /// Only present if the device supports `Features::TIMESTAMP_QUERY`.
+    pub gpu_timer: Option<GpuTimer>,
+    /// Counts of models drawn into the last frame shown in the window.
+    pub draw_stats: DrawStats,
+    /// Draws the depth of chunks before their colors, from the settings.
+    pub depth_prepass: bool,
 }
 
 impl Renderer {
-    pub async fn init(window: &Window) -> (Self, Camera) {
+    const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Creates a renderer drawing into the window.
+    pub async fn init(
+        window: &Window,
+        settings: &mut Settings,
+        resource_packs: &ResourcePacks,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Result<(Self, Camera), LandmarkError> {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::default();
+        let SelectedAdapter {
+            instance,
+            surface,
+            adapter,
+        } = select_adapter(&settings.graphics, Some(window)).await?;
+        let surface = surface.ok_or(LandmarkError::NoSurface)?;
 
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                // Request an adapter which can render to our surface
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .expect("Failed to find an appropriate adapter");
+        // Only request optional features which are available and adjust settings depending on them
+        let capabilities = GraphicsCapabilities::detect(&adapter, Some(&surface));
+        capabilities.log();
+        capabilities.restrict_settings(settings);
+
+        let swapchain_capabilities = surface.get_capabilities(&adapter);
+        let present_mode = settings.graphics.present_mode;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: select_surface_format(&swapchain_capabilities.formats),
+            width: size.width,
+            height: size.height,
+            present_mode: select_present_mode(present_mode, &capabilities),
+            alpha_mode: swapchain_capabilities.alpha_modes[0],
+            view_formats: vec![],
+        };
+
+        let (mut renderer, camera) = Self::create(
+            instance,
+            adapter,
+            Some(surface),
+            config,
+            present_mode,
+            capabilities,
+            ShaderFeatures::from_settings(&settings.graphics),
+            settings.graphics.shadows,
+            resource_packs,
+            resource_dictionary,
+        )
+        .await?;
+        renderer.depth_prepass = settings.graphics.depth_prepass;
+        renderer.set_texture_filtering(settings.graphics.texture_filtering);
+
+        Ok((renderer, camera))
+    }
+
+    /// Creates a renderer drawing into an offscreen texture, so it can be used without a display.
+    /// Rendered frames are read back with `read_pixels`.
+    pub async fn init_headless(
+        size: PhysicalSize<u32>,
+        resource_packs: &ResourcePacks,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Result<(Self, Camera), LandmarkError> {
+        let SelectedAdapter {
+            instance, adapter, ..
+        } = select_adapter(&GraphicsSettings::default(), None).await?;
+
+        let capabilities = GraphicsCapabilities::detect(&adapter, None);
+        capabilities.log();
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: Self::OFFSCREEN_FORMAT,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        Self::create(
+            instance,
+            adapter,
+            None,
+            config,
+            PresentModeSetting::Fifo,
+            capabilities,
+            ShaderFeatures::from_settings(&GraphicsSettings::default()),
+            GraphicsSettings::default().shadows,
+            resource_packs,
+            resource_dictionary,
+        )
+        .await
+    }
+
+    /// Creates the device and all resources shared by both kinds of render targets.
+    #[allow(clippy::too_many_arguments)]
+    async fn create(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        surface: Option<wgpu::Surface>,
+        config: wgpu::SurfaceConfiguration,
+        present_mode: PresentModeSetting,
+        capabilities: GraphicsCapabilities,
+        shader_features: ShaderFeatures,
+        shadow_quality: ShadowQuality,
+        resource_packs: &ResourcePacks,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Result<(Self, Camera), LandmarkError> {
+        let size = PhysicalSize::new(config.width, config.height);
 
         // Create the logical device and command queue
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
+                    features: capabilities.features(),
                     // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
                     limits: wgpu::Limits::default().using_resolution(adapter.limits()),
                 },
                 None,
             )
-            .await
-            .expect("Failed to create device");
-
-        // Load the shaders from disk
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(
-                std::fs::read_to_string("res/shaders/shader.wgsl")
-                    .expect("Could not load the standard shader")
-                    .into(),
-            ),
-        });
+            .await?;
 
         let camera_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -77,24 +273,107 @@ impl Renderer {
                 label: None,
             });
 
+        let atlas_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+
+        let lights = LightBuffers::new(&device, shader_features.clustered_lights);
+
+        let mut fog_layout_entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        fog_layout_entries.extend(lights.layout_entries());
+
+        let fog_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &fog_layout_entries,
+                label: Some("fog_bind_group_layout"),
+            });
+
+        let shadow_bind_group_layout = ShadowMap::bind_group_layout(&device);
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&camera_bind_group_layout],
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                &atlas_bind_group_layout,
+                &fog_bind_group_layout,
+                &shadow_bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
 
-        let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
+        let shadow_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("shadow_pipeline_layout"),
+                bind_group_layouts: &[&camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: swapchain_format,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: swapchain_capabilities.alpha_modes[0],
-            view_formats: vec![],
-        };
+        let water = WaterSurface::new(&device);
+
+        let water_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("water_pipeline_layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &water.bind_group_layout,
+                    &fog_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let bloom = Bloom::new(&device);
+
+        let bloom_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("bloom_pipeline_layout"),
+                bind_group_layouts: &[&atlas_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let composite_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("composite_pipeline_layout"),
+                bind_group_layouts: &[
+                    &atlas_bind_group_layout,
+                    &atlas_bind_group_layout,
+                    &bloom.bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let ui_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ui_pipeline_layout"),
+            bind_group_layouts: &[&atlas_bind_group_layout],
+            push_constant_ranges: &[],
+        });
 
         let camera = Camera::new(&device, &config);
 
@@ -107,90 +386,1470 @@ impl Renderer {
             label: None,
         });
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+        let fog_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fog_buffer"),
+            contents: bytemuck::cast_slice(&[FogUniform {
+                color: SKY_COLOR,
+                end: f32::MAX,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc(), RawTransform::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(swapchain_format.into())],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
+        let mut fog_entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: fog_buffer.as_entire_binding(),
+        }];
+        fog_entries.extend(lights.bind_group_entries());
+
+        let fog_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &fog_bind_group_layout,
+            entries: &fog_entries,
+            label: Some("fog_bind_group"),
         });
 
-        surface.configure(&device, &config);
+        let shadow_map = ShadowMap::new(
+            &device,
+            &camera_bind_group_layout,
+            &shadow_bind_group_layout,
+            shadow_quality,
+        );
+
+        let targets = FrameTargets::new(
+            &device,
+            &atlas_bind_group_layout,
+            config.width,
+            config.height,
+        );
 
-        (
+        let atlas_texture = texture::Texture::create_mipmapped_texture(
+            &device,
+            TextureAtlas::SIZE,
+            TextureAtlas::MIP_LEVELS,
+            TextureFiltering::default(),
+            "atlas_texture",
+        );
+        atlas_texture.write_region(&queue, (0, 0), resource_dictionary.atlas.image());
+
+        let mipmap_generator = MipmapGenerator::new(&device);
+        mipmap_generator.generate(&device, &queue, &atlas_texture);
+
+        // the font uses the same layout as the atlas, so the UI pipeline can draw with both
+        let font_texture =
+            texture::Texture::create_atlas_texture(&device, font::TEXTURE_SIZE, "font_texture");
+        font_texture.write_region(&queue, (0, 0), &font::image());
+
+        let minimap_texture =
+            texture::Texture::create_atlas_texture(&device, minimap::SIZE, "minimap_texture");
+
+        let world_map_texture = texture::Texture::create_atlas_texture(
+            &device,
+            world_map::TEXTURE_SIZE,
+            "world_map_texture",
+        );
+        world_map_texture.write_region(&queue, (0, 0), &world_map::background_tile());
+
+        let mut render_cache = RenderCache::default();
+
+        for (name, texture) in [
+            ("atlas_bind_group", &atlas_texture),
+            ("font_bind_group", &font_texture),
+            ("minimap_bind_group", &minimap_texture),
+            ("world_map_bind_group", &world_map_texture),
+        ] {
+            cache_texture_bind_group(
+                &mut render_cache,
+                &device,
+                &atlas_bind_group_layout,
+                name,
+                texture,
+            );
+        }
+
+        create_pipelines(
+            &mut render_cache,
+            &device,
+            &pipeline_layout,
+            &ui_pipeline_layout,
+            &shadow_pipeline_layout,
+            &water_pipeline_layout,
+            &bloom_pipeline_layout,
+            &composite_pipeline_layout,
+            &ShaderSources::load(resource_packs),
+            shader_features,
+            config.format,
+        );
+
+        let chunk_border_model = Model::new(&device, &chunk_border_model_constructor());
+        let mob_model = Model::new(&device, &mob_model_constructor());
+
+        let chunk_arena = (capabilities.multi_draw_indirect
+            && capabilities.indirect_first_instance)
+            .then(|| ChunkArena::new(&device));
+
+        let gpu_timer = capabilities
+            .timestamp_query
+            .then(|| GpuTimer::new(&device, &queue));
+
+        let mut target = match surface {
+            Some(surface) => RenderTarget::Window(surface),
+            None => RenderTarget::Offscreen(create_offscreen_texture(&device, &config)),
+        };
+        target.configure(&device, &config);
+
+        Ok((
             Self {
                 size,
-                surface,
+                target,
+                instance,
                 adapter,
                 device,
                 queue,
                 config,
-                pipeline,
-                depth_texture,
+                present_mode,
+                capabilities,
+                pipeline_layout,
+                ui_pipeline_layout,
+                shadow_pipeline_layout,
+                water_pipeline_layout,
+                bloom_pipeline_layout,
+                composite_pipeline_layout,
+                render_cache,
+                shader_features,
+                chunk_border_model,
+                mob_model,
+                entity_models: HashMap::new(),
+                targets,
+                texture_bind_group_layout: atlas_bind_group_layout,
+                camera_bind_group_layout,
                 camera_bind_group,
+                sky_color: SKY_COLOR,
+                fog_buffer,
+                lights,
+                fog_bind_group,
+                shadow_map,
+                water,
+                bloom,
+                debug_view: None,
+                atlas_texture,
+                mipmap_generator,
+                texture_filtering: TextureFiltering::default(),
+                minimap_texture,
+                world_map_texture,
+                hud_model: None,
+                crack_model: None,
+                frustum_model: None,
+                particle_instances: None,
+                chunk_arena,
+                gpu_timer,
+                draw_stats: DrawStats::default(),
+                depth_prepass: false,
             },
             camera,
-        )
+        ))
+    }
+
+    /// Recreates the pipelines whose shaders changed in the current resource packs.
+    pub fn reload_shaders(&mut self, resource_packs: &ResourcePacks) {
+        create_pipelines(
+            &mut self.render_cache,
+            &self.device,
+            &self.pipeline_layout,
+            &self.ui_pipeline_layout,
+            &self.shadow_pipeline_layout,
+            &self.water_pipeline_layout,
+            &self.bloom_pipeline_layout,
+            &self.composite_pipeline_layout,
+            &ShaderSources::load(resource_packs),
+            self.shader_features,
+            self.config.format,
+        );
+    }
+
+    /// Returns a pipeline requested by `create_pipelines`.
+    fn pipeline(&self, name: &str) -> &wgpu::RenderPipeline {
+        self.render_cache
+            .get_pipeline(name)
+            .unwrap_or_else(|| panic!("Pipeline {name} was never created"))
+    }
+
+    /// Returns a bind group requested when the renderer was created.
+    fn bind_group(&self, name: &str) -> &wgpu::BindGroup {
+        self.render_cache
+            .get_bind_group(name)
+            .unwrap_or_else(|| panic!("Bind group {name} was never created"))
+    }
+
+    /// Rebuilds meshes of the entity models loaded by the resource dictionary.
+    pub fn update_entity_models(&mut self, resource_dictionary: &ResourceDictionary) {
+        self.entity_models.clear();
+
+        for (kind, vox_model) in resource_dictionary.entity_models() {
+            let Some(model_constructor) = vox_model.mesh() else {
+                log::error!("Model of {kind} has too many faces, drawing it as a box");
+                continue;
+            };
+
+            self.entity_models
+                .insert(kind.clone(), Model::new(&self.device, &model_constructor));
+        }
+    }
+
+    /// Uploads the given atlas slots with their padding, leaving the rest of the texture untouched.
+    /// The mip levels are generated again afterwards.
+    pub fn write_atlas(&self, atlas: &TextureAtlas, slots: &[u32]) {
+        if slots.is_empty() {
+            return;
+        }
+
+        for slot in slots {
+            let (x, y) = TextureAtlas::cell_origin(*slot);
+            let cell = image::imageops::crop_imm(
+                atlas.image(),
+                x,
+                y,
+                TextureAtlas::CELL_SIZE,
+                TextureAtlas::CELL_SIZE,
+            )
+            .to_image();
+
+            self.atlas_texture.write_region(&self.queue, (x, y), &cell);
+        }
+
+        self.mipmap_generator
+            .generate(&self.device, &self.queue, &self.atlas_texture);
+    }
+
+    /// Replaces the sampler of the atlas, the bind group using it is created again.
+    pub fn set_texture_filtering(&mut self, texture_filtering: TextureFiltering) {
+        self.texture_filtering = texture_filtering;
+        self.atlas_texture.sampler =
+            texture::Texture::create_mipmapped_sampler(&self.device, texture_filtering);
+
+        log::info!("Using {texture_filtering:?} texture filtering");
+
+        cache_texture_bind_group(
+            &mut self.render_cache,
+            &self.device,
+            &self.texture_bind_group_layout,
+            "atlas_bind_group",
+            &self.atlas_texture,
+        );
+    }
+
+    /// Reconfigures the surface to use a different present mode.
+    pub fn set_present_mode(&mut self, present_mode: PresentModeSetting) {
+        self.present_mode = present_mode;
+        self.config.present_mode = select_present_mode(present_mode, &self.capabilities);
+
+        log::info!("Using present mode {:?}", self.config.present_mode);
+
+        self.target.configure(&self.device, &self.config);
+    }
+
+    /// Copies the last frame of the offscreen target back to the CPU.
+    /// Returns None when rendering into a window or if the copy failed.
+    pub fn read_pixels(&self) -> Option<image::RgbaImage> {
+        let RenderTarget::Offscreen(texture) = &self.target else {
+            return None;
+        };
+
+        self.read_texture(texture)
+    }
+
+    /// Copies a texture in the surface format back to the CPU, converting it to RGBA.
+    fn read_texture(&self, texture: &wgpu::Texture) -> Option<image::RgbaImage> {
+        let (width, height) = (texture.width(), texture.height());
+
+        // rows of the copy have to be aligned, the padding is stripped afterwards
+        let row_size = 4 * width;
+        let padded_row_size = row_size.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen_readback"),
+            size: padded_row_size as u64 * height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        if let Err(e) = receiver.recv().ok()? {
+            log::error!("Failed to read the offscreen target: {e}");
+            return None;
+        }
+
+        let mut pixels = Vec::with_capacity((row_size * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_row_size as usize) {
+                pixels.extend_from_slice(&row[..row_size as usize]);
+            }
+        }
+        buffer.unmap();
+
+        // window surfaces commonly use BGRA
+        if matches!(
+            texture.format(),
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels)
     }
 }
 
-pub fn rendering_sys(
-    renderer: UniqueView<Renderer>,
-    models: View<Model>,
-) -> Result<(), wgpu::SurfaceError> {
-    let output = renderer.surface.get_current_texture()?;
-    let view = output
-        .texture
-        .create_view(&wgpu::TextureViewDescriptor::default());
+/// Where frames are drawn.
+#[derive(Debug)]
+pub enum RenderTarget {
+    Window(wgpu::Surface),
+    /// Texture which can be copied back to the CPU, used without a window.
+    Offscreen(wgpu::Texture),
+}
 
-    let mut encoder = renderer
-        .device
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+impl RenderTarget {
+    /// Applies the size and present mode of `config`.
+    fn configure(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        match self {
+            RenderTarget::Window(surface) => surface.configure(device, config),
+            RenderTarget::Offscreen(texture) => {
+                if texture.width() != config.width || texture.height() != config.height {
+                    *texture = create_offscreen_texture(device, config);
+                }
+            }
+        }
+    }
+}
+
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen_target"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Returns the first sRGB format the surface supports, so colors written by the shaders are encoded
+/// by the GPU. Without one the preferred format is used and the world looks darker than intended.
+fn select_surface_format(formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+    if let Some(format) = formats.iter().find(|format| format.is_srgb()) {
+        return *format;
+    }
+
+    log::warn!(
+        "Surface supports no sRGB format, colors will be too dark with {:?}",
+        formats[0]
+    );
+
+    formats[0]
+}
+
+/// Returns the requested present mode if it is supported, Fifo otherwise as it is always available.
+fn select_present_mode(
+    requested: PresentModeSetting,
+    capabilities: &GraphicsCapabilities,
+) -> wgpu::PresentMode {
+    if capabilities.supports_present_mode(requested) {
+        requested.into()
+    } else {
+        log::warn!("Present mode {requested:?} is not supported, falling back to Fifo");
+        wgpu::PresentMode::Fifo
+    }
+}
+
+/// Loads a shader from resource packs, falling back to the embedded copy.
+fn load_shader(resource_packs: &ResourcePacks, name: &str, builtin: &str) -> String {
+    load_shader_source(resource_packs, name).unwrap_or_else(|e| {
+        log::error!("{e}, using the built-in shader");
+        builtin.to_string()
+    })
+}
 
+/// Sources of all shaders used by the pipelines.
+#[derive(Debug)]
+pub struct ShaderSources {
+    pub world: String,
+    pub ui: String,
+    pub particle: String,
+    pub water: String,
+    pub bloom: String,
+    /// Files the world, particle and water shaders can include.
+    pub includes: ShaderIncludes,
+}
+
+impl ShaderSources {
+    pub fn load(resource_packs: &ResourcePacks) -> Self {
+        let includes = builtin_shader_includes()
+            .into_iter()
+            .map(|(name, builtin)| {
+                let source = load_shader(resource_packs, &name, &builtin);
+                (name, source)
+            })
+            .collect();
+
+        Self {
+            world: load_shader(resource_packs, "shader.wgsl", BUILTIN_SHADER),
+            ui: load_shader(resource_packs, "ui.wgsl", BUILTIN_UI_SHADER),
+            particle: load_shader(resource_packs, "particle.wgsl", BUILTIN_PARTICLE_SHADER),
+            water: load_shader(resource_packs, "water.wgsl", BUILTIN_WATER_SHADER),
+            bloom: load_shader(resource_packs, "bloom.wgsl", BUILTIN_BLOOM_SHADER),
+            includes,
+        }
+    }
+}
+
+/// Requests all pipelines from the cache, only those whose shader or target format changed are
+/// created again. Shaders are compiled once for all pipelines using them, if any of them is missing.
+/// The world is drawn into the HDR target, only the composite and UI pipelines draw in `format`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_pipelines(
+    cache: &mut RenderCache,
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    ui_layout: &wgpu::PipelineLayout,
+    shadow_layout: &wgpu::PipelineLayout,
+    water_layout: &wgpu::PipelineLayout,
+    bloom_layout: &wgpu::PipelineLayout,
+    composite_layout: &wgpu::PipelineLayout,
+    shader_sources: &ShaderSources,
+    features: ShaderFeatures,
+    format: wgpu::TextureFormat,
+) {
+    let world_shader = |features| {
+        create_shader(
+            device,
+            "world_shader",
+            &shader_sources.world,
+            BUILTIN_SHADER,
+            features,
+            &shader_sources.includes,
+        )
+    };
+    let shader = OnceCell::new();
+    let shader = || shader.get_or_init(|| world_shader(features));
+    // lines and wireframes show the shape of the geometry, so they are drawn without textures
+    let untextured_features = ShaderFeatures {
+        textures: false,
+        ..features
+    };
+    let untextured_shader = OnceCell::new();
+    let untextured_shader = || untextured_shader.get_or_init(|| world_shader(untextured_features));
+
+    let world_pipelines = [
+        (
+            "block",
+            features,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            DepthPass::Color,
+        ),
+        // both passes use the same shader, so the depths of the pre-pass match exactly
+        (
+            "depth_prepass",
+            features,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            DepthPass::Prepass,
+        ),
+        (
+            "block_after_prepass",
+            features,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            DepthPass::AfterPrepass,
+        ),
+        (
+            "line",
+            untextured_features,
+            wgpu::PrimitiveTopology::LineList,
+            wgpu::PolygonMode::Fill,
+            DepthPass::Color,
+        ),
+    ];
+
+    for (name, features, topology, polygon_mode, depth_pass) in world_pipelines {
+        let key = (
+            &shader_sources.world,
+            &shader_sources.includes,
+            features,
+            topology,
+            polygon_mode,
+            depth_pass,
+        );
+        cache.pipeline(name, &key, || {
+            let shader = if features.textures {
+                shader()
+            } else {
+                untextured_shader()
+            };
+            create_pipeline(
+                device,
+                layout,
+                shader,
+                HDR_FORMAT,
+                topology,
+                polygon_mode,
+                depth_pass,
+            )
+        });
+    }
+
+    // only created if the device supports `Features::POLYGON_MODE_LINE`
+    if device
+        .features()
+        .contains(wgpu::Features::POLYGON_MODE_LINE)
     {
+        let key = (
+            &shader_sources.world,
+            &shader_sources.includes,
+            untextured_features,
+            wgpu::PolygonMode::Line,
+        );
+        cache.pipeline("wireframe", &key, || {
+            create_pipeline(
+                device,
+                layout,
+                untextured_shader(),
+                HDR_FORMAT,
+                wgpu::PrimitiveTopology::TriangleList,
+                wgpu::PolygonMode::Line,
+                DepthPass::Color,
+            )
+        });
+    }
+
+    // only needed while shadows are on, the map doesn't depend on the target format
+    if features.shadows {
+        let key = (&shader_sources.world, &shader_sources.includes, features);
+        cache.pipeline("shadow", &key, || {
+            create_shadow_pipeline(device, shadow_layout, shader())
+        });
+    }
+
+    cache.pipeline("ui", &(&shader_sources.ui, format), || {
+        let ui_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("ui_shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_sources.ui.as_str().into()),
+        });
+        create_ui_pipeline(device, ui_layout, &ui_shader, format)
+    });
+
+    let key = (&shader_sources.particle, &shader_sources.includes);
+    cache.pipeline("particle", &key, || {
+        let particle_shader = create_shader(
+            device,
+            "particle_shader",
+            &shader_sources.particle,
+            BUILTIN_PARTICLE_SHADER,
+            features,
+            &shader_sources.includes,
+        );
+        create_particle_pipeline(device, layout, &particle_shader, HDR_FORMAT)
+    });
+
+    let key = (&shader_sources.water, &shader_sources.includes, features);
+    cache.pipeline("water", &key, || {
+        let water_shader = create_shader(
+            device,
+            "water_shader",
+            &shader_sources.water,
+            BUILTIN_WATER_SHADER,
+            features,
+            &shader_sources.includes,
+        );
+        create_water_pipeline(device, water_layout, &water_shader, HDR_FORMAT)
+    });
+
+    let bloom_shader = OnceCell::new();
+    let bloom_shader = || {
+        bloom_shader.get_or_init(|| {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("bloom_shader"),
+                source: wgpu::ShaderSource::Wgsl(shader_sources.bloom.as_str().into()),
+            })
+        })
+    };
+
+    // the light above the threshold is added up in the bloom levels
+    let bloom_pipelines = [
+        ("bloom_prefilter", "fs_prefilter", None),
+        ("bloom_downsample", "fs_downsample", None),
+        (
+            "bloom_upsample",
+            "fs_upsample",
+            Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            }),
+        ),
+    ];
+
+    for (name, entry_point, blend) in bloom_pipelines {
+        cache.pipeline(name, &shader_sources.bloom, || {
+            create_fullscreen_pipeline(
+                device,
+                bloom_layout,
+                bloom_shader(),
+                entry_point,
+                HDR_FORMAT,
+                blend,
+            )
+        });
+    }
+
+    cache.pipeline("composite", &(&shader_sources.bloom, format), || {
+        create_fullscreen_pipeline(
+            device,
+            composite_layout,
+            bloom_shader(),
+            "fs_composite",
+            format,
+            None,
+        )
+    });
+}
+
+/// Requests the bind group sampling a texture, it's only created again if the texture was replaced.
+fn cache_texture_bind_group(
+    cache: &mut RenderCache,
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    name: &'static str,
+    texture: &texture::Texture,
+) {
+    let key = (texture.view.global_id(), texture.sampler.global_id());
+
+    cache.bind_group(name, &key, || {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some(name),
+        })
+    });
+}
+
+/// Preprocesses a shader with the features, falling back to the built-in shader and includes if it
+/// can't be. Shaders without flags ignore the features.
+fn create_shader(
+    device: &wgpu::Device,
+    label: &str,
+    source: &str,
+    builtin: &str,
+    features: ShaderFeatures,
+    includes: &ShaderIncludes,
+) -> wgpu::ShaderModule {
+    let source = features.apply(source, includes).unwrap_or_else(|e| {
+        log::error!("{e}, using the built-in shader");
+        features
+            .apply(builtin, &builtin_shader_includes())
+            .expect("Built-in shader can't be preprocessed")
+    });
+
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+/// Creates the pipeline drawing the UI over the world, without depth testing.
+fn create_ui_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("ui_pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[UiVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Creates the pipeline drawing particles as camera facing squares, one instance each.
+fn create_particle_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("particle_pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[RawParticle::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            // the squares turn with the camera, so they are seen from either side
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Creates the pipeline drawing the depth of the world seen from the sun into the shadow map.
+fn create_shadow_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("shadow_pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc(), RawTransform::desc()],
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            // plants are single quads seen from either side, so both sides cast shadows
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            // pushes surfaces facing the sun at a steep angle back, so they don't shadow themselves
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Creates the pipeline blending fluid surfaces over the world. It only tests depth, the depth buffer
+/// is sampled by the shader at the same time.
+fn create_water_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("water_pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc(), RawTransform::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            // the surface is seen from below too while diving
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Creates a pipeline drawing a single triangle over the whole target, for the bloom and composite passes.
+fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// How a pipeline uses the depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum DepthPass {
+    /// Tests and writes depth while drawing colors.
+    Color,
+    /// Only writes depth, so the color pass shades just the nearest fragments.
+    Prepass,
+    /// Draws colors of the fragments which were nearest in the pre-pass.
+    AfterPrepass,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    topology: wgpu::PrimitiveTopology,
+    polygon_mode: wgpu::PolygonMode,
+    depth_pass: DepthPass,
+) -> wgpu::RenderPipeline {
+    let is_line_list = topology == wgpu::PrimitiveTopology::LineList;
+    let write_mask = if depth_pass == DepthPass::Prepass {
+        wgpu::ColorWrites::empty()
+    } else {
+        wgpu::ColorWrites::ALL
+    };
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc(), RawTransform::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: if is_line_list {
+                None
+            } else {
+                Some(wgpu::Face::Back)
+            },
+            // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+            polygon_mode,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: depth_pass != DepthPass::AfterPrepass,
+            // lines lie exactly on block edges, so let them win ties against faces
+            depth_compare: if is_line_list || depth_pass == DepthPass::AfterPrepass {
+                wgpu::CompareFunction::LessEqual
+            } else {
+                wgpu::CompareFunction::Less
+            },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Views of everything drawn into a frame.
+/// Camera a frame is drawn with. The world is placed relative to the origin of the main camera
+/// in every view, so only the projection differs.
+pub struct Viewpoint<'a> {
+    pub frustum: Frustum,
+    pub camera_bind_group: &'a wgpu::BindGroup,
+}
+
+pub struct Scene<'a, 'v> {
+    pub camera: &'a Camera,
+    pub debug_state: &'a DebugRenderState,
+    pub models: &'a View<'v, Model>,
+    pub chunks: &'a View<'v, ChunkTag>,
+    pub mobs: &'a View<'v, Mob>,
+    pub fluid_models: &'a View<'v, FluidModel>,
+}
+
+impl Renderer {
+    /// Returns the viewpoint of the main camera, which the window shows.
+    /// It culls with the frozen view instead of its own while one is set.
+    pub fn main_viewpoint(&self, camera: &Camera, debug_state: &DebugRenderState) -> Viewpoint<'_> {
+        Viewpoint {
+            frustum: debug_state
+                .frozen_view
+                .map_or_else(|| camera.frustum(), |view| view.frustum(camera.origin)),
+            camera_bind_group: &self.camera_bind_group,
+        }
+    }
+
+    /// Records the world and debug overlays into the targets and composites them onto `view`, followed by
+    /// the HUD if `hud` is set.
+    /// Only frames drawn with `timed` set are measured by the GPU timer.
+    /// Models outside of the view are skipped, returns how many were drawn and skipped.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_frame(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        targets: &FrameTargets,
+        scene: &Scene,
+        viewpoint: &Viewpoint,
+        hud: bool,
+        timed: bool,
+    ) -> DrawStats {
+        let camera = scene.camera;
+        let debug_state = scene.debug_state;
+        let frustum = viewpoint.frustum;
+        let mut stats = DrawStats::default();
+
+        // Chunk border instances are only needed while the overlay is enabled
+        let chunk_border_instances = if debug_state.chunk_borders {
+            let instance_data: Vec<RawTransform> = (scene.chunks, scene.models)
+                .iter()
+                .map(|(chunk, _)| {
+                    RawTransform::from(Transform {
+                        rotation: glam::Quat::IDENTITY,
+                        translation: chunk.coords.translation_from(camera.origin),
+                    })
+                })
+                .collect();
+
+            create_instance_buffer(&self.device, &instance_data)
+        } else {
+            None
+        };
+
+        let origin = camera.origin.as_world_position();
+        let mob_offset = glam::DVec3::new(Mob::SIZE.x as f64 / 2.0, 0.0, Mob::SIZE.z as f64 / 2.0);
+        let mut mob_instance_data: Vec<RawTransform> = Vec::new();
+        let mut entity_instance_data: HashMap<&str, Vec<RawTransform>> = HashMap::new();
+
+        for mob in scene.mobs.iter() {
+            // entity meshes stand centered on the position, boxes start at their corner
+            let (model, translation) = match self.entity_models.get(&mob.model) {
+                Some(model) => (model, mob.position - origin),
+                None => (&self.mob_model, mob.position - mob_offset - origin),
+            };
+            let transform = Transform {
+                rotation: glam::Quat::IDENTITY,
+                translation: translation.as_vec3(),
+            };
+
+            let (min, max) = model.bounds_at(transform);
+            let visible = frustum.intersects_box(min, max);
+            stats.entities.count(visible);
+
+            if !visible {
+                continue;
+            }
+
+            if self.entity_models.contains_key(&mob.model) {
+                entity_instance_data
+                    .entry(mob.model.as_str())
+                    .or_default()
+                    .push(RawTransform::from(transform));
+            } else {
+                mob_instance_data.push(RawTransform::from(transform));
+            }
+        }
+
+        let mob_instances = create_instance_buffer(&self.device, &mob_instance_data);
+        let entity_instances: Vec<(&Model, Option<(wgpu::Buffer, u32)>)> = entity_instance_data
+            .iter()
+            .map(|(kind, instance_data)| {
+                (
+                    &self.entity_models[*kind],
+                    create_instance_buffer(&self.device, instance_data),
+                )
+            })
+            .collect();
+
+        let eye = (camera.eye - origin).as_vec3();
+        let mut chunk_models: Vec<(f32, &Model)> = Vec::new();
+        let mut other_models: Vec<&Model> = Vec::new();
+
+        for (id, model) in scene.models.iter().with_id() {
+            // Empty chunks have no geometry to draw
+            if model.index_count() == 0 {
+                continue;
+            }
+
+            let (min, max) = model.bounds();
+            let visible = frustum.intersects_box(min, max);
+
+            // other models belong to item drops and falling blocks
+            if scene.chunks.contains(id) {
+                stats.chunks.count(visible);
+
+                if visible {
+                    chunk_models.push((eye.clamp(min, max).distance_squared(eye), model));
+                }
+            } else {
+                stats.entities.count(visible);
+
+                if visible {
+                    other_models.push(model);
+                }
+            }
+        }
+
+        // nearest chunks first, so fragments hidden behind them fail the depth test before shading
+        chunk_models.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        // chunks in the arena are drawn with a single indirect draw, the rest one by one
+        let mut indirect_draws: Vec<wgpu::util::DrawIndexedIndirect> = Vec::new();
+        let mut separate_chunk_models: Vec<&Model> = Vec::new();
+
+        for (_, model) in chunk_models {
+            match &model.storage {
+                ModelStorage::Arena(allocation) => indirect_draws.push(allocation.indirect_draw()),
+                ModelStorage::Buffers(_) => separate_chunk_models.push(model),
+            }
+        }
+
+        let indirect_buffer = create_indirect_buffer(&self.device, &indirect_draws);
+        let indirect_chunks = self.chunk_arena.as_ref().zip(indirect_buffer.as_ref());
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.hdr.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: self.sky_color.x as f64,
+                            g: self.sky_color.y as f64,
+                            b: self.sky_color.z as f64,
+                            a: 1.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &targets.depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: self
+                    .gpu_timer
+                    .as_ref()
+                    .filter(|_| timed)
+                    .map(|gpu_timer| gpu_timer.timestamp_writes()),
+                occlusion_query_set: None,
+            });
+
+            let pipeline = match self.render_cache.get_pipeline("wireframe") {
+                Some(wireframe_pipeline) if debug_state.wireframe => wireframe_pipeline,
+                _ => self.pipeline("block"),
+            };
+
+            rpass.set_bind_group(0, viewpoint.camera_bind_group, &[]);
+            rpass.set_bind_group(1, self.bind_group("atlas_bind_group"), &[]);
+            rpass.set_bind_group(2, &self.fog_bind_group, &[]);
+            rpass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
+
+            if self.depth_prepass && !debug_state.wireframe {
+                rpass.set_pipeline(self.pipeline("depth_prepass"));
+                draw_chunks(&mut rpass, indirect_chunks, &separate_chunk_models);
+
+                rpass.set_pipeline(self.pipeline("block_after_prepass"));
+            } else {
+                rpass.set_pipeline(pipeline);
+            }
+
+            draw_chunks(&mut rpass, indirect_chunks, &separate_chunk_models);
+
+            rpass.set_pipeline(pipeline);
+
+            for model in other_models {
+                draw_model(&mut rpass, model);
+            }
+
+            for (model, instances) in entity_instances.iter() {
+                if let Some((instance_buffer, instance_count)) = instances {
+                    draw_instances(&mut rpass, model, instance_buffer, *instance_count);
+                }
+            }
+
+            rpass.set_pipeline(self.pipeline("line"));
+
+            for (model, instances) in [
+                (&self.chunk_border_model, &chunk_border_instances),
+                (&self.mob_model, &mob_instances),
+            ] {
+                if let Some((instance_buffer, instance_count)) = instances {
+                    draw_instances(&mut rpass, model, instance_buffer, *instance_count);
+                }
+            }
+
+            for model in [&self.crack_model, &self.frustum_model]
+                .into_iter()
+                .flatten()
+            {
+                draw_model(&mut rpass, model);
+            }
+
+            if let Some((instance_buffer, instance_count)) = &self.particle_instances {
+                rpass.set_pipeline(self.pipeline("particle"));
+                rpass.set_vertex_buffer(0, instance_buffer.slice(..));
+                rpass.draw(0..4, 0..*instance_count);
+            }
+        }
+
+        self.encode_fluids(encoder, targets, scene, viewpoint);
+        self.encode_bloom(encoder, targets);
+        self.encode_composite(encoder, view, targets);
+
+        let Some(hud_model) = self.hud_model.as_ref().filter(|_| hud) else {
+            return stats;
+        };
+
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
+            label: Some("ui_pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
+                view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLUE),
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        rpass.set_pipeline(self.pipeline("ui"));
+        rpass.set_vertex_buffer(0, hud_model.vertex_buffer.slice(..));
+        rpass.set_index_buffer(hud_model.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        // text is drawn last, so it's never covered by backgrounds of other elements
+        for (bind_group, indices) in [
+            ("world_map_bind_group", &hud_model.world_map_indices),
+            ("minimap_bind_group", &hud_model.minimap_indices),
+            ("atlas_bind_group", &hud_model.atlas_indices),
+            ("font_bind_group", &hud_model.text_indices),
+        ] {
+            if !indices.is_empty() {
+                rpass.set_bind_group(0, self.bind_group(bind_group), &[]);
+                rpass.draw_indexed(indices.clone(), 0, 0..1);
+            }
+        }
+
+        stats
+    }
+
+    /// Blends the visible fluid surfaces over the world drawn into the targets, furthest first.
+    /// The depth buffer is only read, the water shader samples it at the same time.
+    fn encode_fluids(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+        scene: &Scene,
+        viewpoint: &Viewpoint,
+    ) {
+        let eye = (scene.camera.eye - scene.camera.origin.as_world_position()).as_vec3();
+        let mut fluid_models: Vec<(f32, &Model)> = scene
+            .fluid_models
+            .iter()
+            .filter_map(|FluidModel(model)| {
+                let (min, max) = model.bounds();
+
+                viewpoint
+                    .frustum
+                    .intersects_box(min, max)
+                    .then(|| (eye.clamp(min, max).distance_squared(eye), model))
+            })
+            .collect();
+
+        if fluid_models.is_empty() {
+            return;
+        }
+
+        fluid_models.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        let water_bind_group = self.water.bind_group(&self.device, &targets.depth.view);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("water_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.hdr.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
                     store: wgpu::StoreOp::Store,
                 },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &renderer.depth_texture.view,
+                view: &targets.depth.view,
+                depth_ops: None,
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        rpass.set_pipeline(self.pipeline("water"));
+        rpass.set_bind_group(0, viewpoint.camera_bind_group, &[]);
+        rpass.set_bind_group(1, &water_bind_group, &[]);
+        rpass.set_bind_group(2, &self.fog_bind_group, &[]);
+
+        for (_, model) in fluid_models {
+            draw_model(&mut rpass, model);
+        }
+    }
+
+    /// Fills the bloom levels with the light of the frame above the threshold, blurred and added back up
+    /// into the largest level. Does nothing while bloom is off, the composite pass ignores the levels then.
+    fn encode_bloom(&self, encoder: &mut wgpu::CommandEncoder, targets: &FrameTargets) {
+        if self.bloom.intensity <= 0.0 {
+            return;
+        }
+
+        let mut passes: Vec<(
+            &str,
+            &wgpu::BindGroup,
+            &texture::Texture,
+            wgpu::LoadOp<wgpu::Color>,
+        )> = vec![(
+            "bloom_prefilter",
+            &targets.hdr_bind_group,
+            &targets.bloom[0],
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        )];
+
+        for level in 1..targets.bloom.len() {
+            passes.push((
+                "bloom_downsample",
+                &targets.bloom_bind_groups[level - 1],
+                &targets.bloom[level],
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            ));
+        }
+
+        // the larger level keeps its own light, the blurred smaller one is blended on top
+        for level in (1..targets.bloom.len()).rev() {
+            passes.push((
+                "bloom_upsample",
+                &targets.bloom_bind_groups[level],
+                &targets.bloom[level - 1],
+                wgpu::LoadOp::Load,
+            ));
+        }
+
+        for (pipeline, source, target, load) in passes {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(pipeline),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(self.pipeline(pipeline));
+            rpass.set_bind_group(0, source, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+
+    /// Adds the glow to the frame and tone maps it onto `view`.
+    fn encode_composite(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        targets: &FrameTargets,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("composite_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        rpass.set_pipeline(self.pipeline("composite"));
+        rpass.set_bind_group(0, &targets.hdr_bind_group, &[]);
+        rpass.set_bind_group(1, &targets.bloom_bind_groups[0], &[]);
+        rpass.set_bind_group(2, &self.bloom.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    /// Records the models near the camera into the shadow map, which every view of the frame samples.
+    /// Does nothing while shadows are off.
+    pub fn encode_shadow_pass(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene) {
+        if !self.shader_features.shadows {
+            return;
+        }
+
+        let frustum = self.shadow_map.frustum;
+        let mut indirect_draws: Vec<wgpu::util::DrawIndexedIndirect> = Vec::new();
+        let mut separate_models: Vec<&Model> = Vec::new();
+
+        for model in scene.models.iter() {
+            let (min, max) = model.bounds();
+            if model.index_count() == 0 || !frustum.intersects_box(min, max) {
+                continue;
+            }
+
+            match &model.storage {
+                ModelStorage::Arena(allocation) => indirect_draws.push(allocation.indirect_draw()),
+                ModelStorage::Buffers(_) => separate_models.push(model),
+            }
+        }
+
+        let indirect_buffer = create_indirect_buffer(&self.device, &indirect_draws);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("shadow_pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.shadow_map.texture.view,
                 depth_ops: Some(wgpu::Operations {
                     load: wgpu::LoadOp::Clear(1.0),
                     store: wgpu::StoreOp::Store,
@@ -201,23 +1860,225 @@ pub fn rendering_sys(
             occlusion_query_set: None,
         });
 
-        rpass.set_pipeline(&renderer.pipeline);
-        rpass.set_bind_group(0, &renderer.camera_bind_group, &[]);
+        rpass.set_pipeline(self.pipeline("shadow"));
+        rpass.set_bind_group(0, &self.shadow_map.camera_bind_group, &[]);
+        draw_chunks(
+            &mut rpass,
+            self.chunk_arena.as_ref().zip(indirect_buffer.as_ref()),
+            &separate_models,
+        );
+    }
 
-        for model in models.iter() {
-            rpass.set_vertex_buffer(0, model.vertex_buffer.slice(..));
-            rpass.set_vertex_buffer(1, model.instance_buffer.slice(..));
-            rpass.set_index_buffer(model.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            rpass.draw_indexed(0..model.index_count(), 0, 0..1);
+    /// Renders a frame into a separate texture `scale` times the size of the window and returns its pixels.
+    /// The scale is reduced if the texture would exceed the limits of the device.
+    pub fn capture(&self, scene: &Scene, scale: u32, hud: bool) -> Option<image::RgbaImage> {
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        let largest_side = self.config.width.max(self.config.height).max(1);
+        let scale = scale.min(max_dimension / largest_side).max(1);
+
+        let mut config = self.config.clone();
+        config.width *= scale;
+        config.height *= scale;
+
+        let texture = create_offscreen_texture(&self.device, &config);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let targets = FrameTargets::new(
+            &self.device,
+            &self.texture_bind_group_layout,
+            config.width,
+            config.height,
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.encode_frame(
+            &mut encoder,
+            &view,
+            &targets,
+            scene,
+            &self.main_viewpoint(scene.camera, scene.debug_state),
+            hud,
+            false,
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        self.read_texture(&texture)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn rendering_sys(
+    mut renderer: UniqueViewMut<Renderer>,
+    mut profiler: UniqueViewMut<Profiler>,
+    camera: UniqueView<Camera>,
+    debug_state: UniqueView<DebugRenderState>,
+    models: View<Model>,
+    chunks: View<ChunkTag>,
+    mobs: View<Mob>,
+    fluid_models: View<FluidModel>,
+) -> Result<(), wgpu::SurfaceError> {
+    let renderer = &mut *renderer;
+
+    // results of earlier frames arrive with a delay
+    if let Some(gpu_time) = renderer
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.read(&renderer.device))
+    {
+        profiler.record_gpu("gpu_pass", gpu_time);
+    }
+
+    let (output, view) = match &renderer.target {
+        RenderTarget::Window(surface) => {
+            let output = surface.get_current_texture()?;
+            let view = output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
+            (Some(output), view)
         }
+        RenderTarget::Offscreen(texture) => (
+            None,
+            texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        ),
+    };
+
+    let mut encoder = renderer
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+    let scene = Scene {
+        camera: &camera,
+        debug_state: &debug_state,
+        models: &models,
+        chunks: &chunks,
+        mobs: &mobs,
+        fluid_models: &fluid_models,
+    };
+
+    renderer.encode_shadow_pass(&mut encoder, &scene);
+
+    renderer.draw_stats = renderer.encode_frame(
+        &mut encoder,
+        &view,
+        &renderer.targets,
+        &scene,
+        &renderer.main_viewpoint(&camera, &debug_state),
+        true,
+        true,
+    );
+
+    if let Some(gpu_timer) = renderer.gpu_timer.as_mut() {
+        gpu_timer.resolve(&mut encoder);
     }
 
     renderer.queue.submit(std::iter::once(encoder.finish()));
-    output.present();
+
+    if let Some(output) = output {
+        output.present();
+    }
+
+    if let Some(debug_view) = &renderer.debug_view {
+        debug_view.render(renderer, &scene);
+    }
+
+    if let Some(gpu_timer) = renderer.gpu_timer.as_mut() {
+        gpu_timer.map();
+    }
 
     Ok(())
 }
 
+/// Draws a model with its own transform.
+fn draw_model<'a>(rpass: &mut wgpu::RenderPass<'a>, model: &'a Model) {
+    match &model.storage {
+        ModelStorage::Buffers(buffers) => {
+            rpass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+            rpass.set_vertex_buffer(1, buffers.instance_buffer.slice(..));
+            rpass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            rpass.draw_indexed(0..model.index_count(), 0, 0..1);
+        }
+        ModelStorage::Arena(allocation) => allocation.draw(rpass),
+    }
+}
+
+/// Draws a model once for every transform in the instance buffer.
+fn draw_instances<'a>(
+    rpass: &mut wgpu::RenderPass<'a>,
+    model: &'a Model,
+    instance_buffer: &'a wgpu::Buffer,
+    instance_count: u32,
+) {
+    // only chunks are stored in the arena
+    let ModelStorage::Buffers(buffers) = &model.storage else {
+        return;
+    };
+
+    rpass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+    rpass.set_vertex_buffer(1, instance_buffer.slice(..));
+    rpass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+    rpass.draw_indexed(0..model.index_count(), 0, 0..instance_count);
+}
+
+/// Draws the chunks in the arena with one indirect draw, then those with buffers of their own.
+fn draw_chunks<'a>(
+    rpass: &mut wgpu::RenderPass<'a>,
+    indirect_chunks: Option<(&'a ChunkArena, &'a (wgpu::Buffer, u32))>,
+    separate_chunk_models: &[&'a Model],
+) {
+    if let Some((arena, (indirect_buffer, count))) = indirect_chunks {
+        arena.draw_all(rpass, indirect_buffer, *count);
+    }
+
+    for model in separate_chunk_models {
+        draw_model(rpass, model);
+    }
+}
+
+/// Uploads indirect draw arguments, returns None if there is nothing to draw.
+fn create_indirect_buffer(
+    device: &wgpu::Device,
+    draws: &[wgpu::util::DrawIndexedIndirect],
+) -> Option<(wgpu::Buffer, u32)> {
+    if draws.is_empty() {
+        return None;
+    }
+
+    let contents: Vec<u8> = draws
+        .iter()
+        .flat_map(|draw| draw.as_bytes().iter().copied())
+        .collect();
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("chunk_indirect_buffer"),
+        contents: &contents,
+        usage: wgpu::BufferUsages::INDIRECT,
+    });
+
+    Some((buffer, draws.len() as u32))
+}
+
+/// Uploads per-instance transforms, returns None if there is nothing to draw.
+fn create_instance_buffer(
+    device: &wgpu::Device,
+    instance_data: &[RawTransform],
+) -> Option<(wgpu::Buffer, u32)> {
+    if instance_data.is_empty() {
+        return None;
+    }
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(instance_data),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    Some((buffer, instance_data.len() as u32))
+}
+
 pub fn resize_sys(
     new_size: PhysicalSize<u32>,
     mut renderer: UniqueViewMut<Renderer>,
@@ -228,16 +2089,85 @@ pub fn resize_sys(
         renderer.config.width = new_size.width;
         renderer.config.height = new_size.height;
 
+        let renderer = &mut *renderer;
         renderer
-            .surface
+            .target
             .configure(&renderer.device, &renderer.config);
 
-        renderer.depth_texture = texture::Texture::create_depth_texture(
+        renderer.targets = FrameTargets::new(
             &renderer.device,
-            &renderer.config,
-            "depth_texture",
+            &renderer.texture_bind_group_layout,
+            renderer.config.width,
+            renderer.config.height,
         );
 
-        camera.update_view_projection_matrix(&renderer);
+        camera.update_view_projection_matrix(renderer);
     }
 }
+
+/// Moves the end of the fog to the edge of the loaded chunks and colors it like the sky,
+/// so chunks appearing at the edge are hidden.
+pub fn update_fog_sys(settings: UniqueView<Settings>, renderer: UniqueView<Renderer>) {
+    let end = (settings.graphics.view_distance.max(1) as i32 * Chunk::SIZE) as f32;
+
+    renderer.queue.write_buffer(
+        &renderer.fog_buffer,
+        0,
+        bytemuck::cast_slice(&[FogUniform {
+            color: renderer.sky_color,
+            end,
+        }]),
+    );
+}
+
+pub fn apply_present_mode_sys(
+    settings: UniqueView<Settings>,
+    mut renderer: UniqueViewMut<Renderer>,
+) {
+    if settings.graphics.present_mode != renderer.present_mode {
+        renderer.set_present_mode(settings.graphics.present_mode);
+    }
+}
+
+pub fn apply_texture_filtering_sys(
+    settings: UniqueView<Settings>,
+    mut renderer: UniqueViewMut<Renderer>,
+) {
+    if settings.graphics.texture_filtering != renderer.texture_filtering {
+        renderer.set_texture_filtering(settings.graphics.texture_filtering);
+    }
+}
+
+/// Tracks frame timings to cap the framerate when vsync is not used.
+#[derive(Debug, Unique)]
+pub struct FrameLimiter {
+    last_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new() -> Self {
+        Self {
+            last_frame: Instant::now(),
+        }
+    }
+}
+
+pub fn frame_limiter_sys(
+    settings: UniqueView<Settings>,
+    renderer: UniqueView<Renderer>,
+    mut frame_limiter: UniqueViewMut<FrameLimiter>,
+) {
+    // Fifo is already synchronized with the display
+    if renderer.config.present_mode != wgpu::PresentMode::Fifo {
+        if let Some(fps_limit) = settings.graphics.fps_limit.filter(|limit| *limit > 0) {
+            let frame_time = Duration::from_secs_f64(1.0 / fps_limit as f64);
+            let elapsed = frame_limiter.last_frame.elapsed();
+
+            if elapsed < frame_time {
+                std::thread::sleep(frame_time - elapsed);
+            }
+        }
+    }
+
+    frame_limiter.last_frame = Instant::now();
+}
@@ -0,0 +1,285 @@
+//! Recording of the input of every update and playing it back, for reproducible bug reports and
+//! smoke tests of the game loop.
+//!
+//! Only input which reaches the simulation is recorded, keys handled by the window or the overlays
+//! are not. Playback reproduces the same session as long as the world and settings are the same,
+//! `physics.deterministic` should be enabled to get bit exact movement on other machines.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use shipyard::*;
+
+use crate::{camera::Camera, input::InputState};
+
+/// Whether the input of the session is recorded or replaced by a recording, chosen on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayMode {
+    Record(PathBuf),
+    Play(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io { path, source } => {
+                write!(
+                    f,
+                    "Failed to access input recording {}: {source}",
+                    path.display()
+                )
+            }
+            ReplayError::Parse { path, source } => {
+                write!(
+                    f,
+                    "Failed to parse input recording {}: {source}",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::Io { source, .. } => Some(source),
+            ReplayError::Parse { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Input of a single update, one-shot requests are captured before the systems consume them.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct InputFrame {
+    pub cursor_captured: bool,
+    pub forward: bool,
+    pub backward: bool,
+    pub leftward: bool,
+    pub rightward: bool,
+    pub upward: bool,
+    pub downward: bool,
+    pub place_block: bool,
+    pub break_block: bool,
+    pub breaking: bool,
+    pub undo: bool,
+    pub redo: bool,
+    pub select_slot: Option<usize>,
+    pub scroll: i32,
+    /// Orientation of the camera in degrees, mouse motion is applied to it directly.
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl InputFrame {
+    fn capture(input_state: &InputState, yaw: f32, pitch: f32) -> Self {
+        Self {
+            cursor_captured: input_state.cursor_captured,
+            forward: input_state.forward,
+            backward: input_state.backward,
+            leftward: input_state.leftward,
+            rightward: input_state.rightward,
+            upward: input_state.upward,
+            downward: input_state.downward,
+            place_block: input_state.place_block,
+            break_block: input_state.break_block,
+            breaking: input_state.breaking,
+            undo: input_state.undo,
+            redo: input_state.redo,
+            select_slot: input_state.select_slot,
+            scroll: input_state.scroll,
+            yaw,
+            pitch,
+        }
+    }
+
+    fn apply(&self, input_state: &mut InputState, camera: &mut Camera) {
+        input_state.cursor_captured = self.cursor_captured;
+        input_state.forward = self.forward;
+        input_state.backward = self.backward;
+        input_state.leftward = self.leftward;
+        input_state.rightward = self.rightward;
+        input_state.upward = self.upward;
+        input_state.downward = self.downward;
+        input_state.place_block = self.place_block;
+        input_state.break_block = self.break_block;
+        input_state.breaking = self.breaking;
+        input_state.undo = self.undo;
+        input_state.redo = self.redo;
+        input_state.select_slot = self.select_slot;
+        input_state.scroll = self.scroll;
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+    }
+}
+
+/// Contents of a recording file, stored as RON.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InputRecording {
+    /// Position of the camera when the recording started, playback starts from there.
+    pub start: [f64; 3],
+    pub frames: Vec<InputFrame>,
+}
+
+impl InputRecording {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let content = fs::read_to_string(path).map_err(|source| ReplayError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        ron::from_str(&content).map_err(|source| ReplayError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        ron::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            .and_then(|content| fs::write(path, content))
+            .map_err(|source| ReplayError::Io {
+                path: path.to_owned(),
+                source,
+            })
+    }
+}
+
+#[derive(Debug, Default, Unique)]
+pub enum InputReplay {
+    /// Input comes from the window as usual.
+    #[default]
+    Off,
+    Recording {
+        path: PathBuf,
+        recording: Option<InputRecording>,
+    },
+    Playing {
+        recording: InputRecording,
+        /// Index of the frame applied by the next update.
+        next: usize,
+    },
+}
+
+impl InputReplay {
+    /// Sets up the mode, a recording to play is loaded right away.
+    pub fn new(mode: ReplayMode) -> Result<Self, ReplayError> {
+        Ok(match mode {
+            ReplayMode::Record(path) => InputReplay::Recording {
+                path,
+                recording: None,
+            },
+            ReplayMode::Play(path) => InputReplay::Playing {
+                recording: InputRecording::load(&path)?,
+                next: 0,
+            },
+        })
+    }
+
+    /// Returns true while frames of a recording are left to play.
+    pub fn is_playing(&self) -> bool {
+        matches!(self, InputReplay::Playing { .. })
+    }
+
+    /// Writes the recorded frames, called when the game exits.
+    pub fn finish(&mut self) {
+        let InputReplay::Recording {
+            path,
+            recording: Some(recording),
+        } = std::mem::take(self)
+        else {
+            return;
+        };
+
+        match recording.save(&path) {
+            Ok(()) => log::info!(
+                "Recorded {} updates of input to {}",
+                recording.frames.len(),
+                path.display()
+            ),
+            Err(e) => log::error!("{e}"),
+        }
+    }
+}
+
+/// Records the input of the update, or replaces it with the next frame of the recording.
+/// Runs before every other system of the update workload.
+pub fn replay_input_sys(
+    mut replay: UniqueViewMut<InputReplay>,
+    mut input_state: UniqueViewMut<InputState>,
+    mut camera: UniqueViewMut<Camera>,
+) {
+    match &mut *replay {
+        InputReplay::Off => {}
+        InputReplay::Recording { recording, .. } => {
+            let recording = recording.get_or_insert_with(|| InputRecording {
+                start: camera.eye.to_array(),
+                frames: Vec::new(),
+            });
+
+            let frame = InputFrame::capture(&input_state, camera.yaw, camera.pitch);
+            recording.frames.push(frame);
+        }
+        InputReplay::Playing { recording, next } => {
+            if *next == 0 {
+                camera.eye = glam::DVec3::from_array(recording.start);
+            }
+
+            match recording.frames.get(*next) {
+                Some(frame) => {
+                    frame.apply(&mut input_state, &mut camera);
+                    *next += 1;
+                }
+                None => {
+                    log::info!("Played back {} updates of input", recording.frames.len());
+                    input_state.stop_movement();
+                    input_state.breaking = false;
+                    *replay = InputReplay::Off;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_roundtrips_through_ron() {
+        let input_state = InputState {
+            cursor_captured: true,
+            forward: true,
+            break_block: true,
+            select_slot: Some(3),
+            scroll: -2,
+            ..Default::default()
+        };
+        let recording = InputRecording {
+            start: [0.5, -12.0, 1e9],
+            frames: vec![
+                InputFrame::capture(&input_state, 271.25, -89.0),
+                InputFrame::default(),
+            ],
+        };
+
+        let parsed: InputRecording = ron::from_str(&ron::to_string(&recording).unwrap()).unwrap();
+
+        assert_eq!(parsed, recording);
+    }
+}
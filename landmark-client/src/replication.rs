@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use landmark_protocol::{
+    message::ServerMessage,
+    replication::{ComponentData, ComponentKind, NetworkId, Replicated},
+};
+use shipyard::*;
+
+use crate::{
+    interpolation::push_snapshot, physics::EYE_HEIGHT, player::RemotePlayer, transform::Transform,
+};
+
+/// Model of the entities which are players of the server.
+const PLAYER_MODEL: &str = "player";
+
+/// Marks an entity mirrored from the server.
+// The ID is only shown in debug output so far
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, Component)]
+pub struct NetworkEntity(pub NetworkId);
+
+/// Movement of an entity in blocks per second.
+#[derive(Debug, Clone, Copy, Default, Component)]
+pub struct Velocity(pub glam::Vec3);
+
+/// Name of the model an entity is drawn with.
+#[derive(Debug, Clone, Component)]
+pub struct ModelId(pub String);
+
+/// Name shown above an entity.
+#[derive(Debug, Clone, Component)]
+pub struct Name(pub String);
+
+impl Replicated for Transform {
+    const KIND: ComponentKind = ComponentKind::Transform;
+
+    fn to_data(&self) -> ComponentData {
+        ComponentData::Transform {
+            translation: self.translation.to_array(),
+            rotation: self.rotation.to_array(),
+        }
+    }
+
+    fn from_data(data: &ComponentData) -> Option<Self> {
+        match data {
+            ComponentData::Transform {
+                translation,
+                rotation,
+            } => Some(Self {
+                translation: glam::Vec3::from_array(*translation),
+                rotation: glam::Quat::from_array(*rotation).normalize(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Replicated for Velocity {
+    const KIND: ComponentKind = ComponentKind::Velocity;
+
+    fn to_data(&self) -> ComponentData {
+        ComponentData::Velocity(self.0.to_array())
+    }
+
+    fn from_data(data: &ComponentData) -> Option<Self> {
+        match data {
+            ComponentData::Velocity(velocity) => Some(Self(glam::Vec3::from_array(*velocity))),
+            _ => None,
+        }
+    }
+}
+
+impl Replicated for ModelId {
+    const KIND: ComponentKind = ComponentKind::ModelId;
+
+    fn to_data(&self) -> ComponentData {
+        ComponentData::ModelId(self.0.clone())
+    }
+
+    fn from_data(data: &ComponentData) -> Option<Self> {
+        match data {
+            ComponentData::ModelId(model) => Some(Self(model.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl Replicated for Name {
+    const KIND: ComponentKind = ComponentKind::Name;
+
+    fn to_data(&self) -> ComponentData {
+        ComponentData::Name(self.0.clone())
+    }
+
+    fn from_data(data: &ComponentData) -> Option<Self> {
+        match data {
+            ComponentData::Name(name) => Some(Self(name.clone())),
+            _ => None,
+        }
+    }
+}
+
+type ApplyComponent = fn(&mut AllStorages, EntityId, &ComponentData);
+
+fn apply_component<T: Replicated + Component + Send + Sync>(
+    all_storages: &mut AllStorages,
+    entity: EntityId,
+    data: &ComponentData,
+) {
+    if let Some(component) = T::from_data(data) {
+        all_storages.add_component(entity, component);
+    }
+}
+
+/// Entities mirrored from the server, and the components they can have.
+#[derive(Debug, Unique)]
+pub struct Replication {
+    components: HashMap<ComponentKind, ApplyComponent>,
+    entities: HashMap<NetworkId, EntityId>,
+    /// Entity messages received since the last update, in the order they arrived.
+    pub received: Vec<ServerMessage>,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        let mut replication = Self::empty();
+
+        // drawn smoothly between the received transforms
+        replication.register_with::<Transform>(push_snapshot);
+        replication.register::<Velocity>();
+        replication.register::<ModelId>();
+        replication.register::<Name>();
+
+        replication
+    }
+}
+
+impl Replication {
+    fn empty() -> Self {
+        Self {
+            components: HashMap::new(),
+            entities: HashMap::new(),
+            received: Vec::new(),
+        }
+    }
+
+    /// Marks a component as networked, the server's state of it is added to mirrored entities.
+    pub fn register<T: Replicated + Component + Send + Sync>(&mut self) {
+        self.register_with::<T>(apply_component::<T>);
+    }
+
+    /// Marks a component as networked, its state is handled by `apply` instead of being added as is.
+    pub fn register_with<T: Replicated>(&mut self, apply: ApplyComponent) {
+        self.components.insert(T::KIND, apply);
+    }
+
+    fn apply(&mut self, all_storages: &mut AllStorages, message: ServerMessage) {
+        match message {
+            ServerMessage::SpawnEntity { id, components } => {
+                let entity = all_storages.add_entity(NetworkEntity(id));
+
+                if let Some(previous) = self.entities.insert(id, entity) {
+                    log::warn!("Server spawned entity {id} twice");
+                    all_storages.delete_entity(previous);
+                }
+
+                self.apply_components(all_storages, entity, &components);
+            }
+            ServerMessage::UpdateEntity { id, components } => {
+                let Some(&entity) = self.entities.get(&id) else {
+                    log::warn!("Server updated unknown entity {id}");
+                    return;
+                };
+
+                self.apply_components(all_storages, entity, &components);
+            }
+            ServerMessage::DespawnEntity(id) => {
+                if let Some(entity) = self.entities.remove(&id) {
+                    all_storages.delete_entity(entity);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_components(
+        &self,
+        all_storages: &mut AllStorages,
+        entity: EntityId,
+        components: &[ComponentData],
+    ) {
+        for data in components {
+            match self.components.get(&data.kind()) {
+                Some(apply) => apply(all_storages, entity, data),
+                None => log::warn!("Server sent unregistered component {:?}", data.kind()),
+            }
+        }
+    }
+}
+
+/// Spawns, updates and despawns the entities mirrored from the server.
+pub fn replication_sys(mut all_storages: AllStoragesViewMut) {
+    // taken out while applying, adding components needs exclusive access to all storages
+    let mut replication = {
+        let mut replication = all_storages.borrow::<UniqueViewMut<Replication>>().unwrap();
+
+        if replication.received.is_empty() {
+            return;
+        }
+
+        std::mem::replace(&mut *replication, Replication::empty())
+    };
+
+    for message in std::mem::take(&mut replication.received) {
+        replication.apply(&mut all_storages, message);
+    }
+
+    *all_storages.borrow::<UniqueViewMut<Replication>>().unwrap() = replication;
+}
+
+/// Keeps the players of mirrored entities where their transforms are drawn, so they get name tags
+/// and chat bubbles. The transform of a player is at its eyes.
+pub fn remote_players_sys(
+    model_ids: View<ModelId>,
+    names: View<Name>,
+    transforms: View<Transform>,
+    mut remote_players: ViewMut<RemotePlayer>,
+) {
+    for (id, (model_id, name, transform)) in (&model_ids, &names, &transforms).iter().with_id() {
+        if model_id.0 != PLAYER_MODEL {
+            continue;
+        }
+
+        let player = RemotePlayer {
+            name: name.0.clone(),
+            position: transform.translation.as_dvec3() - glam::DVec3::Y * EYE_HEIGHT,
+        };
+        remote_players.add_component_unchecked(id, player);
+    }
+}
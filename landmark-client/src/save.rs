@@ -0,0 +1,1051 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, MutexGuard, Once, TryLockError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use landmark_world::section;
+use shipyard::*;
+
+use crate::{
+    biome::Biome,
+    block::BlockData,
+    camera::Camera,
+    chunk_loader::ServerChunks,
+    game_map::{BlockId, Chunk, ChunkCoords, GameMap},
+    loader::ResourceDictionary,
+    region::RegionStore,
+};
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// Most chunks waiting for the IO thread, edits beyond it stay pending until the queue drains.
+const WRITE_QUEUE_SIZE: usize = 256;
+/// Most chunks the IO thread writes at once, each region file is synced once per batch.
+const WRITE_BATCH_SIZE: usize = 64;
+
+/// Save written by the panic hook, replaced whenever a world save is opened.
+static PANIC_SAVE: Mutex<Option<Arc<Shared>>> = Mutex::new(None);
+static INSTALL_PANIC_HOOK: Once = Once::new();
+
+/// State shared with the IO thread and the panic hook.
+#[derive(Debug)]
+struct Shared {
+    data: Mutex<SaveData>,
+    /// None if the world is never written to disk.
+    regions: Option<Mutex<RegionStore>>,
+    stats: Mutex<SaveStats>,
+}
+
+#[derive(Debug, Default)]
+struct SaveData {
+    /// Edited chunks which weren't handed to the IO thread yet.
+    pending: HashMap<ChunkCoords, Chunk>,
+    /// Snapshots of chunks waiting for the IO thread or being written, they are read from here
+    /// until the write finishes. The number identifies the snapshot, so a newer one isn't dropped
+    /// when an older one is written.
+    queued: HashMap<ChunkCoords, (u64, Chunk)>,
+    next_snapshot: u64,
+    /// Names of blocks by ID. Chunks are stored with names, so IDs may change between sessions.
+    block_names: Arc<HashMap<BlockId, String>>,
+}
+
+/// Work sent to the IO thread.
+#[derive(Debug)]
+enum IoRequest {
+    /// Writes the queued snapshot of a chunk.
+    Write(ChunkCoords),
+    /// Acknowledges once every earlier request is done.
+    Flush(mpsc::Sender<()>),
+}
+
+/// Health of the IO thread, shown with the profiler overlay.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SaveStats {
+    /// Chunks waiting for the IO thread.
+    pub queued: usize,
+    /// Times the queue was full and edits had to wait.
+    pub stalls: u64,
+    pub written: u64,
+    pub failed: u64,
+    /// Duration of the last batch of writes.
+    pub last_batch: Duration,
+}
+
+impl fmt::Display for SaveStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "save queue {}/{WRITE_QUEUE_SIZE}, {} stalls, {} written, {} failed, last batch {:.1} ms",
+            self.queued,
+            self.stalls,
+            self.written,
+            self.failed,
+            self.last_batch.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Chunks edited by the player, stored in region files. The rest of the world is generated again on every start.
+/// Edits are copied here as soon as they are made, so they survive unloading of their chunks.
+/// Region files are written by a dedicated IO thread, so saving never blocks the game loop.
+#[derive(Debug, Unique)]
+pub struct WorldSave {
+    shared: Arc<Shared>,
+    /// None if the world is never written to disk, or the IO thread couldn't be started.
+    requests: Option<mpsc::SyncSender<IoRequest>>,
+    /// Set when edits were made since the last save.
+    dirty: bool,
+    last_save: Instant,
+    /// Where the player respawns while not connected, stored in `spawn.ron` of the world directory
+    /// where the singleplayer server keeps it too.
+    spawn_point: glam::DVec3,
+    /// Where `/home` teleports to while not connected, stored in `home.ron` of the world directory.
+    home_point: Option<glam::DVec3>,
+    /// Directory the spawn and home points are written to, None if the world is never written to disk.
+    dir: Option<PathBuf>,
+    /// Where the map of the explored world is cached, None if the world is never written to disk.
+    map_dir: Option<PathBuf>,
+}
+
+impl WorldSave {
+    pub const DIR: &'static str = "world";
+    const SPAWN_FILE_NAME: &'static str = "spawn.ron";
+    const HOME_FILE_NAME: &'static str = "home.ron";
+    const MAP_DIR_NAME: &'static str = "map";
+
+    /// Opens the world stored in `dir`, region files are only read when their chunks are loaded.
+    /// Also makes sure the edits are written if the game panics.
+    pub fn open(dir: &Path) -> Self {
+        let mut save = Self::with_regions(Some(RegionStore::new(dir.to_path_buf())));
+        if let Some(spawn_point) = load_point(&dir.join(Self::SPAWN_FILE_NAME)) {
+            save.spawn_point = spawn_point;
+        }
+        save.home_point = load_point(&dir.join(Self::HOME_FILE_NAME));
+        save.dir = Some(dir.to_path_buf());
+        save.map_dir = Some(dir.join(Self::MAP_DIR_NAME));
+
+        let (requests, receiver) = mpsc::sync_channel(WRITE_QUEUE_SIZE);
+        let shared = save.shared.clone();
+
+        // the thread stops once the save is dropped together with the sender
+        let io_thread = thread::Builder::new()
+            .name("world-io".to_string())
+            .spawn(move || run_io_thread(&shared, receiver));
+
+        match io_thread {
+            Ok(_) => save.requests = Some(requests),
+            Err(e) => log::error!("Failed to start the world IO thread, edits won't be saved: {e}"),
+        }
+
+        *PANIC_SAVE.lock().unwrap_or_else(|e| e.into_inner()) = Some(save.shared.clone());
+        install_panic_hook();
+
+        save
+    }
+
+    /// Returns another handle to the same save, which shares its IO thread and unwritten edits.
+    /// The IO thread stops once every handle is dropped.
+    pub fn share(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            requests: self.requests.clone(),
+            dirty: false,
+            last_save: Instant::now(),
+            spawn_point: self.spawn_point,
+            home_point: self.home_point,
+            dir: self.dir.clone(),
+            map_dir: self.map_dir.clone(),
+        }
+    }
+
+    /// Creates a save which keeps edits only until the game exits.
+    pub fn in_memory() -> Self {
+        Self::with_regions(None)
+    }
+
+    fn with_regions(regions: Option<RegionStore>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                data: Mutex::default(),
+                regions: regions.map(Mutex::new),
+                stats: Mutex::default(),
+            }),
+            requests: None,
+            dirty: false,
+            last_save: Instant::now(),
+            spawn_point: Camera::SPAWN_POSITION,
+            home_point: None,
+            dir: None,
+            map_dir: None,
+        }
+    }
+
+    pub fn spawn_point(&self) -> glam::DVec3 {
+        self.spawn_point
+    }
+
+    /// Moves the point the player respawns at, and stores it with the world.
+    pub fn set_spawn_point(&mut self, position: glam::DVec3) -> io::Result<()> {
+        self.spawn_point = position;
+        self.write_point(Self::SPAWN_FILE_NAME, position)
+    }
+
+    /// Returns the point set with `/sethome`, None until the player sets one.
+    pub fn home_point(&self) -> Option<glam::DVec3> {
+        self.home_point
+    }
+
+    pub fn set_home_point(&mut self, position: glam::DVec3) -> io::Result<()> {
+        self.home_point = Some(position);
+        self.write_point(Self::HOME_FILE_NAME, position)
+    }
+
+    fn write_point(&self, file_name: &str, position: glam::DVec3) -> io::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        let content = ron::to_string(&position.to_array())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(file_name), content)
+    }
+
+    pub fn map_dir(&self) -> Option<&Path> {
+        self.map_dir.as_deref()
+    }
+
+    pub fn stats(&self) -> SaveStats {
+        *lock(&self.shared.stats)
+    }
+
+    /// Returns the saved state of a chunk, or None if it was never edited.
+    pub fn saved_chunk(
+        &self,
+        coords: ChunkCoords,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Option<Chunk> {
+        {
+            let data = lock(&self.shared.data);
+
+            let unwritten = data
+                .pending
+                .get(&coords)
+                .or(data.queued.get(&coords).map(|(_, chunk)| chunk));
+
+            if let Some(chunk) = unwritten {
+                return Some(chunk.clone());
+            }
+        }
+
+        let regions = self.shared.regions.as_ref()?;
+
+        let result = lock(regions).read(coords).and_then(|bytes| {
+            bytes
+                .map(|bytes| decode_chunk(&bytes, resource_dictionary))
+                .transpose()
+        });
+
+        match result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                log::error!("Failed to read saved chunk {coords:?}, generating it again: {e}");
+                None
+            }
+        }
+    }
+
+    /// Copies chunks edited since the last call into the save.
+    pub fn record_edits(
+        &mut self,
+        game_map: &mut GameMap,
+        resource_dictionary: &ResourceDictionary,
+    ) {
+        if game_map.edited.is_empty() {
+            return;
+        }
+
+        let mut data = lock(&self.shared.data);
+
+        for coords in game_map.edited.drain() {
+            if let Some(chunk) = game_map.chunks.get(&coords) {
+                data.pending.insert(coords, chunk.clone());
+            }
+        }
+
+        data.block_names = Arc::new(block_names(resource_dictionary));
+        self.dirty = true;
+    }
+
+    /// Hands pending edits to the IO thread without waiting, edits not fitting into its queue stay pending.
+    pub fn save_in_background(&mut self) {
+        if self.queue_pending(false) {
+            self.dirty = false;
+        }
+
+        self.last_save = Instant::now();
+    }
+
+    /// Writes edited chunks and waits until they are on disk.
+    pub fn save_now(&mut self) {
+        let Some(requests) = &self.requests else {
+            return;
+        };
+
+        self.queue_pending(true);
+
+        let (done, finished) = mpsc::channel();
+        if requests.send(IoRequest::Flush(done)).is_ok() {
+            let _ = finished.recv();
+        }
+
+        self.dirty = false;
+        self.last_save = Instant::now();
+    }
+
+    /// Moves pending chunks to the queue of the IO thread, returns false if some of them didn't fit.
+    /// With `wait` set it blocks until there is space instead.
+    fn queue_pending(&self, wait: bool) -> bool {
+        let Some(requests) = &self.requests else {
+            return false;
+        };
+
+        let mut data = lock(&self.shared.data);
+        let pending: Vec<ChunkCoords> = data.pending.keys().copied().collect();
+        let mut stalled = false;
+
+        for coords in pending {
+            let Some(chunk) = data.pending.remove(&coords) else {
+                continue;
+            };
+
+            // a snapshot of the chunk already waits in the queue, it's replaced by the newer one,
+            // which the IO thread writes again if it's writing the older one right now
+            let snapshot = data.next_snapshot;
+            data.next_snapshot += 1;
+            if data.queued.insert(coords, (snapshot, chunk)).is_some() {
+                continue;
+            }
+
+            // queued before sending, the IO thread may take the request right away
+            lock(&self.shared.stats).queued += 1;
+
+            let request = IoRequest::Write(coords);
+            let result = if wait {
+                // the IO thread needs the data lock to finish writes, so it's released while waiting
+                drop(data);
+                let result = requests
+                    .send(request)
+                    .map_err(|e| mpsc::TrySendError::Disconnected(e.0));
+                data = lock(&self.shared.data);
+                result
+            } else {
+                requests.try_send(request)
+            };
+
+            if let Err(e) = result {
+                lock(&self.shared.stats).queued -= 1;
+
+                // nobody writes the snapshot, so the chunk is pending again
+                if let Some((_, chunk)) = data.queued.remove(&coords) {
+                    data.pending.entry(coords).or_insert(chunk);
+                }
+
+                if let mpsc::TrySendError::Disconnected(_) = e {
+                    log::error!("The world IO thread stopped, edits won't be saved");
+                    return false;
+                }
+
+                stalled = true;
+                break;
+            }
+        }
+
+        if stalled {
+            lock(&self.shared.stats).stalls += 1;
+            log::warn!("The world IO thread can't keep up, some edits are saved later");
+        }
+
+        !stalled
+    }
+}
+
+/// Copies edits into the save and hands them to the IO thread periodically.
+/// Runs before chunks are unloaded, so edits in chunks leaving the view distance are kept.
+/// While connected, the chunks are saved by the server which sent them.
+pub fn autosave_sys(
+    mut game_map: UniqueViewMut<GameMap>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    mut world_save: UniqueViewMut<WorldSave>,
+    server_chunks: UniqueView<ServerChunks>,
+) {
+    if server_chunks.connected {
+        game_map.edited.clear();
+    }
+
+    world_save.record_edits(&mut game_map, &resource_dictionary);
+
+    if world_save.dirty && world_save.last_save.elapsed() >= AUTOSAVE_INTERVAL {
+        world_save.save_in_background();
+    }
+}
+
+/// Reads a position stored as RON, None if the file is missing or invalid.
+fn load_point(path: &Path) -> Option<glam::DVec3> {
+    match fs::read_to_string(path) {
+        Ok(content) => match ron::from_str::<[f64; 3]>(&content) {
+            Ok(position) => Some(glam::DVec3::from_array(position)),
+            Err(e) => {
+                log::error!("Failed to parse {}: {e}", path.display());
+                None
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::error!("Failed to read {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Locks shared state, it stays consistent even if a thread panicked while holding the lock.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Like `lock`, but returns None instead of waiting, a panicking thread may be holding the lock.
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Writes queued chunks in batches until the save is dropped.
+fn run_io_thread(shared: &Shared, receiver: mpsc::Receiver<IoRequest>) {
+    // chunks edited again while they were written, their newer snapshots go into the next batch
+    let mut rewrites = Vec::new();
+
+    loop {
+        let first = if rewrites.is_empty() {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return,
+            }
+        } else {
+            None
+        };
+
+        let mut batch = std::mem::take(&mut rewrites);
+        let mut received = 0;
+        let mut flushes = Vec::new();
+
+        for request in first.into_iter().chain(receiver.try_iter()) {
+            match request {
+                IoRequest::Write(coords) => {
+                    batch.push(coords);
+                    received += 1;
+                }
+                IoRequest::Flush(done) => flushes.push(done),
+            }
+
+            // requests after a flush are handled with the next batch
+            if batch.len() >= WRITE_BATCH_SIZE || !flushes.is_empty() {
+                break;
+            }
+        }
+
+        lock(&shared.stats).queued -= received;
+
+        if !batch.is_empty() {
+            rewrites = write_batch(shared, &batch);
+        }
+
+        // a flush also waits for the newest snapshots
+        while !flushes.is_empty() && !rewrites.is_empty() {
+            rewrites = write_batch(shared, &rewrites);
+        }
+
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// Writes the queued snapshots of chunks to their region files.
+/// If writing fails they are pending again, unless they were edited in the meantime.
+/// Returns the chunks whose snapshot was replaced while they were written, they need to be written again.
+fn write_batch(shared: &Shared, batch: &[ChunkCoords]) -> Vec<ChunkCoords> {
+    let Some(regions) = &shared.regions else {
+        return Vec::new();
+    };
+
+    let (snapshots, block_names) = {
+        let data = lock(&shared.data);
+        let snapshots: Vec<(ChunkCoords, u64, Chunk)> = batch
+            .iter()
+            .filter_map(|coords| {
+                let (snapshot, chunk) = data.queued.get(coords)?;
+                Some((*coords, *snapshot, chunk.clone()))
+            })
+            .collect();
+
+        (snapshots, data.block_names.clone())
+    };
+
+    let start = Instant::now();
+    let result = lock(regions).write(
+        snapshots
+            .iter()
+            .map(|(coords, _, chunk)| (*coords, encode_chunk(chunk, &block_names))),
+    );
+
+    let mut data = lock(&shared.data);
+    let mut rewrites = Vec::new();
+
+    for (coords, snapshot, chunk) in snapshots.iter() {
+        match data.queued.get(coords) {
+            Some((queued, _)) if queued == snapshot => {
+                data.queued.remove(coords);
+
+                if result.is_err() {
+                    data.pending.entry(*coords).or_insert_with(|| chunk.clone());
+                }
+            }
+            // no request is sent for a replaced snapshot, the chunk still waits in the queue
+            Some(_) => rewrites.push(*coords),
+            None => {}
+        }
+    }
+
+    let mut stats = lock(&shared.stats);
+    stats.last_batch = start.elapsed();
+
+    match result {
+        Ok(()) => stats.written += snapshots.len() as u64,
+        Err(e) => {
+            stats.failed += snapshots.len() as u64;
+            log::error!("Failed to save {} chunks: {e}", snapshots.len());
+        }
+    }
+
+    rewrites
+}
+
+fn install_panic_hook() {
+    INSTALL_PANIC_HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+
+        std::panic::set_hook(Box::new(move |info| {
+            if let Some(shared) = try_lock(&PANIC_SAVE).and_then(|save| save.clone()) {
+                write_on_panic(&shared);
+            }
+
+            previous(info);
+        }));
+    });
+}
+
+/// Writes all unsaved chunks without waiting for locks.
+fn write_on_panic(shared: &Shared) {
+    let Some(mut regions) = shared.regions.as_ref().and_then(try_lock) else {
+        return;
+    };
+    let Some(data) = try_lock(&shared.data) else {
+        return;
+    };
+
+    // pending chunks are newer than the queued ones
+    let chunks: HashMap<&ChunkCoords, &Chunk> = data
+        .queued
+        .iter()
+        .map(|(coords, (_, chunk))| (coords, chunk))
+        .chain(data.pending.iter())
+        .collect();
+
+    if chunks.is_empty() {
+        return;
+    }
+
+    let result = regions.write(
+        chunks
+            .iter()
+            .map(|(coords, chunk)| (**coords, encode_chunk(chunk, &data.block_names))),
+    );
+
+    match result {
+        Ok(()) => eprintln!("Saved {} edited chunks before crashing", chunks.len()),
+        Err(e) => eprintln!("Failed to save the world: {e}"),
+    }
+}
+
+fn block_names(resource_dictionary: &ResourceDictionary) -> HashMap<BlockId, String> {
+    resource_dictionary
+        .block_ids()
+        .iter()
+        .map(|(name, id)| (*id, name.clone()))
+        .collect()
+}
+
+/// Serializes a chunk as runs of equal blocks in the current format version.
+///
+/// Layout, all numbers are little endian:
+/// - palette: count as u16, then each block name as u16 length and UTF-8 bytes
+/// - run count as u32, then the runs as u16 length and u16 palette index plus one, zero meaning air
+///
+/// - optional sections with the biomes, light and state of the chunk, see `landmark_world::section`
+///
+/// A chunk has 32768 blocks, so neither runs nor the palette can get longer than a u16.
+pub fn encode_chunk(chunk: &Chunk, block_names: &HashMap<BlockId, String>) -> Vec<u8> {
+    let mut palette: Vec<&str> = Vec::new();
+    let mut palette_indices: HashMap<&str, u16> = HashMap::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+
+    for (idx, block) in chunk.blocks().enumerate() {
+        // placeholders are saved with the name of the block they stand in for, blocks without a name as air
+        let value = block
+            .and_then(|id| {
+                let name = chunk
+                    .unknown_block(idx)
+                    .or_else(|| block_names.get(&id).map(String::as_str))?;
+
+                Some(*palette_indices.entry(name).or_insert_with(|| {
+                    palette.push(name);
+                    palette.len() as u16
+                }))
+            })
+            .unwrap_or(0);
+
+        match runs.last_mut() {
+            Some((length, last)) if *last == value => *length += 1,
+            _ => runs.push((1, value)),
+        }
+    }
+
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for name in palette {
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (length, value) in runs {
+        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    if let Some(biomes) = chunk.biomes() {
+        write_section(
+            &mut bytes,
+            section::BIOMES,
+            section::BIOMES_VERSION,
+            &encode_biomes(biomes),
+        );
+    }
+
+    if let Some(light) = chunk.light() {
+        let packed: Vec<u8> = light
+            .chunks_exact(2)
+            .map(|pair| pair[0] & 0x0f | pair[1] << 4)
+            .collect();
+
+        write_section(&mut bytes, section::LIGHT, section::LIGHT_VERSION, &packed);
+    }
+
+    let mut state = 0;
+    if chunk.is_modified() {
+        state |= section::STATE_MODIFIED;
+    }
+    write_section(&mut bytes, section::STATE, section::STATE_VERSION, &[state]);
+
+    bytes
+}
+
+fn write_section(bytes: &mut Vec<u8>, kind: u8, version: u8, content: &[u8]) {
+    bytes.push(kind);
+    bytes.push(version);
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+/// Biomes are stored by name, so adding biomes doesn't change the meaning of saved ones:
+/// the palette count as u8, each name as u8 length and UTF-8 bytes, then a palette index per column.
+fn encode_biomes(biomes: &[Biome]) -> Vec<u8> {
+    let mut palette: Vec<Biome> = Vec::new();
+    let indices: Vec<u8> = biomes
+        .iter()
+        .map(|biome| {
+            let idx = palette
+                .iter()
+                .position(|known| known == biome)
+                .unwrap_or_else(|| {
+                    palette.push(*biome);
+                    palette.len() - 1
+                });
+
+            idx as u8
+        })
+        .collect();
+
+    let mut bytes = vec![palette.len() as u8];
+    for biome in palette {
+        bytes.push(biome.name().len() as u8);
+        bytes.extend_from_slice(biome.name().as_bytes());
+    }
+    bytes.extend_from_slice(&indices);
+
+    bytes
+}
+
+fn decode_biomes(bytes: &[u8]) -> io::Result<Vec<Biome>> {
+    let mut reader = Reader { bytes };
+
+    let palette_size = reader.u8()?;
+    let mut palette = Vec::with_capacity(palette_size as usize);
+
+    for _ in 0..palette_size {
+        let length = reader.u8()? as usize;
+        let name = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| invalid_data("biome name is not valid UTF-8"))?;
+
+        palette.push(
+            Biome::from_name(name).ok_or_else(|| invalid_data(&format!("unknown biome {name}")))?,
+        );
+    }
+
+    reader
+        .take(Chunk::COLUMNS_COUNT as usize)?
+        .iter()
+        .map(|idx| {
+            palette
+                .get(*idx as usize)
+                .copied()
+                .ok_or_else(|| invalid_data("biome is not in the palette"))
+        })
+        .collect()
+}
+
+fn decode_light(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    if bytes.len() != Chunk::BLOCKS_COUNT as usize / 2 {
+        return Err(invalid_data("light has a wrong size"));
+    }
+
+    Ok(bytes
+        .iter()
+        .flat_map(|pair| [pair & 0x0f, pair >> 4])
+        .collect())
+}
+
+/// Reads values from the bytes of a saved chunk.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            return Err(invalid_data("unexpected end of chunk"));
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Deserializes a chunk, mapping saved block names to the IDs of the loaded blocks.
+fn decode_chunk(bytes: &[u8], resource_dictionary: &ResourceDictionary) -> io::Result<Chunk> {
+    let mut reader = Reader { bytes };
+
+    let palette_size = reader.u16()?;
+    let mut palette = vec![None];
+    // names of the palette entries which aren't defined, indexed like the palette
+    let mut unknown_names: Vec<Option<Arc<str>>> = vec![None];
+
+    for _ in 0..palette_size {
+        let length = reader.u16()? as usize;
+        let name = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| invalid_data("block name is not valid UTF-8"))?;
+
+        // the chunk remembers the name and saves it again, so the block comes back once it's defined again
+        let id = match resource_dictionary.find_block_id(name) {
+            Some(id) => {
+                unknown_names.push(None);
+                id
+            }
+            None => {
+                log::warn!("Saved block {name} is not defined, replacing it with a placeholder");
+                unknown_names.push(Some(name.into()));
+                resource_dictionary.get_block_id(BlockData::MISSING)
+            }
+        };
+
+        palette.push(Some(id));
+    }
+
+    let run_count = reader.u32()?;
+    let mut blocks = Vec::with_capacity(Chunk::BLOCKS_COUNT as usize);
+    let mut unknown_blocks = Vec::new();
+
+    for _ in 0..run_count {
+        let length = reader.u16()? as usize;
+        let value = reader.u16()? as usize;
+        let block = *palette
+            .get(value)
+            .ok_or_else(|| invalid_data("block is not in the palette"))?;
+
+        if blocks.len() + length > Chunk::BLOCKS_COUNT as usize {
+            return Err(invalid_data("chunk has too many blocks"));
+        }
+
+        if let Some(name) = &unknown_names[value] {
+            unknown_blocks.push((blocks.len()..blocks.len() + length, name.clone()));
+        }
+
+        blocks.resize(blocks.len() + length, block);
+    }
+
+    let mut chunk =
+        Chunk::from_blocks(blocks).ok_or_else(|| invalid_data("chunk has too few blocks"))?;
+    chunk.set_modified(true);
+
+    for (indices, name) in unknown_blocks {
+        for idx in indices {
+            chunk.set_unknown_block(idx, name.clone());
+        }
+    }
+
+    // sections which can't be read are left out, their data is computed again when the chunk is loaded
+    while !reader.is_empty() {
+        let kind = reader.u8()?;
+        let version = reader.u8()?;
+        let length = reader.u32()? as usize;
+        let content = reader.take(length)?;
+
+        let result = match (kind, version) {
+            (section::BIOMES, section::BIOMES_VERSION) => {
+                decode_biomes(content).map(|biomes| chunk.set_biomes(biomes))
+            }
+            (section::LIGHT, section::LIGHT_VERSION) => {
+                decode_light(content).map(|light| chunk.set_light(light))
+            }
+            (section::STATE, section::STATE_VERSION) => Ok(content.first().map(|state| {
+                chunk.set_modified(state & section::STATE_MODIFIED != 0);
+            })),
+            _ => {
+                log::debug!("Skipping unknown chunk section {kind} version {version}");
+                Ok(Some(()))
+            }
+        };
+
+        match result {
+            Ok(Some(())) => {}
+            Ok(None) => log::warn!("Chunk section {kind} has a wrong size, computing it again"),
+            Err(e) => log::warn!("Failed to read chunk section {kind}, computing it again: {e}"),
+        }
+    }
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_map::InnerChunkCoords;
+
+    use super::*;
+
+    /// Edits a block of the chunk at the origin and copies the chunk into the save.
+    fn edit(
+        world_save: &mut WorldSave,
+        game_map: &mut GameMap,
+        resource_dictionary: &ResourceDictionary,
+        x: i32,
+    ) {
+        let coords = ChunkCoords::new(0, 0, 0);
+        let glass = resource_dictionary.get_block_id("Glass");
+
+        game_map
+            .chunks
+            .entry(coords)
+            .or_insert_with(Chunk::new)
+            .set_block(InnerChunkCoords::new(x, 0, 0), Some(glass));
+        game_map.edited.insert(coords);
+
+        world_save.record_edits(game_map, resource_dictionary);
+    }
+
+    #[test]
+    fn edits_made_while_writing_are_written_too() {
+        let dir = tempfile::tempdir().unwrap();
+        let resource_dictionary = ResourceDictionary::builtin(Vec::new()).unwrap();
+        let mut game_map = GameMap::new();
+        let mut world_save = WorldSave::open(dir.path());
+        let shared = world_save.shared.clone();
+        let regions = shared.regions.as_ref().unwrap();
+
+        edit(&mut world_save, &mut game_map, &resource_dictionary, 0);
+
+        {
+            // the IO thread takes the snapshot and waits for the region files
+            let _writing = lock(regions);
+            world_save.save_in_background();
+
+            while world_save.stats().queued > 0 {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(50));
+
+            // replaces the snapshot being written, no new request is sent for it
+            edit(&mut world_save, &mut game_map, &resource_dictionary, 1);
+            world_save.save_in_background();
+        }
+
+        world_save.save_now();
+
+        let bytes = RegionStore::new(dir.path().to_path_buf())
+            .read(ChunkCoords::new(0, 0, 0))
+            .unwrap()
+            .expect("the chunk was written");
+        let saved = decode_chunk(&bytes, &resource_dictionary).unwrap();
+
+        assert_eq!(
+            saved.blocks().collect::<Vec<_>>(),
+            game_map.chunks[&ChunkCoords::new(0, 0, 0)]
+                .blocks()
+                .collect::<Vec<_>>()
+        );
+        assert!(lock(&world_save.shared.data).queued.is_empty());
+    }
+
+    #[test]
+    fn shared_handles_see_and_write_each_others_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let resource_dictionary = ResourceDictionary::builtin(Vec::new()).unwrap();
+        let mut game_map = GameMap::new();
+        let mut world_save = WorldSave::open(dir.path());
+        let mut shared = world_save.share();
+        let coords = ChunkCoords::new(0, 0, 0);
+
+        edit(&mut shared, &mut game_map, &resource_dictionary, 0);
+        assert!(world_save
+            .saved_chunk(coords, &resource_dictionary)
+            .is_some());
+
+        // the IO thread keeps running for the remaining handle
+        shared.save_now();
+        drop(shared);
+        edit(&mut world_save, &mut game_map, &resource_dictionary, 1);
+        world_save.save_now();
+
+        let bytes = RegionStore::new(dir.path().to_path_buf())
+            .read(coords)
+            .unwrap()
+            .expect("the chunk was written");
+        let saved = decode_chunk(&bytes, &resource_dictionary).unwrap();
+
+        assert_eq!(
+            saved.blocks().collect::<Vec<_>>(),
+            game_map.chunks[&coords].blocks().collect::<Vec<_>>()
+        );
+    }
+
+    /// ID no loaded block has, standing for a block of a pack which was removed.
+    const REMOVED: BlockId = 9999;
+
+    fn chunk_with_removed_block(resource_dictionary: &ResourceDictionary) -> Chunk {
+        let glass = resource_dictionary.get_block_id("Glass");
+        let mut chunk = Chunk::new();
+
+        for x in 0..Chunk::SIZE {
+            chunk.set_block(InnerChunkCoords::new(x, 0, 0), Some(glass));
+            chunk.set_block(InnerChunkCoords::new(x, 1, 0), Some(REMOVED));
+        }
+        chunk.set_modified(true);
+
+        chunk
+    }
+
+    fn names_with_removed_block(
+        resource_dictionary: &ResourceDictionary,
+    ) -> HashMap<BlockId, String> {
+        let mut names = block_names(resource_dictionary);
+        names.insert(REMOVED, "Ruby".to_string());
+
+        names
+    }
+
+    #[test]
+    fn unknown_blocks_are_saved_again() {
+        let resource_dictionary = ResourceDictionary::builtin(Vec::new()).unwrap();
+        let saved = encode_chunk(
+            &chunk_with_removed_block(&resource_dictionary),
+            &names_with_removed_block(&resource_dictionary),
+        );
+
+        let chunk = decode_chunk(&saved, &resource_dictionary).unwrap();
+        let missing = resource_dictionary.get_block_id(BlockData::MISSING);
+
+        assert_eq!(
+            chunk.get_block(InnerChunkCoords::new(0, 1, 0)),
+            Some(missing)
+        );
+        assert_eq!(
+            encode_chunk(&chunk, &block_names(&resource_dictionary)),
+            saved
+        );
+    }
+
+    #[test]
+    fn replaced_unknown_blocks_are_forgotten() {
+        let resource_dictionary = ResourceDictionary::builtin(Vec::new()).unwrap();
+        let saved = encode_chunk(
+            &chunk_with_removed_block(&resource_dictionary),
+            &names_with_removed_block(&resource_dictionary),
+        );
+
+        let mut chunk = decode_chunk(&saved, &resource_dictionary).unwrap();
+        let glass = resource_dictionary.get_block_id("Glass");
+        let missing = resource_dictionary.get_block_id(BlockData::MISSING);
+
+        chunk.set_block(InnerChunkCoords::new(0, 1, 0), None);
+        // a placeholder placed again doesn't bring the name back
+        chunk.set_block(InnerChunkCoords::new(1, 1, 0), Some(missing));
+        for x in 2..Chunk::SIZE {
+            chunk.set_block(InnerChunkCoords::new(x, 1, 0), Some(glass));
+        }
+
+        let resaved = encode_chunk(&chunk, &block_names(&resource_dictionary));
+        let chunk = decode_chunk(&resaved, &resource_dictionary).unwrap();
+
+        assert!((0..Chunk::BLOCKS_COUNT as usize).all(|idx| chunk.unknown_block(idx).is_none()));
+        assert!(!resaved.windows(4).any(|window| window == b"Ruby"));
+    }
+}
@@ -0,0 +1,443 @@
+//! Boxes of blocks which can be saved to files and stamped into the world, turned and mirrored.
+//!
+//! The world editor copies selections into schematics and the world generator places structures
+//! loaded from `.vox` models and `.schematic` files as schematics.
+
+use std::{fs, io, path::Path};
+
+use shipyard::*;
+
+use crate::{
+    block::BlockData,
+    coords,
+    edit_history::{apply_changes, Transaction},
+    game_map::{BlockId, Chunk, ChunkCoords, GameMap},
+    loader::{ResourceDictionary, ResourceError},
+    model::MissingModel,
+    world_edit::Selection,
+};
+
+const MAGIC: &[u8; 4] = b"LMSC";
+const VERSION: u8 = 1;
+/// Palette index of positions where the block of the world is left as it is.
+const KEEP: u16 = 0;
+
+/// How a schematic is turned around the Y axis and mirrored when it's stamped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Orientation {
+    /// Clockwise turns by 90 degrees, seen from above.
+    pub quarter_turns: u8,
+    /// Mirrors the X axis before turning.
+    pub mirrored: bool,
+}
+
+impl Orientation {
+    /// Parses arguments of console commands: an angle of 90, 180 or 270 degrees and `mirror`.
+    pub fn from_args(args: &[&str]) -> Result<Self, String> {
+        let mut orientation = Self::default();
+
+        for arg in args {
+            match *arg {
+                "0" => orientation.quarter_turns = 0,
+                "90" => orientation.quarter_turns = 1,
+                "180" => orientation.quarter_turns = 2,
+                "270" => orientation.quarter_turns = 3,
+                "mirror" => orientation.mirrored = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown orientation {arg}, expected 90, 180, 270 or mirror"
+                    ))
+                }
+            }
+        }
+
+        Ok(orientation)
+    }
+
+    /// Turns a vector around the origin.
+    pub fn turn(self, vector: glam::IVec3) -> glam::IVec3 {
+        let mut vector = vector;
+
+        if self.mirrored {
+            vector.x = -vector.x;
+        }
+
+        // north turns to east
+        for _ in 0..self.quarter_turns % 4 {
+            vector = glam::IVec3::new(vector.z, vector.y, -vector.x);
+        }
+
+        vector
+    }
+
+    /// Returns the size of a box after turning it.
+    pub fn turn_size(self, size: glam::IVec3) -> glam::IVec3 {
+        self.turn(size).abs()
+    }
+
+    /// Returns where a block of a box ends up when the box is turned in place,
+    /// keeping its minimum corner.
+    pub fn apply(self, offset: glam::IVec3, size: glam::IVec3) -> glam::IVec3 {
+        self.turn(offset) - self.turn(size - glam::IVec3::ONE).min(glam::IVec3::ZERO)
+    }
+}
+
+/// Box of blocks, positions can also leave the world as it is so a structure doesn't cut a hole
+/// into the terrain around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schematic {
+    size: glam::IVec3,
+    /// Blocks used by the schematic, None is air.
+    palette: Vec<Option<BlockId>>,
+    /// Palette index plus one of every position, ordered like `Selection::positions`,
+    /// `KEEP` where the world is left as it is.
+    indices: Vec<u16>,
+}
+
+impl Schematic {
+    /// Creates a schematic of `size` from blocks at offsets from its minimum corner,
+    /// other positions leave the world as it is.
+    pub fn from_blocks(
+        size: glam::IVec3,
+        blocks: impl IntoIterator<Item = (glam::IVec3, Option<BlockId>)>,
+    ) -> Self {
+        let size = size.max(glam::IVec3::ZERO);
+        let mut schematic = Self {
+            size,
+            palette: Vec::new(),
+            indices: vec![KEEP; (size.x * size.y * size.z) as usize],
+        };
+
+        for (offset, block) in blocks {
+            if let Some(idx) = schematic.index_of(offset) {
+                schematic.indices[idx] = schematic.palette_index(block);
+            }
+        }
+
+        schematic
+    }
+
+    /// Copies every block of a selection, blocks of chunks which aren't loaded are copied as air.
+    pub fn from_selection(game_map: &GameMap, selection: &Selection) -> Self {
+        Self::from_blocks(
+            selection.size(),
+            selection
+                .positions()
+                .map(|position| (position - selection.min, game_map.get_block_world(position))),
+        )
+    }
+
+    pub fn size(&self) -> glam::IVec3 {
+        self.size
+    }
+
+    fn index_of(&self, offset: glam::IVec3) -> Option<usize> {
+        if offset.cmplt(glam::IVec3::ZERO).any() || offset.cmpge(self.size).any() {
+            return None;
+        }
+
+        Some((offset.x + offset.z * self.size.x + offset.y * self.size.x * self.size.z) as usize)
+    }
+
+    fn palette_index(&mut self, block: Option<BlockId>) -> u16 {
+        let idx = self
+            .palette
+            .iter()
+            .position(|known| *known == block)
+            .unwrap_or_else(|| {
+                self.palette.push(block);
+                self.palette.len() - 1
+            });
+
+        idx as u16 + 1
+    }
+
+    /// Returns the blocks which aren't kept with their offsets from the minimum corner of the
+    /// turned schematic.
+    pub fn blocks(
+        &self,
+        orientation: Orientation,
+    ) -> impl Iterator<Item = (glam::IVec3, Option<BlockId>)> + '_ {
+        Selection::new(glam::IVec3::ZERO, self.size - glam::IVec3::ONE)
+            .positions()
+            .zip(self.indices.iter())
+            .filter(|(_, idx)| **idx != KEEP)
+            .map(move |(offset, idx)| {
+                (
+                    orientation.apply(offset, self.size),
+                    self.palette[*idx as usize - 1],
+                )
+            })
+    }
+
+    /// Places the part of the schematic inside of a chunk, with its minimum corner at `origin`.
+    pub fn place_in_chunk(
+        &self,
+        chunk: &mut Chunk,
+        coords: ChunkCoords,
+        origin: glam::IVec3,
+        orientation: Orientation,
+    ) {
+        let min = origin - coords::chunk_origin(coords);
+        let max = min + orientation.turn_size(self.size);
+
+        if max.cmple(glam::IVec3::ZERO).any() || min.cmpge(glam::IVec3::splat(Chunk::SIZE)).any() {
+            return;
+        }
+
+        for (offset, block) in self.blocks(orientation) {
+            if let Some(inner) = coords::inner_from_offset(min + offset) {
+                chunk.set_block(inner, block);
+            }
+        }
+    }
+
+    /// Sets the blocks of loaded chunks with the minimum corner of the schematic at `origin`
+    /// and returns the changes, so they can be recorded in the edit history.
+    pub fn stamp(
+        &self,
+        game_map: &mut GameMap,
+        missing_models: &mut ViewMut<MissingModel>,
+        origin: glam::IVec3,
+        orientation: Orientation,
+    ) -> Transaction {
+        let changes = self
+            .blocks(orientation)
+            .map(|(offset, block)| (origin + offset, block));
+
+        apply_changes(game_map, missing_models, changes)
+    }
+
+    /// Serializes the schematic with blocks stored by name, so it stays valid when block IDs change.
+    ///
+    /// Layout, all numbers are little endian:
+    /// - magic `LMSC` and the format version as u8
+    /// - size as three u16
+    /// - palette: count as u16, then each block name as u16 length and UTF-8 bytes, empty for air
+    /// - run count as u32, then the runs as u32 length and u16 palette index plus one, zero
+    ///   meaning the world is kept
+    pub fn encode(&self, block_name: impl Fn(BlockId) -> String) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        for axis in self.size.to_array() {
+            bytes.extend_from_slice(&(axis as u16).to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.palette.len() as u16).to_le_bytes());
+        for block in &self.palette {
+            let name = block.map(&block_name).unwrap_or_default();
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        let mut runs: Vec<(u32, u16)> = Vec::new();
+        for idx in &self.indices {
+            match runs.last_mut() {
+                Some((length, last)) if last == idx => *length += 1,
+                _ => runs.push((1, *idx)),
+            }
+        }
+
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (length, idx) in runs {
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&idx.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// Deserializes a schematic, mapping block names to the IDs of the loaded blocks.
+    pub fn decode(bytes: &[u8], block_id: impl Fn(&str) -> BlockId) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != MAGIC {
+            return Err("not a schematic file".to_string());
+        }
+
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(format!("unsupported schematic version {version}"));
+        }
+
+        let size = glam::IVec3::new(
+            reader.u16()? as i32,
+            reader.u16()? as i32,
+            reader.u16()? as i32,
+        );
+
+        let palette_size = reader.u16()?;
+        let mut palette = Vec::with_capacity(palette_size as usize);
+        for _ in 0..palette_size {
+            let length = reader.u16()? as usize;
+            let name = std::str::from_utf8(reader.take(length)?)
+                .map_err(|_| "block name is not valid UTF-8".to_string())?;
+
+            palette.push((!name.is_empty()).then(|| block_id(name)));
+        }
+
+        let volume = (size.x * size.y * size.z) as usize;
+        let run_count = reader.u32()?;
+        let mut indices = Vec::with_capacity(volume);
+
+        for _ in 0..run_count {
+            let length = reader.u32()? as usize;
+            let idx = reader.u16()?;
+
+            if idx as usize > palette.len() {
+                return Err("block is not in the palette".to_string());
+            }
+            if indices.len() + length > volume {
+                return Err("schematic has too many blocks".to_string());
+            }
+
+            indices.resize(indices.len() + length, idx);
+        }
+
+        if indices.len() != volume {
+            return Err("schematic has too few blocks".to_string());
+        }
+
+        Ok(Self {
+            size,
+            palette,
+            indices,
+        })
+    }
+
+    pub fn save(&self, path: &Path, resource_dictionary: &ResourceDictionary) -> io::Result<()> {
+        let bytes = self.encode(|block| {
+            resource_dictionary
+                .get_block_data_from_id(block)
+                .name
+                .clone()
+        });
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, bytes)
+    }
+
+    /// Loads a schematic file, blocks which aren't defined are replaced by a placeholder.
+    pub fn load(
+        path: &Path,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Result<Self, ResourceError> {
+        let bytes = fs::read(path).map_err(|source| ResourceError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::decode(&bytes, |name| {
+            resource_dictionary.find_block_id(name).unwrap_or_else(|| {
+                log::warn!("Block {name} of {} is not defined", path.display());
+                resource_dictionary.get_block_id(BlockData::MISSING)
+            })
+        })
+        .map_err(|reason| ResourceError::Invalid {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < count {
+            return Err("unexpected end of the file".to_string());
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turned_blocks_stay_inside_of_the_turned_box() {
+        let size = glam::IVec3::new(3, 1, 2);
+
+        for quarter_turns in 0..4 {
+            for mirrored in [false, true] {
+                let orientation = Orientation {
+                    quarter_turns,
+                    mirrored,
+                };
+                let turned_size = orientation.turn_size(size);
+                let mut seen = std::collections::HashSet::new();
+
+                for offset in Selection::new(glam::IVec3::ZERO, size - glam::IVec3::ONE).positions()
+                {
+                    let turned = orientation.apply(offset, size);
+
+                    assert!(turned.cmpge(glam::IVec3::ZERO).all());
+                    assert!(turned.cmplt(turned_size).all());
+                    assert!(seen.insert(turned));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quarter_turn_moves_north_to_east() {
+        let orientation = Orientation {
+            quarter_turns: 1,
+            mirrored: false,
+        };
+        let size = glam::IVec3::new(3, 1, 2);
+
+        // the block at the north west corner ends up at the north east corner
+        assert_eq!(
+            orientation.apply(glam::IVec3::new(0, 0, 1), size),
+            glam::IVec3::new(1, 0, 2)
+        );
+        assert_eq!(orientation.turn_size(size), glam::IVec3::new(2, 1, 3));
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let schematic = Schematic::from_blocks(
+            glam::IVec3::new(4, 2, 3),
+            [
+                (glam::IVec3::new(0, 0, 0), Some(7)),
+                (glam::IVec3::new(1, 0, 0), None),
+                (glam::IVec3::new(3, 1, 2), Some(2)),
+            ],
+        );
+
+        let bytes = schematic.encode(|block| format!("block{block}"));
+        let decoded = Schematic::decode(&bytes, |name| name[5..].parse().unwrap()).unwrap();
+
+        assert_eq!(decoded, schematic);
+        assert_eq!(
+            decoded.blocks(Orientation::default()).collect::<Vec<_>>(),
+            vec![
+                (glam::IVec3::new(0, 0, 0), Some(7)),
+                (glam::IVec3::new(1, 0, 0), None),
+                (glam::IVec3::new(3, 1, 2), Some(2)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use game_loop::winit::event::ModifiersState;
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    debug::DebugRenderState,
+    game_map::ChunkTag,
+    input::InputState,
+    model::Model,
+    rendererer::{Renderer, Scene},
+    settings::Settings,
+    spawning::Mob,
+    water::FluidModel,
+};
+
+/// Variations of a screenshot, combined from modifier keys or console arguments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScreenshotOptions {
+    /// Captures only the world, without the HUD.
+    pub hide_hud: bool,
+    /// Renders the screenshot at `GraphicsSettings::screenshot_scale` times the window resolution.
+    pub high_resolution: bool,
+    /// Copies the saved screenshot to the clipboard.
+    pub clipboard: bool,
+}
+
+impl ScreenshotOptions {
+    /// Shift hides the HUD, Ctrl increases the resolution and Alt copies the screenshot to the clipboard.
+    pub fn from_modifiers(modifiers: ModifiersState) -> Self {
+        Self {
+            hide_hud: modifiers.shift(),
+            high_resolution: modifiers.ctrl(),
+            clipboard: modifiers.alt(),
+        }
+    }
+
+    /// Parses arguments of the screenshot console command: `nohud`, `hires` and `clipboard`.
+    pub fn from_args(args: &[&str]) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        for arg in args {
+            match *arg {
+                "nohud" => options.hide_hud = true,
+                "hires" => options.high_resolution = true,
+                "clipboard" => options.clipboard = true,
+                _ => return Err(format!("Unknown screenshot option {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Captures a screenshot on request and saves it to the working directory.
+#[allow(clippy::too_many_arguments)]
+pub fn screenshot_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    renderer: UniqueView<Renderer>,
+    settings: UniqueView<Settings>,
+    camera: UniqueView<Camera>,
+    debug_state: UniqueView<DebugRenderState>,
+    models: View<Model>,
+    chunks: View<ChunkTag>,
+    mobs: View<Mob>,
+    fluid_models: View<FluidModel>,
+) {
+    let Some(options) = input_state.screenshot.take() else {
+        return;
+    };
+
+    let scale = if options.high_resolution {
+        settings.graphics.screenshot_scale
+    } else {
+        1
+    };
+
+    let scene = Scene {
+        camera: &camera,
+        debug_state: &debug_state,
+        models: &models,
+        chunks: &chunks,
+        mobs: &mobs,
+        fluid_models: &fluid_models,
+    };
+
+    let Some(image) = renderer.capture(&scene, scale, !options.hide_hud) else {
+        log::error!("Failed to capture a screenshot");
+        return;
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let path = PathBuf::from(format!("screenshot-{timestamp}.png"));
+
+    if let Err(e) = image.save(&path) {
+        log::error!("Failed to save screenshot to {}: {e}", path.display());
+        return;
+    }
+
+    log::info!(
+        "Saved {}x{} screenshot to {}",
+        image.width(),
+        image.height(),
+        path.display()
+    );
+
+    if options.clipboard {
+        match copy_to_clipboard(&path) {
+            Ok(()) => log::info!("Copied screenshot to the clipboard"),
+            Err(e) => log::error!("Failed to copy screenshot to the clipboard: {e}"),
+        }
+    }
+}
+
+/// Copies a PNG file to the clipboard using the tools of the platform.
+fn copy_to_clipboard(path: &Path) -> io::Result<()> {
+    let path = path.canonicalize()?;
+    // verbatim paths returned on Windows are not understood by PowerShell
+    let path = path.to_string_lossy();
+    let path = path.trim_start_matches(r"\\?\");
+
+    let commands: Vec<(&str, Vec<String>)> = if cfg!(target_os = "windows") {
+        vec![(
+            "powershell",
+            vec![
+                "-NoProfile".into(),
+                "-Command".into(),
+                format!(
+                    "Add-Type -AssemblyName System.Windows.Forms, System.Drawing; \
+                     [System.Windows.Forms.Clipboard]::SetImage([System.Drawing.Image]::FromFile('{path}'))"
+                ),
+            ],
+        )]
+    } else if cfg!(target_os = "macos") {
+        vec![(
+            "osascript",
+            vec![
+                "-e".into(),
+                format!("set the clipboard to (read (POSIX file \"{path}\") as «class PNGf»)"),
+            ],
+        )]
+    } else {
+        // Wayland first, X11 as a fallback
+        vec![
+            (
+                "sh",
+                vec![
+                    "-c".into(),
+                    "wl-copy --type image/png < \"$0\"".into(),
+                    path.to_string(),
+                ],
+            ),
+            (
+                "xclip",
+                vec![
+                    "-selection".into(),
+                    "clipboard".into(),
+                    "-t".into(),
+                    "image/png".into(),
+                    "-i".into(),
+                    path.to_string(),
+                ],
+            ),
+        ]
+    };
+
+    let mut last_error = io::Error::new(io::ErrorKind::Unsupported, "no clipboard tool found");
+
+    for (program, args) in commands {
+        match Command::new(program).args(&args).status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => {
+                last_error = io::Error::other(format!("{program} exited with {status}"));
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
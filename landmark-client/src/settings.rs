@@ -0,0 +1,365 @@
+use std::fs;
+
+use shipyard::*;
+
+use crate::{
+    bindings::KeyBindings, debug_view::DebugViewMode, game_mode::GameMode, schematic::Orientation,
+};
+
+/// User configurable settings loaded from `settings.ron` in the working directory.
+#[derive(Debug, Clone, Unique, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Name shown to other players.
+    pub player_name: String,
+    /// Mode every session starts in, it can be switched with `/gamemode` while playing.
+    pub game_mode: GameMode,
+    pub graphics: GraphicsSettings,
+    /// Names of directories in `resourcepacks/`, highest priority first.
+    pub resource_packs: Vec<String>,
+    /// Loads the world with the resource packs it was created with when they differ from
+    /// `resource_packs`, as long as all of them are installed in the same versions.
+    pub load_pinned_packs: bool,
+    pub controls: KeyBindings,
+    pub chat: ChatSettings,
+    pub telemetry: TelemetrySettings,
+    pub world: WorldSettings,
+    pub physics: PhysicsSettings,
+    pub network: NetworkSettings,
+    /// Enables tools for working on the game and resource packs,
+    /// like regenerating the world when the world generation settings change.
+    pub dev_mode: bool,
+    /// Opens a second window showing the world from another camera, only in dev mode.
+    pub debug_view: Option<DebugViewMode>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            player_name: "Player".to_string(),
+            game_mode: GameMode::default(),
+            graphics: GraphicsSettings::default(),
+            resource_packs: Vec::new(),
+            load_pinned_packs: false,
+            controls: KeyBindings::default(),
+            chat: ChatSettings::default(),
+            telemetry: TelemetrySettings::default(),
+            world: WorldSettings::default(),
+            physics: PhysicsSettings::default(),
+            network: NetworkSettings::default(),
+            dev_mode: false,
+            debug_view: None,
+        }
+    }
+}
+
+impl Settings {
+    pub const PATH: &'static str = "settings.ron";
+
+    /// Loads settings from disk, falling back to defaults if the file is missing or invalid.
+    pub fn load() -> Self {
+        let content = match fs::read_to_string(Self::PATH) {
+            Ok(content) => content,
+            Err(_) => {
+                log::info!("Settings file {} not found, using defaults", Self::PATH);
+                return Self::default();
+            }
+        };
+
+        let mut settings: Self = ron::from_str(content.as_str()).unwrap_or_else(|e| {
+            log::error!("Failed to parse settings file {}: {e}", Self::PATH);
+            Self::default()
+        });
+
+        settings.controls.validate();
+
+        settings
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    /// Graphics API used for rendering, others are tried if it isn't available.
+    pub backend: BackendSetting,
+    /// GPU used for rendering when there are several, `--list-adapters` shows their names.
+    pub adapter: AdapterSetting,
+    pub present_mode: PresentModeSetting,
+    /// Maximum amount of frames rendered per second when vsync is not used.
+    pub fps_limit: Option<u32>,
+    /// Radius of loaded chunks around the camera.
+    pub view_distance: u32,
+    /// Multiplier of the window resolution used for high resolution screenshots.
+    pub screenshot_scale: u32,
+    /// Fades terrain into the sky towards the view distance.
+    pub fog: bool,
+    /// How the window covers the screen when fullscreen is toggled.
+    pub fullscreen: FullscreenMode,
+    /// Draws the depth of the terrain before its colors, so hidden fragments are never shaded.
+    /// Helps GPUs limited by fill rate at large view distances.
+    pub depth_prepass: bool,
+    /// Megabytes of model buffers after which new chunk meshes wait until others are unloaded.
+    pub gpu_memory_budget: Option<u32>,
+    /// Resolution of the map of the sun's shadows.
+    pub shadows: ShadowQuality,
+    /// How point lights like torches are drawn.
+    pub lighting: LightingMode,
+    /// Strength of the glow around parts of the frame brighter than white, like glowing blocks.
+    /// 0 turns it off.
+    pub bloom: f32,
+    /// How block textures are sampled on distant and slanted surfaces.
+    pub texture_filtering: TextureFiltering,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            backend: BackendSetting::default(),
+            adapter: AdapterSetting::default(),
+            present_mode: PresentModeSetting::Fifo,
+            fps_limit: None,
+            view_distance: 6,
+            screenshot_scale: 2,
+            fog: true,
+            fullscreen: FullscreenMode::default(),
+            depth_prepass: false,
+            gpu_memory_budget: None,
+            shadows: ShadowQuality::default(),
+            lighting: LightingMode::default(),
+            bloom: 0.5,
+            texture_filtering: TextureFiltering::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ChatSettings {
+    /// Shows recent messages above the players who sent them.
+    pub bubbles: bool,
+    /// Shows names above other players.
+    pub name_tags: bool,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            bubbles: true,
+            name_tags: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// Records anonymous performance statistics to `telemetry.ron`, disabled unless the user opts in.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PhysicsSettings {
+    /// Moves the player and resolves collisions with fixed-point math, so the same inputs give
+    /// bit-identical positions on every platform. Meant for lockstep and replay testing.
+    pub deterministic: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// How far in the past entities of the server are drawn, so there is a later snapshot to move
+    /// them towards. Should be a few times the interval between updates from the server.
+    pub interpolation_delay_ms: u32,
+    /// How long entities keep moving at their last speed when updates from the server stop arriving.
+    pub max_extrapolation_ms: u32,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            interpolation_delay_ms: 100,
+            max_extrapolation_ms: 250,
+        }
+    }
+}
+
+/// Options used when generating terrain, changing them doesn't affect chunks already edited in the world save.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    /// Builds the terrain from an image instead of the test terrain.
+    pub heightmap: Option<HeightmapSettings>,
+    /// Structures from `.vox` models placed on top of the terrain.
+    pub structures: Vec<StructurePlacement>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StructurePlacement {
+    /// File name of the model in `structures/` without the extension.
+    pub name: String,
+    /// Position of the minimum corner of the structure.
+    pub position: [i32; 3],
+    /// Turns and mirrors the structure, it stays in place by its minimum corner.
+    #[serde(default)]
+    pub orientation: Orientation,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HeightmapSettings {
+    /// Path of a grayscale PNG, each pixel becomes a column of blocks.
+    pub image: String,
+    /// Height of black pixels.
+    #[serde(default = "default_min_height")]
+    pub min_height: i32,
+    /// Height of white pixels.
+    #[serde(default = "default_max_height")]
+    pub max_height: i32,
+    /// Path of a color image of the same size, the top block of each column is the block with the closest color.
+    #[serde(default)]
+    pub color_map: Option<String>,
+    /// Top block of each column when no color map is used.
+    #[serde(default = "default_surface_block")]
+    pub surface_block: String,
+    /// Block below the top of each column.
+    #[serde(default = "default_fill_block")]
+    pub fill_block: String,
+}
+
+fn default_min_height() -> i32 {
+    -32
+}
+
+fn default_max_height() -> i32 {
+    64
+}
+
+fn default_surface_block() -> String {
+    "Grass".to_string()
+}
+
+fn default_fill_block() -> String {
+    "Stone".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FullscreenMode {
+    /// A window covering the monitor, switching to other windows is instant.
+    #[default]
+    Borderless,
+    /// Takes over the monitor at its highest resolution and refresh rate.
+    Exclusive,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LightingMode {
+    /// Every fragment adds up the few lights nearest to the camera.
+    #[default]
+    Forward,
+    /// Lights are sorted into a grid around the camera and every fragment only adds up the lights
+    /// of its cell, so dozens of lights can be drawn at once. Needs storage buffers.
+    Clustered,
+}
+
+/// Shadows of the terrain cast by the sun, higher qualities have sharper edges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ShadowQuality {
+    Off,
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl ShadowQuality {
+    /// Returns the width and height of the shadow map in texels, None if shadows are off.
+    pub fn map_size(self) -> Option<u32> {
+        match self {
+            ShadowQuality::Off => None,
+            ShadowQuality::Low => Some(1024),
+            ShadowQuality::Medium => Some(2048),
+            ShadowQuality::High => Some(4096),
+        }
+    }
+}
+
+/// Filtering of block textures, the texels of nearby blocks stay sharp unless anisotropic filtering is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TextureFiltering {
+    /// Always samples the full size textures, so distant blocks shimmer while moving.
+    Nearest,
+    /// Distant blocks sample smaller copies of the textures, which keeps them from shimmering.
+    #[default]
+    Mipmapped,
+    /// Mipmapped, and surfaces seen at a steep angle take up to this many samples along their slope,
+    /// so they stay sharp further away. Also smooths the texels of nearby blocks and item icons.
+    /// GPUs support up to 16 samples.
+    Anisotropic(u16),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BackendSetting {
+    /// Whichever backend of the platform has a suitable adapter.
+    #[default]
+    Auto,
+    Vulkan,
+    Dx12,
+    Metal,
+    /// OpenGL, or WebGL in browsers.
+    Gl,
+}
+
+impl BackendSetting {
+    pub fn backends(self) -> wgpu::Backends {
+        match self {
+            BackendSetting::Auto => wgpu::Backends::all(),
+            BackendSetting::Vulkan => wgpu::Backends::VULKAN,
+            BackendSetting::Dx12 => wgpu::Backends::DX12,
+            BackendSetting::Metal => wgpu::Backends::METAL,
+            BackendSetting::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AdapterSetting {
+    /// The most powerful adapter, usually a discrete GPU.
+    #[default]
+    Auto,
+    Discrete,
+    /// GPUs built into the CPU, they use less power.
+    Integrated,
+    /// The first adapter whose name contains the text, ignoring case.
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PresentModeSetting {
+    /// No vsync, frames are presented immediately and may tear.
+    Immediate,
+    /// No tearing, but newer frames replace queued ones.
+    Mailbox,
+    /// Traditional vsync.
+    Fifo,
+}
+
+impl PresentModeSetting {
+    /// Returns the next mode, used for cycling through modes with a key.
+    pub fn next(self) -> Self {
+        match self {
+            PresentModeSetting::Immediate => PresentModeSetting::Mailbox,
+            PresentModeSetting::Mailbox => PresentModeSetting::Fifo,
+            PresentModeSetting::Fifo => PresentModeSetting::Immediate,
+        }
+    }
+}
+
+impl From<PresentModeSetting> for wgpu::PresentMode {
+    fn from(value: PresentModeSetting) -> Self {
+        match value {
+            PresentModeSetting::Immediate => wgpu::PresentMode::Immediate,
+            PresentModeSetting::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentModeSetting::Fifo => wgpu::PresentMode::Fifo,
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Preprocessing of WGSL shaders, so a single source serves several pipeline permutations.
+//!
+//! Lines starting with `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep or remove the lines between
+//! them depending on which flags are defined, blocks can be nested. Removed lines are replaced by empty ones,
+//! so line numbers in shader compilation errors still point to the source.
+//!
+//! `#include "file.wgsl"` is replaced by the preprocessed lines of a file from the shader includes, which
+//! shifts the lines below it. Files included a second time are skipped, so they can include each other.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use crate::settings::{GraphicsSettings, LightingMode, ShadowQuality};
+
+/// Optional parts of the world shader, each one enables a flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures {
+    /// `TEXTURES`: samples the block atlas, otherwise only vertex colors are drawn.
+    pub textures: bool,
+    /// `FOG`: fades distant geometry into the sky, the distance follows the fog uniform.
+    pub fog: bool,
+    /// `SHADOWS`: darkens geometry the sun's shadow map marks as hidden from the sun.
+    pub shadows: bool,
+    /// `CLUSTERED_LIGHTS`: looks up point lights in the light grid, otherwise the nearest lights are looped over.
+    pub clustered_lights: bool,
+}
+
+impl ShaderFeatures {
+    pub fn from_settings(settings: &GraphicsSettings) -> Self {
+        Self {
+            textures: true,
+            fog: settings.fog,
+            shadows: settings.shadows != ShadowQuality::Off,
+            clustered_lights: settings.lighting == LightingMode::Clustered,
+        }
+    }
+
+    fn defines(&self) -> HashSet<&'static str> {
+        let mut defines = HashSet::new();
+
+        if self.textures {
+            defines.insert("TEXTURES");
+        }
+        if self.fog {
+            defines.insert("FOG");
+        }
+        if self.shadows {
+            defines.insert("SHADOWS");
+        }
+        if self.clustered_lights {
+            defines.insert("CLUSTERED_LIGHTS");
+        }
+
+        defines
+    }
+
+    /// Preprocesses a shader with the flags of the features.
+    pub fn apply(
+        &self,
+        source: &str,
+        includes: &ShaderIncludes,
+    ) -> Result<String, PreprocessError> {
+        preprocess(source, &self.defines(), includes)
+    }
+}
+
+/// Sources of the files shaders can include, by file name.
+pub type ShaderIncludes = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessError {
+    /// Included file the directive is in, None for the shader itself.
+    pub file: Option<String>,
+    /// Line of the directive, starting at 1.
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(
+                f,
+                "Shader include {file} line {}: {}",
+                self.line, self.reason
+            ),
+            None => write!(f, "Shader line {}: {}", self.line, self.reason),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// Block between an `#ifdef` or `#ifndef` and its `#endif`.
+#[derive(Debug)]
+struct Block {
+    /// Line of the directive which opened the block.
+    start: usize,
+    /// Whether the condition of the directive holds.
+    condition: bool,
+    /// Whether the lines around the block are kept.
+    enclosing: bool,
+    in_else: bool,
+}
+
+impl Block {
+    fn is_kept(&self) -> bool {
+        self.enclosing && self.condition != self.in_else
+    }
+}
+
+/// Keeps the lines of the source whose conditions hold for the defined flags and inserts included files.
+pub fn preprocess(
+    source: &str,
+    defines: &HashSet<&str>,
+    includes: &ShaderIncludes,
+) -> Result<String, PreprocessError> {
+    let mut output = String::with_capacity(source.len());
+    let mut included = HashSet::new();
+
+    preprocess_file(source, None, defines, includes, &mut included, &mut output)?;
+
+    Ok(output)
+}
+
+fn preprocess_file<'a>(
+    source: &str,
+    file: Option<&str>,
+    defines: &HashSet<&str>,
+    includes: &'a ShaderIncludes,
+    included: &mut HashSet<&'a str>,
+    output: &mut String,
+) -> Result<(), PreprocessError> {
+    let mut blocks: Vec<Block> = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let number = idx + 1;
+        let error = |reason: &str| PreprocessError {
+            file: file.map(str::to_string),
+            line: number,
+            reason: reason.to_string(),
+        };
+
+        let mut words = line.split_whitespace();
+        let directive = words.next().filter(|word| word.starts_with('#'));
+        let kept = blocks.last().is_none_or(Block::is_kept);
+
+        match directive {
+            Some(directive @ ("#ifdef" | "#ifndef")) => {
+                let name = words.next().ok_or_else(|| error("expected a flag name"))?;
+
+                blocks.push(Block {
+                    start: number,
+                    condition: defines.contains(name) == (directive == "#ifdef"),
+                    enclosing: kept,
+                    in_else: false,
+                });
+            }
+            Some("#else") => {
+                let block = blocks
+                    .last_mut()
+                    .ok_or_else(|| error("#else without #ifdef"))?;
+
+                if block.in_else {
+                    return Err(error("second #else in the same block"));
+                }
+
+                block.in_else = true;
+            }
+            Some("#endif") => {
+                blocks.pop().ok_or_else(|| error("#endif without #ifdef"))?;
+            }
+            Some("#include") if kept => {
+                let name = line
+                    .trim()
+                    .trim_start_matches("#include")
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|name| name.strip_suffix('"'))
+                    .ok_or_else(|| error("expected a quoted file name"))?;
+                let (name, include) = includes
+                    .get_key_value(name)
+                    .ok_or_else(|| error(&format!("unknown include {name}")))?;
+
+                if included.insert(name) {
+                    preprocess_file(include, Some(name), defines, includes, included, output)?;
+                }
+
+                // the directive itself is replaced by the file
+                continue;
+            }
+            Some("#include") => {}
+            Some(directive) => return Err(error(&format!("unknown directive {directive}"))),
+            None if kept => output.push_str(line),
+            None => {}
+        }
+
+        output.push('\n');
+    }
+
+    if let Some(block) = blocks.last() {
+        return Err(PreprocessError {
+            file: file.map(str::to_string),
+            line: block.start,
+            reason: "#ifdef without #endif".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{
+        builtin_shader_includes, BUILTIN_BLOOM_SHADER, BUILTIN_MIPMAP_SHADER,
+        BUILTIN_PARTICLE_SHADER, BUILTIN_SHADER, BUILTIN_UI_SHADER, BUILTIN_WATER_SHADER,
+    };
+
+    fn run(source: &str, defines: &[&str]) -> Result<String, PreprocessError> {
+        preprocess(
+            source,
+            &defines.iter().copied().collect(),
+            &ShaderIncludes::new(),
+        )
+    }
+
+    fn includes(files: &[(&str, &str)]) -> ShaderIncludes {
+        files
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_lines_by_flags() {
+        let source = "a\n#ifdef X\nb\n#else\nc\n#endif\n#ifndef Y\nd\n#endif\n";
+
+        assert_eq!(run(source, &[]).unwrap(), "a\n\n\n\nc\n\n\nd\n\n");
+        assert_eq!(run(source, &["X", "Y"]).unwrap(), "a\n\nb\n\n\n\n\n\n\n");
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let source =
+            "#ifdef X\n#ifdef Y\na\n#else\nb\n#endif\n#else\n#ifdef Y\nc\n#endif\n#endif\n";
+        let kept = |defines: &[&str]| -> String {
+            run(source, defines).unwrap().split_whitespace().collect()
+        };
+
+        assert_eq!(kept(&["X", "Y"]), "a");
+        assert_eq!(kept(&["X"]), "b");
+        assert_eq!(kept(&["Y"]), "c");
+        assert_eq!(kept(&[]), "");
+    }
+
+    #[test]
+    fn reports_unbalanced_directives() {
+        assert_eq!(run("a\n#endif\n", &[]).unwrap_err().line, 2);
+        assert_eq!(
+            run("#ifdef X\n#else\n#else\n#endif", &[]).unwrap_err().line,
+            3
+        );
+        assert_eq!(run("a\n#ifdef X\nb\n", &[]).unwrap_err().line, 2);
+        assert_eq!(run("#ifdef\n#endif", &[]).unwrap_err().line, 1);
+        assert_eq!(run("#define X", &[]).unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn inserts_includes_once() {
+        let includes = includes(&[
+            ("a.wgsl", "#include \"b.wgsl\"\na"),
+            ("b.wgsl", "#ifdef X\nb\n#endif\n#include \"a.wgsl\""),
+        ]);
+        let source = "#include \"a.wgsl\"\n#include \"b.wgsl\"\nc\n";
+
+        let processed = preprocess(source, &["X"].into_iter().collect(), &includes).unwrap();
+        assert_eq!(processed, "\nb\n\na\nc\n");
+    }
+
+    #[test]
+    fn reports_errors_in_includes() {
+        let includes = includes(&[("a.wgsl", "a\n#endif")]);
+
+        let error = preprocess("#include \"a.wgsl\"", &HashSet::new(), &includes).unwrap_err();
+        assert_eq!((error.file.as_deref(), error.line), (Some("a.wgsl"), 2));
+
+        let error = preprocess("\n#include \"b.wgsl\"", &HashSet::new(), &includes).unwrap_err();
+        assert_eq!((error.file, error.line), (None, 2));
+
+        assert!(run("#include a.wgsl", &[]).is_err());
+        // includes in removed blocks are never looked up
+        assert!(run("#ifdef X\n#include \"b.wgsl\"\n#endif", &[]).is_ok());
+    }
+
+    #[test]
+    fn line_numbers_match_between_permutations() {
+        let includes = builtin_shader_includes();
+        let line_count = |features: ShaderFeatures| {
+            features
+                .apply(BUILTIN_SHADER, &includes)
+                .unwrap()
+                .lines()
+                .count()
+        };
+
+        for features in permutations() {
+            assert_eq!(
+                line_count(features),
+                line_count(ShaderFeatures {
+                    textures: true,
+                    fog: true,
+                    shadows: true,
+                    clustered_lights: true,
+                })
+            );
+        }
+    }
+
+    fn validate(source: &str) {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|e| panic!("{}", e.emit_to_string(source)));
+
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .unwrap_or_else(|e| panic!("{e:?}"));
+    }
+
+    /// Returns every combination of the flags.
+    fn permutations() -> Vec<ShaderFeatures> {
+        (0..16)
+            .map(|flags: u32| ShaderFeatures {
+                textures: flags & 1 != 0,
+                fog: flags & 2 != 0,
+                shadows: flags & 4 != 0,
+                clustered_lights: flags & 8 != 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn all_permutations_compile() {
+        let includes = builtin_shader_includes();
+
+        for features in permutations() {
+            validate(&features.apply(BUILTIN_SHADER, &includes).unwrap());
+            validate(&features.apply(BUILTIN_WATER_SHADER, &includes).unwrap());
+        }
+
+        validate(BUILTIN_UI_SHADER);
+        validate(BUILTIN_BLOOM_SHADER);
+        validate(BUILTIN_MIPMAP_SHADER);
+        validate(&preprocess(BUILTIN_PARTICLE_SHADER, &HashSet::new(), &includes).unwrap());
+    }
+}
@@ -0,0 +1,227 @@
+//! Shadows of the terrain cast by the sun.
+//!
+//! Before the frame, models are drawn from the sun's direction into a depth map covering a square
+//! around the camera. The world shader darkens fragments which lie behind the depth stored in the map.
+//! The square follows the camera in steps of whole texels, so shadow edges don't shimmer while moving.
+
+use bytemuck::Zeroable;
+use shipyard::*;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::{Camera, Frustum},
+    rendererer::Renderer,
+    settings::ShadowQuality,
+    sun::SunCycle,
+    texture,
+};
+
+/// Half of the width of the square covered by the shadow map, in blocks.
+const SHADOW_DISTANCE: f32 = 64.0;
+/// How far towards the sun from the camera geometry still casts shadows into the square.
+const CASTER_DISTANCE: f32 = 128.0;
+
+/// View of the sun as laid out in the uniform buffer. It starts like the camera uniform, so the same
+/// buffer serves as the camera of the shadow pass.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct ShadowUniform {
+    view_proj: glam::Mat4,
+    strength: f32,
+    _padding: [f32; 3],
+}
+
+#[derive(Debug)]
+pub struct ShadowMap {
+    pub texture: texture::Texture,
+    /// Width and height of the texture in texels.
+    size: u32,
+    buffer: wgpu::Buffer,
+    /// Binds the view of the sun as the camera of the shadow pass.
+    pub camera_bind_group: wgpu::BindGroup,
+    /// Binds the view of the sun and the map to the world shader.
+    pub bind_group: wgpu::BindGroup,
+    /// Models outside of it cast no shadows into the map.
+    pub frustum: Frustum,
+}
+
+impl ShadowMap {
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("shadow_bind_group_layout"),
+        })
+    }
+
+    /// Creates a map of the size of the quality, limited by the device. The map has a single texel
+    /// if shadows are off, so the world shader can always be bound to one.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        layout: &wgpu::BindGroupLayout,
+        quality: ShadowQuality,
+    ) -> Self {
+        let size = quality
+            .map_size()
+            .unwrap_or(1)
+            .min(device.limits().max_texture_dimension_2d);
+        let texture =
+            texture::Texture::create_sized_depth_texture(device, size, size, "shadow_map");
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("shadow_buffer"),
+            contents: bytemuck::cast_slice(&[ShadowUniform::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("shadow_camera_bind_group"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        });
+
+        Self {
+            texture,
+            size,
+            buffer,
+            camera_bind_group,
+            bind_group,
+            frustum: Frustum::from_view_projection(glam::Mat4::IDENTITY),
+        }
+    }
+
+    /// Points the map at the sun, centered on `center` relative to the origin the world is drawn around.
+    fn update(&mut self, queue: &wgpu::Queue, sun: &SunCycle, center: glam::Vec3) {
+        let view_proj = sun_view_projection(sun.direction(), center, self.size);
+        self.frustum = Frustum::from_view_projection(view_proj);
+
+        let uniform = ShadowUniform {
+            view_proj,
+            strength: sun.strength(),
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+/// Returns the orthographic view from the sun onto the square around `center` covered by a map of
+/// `size` texels. The square moves in steps of whole texels.
+fn sun_view_projection(direction: glam::Vec3, center: glam::Vec3, size: u32) -> glam::Mat4 {
+    // the path of the sun is tilted away from the zenith, so it's never parallel to the up vector
+    let view = glam::Mat4::look_to_lh(glam::Vec3::ZERO, -direction, glam::Vec3::Y);
+
+    let texel = 2.0 * SHADOW_DISTANCE / size as f32;
+    let center = (view.transform_point3(center) / texel).round() * texel;
+
+    let proj = glam::Mat4::orthographic_lh(
+        center.x - SHADOW_DISTANCE,
+        center.x + SHADOW_DISTANCE,
+        center.y - SHADOW_DISTANCE,
+        center.y + SHADOW_DISTANCE,
+        center.z - CASTER_DISTANCE,
+        center.z + SHADOW_DISTANCE,
+    );
+
+    proj * view
+}
+
+/// Centers the shadow map on the camera and turns it towards the sun.
+pub fn update_shadow_map_sys(
+    camera: UniqueView<Camera>,
+    sun: UniqueView<SunCycle>,
+    mut renderer: UniqueViewMut<Renderer>,
+) {
+    let renderer = &mut *renderer;
+    let center = (camera.eye - camera.origin.as_world_position()).as_vec3();
+
+    renderer.shadow_map.update(&renderer.queue, &sun, center);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTION: glam::Vec3 = glam::Vec3::new(0.6, 0.8, 0.0);
+
+    #[test]
+    fn uniform_matches_the_shader_layout() {
+        // the matrix followed by the strength, rounded up to a multiple of 16 bytes
+        assert_eq!(std::mem::size_of::<ShadowUniform>(), 80);
+    }
+
+    #[test]
+    fn geometry_towards_the_sun_is_nearer() {
+        let view_proj = sun_view_projection(DIRECTION, glam::Vec3::ZERO, 2048);
+
+        let center = view_proj.project_point3(glam::Vec3::ZERO);
+        let above = view_proj.project_point3(DIRECTION * 10.0);
+
+        assert!(center.x.abs() < 0.01 && center.y.abs() < 0.01);
+        assert!(above.z < center.z);
+        assert!((0.0..=1.0).contains(&above.z) && (0.0..=1.0).contains(&center.z));
+    }
+
+    #[test]
+    fn map_moves_in_whole_texels() {
+        let texel = 2.0 * SHADOW_DISTANCE / 2048.0;
+        let view_proj = sun_view_projection(DIRECTION, glam::Vec3::ZERO, 2048);
+
+        assert_eq!(
+            sun_view_projection(DIRECTION, glam::Vec3::Z * texel * 0.3, 2048),
+            view_proj
+        );
+        assert_ne!(
+            sun_view_projection(DIRECTION, glam::Vec3::Z * texel * 2.0, 2048),
+            view_proj
+        );
+    }
+}
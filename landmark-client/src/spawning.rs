@@ -0,0 +1,463 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fmt::Write as _,
+    fs,
+    path::Path,
+};
+
+use shipyard::*;
+
+use crate::{
+    biome::Biome,
+    camera::Camera,
+    coords,
+    game_map::{Chunk, ChunkCoords, GameMap},
+    loader::{ResourceDictionary, ResourceError, ResourcePacks},
+    mobs::{MobAi, MobDefinitions},
+    physics::EYE_HEIGHT,
+};
+
+/// Mobs need this much free space above the ground to spawn.
+const MOB_HEIGHT: i32 = 2;
+
+fn default_max_light() -> u8 {
+    Chunk::MAX_LIGHT
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Conditions under which a single kind of mob can spawn, loaded from `spawning.ron`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SpawnRule {
+    pub mob: String,
+    #[serde(default)]
+    pub min_light: u8,
+    #[serde(default = "default_max_light")]
+    pub max_light: u8,
+    /// Names of blocks the mob can stand on, any block if empty.
+    #[serde(default)]
+    pub blocks: Vec<String>,
+    #[serde(default)]
+    pub min_altitude: Option<i32>,
+    #[serde(default)]
+    pub max_altitude: Option<i32>,
+    /// Weight of the rule in biomes not listed in `biome_weights`.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Overrides the weight in specific biomes, zero prevents spawning there.
+    #[serde(default)]
+    pub biome_weights: HashMap<String, u32>,
+    /// Maximum amount of loaded mobs of this kind.
+    #[serde(default)]
+    pub max_count: Option<u32>,
+}
+
+impl SpawnRule {
+    /// Returns the weight of the rule at the site, or the first condition it fails.
+    fn check(&self, site: &SpawnSite) -> Result<u32, Rejection> {
+        if self.min_altitude.is_some_and(|min| site.altitude < min)
+            || self.max_altitude.is_some_and(|max| site.altitude > max)
+        {
+            return Err(Rejection::Altitude);
+        }
+
+        if !(self.min_light..=self.max_light).contains(&site.light) {
+            return Err(Rejection::Light);
+        }
+
+        if !self.blocks.is_empty() && !self.blocks.contains(&site.ground) {
+            return Err(Rejection::Block);
+        }
+
+        let weight = self
+            .biome_weights
+            .get(site.biome.name())
+            .copied()
+            .unwrap_or(self.weight);
+
+        if weight == 0 {
+            return Err(Rejection::Biome);
+        }
+
+        Ok(weight)
+    }
+}
+
+/// Spawn rules together with limits shared by all of them.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct SpawnRules {
+    /// Number of updates between spawn evaluations.
+    pub interval: u32,
+    /// Random positions tried in every loaded chunk per evaluation.
+    pub attempts_per_chunk: u32,
+    /// Maximum amount of loaded mobs of all kinds.
+    pub global_cap: u32,
+    /// Mobs don't spawn closer to the player than this, so they don't appear right in front of them.
+    pub min_player_distance: f64,
+    /// Mobs only spawn within this distance from the player.
+    pub max_player_distance: f64,
+    pub rules: Vec<SpawnRule>,
+}
+
+impl Default for SpawnRules {
+    fn default() -> Self {
+        Self {
+            interval: 240,
+            attempts_per_chunk: 1,
+            global_cap: 50,
+            min_player_distance: 24.0,
+            max_player_distance: 64.0,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl SpawnRules {
+    /// Loads spawn rules from the highest priority resource pack containing them.
+    pub fn load(resource_packs: &ResourcePacks) -> Result<Self, ResourceError> {
+        let relative = Path::new("spawning.ron");
+
+        let Some(path) = resource_packs.find(relative) else {
+            log::info!("No spawn rules found, mobs will not spawn");
+            return Ok(Self::default());
+        };
+
+        let content = fs::read_to_string(&path).map_err(|source| ResourceError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+        ron::from_str(&content).map_err(|source| ResourceError::Parse { path, source })
+    }
+}
+
+/// Reason a spawn attempt failed, ordered by the stage of the evaluation it failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rejection {
+    GlobalCap,
+    /// The chosen position is too close to or too far from the player.
+    Distance,
+    /// The chosen position is inside of a block or there is no room above it.
+    Occupied,
+    /// There is no block to stand on below the chosen position.
+    NoGround,
+    Altitude,
+    Light,
+    Block,
+    Biome,
+    MobCap,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rejection::GlobalCap => "global cap",
+            Rejection::Distance => "distance",
+            Rejection::Occupied => "occupied",
+            Rejection::NoGround => "no ground",
+            Rejection::Altitude => "altitude",
+            Rejection::Light => "light",
+            Rejection::Block => "block",
+            Rejection::Biome => "biome",
+            Rejection::MobCap => "mob cap",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Environment of a position mobs could spawn at.
+#[derive(Debug)]
+struct SpawnSite {
+    altitude: i32,
+    light: u8,
+    /// Name of the block below the position.
+    ground: String,
+    biome: Biome,
+}
+
+/// Counts of spawn attempts since the overlay was last refreshed.
+#[derive(Debug, Default)]
+pub struct SpawnStats {
+    attempts: u32,
+    spawned: u32,
+    rejections: BTreeMap<Rejection, u32>,
+}
+
+impl SpawnStats {
+    fn reject(&mut self, reason: Rejection) {
+        *self.rejections.entry(reason).or_default() += 1;
+    }
+
+    /// Returns a line describing the attempts for the debug overlay and starts counting again.
+    pub fn take_summary(&mut self) -> String {
+        let mut summary = format!("spawns {}/{}", self.spawned, self.attempts);
+
+        for (reason, count) in self.rejections.iter() {
+            let _ = write!(summary, "  {reason} {count}");
+        }
+
+        *self = Self::default();
+
+        summary
+    }
+}
+
+/// Small deterministic random generator (xorshift64*), so spawning doesn't need another dependency.
+#[derive(Debug)]
+struct SpawnRng(u64);
+
+impl SpawnRng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a number in `0..bound`, `bound` must not be zero.
+    fn below(&mut self, bound: u32) -> u32 {
+        (self.next() % bound as u64) as u32
+    }
+}
+
+/// Periodically evaluates spawn rules over the loaded chunks.
+#[derive(Debug, Unique)]
+pub struct MobSpawner {
+    pub rules: SpawnRules,
+    pub stats: SpawnStats,
+    rng: SpawnRng,
+    updates_since_evaluation: u32,
+}
+
+impl MobSpawner {
+    pub fn new(rules: SpawnRules) -> Self {
+        Self {
+            rules,
+            stats: SpawnStats::default(),
+            rng: SpawnRng(0x9e37_79b9_7f4a_7c15),
+            updates_since_evaluation: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Component)]
+pub struct Mob {
+    pub kind: String,
+    /// Name of the entity model the mob is drawn with.
+    pub model: String,
+    /// Position of the center of the mob's feet.
+    pub position: glam::DVec3,
+    // Read once the player can attack mobs
+    #[allow(unused)]
+    pub health: u32,
+}
+
+impl Mob {
+    /// Size of the box the mob is drawn as.
+    pub const SIZE: glam::Vec3 = glam::Vec3::new(0.8, 1.8, 0.8);
+}
+
+/// Picks a random position in the chunk and drops it down to the ground below it.
+fn find_site(
+    game_map: &GameMap,
+    dictionary: &ResourceDictionary,
+    rng: &mut SpawnRng,
+    coords: ChunkCoords,
+) -> Result<(glam::IVec3, SpawnSite), Rejection> {
+    let origin = coords::chunk_origin(coords);
+    let size = Chunk::SIZE as u32;
+    let mut position = origin
+        + glam::IVec3::new(
+            rng.below(size) as i32,
+            rng.below(size) as i32,
+            rng.below(size) as i32,
+        );
+
+    if game_map.get_block_world(position).is_some() {
+        return Err(Rejection::Occupied);
+    }
+
+    // above the highest block of the column within the chunk, the ground is that block
+    let offset = position - origin;
+    match game_map
+        .chunks
+        .get(&coords)
+        .and_then(|chunk| chunk.height(offset.x, offset.z))
+    {
+        Some(height) if position.y > origin.y + height + 1 => position.y = origin.y + height + 1,
+        Some(_) => {}
+        None => return Err(Rejection::NoGround),
+    }
+
+    let ground = loop {
+        if position.y <= origin.y {
+            return Err(Rejection::NoGround);
+        }
+
+        if let Some(block) = game_map.get_block_world(position - glam::IVec3::Y) {
+            break block;
+        }
+
+        position -= glam::IVec3::Y;
+    };
+
+    if (1..MOB_HEIGHT).any(|dy| {
+        game_map
+            .get_block_world(position + glam::IVec3::new(0, dy, 0))
+            .is_some()
+    }) {
+        return Err(Rejection::Occupied);
+    }
+
+    let site = SpawnSite {
+        altitude: position.y,
+        light: game_map.light_at(position),
+        ground: dictionary.get_block_data_from_id(ground).name,
+        biome: game_map.biome_at(position).unwrap_or_default(),
+    };
+
+    Ok((position, site))
+}
+
+/// Evaluates spawn rules in every loaded chunk around the player once per spawn interval
+/// and despawns mobs whose chunk was unloaded.
+#[allow(clippy::too_many_arguments)]
+pub fn mob_spawning_sys(
+    mut spawner: UniqueViewMut<MobSpawner>,
+    dictionary: UniqueView<ResourceDictionary>,
+    definitions: UniqueView<MobDefinitions>,
+    camera: UniqueView<Camera>,
+    game_map: UniqueView<GameMap>,
+    mut entities: EntitiesViewMut,
+    mut mobs: ViewMut<Mob>,
+    mut ais: ViewMut<MobAi>,
+) {
+    let spawner = &mut *spawner;
+
+    spawner.updates_since_evaluation += 1;
+    if spawner.updates_since_evaluation < spawner.rules.interval {
+        return;
+    }
+    spawner.updates_since_evaluation = 0;
+
+    let despawned: Vec<EntityId> = mobs
+        .iter()
+        .with_id()
+        .filter(|(_, mob)| {
+            !game_map
+                .chunks
+                .contains_key(&ChunkCoords::from_world_position(mob.position))
+        })
+        .map(|(id, _)| id)
+        .collect();
+
+    for id in despawned {
+        mobs.delete(id);
+        ais.delete(id);
+        entities.delete_unchecked(id);
+    }
+
+    if spawner.rules.rules.is_empty() {
+        return;
+    }
+
+    let player = camera.eye - glam::DVec3::Y * EYE_HEIGHT;
+
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    for mob in mobs.iter() {
+        *counts.entry(mob.kind.clone()).or_default() += 1;
+    }
+    let mut total: u32 = counts.values().sum();
+
+    // sorted, so the same world always spawns the same mobs
+    let mut chunks: Vec<ChunkCoords> = game_map.chunks.keys().copied().collect();
+    chunks.sort();
+
+    for coords in chunks {
+        for _ in 0..spawner.rules.attempts_per_chunk {
+            spawner.stats.attempts += 1;
+
+            if total >= spawner.rules.global_cap {
+                spawner.stats.reject(Rejection::GlobalCap);
+                continue;
+            }
+
+            let (position, site) = match find_site(&game_map, &dictionary, &mut spawner.rng, coords)
+            {
+                Ok(found) => found,
+                Err(reason) => {
+                    spawner.stats.reject(reason);
+                    continue;
+                }
+            };
+
+            let distance = (position.as_dvec3() - player).length();
+            if distance < spawner.rules.min_player_distance
+                || distance > spawner.rules.max_player_distance
+            {
+                spawner.stats.reject(Rejection::Distance);
+                continue;
+            }
+
+            // an attempt is rejected for the reason of the rule which got furthest in its evaluation
+            let mut rejection = Rejection::Altitude;
+            let mut candidates = Vec::new();
+
+            for rule in spawner.rules.rules.iter() {
+                let result = rule.check(&site).and_then(|weight| {
+                    let count = counts.get(&rule.mob).copied().unwrap_or_default();
+
+                    match rule.max_count {
+                        Some(max_count) if count >= max_count => Err(Rejection::MobCap),
+                        _ => Ok(weight),
+                    }
+                });
+
+                match result {
+                    Ok(weight) => candidates.push((rule, weight)),
+                    Err(reason) => rejection = rejection.max(reason),
+                }
+            }
+
+            let total_weight: u32 = candidates.iter().map(|(_, weight)| weight).sum();
+            if total_weight == 0 {
+                spawner.stats.reject(rejection);
+                continue;
+            }
+
+            let mut roll = spawner.rng.below(total_weight);
+            let Some((rule, _)) = candidates.into_iter().find(|(_, weight)| {
+                if roll < *weight {
+                    true
+                } else {
+                    roll -= weight;
+                    false
+                }
+            }) else {
+                continue;
+            };
+
+            let definition = definitions.get(&rule.mob);
+            let mob = Mob {
+                kind: rule.mob.clone(),
+                model: definition.model.unwrap_or_else(|| rule.mob.clone()),
+                position: position.as_dvec3() + glam::DVec3::new(0.5, 0.0, 0.5),
+                health: definition.health,
+            };
+
+            log::debug!("Spawned {} at {}", mob.kind, position);
+
+            *counts.entry(mob.kind.clone()).or_default() += 1;
+            total += 1;
+            spawner.stats.spawned += 1;
+
+            let ai = MobAi::new(spawner.rng.next());
+            entities.add_entity((&mut mobs, &mut ais), (mob, ai));
+        }
+    }
+}
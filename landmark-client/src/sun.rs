@@ -0,0 +1,77 @@
+//! Course of the sun over the day, which decides where shadows of the terrain fall.
+//!
+//! The sun rises in the east along +X, passes south of the zenith at noon and sets in the west, then
+//! circles below the horizon through the night.
+
+use shipyard::*;
+
+use crate::UPDATES_PER_SECOND;
+
+/// Length of a whole day and night.
+const DAY_SECONDS: f32 = 20.0 * 60.0;
+/// Angle between the path of the sun and the zenith, so it never stands straight above.
+const TILT: f32 = 0.35;
+/// Height of the sun above the horizon at which shadows are fully dark, they fade in while it rises.
+const FULL_STRENGTH_HEIGHT: f32 = 0.2;
+
+#[derive(Debug, Unique)]
+pub struct SunCycle {
+    /// Fraction of the day since sunrise, from 0 to 1.
+    time: f32,
+}
+
+impl Default for SunCycle {
+    fn default() -> Self {
+        // mid-morning, so the first shadows are visible right away
+        Self { time: 0.15 }
+    }
+}
+
+impl SunCycle {
+    /// Returns the direction from the world towards the sun.
+    pub fn direction(&self) -> glam::Vec3 {
+        let angle = self.time * std::f32::consts::TAU;
+
+        glam::Vec3::new(
+            angle.cos() * TILT.cos(),
+            angle.sin() * TILT.cos(),
+            -TILT.sin(),
+        )
+    }
+
+    /// Returns how strongly the sun lights the world, from 0 at night to 1 during the day.
+    pub fn strength(&self) -> f32 {
+        (self.direction().y / FULL_STRENGTH_HEIGHT).clamp(0.0, 1.0)
+    }
+}
+
+/// Moves the sun along its path.
+pub fn sun_sys(mut sun: UniqueViewMut<SunCycle>) {
+    let step = 1.0 / (DAY_SECONDS * UPDATES_PER_SECOND as f32);
+    sun.time = (sun.time + step).fract();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_is_up_during_the_day_only() {
+        let noon = SunCycle { time: 0.25 };
+        let midnight = SunCycle { time: 0.75 };
+
+        assert!(noon.direction().y > 0.9);
+        assert_eq!(noon.strength(), 1.0);
+        assert!(midnight.direction().y < 0.0);
+        assert_eq!(midnight.strength(), 0.0);
+    }
+
+    #[test]
+    fn shadows_fade_in_at_sunrise() {
+        let sunrise = SunCycle { time: 0.0 };
+        let morning = SunCycle { time: 0.02 };
+
+        assert_eq!(sunrise.strength(), 0.0);
+        assert!(morning.strength() > 0.0 && morning.strength() < 1.0);
+    }
+}
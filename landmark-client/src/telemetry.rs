@@ -0,0 +1,172 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use shipyard::*;
+
+use crate::capabilities::GraphicsCapabilities;
+
+/// How often the recorded statistics are written to disk while the game runs.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rough performance class of the graphics adapter, recorded instead of its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HardwareTier {
+    /// Software renderers and unknown adapters.
+    Low,
+    /// Integrated and virtual GPUs.
+    Medium,
+    /// Discrete GPUs.
+    High,
+}
+
+impl HardwareTier {
+    pub fn from_device_type(device_type: wgpu::DeviceType) -> Self {
+        match device_type {
+            wgpu::DeviceType::DiscreteGpu => HardwareTier::High,
+            wgpu::DeviceType::IntegratedGpu | wgpu::DeviceType::VirtualGpu => HardwareTier::Medium,
+            wgpu::DeviceType::Cpu | wgpu::DeviceType::Other => HardwareTier::Low,
+        }
+    }
+}
+
+/// Anonymous statistics accumulated over all sessions. Nothing identifying the user
+/// or their machine is stored, and the file is never sent anywhere automatically.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TelemetryRecord {
+    pub hardware_tier: Option<HardwareTier>,
+    pub sessions: u32,
+    pub crashes: u32,
+    pub average_fps: f64,
+    frames: u64,
+    /// Seconds spent rendering frames, used to keep the average correct across sessions.
+    play_time: f64,
+    /// Set while the game runs, a record found with it set means the last session didn't exit cleanly.
+    running: bool,
+}
+
+impl TelemetryRecord {
+    /// Loads the record, starting a new one if the file is missing or invalid.
+    fn load() -> Self {
+        let Ok(content) = fs::read_to_string(Telemetry::PATH) else {
+            return Self::default();
+        };
+
+        ron::from_str(content.as_str()).unwrap_or_else(|e| {
+            log::warn!(
+                "Failed to parse telemetry file {}, starting a new one: {e}",
+                Telemetry::PATH
+            );
+            Self::default()
+        })
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::write(path, content)
+    }
+}
+
+/// Records performance statistics locally, only when enabled in the settings.
+#[derive(Debug, Unique)]
+pub struct Telemetry {
+    /// None when telemetry is disabled.
+    record: Option<TelemetryRecord>,
+    frames: u64,
+    last_save: Instant,
+}
+
+impl Telemetry {
+    pub const PATH: &'static str = "telemetry.ron";
+
+    /// Starts a session, counting the previous one as a crash if it didn't exit cleanly.
+    pub fn new(enabled: bool, capabilities: &GraphicsCapabilities) -> Self {
+        let record = enabled.then(|| {
+            let mut record = TelemetryRecord::load();
+
+            if record.running {
+                record.crashes += 1;
+            }
+
+            record.hardware_tier = Some(HardwareTier::from_device_type(capabilities.device_type));
+            record.sessions += 1;
+            record.running = true;
+
+            record
+        });
+
+        let mut telemetry = Self {
+            record,
+            frames: 0,
+            last_save: Instant::now(),
+        };
+        telemetry.save();
+
+        telemetry
+    }
+
+    /// Adds frames rendered since the last save to the record and writes it to disk.
+    pub fn save(&mut self) {
+        let Some(record) = &mut self.record else {
+            return;
+        };
+
+        record.frames += std::mem::take(&mut self.frames);
+        record.play_time += self.last_save.elapsed().as_secs_f64();
+        if record.play_time > 0.0 {
+            record.average_fps = record.frames as f64 / record.play_time;
+        }
+
+        self.last_save = Instant::now();
+
+        if let Err(e) = record.save(Path::new(Self::PATH)) {
+            log::warn!("Failed to write telemetry to {}: {e}", Self::PATH);
+        }
+    }
+
+    /// Marks the session as finished without a crash.
+    pub fn finish(&mut self) {
+        if let Some(record) = &mut self.record {
+            record.running = false;
+        }
+
+        self.save();
+    }
+}
+
+/// Counts rendered frames and periodically saves the statistics.
+pub fn record_telemetry_sys(mut telemetry: UniqueViewMut<Telemetry>) {
+    if telemetry.record.is_none() {
+        return;
+    }
+
+    telemetry.frames += 1;
+
+    if telemetry.last_save.elapsed() >= SAVE_INTERVAL {
+        telemetry.save();
+    }
+}
+
+/// Copies the recorded statistics to `path`, so users can share them with the developers.
+pub fn export(path: &Path) -> io::Result<()> {
+    if !Path::new(Telemetry::PATH).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "{} not found, telemetry has to be enabled in the settings first",
+                Telemetry::PATH
+            ),
+        ));
+    }
+
+    let mut record = TelemetryRecord::load();
+    // only meaningful for the running game
+    record.running = false;
+
+    record.save(path)
+}
@@ -0,0 +1,81 @@
+//! Teleports which wait for the chunks at their destination, so the player doesn't fall through terrain
+//! which isn't loaded yet. While connected, the server decides where players teleport to.
+
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    game_map::{ChunkCoords, GameMap},
+};
+
+#[derive(Debug, Default, Unique)]
+pub struct PendingTeleport {
+    /// Set by the network code while connected to a server, only the server teleports the player then.
+    pub connected: bool,
+    /// Set when the player respawned while connected, until the request was sent to the server.
+    respawn_requested: bool,
+    /// Set while the server hasn't moved the respawned player yet.
+    respawning: bool,
+    /// Camera position to move to once its chunks are loaded.
+    target: Option<glam::DVec3>,
+}
+
+impl PendingTeleport {
+    /// Moves the camera to `target` once the chunks around it are loaded, replacing an earlier request.
+    pub fn request(&mut self, target: glam::DVec3) {
+        self.target = Some(target);
+        self.respawning = false;
+    }
+
+    /// Asks the server to move the player to the spawn point.
+    pub fn request_respawn(&mut self) {
+        self.respawn_requested = true;
+        self.respawning = true;
+    }
+
+    /// Returns true once after a respawn was requested, the network code sends it to the server.
+    pub fn take_respawn_request(&mut self) -> bool {
+        std::mem::take(&mut self.respawn_requested)
+    }
+
+    /// Returns true until the player arrived at the destination of the teleport, or the server
+    /// moved the respawned player.
+    pub fn is_pending(&self) -> bool {
+        self.target.is_some() || self.respawning
+    }
+
+    /// Returns the chunks which have to be loaded before the camera moves, the chunk containing the target
+    /// and the ones above and below it, which hold the ground and the head of the player near chunk borders.
+    pub fn required_chunks(&self) -> Vec<ChunkCoords> {
+        let Some(target) = self.target else {
+            return Vec::new();
+        };
+        let center = ChunkCoords::from_world_position(target);
+
+        (-1..=1)
+            .map(|dy| center + ChunkCoords::new(0, dy, 0))
+            .collect()
+    }
+}
+
+/// Moves the camera to the target of the pending teleport as soon as the chunks around it are loaded.
+/// While connected they are the chunks the server sends around the player after it moved them.
+pub fn teleport_sys(
+    mut teleport: UniqueViewMut<PendingTeleport>,
+    mut camera: UniqueViewMut<Camera>,
+    game_map: UniqueView<GameMap>,
+) {
+    let Some(target) = teleport.target else {
+        return;
+    };
+
+    let loaded = teleport
+        .required_chunks()
+        .iter()
+        .all(|coords| game_map.chunks.contains_key(coords));
+
+    if loaded {
+        camera.eye = target;
+        teleport.target = None;
+    }
+}
@@ -1,3 +1,8 @@
+use anyhow::Result;
+use image::GenericImageView;
+
+use crate::settings::TextureFiltering;
+
 #[derive(Debug)]
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -68,14 +73,189 @@ impl Texture {
         })
     }
 
-    pub fn create_depth_texture(
+    /// Creates an empty sampled texture which is filled with `write_region`.
+    pub fn create_atlas_texture(device: &wgpu::Device, size: u32, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // blocks are pixelated on purpose, so don't filter the texels
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Creates an empty sampled texture with `mip_level_count` levels. Only the full size level is
+    /// filled by `write_region`, `MipmapGenerator` fills the smaller ones from it.
+    pub fn create_mipmapped_texture(
+        device: &wgpu::Device,
+        size: u32,
+        mip_level_count: u32,
+        filtering: TextureFiltering,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Self::create_mipmapped_sampler(device, filtering);
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Creates a sampler for a mipmapped texture. Texels close to the camera stay sharp, except with
+    /// anisotropic filtering which needs every filter to be linear.
+    pub fn create_mipmapped_sampler(
+        device: &wgpu::Device,
+        filtering: TextureFiltering,
+    ) -> wgpu::Sampler {
+        let (filter, mipmap_filter, anisotropy_clamp, lod_max_clamp) = match filtering {
+            TextureFiltering::Nearest => {
+                (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest, 1, 0.0)
+            }
+            TextureFiltering::Anisotropic(samples) if samples > 1 => (
+                wgpu::FilterMode::Linear,
+                wgpu::FilterMode::Linear,
+                samples.min(16),
+                32.0,
+            ),
+            _ => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear, 1, 32.0),
+        };
+
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mipmapped_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter,
+            lod_max_clamp,
+            anisotropy_clamp,
+            ..Default::default()
+        })
+    }
+
+    /// Uploads RGBA pixels of a rectangle starting at `origin`.
+    pub fn write_region(&self, queue: &wgpu::Queue, origin: (u32, u32), image: &image::RgbaImage) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+            },
+            image.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Creates a texture which is drawn into and then sampled with filtering, like the targets of bloom.
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Creates a depth texture which can be sampled with a comparison, like the shadow map.
+    pub fn create_sized_depth_texture(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        width: u32,
+        height: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
@@ -0,0 +1,128 @@
+//! Control over the speed of the simulation, independent of rendering.
+//!
+//! The simulation always advances in steps of `1 / UPDATES_PER_SECOND`, a different rate runs more or fewer
+//! of them per fixed update instead of changing the step, so physics behave the same at any speed.
+
+use shipyard::*;
+
+#[derive(Debug, Unique)]
+pub struct TickControl {
+    frozen: bool,
+    /// Steps left to run while frozen.
+    steps: u32,
+    /// Simulation steps per fixed update.
+    rate: f64,
+    /// Fraction of a step carried over to the next fixed update.
+    accumulator: f64,
+}
+
+impl Default for TickControl {
+    fn default() -> Self {
+        Self {
+            frozen: false,
+            steps: 0,
+            rate: 1.0,
+            accumulator: 0.0,
+        }
+    }
+}
+
+impl TickControl {
+    /// Fastest rate, each fixed update runs at most this many steps so a slow machine can keep up.
+    pub const MAX_RATE: f64 = 10.0;
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Stops or resumes the simulation, steps still pending are dropped.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+        self.steps = 0;
+        self.accumulator = 0.0;
+    }
+
+    /// Freezes the simulation after running `count` more steps, one per fixed update.
+    pub fn step(&mut self, count: u32) {
+        self.frozen = true;
+        self.steps = self.steps.saturating_add(count);
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: f64) -> Result<(), String> {
+        if !(rate > 0.0 && rate <= Self::MAX_RATE) {
+            return Err(format!(
+                "the rate has to be above 0 and at most {}",
+                Self::MAX_RATE
+            ));
+        }
+
+        self.rate = rate;
+        self.accumulator = 0.0;
+        Ok(())
+    }
+
+    /// Returns how many simulation steps to run in this fixed update.
+    pub fn advance(&mut self) -> u32 {
+        if self.frozen {
+            let steps = self.steps.min(1);
+            self.steps -= steps;
+
+            return steps;
+        }
+
+        self.accumulator += self.rate;
+        let steps = self.accumulator.floor();
+        self.accumulator -= steps;
+
+        steps as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(control: &mut TickControl, updates: usize) -> u32 {
+        (0..updates).map(|_| control.advance()).sum()
+    }
+
+    #[test]
+    fn fractional_rates_are_accumulated() {
+        let mut control = TickControl::default();
+        assert_eq!(run(&mut control, 10), 10);
+
+        control.set_rate(0.25).unwrap();
+        assert_eq!(run(&mut control, 10), 2);
+
+        control.set_rate(2.5).unwrap();
+        assert_eq!(run(&mut control, 4), 10);
+    }
+
+    #[test]
+    fn frozen_simulation_only_runs_requested_steps() {
+        let mut control = TickControl::default();
+        control.set_frozen(true);
+        assert_eq!(run(&mut control, 10), 0);
+
+        control.step(3);
+        assert_eq!(run(&mut control, 10), 3);
+        assert!(control.is_frozen());
+
+        control.set_frozen(false);
+        assert_eq!(run(&mut control, 10), 10);
+    }
+
+    #[test]
+    fn invalid_rates_are_rejected() {
+        let mut control = TickControl::default();
+
+        assert!(control.set_rate(0.0).is_err());
+        assert!(control.set_rate(f64::NAN).is_err());
+        assert!(control.set_rate(TickControl::MAX_RATE + 1.0).is_err());
+        assert_eq!(control.rate(), 1.0);
+    }
+}
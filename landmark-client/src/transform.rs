@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy, Default)]
+use shipyard::Component;
+
+#[derive(Debug, Clone, Copy, Default, Component)]
 pub struct Transform {
     pub rotation: glam::Quat,
     pub translation: glam::Vec3,
@@ -10,7 +12,7 @@ pub struct RawTransform(glam::Mat4);
 
 impl RawTransform {
     const ATTRIBS: [wgpu::VertexAttribute; 4] =
-        wgpu::vertex_attr_array![2 => Float32x4, 3 => Float32x4, 4 => Float32x4, 5 => Float32x4];
+        wgpu::vertex_attr_array![3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Float32x4];
 
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
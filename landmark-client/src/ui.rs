@@ -0,0 +1,892 @@
+use std::ops::Range;
+
+use shipyard::*;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    atlas::{TextureAtlas, UvRect},
+    camera::Camera,
+    chat::{Chat, ChatBubble},
+    color::RawColor,
+    font,
+    game_mode::GameMode,
+    health::{DamageEvents, Health},
+    input::InputState,
+    inventory::{
+        inventory_rect, slot_at, slot_rect, Inventory, ItemStack, HOTBAR_MARGIN, SLOT_SIZE,
+    },
+    loader::ResourceDictionary,
+    menu::{button_rect, Menu, Screen},
+    minimap,
+    player::{LocalPlayer, RemotePlayer},
+    rendererer::Renderer,
+    settings::{ChatSettings, Settings},
+    world_map::{self, WorldMap},
+};
+
+/// Distance between the edges of a slot and the item inside of it.
+const ITEM_INSET: f32 = 6.0;
+/// Width of the frame around the selected slot.
+const SELECTION_FRAME: f32 = 3.0;
+const CROSSHAIR_SIZE: f32 = 16.0;
+const CROSSHAIR_WIDTH: f32 = 2.0;
+const HEALTH_BAR_HEIGHT: f32 = 8.0;
+/// Space between the health bar and the hotbar.
+const HEALTH_BAR_GAP: f32 = 8.0;
+/// Opacity of the red flash right after the player was hurt.
+const DAMAGE_FLASH_ALPHA: f32 = 0.35;
+/// Players further away than this have no name tags or chat bubbles.
+const BILLBOARD_DISTANCE: f64 = 48.0;
+/// Scale of billboard text one block away from the camera, it shrinks with distance.
+const BILLBOARD_SCALE: f64 = 24.0;
+const MIN_BILLBOARD_SCALE: f64 = 1.0;
+const MAX_BILLBOARD_SCALE: f64 = 3.0;
+/// Space between billboard text and the edges of its background, in font pixels.
+const BILLBOARD_PADDING: f32 = 2.0;
+/// Chat bubbles wrap lines longer than this many characters.
+const CHAT_BUBBLE_LINE_LENGTH: usize = 24;
+/// Distance between the chat window and the left edge of the window.
+const CHAT_MARGIN: f32 = 12.0;
+/// Space between chat text and the edges of its background, in pixels.
+const CHAT_PADDING: f32 = 4.0;
+const CHAT_SCALE: f32 = 2.0;
+/// The chat window wraps lines longer than this many characters.
+const CHAT_LINE_LENGTH: usize = 50;
+const CHAT_VISIBLE_LINES: usize = 10;
+/// While the chat is closed, messages are shown for this many seconds after they arrive.
+const CHAT_MESSAGE_DURATION: f32 = 10.0;
+/// Messages fade out during the last second of their duration.
+const CHAT_FADE_DURATION: f32 = 1.0;
+/// Edge length of the minimap on the screen, in pixels.
+const MINIMAP_SIZE: f32 = 192.0;
+/// Distance between the minimap and the top right corner of the window.
+const MINIMAP_MARGIN: f32 = 12.0;
+const MINIMAP_FRAME: f32 = 2.0;
+/// Length of the arrow marking the player on the minimap.
+const MINIMAP_MARKER_SIZE: f32 = 10.0;
+const WORLD_MAP_MARKER_SIZE: f32 = 16.0;
+const WORLD_MAP_LABEL_SCALE: f32 = 2.0;
+/// Distance between the zoom label and the bottom edge of the window.
+const WORLD_MAP_LABEL_MARGIN: f32 = 24.0;
+const MENU_HEADING_SCALE: f32 = 4.0;
+const MENU_BUTTON_SCALE: f32 = 2.0;
+
+/// Vertex of the 2D UI, positioned in normalized device coordinates.
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct UiVertex {
+    pub position: glam::Vec2,
+    pub uv: glam::Vec2,
+    /// Linear color with alpha, multiplied with the sampled texture.
+    pub color: glam::Vec4,
+}
+
+impl UiVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Geometry of the UI uploaded for the current frame.
+#[derive(Debug)]
+pub struct UiModel {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    /// Indices of quads sampling the tiles of the map screen, drawn first.
+    pub world_map_indices: Range<u32>,
+    /// Indices of quads sampling the minimap, drawn before the atlas so other elements can cover it.
+    pub minimap_indices: Range<u32>,
+    /// Indices of quads sampling the block atlas.
+    pub atlas_indices: Range<u32>,
+    /// Indices of quads sampling the font, drawn after the atlas ones.
+    pub text_indices: Range<u32>,
+}
+
+impl UiModel {
+    fn new(device: &wgpu::Device, builder: &UiBuilder) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("ui_vertex_buffer"),
+            contents: bytemuck::cast_slice(&builder.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices: Vec<u16> = builder
+            .world_map_indices
+            .iter()
+            .chain(builder.minimap_indices.iter())
+            .chain(builder.atlas_indices.iter())
+            .chain(builder.text_indices.iter())
+            .copied()
+            .collect();
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("ui_index_buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let world_map_end = builder.world_map_indices.len() as u32;
+        let minimap_end = world_map_end + builder.minimap_indices.len() as u32;
+        let atlas_end = minimap_end + builder.atlas_indices.len() as u32;
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            world_map_indices: 0..world_map_end,
+            minimap_indices: world_map_end..minimap_end,
+            atlas_indices: minimap_end..atlas_end,
+            text_indices: atlas_end..indices.len() as u32,
+        }
+    }
+}
+
+/// Texture a quad of the UI samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UiTexture {
+    Atlas,
+    Font,
+    Minimap,
+    WorldMap,
+}
+
+/// Collects quads given in pixels, with the origin in the top left corner of the window.
+#[derive(Debug)]
+struct UiBuilder {
+    screen_size: glam::Vec2,
+    vertices: Vec<UiVertex>,
+    world_map_indices: Vec<u16>,
+    minimap_indices: Vec<u16>,
+    atlas_indices: Vec<u16>,
+    text_indices: Vec<u16>,
+}
+
+impl UiBuilder {
+    fn new(screen_size: glam::Vec2) -> Self {
+        Self {
+            screen_size,
+            vertices: Vec::new(),
+            world_map_indices: Vec::new(),
+            minimap_indices: Vec::new(),
+            atlas_indices: Vec::new(),
+            text_indices: Vec::new(),
+        }
+    }
+
+    /// Adds a quad textured from the block atlas.
+    fn quad(&mut self, min: glam::Vec2, max: glam::Vec2, uv: UvRect, color: glam::Vec4) {
+        self.push_quad(min, max, uv, color, UiTexture::Atlas);
+    }
+
+    fn push_quad(
+        &mut self,
+        min: glam::Vec2,
+        max: glam::Vec2,
+        uv: UvRect,
+        color: glam::Vec4,
+        texture: UiTexture,
+    ) {
+        self.push_corners(
+            [
+                min,
+                glam::Vec2::new(max.x, min.y),
+                glam::Vec2::new(min.x, max.y),
+                max,
+            ],
+            uv,
+            color,
+            texture,
+        );
+    }
+
+    /// Adds a quad with arbitrary corners, given in the order top left, top right, bottom left
+    /// and bottom right of the texture rectangle.
+    fn push_corners(
+        &mut self,
+        corners: [glam::Vec2; 4],
+        uv: UvRect,
+        color: glam::Vec4,
+        texture: UiTexture,
+    ) {
+        let to_ndc = |pixel: glam::Vec2| {
+            glam::Vec2::new(
+                pixel.x / self.screen_size.x * 2.0 - 1.0,
+                1.0 - pixel.y / self.screen_size.y * 2.0,
+            )
+        };
+
+        let first = self.vertices.len() as u16;
+        let uvs = [
+            uv.min,
+            glam::Vec2::new(uv.max.x, uv.min.y),
+            glam::Vec2::new(uv.min.x, uv.max.y),
+            uv.max,
+        ];
+
+        for (position, uv) in corners.into_iter().zip(uvs) {
+            self.vertices.push(UiVertex {
+                position: to_ndc(position),
+                uv,
+                color,
+            });
+        }
+
+        let indices = match texture {
+            UiTexture::Atlas => &mut self.atlas_indices,
+            UiTexture::Font => &mut self.text_indices,
+            UiTexture::Minimap => &mut self.minimap_indices,
+            UiTexture::WorldMap => &mut self.world_map_indices,
+        };
+
+        indices.extend([first, first + 2, first + 1, first + 1, first + 2, first + 3]);
+    }
+
+    /// Adds an untextured quad.
+    fn rect(&mut self, min: glam::Vec2, max: glam::Vec2, color: glam::Vec4) {
+        self.quad(
+            min,
+            max,
+            TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT),
+            color,
+        );
+    }
+
+    /// Adds a single line of text with its top left corner at `position`, each font pixel spanning `scale` pixels.
+    fn text(&mut self, position: glam::Vec2, text: &str, scale: f32, color: glam::Vec4) {
+        let glyph_size =
+            glam::Vec2::new(font::GLYPH_WIDTH as f32, font::GLYPH_HEIGHT as f32) * scale;
+
+        for (idx, character) in text.chars().enumerate() {
+            if character == ' ' {
+                continue;
+            }
+
+            let min = position + glam::Vec2::new((idx as u32 * font::ADVANCE) as f32 * scale, 0.0);
+            self.push_quad(
+                min,
+                min + glyph_size,
+                font::glyph_uv(character),
+                color,
+                UiTexture::Font,
+            );
+        }
+    }
+}
+
+/// Adds lines of text centered horizontally on `center_x` above `bottom`, on a background.
+/// Returns the top edge of the background.
+fn build_label(
+    builder: &mut UiBuilder,
+    center_x: f32,
+    bottom: f32,
+    lines: &[String],
+    scale: f32,
+    background: glam::Vec4,
+    color: glam::Vec4,
+) -> f32 {
+    let width = lines
+        .iter()
+        .map(|line| font::text_width(line))
+        .max()
+        .unwrap_or_default() as f32;
+    let height = (lines.len() as u32 * font::LINE_HEIGHT) as f32;
+    let size = (glam::Vec2::new(width, height) + 2.0 * BILLBOARD_PADDING) * scale;
+
+    let min = glam::Vec2::new(center_x - size.x / 2.0, bottom - size.y);
+    builder.rect(min, min + size, background);
+
+    for (idx, line) in lines.iter().enumerate() {
+        let line_width = font::text_width(line) as f32 * scale;
+        let position = glam::Vec2::new(
+            center_x - line_width / 2.0,
+            min.y + (BILLBOARD_PADDING + (idx as u32 * font::LINE_HEIGHT) as f32) * scale,
+        );
+
+        builder.text(position, line, scale, color);
+    }
+
+    min.y
+}
+
+/// Splits text into lines of at most `max_length` characters, breaking between words where possible.
+fn wrap_text(text: &str, max_length: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let word: Vec<char> = word.chars().collect();
+
+        for part in word.chunks(max_length) {
+            let line_length = line.chars().count();
+
+            if line_length > 0 && line_length + 1 + part.len() > max_length {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+
+            line.extend(part);
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Adds name tags and chat bubbles above remote players, facing the camera.
+fn build_billboards(
+    builder: &mut UiBuilder,
+    camera: &Camera,
+    chat_settings: &ChatSettings,
+    remote_players: &View<RemotePlayer>,
+    chat_bubbles: &View<ChatBubble>,
+) {
+    for (id, player) in remote_players.iter().with_id() {
+        let anchor = player.position + glam::DVec3::Y * RemotePlayer::NAME_TAG_HEIGHT;
+        let distance = (anchor - camera.eye).length();
+
+        if distance > BILLBOARD_DISTANCE {
+            continue;
+        }
+
+        let Some(screen_position) = camera.world_to_screen(anchor, builder.screen_size) else {
+            continue;
+        };
+
+        let scale = (BILLBOARD_SCALE / distance).clamp(MIN_BILLBOARD_SCALE, MAX_BILLBOARD_SCALE);
+        let mut bottom = screen_position.y;
+
+        if chat_settings.name_tags {
+            bottom = build_label(
+                builder,
+                screen_position.x,
+                bottom,
+                std::slice::from_ref(&player.name),
+                scale as f32,
+                glam::Vec4::new(0.0, 0.0, 0.0, 0.5),
+                glam::Vec4::ONE,
+            );
+        }
+
+        if !chat_settings.bubbles {
+            continue;
+        }
+
+        if let Ok(bubble) = chat_bubbles.get(id) {
+            build_label(
+                builder,
+                screen_position.x,
+                bottom - BILLBOARD_PADDING * scale as f32,
+                &wrap_text(&bubble.text, CHAT_BUBBLE_LINE_LENGTH),
+                scale as f32,
+                glam::Vec4::new(1.0, 1.0, 1.0, 0.8),
+                glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
+            );
+        }
+    }
+}
+
+/// Adds the hotbar slots, the selection frame and items of the hotbar.
+fn build_hotbar(
+    builder: &mut UiBuilder,
+    inventory: &Inventory,
+    resource_dictionary: &ResourceDictionary,
+) {
+    for (idx, slot) in inventory.hotbar().iter().enumerate() {
+        let (min, max) = slot_rect(builder.screen_size, idx);
+
+        if idx == inventory.selected() {
+            builder.rect(
+                min - SELECTION_FRAME,
+                max + SELECTION_FRAME,
+                glam::Vec4::new(1.0, 1.0, 1.0, 0.9),
+            );
+        }
+
+        builder.rect(min, max, glam::Vec4::new(0.05, 0.05, 0.05, 0.7));
+
+        if let Some(stack) = slot {
+            build_item_stack(builder, min, max, stack, resource_dictionary);
+        }
+    }
+}
+
+/// Adds the icon of the item of a stack inside of a slot, with its size.
+fn build_item_stack(
+    builder: &mut UiBuilder,
+    min: glam::Vec2,
+    max: glam::Vec2,
+    stack: &ItemStack,
+    resource_dictionary: &ResourceDictionary,
+) {
+    let Some(item_id) = resource_dictionary.find_item_id(&stack.item) else {
+        return;
+    };
+
+    let item = resource_dictionary.get_item_data_from_id(item_id);
+    let placed_block = item
+        .places_block
+        .as_deref()
+        .and_then(|name| resource_dictionary.find_block_id(name));
+
+    // items without an icon are drawn as the block they place
+    let icon = match (resource_dictionary.get_item_uv(item_id), placed_block) {
+        (Some(uv), _) => Some((uv, glam::Vec4::ONE)),
+        (None, Some(block_id)) => {
+            let color: RawColor = resource_dictionary
+                .get_block_data_from_id(block_id)
+                .color
+                .into();
+
+            Some((
+                resource_dictionary.get_block_uv(block_id),
+                color.with_alpha(1.0),
+            ))
+        }
+        (None, None) => None,
+    };
+
+    if let Some((uv, color)) = icon {
+        builder.quad(min + ITEM_INSET, max - ITEM_INSET, uv, color);
+    }
+
+    // there is no text rendering yet, so the stack size is shown as a bar below the item
+    let fill = stack.count as f32 / item.max_stack_size.max(1) as f32;
+    let bar_min = glam::Vec2::new(min.x + ITEM_INSET, max.y - ITEM_INSET / 2.0 - 1.0);
+    let bar_width = (SLOT_SIZE - 2.0 * ITEM_INSET) * fill;
+
+    builder.rect(
+        bar_min,
+        bar_min + glam::Vec2::new(bar_width, 2.0),
+        glam::Vec4::new(1.0, 1.0, 1.0, 0.9),
+    );
+}
+
+/// Adds all slots of the open inventory, the hotbar stays in its place below the other slots.
+/// The held stack follows the cursor.
+fn build_inventory(
+    builder: &mut UiBuilder,
+    inventory: &Inventory,
+    resource_dictionary: &ResourceDictionary,
+    cursor: glam::Vec2,
+) {
+    let screen_size = builder.screen_size;
+    let (min, max) = inventory_rect(screen_size);
+    builder.rect(min, max, glam::Vec4::new(0.2, 0.2, 0.2, 0.9));
+
+    build_hotbar(builder, inventory, resource_dictionary);
+
+    let hovered = slot_at(screen_size, cursor);
+
+    for (idx, slot) in inventory
+        .slots()
+        .iter()
+        .enumerate()
+        .skip(Inventory::HOTBAR_SIZE)
+    {
+        let (min, max) = slot_rect(screen_size, idx);
+        builder.rect(min, max, glam::Vec4::new(0.05, 0.05, 0.05, 0.7));
+
+        if let Some(stack) = slot {
+            build_item_stack(builder, min, max, stack, resource_dictionary);
+        }
+    }
+
+    if let Some(idx) = hovered {
+        let (min, max) = slot_rect(screen_size, idx);
+        builder.rect(min, max, glam::Vec4::new(1.0, 1.0, 1.0, 0.2));
+    }
+
+    if let Some(held) = inventory.held() {
+        let half_size = glam::Vec2::splat(SLOT_SIZE / 2.0);
+        build_item_stack(
+            builder,
+            cursor - half_size,
+            cursor + half_size,
+            held,
+            resource_dictionary,
+        );
+    }
+}
+
+/// Adds recent chat messages above the hotbar, and the input line while the chat is open.
+fn build_chat(builder: &mut UiBuilder, chat: &Chat) {
+    let line_height = font::LINE_HEIGHT as f32 * CHAT_SCALE;
+    let width = (CHAT_LINE_LENGTH as u32 * font::ADVANCE) as f32 * CHAT_SCALE + 2.0 * CHAT_PADDING;
+    let left = CHAT_MARGIN;
+    let mut bottom = builder.screen_size.y - 2.0 * HOTBAR_MARGIN - SLOT_SIZE - SELECTION_FRAME;
+
+    if chat.open {
+        let top = bottom - line_height - 2.0 * CHAT_PADDING;
+        builder.rect(
+            glam::Vec2::new(left, top),
+            glam::Vec2::new(left + width, bottom),
+            glam::Vec4::new(0.0, 0.0, 0.0, 0.6),
+        );
+
+        // long lines scroll, so the end of the typed text stays visible
+        let input: Vec<char> = format!("> {}_", chat.input).chars().collect();
+        let visible: String = input[input.len().saturating_sub(CHAT_LINE_LENGTH)..]
+            .iter()
+            .collect();
+
+        builder.text(
+            glam::Vec2::new(left + CHAT_PADDING, top + CHAT_PADDING),
+            &visible,
+            CHAT_SCALE,
+            glam::Vec4::ONE,
+        );
+
+        bottom = top - CHAT_PADDING;
+    }
+
+    let newest = chat.history.len().saturating_sub(chat.scroll);
+    let mut lines: Vec<(String, glam::Vec4)> = Vec::new();
+    let mut background_alpha: f32 = 0.0;
+
+    for line in chat.history.range(..newest).rev() {
+        let alpha = if chat.open {
+            1.0
+        } else {
+            let remaining = CHAT_MESSAGE_DURATION - line.received_at.elapsed().as_secs_f32();
+            (remaining / CHAT_FADE_DURATION).min(1.0)
+        };
+
+        // older messages would be faded out too
+        if alpha <= 0.0 || lines.len() >= CHAT_VISIBLE_LINES {
+            break;
+        }
+
+        background_alpha = background_alpha.max(alpha);
+
+        let (text, color) = match &line.message.sender {
+            Some(sender) => (
+                format!("<{sender}> {}", line.message.text),
+                glam::Vec4::new(1.0, 1.0, 1.0, alpha),
+            ),
+            None => (
+                line.message.text.clone(),
+                glam::Vec4::new(1.0, 0.9, 0.4, alpha),
+            ),
+        };
+
+        for wrapped in wrap_text(&text, CHAT_LINE_LENGTH).into_iter().rev() {
+            lines.push((wrapped, color));
+        }
+    }
+
+    lines.truncate(CHAT_VISIBLE_LINES);
+
+    if lines.is_empty() {
+        return;
+    }
+
+    let top = bottom - lines.len() as f32 * line_height - 2.0 * CHAT_PADDING;
+    builder.rect(
+        glam::Vec2::new(left, top),
+        glam::Vec2::new(left + width, bottom),
+        glam::Vec4::new(0.0, 0.0, 0.0, 0.4 * background_alpha),
+    );
+
+    for (idx, (text, color)) in lines.iter().rev().enumerate() {
+        let position = glam::Vec2::new(
+            left + CHAT_PADDING,
+            top + CHAT_PADDING + idx as f32 * line_height,
+        );
+
+        builder.text(position, text, CHAT_SCALE, *color);
+    }
+}
+
+/// Adds the open screen over the world, with its heading and buttons.
+fn build_menu(builder: &mut UiBuilder, menu: &Menu, cursor: glam::Vec2) {
+    let overlay = match menu.screen {
+        // the map covers the world by itself
+        Screen::Playing | Screen::Map => return,
+        Screen::Paused | Screen::Inventory => glam::Vec4::new(0.0, 0.0, 0.0, 0.5),
+        Screen::Dead => glam::Vec4::new(0.5, 0.0, 0.0, 0.5),
+        // the world behind the title screen is empty
+        Screen::Title => glam::Vec4::new(0.1, 0.1, 0.15, 1.0),
+    };
+
+    let screen_size = builder.screen_size;
+    builder.rect(glam::Vec2::ZERO, screen_size, overlay);
+
+    let heading = menu.screen.heading();
+    let heading_size = glam::Vec2::new(font::text_width(heading) as f32, font::GLYPH_HEIGHT as f32)
+        * MENU_HEADING_SCALE;
+
+    builder.text(
+        glam::Vec2::new(screen_size.x / 2.0, screen_size.y / 3.0) - heading_size / 2.0,
+        heading,
+        MENU_HEADING_SCALE,
+        glam::Vec4::ONE,
+    );
+
+    let buttons = menu.screen.buttons();
+    let hovered = menu.button_at(screen_size, cursor);
+
+    for (idx, (label, _)) in buttons.iter().enumerate() {
+        let (min, max) = button_rect(screen_size, idx, buttons.len());
+
+        let background = if hovered == Some(idx) {
+            glam::Vec4::new(0.4, 0.4, 0.4, 0.9)
+        } else {
+            glam::Vec4::new(0.15, 0.15, 0.15, 0.9)
+        };
+
+        builder.rect(min, max, background);
+
+        let label_size = glam::Vec2::new(font::text_width(label) as f32, font::GLYPH_HEIGHT as f32)
+            * MENU_BUTTON_SCALE;
+
+        builder.text(
+            (min + max - label_size) / 2.0,
+            label,
+            MENU_BUTTON_SCALE,
+            glam::Vec4::ONE,
+        );
+    }
+}
+
+fn build_crosshair(builder: &mut UiBuilder) {
+    let center = builder.screen_size / 2.0;
+    let color = glam::Vec4::new(1.0, 1.0, 1.0, 0.8);
+    let half_size = CROSSHAIR_SIZE / 2.0;
+    let half_width = CROSSHAIR_WIDTH / 2.0;
+
+    builder.rect(
+        center - glam::Vec2::new(half_size, half_width),
+        center + glam::Vec2::new(half_size, half_width),
+        color,
+    );
+    builder.rect(
+        center - glam::Vec2::new(half_width, half_size),
+        center + glam::Vec2::new(half_width, half_size),
+        color,
+    );
+}
+
+/// Adds a bar above the hotbar showing how much health is left.
+fn build_health_bar(builder: &mut UiBuilder, health: &Health) {
+    let (first, _) = slot_rect(builder.screen_size, 0);
+    let (_, last) = slot_rect(builder.screen_size, Inventory::HOTBAR_SIZE - 1);
+
+    let max = glam::Vec2::new(last.x, first.y - HEALTH_BAR_GAP);
+    let min = glam::Vec2::new(first.x, max.y - HEALTH_BAR_HEIGHT);
+
+    builder.rect(min, max, glam::Vec4::new(0.05, 0.05, 0.05, 0.7));
+
+    let filled = health.current as f32 / health.max.max(1) as f32;
+    builder.rect(
+        min,
+        glam::Vec2::new(min.x + (max.x - min.x) * filled, max.y),
+        glam::Vec4::new(0.8, 0.1, 0.1, 0.9),
+    );
+}
+
+/// Adds the minimap to the top right corner, with an arrow in the middle pointing where the
+/// camera looks. North is up.
+fn build_minimap(builder: &mut UiBuilder, camera: &Camera) {
+    let min = glam::Vec2::new(
+        builder.screen_size.x - MINIMAP_MARGIN - MINIMAP_SIZE,
+        MINIMAP_MARGIN,
+    );
+    let max = min + glam::Vec2::splat(MINIMAP_SIZE);
+    let frame_color = glam::Vec4::new(0.05, 0.05, 0.05, 0.8);
+
+    builder.push_quad(
+        min,
+        max,
+        UvRect {
+            min: glam::Vec2::ZERO,
+            max: glam::Vec2::ONE,
+        },
+        glam::Vec4::ONE,
+        UiTexture::Minimap,
+    );
+
+    // the edges are separate, atlas quads are drawn over the minimap
+    let frame = glam::Vec2::splat(MINIMAP_FRAME);
+    for (edge_min, edge_max) in [
+        (min - frame, glam::Vec2::new(max.x + MINIMAP_FRAME, min.y)),
+        (glam::Vec2::new(min.x - MINIMAP_FRAME, max.y), max + frame),
+        (min - frame, glam::Vec2::new(min.x, max.y + MINIMAP_FRAME)),
+        (glam::Vec2::new(max.x, min.y - MINIMAP_FRAME), max + frame),
+    ] {
+        builder.rect(edge_min, edge_max, frame_color);
+    }
+
+    // the player stands in the middle of the texel right of and below the center
+    let texel = MINIMAP_SIZE / minimap::SIZE as f32;
+    let center = (min + max) / 2.0 + glam::Vec2::splat(texel / 2.0);
+
+    build_player_marker(builder, center, camera.yaw, MINIMAP_MARKER_SIZE);
+}
+
+/// Adds an arrow of `length` pixels centered on `center`, pointing in the direction of `yaw` on a
+/// map with north up.
+fn build_player_marker(builder: &mut UiBuilder, center: glam::Vec2, yaw: f32, length: f32) {
+    let yaw = yaw.to_radians();
+    let forward = glam::Vec2::new(yaw.sin(), -yaw.cos()) * length;
+    let right = glam::Vec2::new(-forward.y, forward.x);
+    let back = center - forward * 0.5;
+
+    builder.push_corners(
+        [
+            center + forward * 0.5,
+            back + right * 0.4,
+            back - right * 0.4,
+            back,
+        ],
+        TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT),
+        glam::Vec4::new(1.0, 0.2, 0.2, 1.0),
+        UiTexture::Atlas,
+    );
+}
+
+/// Adds the map screen, the explored tiles around the player on a background with the zoom below.
+fn build_world_map(builder: &mut UiBuilder, world_map: &WorldMap, camera: &Camera) {
+    let screen_size = builder.screen_size;
+
+    builder.push_quad(
+        glam::Vec2::ZERO,
+        screen_size,
+        world_map::slot_uv(world_map::BACKGROUND_SLOT),
+        glam::Vec4::ONE,
+        UiTexture::WorldMap,
+    );
+
+    for tile in &world_map.visible {
+        builder.push_quad(
+            tile.min,
+            tile.max,
+            world_map::slot_uv(tile.slot),
+            glam::Vec4::ONE,
+            UiTexture::WorldMap,
+        );
+    }
+
+    build_player_marker(
+        builder,
+        screen_size / 2.0,
+        camera.yaw,
+        WORLD_MAP_MARKER_SIZE,
+    );
+
+    let label = format!("Zoom {}x, scroll to change", world_map.zoom());
+    let label_size = glam::Vec2::new(font::text_width(&label) as f32, font::GLYPH_HEIGHT as f32)
+        * WORLD_MAP_LABEL_SCALE;
+
+    builder.text(
+        glam::Vec2::new(
+            (screen_size.x - label_size.x) / 2.0,
+            screen_size.y - WORLD_MAP_LABEL_MARGIN - label_size.y,
+        ),
+        &label,
+        WORLD_MAP_LABEL_SCALE,
+        glam::Vec4::ONE,
+    );
+}
+
+/// Tints the whole screen red for a moment after the player was hurt.
+fn build_damage_flash(builder: &mut UiBuilder, flash: f32) {
+    if flash <= 0.0 {
+        return;
+    }
+
+    builder.rect(
+        glam::Vec2::ZERO,
+        builder.screen_size,
+        glam::Vec4::new(1.0, 0.0, 0.0, DAMAGE_FLASH_ALPHA * flash),
+    );
+}
+
+/// Rebuilds the HUD drawn in the UI pass.
+#[allow(clippy::too_many_arguments)]
+pub fn update_hud_sys(
+    mut renderer: UniqueViewMut<Renderer>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    camera: UniqueView<Camera>,
+    settings: UniqueView<Settings>,
+    players: View<LocalPlayer>,
+    chat: UniqueView<Chat>,
+    world_map: UniqueView<WorldMap>,
+    // grouped, systems can't take more than ten views
+    (remote_players, chat_bubbles): (View<RemotePlayer>, View<ChatBubble>),
+    (inventories, healths): (View<Inventory>, View<Health>),
+    (menu, input_state, damage_events, game_mode): (
+        UniqueView<Menu>,
+        UniqueView<InputState>,
+        UniqueView<DamageEvents>,
+        UniqueView<GameMode>,
+    ),
+) {
+    let screen_size = glam::Vec2::new(renderer.size.width as f32, renderer.size.height as f32);
+    let mut builder = UiBuilder::new(screen_size);
+
+    if menu.screen == Screen::Map {
+        build_world_map(&mut builder, &world_map, &camera);
+        build_chat(&mut builder, &chat);
+    } else if menu.screen != Screen::Title {
+        build_damage_flash(&mut builder, damage_events.flash());
+
+        build_billboards(
+            &mut builder,
+            &camera,
+            &settings.chat,
+            &remote_players,
+            &chat_bubbles,
+        );
+
+        if !menu.is_open() {
+            build_crosshair(&mut builder);
+        }
+
+        if menu.screen != Screen::Inventory {
+            if let Some((_, inventory)) = (&players, &inventories).iter().next() {
+                build_hotbar(&mut builder, inventory, &resource_dictionary);
+            }
+
+            build_minimap(&mut builder, &camera);
+
+            // the player can't be hurt in creative
+            if let Some((_, health)) = (&players, &healths)
+                .iter()
+                .next()
+                .filter(|_| game_mode.takes_damage())
+            {
+                build_health_bar(&mut builder, health);
+            }
+        }
+
+        build_chat(&mut builder, &chat);
+    }
+
+    build_menu(&mut builder, &menu, input_state.cursor_position);
+
+    // drawn over the overlay of the menu
+    if menu.screen == Screen::Inventory {
+        if let Some((_, inventory)) = (&players, &inventories).iter().next() {
+            build_inventory(
+                &mut builder,
+                inventory,
+                &resource_dictionary,
+                input_state.cursor_position,
+            );
+        }
+    }
+
+    renderer.hud_model = Some(UiModel::new(&renderer.device, &builder));
+}
@@ -0,0 +1,292 @@
+//! Models made in MagicaVoxel, loaded from `.vox` files in resource packs.
+//!
+//! Models in `structures/` become schematics placed into the world by the world generator, every
+//! color of their palette becomes a block of that color. Models in `entities/` become meshes of the mobs
+//! with the same kind as the file name.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    atlas::TextureAtlas,
+    block::{BlockData, BlockFaces},
+    color::Color,
+    game_map::{BlockId, FaceDirection, InnerChunkCoords},
+    loader::{ResourceDictionary, ResourceError, ResourcePacks},
+    mesher::ModelConstructorChunkExt,
+    model::ModelConstructor,
+    schematic::Schematic,
+};
+
+const MAGIC: &[u8; 4] = b"VOX ";
+/// Voxels along one edge of a block, entity meshes are scaled down by it.
+const VOXELS_PER_BLOCK: f32 = 16.0;
+
+/// The first model of a `.vox` file, converted so that Y points up.
+#[derive(Debug, Clone)]
+pub struct VoxModel {
+    pub size: glam::IVec3,
+    /// Position and palette index of every voxel, index 0 is never used.
+    voxels: Vec<(glam::IVec3, u8)>,
+    palette: Vec<Color>,
+}
+
+impl VoxModel {
+    pub fn load(path: &Path) -> Result<Self, ResourceError> {
+        let bytes = fs::read(path).map_err(|source| ResourceError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::parse(&bytes).map_err(|reason| ResourceError::Invalid {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    /// Parses the file, which consists of chunks with a four letter ID, the length of their content and
+    /// the length of their children. The `MAIN` chunk contains `SIZE` and `XYZI` chunks of every model
+    /// followed by the `RGBA` palette. Scene graph and material chunks are ignored.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err("not a MagicaVoxel file".to_string());
+        }
+
+        let mut size = None;
+        let mut voxels = None;
+        let mut palette = None;
+        let mut model_count = 0;
+
+        // children of MAIN follow its header directly, so every chunk can be read in sequence
+        let mut offset = 8;
+        while offset + 12 <= bytes.len() {
+            let id = &bytes[offset..offset + 4];
+            let content_len = read_u32(bytes, offset + 4)? as usize;
+            let content = bytes
+                .get(offset + 12..offset + 12 + content_len)
+                .ok_or("chunk is past the end of the file")?;
+
+            offset += 12 + content_len;
+
+            match id {
+                b"SIZE" => {
+                    model_count += 1;
+
+                    if size.is_none() {
+                        // MagicaVoxel uses Z as the up axis
+                        size = Some(glam::IVec3::new(
+                            read_u32(content, 0)? as i32,
+                            read_u32(content, 8)? as i32,
+                            read_u32(content, 4)? as i32,
+                        ));
+                    }
+                }
+                b"XYZI" if voxels.is_none() => {
+                    let count = read_u32(content, 0)? as usize;
+                    let data = content
+                        .get(4..4 + count.saturating_mul(4))
+                        .ok_or("voxel data is shorter than its voxel count")?;
+
+                    voxels = Some(
+                        data.chunks_exact(4)
+                            .map(|voxel| {
+                                let position = glam::IVec3::new(
+                                    voxel[0] as i32,
+                                    voxel[2] as i32,
+                                    voxel[1] as i32,
+                                );
+
+                                (position, voxel[3])
+                            })
+                            .collect::<Vec<_>>(),
+                    );
+                }
+                b"RGBA" => {
+                    // the palette is shifted by one, color index 0 means an empty voxel
+                    let mut colors = vec![Color { r: 0, g: 0, b: 0 }];
+                    colors.extend(content.chunks_exact(4).take(255).map(|rgba| Color {
+                        r: rgba[0],
+                        g: rgba[1],
+                        b: rgba[2],
+                    }));
+
+                    palette = Some(colors);
+                }
+                _ => {}
+            }
+        }
+
+        if model_count > 1 {
+            log::warn!("Only the first of {model_count} models in a .vox file is used");
+        }
+
+        let size = size.ok_or("file has no model")?;
+        let voxels = voxels.ok_or("file has no voxel data")?;
+        let palette = palette.ok_or("file has no palette, save it with a newer MagicaVoxel")?;
+
+        if let Some((position, _)) = voxels
+            .iter()
+            .find(|(position, _)| position.cmpge(size).any())
+        {
+            return Err(format!("voxel at {position} is outside of the model"));
+        }
+
+        Ok(Self {
+            size,
+            voxels,
+            palette,
+        })
+    }
+
+    /// Returns the palette indices used by at least one voxel.
+    pub fn used_colors(&self) -> BTreeSet<u8> {
+        self.voxels.iter().map(|(_, color)| *color).collect()
+    }
+
+    pub fn color(&self, idx: u8) -> Color {
+        self.palette
+            .get(idx as usize)
+            .copied()
+            .unwrap_or(Color { r: 0, g: 0, b: 0 })
+    }
+
+    /// Converts a structure model to a schematic of the blocks of its palette colors,
+    /// empty voxels keep the world as it is.
+    pub fn to_schematic(&self, name: &str, resource_dictionary: &ResourceDictionary) -> Schematic {
+        let block_ids: HashMap<u8, BlockId> = self
+            .used_colors()
+            .into_iter()
+            .map(|idx| {
+                (
+                    idx,
+                    resource_dictionary.get_block_id(&palette_block_name(name, idx)),
+                )
+            })
+            .collect();
+
+        Schematic::from_blocks(
+            self.size,
+            self.voxels
+                .iter()
+                .map(|(position, color)| (*position, Some(block_ids[color]))),
+        )
+    }
+
+    /// Builds a mesh of the faces of voxels which aren't covered by other voxels, colored by the palette.
+    /// The mesh is centered horizontally on the origin and stands on it, a block is 16 voxels wide.
+    /// Returns None if the model has too many faces to be indexed by u16.
+    pub fn mesh(&self) -> Option<ModelConstructor> {
+        let occupied: HashSet<glam::IVec3> =
+            self.voxels.iter().map(|(position, _)| *position).collect();
+
+        // meshes aren't textured, so the color multiplies white
+        let uv = TextureAtlas::slot_uv(TextureAtlas::WHITE_SLOT);
+        let mut model_constructor = ModelConstructor::new();
+
+        for (position, color) in self.voxels.iter() {
+            for face in 0..6 {
+                let face_dir = FaceDirection::from(face);
+
+                if occupied.contains(&(*position + face_dir.normal())) {
+                    continue;
+                }
+
+                if model_constructor.vertices.len() + 4 > u16::MAX as usize {
+                    return None;
+                }
+
+                model_constructor.add_block_face(
+                    InnerChunkCoords::new(position.x, position.y, position.z),
+                    face_dir,
+                    self.color(*color),
+                    uv,
+                );
+            }
+        }
+
+        let offset = glam::Vec3::new(self.size.x as f32 / 2.0, 0.0, self.size.z as f32 / 2.0);
+        for vertex in model_constructor.vertices.iter_mut() {
+            vertex.position = (vertex.position - offset) / VOXELS_PER_BLOCK;
+        }
+
+        Some(model_constructor)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|value| u32::from_le_bytes(value.try_into().unwrap()))
+        .ok_or_else(|| "unexpected end of the file".to_string())
+}
+
+/// Models loaded from the resource packs, before block IDs of their palettes are known.
+#[derive(Debug, Default)]
+pub struct VoxResources {
+    pub structures: Vec<(String, VoxModel)>,
+    pub entities: Vec<(String, VoxModel)>,
+}
+
+impl VoxResources {
+    /// Loads every model of the resource packs, models which can't be loaded are skipped.
+    pub fn load(resource_packs: &ResourcePacks) -> Self {
+        Self {
+            structures: load_models(resource_packs, "structures"),
+            entities: load_models(resource_packs, "entities"),
+        }
+    }
+
+    /// Returns a block for every color used by a structure, without a texture so the color shows as is.
+    pub fn palette_blocks(&self) -> Vec<BlockData> {
+        self.structures
+            .iter()
+            .flat_map(|(name, model)| {
+                model.used_colors().into_iter().map(|idx| BlockData {
+                    name: palette_block_name(name, idx),
+                    color: model.color(idx),
+                    texture: None,
+                    variants: Vec::new(),
+                    connected_texture: None,
+                    faces: BlockFaces::default(),
+                    hardness: 1.0,
+                    falls: false,
+                    portal: None,
+                    random_tick: None,
+                    light: None,
+                    fluid: false,
+                })
+            })
+            .collect()
+    }
+}
+
+fn load_models(resource_packs: &ResourcePacks, dir: &str) -> Vec<(String, VoxModel)> {
+    // the directories are optional
+    let Ok(paths) = resource_packs.list(Path::new(dir)) else {
+        return Vec::new();
+    };
+
+    paths
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|extension| extension == "vox"))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+
+            match VoxModel::load(&path) {
+                Ok(model) => Some((name, model)),
+                Err(e) => {
+                    log::error!("{e}, skipping it");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Name of the block a palette color of a structure is placed as, e.g. `tower#12`.
+pub fn palette_block_name(structure: &str, idx: u8) -> String {
+    format!("{structure}#{idx}")
+}
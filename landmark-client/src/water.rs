@@ -0,0 +1,189 @@
+//! Surfaces of fluids like water, drawn after the rest of the world so the terrain below shows through.
+//!
+//! Fluid faces are meshed separately from the other blocks of a chunk and blended over the frame in a
+//! pass of their own. Waves only tilt the normal of the flat surface, which reflects the sky more
+//! strongly at grazing angles. The depth buffer of the world tells how much fluid lies in front of the
+//! terrain, so shallow water stays clear while deep water fades into a dark, opaque color.
+
+use std::time::Instant;
+
+use bytemuck::Zeroable;
+use shipyard::*;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    game_map::ChunkTag,
+    model::{Model, ModelConstructor},
+    rendererer::Renderer,
+    sun::SunCycle,
+};
+
+/// Parameters of the water shader as laid out in the uniform buffer.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct WaterUniform {
+    /// Relative to the origin the world is drawn around.
+    eye: glam::Vec3,
+    /// Seconds the waves have been moving.
+    time: f32,
+    /// Direction from the world towards the sun.
+    sun_direction: glam::Vec3,
+    sun_strength: f32,
+    /// Distance of the near plane of the camera, needed to turn depths back into distances.
+    near: f32,
+    _padding: [f32; 3],
+}
+
+/// Model of the fluid faces of a chunk, only present for chunks containing visible fluids.
+#[derive(Debug, Component)]
+pub struct FluidModel(pub Model);
+
+/// New mesh of the fluid faces of a chunk, which replaces its fluid model.
+#[derive(Debug, Component)]
+pub struct UpdatedFluidModel(pub ModelConstructor);
+
+#[derive(Debug)]
+pub struct WaterSurface {
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    started: Instant,
+}
+
+impl WaterSurface {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("water_buffer"),
+            contents: bytemuck::cast_slice(&[WaterUniform::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        // read as plain floats, the GL backend can't load from depth textures
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("water_bind_group_layout"),
+        });
+
+        Self {
+            buffer,
+            bind_group_layout,
+            started: Instant::now(),
+        }
+    }
+
+    /// Binds the uniform together with the depth of the world drawn into a view. Every view has a depth
+    /// texture of its own, so the bind group is created for each frame.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        depth_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+            ],
+            label: Some("water_bind_group"),
+        })
+    }
+
+    /// Moves the waves on and places the reflections for the camera. Other views reuse the eye of the
+    /// main camera, which is close enough for the debug view.
+    fn update(&self, queue: &wgpu::Queue, camera: &Camera, sun: &SunCycle) {
+        let uniform = WaterUniform {
+            eye: (camera.eye - camera.origin.as_world_position()).as_vec3(),
+            time: self.started.elapsed().as_secs_f32(),
+            sun_direction: sun.direction(),
+            sun_strength: sun.strength(),
+            near: camera.near(),
+            _padding: [0.0; 3],
+        };
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+pub fn update_water_sys(
+    camera: UniqueView<Camera>,
+    sun: UniqueView<SunCycle>,
+    renderer: UniqueView<Renderer>,
+) {
+    renderer.water.update(&renderer.queue, &camera, &sun);
+}
+
+/// Creates models of updated fluid meshes and places them with their chunks, so they rise in together
+/// with the terrain.
+pub fn update_fluid_models_sys(
+    renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    chunks: View<ChunkTag>,
+    models: View<Model>,
+    mut fluid_models: ViewMut<FluidModel>,
+    mut updated_fluid_models: ViewMut<UpdatedFluidModel>,
+) {
+    let mut processed_models: Vec<EntityId> = Vec::new();
+
+    for (id, updated_model) in updated_fluid_models.iter().with_id() {
+        // most chunks contain no fluids
+        if updated_model.0.indices.is_empty() {
+            fluid_models.delete(id);
+        } else {
+            let model = Model::new(&renderer.device, &updated_model.0);
+            fluid_models.add_component_unchecked(id, FluidModel(model));
+        }
+
+        processed_models.push(id);
+    }
+
+    for id in processed_models.into_iter() {
+        updated_fluid_models.delete(id);
+    }
+
+    for (id, (chunk, fluid_model)) in (&chunks, &mut fluid_models).iter().with_id() {
+        let translation = models.get(id).map_or_else(
+            |_| chunk.coords.translation_from(camera.origin),
+            |model| model.translation(),
+        );
+
+        fluid_model.0.set_translation(&renderer.queue, translation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_matches_the_shader_layout() {
+        // the eye, time, sun and near plane, rounded up to a multiple of 16 bytes
+        assert_eq!(std::mem::size_of::<WaterUniform>(), 48);
+    }
+}
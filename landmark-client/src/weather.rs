@@ -0,0 +1,70 @@
+//! Weather decided by the server, shown as rain or snow falling around the camera under a darker sky.
+
+use landmark_protocol::message::Weather;
+use shipyard::*;
+
+use crate::{
+    camera::Camera,
+    game_map::GameMap,
+    particles::Particles,
+    rendererer::{Renderer, SKY_COLOR},
+    UPDATES_PER_SECOND,
+};
+
+/// Seconds it takes for rain to start or stop completely.
+const TRANSITION_SECONDS: f32 = 8.0;
+/// Drops spawned per update at full intensity.
+const MAX_DROPS_PER_UPDATE: f32 = 8.0;
+/// Color of the sky during a storm.
+const STORM_SKY_COLOR: glam::Vec3 = glam::Vec3::new(0.2, 0.22, 0.28);
+
+#[derive(Debug, Default, Unique)]
+pub struct WeatherState {
+    weather: Weather,
+    /// How hard it rains, from 0 when clear to 1 in a storm. Follows the weather smoothly.
+    intensity: f32,
+    /// Fraction of a drop carried over to the next update.
+    drops: f32,
+}
+
+impl WeatherState {
+    pub fn set(&mut self, weather: Weather) {
+        self.weather = weather;
+    }
+
+    /// Returns how hard it rains, meant for everything following the weather, like the sky and ambient sounds.
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    fn target_intensity(&self) -> f32 {
+        match self.weather {
+            Weather::Clear => 0.0,
+            Weather::Rain => 0.5,
+            Weather::Storm => 1.0,
+        }
+    }
+}
+
+/// Moves the intensity towards the weather and spawns rain or snow around the camera.
+pub fn weather_sys(
+    camera: UniqueView<Camera>,
+    game_map: UniqueView<GameMap>,
+    mut weather: UniqueViewMut<WeatherState>,
+    mut particles: UniqueViewMut<Particles>,
+) {
+    let step = 1.0 / (TRANSITION_SECONDS * UPDATES_PER_SECOND as f32);
+    let difference = weather.target_intensity() - weather.intensity;
+    weather.intensity += difference.clamp(-step, step);
+
+    weather.drops += weather.intensity * MAX_DROPS_PER_UPDATE;
+    let count = weather.drops.floor();
+    weather.drops -= count;
+
+    particles.precipitate(camera.eye, &game_map, count as usize);
+}
+
+/// Darkens the sky and the fog matching it while it rains.
+pub fn update_sky_sys(weather: UniqueView<WeatherState>, mut renderer: UniqueViewMut<Renderer>) {
+    renderer.sky_color = SKY_COLOR.lerp(STORM_SKY_COLOR, weather.intensity() * 0.8);
+}
@@ -0,0 +1,177 @@
+//! Editing of many blocks at once from the console.
+//!
+//! A box is selected by picking its two corners, then it can be filled, have one block replaced by
+//! another, or be copied and pasted somewhere else. Edits are returned as transactions, so they
+//! can be recorded in the edit history.
+
+use shipyard::*;
+
+use crate::{
+    edit_history::{apply_changes, Transaction},
+    game_map::{BlockId, GameMap},
+    model::MissingModel,
+    schematic::{Orientation, Schematic},
+};
+
+/// Operations changing more blocks than this are refused, so a typo can't freeze the game.
+pub const MAX_VOLUME: u64 = 64 * 64 * 64;
+
+/// Box of blocks between two corners, both included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub min: glam::IVec3,
+    pub max: glam::IVec3,
+}
+
+impl Selection {
+    pub fn new(a: glam::IVec3, b: glam::IVec3) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    pub fn size(&self) -> glam::IVec3 {
+        self.max - self.min + glam::IVec3::ONE
+    }
+
+    pub fn volume(&self) -> u64 {
+        let size = self.size().as_i64vec3();
+        (size.x * size.y * size.z) as u64
+    }
+
+    /// Returns the positions of all blocks in the box, X changes fastest and Y slowest.
+    pub fn positions(self) -> impl Iterator<Item = glam::IVec3> {
+        let Self { min, max } = self;
+
+        (min.y..=max.y).flat_map(move |y| {
+            (min.z..=max.z)
+                .flat_map(move |z| (min.x..=max.x).map(move |x| glam::IVec3::new(x, y, z)))
+        })
+    }
+}
+
+/// Blocks copied from a selection.
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    schematic: Schematic,
+    /// Position of the minimum corner relative to where the player stood while copying,
+    /// so pasting keeps the blocks in the same place relative to the player.
+    offset: glam::IVec3,
+}
+
+#[derive(Debug, Default, Unique)]
+pub struct WorldEditor {
+    pub corners: [Option<glam::IVec3>; 2],
+    clipboard: Option<Clipboard>,
+}
+
+impl WorldEditor {
+    /// Returns the selected box, or an error naming the corner which wasn't picked yet.
+    pub fn selection(&self) -> Result<Selection, String> {
+        match self.corners {
+            [Some(a), Some(b)] => {
+                let selection = Selection::new(a, b);
+
+                if selection.volume() > MAX_VOLUME {
+                    return Err(format!(
+                        "the selection has {} blocks, at most {MAX_VOLUME} can be edited at once",
+                        selection.volume()
+                    ));
+                }
+
+                Ok(selection)
+            }
+            [None, _] => Err("the first corner isn't set, use /pos1".to_string()),
+            [_, None] => Err("the second corner isn't set, use /pos2".to_string()),
+        }
+    }
+
+    /// Sets every block of the selection to `block`.
+    pub fn fill(
+        &self,
+        game_map: &mut GameMap,
+        missing_models: &mut ViewMut<MissingModel>,
+        block: Option<BlockId>,
+    ) -> Result<Transaction, String> {
+        let selection = self.selection()?;
+        let changes = selection.positions().map(|position| (position, block));
+
+        Ok(apply_changes(game_map, missing_models, changes))
+    }
+
+    /// Replaces every `from` block of the selection with `to`.
+    pub fn replace(
+        &self,
+        game_map: &mut GameMap,
+        missing_models: &mut ViewMut<MissingModel>,
+        from: Option<BlockId>,
+        to: Option<BlockId>,
+    ) -> Result<Transaction, String> {
+        let selection = self.selection()?;
+        let changes: Vec<_> = selection
+            .positions()
+            .filter(|position| game_map.get_block_world(*position) == from)
+            .map(|position| (position, to))
+            .collect();
+
+        Ok(apply_changes(game_map, missing_models, changes))
+    }
+
+    /// Copies the blocks of the selection, returns how many were copied.
+    pub fn copy(&mut self, game_map: &GameMap, player: glam::IVec3) -> Result<u64, String> {
+        let selection = self.selection()?;
+
+        self.clipboard = Some(Clipboard {
+            schematic: Schematic::from_selection(game_map, &selection),
+            offset: selection.min - player,
+        });
+
+        Ok(selection.volume())
+    }
+
+    /// Places the copied blocks relative to the player like they were when copying,
+    /// turned around the player.
+    pub fn paste(
+        &self,
+        game_map: &mut GameMap,
+        missing_models: &mut ViewMut<MissingModel>,
+        player: glam::IVec3,
+        orientation: Orientation,
+    ) -> Result<Transaction, String> {
+        let Some(clipboard) = &self.clipboard else {
+            return Err("nothing was copied, use /copy".to_string());
+        };
+
+        let far_corner = clipboard.offset + clipboard.schematic.size() - glam::IVec3::ONE;
+        let min = orientation
+            .turn(clipboard.offset)
+            .min(orientation.turn(far_corner));
+
+        Ok(clipboard
+            .schematic
+            .stamp(game_map, missing_models, player + min, orientation))
+    }
+
+    /// Replaces the copied blocks with a schematic, pasted with its minimum corner at the player.
+    pub fn set_clipboard(&mut self, schematic: Schematic) {
+        self.clipboard = Some(Clipboard {
+            schematic,
+            offset: glam::IVec3::ZERO,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_includes_both_corners() {
+        let selection = Selection::new(glam::IVec3::new(2, 0, -1), glam::IVec3::new(0, 1, 1));
+
+        assert_eq!(selection.min, glam::IVec3::new(0, 0, -1));
+        assert_eq!(selection.volume(), 3 * 2 * 3);
+        assert_eq!(selection.positions().count(), 18);
+    }
+}
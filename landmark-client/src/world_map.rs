@@ -0,0 +1,356 @@
+//! Map of every part of the world the player has seen, shown on its own screen.
+//!
+//! The map is split into tiles of one chunk column each, drawn from the same columns as the minimap.
+//! Tiles are cached as images in the map directory of the world save together with the explored
+//! chunks and the zoom, and only read from disk once the map shows them. Tiles on the screen are
+//! copied into slots of a texture, which are reused for other tiles once they leave the screen.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use image::{Rgba, RgbaImage};
+use shipyard::*;
+
+use crate::{
+    atlas::UvRect,
+    camera::Camera,
+    game_map::Chunk,
+    loader::ResourceDictionary,
+    menu::{Menu, Screen},
+    minimap::{BlockColors, Minimap},
+    rendererer::Renderer,
+};
+
+/// Edge length of a tile, every pixel shows one column of blocks.
+pub const TILE_SIZE: u32 = Chunk::SIZE as u32;
+/// Edge length of the texture holding the tiles on the screen.
+pub const TEXTURE_SIZE: u32 = 2048;
+const SLOTS_PER_ROW: u32 = TEXTURE_SIZE / TILE_SIZE;
+/// Slot filled with the color of unexplored parts of the map.
+pub const BACKGROUND_SLOT: u32 = 0;
+const BACKGROUND_COLOR: Rgba<u8> = Rgba([15, 15, 20, 255]);
+/// Screen pixels per block.
+const DEFAULT_ZOOM: f32 = 4.0;
+const MIN_ZOOM: f32 = 1.0;
+const MAX_ZOOM: f32 = 16.0;
+/// Changed tiles are written to disk at most this often.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+const STATE_FILE_NAME: &str = "state.ron";
+
+/// Chunk column a tile shows.
+pub type TileCoords = (i32, i32);
+
+/// Part of the map stored in `state.ron`, the tiles are stored next to it.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct MapState {
+    zoom: f32,
+    explored: Vec<TileCoords>,
+}
+
+/// Tile on the screen, placed in pixels with the origin in the top left corner.
+#[derive(Debug, Clone, Copy)]
+pub struct VisibleTile {
+    pub min: glam::Vec2,
+    pub max: glam::Vec2,
+    pub slot: u32,
+}
+
+#[derive(Debug, Unique)]
+pub struct WorldMap {
+    /// None if the map is never written to disk.
+    dir: Option<PathBuf>,
+    /// Chunk columns the player has seen.
+    explored: HashSet<TileCoords>,
+    /// Tiles read from disk or drawn in this session.
+    tiles: HashMap<TileCoords, RgbaImage>,
+    /// Tiles changed since they were last written.
+    unsaved: HashSet<TileCoords>,
+    /// Tiles copied into the texture, by the slot they occupy.
+    slots: HashMap<TileCoords, u32>,
+    free_slots: Vec<u32>,
+    /// Screen pixels per block.
+    zoom: f32,
+    /// Tiles the map screen shows in the current frame.
+    pub visible: Vec<VisibleTile>,
+    last_save: Instant,
+}
+
+impl WorldMap {
+    /// Opens the map cached in `dir`, or starts an empty map which is only kept in memory without one.
+    pub fn open(dir: Option<&Path>) -> Self {
+        let state = dir
+            .and_then(|dir| read_state(&dir.join(STATE_FILE_NAME)))
+            .unwrap_or(MapState {
+                zoom: DEFAULT_ZOOM,
+                explored: Vec::new(),
+            });
+
+        Self {
+            dir: dir.map(Path::to_path_buf),
+            explored: state.explored.into_iter().collect(),
+            tiles: HashMap::new(),
+            unsaved: HashSet::new(),
+            slots: HashMap::new(),
+            // the last slot is handed out first
+            free_slots: (0..SLOTS_PER_ROW * SLOTS_PER_ROW)
+                .rev()
+                .filter(|slot| *slot != BACKGROUND_SLOT)
+                .collect(),
+            zoom: state.zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+            visible: Vec::new(),
+            last_save: Instant::now(),
+        }
+    }
+
+    pub fn is_explored(&self, coords: TileCoords) -> bool {
+        self.explored.contains(&coords)
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Zooms in for positive steps and out for negative ones, doubling the scale with every step.
+    pub fn zoom_by(&mut self, steps: i32) {
+        self.zoom = (self.zoom * 2f32.powi(steps)).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Returns a tile, reading it from disk if it's not in memory yet.
+    fn tile(&mut self, coords: TileCoords) -> &mut RgbaImage {
+        let dir = self.dir.as_deref();
+
+        self.tiles.entry(coords).or_insert_with(|| {
+            dir.and_then(|dir| read_tile(&tile_path(dir, coords)))
+                .unwrap_or_else(|| RgbaImage::new(TILE_SIZE, TILE_SIZE))
+        })
+    }
+
+    /// Marks the chunk column as explored and draws its tile from the columns of the minimap.
+    /// Columns which aren't loaded keep their colors from earlier visits.
+    pub fn draw_tile(&mut self, coords: TileCoords, minimap: &Minimap, colors: &mut BlockColors) {
+        let (origin_x, origin_z) = (coords.0 * Chunk::SIZE, coords.1 * Chunk::SIZE);
+        let tile = self.tile(coords);
+
+        for v in 0..TILE_SIZE {
+            for u in 0..TILE_SIZE {
+                let x = origin_x + u as i32;
+                let z = origin_z + Chunk::SIZE - 1 - v as i32;
+
+                if let Some(color) = minimap.column_color(x, z, colors) {
+                    tile.put_pixel(u, v, color);
+                }
+            }
+        }
+
+        self.explored.insert(coords);
+        self.unsaved.insert(coords);
+
+        // copied into the texture again the next time it's shown
+        if let Some(slot) = self.slots.remove(&coords) {
+            self.free_slots.push(slot);
+        }
+    }
+
+    /// Finds the explored tiles covering a screen of `screen_size` pixels centered on `center`,
+    /// and copies the ones missing from the texture into free slots.
+    fn place_tiles(&mut self, center: glam::DVec2, screen_size: glam::Vec2, renderer: &Renderer) {
+        let blocks_per_pixel = 1.0 / self.zoom as f64;
+        let half_extent = (screen_size / 2.0).as_dvec2() * blocks_per_pixel;
+        let tile_min = ((center - half_extent) / Chunk::SIZE as f64)
+            .floor()
+            .as_ivec2();
+        let tile_max = ((center + half_extent) / Chunk::SIZE as f64)
+            .floor()
+            .as_ivec2();
+
+        let on_screen: Vec<TileCoords> = (tile_min.y..=tile_max.y)
+            .flat_map(|z| (tile_min.x..=tile_max.x).map(move |x| (x, z)))
+            .filter(|coords| self.explored.contains(coords))
+            .collect();
+
+        // slots of tiles which left the screen are only taken back once they run out
+        if on_screen
+            .iter()
+            .any(|coords| !self.slots.contains_key(coords))
+            && self.free_slots.is_empty()
+        {
+            let keep: HashSet<&TileCoords> = on_screen.iter().collect();
+            self.slots.retain(|coords, slot| {
+                let kept = keep.contains(coords);
+                if !kept {
+                    self.free_slots.push(*slot);
+                }
+                kept
+            });
+        }
+
+        self.visible.clear();
+
+        for coords in on_screen {
+            let slot = match self.slots.get(&coords) {
+                Some(slot) => *slot,
+                None => {
+                    // only very large screens at the lowest zoom show more tiles than fit into it
+                    let Some(slot) = self.free_slots.pop() else {
+                        break;
+                    };
+
+                    renderer.world_map_texture.write_region(
+                        &renderer.queue,
+                        slot_origin(slot),
+                        self.tile(coords),
+                    );
+                    self.slots.insert(coords, slot);
+                    slot
+                }
+            };
+
+            let to_screen = |x: i32, z: i32| {
+                let offset = (glam::DVec2::new(x as f64, z as f64) - center) * self.zoom as f64;
+                screen_size / 2.0 + glam::Vec2::new(offset.x as f32, -offset.y as f32)
+            };
+            let (x, z) = (coords.0 * Chunk::SIZE, coords.1 * Chunk::SIZE);
+
+            self.visible.push(VisibleTile {
+                min: to_screen(x, z + Chunk::SIZE),
+                max: to_screen(x + Chunk::SIZE, z),
+                slot,
+            });
+        }
+    }
+
+    /// Writes the explored chunks, the zoom and the changed tiles to the map directory.
+    pub fn save(&mut self) {
+        self.last_save = Instant::now();
+
+        let Some(dir) = self.dir.clone() else {
+            return;
+        };
+
+        if let Err(e) = self.write(&dir) {
+            log::error!("Failed to save the map to {}: {e}", dir.display());
+        }
+    }
+
+    fn write(&mut self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+
+        for coords in self.unsaved.drain() {
+            let Some(tile) = self.tiles.get(&coords) else {
+                continue;
+            };
+
+            tile.save(tile_path(dir, coords))
+                .map_err(io::Error::other)?;
+        }
+
+        let state = MapState {
+            zoom: self.zoom,
+            explored: self.explored.iter().copied().collect(),
+        };
+        let content =
+            ron::to_string(&state).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::write(dir.join(STATE_FILE_NAME), content)
+    }
+}
+
+fn tile_path(dir: &Path, coords: TileCoords) -> PathBuf {
+    dir.join(format!("{}_{}.png", coords.0, coords.1))
+}
+
+fn read_state(path: &Path) -> Option<MapState> {
+    match fs::read_to_string(path) {
+        Ok(content) => ron::from_str(&content)
+            .map_err(|e| log::error!("Failed to parse {}: {e}", path.display()))
+            .ok(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::error!("Failed to read {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Reads a cached tile, tiles of the wrong size are ignored and drawn again.
+fn read_tile(path: &Path) -> Option<RgbaImage> {
+    let tile = image::open(path)
+        .map_err(|e| log::warn!("Failed to read the map tile {}: {e}", path.display()))
+        .ok()?
+        .to_rgba8();
+
+    (tile.dimensions() == (TILE_SIZE, TILE_SIZE)).then_some(tile)
+}
+
+/// Returns the pixel position of the top left corner of a slot in the texture.
+fn slot_origin(slot: u32) -> (u32, u32) {
+    (
+        slot % SLOTS_PER_ROW * TILE_SIZE,
+        slot / SLOTS_PER_ROW * TILE_SIZE,
+    )
+}
+
+/// Returns the normalized texture coordinates of a slot.
+pub fn slot_uv(slot: u32) -> UvRect {
+    let (x, y) = slot_origin(slot);
+    let min = glam::Vec2::new(x as f32, y as f32) / TEXTURE_SIZE as f32;
+
+    UvRect {
+        min,
+        max: min + glam::Vec2::splat(TILE_SIZE as f32 / TEXTURE_SIZE as f32),
+    }
+}
+
+/// Returns the image the background slot is filled with.
+pub fn background_tile() -> RgbaImage {
+    RgbaImage::from_pixel(TILE_SIZE, TILE_SIZE, BACKGROUND_COLOR)
+}
+
+/// Draws the tiles of chunk columns which changed since the last frame. Runs after the minimap
+/// took the new columns.
+pub fn update_world_map_sys(
+    mut world_map: UniqueViewMut<WorldMap>,
+    mut minimap: UniqueViewMut<Minimap>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+) {
+    let changed = minimap.take_changed_chunks();
+
+    if !changed.is_empty() {
+        let mut colors = BlockColors::new(&resource_dictionary);
+
+        for &(x, z) in &changed {
+            world_map.draw_tile((x, z), &minimap, &mut colors);
+
+            // the northern row of the tile south of it is shaded by the slope towards this one
+            if world_map.is_explored((x, z - 1)) && !changed.contains(&(x, z - 1)) {
+                world_map.draw_tile((x, z - 1), &minimap, &mut colors);
+            }
+        }
+    }
+
+    if !world_map.unsaved.is_empty() && world_map.last_save.elapsed() >= SAVE_INTERVAL {
+        world_map.save();
+    }
+}
+
+/// Places the tiles the map screen shows in this frame, while it's open.
+pub fn place_world_map_tiles_sys(
+    mut world_map: UniqueViewMut<WorldMap>,
+    renderer: UniqueView<Renderer>,
+    camera: UniqueView<Camera>,
+    menu: UniqueView<Menu>,
+) {
+    if menu.screen != Screen::Map {
+        world_map.visible.clear();
+        return;
+    }
+
+    let screen_size = glam::Vec2::new(renderer.size.width as f32, renderer.size.height as f32);
+    let center = glam::DVec2::new(camera.eye.x, camera.eye.z);
+
+    world_map.place_tiles(center, screen_size, &renderer);
+}
@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+
+use landmark_protocol::message::ChunkData;
+use landmark_server::{BlockPos, ChunkPos, ChunkStore, WorldTick};
+
+use crate::{
+    coords,
+    game_map::{BlockId, Chunk, ChunkCoords, GameMap},
+    loader::ResourceDictionary,
+    plugins::Plugins,
+    portal::portal_at,
+    priority::ChunkPriority,
+    random_tick,
+    save::WorldSave,
+    settings::WorldSettings,
+    worldgen::{PlacedStructure, Terrain, WorldGenerator},
+};
+
+/// Chunks of the world kept by the server. Chunks are generated by the world generator unless they were
+/// saved to the region files of the world, mods see every chunk generated for the first time.
+#[derive(Debug)]
+pub struct WorldStore {
+    resource_dictionary: ResourceDictionary,
+    /// Names of blocks by ID, which tick callbacks are registered with.
+    block_names: HashMap<BlockId, String>,
+    plugins: Plugins,
+    world_generator: WorldGenerator,
+    world_save: WorldSave,
+    /// Loaded chunks and the ones changed since they were last copied to the save.
+    game_map: GameMap,
+    /// Chunks requested by the server which aren't loaded yet.
+    requested: HashSet<ChunkCoords>,
+}
+
+impl WorldStore {
+    /// Opens the world kept in `world_save`, chunks which were never saved are generated with `settings`.
+    pub fn open(
+        world_save: WorldSave,
+        settings: &WorldSettings,
+        resource_dictionary: ResourceDictionary,
+        plugins: Plugins,
+    ) -> Self {
+        let terrain = Terrain::from_settings(settings, &resource_dictionary);
+        let structures = PlacedStructure::from_settings(settings, &resource_dictionary);
+
+        let block_names = resource_dictionary
+            .block_ids()
+            .iter()
+            .map(|(name, id)| (*id, name.clone()))
+            .collect();
+
+        Self {
+            resource_dictionary,
+            block_names,
+            plugins,
+            world_generator: WorldGenerator::new(terrain, structures),
+            world_save,
+            game_map: GameMap::new(),
+            requested: HashSet::new(),
+        }
+    }
+
+    /// Copies the changed chunks into the save.
+    fn record_edits(&mut self) {
+        self.world_save
+            .record_edits(&mut self.game_map, &self.resource_dictionary);
+    }
+}
+
+impl ChunkStore for WorldStore {
+    fn request(&mut self, pos: ChunkPos, near: [f64; 3]) {
+        let coords = chunk_coords(pos);
+
+        if self.requested.insert(coords) {
+            let priority = ChunkPriority::around(glam::DVec3::from_array(near));
+            self.world_generator.request(coords, &priority);
+        }
+    }
+
+    fn poll(&mut self) -> Vec<ChunkPos> {
+        let mut loaded = Vec::new();
+
+        for (coords, chunk) in self.world_generator.receive() {
+            // the server may have unloaded it meanwhile
+            if !self.requested.remove(&coords) {
+                continue;
+            }
+
+            // edited chunks are loaded from the save, mods have already seen them when they were first generated
+            let chunk = match self
+                .world_save
+                .saved_chunk(coords, &self.resource_dictionary)
+            {
+                Some(mut saved) => {
+                    saved.restore_missing(&chunk);
+                    saved
+                }
+                None => self.plugins.chunk_generated(
+                    coords,
+                    chunk,
+                    self.resource_dictionary.block_ids(),
+                ),
+            };
+
+            self.game_map.chunks.insert(coords, chunk);
+            loaded.push(ChunkPos::new(coords.x, coords.y, coords.z));
+        }
+
+        loaded
+    }
+
+    fn chunk_data(&self, pos: ChunkPos) -> Option<ChunkData> {
+        let chunk = self.game_map.chunks.get(&chunk_coords(pos))?;
+
+        Some(ChunkData {
+            coords: [pos.x, pos.y, pos.z],
+            blocks: chunk.blocks().collect(),
+            biomes: chunk
+                .biomes()
+                .map(|biomes| biomes.iter().map(|biome| biome.id()).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn block(&self, pos: BlockPos) -> Option<Option<u32>> {
+        let (coords, inner) = coords::split_block(block_position(pos));
+
+        self.game_map
+            .chunks
+            .get(&coords)
+            .map(|chunk| chunk.get_block(inner))
+    }
+
+    fn set_block(&mut self, pos: BlockPos, block: Option<u32>) -> bool {
+        let (coords, inner) = coords::split_block(block_position(pos));
+
+        let Some(chunk) = self.game_map.chunks.get_mut(&coords) else {
+            return false;
+        };
+
+        chunk.set_block(inner, block);
+        chunk.set_modified(true);
+        self.game_map.edited.insert(coords);
+
+        true
+    }
+
+    fn block_name(&self, block: u32) -> Option<&str> {
+        self.block_names.get(&block).map(String::as_str)
+    }
+
+    fn sees_sky(&self, pos: BlockPos) -> bool {
+        self.game_map.light_at(block_position(pos)) == Chunk::MAX_LIGHT
+    }
+
+    fn portal_destination(&self, eye: [f64; 3]) -> Option<[f64; 3]> {
+        let eye = glam::DVec3::from_array(eye);
+
+        portal_at(&self.game_map, &self.resource_dictionary, eye)
+            .map(|link| link.destination(eye).to_array())
+    }
+
+    fn register_ticks(&self, world_tick: &mut WorldTick) {
+        random_tick::register(world_tick, &self.resource_dictionary);
+    }
+
+    fn unload(&mut self, pos: ChunkPos) {
+        let coords = chunk_coords(pos);
+
+        self.requested.remove(&coords);
+
+        if self.game_map.edited.contains(&coords) {
+            self.record_edits();
+        }
+        self.game_map.chunks.remove(&coords);
+    }
+
+    fn save(&mut self) {
+        self.record_edits();
+        self.world_save.save_now();
+    }
+}
+
+fn chunk_coords(pos: ChunkPos) -> ChunkCoords {
+    ChunkCoords::new(pos.x, pos.y, pos.z)
+}
+
+fn block_position(pos: BlockPos) -> glam::IVec3 {
+    glam::IVec3::new(pos.x, pos.y, pos.z)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    /// Polls the store until the chunk is loaded.
+    pub fn load(store: &mut WorldStore, pos: ChunkPos) {
+        store.request(pos, [0.0, 0.0, 0.0]);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !store.poll().contains(&pos) {
+            assert!(Instant::now() < deadline, "chunk {pos:?} wasn't loaded");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn open(dir: &Path) -> WorldStore {
+        WorldStore::open(
+            WorldSave::open(dir),
+            &WorldSettings::default(),
+            ResourceDictionary::builtin(Vec::new()).unwrap(),
+            Plugins::new(),
+        )
+    }
+
+    #[test]
+    fn changed_blocks_are_kept_after_unloading_and_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = open(dir.path());
+        let chunk = ChunkPos::new(0, 0, 0);
+        let block = BlockPos::new(1, 10, 2);
+
+        assert_eq!(store.block(block), None);
+        load(&mut store, chunk);
+        assert_eq!(store.block(block), Some(None));
+
+        let glass = store.resource_dictionary.get_block_id("Glass");
+        assert!(store.set_block(block, Some(glass)));
+
+        store.unload(chunk);
+        assert!(store.chunk_data(chunk).is_none());
+
+        load(&mut store, chunk);
+        assert_eq!(store.block(block), Some(Some(glass)));
+
+        store.save();
+        drop(store);
+
+        let mut store = open(dir.path());
+        load(&mut store, chunk);
+        assert_eq!(store.block(block), Some(Some(glass)));
+        assert_eq!(
+            store.chunk_data(chunk).unwrap().blocks
+                [coords::split_block(block_position(block)).1.as_idx()],
+            Some(glass)
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::HashSet,
+    fs,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use shipyard::*;
+
+use crate::{
+    biome::Biome,
+    chat::Chat,
+    chunk_loader::ServerChunks,
+    game_map::{Chunk, ChunkCoords, GameMap, InnerChunkCoords},
+    heightmap::Heightmap,
+    input::InputState,
+    loader::ResourceDictionary,
+    model::{MissingModel, Model, UpdatedModel},
+    priority::{ChunkJobQueue, ChunkPriority},
+    schematic::{Orientation, Schematic},
+    settings::{Settings, WorldSettings},
+};
+
+/// How often the settings file is checked for changed world generation settings in dev mode.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shape of the generated world.
+#[derive(Debug)]
+pub enum Terrain {
+    /// Flat plateaus generated by `generate_chunk`.
+    Test,
+    Heightmap(Heightmap),
+}
+
+impl Terrain {
+    /// Creates the terrain chosen in the settings, falling back to the test terrain if it can't be loaded.
+    pub fn from_settings(
+        settings: &WorldSettings,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Self {
+        let Some(heightmap) = &settings.heightmap else {
+            return Terrain::Test;
+        };
+
+        match Heightmap::load(heightmap, resource_dictionary) {
+            Ok(heightmap) => Terrain::Heightmap(heightmap),
+            Err(e) => {
+                log::error!("{e}, using the test terrain");
+                Terrain::Test
+            }
+        }
+    }
+
+    /// Generates the blocks of a chunk and assigns biomes to its columns.
+    pub fn generate(&self, coords: ChunkCoords) -> Chunk {
+        let (mut chunk, biomes) = match self {
+            Terrain::Test => (
+                generate_chunk(coords),
+                vec![Biome::Plains; Chunk::COLUMNS_COUNT as usize],
+            ),
+            Terrain::Heightmap(heightmap) => {
+                (heightmap.generate_chunk(coords), heightmap.biomes(coords))
+            }
+        };
+
+        chunk.set_biomes(biomes);
+        chunk
+    }
+}
+
+/// Structure placed into every generated chunk it intersects.
+#[derive(Debug)]
+pub struct PlacedStructure {
+    structure: Arc<Schematic>,
+    origin: glam::IVec3,
+    orientation: Orientation,
+}
+
+impl PlacedStructure {
+    /// Returns the structures listed in the settings, skipping the ones which aren't loaded.
+    pub fn from_settings(
+        settings: &WorldSettings,
+        resource_dictionary: &ResourceDictionary,
+    ) -> Vec<Self> {
+        settings
+            .structures
+            .iter()
+            .filter_map(|placement| {
+                let Some(structure) = resource_dictionary.find_structure(&placement.name) else {
+                    log::error!("Structure {} is not loaded, skipping it", placement.name);
+                    return None;
+                };
+
+                Some(Self {
+                    structure,
+                    origin: glam::IVec3::from_array(placement.position),
+                    orientation: placement.orientation,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct RequestQueue {
+    state: Mutex<RequestQueueState>,
+    available: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct RequestQueueState {
+    jobs: ChunkJobQueue,
+    /// Set when the generator is dropped to stop the workers.
+    closed: bool,
+}
+
+/// Dispatches chunk generation to worker threads, so terrain generation doesn't block the game loop.
+/// Workers always take the request with the highest priority.
+#[derive(Debug, Unique)]
+pub struct WorldGenerator {
+    requests: Arc<RequestQueue>,
+    results: Mutex<mpsc::Receiver<(ChunkCoords, Chunk)>>,
+    /// Chunks which were requested but haven't been received yet.
+    pending: HashSet<ChunkCoords>,
+    /// Set when the world was regenerated with changed settings,
+    /// saved chunks the player didn't modify are then replaced by generated ones.
+    replaces_unmodified: bool,
+}
+
+impl WorldGenerator {
+    /// Spawns worker threads generating the given terrain with the structures,
+    /// leaving one core for the main thread.
+    pub fn new(terrain: Terrain, structures: Vec<PlacedStructure>) -> Self {
+        let worker_count = thread::available_parallelism()
+            .map(|count| count.get().saturating_sub(1))
+            .unwrap_or(1)
+            .max(1);
+
+        let terrain = Arc::new(terrain);
+        let structures = Arc::new(structures);
+        let requests = Arc::new(RequestQueue::default());
+        let (result_sender, results) = mpsc::channel();
+
+        for idx in 0..worker_count {
+            let requests = Arc::clone(&requests);
+            let result_sender = result_sender.clone();
+            let terrain = Arc::clone(&terrain);
+            let structures = Arc::clone(&structures);
+
+            thread::Builder::new()
+                .name(format!("worldgen-{idx}"))
+                .spawn(move || loop {
+                    // the lock is released before generating, so other workers can take requests
+                    let coords = {
+                        let mut state = requests.state.lock().unwrap();
+
+                        loop {
+                            if state.closed {
+                                return;
+                            }
+
+                            if let Some(coords) = state.jobs.pop() {
+                                break coords;
+                            }
+
+                            state = requests.available.wait(state).unwrap();
+                        }
+                    };
+
+                    let mut chunk = terrain.generate(coords);
+                    for placed in structures.iter() {
+                        placed.structure.place_in_chunk(
+                            &mut chunk,
+                            coords,
+                            placed.origin,
+                            placed.orientation,
+                        );
+                    }
+                    chunk.relight();
+                    chunk.compact();
+
+                    if result_sender.send((coords, chunk)).is_err() {
+                        return;
+                    }
+                })
+                .expect("Failed to spawn a world generation thread");
+        }
+
+        log::info!("Started {worker_count} world generation threads");
+
+        Self {
+            requests,
+            results: Mutex::new(results),
+            pending: HashSet::new(),
+            replaces_unmodified: false,
+        }
+    }
+
+    pub fn replaces_unmodified(&self) -> bool {
+        self.replaces_unmodified
+    }
+
+    pub fn is_pending(&self, coords: ChunkCoords) -> bool {
+        self.pending.contains(&coords)
+    }
+
+    /// Queues generation of a chunk unless it's already pending.
+    pub fn request(&mut self, coords: ChunkCoords, priority: &ChunkPriority) {
+        if self.pending.insert(coords) {
+            self.requests
+                .state
+                .lock()
+                .unwrap()
+                .jobs
+                .push(coords, priority);
+            self.requests.available.notify_one();
+        }
+    }
+
+    /// Updates priorities of queued requests and cancels the ones for which `keep` returns false.
+    pub fn reprioritize(
+        &mut self,
+        priority: &ChunkPriority,
+        keep: impl FnMut(ChunkCoords) -> bool,
+    ) {
+        let dropped = self
+            .requests
+            .state
+            .lock()
+            .unwrap()
+            .jobs
+            .reprioritize(priority, keep);
+
+        for coords in dropped {
+            self.pending.remove(&coords);
+        }
+    }
+
+    /// Returns all chunks finished since the last call.
+    pub fn receive(&mut self) -> Vec<(ChunkCoords, Chunk)> {
+        let finished: Vec<(ChunkCoords, Chunk)> =
+            self.results.get_mut().unwrap().try_iter().collect();
+
+        for (coords, _) in finished.iter() {
+            self.pending.remove(coords);
+        }
+
+        finished
+    }
+}
+
+impl Drop for WorldGenerator {
+    fn drop(&mut self) {
+        self.requests.state.lock().unwrap().closed = true;
+        self.requests.available.notify_all();
+    }
+}
+
+/// Notices changes of the world generation settings in dev mode and offers to regenerate the world with them.
+#[derive(Debug, Unique)]
+pub struct WorldgenWatcher {
+    /// Fingerprint of the settings used by the world generator.
+    current: String,
+    /// Fingerprint of changed settings the player was told about.
+    announced: Option<String>,
+    last_check: Instant,
+}
+
+impl WorldgenWatcher {
+    pub fn new(settings: &WorldSettings) -> Self {
+        Self {
+            current: fingerprint(settings),
+            announced: None,
+            last_check: Instant::now(),
+        }
+    }
+}
+
+/// Describes the settings together with modification times of the images they refer to,
+/// so editing the heightmap counts as a change too.
+fn fingerprint(settings: &WorldSettings) -> String {
+    let modified = |path: &str| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+
+    let images = settings.heightmap.as_ref().map(|heightmap| {
+        (
+            modified(&heightmap.image),
+            heightmap.color_map.as_deref().and_then(modified),
+        )
+    });
+
+    format!("{settings:?} {images:?}")
+}
+
+/// Periodically reads the world settings from the settings file in dev mode
+/// and tells the player when they differ from the ones the world is generated with.
+pub fn watch_worldgen_sys(
+    settings: UniqueView<Settings>,
+    mut watcher: UniqueViewMut<WorldgenWatcher>,
+    mut chat: UniqueViewMut<Chat>,
+) {
+    if !settings.dev_mode || watcher.last_check.elapsed() < WATCH_INTERVAL {
+        return;
+    }
+
+    watcher.last_check = Instant::now();
+
+    // the file may be in the middle of being edited, so it's only read once it's valid again
+    let Some(world) = fs::read_to_string(Settings::PATH)
+        .ok()
+        .and_then(|content| ron::from_str::<Settings>(&content).ok())
+        .map(|settings| settings.world)
+    else {
+        return;
+    };
+
+    let fingerprint = fingerprint(&world);
+
+    if fingerprint == watcher.current {
+        watcher.announced = None;
+    } else if watcher.announced.as_ref() != Some(&fingerprint) {
+        chat.push_system(
+            "World generation settings changed, type /regenerate to generate unmodified chunks again",
+        );
+        watcher.announced = Some(fingerprint);
+    }
+}
+
+/// Replaces the world generator with one using the current world settings
+/// and unloads chunks the player didn't modify, so the chunk loader requests them again.
+/// Only worlds generated by the client can be regenerated, not the ones sent by a server.
+#[allow(clippy::too_many_arguments)]
+pub fn regenerate_world_sys(
+    mut input_state: UniqueViewMut<InputState>,
+    mut settings: UniqueViewMut<Settings>,
+    resource_dictionary: UniqueView<ResourceDictionary>,
+    mut watcher: UniqueViewMut<WorldgenWatcher>,
+    mut world_generator: UniqueViewMut<WorldGenerator>,
+    mut game_map: UniqueViewMut<GameMap>,
+    mut chat: UniqueViewMut<Chat>,
+    server_chunks: UniqueView<ServerChunks>,
+    (mut models, mut missing_models, mut updated_models): (
+        ViewMut<Model>,
+        ViewMut<MissingModel>,
+        ViewMut<UpdatedModel>,
+    ),
+) {
+    if !input_state.regenerate_world {
+        return;
+    }
+
+    input_state.regenerate_world = false;
+
+    if server_chunks.connected {
+        chat.push_system(
+            "The world is generated by the server, restart it to apply the new settings",
+        );
+        return;
+    }
+
+    settings.world = Settings::load().world;
+    watcher.current = fingerprint(&settings.world);
+    watcher.announced = None;
+
+    let terrain = Terrain::from_settings(&settings.world, &resource_dictionary);
+    let structures = PlacedStructure::from_settings(&settings.world, &resource_dictionary);
+
+    // dropping the old generator stops its workers, chunks they were generating are never received
+    *world_generator = WorldGenerator::new(terrain, structures);
+    world_generator.replaces_unmodified = true;
+
+    let regenerated: Vec<ChunkCoords> = game_map
+        .chunks
+        .iter()
+        .filter(|(_, chunk)| !chunk.is_modified())
+        .map(|(coords, _)| *coords)
+        .collect();
+
+    for coords in regenerated.iter() {
+        game_map.chunks.remove(coords);
+
+        if let Some(id) = game_map.chunk_entity_map.get(coords) {
+            models.delete(*id);
+            missing_models.delete(*id);
+            updated_models.delete(*id);
+        }
+    }
+
+    log::info!("Regenerating {} chunks", regenerated.len());
+    chat.push_system(&format!(
+        "Regenerating {} chunks, keeping {} modified ones",
+        regenerated.len(),
+        game_map.chunks.len()
+    ));
+}
+
+/// Generates the test terrain - a checkerboard of flat plateaus on top of solid ground.
+pub fn generate_chunk(coords: ChunkCoords) -> Chunk {
+    let mut chunk = Chunk::new();
+
+    if coords.y > 0 {
+        return chunk;
+    }
+
+    for bz in 0..Chunk::SIZE {
+        for bx in 0..Chunk::SIZE {
+            let max_y = if coords.y < 0 {
+                Chunk::SIZE
+            } else {
+                let mut max_y = if (coords.x + coords.z) % 2 == 0 { 3 } else { 2 };
+
+                if (3..=Chunk::SIZE - 3).contains(&bx) && (3..=Chunk::SIZE - 3).contains(&bz) {
+                    max_y += 1;
+                }
+
+                max_y
+            };
+
+            let block: u32 = (bx + bz) as u32 % 3;
+
+            for by in 0..max_y {
+                chunk.set_block(InnerChunkCoords::new(bx, by, bz), Some(block));
+            }
+        }
+    }
+
+    chunk
+}
@@ -0,0 +1,33 @@
+//! Runs the game without a window and renders it into an offscreen texture.
+//! Skipped on machines without any graphics adapter, not even a software one.
+
+use landmark_client::{HeadlessGame, LandmarkError};
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 64;
+
+#[test]
+fn renders_a_frame() {
+    // resources are looked up relative to the working directory, like when running the game
+    std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/..")).unwrap();
+
+    let mut game = match HeadlessGame::new(WIDTH, HEIGHT) {
+        Ok(game) => game,
+        Err(LandmarkError::NoAdapter) => {
+            eprintln!("Skipping, no graphics adapter is available");
+            return;
+        }
+        Err(e) => panic!("Failed to start the headless game: {e}"),
+    };
+
+    for _ in 0..3 {
+        game.update();
+    }
+
+    let frame = game.render().expect("Frame wasn't rendered");
+    assert_eq!(frame.dimensions(), (WIDTH, HEIGHT));
+
+    // the hotbar is drawn over the world, so the frame can't be a single color
+    let corner = frame.get_pixel(0, 0);
+    assert!(frame.pixels().any(|pixel| pixel != corner));
+}
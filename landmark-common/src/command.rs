@@ -0,0 +1,226 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// Runs a command with its arguments and returns the text shown to the user.
+pub type CommandHandler<C> = Box<dyn Fn(&mut C, &[&str]) -> Result<String, String> + Send + Sync>;
+
+/// What a player is allowed to do, each level includes the ones below it.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionLevel {
+    /// Players who aren't known to the server.
+    #[default]
+    Guest,
+    Member,
+    Moderator,
+    /// Server operators, also used for the server console and single player.
+    Admin,
+}
+
+impl PermissionLevel {
+    pub const ALL: [PermissionLevel; 4] = [
+        PermissionLevel::Guest,
+        PermissionLevel::Member,
+        PermissionLevel::Moderator,
+        PermissionLevel::Admin,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PermissionLevel::Guest => "guest",
+            PermissionLevel::Member => "member",
+            PermissionLevel::Moderator => "moderator",
+            PermissionLevel::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for PermissionLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for PermissionLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|level| level.name() == s)
+            .ok_or_else(|| format!("{s} is not a permission level"))
+    }
+}
+
+struct Command<C> {
+    /// Lowest level allowed to execute the command.
+    level: PermissionLevel,
+    /// Arguments of the command, shown by `help`.
+    usage: &'static str,
+    description: &'static str,
+    handler: CommandHandler<C>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Empty,
+    Unknown(String),
+    Forbidden {
+        command: String,
+        required: PermissionLevel,
+    },
+    /// The command ran, but couldn't do what was asked.
+    Failed {
+        command: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Empty => write!(f, "No command given"),
+            CommandError::Unknown(name) => {
+                write!(f, "Unknown command {name}, use help to list commands")
+            }
+            CommandError::Forbidden { command, required } => {
+                write!(f, "{command} requires the {required} permission level")
+            }
+            CommandError::Failed { command, message } => write!(f, "{command}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Commands typed into a console, executed with mutable access to a context `C`,
+/// e.g. the game world on the client or the server state.
+///
+/// Every command requires a permission level, checked against the level of whoever executes it.
+/// `help` is always available and lists the commands the caller may execute.
+pub struct CommandRegistry<C> {
+    commands: BTreeMap<String, Command<C>>,
+}
+
+impl<C> CommandRegistry<C> {
+    pub fn new() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a command anyone can execute, replacing any previous command with the same name.
+    pub fn register(
+        &mut self,
+        name: &str,
+        usage: &'static str,
+        description: &'static str,
+        handler: impl Fn(&mut C, &[&str]) -> Result<String, String> + Send + Sync + 'static,
+    ) {
+        self.register_restricted(name, PermissionLevel::Guest, usage, description, handler);
+    }
+
+    /// Registers a command which requires at least the given permission level.
+    pub fn register_restricted(
+        &mut self,
+        name: &str,
+        level: PermissionLevel,
+        usage: &'static str,
+        description: &'static str,
+        handler: impl Fn(&mut C, &[&str]) -> Result<String, String> + Send + Sync + 'static,
+    ) {
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                level,
+                usage,
+                description,
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    /// Executes a line of input on behalf of a caller with the given level, a leading `/` is ignored.
+    pub fn execute(
+        &self,
+        context: &mut C,
+        line: &str,
+        level: PermissionLevel,
+    ) -> Result<String, CommandError> {
+        let line = line.trim().trim_start_matches('/');
+        let mut words = line.split_whitespace();
+
+        let Some(name) = words.next() else {
+            return Err(CommandError::Empty);
+        };
+
+        let args: Vec<&str> = words.collect();
+
+        if name == "help" {
+            return Ok(self.help(level));
+        }
+
+        let command = self
+            .commands
+            .get(name)
+            .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+
+        if level < command.level {
+            return Err(CommandError::Forbidden {
+                command: name.to_string(),
+                required: command.level,
+            });
+        }
+
+        (command.handler)(context, &args).map_err(|message| CommandError::Failed {
+            command: name.to_string(),
+            message,
+        })
+    }
+
+    /// Returns one line per command allowed at the given level with its usage and description, sorted by name.
+    pub fn help(&self, level: PermissionLevel) -> String {
+        let mut lines = vec!["help - lists available commands".to_string()];
+
+        for (name, command) in self
+            .commands
+            .iter()
+            .filter(|(_, command)| command.level <= level)
+        {
+            let mut line = name.clone();
+
+            if !command.usage.is_empty() {
+                line = format!("{line} {}", command.usage);
+            }
+
+            lines.push(format!("{line} - {}", command.description));
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl<C> Default for CommandRegistry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> fmt::Debug for CommandRegistry<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandRegistry")
+            .field("commands", &self.commands.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -0,0 +1,3 @@
+pub mod command;
+pub mod netsim;
+pub mod secure;
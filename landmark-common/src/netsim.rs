@@ -0,0 +1,314 @@
+//! Simulation of bad network conditions, for testing how the game behaves with a slow or unreliable
+//! connection while running the client and the server locally.
+//!
+//! Conditions are applied to whole messages above the encryption, since dropping or reordering
+//! encrypted messages would break the transport. Messages keep their order, a delayed message also
+//! holds back the ones sent after it, like it would over TCP. Messages larger than a single encrypted
+//! message are split into parts below the simulation, so a dropped message is never partly received.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::secure::{SecureError, SecureStream, MAX_PAYLOAD_LEN};
+
+/// Largest message put together from parts, so a broken peer can't make the other side run out of memory.
+const MAX_ASSEMBLED_LEN: usize = 16 * 1024 * 1024;
+
+/// Artificial delay and loss added to every message, in both directions.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// Delay added to every message.
+    pub latency: Duration,
+    /// Largest random deviation from the latency, in either direction.
+    pub jitter: Duration,
+    /// Chance of a message being dropped, from 0 to 1.
+    pub loss: f32,
+}
+
+impl NetworkConditions {
+    /// Parses command arguments, either `off` or `<latency_ms> [jitter_ms] [loss_percent]`.
+    pub fn from_args(args: &[&str]) -> Result<Self, String> {
+        let parse_ms = |value: &str| {
+            value
+                .parse::<u64>()
+                .map(Duration::from_millis)
+                .map_err(|_| format!("{value} is not a number of milliseconds"))
+        };
+
+        let (latency, jitter, loss) = match args {
+            ["off"] => return Ok(Self::default()),
+            [latency] => (parse_ms(latency)?, Duration::ZERO, 0.0),
+            [latency, jitter] => (parse_ms(latency)?, parse_ms(jitter)?, 0.0),
+            [latency, jitter, loss] => {
+                let percent = loss
+                    .trim_end_matches('%')
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|percent| (0.0..=100.0).contains(percent))
+                    .ok_or_else(|| format!("{loss} is not a percentage"))?;
+
+                (parse_ms(latency)?, parse_ms(jitter)?, percent / 100.0)
+            }
+            _ => {
+                return Err(
+                    "expected off, or a latency with an optional jitter and loss".to_string(),
+                )
+            }
+        };
+
+        Ok(Self {
+            latency,
+            jitter,
+            loss,
+        })
+    }
+
+    pub fn is_ideal(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for NetworkConditions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ideal() {
+            return write!(f, "no simulated network conditions");
+        }
+
+        write!(
+            f,
+            "{} ms latency, {} ms jitter, {:.1}% loss",
+            self.latency.as_millis(),
+            self.jitter.as_millis(),
+            self.loss * 100.0
+        )
+    }
+}
+
+/// Conditions shared by every simulated connection, changing them affects messages sent from then on.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkSimulator {
+    conditions: Arc<Mutex<NetworkConditions>>,
+}
+
+impl NetworkSimulator {
+    pub fn conditions(&self) -> NetworkConditions {
+        *self.conditions.lock().unwrap()
+    }
+
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        log::info!("Simulating {conditions}");
+        *self.conditions.lock().unwrap() = conditions;
+    }
+}
+
+/// Messages held back until the simulated delay passes, in one direction.
+#[derive(Debug)]
+struct DelayQueue {
+    messages: VecDeque<(Instant, Vec<u8>)>,
+    /// State of the xorshift generator deciding the jitter and loss.
+    rng: u64,
+}
+
+impl DelayQueue {
+    fn new(seed: u64) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            // xorshift never leaves zero
+            rng: seed | 1,
+        }
+    }
+
+    /// Returns a random number from 0 to 1.
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn push(&mut self, payload: Vec<u8>, now: Instant, conditions: NetworkConditions) {
+        if self.random() < conditions.loss as f64 {
+            return;
+        }
+
+        let jitter = conditions.jitter.as_secs_f64() * (self.random() * 2.0 - 1.0);
+        let delay = (conditions.latency.as_secs_f64() + jitter).max(0.0);
+        let mut due = now + Duration::from_secs_f64(delay);
+
+        if let Some((last_due, _)) = self.messages.back() {
+            due = due.max(*last_due);
+        }
+
+        self.messages.push_back((due, payload));
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.messages.front().map(|(due, _)| *due)
+    }
+
+    fn pop_due(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if self.next_due()? <= now {
+            self.messages.pop_front().map(|(_, payload)| payload)
+        } else {
+            None
+        }
+    }
+}
+
+/// Secure stream which passes its messages through the conditions of a [`NetworkSimulator`].
+///
+/// Delayed outgoing messages are only sent by a later call to `send` or `flush`,
+/// so `flush` has to be called regularly, e.g. every tick.
+///
+/// Every message is sent as one or more encrypted parts, each starting with a byte which is 1 if more
+/// parts of the same message follow.
+pub struct SimulatedStream<S> {
+    stream: SecureStream<S>,
+    simulator: NetworkSimulator,
+    outgoing: DelayQueue,
+    incoming: DelayQueue,
+    /// Parts of the incoming message received so far.
+    partial: Vec<u8>,
+}
+
+impl<S: Read + Write> SimulatedStream<S> {
+    pub fn new(stream: SecureStream<S>, simulator: NetworkSimulator) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default();
+
+        Self {
+            stream,
+            simulator,
+            outgoing: DelayQueue::new(seed),
+            incoming: DelayQueue::new(seed.rotate_left(32)),
+            partial: Vec::new(),
+        }
+    }
+
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), SecureError> {
+        let now = Instant::now();
+        self.outgoing
+            .push(payload.to_vec(), now, self.simulator.conditions());
+
+        self.flush()
+    }
+
+    /// Sends the outgoing messages whose delay has passed.
+    pub fn flush(&mut self) -> Result<(), SecureError> {
+        let now = Instant::now();
+
+        while let Some(payload) = self.outgoing.pop_due(now) {
+            let mut parts = payload.chunks(MAX_PAYLOAD_LEN - 1).peekable();
+
+            // an empty message is still sent as a single part
+            if parts.peek().is_none() {
+                self.stream.send(&[0])?;
+            }
+
+            while let Some(content) = parts.next() {
+                let mut part = Vec::with_capacity(content.len() + 1);
+                part.push(parts.peek().is_some() as u8);
+                part.extend_from_slice(content);
+
+                self.stream.send(&part)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Receives the next encrypted part, a completed message is passed through the simulated conditions.
+    fn receive_part(&mut self) -> Result<(), SecureError> {
+        let part = self.stream.recv()?;
+        let (&more, content) = part
+            .split_first()
+            .ok_or_else(|| invalid_data("received an empty message part"))?;
+
+        self.partial.extend_from_slice(content);
+
+        if self.partial.len() > MAX_ASSEMBLED_LEN {
+            return Err(invalid_data("received a message exceeding the size limit"));
+        }
+
+        if more == 0 {
+            let payload = std::mem::take(&mut self.partial);
+            self.incoming
+                .push(payload, Instant::now(), self.simulator.conditions());
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the next message which isn't dropped and whose delay has passed.
+    pub fn recv(&mut self) -> Result<Vec<u8>, SecureError> {
+        loop {
+            if let Some(due) = self.incoming.next_due() {
+                // messages received meanwhile would be due later anyway
+                std::thread::sleep(due.saturating_duration_since(Instant::now()));
+
+                return Ok(self.incoming.pop_due(due).expect("Message is due"));
+            }
+
+            self.receive_part()?;
+        }
+    }
+
+    /// Static public key the remote peer authenticated with.
+    pub fn remote_public_key(&self) -> Result<&[u8], SecureError> {
+        self.stream.remote_public_key()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.stream.get_ref()
+    }
+}
+
+impl SimulatedStream<TcpStream> {
+    /// Returns the next message whose delay has passed, without waiting.
+    /// Also sends the outgoing messages whose delay has passed.
+    pub fn try_recv(&mut self) -> Result<Option<Vec<u8>>, SecureError> {
+        self.flush()?;
+
+        while self.stream.has_message()? {
+            self.receive_part()?;
+        }
+
+        Ok(self.incoming.pop_due(Instant::now()))
+    }
+}
+
+fn invalid_data(message: &str) -> SecureError {
+    SecureError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secure::{tests::handshake, Keypair};
+
+    #[test]
+    fn messages_larger_than_a_part_arrive_whole() {
+        let (client, server) =
+            handshake(&Keypair::generate().unwrap(), &Keypair::generate().unwrap());
+        let mut client = SimulatedStream::new(client, NetworkSimulator::default());
+        let mut server = SimulatedStream::new(server, NetworkSimulator::default());
+
+        let large: Vec<u8> = (0..MAX_PAYLOAD_LEN * 2 + 10).map(|i| i as u8).collect();
+        client.send(&large).unwrap();
+        client.send(&[]).unwrap();
+        client.send(b"after").unwrap();
+
+        assert_eq!(server.recv().unwrap(), large);
+        assert_eq!(server.recv().unwrap(), b"");
+        assert_eq!(server.recv().unwrap(), b"after");
+    }
+}
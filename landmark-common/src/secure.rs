@@ -0,0 +1,462 @@
+//! Encrypted transport based on the Noise protocol framework.
+//!
+//! Both sides authenticate with static X25519 keys. The server key is its identity, which the
+//! client checks against previously seen keys stored in a [`TrustStore`].
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+};
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+const MAX_MESSAGE_LEN: usize = 65535;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Largest payload which fits into a single encrypted message.
+pub const MAX_PAYLOAD_LEN: usize = MAX_MESSAGE_LEN - TAG_LEN;
+
+#[derive(Debug)]
+pub enum SecureError {
+    Io(io::Error),
+    Noise(snow::Error),
+    PayloadTooLarge(usize),
+    InvalidKeyFile(PathBuf),
+    MissingRemoteKey,
+}
+
+impl fmt::Display for SecureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecureError::Io(e) => write!(f, "Connection error: {e}"),
+            SecureError::Noise(e) => write!(f, "Encryption error: {e}"),
+            SecureError::PayloadTooLarge(len) => write!(
+                f,
+                "Payload of {len} bytes exceeds the limit of {MAX_PAYLOAD_LEN} bytes"
+            ),
+            SecureError::InvalidKeyFile(path) => {
+                write!(f, "Key file {} is corrupted", path.display())
+            }
+            SecureError::MissingRemoteKey => write!(f, "Remote peer did not present its key"),
+        }
+    }
+}
+
+impl std::error::Error for SecureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SecureError::Io(e) => Some(e),
+            SecureError::Noise(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SecureError {
+    fn from(value: io::Error) -> Self {
+        SecureError::Io(value)
+    }
+}
+
+impl From<snow::Error> for SecureError {
+    fn from(value: snow::Error) -> Self {
+        SecureError::Noise(value)
+    }
+}
+
+/// Static X25519 key pair identifying a peer.
+#[derive(Clone)]
+pub struct Keypair {
+    pub private: Vec<u8>,
+    pub public: Vec<u8>,
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never print the private key
+        f.debug_struct("Keypair")
+            .field("public", &fingerprint(&self.public))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Keypair {
+    pub fn generate() -> Result<Self, SecureError> {
+        let keypair = builder().generate_keypair()?;
+
+        Ok(Self {
+            private: keypair.private,
+            public: keypair.public,
+        })
+    }
+
+    /// Loads the key pair from `path`, generating and saving a new one if the file doesn't exist.
+    pub fn load_or_generate(path: &Path) -> Result<Self, SecureError> {
+        match fs::read(path) {
+            Ok(bytes) => {
+                if bytes.len() != KEY_LEN * 2 {
+                    return Err(SecureError::InvalidKeyFile(path.to_path_buf()));
+                }
+
+                let (private, public) = bytes.split_at(KEY_LEN);
+
+                Ok(Self {
+                    private: private.to_vec(),
+                    public: public.to_vec(),
+                })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                log::info!("Generating a new key pair in {}", path.display());
+
+                let keypair = Self::generate()?;
+                keypair.save(path)?;
+
+                Ok(keypair)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), SecureError> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(path)?;
+        file.write_all(&self.private)?;
+        file.write_all(&self.public)?;
+
+        Ok(())
+    }
+}
+
+fn builder() -> snow::Builder<'static> {
+    snow::Builder::new(NOISE_PARAMS.parse().expect("Noise parameters are valid"))
+}
+
+/// Returns a human readable representation of a public key.
+pub fn fingerprint(public_key: &[u8]) -> String {
+    public_key
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Encrypted, authenticated message stream over any reliable byte stream.
+pub struct SecureStream<S> {
+    stream: S,
+    transport: snow::TransportState,
+    buffer: Vec<u8>,
+}
+
+impl<S: Read + Write> SecureStream<S> {
+    /// Performs the handshake as the connecting side.
+    pub fn connect(mut stream: S, local: &Keypair) -> Result<Self, SecureError> {
+        let mut handshake = builder()
+            .local_private_key(&local.private)
+            .build_initiator()?;
+        let mut buffer = vec![0; MAX_MESSAGE_LEN];
+
+        // -> e
+        let len = handshake.write_message(&[], &mut buffer)?;
+        write_frame(&mut stream, &buffer[..len])?;
+
+        // <- e, ee, s, es
+        let frame = read_frame(&mut stream)?;
+        handshake.read_message(&frame, &mut buffer)?;
+
+        // -> s, se
+        let len = handshake.write_message(&[], &mut buffer)?;
+        write_frame(&mut stream, &buffer[..len])?;
+
+        Ok(Self {
+            stream,
+            transport: handshake.into_transport_mode()?,
+            buffer,
+        })
+    }
+
+    /// Performs the handshake as the listening side.
+    pub fn accept(mut stream: S, local: &Keypair) -> Result<Self, SecureError> {
+        let mut handshake = builder()
+            .local_private_key(&local.private)
+            .build_responder()?;
+        let mut buffer = vec![0; MAX_MESSAGE_LEN];
+
+        // -> e
+        let frame = read_frame(&mut stream)?;
+        handshake.read_message(&frame, &mut buffer)?;
+
+        // <- e, ee, s, es
+        let len = handshake.write_message(&[], &mut buffer)?;
+        write_frame(&mut stream, &buffer[..len])?;
+
+        // -> s, se
+        let frame = read_frame(&mut stream)?;
+        handshake.read_message(&frame, &mut buffer)?;
+
+        Ok(Self {
+            stream,
+            transport: handshake.into_transport_mode()?,
+            buffer,
+        })
+    }
+
+    /// Static public key the remote peer authenticated with.
+    pub fn remote_public_key(&self) -> Result<&[u8], SecureError> {
+        self.transport
+            .get_remote_static()
+            .ok_or(SecureError::MissingRemoteKey)
+    }
+
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), SecureError> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(SecureError::PayloadTooLarge(payload.len()));
+        }
+
+        let len = self.transport.write_message(payload, &mut self.buffer)?;
+        write_frame(&mut self.stream, &self.buffer[..len])?;
+
+        Ok(())
+    }
+
+    pub fn recv(&mut self) -> Result<Vec<u8>, SecureError> {
+        let frame = read_frame(&mut self.stream)?;
+        let len = self.transport.read_message(&frame, &mut self.buffer)?;
+
+        Ok(self.buffer[..len].to_vec())
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+}
+
+impl SecureStream<TcpStream> {
+    /// Checks without blocking whether a whole message has arrived, so `recv` won't wait for it.
+    pub fn has_message(&self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let ready = self.peek_frame();
+        self.stream.set_nonblocking(false)?;
+
+        match ready {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            ready => ready,
+        }
+    }
+
+    fn peek_frame(&self) -> io::Result<bool> {
+        let mut len = [0; 2];
+        match self.stream.peek(&mut len)? {
+            // the remote closed the connection, let `recv` report it
+            0 => return Ok(true),
+            1 => return Ok(false),
+            _ => {}
+        }
+
+        let mut frame = vec![0; 2 + u16::from_be_bytes(len) as usize];
+        Ok(self.stream.peek(&mut frame)? == frame.len())
+    }
+}
+
+fn write_frame(stream: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    stream.write_all(&(frame.len() as u16).to_be_bytes())?;
+    stream.write_all(frame)?;
+    stream.flush()
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+
+    let mut frame = vec![0; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut frame)?;
+
+    Ok(frame)
+}
+
+/// Result of checking a server key against the trust store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// The key matches the one seen previously.
+    Trusted,
+    /// The server was never seen before, its key is trusted from now on.
+    NewServer,
+    /// The server presented a different key than before, the connection should be aborted.
+    Changed { expected: Vec<u8> },
+}
+
+/// Server identities seen by a client, persisted as `address hex_key` lines.
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    path: PathBuf,
+    servers: HashMap<String, Vec<u8>>,
+}
+
+impl TrustStore {
+    pub fn load(path: &Path) -> Result<Self, SecureError> {
+        let mut servers = HashMap::new();
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let key = line
+                .split_once(' ')
+                .and_then(|(address, key)| Some((address, decode_hex(key.trim())?)));
+
+            match key {
+                Some((address, key)) => {
+                    servers.insert(address.to_string(), key);
+                }
+                None => return Err(SecureError::InvalidKeyFile(path.to_path_buf())),
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            servers,
+        })
+    }
+
+    /// Checks the key of the server at `address`, remembering it if the server is new.
+    pub fn verify(&mut self, address: &str, key: &[u8]) -> Result<Verification, SecureError> {
+        match self.servers.get(address) {
+            Some(expected) if expected == key => Ok(Verification::Trusted),
+            Some(expected) => Ok(Verification::Changed {
+                expected: expected.clone(),
+            }),
+            None => {
+                self.servers.insert(address.to_string(), key.to_vec());
+                self.save()?;
+
+                Ok(Verification::NewServer)
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), SecureError> {
+        let mut content = String::new();
+
+        for (address, key) in self.servers.iter() {
+            let key: String = key.iter().map(|byte| format!("{byte:02x}")).collect();
+            content.push_str(&format!("{address} {key}\n"));
+        }
+
+        fs::write(&self.path, content)?;
+
+        Ok(())
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Connects a client and a server over loopback TCP, returning both ends after the handshake.
+    pub fn handshake(
+        client: &Keypair,
+        server: &Keypair,
+    ) -> (SecureStream<TcpStream>, SecureStream<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = server.clone();
+        let accepted = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            SecureStream::accept(stream, &server).unwrap()
+        });
+
+        let client = SecureStream::connect(TcpStream::connect(address).unwrap(), client).unwrap();
+
+        (client, accepted.join().unwrap())
+    }
+
+    #[test]
+    fn handshake_authenticates_both_sides() {
+        let client_key = Keypair::generate().unwrap();
+        let server_key = Keypair::generate().unwrap();
+
+        let (mut client, mut server) = handshake(&client_key, &server_key);
+
+        assert_eq!(client.remote_public_key().unwrap(), server_key.public);
+        assert_eq!(server.remote_public_key().unwrap(), client_key.public);
+
+        client.send(b"hello").unwrap();
+        assert_eq!(server.recv().unwrap(), b"hello");
+
+        let large = vec![7; MAX_PAYLOAD_LEN];
+        server.send(&large).unwrap();
+        assert_eq!(client.recv().unwrap(), large);
+
+        assert!(matches!(
+            client.send(&vec![0; MAX_PAYLOAD_LEN + 1]),
+            Err(SecureError::PayloadTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn trust_store_detects_changed_server_key() {
+        let path =
+            std::env::temp_dir().join(format!("landmark-known-servers-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let client_key = Keypair::generate().unwrap();
+        let server_key = Keypair::generate().unwrap();
+        let (client, _server) = handshake(&client_key, &server_key);
+        let received = client.remote_public_key().unwrap();
+
+        let mut store = TrustStore::load(&path).unwrap();
+        assert_eq!(
+            store.verify("example.com:25665", received).unwrap(),
+            Verification::NewServer
+        );
+
+        // the key is remembered across sessions
+        let mut store = TrustStore::load(&path).unwrap();
+        assert_eq!(
+            store.verify("example.com:25665", received).unwrap(),
+            Verification::Trusted
+        );
+
+        let impostor = Keypair::generate().unwrap();
+        let (client, _server) = handshake(&client_key, &impostor);
+
+        assert_eq!(
+            store
+                .verify("example.com:25665", client.remote_public_key().unwrap())
+                .unwrap(),
+            Verification::Changed {
+                expected: server_key.public.clone()
+            }
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+//! Length-prefixed frames holding a single message.
+//!
+//! A frame is the length of the rest of the frame as u32 in little endian, a flags byte and the
+//! message encoded with bincode, compressed with zstd if `FLAG_COMPRESSED` is set. Streams which
+//! already keep messages apart, like `SecureStream`, carry the output of `encode` without the length.
+
+use std::io::{Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{message::Compressible, ProtocolError};
+
+/// Largest frame accepted, also the largest a compressed message may expand to.
+pub const MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+/// Messages shorter than this are sent uncompressed, compressing them saves too little.
+pub const COMPRESSION_THRESHOLD: usize = 256;
+
+const FLAG_COMPRESSED: u8 = 0b1;
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Encodes a message as a flags byte followed by its content. Compressible messages are compressed
+/// if `compression` was agreed on during the handshake.
+pub fn encode<M: Serialize + Compressible>(
+    message: &M,
+    compression: bool,
+) -> Result<Vec<u8>, ProtocolError> {
+    let encoded = bincode::serialize(message)?;
+
+    let (flags, content) =
+        if compression && message.is_compressible() && encoded.len() >= COMPRESSION_THRESHOLD {
+            let compressed = zstd::encode_all(encoded.as_slice(), COMPRESSION_LEVEL)
+                .map_err(ProtocolError::Compression)?;
+
+            (FLAG_COMPRESSED, compressed)
+        } else {
+            (0, encoded)
+        };
+
+    let mut bytes = Vec::with_capacity(content.len() + 1);
+    bytes.push(flags);
+    bytes.extend_from_slice(&content);
+
+    if bytes.len() > MAX_FRAME_LEN {
+        return Err(ProtocolError::FrameTooLarge(bytes.len()));
+    }
+
+    Ok(bytes)
+}
+
+/// Decodes a message encoded by `encode`.
+pub fn decode<M: DeserializeOwned>(bytes: &[u8]) -> Result<M, ProtocolError> {
+    let (&flags, content) = bytes
+        .split_first()
+        .ok_or(ProtocolError::UnexpectedMessage("a message"))?;
+
+    match flags {
+        0 => Ok(bincode::deserialize(content)?),
+        FLAG_COMPRESSED => {
+            let mut decoder =
+                zstd::stream::Decoder::new(content).map_err(ProtocolError::Compression)?;
+            let mut decompressed = Vec::new();
+
+            // never trust the remote with the size of the decompressed message
+            (&mut decoder)
+                .take(MAX_FRAME_LEN as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(ProtocolError::Compression)?;
+
+            if decompressed.len() > MAX_FRAME_LEN {
+                return Err(ProtocolError::FrameTooLarge(decompressed.len()));
+            }
+
+            Ok(bincode::deserialize(&decompressed)?)
+        }
+        flags => Err(ProtocolError::UnknownFlags(flags)),
+    }
+}
+
+/// Writes a message as a frame, for streams which don't keep messages apart.
+pub fn write_message<W: Write, M: Serialize + Compressible>(
+    writer: &mut W,
+    message: &M,
+    compression: bool,
+) -> Result<(), ProtocolError> {
+    let bytes = encode(message, compression)?;
+
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a frame written by `write_message`.
+pub fn read_message<R: Read, M: DeserializeOwned>(reader: &mut R) -> Result<M, ProtocolError> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(ProtocolError::FrameTooLarge(len));
+    }
+
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+
+    decode(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ChunkData, ClientMessage, ServerMessage};
+
+    fn chunk_message() -> ServerMessage {
+        let mut blocks = vec![None; 32 * 32 * 32];
+        blocks[..32 * 32 * 8].fill(Some(3));
+
+        ServerMessage::Chunk(ChunkData {
+            coords: [-1, 0, 2],
+            blocks,
+            biomes: vec![0; 32 * 32],
+        })
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut stream = Vec::new();
+        write_message(&mut stream, &ClientMessage::Chat("hi".to_string()), true).unwrap();
+        write_message(&mut stream, &chunk_message(), true).unwrap();
+
+        let mut reader = stream.as_slice();
+        let chat: ClientMessage = read_message(&mut reader).unwrap();
+        let chunk: ServerMessage = read_message(&mut reader).unwrap();
+
+        assert_eq!(chat, ClientMessage::Chat("hi".to_string()));
+        assert_eq!(chunk, chunk_message());
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn only_chunks_are_compressed_when_agreed_on() {
+        let chunk = chunk_message();
+        let compressed = encode(&chunk, true).unwrap();
+        let uncompressed = encode(&chunk, false).unwrap();
+
+        assert_eq!(compressed[0], FLAG_COMPRESSED);
+        assert_eq!(uncompressed[0], 0);
+        assert!(compressed.len() < uncompressed.len() / 10);
+
+        let command = ClientMessage::Command("x".repeat(COMPRESSION_THRESHOLD * 2));
+        assert_eq!(encode(&command, true).unwrap()[0], 0);
+    }
+
+    #[test]
+    fn oversized_and_unknown_frames_are_rejected() {
+        let mut stream = (MAX_FRAME_LEN as u32 + 1).to_le_bytes().to_vec();
+        stream.push(0);
+
+        assert!(matches!(
+            read_message::<_, ServerMessage>(&mut stream.as_slice()),
+            Err(ProtocolError::FrameTooLarge(_))
+        ));
+        assert!(matches!(
+            decode::<ServerMessage>(&[0b10, 0]),
+            Err(ProtocolError::UnknownFlags(0b10))
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+//! Agreement on the protocol version and compression at the start of every connection.
+//!
+//! The client sends a `Hello` with the range of versions it speaks and its player name, the server
+//! answers with a `Welcome` naming the newest version both speak, or rejects the client if there is none.
+//! Once the player is registered, the server follows up with `Joined`.
+
+use crate::{
+    message::{Hello, PlayerData, ServerMessage, Welcome},
+    ProtocolError, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+
+/// Hello sent by this build.
+pub fn hello(name: &str) -> Hello {
+    Hello {
+        min_version: MIN_PROTOCOL_VERSION,
+        max_version: PROTOCOL_VERSION,
+        compression: true,
+        name: name.to_string(),
+    }
+}
+
+/// Picks the newest version spoken by both sides, compression is used if the client supports it.
+pub fn negotiate(hello: &Hello, motd: &str) -> Result<Welcome, ProtocolError> {
+    let version = hello.max_version.min(PROTOCOL_VERSION);
+
+    if version < hello.min_version || version < MIN_PROTOCOL_VERSION {
+        return Err(ProtocolError::IncompatibleVersion {
+            min: hello.min_version,
+            max: hello.max_version,
+        });
+    }
+
+    Ok(Welcome {
+        version,
+        compression: hello.compression,
+        motd: motd.to_string(),
+    })
+}
+
+/// Answer of the server to a hello, a rejection explains why the versions don't match.
+pub fn respond(hello: &Hello, motd: &str) -> ServerMessage {
+    match negotiate(hello, motd) {
+        Ok(welcome) => ServerMessage::Welcome(welcome),
+        Err(e) => ServerMessage::Rejected {
+            reason: e.to_string(),
+        },
+    }
+}
+
+/// Checks the answer of the server to the hello of this build.
+pub fn accept_response(response: ServerMessage) -> Result<Welcome, ProtocolError> {
+    match response {
+        ServerMessage::Welcome(welcome)
+            if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&welcome.version) =>
+        {
+            Ok(welcome)
+        }
+        ServerMessage::Welcome(welcome) => Err(ProtocolError::IncompatibleVersion {
+            min: welcome.version,
+            max: welcome.version,
+        }),
+        ServerMessage::Rejected { reason } => Err(ProtocolError::Rejected(reason)),
+        _ => Err(ProtocolError::UnexpectedMessage("a welcome")),
+    }
+}
+
+/// Checks the message following the welcome, which completes the join.
+pub fn accept_join(response: ServerMessage) -> Result<PlayerData, ProtocolError> {
+    match response {
+        ServerMessage::Joined(player) => Ok(player),
+        ServerMessage::Rejected { reason } => Err(ProtocolError::Rejected(reason)),
+        _ => Err(ProtocolError::UnexpectedMessage("the player data")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_newest_common_version() {
+        let newer_client = Hello {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION + 5,
+            compression: false,
+            name: "newer".to_string(),
+        };
+        let welcome = negotiate(&newer_client, "").unwrap();
+
+        assert_eq!(welcome.version, PROTOCOL_VERSION);
+        assert!(!welcome.compression);
+        assert_eq!(
+            accept_response(respond(&hello("player"), "motd"))
+                .unwrap()
+                .motd,
+            "motd"
+        );
+    }
+
+    #[test]
+    fn rejects_clients_without_common_version() {
+        let future_client = Hello {
+            min_version: PROTOCOL_VERSION + 1,
+            max_version: PROTOCOL_VERSION + 2,
+            compression: true,
+            name: "future".to_string(),
+        };
+
+        assert!(matches!(
+            accept_response(respond(&future_client, "")),
+            Err(ProtocolError::Rejected(_))
+        ));
+    }
+}
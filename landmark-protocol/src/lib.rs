@@ -0,0 +1,90 @@
+//! Messages exchanged between the client and the server, and how they are put on the wire.
+//!
+//! Every message is a frame of its length as u32, a flags byte and the message encoded with bincode.
+//! Chunk payloads are compressed with zstd when both sides agreed on it during the handshake.
+
+pub mod frame;
+pub mod handshake;
+pub mod message;
+pub mod replication;
+pub mod transport;
+
+use std::{fmt, io};
+
+/// Version of the protocol spoken by this build, increased on every incompatible change.
+pub const PROTOCOL_VERSION: u16 = 7;
+/// Oldest version this build can still speak.
+pub const MIN_PROTOCOL_VERSION: u16 = 7;
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    Encoding(bincode::Error),
+    Compression(io::Error),
+    FrameTooLarge(usize),
+    UnknownFlags(u8),
+    /// The versions supported by both sides don't overlap.
+    IncompatibleVersion {
+        min: u16,
+        max: u16,
+    },
+    Rejected(String),
+    UnexpectedMessage(&'static str),
+    /// The other side closed the connection.
+    Closed,
+    /// Failure of the connection below the protocol, e.g. its encryption.
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "Connection error: {e}"),
+            ProtocolError::Encoding(e) => write!(f, "Malformed message: {e}"),
+            ProtocolError::Compression(e) => write!(f, "Malformed compressed message: {e}"),
+            ProtocolError::FrameTooLarge(len) => write!(
+                f,
+                "Message of {len} bytes exceeds the limit of {} bytes",
+                frame::MAX_FRAME_LEN
+            ),
+            ProtocolError::UnknownFlags(flags) => {
+                write!(f, "Message has unknown flags {flags:#010b}")
+            }
+            ProtocolError::IncompatibleVersion { min, max } => write!(
+                f,
+                "Remote speaks protocol versions {min} to {max}, \
+                 this build speaks {MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION}"
+            ),
+            ProtocolError::Rejected(reason) => write!(f, "Connection rejected: {reason}"),
+            ProtocolError::UnexpectedMessage(expected) => {
+                write!(f, "Expected {expected} from the remote")
+            }
+            ProtocolError::Closed => write!(f, "Connection closed"),
+            ProtocolError::Transport(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtocolError::Io(e) => Some(e),
+            ProtocolError::Encoding(e) => Some(e),
+            ProtocolError::Compression(e) => Some(e),
+            ProtocolError::Transport(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(value: io::Error) -> Self {
+        ProtocolError::Io(value)
+    }
+}
+
+impl From<bincode::Error> for ProtocolError {
+    fn from(value: bincode::Error) -> Self {
+        ProtocolError::Encoding(value)
+    }
+}
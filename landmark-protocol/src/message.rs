@@ -0,0 +1,170 @@
+use landmark_common::command::PermissionLevel;
+use serde::{Deserialize, Serialize};
+
+use crate::replication::{ComponentData, NetworkId};
+
+/// First message of a connection, sent by the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub min_version: u16,
+    pub max_version: u16,
+    /// Whether the client can decompress chunk payloads.
+    pub compression: bool,
+    /// Name the player wants to join with, their identity is the key of the encrypted connection.
+    pub name: String,
+}
+
+/// Answer of the server to a `Hello` it accepts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Welcome {
+    /// Version both sides speak from now on.
+    pub version: u16,
+    /// Whether chunk payloads are compressed on this connection.
+    pub compression: bool,
+    pub motd: String,
+}
+
+/// Items in an inventory slot, identified by name as item IDs differ between resource packs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+/// State of a player kept by the server between sessions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerData {
+    pub name: String,
+    /// Position of the player's eye.
+    pub position: [f64; 3],
+    /// Slots of the player's inventory, empty for players who never synced one.
+    pub inventory: Vec<Option<ItemStack>>,
+    /// Eye position `/home` teleports to, None until the player sets one with `/sethome`.
+    #[serde(default)]
+    pub home: Option<[f64; 3]>,
+}
+
+/// Blocks of a chunk in the same order as the chunks of the client store them, None is air.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkData {
+    pub coords: [i32; 3],
+    pub blocks: Vec<Option<u32>>,
+    /// Biome of every column as its index in the biome list of the client, empty if they're unknown.
+    pub biomes: Vec<u8>,
+}
+
+/// Where the player moved during one update, numbered so the server can acknowledge it.
+/// The server checks whether the player could have got there and moves them back otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub sequence: u32,
+    /// Position of the player's eye after the update.
+    pub position: [f64; 3],
+    /// Yaw of the camera in degrees.
+    pub yaw: f32,
+}
+
+/// Weather of the whole world, decided by the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Rain,
+    /// Heavier rain under a darker sky.
+    Storm,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Hello(Hello),
+    /// Command typed by the player, without the leading slash.
+    Command(String),
+    Chat(String),
+    RequestChunk([i32; 3]),
+    SetBlock {
+        position: [i32; 3],
+        block: Option<u32>,
+    },
+    Input(PlayerInput),
+    /// Slots of the player's inventory, sent whenever they change so the server can save them.
+    Inventory(Vec<Option<ItemStack>>),
+    /// The player died and wants to be moved to the spawn point.
+    Respawn,
+    /// The player switched between creative and survival, also sent after joining. The server only
+    /// lets players play creative if it allowed them to.
+    GameMode {
+        creative: bool,
+    },
+    Disconnect,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome(Welcome),
+    /// The player was registered after the welcome, with the state saved in their previous session.
+    Joined(PlayerData),
+    Rejected {
+        reason: String,
+    },
+    /// Chunk which entered the view distance of the player, or changed too much to send single blocks.
+    Chunk(ChunkData),
+    /// Chunk which left the view distance of the player, the client should drop it.
+    UnloadChunk([i32; 3]),
+    /// Chat message of a player, or output of a command if there is no sender.
+    Chat {
+        sender: Option<String>,
+        text: String,
+    },
+    BlockChanged {
+        position: [i32; 3],
+        block: Option<u32>,
+    },
+    /// Position of the player's eye after processing the input with the given sequence number.
+    PlayerState {
+        last_input: u32,
+        position: [f64; 3],
+    },
+    /// Entity which started being replicated, with all of its networked components.
+    SpawnEntity {
+        id: NetworkId,
+        components: Vec<ComponentData>,
+    },
+    /// Networked components of an entity which changed since the last update.
+    UpdateEntity {
+        id: NetworkId,
+        components: Vec<ComponentData>,
+    },
+    DespawnEntity(NetworkId),
+    /// The weather changed, also sent to players when they join.
+    Weather(Weather),
+    /// The server moved the player's eye to a position, e.g. with a command or a portal. Inputs are
+    /// ignored until one reports the player arrived there.
+    Teleport([f64; 3]),
+    /// What the player is allowed to do, sent after `Joined` and whenever their level changes.
+    /// Commands of the client run at `level`, and only players allowed to play creative may change
+    /// blocks out of reach.
+    Permissions {
+        level: PermissionLevel,
+        creative: bool,
+    },
+    Disconnect {
+        reason: String,
+    },
+}
+
+/// Messages which may be compressed, only ones carrying chunk payloads are worth it.
+pub trait Compressible {
+    fn is_compressible(&self) -> bool;
+}
+
+impl Compressible for ClientMessage {
+    fn is_compressible(&self) -> bool {
+        false
+    }
+}
+
+impl Compressible for ServerMessage {
+    fn is_compressible(&self) -> bool {
+        matches!(self, ServerMessage::Chunk(_))
+    }
+}
@@ -0,0 +1,244 @@
+//! Synchronization of entities from the server to clients.
+//!
+//! Networked components are converted to `ComponentData`. The server keeps the last state of every
+//! replicated entity in a `Replicator`, which turns changes into spawn, update and despawn messages,
+//! so new kinds of entities don't need their own messages.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::message::ServerMessage;
+
+/// Identifies a replicated entity on the server and every client, IDs are never reused.
+pub type NetworkId = u32;
+
+/// State of a networked component.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ComponentData {
+    Transform {
+        translation: [f32; 3],
+        rotation: [f32; 4],
+    },
+    /// Movement in blocks per second, lets clients extrapolate between updates.
+    Velocity([f32; 3]),
+    /// Name of the model the entity is drawn with.
+    ModelId(String),
+    /// Name shown above the entity, like the name of a player.
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ComponentKind {
+    Transform,
+    Velocity,
+    ModelId,
+    Name,
+}
+
+impl ComponentData {
+    pub fn kind(&self) -> ComponentKind {
+        match self {
+            ComponentData::Transform { .. } => ComponentKind::Transform,
+            ComponentData::Velocity(_) => ComponentKind::Velocity,
+            ComponentData::ModelId(_) => ComponentKind::ModelId,
+            ComponentData::Name(_) => ComponentKind::Name,
+        }
+    }
+}
+
+/// Component which is synchronized to clients.
+pub trait Replicated: Sized {
+    const KIND: ComponentKind;
+
+    fn to_data(&self) -> ComponentData;
+
+    /// Returns None if the data is of a different component.
+    fn from_data(data: &ComponentData) -> Option<Self>;
+}
+
+#[derive(Debug, Default)]
+struct ReplicatedEntity {
+    components: Vec<ComponentData>,
+    /// Whether clients were told about the entity yet.
+    spawned: bool,
+    changed: Vec<ComponentKind>,
+}
+
+/// Last known state of every replicated entity, which is diffed into messages once per tick.
+#[derive(Debug, Default)]
+pub struct Replicator {
+    next_id: NetworkId,
+    entities: BTreeMap<NetworkId, ReplicatedEntity>,
+    despawned: Vec<NetworkId>,
+}
+
+impl Replicator {
+    /// Starts replicating a new entity, it's spawned on clients once it has components.
+    pub fn spawn(&mut self) -> NetworkId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.entities.insert(id, ReplicatedEntity::default());
+
+        id
+    }
+
+    /// Sets the state of a component, clients are only updated if it differs from the previous one.
+    pub fn set(&mut self, id: NetworkId, data: ComponentData) {
+        let Some(entity) = self.entities.get_mut(&id) else {
+            return;
+        };
+
+        let kind = data.kind();
+
+        match entity.components.iter_mut().find(|c| c.kind() == kind) {
+            Some(component) if *component == data => return,
+            Some(component) => *component = data,
+            None => entity.components.push(data),
+        }
+
+        if !entity.changed.contains(&kind) {
+            entity.changed.push(kind);
+        }
+    }
+
+    pub fn despawn(&mut self, id: NetworkId) {
+        if let Some(entity) = self.entities.remove(&id) {
+            // clients never heard of it
+            if entity.spawned {
+                self.despawned.push(id);
+            }
+        }
+    }
+
+    /// Returns the messages bringing clients up to date with the changes since the last call,
+    /// with the entity they are about.
+    pub fn changes(&mut self) -> Vec<(NetworkId, ServerMessage)> {
+        let mut messages = Vec::new();
+
+        for (&id, entity) in &mut self.entities {
+            if entity.components.is_empty() {
+                continue;
+            }
+
+            if !entity.spawned {
+                entity.spawned = true;
+                entity.changed.clear();
+
+                messages.push((
+                    id,
+                    ServerMessage::SpawnEntity {
+                        id,
+                        components: entity.components.clone(),
+                    },
+                ));
+            } else if !entity.changed.is_empty() {
+                let components = entity
+                    .components
+                    .iter()
+                    .filter(|c| entity.changed.contains(&c.kind()))
+                    .cloned()
+                    .collect();
+                entity.changed.clear();
+
+                messages.push((id, ServerMessage::UpdateEntity { id, components }));
+            }
+        }
+
+        messages.extend(
+            self.despawned
+                .drain(..)
+                .map(|id| (id, ServerMessage::DespawnEntity(id))),
+        );
+
+        messages
+    }
+
+    /// Messages spawning every entity clients already know about, for a client which just joined.
+    pub fn snapshot(&self) -> Vec<(NetworkId, ServerMessage)> {
+        self.entities
+            .iter()
+            .filter(|(_, entity)| entity.spawned)
+            .map(|(&id, entity)| {
+                (
+                    id,
+                    ServerMessage::SpawnEntity {
+                        id,
+                        components: entity.components.clone(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(x: f32) -> ComponentData {
+        ComponentData::Transform {
+            translation: [x, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    #[test]
+    fn sends_spawn_then_only_changed_components() {
+        let mut replicator = Replicator::default();
+        let id = replicator.spawn();
+
+        assert!(replicator.changes().is_empty());
+
+        replicator.set(id, transform(1.0));
+        replicator.set(id, ComponentData::ModelId("player".to_string()));
+        assert_eq!(
+            replicator.changes(),
+            vec![(
+                id,
+                ServerMessage::SpawnEntity {
+                    id,
+                    components: vec![transform(1.0), ComponentData::ModelId("player".to_string())],
+                }
+            )]
+        );
+
+        replicator.set(id, transform(1.0));
+        assert!(replicator.changes().is_empty());
+
+        replicator.set(id, transform(2.0));
+        assert_eq!(
+            replicator.changes(),
+            vec![(
+                id,
+                ServerMessage::UpdateEntity {
+                    id,
+                    components: vec![transform(2.0)],
+                }
+            )]
+        );
+
+        assert_eq!(replicator.snapshot().len(), 1);
+
+        replicator.despawn(id);
+        assert_eq!(
+            replicator.changes(),
+            vec![(id, ServerMessage::DespawnEntity(id))]
+        );
+        assert!(replicator.snapshot().is_empty());
+    }
+
+    #[test]
+    fn entities_despawned_before_spawning_are_never_sent() {
+        let mut replicator = Replicator::default();
+        let first = replicator.spawn();
+        replicator.set(first, transform(0.0));
+        replicator.despawn(first);
+
+        let second = replicator.spawn();
+
+        assert!(replicator.changes().is_empty());
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,163 @@
+//! Ways of carrying encoded messages between the client and the server.
+//!
+//! The server only sees `Transport`s, so a client connected over the network and a singleplayer client
+//! talking to a server running in the same process go through exactly the same code.
+
+use std::{
+    net::TcpStream,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    time::Duration,
+};
+
+use landmark_common::{netsim::SimulatedStream, secure::SecureError};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{frame, message::Compressible, ProtocolError};
+
+/// Carries messages encoded by `frame::encode` in order and without losing any.
+pub trait Transport: Send {
+    fn send(&mut self, payload: Vec<u8>) -> Result<(), ProtocolError>;
+
+    /// Waits for the next message.
+    fn recv(&mut self) -> Result<Vec<u8>, ProtocolError>;
+
+    /// Returns the next message if one has arrived, without waiting.
+    fn try_recv(&mut self) -> Result<Option<Vec<u8>>, ProtocolError>;
+}
+
+pub fn send_message<M: Serialize + Compressible>(
+    transport: &mut dyn Transport,
+    message: &M,
+    compression: bool,
+) -> Result<(), ProtocolError> {
+    transport.send(frame::encode(message, compression)?)
+}
+
+pub fn recv_message<M: DeserializeOwned>(
+    transport: &mut dyn Transport,
+) -> Result<M, ProtocolError> {
+    frame::decode(&transport.recv()?)
+}
+
+pub fn try_recv_message<M: DeserializeOwned>(
+    transport: &mut dyn Transport,
+) -> Result<Option<M>, ProtocolError> {
+    transport
+        .try_recv()?
+        .map(|payload| frame::decode(&payload))
+        .transpose()
+}
+
+/// One end of an in-memory connection, used between the client and a server in the same process.
+#[derive(Debug)]
+pub struct ChannelTransport {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl ChannelTransport {
+    /// Creates both ends of a connection.
+    pub fn pair() -> (Self, Self) {
+        let (client_sender, server_receiver) = mpsc::channel();
+        let (server_sender, client_receiver) = mpsc::channel();
+
+        (
+            Self {
+                sender: client_sender,
+                receiver: client_receiver,
+            },
+            Self {
+                sender: server_sender,
+                receiver: server_receiver,
+            },
+        )
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, payload: Vec<u8>) -> Result<(), ProtocolError> {
+        self.sender.send(payload).map_err(|_| ProtocolError::Closed)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, ProtocolError> {
+        self.receiver.recv().map_err(|_| ProtocolError::Closed)
+    }
+
+    fn try_recv(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        match self.receiver.try_recv() {
+            Ok(payload) => Ok(Some(payload)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(ProtocolError::Closed),
+        }
+    }
+}
+
+/// Encrypted connection over TCP, with the simulated network conditions applied.
+pub struct TcpTransport(SimulatedStream<TcpStream>);
+
+impl TcpTransport {
+    pub fn new(stream: SimulatedStream<TcpStream>) -> Self {
+        Self(stream)
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, payload: Vec<u8>) -> Result<(), ProtocolError> {
+        self.0.send(&payload).map_err(transport_error)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, ProtocolError> {
+        // polls instead of blocking, delayed outgoing messages are only sent while polling
+        loop {
+            if let Some(payload) = self.try_recv()? {
+                return Ok(payload);
+            }
+
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn try_recv(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        self.0.try_recv().map_err(transport_error)
+    }
+}
+
+fn transport_error(error: SecureError) -> ProtocolError {
+    match error {
+        SecureError::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            ProtocolError::Closed
+        }
+        e => ProtocolError::Transport(Box::new(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ClientMessage, ServerMessage};
+
+    #[test]
+    fn channel_carries_messages_until_closed() {
+        let (mut client, mut server) = ChannelTransport::pair();
+
+        assert!(try_recv_message::<ClientMessage>(&mut server)
+            .unwrap()
+            .is_none());
+
+        send_message(&mut client, &ClientMessage::Chat("hi".to_string()), true).unwrap();
+        assert!(matches!(
+            recv_message(&mut server).unwrap(),
+            ClientMessage::Chat(text) if text == "hi"
+        ));
+
+        drop(server);
+        assert!(matches!(
+            send_message(&mut client, &ClientMessage::Disconnect, false),
+            Err(ProtocolError::Closed)
+        ));
+        assert!(matches!(
+            recv_message::<ServerMessage>(&mut client),
+            Err(ProtocolError::Closed)
+        ));
+    }
+}
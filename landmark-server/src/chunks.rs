@@ -0,0 +1,384 @@
+//! Chunks of the world kept loaded by the server while players are near them.
+//!
+//! The server only decides which chunks are loaded and who receives them, generating, loading and saving
+//! chunks is left to a [`ChunkStore`] provided by the game.
+
+use std::fmt;
+
+use landmark_common::command::PermissionLevel;
+use landmark_protocol::message::{ChunkData, ServerMessage};
+
+use crate::{
+    interest::{ChunkPos, ConnectionId},
+    movement::distance,
+    tick::{BlockPos, WorldTick},
+    Server,
+};
+
+/// Level players need to play creative, which lets them change blocks out of reach.
+pub const CREATIVE_LEVEL: PermissionLevel = PermissionLevel::Moderator;
+/// Farthest distance from the eye to the center of a block players in survival can change, a bit more
+/// than clients allow so lagging positions aren't rejected.
+const REACH: f64 = 8.0;
+
+/// Blocks of the world, provided by the game which knows how to generate, load and save its chunks.
+pub trait ChunkStore: Send + fmt::Debug {
+    /// Starts loading a chunk for a player at `near`, closer chunks should be loaded first.
+    /// The chunk is returned by `poll` once it's ready.
+    fn request(&mut self, pos: ChunkPos, near: [f64; 3]);
+
+    /// Returns the chunks which finished loading since the last call, they stay loaded until `unload`.
+    fn poll(&mut self) -> Vec<ChunkPos>;
+
+    /// Returns a loaded chunk as it's sent to clients, None if it isn't loaded yet.
+    fn chunk_data(&self, pos: ChunkPos) -> Option<ChunkData>;
+
+    /// Returns the block at a position, None if its chunk isn't loaded.
+    fn block(&self, pos: BlockPos) -> Option<Option<u32>>;
+
+    /// Sets a block in a loaded chunk, returns false if the chunk isn't loaded.
+    fn set_block(&mut self, pos: BlockPos, block: Option<u32>) -> bool;
+
+    /// Returns the name of a block, which tick callbacks are registered with.
+    fn block_name(&self, block: u32) -> Option<&str>;
+
+    /// Returns true if no block of the loaded chunks is at or above a position.
+    fn sees_sky(&self, pos: BlockPos) -> bool;
+
+    /// Returns where the portal leads which a player with the eye at `eye` stands in or on, None if
+    /// there is no portal there.
+    fn portal_destination(&self, eye: [f64; 3]) -> Option<[f64; 3]>;
+
+    /// Registers what the blocks of the world do when they are updated or hit by random ticks.
+    fn register_ticks(&self, world_tick: &mut WorldTick);
+
+    /// Drops a loaded or requested chunk, its changes are kept for the next save.
+    fn unload(&mut self, pos: ChunkPos);
+
+    /// Writes the changed chunks to disk and waits until they are written.
+    fn save(&mut self);
+}
+
+impl Server {
+    /// Updates which chunks a client receives after its player moved. Loaded chunks are sent as
+    /// `ServerMessage::Chunk`, unloaded ones as `ServerMessage::UnloadChunk`.
+    pub fn player_moved(&mut self, connection: ConnectionId, position: [f64; 3]) {
+        let change = self
+            .interest
+            .player_moved(connection, position, self.config.view_distance);
+
+        for pos in change.load {
+            if self.loaded_chunks.insert(pos) {
+                self.chunks.request(pos, position);
+            } else if let Some(data) = self.chunks.chunk_data(pos) {
+                self.send(connection, ServerMessage::Chunk(data));
+            }
+        }
+
+        for pos in change.unload {
+            self.send(
+                connection,
+                ServerMessage::UnloadChunk([pos.x, pos.y, pos.z]),
+            );
+            self.release_chunk(pos);
+        }
+    }
+
+    /// Sends chunks which finished loading to the players waiting for them.
+    pub fn poll_chunks(&mut self) {
+        for pos in self.chunks.poll() {
+            let subscribers: Vec<ConnectionId> = self.interest.subscribers(pos).collect();
+
+            // every player moved away while it was loading
+            if subscribers.is_empty() || !self.loaded_chunks.contains(&pos) {
+                self.release_chunk(pos);
+                continue;
+            }
+
+            let Some(data) = self.chunks.chunk_data(pos) else {
+                continue;
+            };
+
+            for connection in subscribers {
+                self.send(connection, ServerMessage::Chunk(data.clone()));
+            }
+        }
+    }
+
+    /// Sends a loaded chunk again to a client which has it in view, e.g. because its copy got out of sync.
+    pub fn resend_chunk(&mut self, connection: ConnectionId, pos: ChunkPos) {
+        if !self.interest.is_subscribed(connection, pos) {
+            return;
+        }
+
+        if let Some(data) = self.chunks.chunk_data(pos) {
+            self.send(connection, ServerMessage::Chunk(data));
+        }
+    }
+
+    /// Changes a block on behalf of a player and tells the other players who have the chunk loaded.
+    /// Players can only change blocks in the chunks they have in view, and only within reach unless
+    /// they play creative. Rejected changes are reverted on the player's client.
+    pub fn player_set_block(
+        &mut self,
+        connection: ConnectionId,
+        pos: BlockPos,
+        block: Option<u32>,
+    ) {
+        if !self
+            .interest
+            .is_subscribed(connection, ChunkPos::of_block(pos))
+        {
+            log::warn!("Connection {connection} changed a block in a chunk it doesn't have");
+            return;
+        }
+
+        let Some(player) = self.players.get(connection) else {
+            return;
+        };
+
+        // the level is checked again as it may have been lowered since the player switched
+        let creative =
+            player.creative && self.permissions.level_of(&player.public_key) >= CREATIVE_LEVEL;
+        let center = [pos.x, pos.y, pos.z].map(|coord| coord as f64 + 0.5);
+
+        if !creative && distance(player.data.position, center) > REACH {
+            log::warn!("{} changed a block out of reach", player.data.name);
+
+            let current = self.chunks.block(pos).flatten();
+            let message = ServerMessage::BlockChanged {
+                position: [pos.x, pos.y, pos.z],
+                block: current,
+            };
+            return self.send(connection, message);
+        }
+
+        if self.chunks.set_block(pos, block) {
+            self.block_changed(pos, block, Some(connection));
+        }
+    }
+
+    /// Switches a player between creative and survival as their client reported, players who aren't
+    /// allowed to play creative stay in survival.
+    pub fn player_game_mode(&mut self, connection: ConnectionId, creative: bool) {
+        let Some(player) = self.players.get_mut(connection) else {
+            return;
+        };
+
+        if creative && self.permissions.level_of(&player.public_key) < CREATIVE_LEVEL {
+            log::warn!(
+                "{} switched to creative without being allowed",
+                player.data.name
+            );
+            player.creative = false;
+        } else {
+            player.creative = creative;
+        }
+    }
+
+    /// Tells the players who have the chunk loaded about a changed block, except the one who changed it.
+    pub fn block_changed(
+        &mut self,
+        pos: BlockPos,
+        block: Option<u32>,
+        except: Option<ConnectionId>,
+    ) {
+        let recipients: Vec<ConnectionId> = self
+            .interest
+            .block_update_recipients(pos)
+            .filter(|recipient| Some(*recipient) != except)
+            .collect();
+
+        for recipient in recipients {
+            self.send(
+                recipient,
+                ServerMessage::BlockChanged {
+                    position: [pos.x, pos.y, pos.z],
+                    block,
+                },
+            );
+        }
+    }
+
+    /// Unloads a chunk from the store once no player has it in view anymore.
+    pub fn release_chunk(&mut self, pos: ChunkPos) {
+        if self.interest.subscribers(pos).next().is_some() {
+            return;
+        }
+
+        self.loaded_chunks.remove(&pos);
+        self.chunks.unload(pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use landmark_common::secure::fingerprint;
+    use landmark_protocol::transport::{try_recv_message, ChannelTransport};
+
+    use super::*;
+    use crate::tests::{join, test_server};
+
+    /// Returns the messages about chunks and blocks sent to the client.
+    fn chunk_messages(server: &mut Server, client: &mut ChannelTransport) -> Vec<ServerMessage> {
+        server.flush_outbox();
+
+        let mut messages = Vec::new();
+        while let Some(message) = try_recv_message::<ServerMessage>(client).unwrap() {
+            if matches!(
+                message,
+                ServerMessage::Chunk(_)
+                    | ServerMessage::UnloadChunk(_)
+                    | ServerMessage::BlockChanged { .. }
+            ) {
+                messages.push(message);
+            }
+        }
+
+        messages
+    }
+
+    fn received_chunks(messages: &[ServerMessage]) -> HashSet<[i32; 3]> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                ServerMessage::Chunk(data) => Some(data.coords),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn players_receive_chunks_around_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (connection, mut client) = join(&mut server, b"player", "Player");
+
+        // chunks are sent once the store loaded them
+        server.poll_chunks();
+        let chunks = received_chunks(&chunk_messages(&mut server, &mut client));
+        assert!(chunks.contains(&[0, 2, 0]));
+        assert!(chunks.contains(&[1, 2, 0]));
+        assert!(!chunks.contains(&[3, 2, 0]));
+
+        server.player_moved(connection, [200.0, 80.0, 0.0]);
+        server.poll_chunks();
+        let messages = chunk_messages(&mut server, &mut client);
+
+        assert!(messages.contains(&ServerMessage::UnloadChunk([0, 2, 0])));
+        assert!(received_chunks(&messages).contains(&[6, 2, 0]));
+        assert!(server.chunks.chunk_data(ChunkPos::new(0, 2, 0)).is_none());
+    }
+
+    #[test]
+    fn chunks_in_view_can_be_requested_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (connection, mut client) = join(&mut server, b"player", "Player");
+        server.poll_chunks();
+        chunk_messages(&mut server, &mut client);
+
+        server.resend_chunk(connection, ChunkPos::new(0, 2, 0));
+        server.resend_chunk(connection, ChunkPos::new(5, 2, 0));
+
+        assert_eq!(
+            received_chunks(&chunk_messages(&mut server, &mut client)),
+            HashSet::from([[0, 2, 0]])
+        );
+    }
+
+    #[test]
+    fn block_changes_reach_other_players() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (first, mut first_client) = join(&mut server, b"first", "First");
+        let (_, mut second_client) = join(&mut server, b"second", "Second");
+        server
+            .permissions
+            .set(&fingerprint(b"first"), PermissionLevel::Moderator);
+        server.player_game_mode(first, true);
+        server.poll_chunks();
+        chunk_messages(&mut server, &mut first_client);
+        chunk_messages(&mut server, &mut second_client);
+
+        let pos = BlockPos::new(1, 70, 1);
+        server.player_set_block(first, pos, Some(3));
+
+        assert_eq!(server.chunks.block(pos), Some(Some(3)));
+        assert!(chunk_messages(&mut server, &mut first_client).is_empty());
+        assert_eq!(
+            chunk_messages(&mut server, &mut second_client),
+            vec![ServerMessage::BlockChanged {
+                position: [1, 70, 1],
+                block: Some(3),
+            }]
+        );
+
+        // blocks out of view can't be changed
+        let far = BlockPos::new(1000, 70, 1);
+        server.player_set_block(first, far, Some(3));
+        assert_eq!(server.chunks.block(far), None);
+    }
+
+    #[test]
+    fn survival_players_only_change_blocks_within_reach() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (player, mut client) = join(&mut server, b"player", "Player");
+        server.poll_chunks();
+        chunk_messages(&mut server, &mut client);
+
+        let near = BlockPos::new(1, 76, 1);
+        server.player_set_block(player, near, Some(3));
+        assert_eq!(server.chunks.block(near), Some(Some(3)));
+
+        // guests can't play creative to get around it, the client is told the block didn't change
+        server.player_game_mode(player, true);
+        let far = BlockPos::new(1, 70, 1);
+        server.player_set_block(player, far, Some(3));
+
+        assert_eq!(server.chunks.block(far), Some(None));
+        assert_eq!(
+            chunk_messages(&mut server, &mut client),
+            vec![ServerMessage::BlockChanged {
+                position: [1, 70, 1],
+                block: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn players_lose_creative_with_their_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (player, mut client) = join(&mut server, b"player", "Player");
+        server
+            .permissions
+            .set(&fingerprint(b"player"), PermissionLevel::Moderator);
+        server.player_game_mode(player, true);
+        server.poll_chunks();
+
+        let pos = BlockPos::new(1, 70, 1);
+        server.player_set_block(player, pos, Some(3));
+        assert_eq!(server.chunks.block(pos), Some(Some(3)));
+
+        server
+            .permissions
+            .set(&fingerprint(b"player"), PermissionLevel::Guest);
+        server.permissions_changed();
+        server.player_set_block(player, pos, None);
+        assert_eq!(server.chunks.block(pos), Some(Some(3)));
+
+        server.flush_outbox();
+        let mut messages = Vec::new();
+        while let Some(message) = try_recv_message::<ServerMessage>(&mut client).unwrap() {
+            messages.push(message);
+        }
+        assert!(messages.contains(&ServerMessage::Permissions {
+            level: PermissionLevel::Guest,
+            creative: false,
+        }));
+    }
+}
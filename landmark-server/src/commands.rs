@@ -0,0 +1,280 @@
+use landmark_common::{
+    command::{CommandRegistry, PermissionLevel},
+    netsim::NetworkConditions,
+};
+
+use crate::{movement::TELEPORT_LEVEL, weather::parse_weather, Server};
+
+/// Creates the registry of commands typed into the server console or sent by players.
+pub fn server_commands() -> CommandRegistry<Server> {
+    let mut commands = CommandRegistry::<Server>::new();
+
+    commands.register_restricted(
+        "reload",
+        PermissionLevel::Admin,
+        "",
+        "reloads the server configuration and permissions",
+        |server, _| {
+            server.reload_config()?;
+            Ok("Reloaded the configuration".to_string())
+        },
+    );
+
+    commands.register_restricted(
+        "permission",
+        PermissionLevel::Admin,
+        "<fingerprint> [level]",
+        "shows or sets the permission level of a player",
+        |server, args| match args {
+            [player] => Ok(format!(
+                "{player} is {}",
+                server.permissions.level_of_fingerprint(player)
+            )),
+            [player, level] => {
+                let level = level.parse::<PermissionLevel>()?;
+
+                server.permissions.set(player, level);
+                server.permissions.save().map_err(|e| e.to_string())?;
+                server.permissions_changed();
+
+                Ok(format!("{player} is now {level}"))
+            }
+            _ => Err("expected a key fingerprint and an optional level".to_string()),
+        },
+    );
+
+    commands.register_restricted(
+        "whitelist",
+        PermissionLevel::Admin,
+        "<add | remove> <fingerprint>",
+        "allows or forbids a player to join while the whitelist is enabled",
+        |server, args| {
+            let message = match args {
+                ["add", player] => {
+                    server.permissions.whitelist.insert(player.to_string());
+                    format!("Added {player} to the whitelist")
+                }
+                ["remove", player] => {
+                    server.permissions.whitelist.remove(*player);
+                    format!("Removed {player} from the whitelist")
+                }
+                _ => return Err("expected add or remove and a key fingerprint".to_string()),
+            };
+
+            server.permissions.save().map_err(|e| e.to_string())?;
+            Ok(message)
+        },
+    );
+
+    commands.register_restricted(
+        "netsim",
+        PermissionLevel::Admin,
+        "[off | <latency_ms> [jitter_ms] [loss%]]",
+        "shows or sets simulated latency, jitter and packet loss of player connections",
+        |server, args| {
+            if args.is_empty() {
+                return Ok(format!(
+                    "Simulating {}",
+                    server.network_simulator.conditions()
+                ));
+            }
+
+            let conditions = NetworkConditions::from_args(args)?;
+            server.network_simulator.set_conditions(conditions);
+
+            Ok(format!("Now simulating {conditions}"))
+        },
+    );
+
+    commands.register_restricted(
+        "setspawn",
+        PermissionLevel::Moderator,
+        "",
+        "makes players spawn where the player stands",
+        |server, _| {
+            let position = server.sender_position()?;
+
+            server
+                .spawn_point
+                .set(position)
+                .map_err(|e| format!("failed to save the spawn point: {e}"))?;
+
+            Ok(format!(
+                "Set the spawn point to {:.1} {:.1} {:.1}",
+                position[0], position[1], position[2]
+            ))
+        },
+    );
+
+    commands.register("spawn", "", "teleports to the spawn point", |server, _| {
+        let connection = server.command_sender()?;
+        server.teleport(connection, server.spawn_point.position());
+
+        Ok("Teleporting to the spawn point".to_string())
+    });
+
+    commands.register(
+        "sethome",
+        "",
+        "makes /home teleport to where the player stands",
+        |server, _| {
+            let connection = server.command_sender()?;
+            let Some(player) = server.players.get_mut(connection) else {
+                return Err("the player isn't online".to_string());
+            };
+
+            let position = player.data.position;
+            player.data.home = Some(position);
+
+            Ok(format!(
+                "Set the home point to {:.1} {:.1} {:.1}",
+                position[0], position[1], position[2]
+            ))
+        },
+    );
+
+    commands.register(
+        "home",
+        "",
+        "teleports to the point set with /sethome",
+        |server, _| {
+            let connection = server.command_sender()?;
+            let Some(position) = server
+                .players
+                .get(connection)
+                .and_then(|player| player.data.home)
+            else {
+                return Err("no home point is set, use /sethome first".to_string());
+            };
+
+            server.teleport(connection, position);
+            Ok("Teleporting home".to_string())
+        },
+    );
+
+    commands.register(
+        "top",
+        "",
+        "teleports onto the highest block of the column the player is in",
+        |server, _| {
+            let connection = server.command_sender()?;
+            let position = server
+                .surface_above(server.sender_position()?)
+                .ok_or_else(|| "there is no block above or below the player".to_string())?;
+
+            server.teleport(connection, position);
+            Ok("Teleporting to the surface".to_string())
+        },
+    );
+
+    commands.register_restricted(
+        "tp",
+        TELEPORT_LEVEL,
+        "<x> <y> <z>",
+        "teleports to the given position",
+        |server, args| {
+            let [x, y, z] = args else {
+                return Err("expected three coordinates".to_string());
+            };
+
+            let parse = |value: &str| {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| format!("{value} is not a number"))
+            };
+            let position = [parse(x)?, parse(y)?, parse(z)?];
+
+            let connection = server.command_sender()?;
+            server.teleport(connection, position);
+
+            Ok(format!(
+                "Teleported to {:.1} {:.1} {:.1}",
+                position[0], position[1], position[2]
+            ))
+        },
+    );
+
+    commands.register(
+        "list",
+        "",
+        "lists the players who are online",
+        |server, _| {
+            let names: Vec<&str> = server
+                .players
+                .online()
+                .map(|player| player.data.name.as_str())
+                .collect();
+
+            Ok(match names.as_slice() {
+                [] => "Nobody is online".to_string(),
+                names => format!("{} online: {}", names.len(), names.join(", ")),
+            })
+        },
+    );
+
+    commands.register_restricted(
+        "kick",
+        PermissionLevel::Moderator,
+        "<name> [reason]",
+        "disconnects a player",
+        |server, args| {
+            let [name, reason @ ..] = args else {
+                return Err("expected the name of a player".to_string());
+            };
+
+            let reason = match reason {
+                [] => "Kicked by an operator".to_string(),
+                words => words.join(" "),
+            };
+
+            server.kick(name, &reason)?;
+            Ok(format!("Kicked {name}: {reason}"))
+        },
+    );
+
+    commands.register_restricted(
+        "save-all",
+        PermissionLevel::Admin,
+        "",
+        "saves the world and the data of every online player",
+        |server, _| {
+            server.save_all();
+            Ok("Saved the world".to_string())
+        },
+    );
+
+    commands.register_restricted(
+        "stop",
+        PermissionLevel::Admin,
+        "",
+        "saves everything and stops the server",
+        |server, _| {
+            server.running = false;
+            Ok("Stopping the server".to_string())
+        },
+    );
+
+    commands.register_restricted(
+        "weather",
+        PermissionLevel::Moderator,
+        "[clear | rain | storm]",
+        "shows or changes the weather",
+        |server, args| match args {
+            [] => Ok(format!("The weather is {:?}", server.weather.current())),
+            [name] => {
+                let weather = parse_weather(name)
+                    .ok_or_else(|| format!("{name} is not a kind of weather"))?;
+
+                server.weather.set(weather);
+                server.broadcast_weather(weather);
+
+                Ok(format!("Changed the weather to {weather:?}"))
+            }
+            _ => Err("expected clear, rain or storm".to_string()),
+        },
+    );
+
+    commands
+}
@@ -0,0 +1,179 @@
+use std::{fmt, fs, io, net::SocketAddr, path::PathBuf};
+
+use crate::scheduler::{TaskAction, TaskConfig};
+
+/// Server configuration loaded from `server.toml` in the working directory.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    pub max_players: u32,
+    /// Radius of chunks sent to players.
+    pub view_distance: u32,
+    pub world_path: PathBuf,
+    /// Disables autosaving if not present.
+    pub autosave_interval_secs: Option<u64>,
+    /// Only lets in the players whitelisted in the permissions if enabled.
+    pub whitelist: bool,
+    pub motd: String,
+    /// Seed of the weather of new worlds, a random one is picked if not present. Existing worlds keep
+    /// their seed. Terrain is generated by the client and doesn't depend on it.
+    pub seed: Option<u64>,
+    /// Address of the remote admin console, which is disabled if not present.
+    pub admin_address: Option<SocketAddr>,
+    /// Password of the remote admin console, required if it's enabled.
+    pub admin_password: Option<String>,
+    /// File holding the key pair which identifies the server to clients.
+    pub identity_key_path: PathBuf,
+    pub tasks: Vec<TaskConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 25665)),
+            max_players: 16,
+            view_distance: 8,
+            world_path: PathBuf::from("world"),
+            autosave_interval_secs: Some(300),
+            whitelist: false,
+            motd: String::from("A Landmark server"),
+            seed: None,
+            admin_address: None,
+            admin_password: None,
+            identity_key_path: PathBuf::from("server.key"),
+            tasks: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub const PATH: &'static str = "server.toml";
+    pub const MAX_VIEW_DISTANCE: u32 = 32;
+
+    /// Loads and validates the configuration, using defaults if the file doesn't exist.
+    pub fn load() -> Result<Self, ConfigError> {
+        let content = match fs::read_to_string(Self::PATH) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                log::info!("Config file {} not found, using defaults", Self::PATH);
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(ConfigError::Io(e)),
+        };
+
+        let config: Self = toml::from_str(content.as_str()).map_err(ConfigError::Parse)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_players == 0 {
+            return Err(ConfigError::Invalid(
+                "max_players must be greater than 0".to_string(),
+            ));
+        }
+
+        if !(1..=Self::MAX_VIEW_DISTANCE).contains(&self.view_distance) {
+            return Err(ConfigError::Invalid(format!(
+                "view_distance must be between 1 and {}",
+                Self::MAX_VIEW_DISTANCE
+            )));
+        }
+
+        if self.autosave_interval_secs == Some(0) {
+            return Err(ConfigError::Invalid(
+                "autosave_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.admin_address.is_some() && self.admin_password.as_deref().unwrap_or("").is_empty() {
+            return Err(ConfigError::Invalid(
+                "admin_password must be set to enable the admin console".to_string(),
+            ));
+        }
+
+        if let Some(task) = self.tasks.iter().find(|task| task.interval_secs == 0) {
+            return Err(ConfigError::Invalid(format!(
+                "Task {} must have an interval greater than 0",
+                task.name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns all recurring tasks including the ones implied by other options.
+    pub fn scheduled_tasks(&self) -> Vec<TaskConfig> {
+        let mut tasks = self.tasks.clone();
+
+        if let Some(interval_secs) = self.autosave_interval_secs {
+            tasks.push(TaskConfig {
+                name: String::from("autosave"),
+                interval_secs,
+                delay_secs: None,
+                action: TaskAction::Autosave,
+            });
+        }
+
+        tasks
+    }
+
+    /// Applies values which can change while the server is running, keeping the rest.
+    pub fn apply_reload(&mut self, new: ServerConfig) {
+        if new.bind_address != self.bind_address {
+            log::warn!("Changing bind_address requires a restart");
+        }
+
+        if new.world_path != self.world_path {
+            log::warn!("Changing world_path requires a restart");
+        }
+
+        if new.identity_key_path != self.identity_key_path {
+            log::warn!("Changing identity_key_path requires a restart");
+        }
+
+        if new.admin_address != self.admin_address || new.admin_password != self.admin_password {
+            log::warn!("Changing admin_address or admin_password requires a restart");
+        }
+
+        *self = ServerConfig {
+            bind_address: self.bind_address,
+            world_path: self.world_path.clone(),
+            identity_key_path: self.identity_key_path.clone(),
+            admin_address: self.admin_address,
+            admin_password: self.admin_password.clone(),
+            ..new
+        };
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Failed to read {}: {e}", ServerConfig::PATH),
+            ConfigError::Parse(e) => write!(f, "Failed to parse {}: {e}", ServerConfig::PATH),
+            ConfigError::Invalid(reason) => {
+                write!(f, "Invalid value in {}: {reason}", ServerConfig::PATH)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Parse(e) => Some(e),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Sources of commands executed with the admin permission level: the terminal the server runs in,
+//! and optionally a remote admin console reachable over TCP.
+//!
+//! The remote console speaks plain text lines. The first line has to be the password from the config,
+//! every following line is executed as a command and answered with its output and an empty line.
+//! The connection isn't encrypted, so the console should only listen on trusted networks.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+/// A command typed into one of the consoles.
+#[derive(Debug)]
+pub struct ConsoleLine {
+    pub line: String,
+    /// Where to send the output, lines typed into the terminal are answered in the log.
+    pub reply: Option<Sender<String>>,
+}
+
+/// Starts reading commands from the terminal and, if configured, from remote admin consoles.
+/// Lines are read on separate threads so the tick loop never blocks on them.
+pub fn spawn_consoles(admin: Option<(SocketAddr, String)>) -> Receiver<ConsoleLine> {
+    let (sender, receiver) = mpsc::channel();
+
+    spawn_terminal_reader(sender.clone());
+
+    if let Some((address, password)) = admin {
+        match TcpListener::bind(address) {
+            Ok(listener) => {
+                log::info!("Admin console listening on {address}");
+                spawn_admin_listener(listener, password, sender);
+            }
+            Err(e) => log::error!("Failed to start the admin console on {address}: {e}"),
+        }
+    }
+
+    receiver
+}
+
+fn spawn_terminal_reader(sender: Sender<ConsoleLine>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if sender.send(ConsoleLine { line, reply: None }).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_admin_listener(listener: TcpListener, password: String, sender: Sender<ConsoleLine>) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("Failed to accept an admin console connection: {e}");
+                    continue;
+                }
+            };
+
+            let password = password.clone();
+            let sender = sender.clone();
+
+            std::thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+
+                if let Err(e) = serve_admin(stream, &password, &sender) {
+                    log::warn!("Admin console connection from {peer:?} failed: {e}");
+                }
+            });
+        }
+    });
+}
+
+fn serve_admin(
+    stream: TcpStream,
+    password: &str,
+    sender: &Sender<ConsoleLine>,
+) -> std::io::Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut writer = stream.try_clone()?;
+    let mut lines = BufReader::new(stream).lines();
+
+    match lines.next() {
+        Some(Ok(line)) if line == password => {
+            log::info!("Admin console connected from {peer}");
+            writeln!(writer, "Logged in\n")?;
+        }
+        Some(Ok(_)) => {
+            log::warn!("Admin console login from {peer} with a wrong password");
+            writeln!(writer, "Wrong password")?;
+            return Ok(());
+        }
+        _ => return Ok(()),
+    }
+
+    for line in lines {
+        let line = line?;
+        let (reply, output) = mpsc::channel();
+
+        log::info!("Admin console {peer} executed {line}");
+
+        let line = ConsoleLine {
+            line,
+            reply: Some(reply),
+        };
+
+        // the server stopped
+        if sender.send(line).is_err() {
+            break;
+        }
+
+        let Ok(output) = output.recv() else {
+            break;
+        };
+
+        writeln!(writer, "{output}\n")?;
+    }
+
+    log::info!("Admin console {peer} disconnected");
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::tick::BlockPos;
+
+/// Edge length of a chunk in blocks, the same as on the client.
+pub const CHUNK_SIZE: i32 = 32;
+/// Layers of chunks sent above and below the player, the same as the client loads.
+const VERTICAL_RADIUS: i32 = 1;
+
+/// Identifies a connected client.
+pub type ConnectionId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Returns the chunk containing a position in world space.
+    pub fn containing(position: [f64; 3]) -> Self {
+        let [x, y, z] = position.map(|axis| (axis.floor() as i32).div_euclid(CHUNK_SIZE));
+
+        Self::new(x, y, z)
+    }
+
+    pub fn of_block(pos: BlockPos) -> Self {
+        Self::new(
+            pos.x.div_euclid(CHUNK_SIZE),
+            pos.y.div_euclid(CHUNK_SIZE),
+            pos.z.div_euclid(CHUNK_SIZE),
+        )
+    }
+
+    fn distance_squared(self, other: ChunkPos) -> i32 {
+        let (dx, dz) = (self.x - other.x, self.z - other.z);
+        dx * dx + dz * dz
+    }
+
+    /// Returns true if the chunk lies within `radius` chunks of `center`, in the same shape the client loads.
+    fn is_in_range(self, center: ChunkPos, radius: i32) -> bool {
+        self.distance_squared(center) <= radius * radius
+            && (self.y - center.y).abs() <= VERTICAL_RADIUS
+    }
+}
+
+/// Chunks to send to a client and chunks it should drop after it moved.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SubscriptionChange {
+    /// Closest chunks first, so the client sees its surroundings first.
+    pub load: Vec<ChunkPos>,
+    pub unload: Vec<ChunkPos>,
+}
+
+/// Chunks a single client receives, the ones within its view distance.
+#[derive(Debug, Default)]
+pub struct Subscription {
+    center: Option<ChunkPos>,
+    chunks: HashSet<ChunkPos>,
+}
+
+impl Subscription {
+    pub fn contains(&self, chunk: ChunkPos) -> bool {
+        self.chunks.contains(&chunk)
+    }
+
+    /// Moves the subscription to a new center. Chunks are unloaded with a margin of one chunk,
+    /// so walking along the border of the view distance doesn't send the same chunks again and again.
+    pub fn update(&mut self, center: ChunkPos, view_distance: u32) -> SubscriptionChange {
+        if self.center == Some(center) {
+            return SubscriptionChange::default();
+        }
+
+        self.center = Some(center);
+
+        let radius = view_distance as i32;
+        let mut change = SubscriptionChange::default();
+
+        self.chunks.retain(|chunk| {
+            let keep = chunk.is_in_range(center, radius + 1);
+
+            if !keep {
+                change.unload.push(*chunk);
+            }
+
+            keep
+        });
+
+        for dy in -VERTICAL_RADIUS..=VERTICAL_RADIUS {
+            for dz in -radius..=radius {
+                for dx in -radius..=radius {
+                    let chunk = ChunkPos::new(center.x + dx, center.y + dy, center.z + dz);
+
+                    if chunk.is_in_range(center, radius) && self.chunks.insert(chunk) {
+                        change.load.push(chunk);
+                    }
+                }
+            }
+        }
+
+        change
+            .load
+            .sort_by_key(|chunk| (chunk.distance_squared(center), (chunk.y - center.y).abs()));
+
+        change
+    }
+}
+
+/// Subscriptions of every connected client, deciding who receives chunks and block updates.
+#[derive(Debug, Default)]
+pub struct Interest {
+    subscriptions: HashMap<ConnectionId, Subscription>,
+}
+
+impl Interest {
+    pub fn connect(&mut self, connection: ConnectionId) {
+        self.subscriptions
+            .insert(connection, Subscription::default());
+    }
+
+    /// Removes the subscription of a client, returns the chunks it had loaded.
+    pub fn disconnect(&mut self, connection: ConnectionId) -> Vec<ChunkPos> {
+        self.subscriptions
+            .remove(&connection)
+            .map(|subscription| subscription.chunks.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Updates the subscription of a client after its player moved to `position`.
+    pub fn player_moved(
+        &mut self,
+        connection: ConnectionId,
+        position: [f64; 3],
+        view_distance: u32,
+    ) -> SubscriptionChange {
+        match self.subscriptions.get_mut(&connection) {
+            Some(subscription) => {
+                subscription.update(ChunkPos::containing(position), view_distance)
+            }
+            None => SubscriptionChange::default(),
+        }
+    }
+
+    pub fn is_subscribed(&self, connection: ConnectionId, chunk: ChunkPos) -> bool {
+        self.subscriptions
+            .get(&connection)
+            .is_some_and(|subscription| subscription.contains(chunk))
+    }
+
+    /// Returns the clients which have the chunk loaded.
+    pub fn subscribers(&self, chunk: ChunkPos) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.subscriptions
+            .iter()
+            .filter(move |(_, subscription)| subscription.contains(chunk))
+            .map(|(connection, _)| *connection)
+    }
+
+    /// Returns the clients which should be told about a change of the block.
+    pub fn block_update_recipients(
+        &self,
+        pos: BlockPos,
+    ) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.subscribers(ChunkPos::of_block(pos))
+    }
+
+    /// Forgets the centers of all subscriptions, so the next movement applies a changed view distance.
+    pub fn reset_centers(&mut self) {
+        for subscription in self.subscriptions.values_mut() {
+            subscription.center = None;
+        }
+    }
+}
@@ -1 +1,644 @@
-pub fn run() {}
+mod chunks;
+mod commands;
+mod config;
+mod console;
+mod interest;
+mod movement;
+mod network;
+mod permissions;
+mod players;
+mod scheduler;
+mod tick;
+mod weather;
+mod world;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use chunks::CREATIVE_LEVEL;
+use commands::server_commands;
+use config::ServerConfig;
+use console::{spawn_consoles, ConsoleLine};
+use interest::{ConnectionId, Interest};
+use landmark_common::{
+    command::{CommandError, CommandRegistry, PermissionLevel},
+    netsim::NetworkSimulator,
+    secure::{fingerprint, Keypair},
+};
+use landmark_protocol::{
+    message::{ServerMessage, Weather},
+    replication::{NetworkId, Replicator},
+    transport::{ChannelTransport, TcpTransport},
+};
+use network::Connection;
+use permissions::Permissions;
+use players::PlayerRegistry;
+use scheduler::{Scheduler, TaskAction};
+use weather::WeatherCycle;
+use world::{SpawnPoint, WorldInfo};
+
+pub use chunks::ChunkStore;
+pub use interest::ChunkPos;
+pub use tick::{BlockPos, TickCallback, TickContext, WorldTick};
+
+#[derive(Debug)]
+struct Server {
+    config: ServerConfig,
+    permissions: Permissions,
+    /// Shared, so commands can be executed with mutable access to the server.
+    commands: Arc<CommandRegistry<Server>>,
+    /// Player executing the current command, None while the console executes one.
+    command_sender: Option<ConnectionId>,
+    /// Identity presented to clients during the encrypted handshake.
+    identity: Keypair,
+    /// Clients which completed the handshake with the listener, accepted at the start of the next tick.
+    incoming: Option<Receiver<(TcpTransport, Vec<u8>)>>,
+    /// Conditions simulated on connections of players, for testing bad networks locally.
+    network_simulator: NetworkSimulator,
+    scheduler: Scheduler,
+    /// Number of ticks since the start of the current day.
+    day_time: u64,
+    world_tick: WorldTick,
+    weather: WeatherCycle,
+    /// Chunks every client is subscribed to.
+    interest: Interest,
+    /// Blocks of the world, only the chunks players have in view are loaded.
+    chunks: Box<dyn ChunkStore>,
+    /// Chunks requested from the store which a player still has in view.
+    loaded_chunks: HashSet<ChunkPos>,
+    players: PlayerRegistry,
+    spawn_point: SpawnPoint,
+    connections: HashMap<ConnectionId, Connection>,
+    next_connection: ConnectionId,
+    /// Entities synchronized to clients.
+    entities: Replicator,
+    /// Entity of the player on each connection.
+    player_entities: HashMap<ConnectionId, NetworkId>,
+    /// Messages to clients, sent at the end of the tick.
+    outbox: Vec<(ConnectionId, ServerMessage)>,
+    /// Cleared by the `stop` command to shut the server down after the current tick.
+    running: bool,
+}
+
+impl Server {
+    pub const TICKS_PER_SECOND: u32 = 20;
+
+    pub fn new(
+        config: ServerConfig,
+        permissions: Permissions,
+        identity: Keypair,
+        world_info: WorldInfo,
+        chunks: Box<dyn ChunkStore>,
+    ) -> Self {
+        let scheduler = Scheduler::new(&config.scheduled_tasks(), Instant::now());
+        let players = PlayerRegistry::new(&config.world_path);
+        let spawn_point = SpawnPoint::load(&config.world_path);
+        let mut world_tick = WorldTick::new();
+        chunks.register_ticks(&mut world_tick);
+
+        Self {
+            config,
+            permissions,
+            commands: Arc::new(server_commands()),
+            command_sender: None,
+            identity,
+            incoming: None,
+            network_simulator: NetworkSimulator::default(),
+            scheduler,
+            day_time: 0,
+            world_tick,
+            weather: WeatherCycle::new(world_info.seed),
+            interest: Interest::default(),
+            chunks,
+            loaded_chunks: HashSet::new(),
+            players,
+            spawn_point,
+            connections: HashMap::new(),
+            next_connection: 0,
+            entities: Replicator::default(),
+            player_entities: HashMap::new(),
+            outbox: Vec::new(),
+            running: true,
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        for action in self.scheduler.poll(now) {
+            self.run_task(action);
+        }
+
+        let changed = self
+            .world_tick
+            .step(self.chunks.as_mut(), &self.loaded_chunks);
+        for pos in changed {
+            if let Some(block) = self.chunks.block(pos) {
+                self.block_changed(pos, block, None);
+            }
+        }
+
+        self.day_time += 1;
+
+        if let Some(weather) = self.weather.step() {
+            self.broadcast_weather(weather);
+        }
+    }
+
+    /// Tells every player about a change of the weather.
+    fn broadcast_weather(&mut self, weather: Weather) {
+        log::info!("The weather changed to {weather:?}");
+
+        for connection in self.players.connections() {
+            self.send(connection, ServerMessage::Weather(weather));
+        }
+    }
+
+    fn run_task(&mut self, action: TaskAction) {
+        match action {
+            TaskAction::Autosave => {
+                log::info!("Autosaving the world");
+                self.save_all();
+            }
+            TaskAction::Backup => log::info!("Creating a world backup"),
+            TaskAction::Announce { message } => log::info!("[Announcement] {message}"),
+            TaskAction::DayReset => {
+                log::info!("Resetting the day cycle");
+                self.day_time = 0;
+            }
+        }
+    }
+
+    /// Disconnects a player, telling their client why.
+    fn kick(&mut self, name: &str, reason: &str) -> Result<(), String> {
+        let connection = self
+            .players
+            .connection_of(name)
+            .ok_or_else(|| format!("{name} isn't online"))?;
+
+        self.send(
+            connection,
+            ServerMessage::Disconnect {
+                reason: reason.to_string(),
+            },
+        );
+
+        Ok(())
+    }
+
+    fn save_all(&mut self) {
+        self.chunks.save();
+        self.players.save_all();
+    }
+
+    /// Saves everything before the server exits.
+    fn shutdown(&mut self) {
+        log::info!("Stopping the server");
+
+        self.save_all();
+
+        for connection in self.connections.keys().copied().collect::<Vec<_>>() {
+            self.send(
+                connection,
+                ServerMessage::Disconnect {
+                    reason: "The server stopped".to_string(),
+                },
+            );
+        }
+
+        self.flush_outbox();
+    }
+
+    /// Executes a command typed into a console, which is allowed to execute every command.
+    /// The output is logged and sent back to remote consoles.
+    pub fn handle_console_line(&mut self, console_line: ConsoleLine) {
+        let ConsoleLine { line, reply } = console_line;
+
+        if line.trim().is_empty() {
+            return;
+        }
+
+        let output = match self.execute_command(&line, PermissionLevel::Admin) {
+            Ok(output) => {
+                for line in output.lines() {
+                    log::info!("{line}");
+                }
+
+                output
+            }
+            Err(e) => {
+                log::warn!("{e}");
+                e.to_string()
+            }
+        };
+
+        if let Some(reply) = reply {
+            // the remote console may have disconnected meanwhile
+            let _ = reply.send(output);
+        }
+    }
+
+    /// Executes a command forwarded by a player with the permission level of their identity,
+    /// returns the text shown to the player.
+    pub fn handle_player_command(&mut self, connection: ConnectionId, line: &str) -> String {
+        let public_key = self.connections[&connection].public_key.clone();
+        let level = self.permissions.level_of(&public_key);
+
+        self.command_sender = Some(connection);
+        let result = self.execute_command(line, level);
+        self.command_sender = None;
+
+        log::info!(
+            "Player {} ({level}) executed {line}: {}",
+            fingerprint(&public_key),
+            if result.is_ok() { "ok" } else { "failed" }
+        );
+
+        result.unwrap_or_else(|e| e.to_string())
+    }
+
+    /// Tells a player their permission level and whether they may play creative.
+    fn send_permissions(&mut self, connection: ConnectionId) {
+        let Some(player) = self.players.get(connection) else {
+            return;
+        };
+
+        let level = self.permissions.level_of(&player.public_key);
+        let message = ServerMessage::Permissions {
+            level,
+            creative: level >= CREATIVE_LEVEL,
+        };
+        self.send(connection, message);
+    }
+
+    /// Tells every online player their permission level again, after the permissions changed.
+    fn permissions_changed(&mut self) {
+        for connection in self.players.connections() {
+            self.send_permissions(connection);
+        }
+    }
+
+    /// Returns the player executing the current command, for commands which only players can execute.
+    fn command_sender(&self) -> Result<ConnectionId, String> {
+        self.command_sender
+            .ok_or_else(|| "only players can execute this command".to_string())
+    }
+
+    /// Returns the eye position of the player executing the current command.
+    fn sender_position(&self) -> Result<[f64; 3], String> {
+        let connection = self.command_sender()?;
+
+        self.players
+            .get(connection)
+            .map(|player| player.data.position)
+            .ok_or_else(|| "the player isn't online".to_string())
+    }
+
+    fn execute_command(
+        &mut self,
+        line: &str,
+        level: PermissionLevel,
+    ) -> Result<String, CommandError> {
+        let commands = Arc::clone(&self.commands);
+        commands.execute(self, line, level)
+    }
+
+    /// Reloads the config and permission files, keeping the current ones if the new ones are invalid.
+    fn reload_config(&mut self) -> Result<(), String> {
+        let config =
+            ServerConfig::load().map_err(|e| format!("{e}, keeping the current configuration"))?;
+        let permissions =
+            Permissions::load().map_err(|e| format!("{e}, keeping the current permissions"))?;
+
+        let view_distance = self.config.view_distance;
+        self.config.apply_reload(config);
+
+        if self.config.view_distance != view_distance {
+            self.interest.reset_centers();
+        }
+        self.scheduler = Scheduler::new(&self.config.scheduled_tasks(), Instant::now());
+        self.permissions = permissions;
+        self.permissions_changed();
+
+        log::info!("Reloaded {} and {}", ServerConfig::PATH, Permissions::PATH);
+
+        Ok(())
+    }
+}
+
+/// Runs a dedicated server until it's stopped. Its world is opened by `open_world` from the directory
+/// configured in `server.toml`.
+pub fn run<E: fmt::Display>(open_world: impl FnOnce(&Path) -> Result<Box<dyn ChunkStore>, E>) {
+    env_logger::init();
+
+    let config = match ServerConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{e}");
+            return;
+        }
+    };
+
+    let identity = match Keypair::load_or_generate(&config.identity_key_path) {
+        Ok(identity) => identity,
+        Err(e) => {
+            log::error!("Failed to load the server identity: {e}");
+            return;
+        }
+    };
+
+    log::info!("Server identity: {}", fingerprint(&identity.public));
+
+    let permissions = match Permissions::load() {
+        Ok(permissions) => permissions,
+        Err(e) => {
+            log::error!("{e}");
+            return;
+        }
+    };
+
+    let world_info = match WorldInfo::load_or_create(&config.world_path, config.seed) {
+        Ok(world_info) => world_info,
+        Err(e) => {
+            log::error!("Failed to load the world info: {e}");
+            return;
+        }
+    };
+
+    let chunks = match open_world(&config.world_path) {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            log::error!("Failed to open the world: {e}");
+            return;
+        }
+    };
+
+    let admin = config.admin_address.zip(config.admin_password.clone());
+    let mut server = Server::new(config, permissions, identity, world_info, chunks);
+
+    if let Err(e) = server.listen() {
+        log::error!("Failed to listen on {}: {e}", server.config.bind_address);
+        return;
+    }
+
+    let console = spawn_consoles(admin);
+
+    run_loop(&mut server, Some(&console), &AtomicBool::new(false));
+}
+
+/// Key identifying the player of a server running in the same process as the client,
+/// which connects without the encrypted handshake.
+pub const LOCAL_PLAYER_KEY: &[u8] = b"local player";
+
+/// Server running in the same process as the client, used for singleplayer so it behaves like
+/// playing on a dedicated server.
+pub struct LocalServer {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LocalServer {
+    /// Singleplayer worlds are saved every minute, more often than the worlds of dedicated servers.
+    const AUTOSAVE_INTERVAL_SECS: u64 = 60;
+
+    /// Starts the server on its own thread with the default configuration for the world in `world_path`,
+    /// the local player is an admin. Returns the client end of the connection, its player joins once
+    /// the client sends its hello.
+    pub fn spawn(
+        world_path: &Path,
+        chunks: Box<dyn ChunkStore>,
+    ) -> io::Result<(Self, ChannelTransport)> {
+        let config = ServerConfig {
+            world_path: world_path.to_path_buf(),
+            max_players: 1,
+            autosave_interval_secs: Some(Self::AUTOSAVE_INTERVAL_SECS),
+            motd: String::from("Singleplayer"),
+            ..ServerConfig::default()
+        };
+        let permissions = Permissions {
+            default_level: PermissionLevel::Admin,
+            ..Permissions::default()
+        };
+        let identity = Keypair::generate().map_err(io::Error::other)?;
+        let world_info = WorldInfo::load_or_create(&config.world_path, config.seed)?;
+
+        let (client, server_end) = ChannelTransport::pair();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = std::thread::Builder::new()
+            .name("local-server".to_string())
+            .spawn({
+                let stop = Arc::clone(&stop);
+
+                move || {
+                    let mut server = Server::new(config, permissions, identity, world_info, chunks);
+                    server.accept(Box::new(server_end), LOCAL_PLAYER_KEY.to_vec());
+
+                    run_loop(&mut server, None, &stop);
+                }
+            })?;
+
+        Ok((
+            Self {
+                stop,
+                thread: Some(thread),
+            },
+            client,
+        ))
+    }
+
+    /// Stops the server after its current tick and waits until it saved everything.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("The local server panicked");
+            }
+        }
+    }
+}
+
+impl Drop for LocalServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Ticks the server at a fixed rate until it's stopped by a command or the `stop` flag.
+fn run_loop(server: &mut Server, console: Option<&Receiver<ConsoleLine>>, stop: &AtomicBool) {
+    let tick_duration = Duration::from_secs(1) / Server::TICKS_PER_SECOND;
+    let mut next_tick = Instant::now();
+
+    while server.running && !stop.load(Ordering::Relaxed) {
+        if let Some(console) = console {
+            while let Ok(line) = console.try_recv() {
+                server.handle_console_line(line);
+            }
+        }
+
+        let now = Instant::now();
+        server.poll_connections();
+        server.poll_chunks();
+        server.acknowledge_inputs();
+        server.tick(now);
+        server.replicate_entities();
+        server.flush_outbox();
+
+        // Advance by a fixed step to avoid drifting, but don't try to catch up after long stalls.
+        next_tick += tick_duration;
+        if next_tick < now {
+            next_tick = now;
+        }
+
+        if let Some(sleep) = next_tick.checked_duration_since(Instant::now()) {
+            std::thread::sleep(sleep);
+        }
+    }
+
+    server.shutdown();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use landmark_protocol::{
+        handshake,
+        message::{ChunkData, ClientMessage},
+        transport::{send_message, Transport},
+    };
+
+    use super::*;
+
+    /// World of stone below height zero and air above, chunks finish loading on the next poll.
+    #[derive(Debug, Default)]
+    pub struct FlatWorld {
+        requested: Vec<ChunkPos>,
+        loaded: HashSet<ChunkPos>,
+        changed: HashMap<BlockPos, Option<u32>>,
+    }
+
+    impl FlatWorld {
+        pub const STONE: u32 = 1;
+    }
+
+    impl ChunkStore for FlatWorld {
+        fn request(&mut self, pos: ChunkPos, _near: [f64; 3]) {
+            self.requested.push(pos);
+        }
+
+        fn poll(&mut self) -> Vec<ChunkPos> {
+            let finished = std::mem::take(&mut self.requested);
+            self.loaded.extend(finished.iter().copied());
+
+            finished
+        }
+
+        fn chunk_data(&self, pos: ChunkPos) -> Option<ChunkData> {
+            self.loaded.contains(&pos).then(|| ChunkData {
+                coords: [pos.x, pos.y, pos.z],
+                blocks: vec![(pos.y < 0).then_some(Self::STONE); 32 * 32 * 32],
+                biomes: Vec::new(),
+            })
+        }
+
+        fn block(&self, pos: BlockPos) -> Option<Option<u32>> {
+            if !self.loaded.contains(&ChunkPos::of_block(pos)) {
+                return None;
+            }
+
+            Some(
+                self.changed
+                    .get(&pos)
+                    .copied()
+                    .unwrap_or((pos.y < 0).then_some(Self::STONE)),
+            )
+        }
+
+        fn set_block(&mut self, pos: BlockPos, block: Option<u32>) -> bool {
+            let loaded = self.loaded.contains(&ChunkPos::of_block(pos));
+            if loaded {
+                self.changed.insert(pos, block);
+            }
+
+            loaded
+        }
+
+        fn block_name(&self, block: u32) -> Option<&str> {
+            (block == Self::STONE).then_some("stone")
+        }
+
+        fn sees_sky(&self, pos: BlockPos) -> bool {
+            pos.y >= 0
+                && !self.changed.iter().any(|(changed, block)| {
+                    block.is_some()
+                        && (changed.x, changed.z) == (pos.x, pos.z)
+                        && changed.y >= pos.y
+                })
+        }
+
+        fn portal_destination(&self, _eye: [f64; 3]) -> Option<[f64; 3]> {
+            None
+        }
+
+        fn register_ticks(&self, _world_tick: &mut WorldTick) {}
+
+        fn unload(&mut self, pos: ChunkPos) {
+            self.requested.retain(|requested| *requested != pos);
+            self.loaded.remove(&pos);
+        }
+
+        fn save(&mut self) {}
+    }
+
+    /// Creates a server which listens on a free port of the loopback address and stores its world in
+    /// `world_path`. Players see the chunks next to theirs, generated as a `FlatWorld`.
+    pub fn test_server(world_path: &Path) -> Server {
+        let config = ServerConfig {
+            bind_address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            world_path: world_path.to_path_buf(),
+            view_distance: 1,
+            ..ServerConfig::default()
+        };
+
+        Server::new(
+            config,
+            Permissions::default(),
+            Keypair::generate().unwrap(),
+            WorldInfo { seed: 1 },
+            Box::new(FlatWorld::default()),
+        )
+    }
+
+    /// Joins the server over an in-memory channel, the messages sent while joining are skipped.
+    pub fn join(
+        server: &mut Server,
+        public_key: &[u8],
+        name: &str,
+    ) -> (ConnectionId, ChannelTransport) {
+        let (mut client, server_end) = ChannelTransport::pair();
+        let connection = server.accept(Box::new(server_end), public_key.to_vec());
+
+        send_message(
+            &mut client,
+            &ClientMessage::Hello(handshake::hello(name)),
+            false,
+        )
+        .unwrap();
+        server.poll_connections();
+        server.flush_outbox();
+
+        while client.try_recv().unwrap().is_some() {}
+
+        (connection, client)
+    }
+}
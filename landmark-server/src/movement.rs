@@ -0,0 +1,378 @@
+//! Movement of players. Clients simulate their player themselves and report where it went after every
+//! update, the server accepts positions the player could have reached and moves the player back otherwise.
+//! Teleports are decided by the server, which tells the client where the player went.
+
+use landmark_common::command::PermissionLevel;
+use landmark_protocol::{
+    message::{PlayerInput, ServerMessage},
+    replication::ComponentData,
+};
+
+use crate::{
+    interest::{ChunkPos, ConnectionId, CHUNK_SIZE},
+    tick::BlockPos,
+    Server,
+};
+
+/// Farthest a player may move in one update, a bit more than falling at terminal velocity.
+const MAX_INPUT_DISTANCE: f64 = 0.25;
+/// Inputs a single move may make up for, in case the network simulation dropped some.
+const MAX_SKIPPED_INPUTS: u32 = 20;
+/// Players of this level may teleport, moves of others have to be plausible.
+pub const TELEPORT_LEVEL: PermissionLevel = PermissionLevel::Moderator;
+/// Height of the eye above the feet of a player, as clients simulate it.
+const EYE_HEIGHT: f64 = 1.6;
+
+/// Returns the replicated transform of a player with the eye at `position` looking along `yaw` degrees.
+pub fn player_transform(position: [f64; 3], yaw: f32) -> ComponentData {
+    let [x, y, z] = position;
+    let (sin, cos) = (yaw.to_radians() / 2.0).sin_cos();
+
+    ComponentData::Transform {
+        translation: [x as f32, y as f32, z as f32],
+        rotation: [0.0, sin, 0.0, cos],
+    }
+}
+
+impl Server {
+    /// Moves the player to the position reported with an input if they could have got there, inputs
+    /// arriving out of order are ignored. The position is acknowledged by `acknowledge_inputs`.
+    pub fn handle_input(&mut self, connection: ConnectionId, input: PlayerInput) {
+        if !input.position.iter().all(|value| value.is_finite()) || !input.yaw.is_finite() {
+            log::warn!("Connection {connection} sent a position which isn't finite, ignoring it");
+            return;
+        }
+
+        let Some(client) = self.connections.get_mut(&connection) else {
+            return;
+        };
+
+        let skipped = match client.last_input {
+            Some(last) => input.sequence.wrapping_sub(last) as i32,
+            None => 1,
+        };
+
+        if skipped <= 0 {
+            return;
+        }
+
+        // inputs the client sent before it applied the teleport
+        if let Some(target) = client.teleport {
+            if distance(target, input.position) > MAX_INPUT_DISTANCE {
+                return;
+            }
+
+            client.teleport = None;
+        }
+
+        client.last_input = Some(input.sequence);
+        client.input_acknowledged = false;
+
+        let public_key = client.public_key.clone();
+        let Some(player) = self.players.get_mut(connection) else {
+            return;
+        };
+
+        let distance = distance(player.data.position, input.position);
+        let allowed = MAX_INPUT_DISTANCE * skipped.min(MAX_SKIPPED_INPUTS as i32) as f64;
+
+        if distance > allowed && self.permissions.level_of(&public_key) < TELEPORT_LEVEL {
+            log::warn!(
+                "{} moved {distance:.2} blocks at once, moving them back",
+                player.data.name
+            );
+            return;
+        }
+
+        let previous = std::mem::replace(&mut player.data.position, input.position);
+
+        if let Some(entity) = self.player_entities.get(&connection) {
+            self.entities
+                .set(*entity, player_transform(input.position, input.yaw));
+        }
+
+        self.player_moved(connection, input.position);
+
+        // portals only trigger when stepped onto, so players arriving on one aren't sent back
+        if self.chunks.portal_destination(previous).is_none() {
+            if let Some(destination) = self.chunks.portal_destination(input.position) {
+                self.teleport(connection, destination);
+            }
+        }
+    }
+
+    /// Moves the player's eye to `position` and tells the client, which waits for the chunks there
+    /// before it follows.
+    pub fn teleport(&mut self, connection: ConnectionId, position: [f64; 3]) {
+        let Some(player) = self.players.get_mut(connection) else {
+            return;
+        };
+
+        player.data.position = position;
+
+        if let Some(client) = self.connections.get_mut(&connection) {
+            client.teleport = Some(position);
+            // the position after the inputs of this tick is outdated
+            client.input_acknowledged = true;
+        }
+
+        if let Some(entity) = self.player_entities.get(&connection) {
+            self.entities.set(*entity, player_transform(position, 0.0));
+        }
+
+        self.player_moved(connection, position);
+        self.send(connection, ServerMessage::Teleport(position));
+    }
+
+    /// Returns the eye position of a player standing on the highest block of the column at
+    /// `position`, None if the chunks of the column aren't loaded or hold no block.
+    pub fn surface_above(&self, position: [f64; 3]) -> Option<[f64; 3]> {
+        let [x, _, z] = position;
+        let column = ChunkPos::containing(position);
+        let top = self
+            .loaded_chunks
+            .iter()
+            .filter(|chunk| (chunk.x, chunk.z) == (column.x, column.z))
+            .map(|chunk| chunk.y)
+            .max()?;
+
+        let (block_x, block_z) = (x.floor() as i32, z.floor() as i32);
+
+        // down from the top of the highest loaded chunk, until a chunk below isn't loaded
+        let mut y = (top + 1) * CHUNK_SIZE - 1;
+        while let Some(block) = self.chunks.block(BlockPos::new(block_x, y, block_z)) {
+            if block.is_some() {
+                return Some([x, (y + 1) as f64 + EYE_HEIGHT, z]);
+            }
+
+            y -= 1;
+        }
+
+        None
+    }
+
+    /// Tells every client which sent inputs during the tick where its player is after the last of them.
+    pub fn acknowledge_inputs(&mut self) {
+        let mut states = Vec::new();
+
+        for (connection, client) in self.connections.iter_mut() {
+            let (Some(last_input), false) = (client.last_input, client.input_acknowledged) else {
+                continue;
+            };
+            let Some(player) = self.players.get(*connection) else {
+                continue;
+            };
+
+            client.input_acknowledged = true;
+            states.push((
+                *connection,
+                ServerMessage::PlayerState {
+                    last_input,
+                    position: player.data.position,
+                },
+            ));
+        }
+
+        for (connection, message) in states {
+            self.send(connection, message);
+        }
+    }
+}
+
+pub(crate) fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f64>()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use landmark_common::secure::fingerprint;
+    use landmark_protocol::transport::{try_recv_message, ChannelTransport};
+
+    use super::*;
+    use crate::tests::{join, test_server};
+
+    fn input(sequence: u32, position: [f64; 3]) -> PlayerInput {
+        PlayerInput {
+            sequence,
+            position,
+            yaw: 0.0,
+        }
+    }
+
+    /// Returns the last position acknowledged to the client.
+    fn acknowledged(server: &mut Server, client: &mut ChannelTransport) -> Option<(u32, [f64; 3])> {
+        server.acknowledge_inputs();
+        server.flush_outbox();
+
+        let mut state = None;
+        while let Some(message) = try_recv_message::<ServerMessage>(client).unwrap() {
+            if let ServerMessage::PlayerState {
+                last_input,
+                position,
+            } = message
+            {
+                state = Some((last_input, position));
+            }
+        }
+
+        state
+    }
+
+    #[test]
+    fn plausible_moves_are_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (connection, mut client) = join(&mut server, b"player", "Player");
+
+        server.handle_input(connection, input(0, [0.0, 79.9, 0.0]));
+        server.handle_input(connection, input(1, [0.1, 79.8, 0.0]));
+        assert_eq!(
+            acknowledged(&mut server, &mut client),
+            Some((1, [0.1, 79.8, 0.0]))
+        );
+
+        // nothing new to acknowledge
+        assert_eq!(acknowledged(&mut server, &mut client), None);
+
+        // an input arriving late doesn't move the player back
+        server.handle_input(connection, input(0, [0.0, 79.9, 0.0]));
+        assert_eq!(acknowledged(&mut server, &mut client), None);
+        assert_eq!(
+            server.players.get(connection).unwrap().data.position,
+            [0.1, 79.8, 0.0]
+        );
+    }
+
+    #[test]
+    fn only_moderators_teleport() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (player, mut player_client) = join(&mut server, b"player", "Player");
+        let (moderator, mut moderator_client) = join(&mut server, b"moderator", "Moderator");
+        server
+            .permissions
+            .set(&fingerprint(b"moderator"), PermissionLevel::Moderator);
+
+        server.handle_input(player, input(0, [100.0, 80.0, 0.0]));
+        server.handle_input(moderator, input(0, [100.0, 80.0, 0.0]));
+
+        assert_eq!(
+            acknowledged(&mut server, &mut player_client),
+            Some((0, [0.0, 80.0, 0.0]))
+        );
+        assert_eq!(
+            acknowledged(&mut server, &mut moderator_client),
+            Some((0, [100.0, 80.0, 0.0]))
+        );
+    }
+
+    #[test]
+    fn positions_which_arent_finite_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (connection, mut client) = join(&mut server, b"player", "Player");
+
+        server.handle_input(connection, input(0, [f64::NAN, 80.0, 0.0]));
+        server.handle_input(connection, input(1, [0.0, f64::INFINITY, 0.0]));
+
+        assert_eq!(acknowledged(&mut server, &mut client), None);
+        assert_eq!(
+            server.players.get(connection).unwrap().data.position,
+            [0.0, 80.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn inputs_are_ignored_until_the_player_arrived_at_the_teleport() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (connection, mut client) = join(&mut server, b"player", "Player");
+
+        server.teleport(connection, [100.0, 80.0, 0.0]);
+        server.flush_outbox();
+
+        let mut messages = Vec::new();
+        while let Some(message) = try_recv_message::<ServerMessage>(&mut client).unwrap() {
+            messages.push(message);
+        }
+        assert!(messages.contains(&ServerMessage::Teleport([100.0, 80.0, 0.0])));
+
+        // sent before the client received the teleport
+        server.handle_input(connection, input(0, [0.1, 80.0, 0.0]));
+        assert_eq!(acknowledged(&mut server, &mut client), None);
+
+        server.handle_input(connection, input(1, [100.0, 80.0, 0.0]));
+        server.handle_input(connection, input(2, [100.1, 80.0, 0.0]));
+        assert_eq!(
+            acknowledged(&mut server, &mut client),
+            Some((2, [100.1, 80.0, 0.0]))
+        );
+    }
+
+    #[test]
+    fn top_moves_players_onto_the_highest_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (connection, mut client) = join(&mut server, b"player", "Player");
+
+        // the stone below height zero is in view from here
+        server.teleport(connection, [0.5, 10.0, 0.5]);
+        server.poll_chunks();
+        server.handle_player_command(connection, "top");
+        server.flush_outbox();
+
+        let mut teleports = Vec::new();
+        while let Some(message) = try_recv_message::<ServerMessage>(&mut client).unwrap() {
+            if let ServerMessage::Teleport(position) = message {
+                teleports.push(position);
+            }
+        }
+
+        assert_eq!(teleports, [[0.5, 10.0, 0.5], [0.5, EYE_HEIGHT, 0.5]]);
+    }
+
+    #[test]
+    fn spawn_and_home_points_are_kept_by_the_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let (connection, _client) = join(&mut server, b"player", "Player");
+        server
+            .permissions
+            .set(&fingerprint(b"player"), PermissionLevel::Moderator);
+
+        server.teleport(connection, [10.0, 80.0, 0.0]);
+        server.handle_player_command(connection, "setspawn");
+        server.teleport(connection, [20.0, 80.0, 0.0]);
+        server.handle_player_command(connection, "sethome");
+
+        server.handle_player_command(connection, "spawn");
+        assert_eq!(
+            server.players.get(connection).unwrap().data.position,
+            [10.0, 80.0, 0.0]
+        );
+        server.handle_player_command(connection, "home");
+        assert_eq!(
+            server.players.get(connection).unwrap().data.position,
+            [20.0, 80.0, 0.0]
+        );
+
+        // both outlive the server, new players join at the spawn point
+        server.players.save_all();
+        let mut server = test_server(dir.path());
+        let (connection, _client) = join(&mut server, b"player", "Player");
+        let (newcomer, _newcomer_client) = join(&mut server, b"newcomer", "Newcomer");
+
+        assert_eq!(
+            server.players.get(connection).unwrap().data.home,
+            Some([20.0, 80.0, 0.0])
+        );
+        assert_eq!(
+            server.players.get(newcomer).unwrap().data.position,
+            [10.0, 80.0, 0.0]
+        );
+    }
+}
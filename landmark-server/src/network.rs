@@ -0,0 +1,532 @@
+use std::{
+    fmt, io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Sender},
+    time::Duration,
+};
+
+use landmark_common::{
+    netsim::{NetworkSimulator, SimulatedStream},
+    secure::{fingerprint, Keypair, SecureError, SecureStream},
+};
+use landmark_protocol::{
+    handshake,
+    message::{ClientMessage, Hello, ServerMessage},
+    replication::ComponentData,
+    transport::{send_message, try_recv_message, TcpTransport, Transport},
+    ProtocolError,
+};
+
+use crate::{
+    interest::{ChunkPos, ConnectionId},
+    movement::player_transform,
+    players::MAX_INVENTORY_SLOTS,
+    tick::BlockPos,
+    Server,
+};
+
+/// Messages handled from a single client per tick, so one client can't stall the server.
+const MAX_MESSAGES_PER_TICK: usize = 256;
+/// How long a connecting client may take to complete the encrypted handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A client connected over any transport, remote or in the same process.
+pub struct Connection {
+    transport: Box<dyn Transport>,
+    /// Key the client authenticated with, the identity of its player.
+    pub public_key: Vec<u8>,
+    /// Whether chunk payloads are compressed, agreed on in the handshake.
+    compression: bool,
+    /// Sequence number of the last input of the client which was handled.
+    pub last_input: Option<u32>,
+    /// Cleared when an input was handled, until the position after it was sent to the client.
+    pub input_acknowledged: bool,
+    /// Position the player was teleported to, until an input reports they arrived there.
+    pub teleport: Option<[f64; 3]>,
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("player", &fingerprint(&self.public_key))
+            .field("compression", &self.compression)
+            .field("last_input", &self.last_input)
+            .finish()
+    }
+}
+
+impl Server {
+    /// Starts listening for players on the configured address. Handshakes run on their own threads,
+    /// so slow clients don't stall the tick loop, finished ones are accepted by `poll_connections`.
+    /// Returns the address the server listens on.
+    pub fn listen(&mut self) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(self.config.bind_address)?;
+        let address = listener.local_addr()?;
+        let (sender, receiver) = mpsc::channel();
+
+        log::info!("Listening for players on {address}");
+
+        let identity = self.identity.clone();
+        let simulator = self.network_simulator.clone();
+
+        std::thread::Builder::new()
+            .name("listener".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            log::warn!("Failed to accept a connection: {e}");
+                            continue;
+                        }
+                    };
+
+                    let identity = identity.clone();
+                    let simulator = simulator.clone();
+                    let sender = sender.clone();
+
+                    std::thread::spawn(move || {
+                        let peer = stream.peer_addr().ok();
+
+                        if let Err(e) = secure_handshake(stream, &identity, simulator, &sender) {
+                            log::warn!("Handshake with {peer:?} failed: {e}");
+                        }
+                    });
+                }
+            })?;
+
+        self.incoming = Some(receiver);
+
+        Ok(address)
+    }
+
+    /// Accepts a client which authenticated with the given key, it joins once it sends its hello.
+    pub fn accept(&mut self, transport: Box<dyn Transport>, public_key: Vec<u8>) -> ConnectionId {
+        let connection = self.next_connection;
+        self.next_connection += 1;
+
+        self.connections.insert(
+            connection,
+            Connection {
+                transport,
+                public_key,
+                compression: false,
+                last_input: None,
+                input_acknowledged: true,
+                teleport: None,
+            },
+        );
+
+        connection
+    }
+
+    /// Handles the messages clients sent since the last tick.
+    pub fn poll_connections(&mut self) {
+        let incoming: Vec<_> = self
+            .incoming
+            .as_ref()
+            .map(|incoming| incoming.try_iter().collect())
+            .unwrap_or_default();
+
+        for (transport, public_key) in incoming {
+            let connection = self.accept(Box::new(transport), public_key);
+            log::info!("Accepted connection {connection}");
+        }
+
+        let ids: Vec<ConnectionId> = self.connections.keys().copied().collect();
+
+        for connection in ids {
+            for _ in 0..MAX_MESSAGES_PER_TICK {
+                let Some(client) = self.connections.get_mut(&connection) else {
+                    break;
+                };
+
+                match try_recv_message::<ClientMessage>(client.transport.as_mut()) {
+                    Ok(Some(message)) => self.handle_message(connection, message),
+                    Ok(None) => break,
+                    Err(e) => {
+                        if !matches!(e, ProtocolError::Closed) {
+                            log::warn!("Dropping connection {connection}: {e}");
+                        }
+
+                        self.close(connection);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_message(&mut self, connection: ConnectionId, message: ClientMessage) {
+        if let ClientMessage::Hello(hello) = &message {
+            self.handle_hello(connection, hello);
+            return;
+        }
+
+        let Some(player) = self.players.get(connection) else {
+            self.send(connection, self.reject("Expected a hello".to_string()));
+            return;
+        };
+        let name = player.data.name.clone();
+
+        match message {
+            ClientMessage::Hello(_) => {}
+            ClientMessage::Command(line) => {
+                let text = self.handle_player_command(connection, &line);
+
+                self.send(connection, ServerMessage::Chat { sender: None, text });
+            }
+            ClientMessage::Chat(text) => {
+                log::info!("<{name}> {text}");
+
+                for other in self.players.connections() {
+                    self.send(
+                        other,
+                        ServerMessage::Chat {
+                            sender: Some(name.clone()),
+                            text: text.clone(),
+                        },
+                    );
+                }
+            }
+            ClientMessage::Input(input) => self.handle_input(connection, input),
+            ClientMessage::Inventory(slots) => {
+                if slots.len() > MAX_INVENTORY_SLOTS {
+                    log::warn!("{name} sent {} inventory slots, ignoring them", slots.len());
+                } else if let Some(player) = self.players.get_mut(connection) {
+                    player.data.inventory = slots;
+                }
+            }
+            ClientMessage::RequestChunk([x, y, z]) => {
+                self.resend_chunk(connection, ChunkPos::new(x, y, z))
+            }
+            ClientMessage::SetBlock {
+                position: [x, y, z],
+                block,
+            } => self.player_set_block(connection, BlockPos::new(x, y, z), block),
+            ClientMessage::Respawn => self.teleport(connection, self.spawn_point.position()),
+            ClientMessage::GameMode { creative } => self.player_game_mode(connection, creative),
+            ClientMessage::Disconnect => self.close(connection),
+        }
+    }
+
+    /// Answers the hello a client opens its connection with. Clients of incompatible versions and
+    /// players who can't join are rejected, others are welcomed and receive their saved data.
+    fn handle_hello(&mut self, connection: ConnectionId, hello: &Hello) {
+        let welcome = match handshake::negotiate(hello, &self.config.motd) {
+            Ok(welcome) => welcome,
+            Err(e) => return self.send(connection, self.reject(e.to_string())),
+        };
+
+        let public_key = self.connections[&connection].public_key.clone();
+
+        if self.config.whitelist && !self.permissions.is_whitelisted(&public_key) {
+            let reason = format!(
+                "You are not whitelisted on this server, your key fingerprint is {}",
+                fingerprint(&public_key)
+            );
+            return self.send(connection, self.reject(reason));
+        }
+
+        let player = match self.players.join(
+            connection,
+            &public_key,
+            &hello.name,
+            self.config.max_players,
+            self.spawn_point.position(),
+        ) {
+            Ok(player) => player.clone(),
+            Err(e) => return self.send(connection, self.reject(e.to_string())),
+        };
+
+        self.interest.connect(connection);
+
+        if let Some(client) = self.connections.get_mut(&connection) {
+            client.compression = welcome.compression;
+        }
+
+        self.send(connection, ServerMessage::Welcome(welcome));
+        self.send(connection, ServerMessage::Joined(player.clone()));
+        self.send_permissions(connection);
+        self.send(connection, ServerMessage::Weather(self.weather.current()));
+        self.player_moved(connection, player.position);
+
+        for (_, message) in self.entities.snapshot() {
+            self.send(connection, message);
+        }
+
+        let entity = self.entities.spawn();
+        self.entities
+            .set(entity, player_transform(player.position, 0.0));
+        self.entities
+            .set(entity, ComponentData::ModelId("player".to_string()));
+        self.entities
+            .set(entity, ComponentData::Name(player.name.clone()));
+        self.player_entities.insert(connection, entity);
+    }
+
+    /// Sends the changes of replicated entities to every player, except for changes of their own entity.
+    pub fn replicate_entities(&mut self) {
+        let changes = self.entities.changes();
+
+        for connection in self.players.connections() {
+            let own_entity = self.player_entities.get(&connection).copied();
+
+            for (entity, message) in &changes {
+                if Some(*entity) != own_entity {
+                    self.send(connection, message.clone());
+                }
+            }
+        }
+    }
+
+    fn reject(&self, reason: String) -> ServerMessage {
+        log::info!("Rejected a client: {reason}");
+        ServerMessage::Rejected { reason }
+    }
+
+    pub fn send(&mut self, connection: ConnectionId, message: ServerMessage) {
+        self.outbox.push((connection, message));
+    }
+
+    /// Sends the messages queued for clients. Clients which were rejected or told to disconnect are
+    /// disconnected after their last message.
+    pub fn flush_outbox(&mut self) {
+        for (connection, message) in std::mem::take(&mut self.outbox) {
+            let Some(client) = self.connections.get_mut(&connection) else {
+                continue;
+            };
+
+            let closing = matches!(
+                message,
+                ServerMessage::Rejected { .. } | ServerMessage::Disconnect { .. }
+            );
+
+            if let Err(e) = send_message(client.transport.as_mut(), &message, client.compression) {
+                if !matches!(e, ProtocolError::Closed) {
+                    log::warn!("Dropping connection {connection}: {e}");
+                }
+
+                self.close(connection);
+                continue;
+            }
+
+            if closing {
+                self.close(connection);
+            }
+        }
+    }
+
+    /// Drops the connection and saves the data of its player.
+    fn close(&mut self, connection: ConnectionId) {
+        self.connections.remove(&connection);
+        self.players.leave(connection);
+
+        for chunk in self.interest.disconnect(connection) {
+            self.release_chunk(chunk);
+        }
+
+        if let Some(entity) = self.player_entities.remove(&connection) {
+            self.entities.despawn(entity);
+        }
+    }
+}
+
+/// Authenticates a client which connected to the listener and passes its connection to the tick loop.
+fn secure_handshake(
+    stream: TcpStream,
+    identity: &Keypair,
+    simulator: NetworkSimulator,
+    sender: &Sender<(TcpTransport, Vec<u8>)>,
+) -> Result<(), SecureError> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+    let stream = SecureStream::accept(stream, identity)?;
+    stream.get_ref().set_read_timeout(None)?;
+
+    let public_key = stream.remote_public_key()?.to_vec();
+    let transport = TcpTransport::new(SimulatedStream::new(stream, simulator));
+
+    // the server is stopping
+    let _ = sender.send((transport, public_key));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use landmark_protocol::{
+        message::ItemStack,
+        transport::{recv_message, ChannelTransport},
+    };
+
+    use super::*;
+    use crate::tests::{join, test_server};
+
+    /// Runs the connection handling of the server until the client thread finishes.
+    fn serve_until<T>(server: &mut Server, client: std::thread::JoinHandle<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while !client.is_finished() {
+            assert!(Instant::now() < deadline, "The client didn't finish");
+
+            server.poll_connections();
+            server.flush_outbox();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        client.join().unwrap()
+    }
+
+    #[test]
+    fn players_join_over_tcp() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let address = server.listen().unwrap();
+        let server_key = server.identity.public.clone();
+
+        let client = std::thread::spawn(move || {
+            let identity = Keypair::generate().unwrap();
+            let stream =
+                SecureStream::connect(TcpStream::connect(address).unwrap(), &identity).unwrap();
+            assert_eq!(stream.remote_public_key().unwrap(), server_key);
+
+            let mut transport =
+                TcpTransport::new(SimulatedStream::new(stream, NetworkSimulator::default()));
+            send_message(
+                &mut transport,
+                &ClientMessage::Hello(handshake::hello("Alice")),
+                false,
+            )
+            .unwrap();
+
+            handshake::accept_response(recv_message(&mut transport).unwrap()).unwrap();
+            let player = handshake::accept_join(recv_message(&mut transport).unwrap()).unwrap();
+
+            // returned so the connection stays open
+            (transport, identity.public, player.name)
+        });
+
+        let (_transport, public_key, name) = serve_until(&mut server, client);
+
+        assert_eq!(name, "Alice");
+        assert_eq!(server.players.connection_of("Alice"), Some(0));
+        assert_eq!(server.connections[&0].public_key, public_key);
+    }
+
+    #[test]
+    fn second_player_with_the_same_name_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+
+        let (mut first, server_end) = ChannelTransport::pair();
+        server.accept(Box::new(server_end), b"first".to_vec());
+        let (mut second, server_end) = ChannelTransport::pair();
+        server.accept(Box::new(server_end), b"second".to_vec());
+
+        // one after the other, the order connections are polled in isn't defined
+        for client in [&mut first, &mut second] {
+            send_message(
+                client,
+                &ClientMessage::Hello(handshake::hello("Alice")),
+                false,
+            )
+            .unwrap();
+
+            server.poll_connections();
+            server.flush_outbox();
+        }
+
+        assert!(matches!(
+            recv_message(&mut first).unwrap(),
+            ServerMessage::Welcome(_)
+        ));
+        assert!(matches!(
+            recv_message(&mut second).unwrap(),
+            ServerMessage::Rejected { reason } if reason.contains("Alice is already online")
+        ));
+
+        // the rejected client is disconnected, the first player stays
+        assert!(matches!(
+            recv_message::<ServerMessage>(&mut second),
+            Err(ProtocolError::Closed)
+        ));
+        assert_eq!(server.players.connections(), vec![0]);
+    }
+
+    #[test]
+    fn players_not_on_the_whitelist_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        server.config.whitelist = true;
+        server.permissions.whitelist.insert(fingerprint(b"alice"));
+
+        let mut clients = Vec::new();
+
+        for (public_key, name) in [(b"alice", "Alice"), (b"bobby", "Bob")] {
+            let (mut client, server_end) = ChannelTransport::pair();
+            server.accept(Box::new(server_end), public_key.to_vec());
+            send_message(
+                &mut client,
+                &ClientMessage::Hello(handshake::hello(name)),
+                false,
+            )
+            .unwrap();
+            clients.push(client);
+        }
+
+        server.poll_connections();
+        server.flush_outbox();
+
+        assert!(matches!(
+            recv_message(&mut clients[0]).unwrap(),
+            ServerMessage::Welcome(_)
+        ));
+        assert!(matches!(
+            recv_message(&mut clients[1]).unwrap(),
+            ServerMessage::Rejected { reason } if reason.contains("not whitelisted")
+        ));
+        assert_eq!(server.players.connections(), vec![0]);
+    }
+
+    #[test]
+    fn inventory_is_restored_when_rejoining() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = test_server(dir.path());
+        let inventory = vec![
+            Some(ItemStack {
+                item: "stone".to_string(),
+                count: 12,
+            }),
+            None,
+        ];
+
+        let (_, mut client) = join(&mut server, b"player", "Player");
+        send_message(
+            &mut client,
+            &ClientMessage::Inventory(inventory.clone()),
+            false,
+        )
+        .unwrap();
+        send_message(&mut client, &ClientMessage::Disconnect, false).unwrap();
+        server.poll_connections();
+
+        let (mut client, server_end) = ChannelTransport::pair();
+        server.accept(Box::new(server_end), b"player".to_vec());
+        send_message(
+            &mut client,
+            &ClientMessage::Hello(handshake::hello("Player")),
+            false,
+        )
+        .unwrap();
+        server.poll_connections();
+        server.flush_outbox();
+
+        handshake::accept_response(recv_message(&mut client).unwrap()).unwrap();
+        let player = handshake::accept_join(recv_message(&mut client).unwrap()).unwrap();
+        assert_eq!(player.inventory, inventory);
+    }
+}
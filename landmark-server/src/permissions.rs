@@ -0,0 +1,97 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+};
+
+use landmark_common::{command::PermissionLevel, secure::fingerprint};
+
+/// Permission levels of players loaded from `permissions.toml` in the working directory.
+/// Players are identified by the fingerprint of the key they present during the handshake.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Level of players without an entry.
+    pub default_level: PermissionLevel,
+    /// Levels by key fingerprint.
+    pub players: BTreeMap<String, PermissionLevel>,
+    /// Fingerprints of the players allowed to join while the whitelist is enabled.
+    pub whitelist: BTreeSet<String>,
+}
+
+impl Permissions {
+    pub const PATH: &'static str = "permissions.toml";
+
+    /// Loads permissions, every player is a guest if the file doesn't exist.
+    pub fn load() -> Result<Self, PermissionsError> {
+        let content = match fs::read_to_string(Self::PATH) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                log::info!("Permissions file {} not found, using defaults", Self::PATH);
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(PermissionsError::Io(e)),
+        };
+
+        toml::from_str(content.as_str()).map_err(PermissionsError::Parse)
+    }
+
+    pub fn save(&self) -> Result<(), PermissionsError> {
+        let content = toml::to_string_pretty(self).map_err(PermissionsError::Serialize)?;
+        fs::write(Self::PATH, content).map_err(PermissionsError::Io)
+    }
+
+    pub fn level_of(&self, public_key: &[u8]) -> PermissionLevel {
+        self.level_of_fingerprint(&fingerprint(public_key))
+    }
+
+    pub fn level_of_fingerprint(&self, fingerprint: &str) -> PermissionLevel {
+        self.players
+            .get(fingerprint)
+            .copied()
+            .unwrap_or(self.default_level)
+    }
+
+    pub fn is_whitelisted(&self, public_key: &[u8]) -> bool {
+        self.whitelist.contains(&fingerprint(public_key))
+    }
+
+    /// Sets the level of a player, an entry equal to the default level is removed.
+    pub fn set(&mut self, fingerprint: &str, level: PermissionLevel) {
+        if level == self.default_level {
+            self.players.remove(fingerprint);
+        } else {
+            self.players.insert(fingerprint.to_string(), level);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PermissionsError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for PermissionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PermissionsError::Io(e) => write!(f, "Failed to access {}: {e}", Permissions::PATH),
+            PermissionsError::Parse(e) => {
+                write!(f, "Failed to parse {}: {e}", Permissions::PATH)
+            }
+            PermissionsError::Serialize(e) => {
+                write!(f, "Failed to write {}: {e}", Permissions::PATH)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PermissionsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PermissionsError::Io(e) => Some(e),
+            PermissionsError::Parse(e) => Some(e),
+            PermissionsError::Serialize(e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use landmark_common::secure::fingerprint;
+use landmark_protocol::message::PlayerData;
+
+use crate::interest::ConnectionId;
+
+const MAX_NAME_LEN: usize = 16;
+/// Most inventory slots kept for a player, more than the inventory of the client has.
+pub const MAX_INVENTORY_SLOTS: usize = 64;
+
+#[derive(Debug)]
+pub enum JoinError {
+    InvalidName(String),
+    NameTaken(String),
+    AlreadyOnline,
+    ServerFull,
+    Io(io::Error),
+    CorruptedData(PathBuf, ron::error::SpannedError),
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::InvalidName(name) => write!(
+                f,
+                "Name {name:?} must have 1 to {MAX_NAME_LEN} letters, digits or underscores"
+            ),
+            JoinError::NameTaken(name) => write!(f, "A player named {name} is already online"),
+            JoinError::AlreadyOnline => write!(f, "This identity is already online"),
+            JoinError::ServerFull => write!(f, "The server is full"),
+            JoinError::Io(e) => write!(f, "Failed to load the player data: {e}"),
+            JoinError::CorruptedData(path, e) => {
+                write!(f, "Player data in {} is corrupted: {e}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+impl From<io::Error> for JoinError {
+    fn from(value: io::Error) -> Self {
+        JoinError::Io(value)
+    }
+}
+
+#[derive(Debug)]
+pub struct OnlinePlayer {
+    /// Key the player authenticated with during the encrypted handshake, their identity.
+    pub public_key: Vec<u8>,
+    pub data: PlayerData,
+    /// Whether the client reported playing creative, players start in survival until it does.
+    pub creative: bool,
+}
+
+/// Players currently online, with their data saved in `players/` of the world directory between sessions.
+/// Data is stored by the fingerprint of the player's key, so players may change their names.
+#[derive(Debug)]
+pub struct PlayerRegistry {
+    dir: PathBuf,
+    online: HashMap<ConnectionId, OnlinePlayer>,
+}
+
+impl PlayerRegistry {
+    pub fn new(world_path: &Path) -> Self {
+        Self {
+            dir: world_path.join("players"),
+            online: HashMap::new(),
+        }
+    }
+
+    pub fn online(&self) -> impl Iterator<Item = &OnlinePlayer> {
+        self.online.values()
+    }
+
+    /// Returns the connection of the online player with the name, ignoring case.
+    pub fn connection_of(&self, name: &str) -> Option<ConnectionId> {
+        self.online
+            .iter()
+            .find(|(_, player)| player.data.name.eq_ignore_ascii_case(name))
+            .map(|(connection, _)| *connection)
+    }
+
+    pub fn connections(&self) -> Vec<ConnectionId> {
+        self.online.keys().copied().collect()
+    }
+
+    pub fn get(&self, connection: ConnectionId) -> Option<&OnlinePlayer> {
+        self.online.get(&connection)
+    }
+
+    pub fn get_mut(&mut self, connection: ConnectionId) -> Option<&mut OnlinePlayer> {
+        self.online.get_mut(&connection)
+    }
+
+    /// Registers a player under a name no other online player has, returning their saved data.
+    /// Players joining for the first time start at `spawn_point`.
+    pub fn join(
+        &mut self,
+        connection: ConnectionId,
+        public_key: &[u8],
+        name: &str,
+        max_players: u32,
+        spawn_point: [f64; 3],
+    ) -> Result<&PlayerData, JoinError> {
+        if name.is_empty()
+            || name.len() > MAX_NAME_LEN
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(JoinError::InvalidName(name.to_string()));
+        }
+
+        if self
+            .online
+            .values()
+            .any(|player| player.public_key == public_key)
+        {
+            return Err(JoinError::AlreadyOnline);
+        }
+
+        if self
+            .online
+            .values()
+            .any(|player| player.data.name.eq_ignore_ascii_case(name))
+        {
+            return Err(JoinError::NameTaken(name.to_string()));
+        }
+
+        if self.online.len() >= max_players as usize {
+            return Err(JoinError::ServerFull);
+        }
+
+        let mut data = self.load(public_key)?.unwrap_or_else(|| PlayerData {
+            name: name.to_string(),
+            position: spawn_point,
+            inventory: Vec::new(),
+            home: None,
+        });
+
+        if data.name != name {
+            log::info!("{} is now called {name}", data.name);
+            data.name = name.to_string();
+        }
+
+        log::info!("{name} ({}) joined", fingerprint(public_key));
+
+        let player = OnlinePlayer {
+            public_key: public_key.to_vec(),
+            data,
+            creative: false,
+        };
+
+        Ok(&self.online.entry(connection).or_insert(player).data)
+    }
+
+    /// Saves the data of a player and removes them from the online players.
+    pub fn leave(&mut self, connection: ConnectionId) {
+        let Some(player) = self.online.remove(&connection) else {
+            return;
+        };
+
+        log::info!("{} left", player.data.name);
+
+        if let Err(e) = self.save(&player) {
+            log::error!("Failed to save the data of {}: {e}", player.data.name);
+        }
+    }
+
+    /// Saves the data of every online player.
+    pub fn save_all(&self) {
+        for player in self.online.values() {
+            if let Err(e) = self.save(player) {
+                log::error!("Failed to save the data of {}: {e}", player.data.name);
+            }
+        }
+    }
+
+    fn path_of(&self, public_key: &[u8]) -> PathBuf {
+        // colons aren't allowed in file names everywhere
+        self.dir
+            .join(format!("{}.ron", fingerprint(public_key).replace(':', "")))
+    }
+
+    fn load(&self, public_key: &[u8]) -> Result<Option<PlayerData>, JoinError> {
+        let path = self.path_of(public_key);
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(JoinError::Io(e)),
+        };
+
+        ron::from_str(&content)
+            .map(Some)
+            .map_err(|e| JoinError::CorruptedData(path, e))
+    }
+
+    fn save(&self, player: &OnlinePlayer) -> io::Result<()> {
+        let content = ron::ser::to_string_pretty(&player.data, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path_of(&player.public_key), content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPAWN: [f64; 3] = [1.0, 2.0, 3.0];
+
+    #[test]
+    fn names_and_identities_are_unique() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut players = PlayerRegistry::new(dir.path());
+
+        assert!(matches!(
+            players.join(0, b"alice", "no spaces", 4, SPAWN),
+            Err(JoinError::InvalidName(_))
+        ));
+
+        players.join(0, b"alice", "Alice", 4, SPAWN).unwrap();
+
+        assert!(matches!(
+            players.join(1, b"bob", "alice", 4, SPAWN),
+            Err(JoinError::NameTaken(name)) if name == "alice"
+        ));
+        assert!(matches!(
+            players.join(1, b"alice", "Alice2", 4, SPAWN),
+            Err(JoinError::AlreadyOnline)
+        ));
+        assert!(matches!(
+            players.join(1, b"bob", "Bob", 1, SPAWN),
+            Err(JoinError::ServerFull)
+        ));
+
+        players.join(1, b"bob", "Bob", 4, SPAWN).unwrap();
+        assert_eq!(players.connection_of("BOB"), Some(1));
+    }
+
+    #[test]
+    fn data_is_kept_by_identity_across_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut players = PlayerRegistry::new(dir.path());
+
+        players.join(0, b"alice", "Alice", 4, SPAWN).unwrap();
+        players.leave(0);
+
+        // the name is free again once its player left
+        let data = players.join(1, b"alice", "Alicia", 4, SPAWN).unwrap();
+        assert_eq!(data.name, "Alicia");
+        assert_eq!(data.position, SPAWN);
+
+        players.join(2, b"bob", "Alice", 4, SPAWN).unwrap();
+    }
+}
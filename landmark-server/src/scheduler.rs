@@ -0,0 +1,88 @@
+use std::time::{Duration, Instant};
+
+/// A recurring job as described in the server config file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TaskConfig {
+    pub name: String,
+    /// Time between consecutive runs.
+    pub interval_secs: u64,
+    /// Time before the first run, defaults to the interval.
+    pub delay_secs: Option<u64>,
+    #[serde(flatten)]
+    pub action: TaskAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TaskAction {
+    Autosave,
+    Backup,
+    Announce { message: String },
+    DayReset,
+}
+
+#[derive(Debug)]
+struct ScheduledTask {
+    name: String,
+    interval: Duration,
+    next_run: Instant,
+    action: TaskAction,
+}
+
+/// Runs recurring tasks on the tick thread.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    tasks: Vec<ScheduledTask>,
+}
+
+impl Scheduler {
+    pub fn new(tasks: &[TaskConfig], now: Instant) -> Self {
+        let mut scheduler = Self::default();
+
+        for task in tasks {
+            if task.interval_secs == 0 {
+                log::error!("Task {} has zero interval, skipping it", task.name);
+                continue;
+            }
+
+            let interval = Duration::from_secs(task.interval_secs);
+            let delay = task.delay_secs.map(Duration::from_secs).unwrap_or(interval);
+
+            scheduler.tasks.push(ScheduledTask {
+                name: task.name.clone(),
+                interval,
+                next_run: now + delay,
+                action: task.action.clone(),
+            });
+        }
+
+        scheduler
+    }
+
+    /// Returns actions of all tasks which are due at `now` and schedules their next runs.
+    pub fn poll(&mut self, now: Instant) -> Vec<TaskAction> {
+        let mut due = Vec::new();
+
+        for task in self.tasks.iter_mut() {
+            if task.next_run > now {
+                continue;
+            }
+
+            due.push(task.action.clone());
+
+            // Schedule relative to the planned time rather than `now`, so late ticks don't accumulate drift.
+            task.next_run += task.interval;
+
+            // If the server stalled for longer than an interval, skip the missed runs instead of bursting them.
+            if task.next_run <= now {
+                let behind = (now - task.next_run).as_nanos() / task.interval.as_nanos();
+                let skipped = behind as u32 + 1;
+
+                log::warn!("Task {} skipped {skipped} missed run(s)", task.name);
+                task.next_run += task.interval * skipped;
+            }
+        }
+
+        due
+    }
+}
@@ -0,0 +1,462 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    chunks::ChunkStore,
+    interest::{ChunkPos, CHUNK_SIZE},
+};
+
+/// Number of world ticks since the world was created.
+pub type Tick = u64;
+
+/// Limits block updates processed in a single tick, the rest are delayed to the following ticks.
+const MAX_UPDATES_PER_TICK: usize = 4096;
+/// Blocks picked for random ticks in every loaded chunk per tick, a block is hit every two minutes on average.
+const RANDOM_TICKS_PER_CHUNK: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn offset(self, x: i32, y: i32, z: i32) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+}
+
+/// Called when a scheduled update or a random tick of a block is due.
+pub type TickCallback = Box<dyn FnMut(&mut TickContext, BlockPos) + Send>;
+
+/// Passed to tick callbacks, so updates can change blocks and schedule further updates
+/// (e.g. spreading fluids).
+pub struct TickContext<'a> {
+    tick: Tick,
+    world: &'a mut dyn ChunkStore,
+    /// State of the xorshift generator of the world, written back after the tick.
+    rng: u64,
+    scheduled: Vec<(BlockPos, String, Tick)>,
+    changed: Vec<BlockPos>,
+}
+
+impl TickContext<'_> {
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    /// Returns the loaded blocks of the world.
+    pub fn world(&self) -> &dyn ChunkStore {
+        self.world
+    }
+
+    /// Sets a block in a loaded chunk, players are told about it at the end of the tick.
+    /// Returns false if the chunk isn't loaded.
+    pub fn set_block(&mut self, pos: BlockPos, block: Option<u32>) -> bool {
+        let set = self.world.set_block(pos, block);
+        if set {
+            self.changed.push(pos);
+        }
+
+        set
+    }
+
+    /// Returns the next number of the random generator of the world, which starts with a fixed seed.
+    pub fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        self.rng
+    }
+
+    /// Schedules an update of `block` at `pos` after `delay` ticks, at least one.
+    pub fn schedule(&mut self, pos: BlockPos, block: &str, delay: Tick) {
+        self.scheduled.push((pos, block.to_string(), delay));
+    }
+}
+
+impl fmt::Debug for TickContext<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TickContext")
+            .field("tick", &self.tick)
+            .field("scheduled", &self.scheduled)
+            .field("changed", &self.changed)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ScheduledUpdate {
+    tick: Tick,
+    /// Order of scheduling, keeps updates due in the same tick deterministic.
+    sequence: u64,
+    pos: BlockPos,
+    block: String,
+}
+
+/// Fixed step world simulation advancing in whole ticks.
+///
+/// Block updates are queued by the tick they are due in and processed in the order they were
+/// scheduled, so the same inputs always produce the same world. Random ticks hit a few blocks of
+/// every loaded chunk per tick, like grass spreading over soil and crops growing. They are picked
+/// with a fixed seed in a fixed order too.
+pub struct WorldTick {
+    tick: Tick,
+    next_sequence: u64,
+    queue: BinaryHeap<Reverse<ScheduledUpdate>>,
+    /// Pending updates, so the same block isn't updated multiple times by one change.
+    pending: HashSet<(BlockPos, String)>,
+    callbacks: HashMap<String, TickCallback>,
+    random_callbacks: HashMap<String, TickCallback>,
+    /// State of the xorshift generator picking the blocks of random ticks.
+    rng: u64,
+}
+
+impl WorldTick {
+    pub fn new() -> Self {
+        Self {
+            tick: 0,
+            next_sequence: 0,
+            queue: BinaryHeap::new(),
+            pending: HashSet::new(),
+            callbacks: HashMap::new(),
+            random_callbacks: HashMap::new(),
+            // xorshift never leaves zero
+            rng: 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    pub fn current_tick(&self) -> Tick {
+        self.tick
+    }
+
+    /// Registers the function run on scheduled updates of a block, replacing the previous one.
+    pub fn register(&mut self, block: &str, callback: TickCallback) {
+        self.callbacks.insert(block.to_string(), callback);
+    }
+
+    /// Registers the function run when a random tick hits a block, replacing the previous one.
+    pub fn register_random(&mut self, block: &str, callback: TickCallback) {
+        self.random_callbacks.insert(block.to_string(), callback);
+    }
+
+    /// Schedules an update of `block` at `pos` after `delay` ticks, at least one.
+    /// Does nothing if an update of the same block at the same position is already pending.
+    pub fn schedule(&mut self, pos: BlockPos, block: &str, delay: Tick) {
+        if !self.pending.insert((pos, block.to_string())) {
+            return;
+        }
+
+        self.queue.push(Reverse(ScheduledUpdate {
+            tick: self.tick + delay.max(1),
+            sequence: self.next_sequence,
+            pos,
+            block: block.to_string(),
+        }));
+
+        self.next_sequence += 1;
+    }
+
+    /// Advances the world by one tick, runs all updates due in it and the random ticks of the loaded
+    /// `chunks`. Returns the blocks changed by them.
+    pub fn step(
+        &mut self,
+        world: &mut dyn ChunkStore,
+        chunks: &HashSet<ChunkPos>,
+    ) -> Vec<BlockPos> {
+        self.tick += 1;
+
+        let mut context = TickContext {
+            tick: self.tick,
+            world,
+            rng: self.rng,
+            scheduled: Vec::new(),
+            changed: Vec::new(),
+        };
+        let mut processed = 0;
+
+        while processed < MAX_UPDATES_PER_TICK {
+            match self.queue.peek() {
+                Some(Reverse(update)) if update.tick <= self.tick => {}
+                _ => break,
+            }
+
+            let Some(Reverse(update)) = self.queue.pop() else {
+                break;
+            };
+
+            self.pending.remove(&(update.pos, update.block.clone()));
+            processed += 1;
+
+            let Some(callback) = self.callbacks.get_mut(&update.block) else {
+                log::warn!("Block {} has no tick callback registered", update.block);
+                continue;
+            };
+
+            callback(&mut context, update.pos);
+
+            for (pos, block, delay) in std::mem::take(&mut context.scheduled) {
+                self.schedule(pos, &block, delay);
+            }
+        }
+
+        if processed == MAX_UPDATES_PER_TICK {
+            log::warn!(
+                "Tick {} reached the block update limit, delaying the remaining updates",
+                self.tick
+            );
+        }
+
+        if !self.random_callbacks.is_empty() {
+            // sorted, so the same chunks are ticked in the same order
+            let mut chunks: Vec<ChunkPos> = chunks.iter().copied().collect();
+            chunks.sort_unstable_by_key(|chunk| (chunk.x, chunk.y, chunk.z));
+
+            for chunk in chunks {
+                for _ in 0..RANDOM_TICKS_PER_CHUNK {
+                    let random = context.random();
+                    let pos = random_block(chunk, random);
+
+                    let Some(Some(block)) = context.world.block(pos) else {
+                        continue;
+                    };
+                    let Some(callback) = context
+                        .world
+                        .block_name(block)
+                        .and_then(|name| self.random_callbacks.get_mut(name))
+                    else {
+                        continue;
+                    };
+
+                    callback(&mut context, pos);
+                }
+            }
+        }
+
+        for (pos, block, delay) in std::mem::take(&mut context.scheduled) {
+            self.schedule(pos, &block, delay);
+        }
+
+        self.rng = context.rng;
+        context.changed
+    }
+}
+
+/// Returns the block of a chunk picked by a random number.
+fn random_block(chunk: ChunkPos, random: u64) -> BlockPos {
+    let size = CHUNK_SIZE as u64;
+
+    BlockPos::new(
+        chunk.x * CHUNK_SIZE + (random % size) as i32,
+        chunk.y * CHUNK_SIZE + (random / size % size) as i32,
+        chunk.z * CHUNK_SIZE + (random / (size * size) % size) as i32,
+    )
+}
+
+impl Default for WorldTick {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for WorldTick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorldTick")
+            .field("tick", &self.tick)
+            .field("queued_updates", &self.queue.len())
+            .field("callbacks", &self.callbacks.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::tests::FlatWorld;
+
+    /// Advances a tick without loaded chunks.
+    fn step(world_tick: &mut WorldTick) {
+        world_tick.step(&mut FlatWorld::default(), &HashSet::new());
+    }
+
+    /// Registers a callback for `block` which records the tick and position of every update.
+    fn record(world_tick: &mut WorldTick, block: &str) -> Arc<Mutex<Vec<(Tick, BlockPos)>>> {
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&updates);
+
+        world_tick.register(
+            block,
+            Box::new(move |context, pos| recorded.lock().unwrap().push((context.tick(), pos))),
+        );
+
+        updates
+    }
+
+    fn pos(x: i32) -> BlockPos {
+        BlockPos::new(x, 0, 0)
+    }
+
+    #[test]
+    fn same_tick_updates_run_in_scheduling_order() {
+        let mut world_tick = WorldTick::new();
+        let updates = record(&mut world_tick, "sand");
+
+        for x in [3, 1, 2] {
+            world_tick.schedule(pos(x), "sand", 2);
+        }
+
+        step(&mut world_tick);
+        assert!(updates.lock().unwrap().is_empty());
+
+        step(&mut world_tick);
+        assert_eq!(
+            *updates.lock().unwrap(),
+            vec![(2, pos(3)), (2, pos(1)), (2, pos(2))]
+        );
+    }
+
+    #[test]
+    fn zero_delay_runs_in_the_next_tick() {
+        let mut world_tick = WorldTick::new();
+        let updates = record(&mut world_tick, "sand");
+
+        world_tick.schedule(pos(0), "sand", 0);
+        step(&mut world_tick);
+
+        assert_eq!(*updates.lock().unwrap(), vec![(1, pos(0))]);
+    }
+
+    #[test]
+    fn callbacks_scheduling_without_delay_run_in_a_later_tick() {
+        let mut world_tick = WorldTick::new();
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&updates);
+
+        world_tick.register(
+            "water",
+            Box::new(move |context, pos| {
+                recorded.lock().unwrap().push((context.tick(), pos));
+                if pos.x < 2 {
+                    context.schedule(BlockPos::new(pos.x + 1, pos.y, pos.z), "water", 0);
+                }
+            }),
+        );
+
+        world_tick.schedule(pos(0), "water", 1);
+        for _ in 0..3 {
+            step(&mut world_tick);
+        }
+
+        assert_eq!(
+            *updates.lock().unwrap(),
+            vec![(1, pos(0)), (2, pos(1)), (3, pos(2))]
+        );
+    }
+
+    #[test]
+    fn pending_updates_are_deduplicated() {
+        let mut world_tick = WorldTick::new();
+        let updates = record(&mut world_tick, "sand");
+        let gravel = record(&mut world_tick, "gravel");
+
+        world_tick.schedule(pos(0), "sand", 1);
+        // a second update of the same block is dropped, even with another delay
+        world_tick.schedule(pos(0), "sand", 3);
+        // other blocks at the same position are updated separately
+        world_tick.schedule(pos(0), "gravel", 1);
+
+        for _ in 0..3 {
+            step(&mut world_tick);
+        }
+
+        assert_eq!(*updates.lock().unwrap(), vec![(1, pos(0))]);
+        assert_eq!(*gravel.lock().unwrap(), vec![(1, pos(0))]);
+
+        // once the update ran, the block can be scheduled again
+        world_tick.schedule(pos(0), "sand", 1);
+        step(&mut world_tick);
+
+        assert_eq!(*updates.lock().unwrap(), vec![(1, pos(0)), (4, pos(0))]);
+    }
+
+    #[test]
+    fn updates_over_the_limit_carry_over() {
+        let mut world_tick = WorldTick::new();
+        let updates = record(&mut world_tick, "sand");
+        let scheduled = MAX_UPDATES_PER_TICK + 10;
+
+        for x in 0..scheduled {
+            world_tick.schedule(pos(x as i32), "sand", 1);
+        }
+
+        step(&mut world_tick);
+        assert_eq!(updates.lock().unwrap().len(), MAX_UPDATES_PER_TICK);
+
+        step(&mut world_tick);
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), scheduled);
+
+        // the delayed updates keep their order and run in the following tick
+        let carried: Vec<_> = updates[MAX_UPDATES_PER_TICK..].to_vec();
+        let expected: Vec<_> = (MAX_UPDATES_PER_TICK..scheduled)
+            .map(|x| (2, pos(x as i32)))
+            .collect();
+        assert_eq!(carried, expected);
+    }
+
+    #[test]
+    fn random_ticks_hit_blocks_of_loaded_chunks() {
+        let chunk = ChunkPos::new(0, -1, 0);
+        let mut world = FlatWorld::default();
+        world.request(chunk, [0.0, 0.0, 0.0]);
+        world.poll();
+
+        // stone crumbles whenever a random tick hits it
+        let mut world_tick = WorldTick::new();
+        world_tick.register_random(
+            "stone",
+            Box::new(|context, pos| {
+                context.set_block(pos, None);
+            }),
+        );
+
+        let changed = world_tick.step(&mut world, &HashSet::from([chunk]));
+        assert_eq!(changed.len(), RANDOM_TICKS_PER_CHUNK);
+        for pos in changed.iter() {
+            assert_eq!(ChunkPos::of_block(*pos), chunk);
+            assert_eq!(world.block(*pos), Some(None));
+        }
+
+        // the same blocks are picked in every world
+        let mut other_tick = WorldTick::new();
+        other_tick.register_random(
+            "stone",
+            Box::new(|context, pos| {
+                context.set_block(pos, None);
+            }),
+        );
+        let mut other_world = FlatWorld::default();
+        other_world.request(chunk, [0.0, 0.0, 0.0]);
+        other_world.poll();
+        assert_eq!(
+            other_tick.step(&mut other_world, &HashSet::from([chunk])),
+            changed
+        );
+
+        // chunks which aren't loaded have no blocks to tick
+        assert!(world_tick
+            .step(&mut world, &HashSet::from([ChunkPos::new(5, -1, 0)]))
+            .is_empty());
+    }
+}
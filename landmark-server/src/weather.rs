@@ -0,0 +1,93 @@
+use landmark_protocol::message::Weather;
+
+use crate::{tick::Tick, Server};
+
+/// Shortest spell of rain or storm, clear weather lasts twice as long.
+const MIN_DURATION: Tick = 5 * 60 * Server::TICKS_PER_SECOND as Tick;
+/// Longest spell of rain or storm, clear weather lasts twice as long.
+const MAX_DURATION: Tick = 15 * 60 * Server::TICKS_PER_SECOND as Tick;
+
+/// Weather of the world, moving between clear skies, rain and storms after spells of random length.
+///
+/// Storms only follow rain or clear skies and mostly calm down to rain again, so the weather doesn't
+/// jump between extremes too often. The generator is seeded by the world, so each world has its own weather.
+#[derive(Debug)]
+pub struct WeatherCycle {
+    weather: Weather,
+    /// Ticks until the weather changes.
+    remaining: Tick,
+    /// State of the splitmix64 generator.
+    rng: u64,
+}
+
+impl WeatherCycle {
+    pub fn new(seed: u64) -> Self {
+        let mut cycle = Self {
+            weather: Weather::Clear,
+            remaining: 0,
+            rng: seed,
+        };
+        cycle.remaining = cycle.spell_length(Weather::Clear);
+
+        cycle
+    }
+
+    pub fn current(&self) -> Weather {
+        self.weather
+    }
+
+    /// Changes the weather at once, it lasts for a full spell.
+    pub fn set(&mut self, weather: Weather) {
+        self.weather = weather;
+        self.remaining = self.spell_length(weather);
+    }
+
+    /// Advances the weather by one tick, returns the new weather when it changes.
+    pub fn step(&mut self) -> Option<Weather> {
+        self.remaining = self.remaining.saturating_sub(1);
+        if self.remaining > 0 {
+            return None;
+        }
+
+        let roll = self.random() % 100;
+        let next = match self.weather {
+            Weather::Clear if roll < 80 => Weather::Rain,
+            Weather::Clear => Weather::Storm,
+            Weather::Rain if roll < 60 => Weather::Clear,
+            Weather::Rain => Weather::Storm,
+            Weather::Storm if roll < 70 => Weather::Rain,
+            Weather::Storm => Weather::Clear,
+        };
+
+        self.set(next);
+        Some(next)
+    }
+
+    fn spell_length(&mut self, weather: Weather) -> Tick {
+        let length = MIN_DURATION + self.random() % (MAX_DURATION - MIN_DURATION);
+
+        match weather {
+            Weather::Clear => length * 2,
+            Weather::Rain | Weather::Storm => length,
+        }
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Parses the weather names used by the `weather` command.
+pub fn parse_weather(name: &str) -> Option<Weather> {
+    match name {
+        "clear" => Some(Weather::Clear),
+        "rain" => Some(Weather::Rain),
+        "storm" => Some(Weather::Storm),
+        _ => None,
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Settings of a world fixed when it's created, stored in `world.toml` of the world directory.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WorldInfo {
+    pub seed: u64,
+}
+
+impl WorldInfo {
+    const FILE_NAME: &'static str = "world.toml";
+
+    /// Loads the info of an existing world, or creates a new world with the configured or a random seed.
+    pub fn load_or_create(world_path: &Path, seed: Option<u64>) -> io::Result<Self> {
+        let path = Self::path(world_path);
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let info: Self = toml::from_str(&content)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                if seed.is_some_and(|seed| seed != info.seed) {
+                    log::warn!(
+                        "The configured seed only applies to new worlds, {} keeps seed {}",
+                        world_path.display(),
+                        info.seed
+                    );
+                }
+
+                Ok(info)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let info = Self {
+                    seed: seed.unwrap_or_else(random_seed),
+                };
+
+                let content = toml::to_string_pretty(&info)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                fs::create_dir_all(world_path)?;
+                fs::write(&path, content)?;
+
+                log::info!("Created a new world with seed {}", info.seed);
+
+                Ok(info)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn path(world_path: &Path) -> PathBuf {
+        world_path.join(Self::FILE_NAME)
+    }
+}
+
+/// Where players join for the first time and respawn, stored in `spawn.ron` of the world directory.
+#[derive(Debug)]
+pub struct SpawnPoint {
+    path: PathBuf,
+    position: [f64; 3],
+}
+
+impl SpawnPoint {
+    const FILE_NAME: &'static str = "spawn.ron";
+    /// Eye position players spawn at until an operator moves the spawn point.
+    pub const DEFAULT: [f64; 3] = [0.0, 80.0, 0.0];
+
+    /// Loads the spawn point of the world, the default one is used if none was set or it's invalid.
+    pub fn load(world_path: &Path) -> Self {
+        let path = world_path.join(Self::FILE_NAME);
+
+        let position = match fs::read_to_string(&path) {
+            Ok(content) => ron::from_str(&content).unwrap_or_else(|e| {
+                log::error!("Failed to parse {}: {e}", path.display());
+                Self::DEFAULT
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::DEFAULT,
+            Err(e) => {
+                log::error!("Failed to read {}: {e}", path.display());
+                Self::DEFAULT
+            }
+        };
+
+        Self { path, position }
+    }
+
+    pub fn position(&self) -> [f64; 3] {
+        self.position
+    }
+
+    /// Moves the spawn point and stores it with the world.
+    pub fn set(&mut self, position: [f64; 3]) -> io::Result<()> {
+        self.position = position;
+
+        let content =
+            ron::to_string(&position).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, content)
+    }
+}
+
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or(0);
+
+    // splitmix64, so seeds of worlds created shortly after each other don't look alike
+    let mut z = nanos.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+
+    // toml only stores signed 64 bit integers
+    z >> 1
+}
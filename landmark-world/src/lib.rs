@@ -0,0 +1,32 @@
+//! On-disk format of saved worlds, shared by everything reading or writing them.
+
+pub mod migrate;
+
+/// Version of the format chunks are saved in, stored in the header of every region file.
+///
+/// - 1: palette with u32 counts, runs of u32 length and u32 palette index
+/// - 2: palette with a u16 count, runs of u16 length and u16 palette index
+/// - 3: optional sections after the runs
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Optional data following the blocks of a chunk, every section is stored as its kind as u8,
+/// its version as u8, the length of its content as u32 and the content.
+///
+/// Sections only hold data which can be computed again, so readers skip sections of unknown kinds
+/// or versions and regenerate their data instead. A chunk without a readable state counts as modified,
+/// older saves only held chunks edited by the player.
+pub mod section {
+    /// Biome of every column, as a palette of names and a palette index per column.
+    pub const BIOMES: u8 = 1;
+    pub const BIOMES_VERSION: u8 = 1;
+
+    /// Sky light of every block, two blocks per byte with the first one in the low bits.
+    pub const LIGHT: u8 = 2;
+    pub const LIGHT_VERSION: u8 = 1;
+
+    /// Flags of the chunk as a single u8, see `STATE_MODIFIED`.
+    pub const STATE: u8 = 3;
+    pub const STATE_VERSION: u8 = 1;
+    /// Set if the player changed the chunk, unmodified chunks may be generated again.
+    pub const STATE_MODIFIED: u8 = 0b1;
+}
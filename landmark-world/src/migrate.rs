@@ -0,0 +1,287 @@
+//! Upgrades of saved chunks from older format versions.
+//!
+//! Every migration converts a chunk from one version to the next one, older chunks pass through all
+//! of them in order. Migrations work on the bytes of a chunk, so they don't depend on loaded blocks.
+
+use std::fmt;
+
+use crate::FORMAT_VERSION;
+
+type Migration = fn(&[u8]) -> Result<Vec<u8>, MigrationError>;
+
+/// Migrations indexed by the version they upgrade from, starting at version 1.
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize - 1] = [v1_to_v2, v2_to_v3];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationError {
+    /// Saved by a newer version of the game.
+    FutureVersion {
+        found: u32,
+    },
+    UnknownVersion {
+        found: u32,
+    },
+    Invalid {
+        version: u32,
+        reason: String,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::FutureVersion { found } => write!(
+                f,
+                "World format version {found} is newer than the supported version {FORMAT_VERSION}, \
+                 update the game to load this world"
+            ),
+            MigrationError::UnknownVersion { found } => {
+                write!(f, "Unknown world format version {found}")
+            }
+            MigrationError::Invalid { version, reason } => {
+                write!(f, "Chunk in world format version {version} is damaged: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+/// Checks that data saved in `version` can be loaded, i.e. migrated to the current version.
+pub fn check_version(version: u32) -> Result<(), MigrationError> {
+    if version == 0 {
+        Err(MigrationError::UnknownVersion { found: version })
+    } else if version > FORMAT_VERSION {
+        Err(MigrationError::FutureVersion { found: version })
+    } else {
+        Ok(())
+    }
+}
+
+/// Upgrades a chunk saved in `version` to the current format version.
+pub fn migrate_chunk(version: u32, bytes: Vec<u8>) -> Result<Vec<u8>, MigrationError> {
+    check_version(version)?;
+
+    let mut bytes = bytes;
+
+    for (from, migration) in MIGRATIONS
+        .iter()
+        .enumerate()
+        .map(|(idx, migration)| (idx as u32 + 1, migration))
+        .skip_while(|(from, _)| *from < version)
+    {
+        bytes = migration(&bytes).map_err(|e| match e {
+            MigrationError::Invalid { reason, .. } => MigrationError::Invalid {
+                version: from,
+                reason,
+            },
+            e => e,
+        })?;
+    }
+
+    Ok(bytes)
+}
+
+/// Reads little endian values from a chunk.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], MigrationError> {
+        if self.bytes.len() < count {
+            return Err(invalid("unexpected end of chunk"));
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, MigrationError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, MigrationError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// The version is filled in by `migrate_chunk`.
+fn invalid(reason: &str) -> MigrationError {
+    MigrationError::Invalid {
+        version: 0,
+        reason: reason.to_string(),
+    }
+}
+
+fn narrow(value: u32, what: &str) -> Result<u16, MigrationError> {
+    u16::try_from(value).map_err(|_| invalid(&format!("{what} {value} is too large")))
+}
+
+/// Narrows counts, run lengths and palette indices to u16, a chunk has 32768 blocks at most.
+fn v1_to_v2(bytes: &[u8]) -> Result<Vec<u8>, MigrationError> {
+    let mut reader = Reader { bytes };
+    let mut upgraded = Vec::with_capacity(bytes.len());
+
+    let palette_size = reader.u32()?;
+    upgraded.extend_from_slice(&narrow(palette_size, "palette size")?.to_le_bytes());
+
+    for _ in 0..palette_size {
+        let length = reader.u16()?;
+        upgraded.extend_from_slice(&length.to_le_bytes());
+        upgraded.extend_from_slice(reader.take(length as usize)?);
+    }
+
+    let run_count = reader.u32()?;
+    upgraded.extend_from_slice(&run_count.to_le_bytes());
+
+    for _ in 0..run_count {
+        let length = narrow(reader.u32()?, "run length")?;
+        let value = narrow(reader.u32()?, "palette index")?;
+
+        upgraded.extend_from_slice(&length.to_le_bytes());
+        upgraded.extend_from_slice(&value.to_le_bytes());
+    }
+
+    Ok(upgraded)
+}
+
+/// Chunks without sections are valid in version 3, their biomes and light are computed when they are loaded.
+fn v2_to_v3(bytes: &[u8]) -> Result<Vec<u8>, MigrationError> {
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a chunk in version 1, all numbers but name lengths are u32.
+    fn v1_chunk(palette: &[&str], runs: &[(u32, u32)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+
+        for name in palette {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+
+        for (length, value) in runs {
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// The same chunk in version 2, with the palette size, run lengths and indices as u16.
+    fn v2_chunk(palette: &[&str], runs: &[(u16, u16)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+
+        for name in palette {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+
+        for (length, value) in runs {
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn v1_values_are_narrowed() {
+        let palette = ["Air", "Stone"];
+        let v1 = v1_chunk(&palette, &[(32000, 0), (768, 1)]);
+
+        assert_eq!(
+            v1_to_v2(&v1).unwrap(),
+            v2_chunk(&palette, &[(32000, 0), (768, 1)])
+        );
+    }
+
+    #[test]
+    fn v1_values_too_large_are_invalid() {
+        let too_long = v1_chunk(&["Air"], &[(70000, 0)]);
+        let too_large_index = v1_chunk(&["Air"], &[(1, 65536)]);
+
+        for bytes in [too_long, too_large_index] {
+            assert!(matches!(
+                v1_to_v2(&bytes),
+                Err(MigrationError::Invalid { .. })
+            ));
+        }
+
+        let mut too_large_palette = Vec::new();
+        too_large_palette.extend_from_slice(&70000u32.to_le_bytes());
+
+        assert!(matches!(
+            v1_to_v2(&too_large_palette),
+            Err(MigrationError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_v1_chunks_are_invalid() {
+        let bytes = v1_chunk(&["Stone"], &[(5, 0)]);
+
+        assert!(matches!(
+            v1_to_v2(&bytes[..bytes.len() - 1]),
+            Err(MigrationError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn v1_chunks_migrate_to_the_current_version() {
+        let v1 = v1_chunk(&["Grass", "Dirt"], &[(16, 0), (48, 1)]);
+
+        assert_eq!(
+            migrate_chunk(1, v1).unwrap(),
+            v2_chunk(&["Grass", "Dirt"], &[(16, 0), (48, 1)])
+        );
+    }
+
+    #[test]
+    fn current_chunks_are_unchanged() {
+        let bytes = v2_chunk(&["Sand"], &[(8, 0)]);
+
+        assert_eq!(migrate_chunk(FORMAT_VERSION, bytes.clone()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn errors_name_the_failing_version() {
+        let bytes = v1_chunk(&["Air"], &[(70000, 0)]);
+
+        assert!(matches!(
+            migrate_chunk(1, bytes),
+            Err(MigrationError::Invalid { version: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        assert_eq!(
+            check_version(0),
+            Err(MigrationError::UnknownVersion { found: 0 })
+        );
+        assert_eq!(
+            check_version(FORMAT_VERSION + 1),
+            Err(MigrationError::FutureVersion {
+                found: FORMAT_VERSION + 1
+            })
+        );
+        assert!(migrate_chunk(0, Vec::new()).is_err());
+
+        for version in 1..=FORMAT_VERSION {
+            assert_eq!(check_version(version), Ok(()));
+        }
+    }
+}
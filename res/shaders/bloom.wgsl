@@ -0,0 +1,105 @@
+// Bloom and tone mapping of the floating point frame.
+//
+// The pre-filter keeps the light above the threshold while halving the frame, the down-sampling passes
+// halve it again and again, and the up-sampling passes add every level onto the next larger one with a
+// blur. The composite pass adds the result to the frame and maps it onto the range of the display.
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+// only used by the composite pass
+@group(1) @binding(0)
+var t_bloom: texture_2d<f32>;
+@group(1) @binding(1)
+var s_bloom: sampler;
+
+struct BloomUniform {
+    intensity: f32,
+};
+
+@group(2) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+// Light above this brightness glows, white surfaces stay below it.
+const THRESHOLD: f32 = 1.0;
+// Colors up to this brightness are shown unchanged, brighter ones are compressed smoothly towards white.
+const SHOULDER: f32 = 0.8;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// Covers the whole target with a single triangle.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.uv = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+// Averages the 4x4 texels of the source around a texel of a target half its size.
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+
+    let color = textureSample(t_source, s_source, uv + texel * vec2<f32>(-1.0, -1.0)).rgb
+        + textureSample(t_source, s_source, uv + texel * vec2<f32>(1.0, -1.0)).rgb
+        + textureSample(t_source, s_source, uv + texel * vec2<f32>(-1.0, 1.0)).rgb
+        + textureSample(t_source, s_source, uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+
+    return color * 0.25;
+}
+
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = downsample(in.uv);
+    let brightness = max(color.r, max(color.g, color.b));
+
+    // scaling the whole color keeps its hue
+    let weight = max(brightness - THRESHOLD, 0.0) / max(brightness, 0.0001);
+
+    return vec4<f32>(color * weight, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// Blurs the smaller level with a 3x3 tent while it's added onto the larger one.
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    var color = vec3<f32>(0.0);
+
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let weight = f32((2 - abs(x)) * (2 - abs(y)));
+            let offset = texel * vec2<f32>(f32(x), f32(y));
+            color += textureSample(t_source, s_source, in.uv + offset).rgb * weight;
+        }
+    }
+
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    let over = max(color - SHOULDER, vec3<f32>(0.0));
+    let compressed = SHOULDER + (1.0 - SHOULDER) * (1.0 - exp(-over / (1.0 - SHOULDER)));
+
+    return select(color, compressed, color > vec3<f32>(SHOULDER));
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = textureSample(t_source, s_source, in.uv).rgb;
+    let glow = textureSample(t_bloom, s_bloom, in.uv).rgb;
+
+    return vec4<f32>(tone_map(frame + glow * bloom.intensity), 1.0);
+}
@@ -0,0 +1,8 @@
+// Camera of the viewpoint, shared by the world and particle shaders.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
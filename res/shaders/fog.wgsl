@@ -0,0 +1,18 @@
+// Fog fading distant geometry into the sky color, hiding it completely at the end distance.
+
+struct FogUniform {
+    color: vec3<f32>,
+    end: f32,
+};
+
+@group(2) @binding(0)
+var<uniform> fog: FogUniform;
+
+// Squared exponential fog is 99% dense at this many end distances
+const FOG_DENSITY: f32 = 2.146;
+
+// Depth is the distance from the camera along the view direction.
+fn apply_fog(color: vec3<f32>, depth: f32) -> vec3<f32> {
+    let fog_depth = depth * FOG_DENSITY / fog.end;
+    return mix(color, fog.color, 1.0 - exp(-fog_depth * fog_depth));
+}
@@ -0,0 +1,72 @@
+// Point lights of entities and blocks, like a torch held by the player. They brighten nearby geometry on top
+// of its own color and fade out towards their radius.
+//
+// Flags: CLUSTERED_LIGHTS looks up the lights of the cell of the light grid a fragment is in, otherwise
+// every fragment adds up the few lights nearest to the camera.
+
+struct PointLight {
+    // relative to the origin the world is drawn around
+    position: vec3<f32>,
+    radius: f32,
+    color: vec3<f32>,
+};
+
+#ifdef CLUSTERED_LIGHTS
+struct LightGrid {
+    // corner of the grid with the lowest coordinates, relative to the origin the world is drawn around
+    min: vec3<f32>,
+    cell_size: f32,
+    // cells along each axis
+    size: vec3<u32>,
+};
+
+@group(2) @binding(1)
+var<uniform> light_grid: LightGrid;
+@group(2) @binding(2)
+var<storage, read> lights: array<PointLight>;
+// offset into the light indices and count of the lights of every cell, x changing fastest
+@group(2) @binding(3)
+var<storage, read> light_cells: array<vec2<u32>>;
+@group(2) @binding(4)
+var<storage, read> light_indices: array<u32>;
+#else
+const MAX_POINT_LIGHTS: u32 = 8u;
+
+struct LightsUniform {
+    lights: array<PointLight, MAX_POINT_LIGHTS>,
+    count: u32,
+};
+
+@group(2) @binding(1)
+var<uniform> lights: LightsUniform;
+#endif
+
+fn point_light(point: PointLight, position: vec3<f32>) -> vec3<f32> {
+    let falloff = saturate(1.0 - distance(position, point.position) / point.radius);
+    return point.color * falloff * falloff;
+}
+
+// Position is relative to the origin the world is drawn around, like the positions of the lights.
+fn apply_point_lights(color: vec3<f32>, position: vec3<f32>) -> vec3<f32> {
+    var light = vec3<f32>(0.0);
+
+#ifdef CLUSTERED_LIGHTS
+    let cell = vec3<i32>(floor((position - light_grid.min) / light_grid.cell_size));
+
+    // fragments outside of the grid are too far away to be lit
+    if all(cell >= vec3<i32>(0)) && all(cell < vec3<i32>(light_grid.size)) {
+        let size = light_grid.size;
+        let range = light_cells[(u32(cell.z) * size.y + u32(cell.y)) * size.x + u32(cell.x)];
+
+        for (var i = range.x; i < range.x + range.y; i++) {
+            light += point_light(lights[light_indices[i]], position);
+        }
+    }
+#else
+    for (var i = 0u; i < min(lights.count, MAX_POINT_LIGHTS); i++) {
+        light += point_light(lights.lights[i], position);
+    }
+#endif
+
+    return color * (1.0 + light);
+}
@@ -0,0 +1,64 @@
+// Vertex shader
+//
+// Particles are squares facing the camera, every instance is one particle. The corners aren't
+// stored in a buffer, they are picked by the vertex index of a four vertex triangle strip.
+
+#include "camera.wgsl"
+
+@group(1) @binding(0)
+var t_atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var s_atlas: sampler;
+
+struct InstanceInput {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) color: vec4<f32>,
+    @location(3) uv_min: vec2<f32>,
+    @location(4) uv_max: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    // 0: bottom left, 1: bottom right, 2: top left, 3: top right
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+
+    // the first two rows of the view projection are the screen axes in world space
+    let vp = camera.view_proj;
+    let right = normalize(vec3<f32>(vp[0].x, vp[1].x, vp[2].x));
+    let up = normalize(vec3<f32>(vp[0].y, vp[1].y, vp[2].y));
+
+    let offset = (corner - 0.5) * instance.size;
+    let position = instance.position + right * offset.x + up * offset.y;
+
+    out.color = instance.color;
+    out.uv = mix(instance.uv_min, instance.uv_max, vec2<f32>(corner.x, 1.0 - corner.y));
+    out.clip_position = vp * vec4<f32>(position, 1.0);
+
+    return out;
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_atlas, s_atlas, in.uv) * in.color;
+
+    // particles are drawn without blending, so faded ones are cut out instead
+    if color.a < 0.5 {
+        discard;
+    }
+
+    return vec4<f32>(color.rgb, 1.0);
+}
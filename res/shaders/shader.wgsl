@@ -1,27 +1,42 @@
 // Vertex shader
+//
+// Flags: TEXTURES samples the block atlas, FOG fades distant geometry into the sky color, hiding it
+// completely at the end distance of the fog uniform, SHADOWS darkens geometry in the shadow of the sun.
 
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-};
+#include "camera.wgsl"
+
+@group(1) @binding(0)
+var t_atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var s_atlas: sampler;
 
-@group(0) @binding(0)
-var<uniform> camera: CameraUniform;
+#include "fog.wgsl"
+#include "lights.wgsl"
+#include "shadows.wgsl"
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
+    @location(2) uv: vec2<f32>,
 };
 
 struct InstanceInput {
-    @location(2) model_matrix_0: vec4<f32>,
-    @location(3) model_matrix_1: vec4<f32>,
-    @location(4) model_matrix_2: vec4<f32>,
-    @location(5) model_matrix_3: vec4<f32>,
+    @location(3) model_matrix_0: vec4<f32>,
+    @location(4) model_matrix_1: vec4<f32>,
+    @location(5) model_matrix_2: vec4<f32>,
+    @location(6) model_matrix_3: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    // relative to the origin the world is drawn around
+    @location(2) position: vec3<f32>,
+#ifdef FOG
+    // distance from the camera along the view direction
+    @location(3) depth: f32,
+#endif
 };
 
 @vertex
@@ -39,7 +54,13 @@ fn vs_main(
     );
 
     out.color = model.color;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.uv = model.uv;
+    let position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.position = position.xyz;
+    out.clip_position = camera.view_proj * position;
+#ifdef FOG
+    out.depth = out.clip_position.w;
+#endif
 
     return out;
 }
@@ -48,5 +69,20 @@ fn vs_main(
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+#ifdef TEXTURES
+    var color = textureSample(t_atlas, s_atlas, in.uv).rgb * in.color;
+#else
+    var color = in.color;
+#endif
+
+#ifdef SHADOWS
+    color = apply_shadow(color, in.position);
+#endif
+    color = apply_point_lights(color, in.position);
+
+#ifdef FOG
+    color = apply_fog(color, in.depth);
+#endif
+
+    return vec4<f32>(color, 1.0);
 }
@@ -0,0 +1,35 @@
+// Shadows cast by the sun, looked up in a depth map drawn from its direction. Geometry outside of the
+// map is never shadowed.
+
+struct ShadowUniform {
+    // view of the sun, from the origin the world is drawn around
+    view_proj: mat4x4<f32>,
+    // how dark shadows are, fades out while the sun sets
+    strength: f32,
+};
+
+@group(3) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(3) @binding(1)
+var t_shadow: texture_depth_2d;
+@group(3) @binding(2)
+var s_shadow: sampler_comparison;
+
+// Fraction of the light taken away in full shadow
+const SHADOW_DARKNESS: f32 = 0.5;
+// Keeps lit surfaces from shadowing themselves
+const SHADOW_BIAS: f32 = 0.0005;
+
+// Position is relative to the origin the world is drawn around.
+fn apply_shadow(color: vec3<f32>, position: vec3<f32>) -> vec3<f32> {
+    // the projection is orthographic, so w is always 1
+    let clip = shadow.view_proj * vec4<f32>(position, 1.0);
+    let uv = clip.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || clip.z > 1.0 {
+        return color;
+    }
+
+    let lit = textureSampleCompareLevel(t_shadow, s_shadow, uv, clip.z - SHADOW_BIAS);
+    return color * (1.0 - SHADOW_DARKNESS * shadow.strength * (1.0 - lit));
+}
@@ -0,0 +1,144 @@
+// Surfaces of fluids, blended over the world after everything else is drawn.
+//
+// Waves only tilt the normal of the flat surface. It reflects the sky, more strongly at grazing angles,
+// and lets the terrain below show through where the fluid is shallow, judged by the depth of the world.
+//
+// Flags: FOG fades distant surfaces into the sky color like the rest of the world.
+
+#include "camera.wgsl"
+
+struct WaterUniform {
+    // relative to the origin the world is drawn around
+    eye: vec3<f32>,
+    // seconds the waves have been moving
+    time: f32,
+    // direction from the world towards the sun
+    sun_direction: vec3<f32>,
+    sun_strength: f32,
+    // distance of the near plane of the camera
+    near: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> water: WaterUniform;
+// depth of the world drawn before the fluids
+@group(1) @binding(1)
+var t_depth: texture_2d<f32>;
+
+#include "fog.wgsl"
+
+// Wave vectors are given in multiples of this, so the waves repeat every chunk and still line up after
+// the origin moves by whole chunks.
+const CHUNK_FREQUENCY: f32 = 0.19634954;
+// How quickly the fluid gets opaque with the distance light travels through it, per block.
+const FADE_DENSITY: f32 = 0.35;
+// Opacity where the fluid meets the terrain behind it.
+const SHALLOW_ALPHA: f32 = 0.35;
+// Reflectance when looking straight down onto water.
+const BASE_REFLECTANCE: f32 = 0.02;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(3) model_matrix_0: vec4<f32>,
+    @location(4) model_matrix_1: vec4<f32>,
+    @location(5) model_matrix_2: vec4<f32>,
+    @location(6) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    // relative to the origin the world is drawn around
+    @location(1) position: vec3<f32>,
+    // distance from the camera along the view direction
+    @location(2) depth: f32,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    out.color = model.color;
+    let position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.position = position.xyz;
+    out.clip_position = camera.view_proj * position;
+    out.depth = out.clip_position.w;
+
+    return out;
+}
+
+// Returns the slope along x and z of a wave travelling along `wave`, given in chunk frequencies.
+fn wave_slope(position: vec2<f32>, wave: vec2<f32>, amplitude: f32, speed: f32) -> vec2<f32> {
+    let wave_vector = wave * CHUNK_FREQUENCY;
+    let phase = dot(wave_vector, position) - speed * water.time;
+    return wave_vector * amplitude * cos(phase);
+}
+
+fn wave_normal(position: vec2<f32>) -> vec3<f32> {
+    let slope = wave_slope(position, vec2<f32>(3.0, 1.0), 0.1, 1.1)
+        + wave_slope(position, vec2<f32>(-2.0, 5.0), 0.06, 1.7)
+        + wave_slope(position, vec2<f32>(7.0, -4.0), 0.03, 2.3)
+        + wave_slope(position, vec2<f32>(-9.0, -11.0), 0.015, 3.1);
+
+    return normalize(vec3<f32>(-slope.x, 1.0, -slope.y));
+}
+
+// Returns the color of the sky in a direction, brightest at the horizon like the fog and deeper
+// towards the zenith, with the sun reflected as a bright spot.
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    let sky = mix(fog.color, fog.color * 0.6, saturate(direction.y));
+    let sun = pow(saturate(dot(direction, water.sun_direction)), 400.0) * water.sun_strength;
+
+    return sky + vec3<f32>(sun);
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let to_eye = normalize(water.eye - in.position);
+
+    // only the tops of fluids have waves, the sides of falling fluids stay flat
+    var normal = normalize(cross(dpdy(in.position), dpdx(in.position)));
+    if dot(normal, to_eye) < 0.0 {
+        normal = -normal;
+    }
+    if normal.y > 0.5 {
+        normal = wave_normal(in.position.xz);
+    }
+
+    // Schlick's approximation of how much light the surface reflects
+    let facing = saturate(dot(normal, to_eye));
+    let fresnel = BASE_REFLECTANCE + (1.0 - BASE_REFLECTANCE) * pow(1.0 - facing, 5.0);
+    let reflection = sky_color(reflect(-to_eye, normal));
+
+    // turns the depth of the world behind the surface back into a distance, the sky is infinitely far
+    let world_depth = textureLoad(t_depth, vec2<i32>(in.clip_position.xy), 0).r;
+    let world_distance = water.near / max(1.0 - world_depth, 1e-7);
+    let opacity = 1.0 - exp(-max(world_distance - in.depth, 0.0) * FADE_DENSITY);
+
+    let body = mix(in.color, in.color * 0.35, opacity);
+    var color = mix(body, reflection, fresnel);
+    let alpha = mix(mix(SHALLOW_ALPHA, 1.0, opacity), 1.0, fresnel);
+
+#ifdef FOG
+    color = apply_fog(color, in.depth);
+#endif
+
+    return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,26 @@
+use clap::Parser;
+
+/// Measures worldgen and meshing throughput on chunks generated from a fixed seed.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Number of chunks to generate and mesh.
+    #[arg(long, default_value_t = 512)]
+    chunks: usize,
+
+    /// Seed of the generated terrain, the same seed always generates the same chunks.
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    match landmark_client::run_benchmark(args.chunks, args.seed) {
+        Ok(report) => println!("{report}"),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,3 +1,91 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use landmark_client::ReplayMode;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Run a dedicated server instead of the client.
+    #[arg(long)]
+    server: bool,
+
+    /// Join the server at the address instead of playing singleplayer.
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "server")]
+    connect: Option<String>,
+
+    /// Write the locally recorded telemetry to a file and exit.
+    #[arg(long, value_name = "PATH")]
+    export_telemetry: Option<PathBuf>,
+
+    /// Print the GPUs which can be selected in the graphics settings and exit.
+    #[arg(long)]
+    list_adapters: bool,
+
+    /// Import a Minecraft world from its region directory into the saved world and exit.
+    #[cfg(feature = "anvil")]
+    #[arg(long, value_name = "DIR")]
+    import_anvil: Option<PathBuf>,
+
+    /// Mapping of Minecraft blocks to Landmark blocks used by the import.
+    #[cfg(feature = "anvil")]
+    #[arg(long, value_name = "PATH", default_value = "res/anvil_mapping.ron")]
+    block_mapping: PathBuf,
+
+    /// Record the input of every update to a file, written when the game exits.
+    #[arg(long, value_name = "PATH", conflicts_with = "replay_input")]
+    record_input: Option<PathBuf>,
+
+    /// Play back input recorded with --record-input instead of reading the keyboard and mouse.
+    #[arg(long, value_name = "PATH")]
+    replay_input: Option<PathBuf>,
+}
+
 fn main() {
-    landmark_client::run();
+    let args = Args::parse();
+
+    if let Some(path) = args.export_telemetry {
+        match landmark_client::export_telemetry(&path) {
+            Ok(()) => println!("Wrote telemetry to {}", path.display()),
+            Err(e) => {
+                eprintln!("Failed to export telemetry: {e}");
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    if args.list_adapters {
+        for adapter in landmark_client::list_adapters() {
+            println!("{adapter}");
+        }
+
+        return;
+    }
+
+    #[cfg(feature = "anvil")]
+    if let Some(source) = args.import_anvil {
+        match landmark_client::import_anvil(&source, &args.block_mapping) {
+            Ok(summary) => println!("{summary}"),
+            Err(e) => {
+                eprintln!("Failed to import the world: {e}");
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    let replay = args
+        .record_input
+        .map(ReplayMode::Record)
+        .or(args.replay_input.map(ReplayMode::Play));
+
+    if args.server {
+        landmark_server::run(landmark_client::open_world);
+    } else if let Err(e) = landmark_client::run(replay, args.connect) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 }